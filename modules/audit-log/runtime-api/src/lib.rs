@@ -0,0 +1,16 @@
+//! Runtime API definition for audit log module.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::Codec;
+use module_primitives::AuditRecord;
+use sp_runtime::traits::NumberFor;
+use sp_std::prelude::*;
+
+sp_api::decl_runtime_apis! {
+	pub trait AuditLogApi<AccountId> where
+		AccountId: Codec,
+	{
+		fn audit_records(from_id: u64, limit: u32) -> Vec<(u64, AuditRecord<AccountId, NumberFor<Block>>)>;
+	}
+}
@@ -0,0 +1,79 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use frame_support::{decl_module, decl_storage, traits::Get};
+use frame_system::{self as system, RawOrigin};
+use module_primitives::{AuditActor, AuditRecord};
+use module_traits::AuditLog;
+use sp_std::{cmp, prelude::*};
+
+mod mock;
+mod tests;
+
+pub trait Trait: frame_system::Trait {
+	/// Maximum number of audit records to retain. Oldest records are removed first.
+	type MaxAuditRecords: Get<u32>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as AuditLog {
+		/// Audit records of privileged parameter changes, keyed by record ID.
+		AuditRecords get(fn audit_records): map hasher(twox_64_concat) u64 => Option<AuditRecord<T::AccountId, T::BlockNumber>>;
+
+		/// ID of the next audit record.
+		NextAuditRecordId get(fn next_audit_record_id): u64;
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		const MaxAuditRecords: u32 = T::MaxAuditRecords::get();
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Retained audit records with ID not less than `from_id`, at most `limit` records.
+	pub fn audit_records_from(from_id: u64, limit: u32) -> Vec<(u64, AuditRecord<T::AccountId, T::BlockNumber>)> {
+		let next_id = Self::next_audit_record_id();
+		let oldest_id = next_id.saturating_sub(T::MaxAuditRecords::get().into());
+		let from_id = cmp::max(from_id, oldest_id);
+		let to_id = cmp::min(next_id, from_id.saturating_add(limit.into()));
+
+		(from_id..to_id)
+			.filter_map(|id| Self::audit_records(id).map(|record| (id, record)))
+			.collect()
+	}
+}
+
+impl<T: Trait> AuditLog<T::Origin> for Module<T> {
+	fn record(
+		origin: T::Origin,
+		pallet: &[u8],
+		parameter: &[u8],
+		key: Vec<u8>,
+		old_value: Vec<u8>,
+		new_value: Vec<u8>,
+	) {
+		let actor = match origin.into() {
+			Ok(RawOrigin::Root) => AuditActor::Root,
+			Ok(RawOrigin::Signed(who)) => AuditActor::Signed(who),
+			_ => AuditActor::Other,
+		};
+		let record = AuditRecord {
+			actor,
+			pallet: pallet.to_vec(),
+			parameter: parameter.to_vec(),
+			key,
+			old_value,
+			new_value,
+			block_number: <system::Module<T>>::block_number(),
+		};
+
+		let id = Self::next_audit_record_id();
+		AuditRecords::<T>::insert(id, record);
+		NextAuditRecordId::put(id.saturating_add(1));
+
+		if let Some(expired_id) = id.checked_sub(T::MaxAuditRecords::get().into()) {
+			AuditRecords::<T>::remove(expired_id);
+		}
+	}
+}
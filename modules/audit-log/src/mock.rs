@@ -0,0 +1,47 @@
+//! Mocks for the audit-log module.
+
+#![cfg(test)]
+
+use frame_support::{impl_outer_origin, parameter_types};
+use frame_system as system;
+
+use super::*;
+
+impl_outer_origin! {
+	pub enum Origin for Runtime {}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Runtime;
+parameter_types! {
+	pub const MaxAuditRecords: u32 = 2;
+}
+
+pub type AccountId = u64;
+laminar_test_support::impl_mock_system!(Runtime, AccountId, (), ());
+pub type System = system::Module<Runtime>;
+
+impl Trait for Runtime {
+	type MaxAuditRecords = MaxAuditRecords;
+}
+
+pub type AuditLogModule = Module<Runtime>;
+
+pub const ALICE: AccountId = 0;
+
+#[derive(Default)]
+pub struct ExtBuilder;
+
+impl ExtBuilder {
+	pub fn build(self) -> sp_io::TestExternalities {
+		let t = frame_system::GenesisConfig::default()
+			.build_storage::<Runtime>()
+			.unwrap()
+			.into();
+
+		let mut ext = sp_io::TestExternalities::new(t);
+		ext.execute_with(|| System::set_block_number(1));
+		ext
+	}
+}
@@ -0,0 +1,83 @@
+//! Unit tests for the audit-log module.
+
+#![cfg(test)]
+
+use super::*;
+use mock::*;
+
+fn record_change(origin: Origin, old_value: u32, new_value: u32) {
+	<AuditLogModule as AuditLog<Origin>>::record(
+		origin,
+		b"Pallet",
+		b"Parameter",
+		vec![],
+		old_value.to_le_bytes().to_vec(),
+		new_value.to_le_bytes().to_vec(),
+	);
+}
+
+#[test]
+fn record_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		record_change(Origin::root(), 1, 2);
+		System::set_block_number(2);
+		record_change(Origin::signed(ALICE), 2, 3);
+
+		assert_eq!(AuditLogModule::next_audit_record_id(), 2);
+		assert_eq!(
+			AuditLogModule::audit_records(0),
+			Some(AuditRecord {
+				actor: AuditActor::Root,
+				pallet: b"Pallet".to_vec(),
+				parameter: b"Parameter".to_vec(),
+				key: vec![],
+				old_value: 1u32.to_le_bytes().to_vec(),
+				new_value: 2u32.to_le_bytes().to_vec(),
+				block_number: 1,
+			})
+		);
+		assert_eq!(
+			AuditLogModule::audit_records(1).map(|r| (r.actor, r.block_number)),
+			Some((AuditActor::Signed(ALICE), 2))
+		);
+
+		record_change(Origin::none(), 3, 4);
+		assert_eq!(AuditLogModule::audit_records(2).map(|r| r.actor), Some(AuditActor::Other));
+	});
+}
+
+#[test]
+fn oldest_records_are_removed() {
+	ExtBuilder::default().build().execute_with(|| {
+		record_change(Origin::root(), 1, 2);
+		record_change(Origin::root(), 2, 3);
+		assert!(AuditLogModule::audit_records(0).is_some());
+
+		record_change(Origin::root(), 3, 4);
+		assert_eq!(AuditLogModule::audit_records(0), None);
+		assert!(AuditLogModule::audit_records(1).is_some());
+		assert!(AuditLogModule::audit_records(2).is_some());
+	});
+}
+
+#[test]
+fn audit_records_from_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert!(AuditLogModule::audit_records_from(0, 10).is_empty());
+
+		record_change(Origin::root(), 1, 2);
+		record_change(Origin::root(), 2, 3);
+		record_change(Origin::root(), 3, 4);
+
+		let ids = |from_id, limit| {
+			AuditLogModule::audit_records_from(from_id, limit)
+				.into_iter()
+				.map(|(id, _)| id)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(ids(0, 10), vec![1, 2]);
+		assert_eq!(ids(0, 1), vec![1]);
+		assert_eq!(ids(2, 10), vec![2]);
+		assert!(ids(3, 10).is_empty());
+	});
+}
@@ -0,0 +1,593 @@
+//! Earn vault of the liquidity currency.
+//!
+//! Depositors get vault shares, and the vault allocates deposits across liquidity pools approved by governance, by
+//! their weights. Pools pay interest on their allocations at the governance-set rates, counted in the share price
+//! as it accrues, as far as the pools could pay, and harvested into the vault. Withdrawals are paid out of idle
+//! vault balance; if not enough, they are queued and paid first-in-first-out on later rebalances, as allocations
+//! are pulled back from pools.
+//!
+//! Allocations are tracked as principal until liquidity pool shares exist. Pool owners can't withdraw the principal
+//! and interest owed to the vault, and pool losses beyond the owner's own liquidity are losses of the vault.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Decode, Encode};
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage, ensure,
+	traits::{EnsureOrigin, Get},
+	IterableStorageMap,
+};
+use sp_arithmetic::{
+	traits::{Saturating, UniqueSaturatedInto, Zero},
+	Permill,
+};
+use sp_runtime::{traits::AccountIdConversion, DispatchError, DispatchResult, ModuleId, RuntimeDebug};
+use sp_std::{cmp, prelude::*, result};
+// FIXME: `pallet/frame-` prefix should be used for all pallet modules, but currently `frame_system`
+// would cause compiling error in `decl_module!` and `construct_runtime!`
+// #3295 https://github.com/paritytech/substrate/issues/3295
+use frame_system::{self as system, ensure_signed};
+
+use orml_traits::BasicCurrency;
+use orml_utilities::with_transaction_result;
+
+use module_primitives::{
+	arithmetic::{balance_mul_div_with_rounding, fixed_u128_mul_int_with_rounding, Rounding},
+	Balance, LiquidityPoolId, Price,
+};
+use module_traits::{BaseLiquidityPoolManager, LiquidityPools};
+
+mod mock;
+mod tests;
+
+const MODULE_ID: ModuleId = ModuleId(*b"lami/ern");
+
+pub trait Trait: frame_system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+
+	/// The currency deposited into the vault, and allocated as pool liquidity.
+	type LiquidityCurrency: BasicCurrency<Self::AccountId, Balance = Balance>;
+
+	/// The liquidity pools the vault allocates to.
+	type LiquidityPools: LiquidityPools<Self::AccountId>;
+
+	/// Withdrawal checks of the liquidity pools, for pulling back allocations and harvesting interest.
+	type PoolManager: BaseLiquidityPoolManager<LiquidityPoolId, Balance>;
+
+	/// Number of blocks the interest rates of pools are charged for.
+	type InterestPeriod: Get<Self::BlockNumber>;
+
+	/// Maximum number of approved pools.
+	type GetMaxPools: Get<u32>;
+
+	/// Maximum number of queued withdrawals.
+	type GetMaxQueuedWithdrawals: Get<u32>;
+
+	/// Required origin for approving and disapproving pools.
+	type UpdateOrigin: EnsureOrigin<Self::Origin>;
+}
+
+/// Allocation parameters of an approved pool.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct PoolConfig {
+	/// Share of the vault allocated to the pool is `weight` over the sum of all weights.
+	pub weight: u32,
+	/// Interest the pool pays on the allocation per `InterestPeriod`.
+	pub interest_rate: Permill,
+}
+
+/// Vault allocation in a pool.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct Allocation<BlockNumber> {
+	/// Allocated amount, excluding losses realized.
+	pub principal: Balance,
+	/// Accrued interest not harvested yet.
+	pub interest: Balance,
+	/// The block interest accrued to.
+	pub last_accrual: BlockNumber,
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as EarnVault {
+		/// Approved pools and their allocation parameters.
+		PoolConfigs get(fn pool_configs): map hasher(twox_64_concat) LiquidityPoolId => Option<PoolConfig>;
+
+		/// Vault allocations in pools.
+		Allocations get(fn allocations): map hasher(twox_64_concat) LiquidityPoolId => Option<Allocation<T::BlockNumber>>;
+
+		/// Vault balance not allocated to any pool.
+		Idle get(fn idle): Balance;
+
+		/// Vault shares of accounts, excluding queued withdrawals.
+		Shares get(fn shares): map hasher(twox_64_concat) T::AccountId => Balance;
+
+		/// Total vault shares, including queued withdrawals.
+		TotalShares get(fn total_shares): Balance;
+
+		/// Queued withdrawals of `(who, shares)`, paid first-in-first-out.
+		WithdrawalQueue get(fn withdrawal_queue): Vec<(T::AccountId, Balance)>;
+
+		/// Total shares of queued withdrawals.
+		QueuedShares get(fn queued_shares): Balance;
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where
+		<T as frame_system::Trait>::AccountId,
+	{
+		/// Deposited: [who, amount, shares]
+		Deposited(AccountId, Balance, Balance),
+
+		/// Withdrew: [who, shares, amount]
+		Withdrew(AccountId, Balance, Balance),
+
+		/// Withdrawal queued: [who, shares]
+		WithdrawalQueued(AccountId, Balance),
+
+		/// Allocated to pool: [pool_id, amount]
+		Allocated(LiquidityPoolId, Balance),
+
+		/// Deallocated from pool: [pool_id, amount]
+		Deallocated(LiquidityPoolId, Balance),
+
+		/// Pool allocation loss realized: [pool_id, amount]
+		LossRealized(LiquidityPoolId, Balance),
+
+		/// Interest harvested: [pool_id, amount]
+		Harvested(LiquidityPoolId, Balance),
+
+		/// Pool approved: [pool_id, config]
+		PoolApproved(LiquidityPoolId, PoolConfig),
+
+		/// Pool disapproved: [pool_id]
+		PoolDisapproved(LiquidityPoolId),
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		type Error = Error<T>;
+
+		fn deposit_event() = default;
+
+		const InterestPeriod: T::BlockNumber = T::InterestPeriod::get();
+		const GetMaxPools: u32 = T::GetMaxPools::get();
+		const GetMaxQueuedWithdrawals: u32 = T::GetMaxQueuedWithdrawals::get();
+
+		/// Deposit `amount` into the vault for shares at the current share price.
+		#[weight = 10_000]
+		pub fn deposit(origin, #[compact] amount: Balance) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let shares = Self::do_deposit(&who, amount)?;
+				Self::deposit_event(RawEvent::Deposited(who, amount, shares));
+				Ok(())
+			})?;
+		}
+
+		/// Withdraw `shares` from the vault.
+		///
+		/// Paid immediately if the idle vault balance is enough and no withdrawals are queued, or else queued.
+		/// Queued withdrawals are paid at the share price of when they are processed.
+		#[weight = 10_000]
+		pub fn withdraw(origin, #[compact] shares: Balance) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::do_withdraw(&who, shares)?;
+				Ok(())
+			})?;
+		}
+
+		/// Move allocations toward the pool weights, and pay queued withdrawals.
+		///
+		/// Could be called by anyone.
+		#[weight = 10_000 + T::GetMaxPools::get() as u64 * 20_000 + T::GetMaxQueuedWithdrawals::get() as u64 * 10_000]
+		pub fn rebalance(origin) {
+			with_transaction_result(|| {
+				ensure_signed(origin)?;
+				Self::do_rebalance()
+			})?;
+		}
+
+		/// Harvest interest owed by `pool_id`, as much as the pool could pay.
+		///
+		/// Could be called by anyone.
+		#[weight = 10_000]
+		pub fn harvest(origin, #[compact] pool_id: LiquidityPoolId) {
+			with_transaction_result(|| {
+				ensure_signed(origin)?;
+				let amount = Self::do_harvest(pool_id)?;
+				Self::deposit_event(RawEvent::Harvested(pool_id, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Approve `pool_id` for allocations, or update its config. Interest accrued so far is charged at the
+		/// previous rate.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn approve_pool(origin, #[compact] pool_id: LiquidityPoolId, config: PoolConfig) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				Self::do_approve_pool(pool_id, config)?;
+				Self::deposit_event(RawEvent::PoolApproved(pool_id, config));
+				Ok(())
+			})?;
+		}
+
+		/// Disapprove `pool_id`. Its allocation would be pulled back on later rebalances.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn disapprove_pool(origin, #[compact] pool_id: LiquidityPoolId) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				Self::do_disapprove_pool(pool_id)?;
+				Self::deposit_event(RawEvent::PoolDisapproved(pool_id));
+				Ok(())
+			})?;
+		}
+	}
+}
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// Liquidity pool not found.
+		PoolNotFound,
+
+		/// Pool is not approved.
+		PoolNotApproved,
+
+		/// Reached the max number of approved pools.
+		TooManyPools,
+
+		/// The vault has no allocation in pool.
+		NoAllocation,
+
+		/// Amount or shares is zero.
+		ZeroAmount,
+
+		/// Account doesn't have enough shares.
+		InsufficientShares,
+
+		/// Reached the max number of queued withdrawals.
+		WithdrawalQueueFull,
+
+		/// The vault has shares but no assets, and can't take deposits.
+		VaultInsolvent,
+
+		/// Withdrawal would take principal or interest owed to the vault.
+		CannotWithdrawVaultAllocation,
+
+		/// Number overflow in calculation.
+		NumOverflow,
+	}
+}
+
+type BalanceResult = result::Result<Balance, DispatchError>;
+
+// Dispatchable calls implementation
+impl<T: Trait> Module<T> {
+	fn do_deposit(who: &T::AccountId, amount: Balance) -> BalanceResult {
+		ensure!(!amount.is_zero(), Error::<T>::ZeroAmount);
+
+		let shares = Self::shares_of_amount(amount)?;
+		ensure!(!shares.is_zero(), Error::<T>::ZeroAmount);
+		let idle = Self::idle().checked_add(amount).ok_or(Error::<T>::NumOverflow)?;
+		let total_shares = Self::total_shares()
+			.checked_add(shares)
+			.ok_or(Error::<T>::NumOverflow)?;
+
+		T::LiquidityCurrency::transfer(who, &Self::account_id(), amount)?;
+		Idle::put(idle);
+		TotalShares::put(total_shares);
+		<Shares<T>>::mutate(who, |s| *s = s.saturating_add(shares));
+
+		Ok(shares)
+	}
+
+	fn do_withdraw(who: &T::AccountId, shares: Balance) -> DispatchResult {
+		ensure!(!shares.is_zero(), Error::<T>::ZeroAmount);
+		let remaining = Self::shares(who)
+			.checked_sub(shares)
+			.ok_or(Error::<T>::InsufficientShares)?;
+
+		let amount = Self::amount_of_shares(shares)?;
+		<Shares<T>>::insert(who, remaining);
+		if Self::withdrawal_queue().is_empty() && amount <= Self::idle() {
+			return Self::pay_out(who, shares, amount);
+		}
+
+		let mut queue = Self::withdrawal_queue();
+		ensure!(
+			queue.len() < T::GetMaxQueuedWithdrawals::get() as usize,
+			Error::<T>::WithdrawalQueueFull
+		);
+		queue.push((who.clone(), shares));
+		<WithdrawalQueue<T>>::put(queue);
+		QueuedShares::mutate(|s| *s = s.saturating_add(shares));
+		Self::deposit_event(RawEvent::WithdrawalQueued(who.clone(), shares));
+
+		Ok(())
+	}
+
+	fn do_rebalance() -> DispatchResult {
+		let queued = Self::amount_of_shares(Self::queued_shares())?;
+		let investable = Self::principal_assets().saturating_sub(queued);
+		let total_weight: u128 = PoolConfigs::iter().map(|(_, config)| config.weight as u128).sum();
+		let target = |pool_id: LiquidityPoolId| -> BalanceResult {
+			match Self::pool_configs(pool_id) {
+				Some(config) if !total_weight.is_zero() => {
+					balance_mul_div_with_rounding(investable, config.weight as u128, total_weight, Rounding::Down)
+						.ok_or_else(|| Error::<T>::NumOverflow.into())
+				}
+				_ => Ok(Zero::zero()),
+			}
+		};
+
+		// Pull back over-allocations first, to pay queued withdrawals and fund the under-allocated pools.
+		for (pool_id, allocation) in <Allocations<T>>::iter().collect::<Vec<_>>() {
+			let claim = Self::claim(pool_id, &allocation);
+			let amount = claim.saturating_sub(target(pool_id)?);
+			if amount.is_zero() && claim == allocation.principal {
+				continue;
+			}
+			// Pools that can't afford the withdrawal now are left to later rebalances.
+			if !amount.is_zero() && T::PoolManager::ensure_can_withdraw(pool_id, amount).is_err() {
+				continue;
+			}
+			Self::deallocate(pool_id, amount)?;
+		}
+
+		Self::process_withdrawal_queue()?;
+		// Idle balance is reserved for withdrawals still queued.
+		if !Self::withdrawal_queue().is_empty() {
+			return Ok(());
+		}
+
+		for (pool_id, _) in PoolConfigs::iter().collect::<Vec<_>>() {
+			let claim = Self::allocations(pool_id).map_or(Zero::zero(), |a| Self::claim(pool_id, &a));
+			let amount = cmp::min(target(pool_id)?.saturating_sub(claim), Self::idle());
+			if !amount.is_zero() {
+				Self::allocate(pool_id, amount)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	fn do_harvest(pool_id: LiquidityPoolId) -> BalanceResult {
+		let mut allocation = Self::allocations(pool_id).ok_or(Error::<T>::NoAllocation)?;
+		Self::accrue(pool_id, &mut allocation)?;
+
+		let excess = T::LiquidityPools::liquidity(pool_id).saturating_sub(allocation.principal);
+		let amount = cmp::min(allocation.interest, excess);
+		if !amount.is_zero() {
+			T::PoolManager::ensure_can_withdraw(pool_id, amount)?;
+			T::LiquidityPools::withdraw_liquidity(&Self::account_id(), pool_id, amount)?;
+			allocation.interest -= amount;
+			Idle::mutate(|i| *i = i.saturating_add(amount));
+		}
+		Self::put_allocation(pool_id, allocation);
+
+		Ok(amount)
+	}
+
+	fn do_approve_pool(pool_id: LiquidityPoolId, config: PoolConfig) -> DispatchResult {
+		ensure!(T::LiquidityPools::pool_exists(pool_id), Error::<T>::PoolNotFound);
+		if !PoolConfigs::contains_key(pool_id) {
+			ensure!(
+				(PoolConfigs::iter().count() as u32) < T::GetMaxPools::get(),
+				Error::<T>::TooManyPools
+			);
+		}
+
+		Self::accrue_allocation(pool_id)?;
+		PoolConfigs::insert(pool_id, config);
+
+		Ok(())
+	}
+
+	fn do_disapprove_pool(pool_id: LiquidityPoolId) -> DispatchResult {
+		ensure!(PoolConfigs::contains_key(pool_id), Error::<T>::PoolNotApproved);
+
+		Self::accrue_allocation(pool_id)?;
+		PoolConfigs::remove(pool_id);
+
+		Ok(())
+	}
+}
+
+// Private methods
+impl<T: Trait> Module<T> {
+	pub fn account_id() -> T::AccountId {
+		MODULE_ID.into_account()
+	}
+
+	/// Vault assets: the idle balance, plus allocation claims on pools and the interest they could pay.
+	pub fn total_assets() -> Balance {
+		<Allocations<T>>::iter().fold(Self::idle(), |assets, (pool_id, allocation)| {
+			let interest = Self::accrued_interest(pool_id, &allocation).unwrap_or(allocation.interest);
+			let owed = allocation.principal.saturating_add(interest);
+			assets.saturating_add(cmp::min(owed, T::LiquidityPools::liquidity(pool_id)))
+		})
+	}
+
+	/// Vault assets to allocate: the idle balance, plus allocation claims on pools, excluding interest not harvested.
+	fn principal_assets() -> Balance {
+		<Allocations<T>>::iter().fold(Self::idle(), |assets, (pool_id, allocation)| {
+			assets.saturating_add(Self::claim(pool_id, &allocation))
+		})
+	}
+
+	/// Shares of `amount` at the current share price, rounded down in favor of the vault.
+	fn shares_of_amount(amount: Balance) -> BalanceResult {
+		let total_shares = Self::total_shares();
+		if total_shares.is_zero() {
+			return Ok(amount);
+		}
+
+		let total_assets = Self::total_assets();
+		ensure!(!total_assets.is_zero(), Error::<T>::VaultInsolvent);
+		balance_mul_div_with_rounding(amount, total_shares, total_assets, Rounding::Down)
+			.ok_or_else(|| Error::<T>::NumOverflow.into())
+	}
+
+	/// Value of `shares` at the current share price, rounded down in favor of the vault.
+	fn amount_of_shares(shares: Balance) -> BalanceResult {
+		let total_shares = Self::total_shares();
+		if total_shares.is_zero() {
+			return Ok(Zero::zero());
+		}
+
+		balance_mul_div_with_rounding(shares, Self::total_assets(), total_shares, Rounding::Down)
+			.ok_or_else(|| Error::<T>::NumOverflow.into())
+	}
+
+	/// The vault claim on `pool_id`: the principal, or all the pool liquidity if less.
+	fn claim(pool_id: LiquidityPoolId, allocation: &Allocation<T::BlockNumber>) -> Balance {
+		cmp::min(allocation.principal, T::LiquidityPools::liquidity(pool_id))
+	}
+
+	/// Interest owed on `allocation`, including interest accrued since the last accrual.
+	///
+	/// interest = principal * interest_rate * elapsed / interest_period
+	fn accrued_interest(pool_id: LiquidityPoolId, allocation: &Allocation<T::BlockNumber>) -> BalanceResult {
+		let rate: Price = match Self::pool_configs(pool_id) {
+			Some(config) => config.interest_rate.into(),
+			None => return Ok(allocation.interest),
+		};
+		let elapsed: u128 = <system::Module<T>>::block_number()
+			.saturating_sub(allocation.last_accrual)
+			.unique_saturated_into();
+		let period: u128 = T::InterestPeriod::get().unique_saturated_into();
+
+		balance_mul_div_with_rounding(allocation.principal, elapsed, period, Rounding::Down)
+			.and_then(|principal_periods| fixed_u128_mul_int_with_rounding(rate, principal_periods, Rounding::Down))
+			.and_then(|interest| interest.checked_add(allocation.interest))
+			.ok_or_else(|| Error::<T>::NumOverflow.into())
+	}
+
+	fn accrue(pool_id: LiquidityPoolId, allocation: &mut Allocation<T::BlockNumber>) -> DispatchResult {
+		allocation.interest = Self::accrued_interest(pool_id, allocation)?;
+		allocation.last_accrual = <system::Module<T>>::block_number();
+		Ok(())
+	}
+
+	/// Accrue the allocation in `pool_id` if any, before its interest rate changes.
+	fn accrue_allocation(pool_id: LiquidityPoolId) -> DispatchResult {
+		if let Some(mut allocation) = Self::allocations(pool_id) {
+			Self::accrue(pool_id, &mut allocation)?;
+			Self::put_allocation(pool_id, allocation);
+		}
+		Ok(())
+	}
+
+	fn put_allocation(pool_id: LiquidityPoolId, allocation: Allocation<T::BlockNumber>) {
+		if allocation.principal.is_zero() && allocation.interest.is_zero() {
+			<Allocations<T>>::remove(pool_id);
+		} else {
+			<Allocations<T>>::insert(pool_id, allocation);
+		}
+	}
+
+	fn allocate(pool_id: LiquidityPoolId, amount: Balance) -> DispatchResult {
+		let mut allocation = Self::allocations(pool_id).unwrap_or_default();
+		Self::accrue(pool_id, &mut allocation)?;
+		let claim = Self::claim(pool_id, &allocation);
+		let idle = Self::idle().checked_sub(amount).ok_or(Error::<T>::NumOverflow)?;
+
+		T::LiquidityPools::deposit_liquidity(&Self::account_id(), pool_id, amount)?;
+		Self::realize_loss(pool_id, &allocation, claim);
+		allocation.principal = claim.checked_add(amount).ok_or(Error::<T>::NumOverflow)?;
+		Idle::put(idle);
+		Self::put_allocation(pool_id, allocation);
+		Self::deposit_event(RawEvent::Allocated(pool_id, amount));
+
+		Ok(())
+	}
+
+	/// Pull back `amount` from `pool_id`, and realize losses of the allocation.
+	fn deallocate(pool_id: LiquidityPoolId, amount: Balance) -> DispatchResult {
+		let mut allocation = Self::allocations(pool_id).ok_or(Error::<T>::NoAllocation)?;
+		Self::accrue(pool_id, &mut allocation)?;
+		let claim = Self::claim(pool_id, &allocation);
+		let idle = Self::idle().checked_add(amount).ok_or(Error::<T>::NumOverflow)?;
+
+		if !amount.is_zero() {
+			T::LiquidityPools::withdraw_liquidity(&Self::account_id(), pool_id, amount)?;
+			Self::deposit_event(RawEvent::Deallocated(pool_id, amount));
+		}
+		Self::realize_loss(pool_id, &allocation, claim);
+		allocation.principal = claim.checked_sub(amount).ok_or(Error::<T>::NumOverflow)?;
+		Idle::put(idle);
+		Self::put_allocation(pool_id, allocation);
+
+		Ok(())
+	}
+
+	fn realize_loss(pool_id: LiquidityPoolId, allocation: &Allocation<T::BlockNumber>, claim: Balance) {
+		let loss = allocation.principal.saturating_sub(claim);
+		if !loss.is_zero() {
+			Self::deposit_event(RawEvent::LossRealized(pool_id, loss));
+		}
+	}
+
+	/// Pay out `amount` to `who` and burn `shares`.
+	fn pay_out(who: &T::AccountId, shares: Balance, amount: Balance) -> DispatchResult {
+		let idle = Self::idle().checked_sub(amount).ok_or(Error::<T>::NumOverflow)?;
+		let total_shares = Self::total_shares()
+			.checked_sub(shares)
+			.ok_or(Error::<T>::NumOverflow)?;
+
+		T::LiquidityCurrency::transfer(&Self::account_id(), who, amount)?;
+		Idle::put(idle);
+		TotalShares::put(total_shares);
+		Self::deposit_event(RawEvent::Withdrew(who.clone(), shares, amount));
+
+		Ok(())
+	}
+
+	/// Pay queued withdrawals in order, until the idle balance isn't enough for the next one.
+	fn process_withdrawal_queue() -> DispatchResult {
+		let mut queue = Self::withdrawal_queue();
+		let mut processed = 0;
+		for (who, shares) in queue.iter() {
+			let amount = Self::amount_of_shares(*shares)?;
+			if amount > Self::idle() {
+				break;
+			}
+			Self::pay_out(who, *shares, amount)?;
+			QueuedShares::mutate(|s| *s = s.saturating_sub(*shares));
+			processed += 1;
+		}
+
+		if processed > 0 {
+			queue.drain(..processed);
+			<WithdrawalQueue<T>>::put(queue);
+		}
+		Ok(())
+	}
+}
+
+impl<T: Trait> BaseLiquidityPoolManager<LiquidityPoolId, Balance> for Module<T> {
+	/// Pools with vault principal can't be removed.
+	fn can_remove(pool_id: LiquidityPoolId) -> bool {
+		Self::allocations(pool_id).map_or(true, |allocation| allocation.principal.is_zero())
+	}
+
+	/// Pool owners can't withdraw the principal and interest owed to the vault.
+	fn ensure_can_withdraw(pool_id: LiquidityPoolId, amount: Balance) -> DispatchResult {
+		if let Some(allocation) = Self::allocations(pool_id) {
+			let owed = Self::accrued_interest(pool_id, &allocation)?.saturating_add(allocation.principal);
+			let remaining = T::LiquidityPools::liquidity(pool_id)
+				.checked_sub(amount)
+				.ok_or(Error::<T>::CannotWithdrawVaultAllocation)?;
+			ensure!(remaining >= owed, Error::<T>::CannotWithdrawVaultAllocation);
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,145 @@
+//! Mocks for the earn vault module.
+
+#![cfg(test)]
+
+use frame_support::{impl_outer_event, impl_outer_origin, ord_parameter_types, parameter_types};
+use frame_system as system;
+use system::EnsureSignedBy;
+
+use orml_currencies::Currency;
+
+use super::*;
+
+pub use laminar_test_support::MockPoolManager;
+pub use module_primitives::{Balance, CurrencyId};
+
+impl_outer_origin! {
+	pub enum Origin for Runtime {}
+}
+
+ord_parameter_types! {
+	pub const UpdateOrigin: AccountId = 3;
+}
+
+mod earn_vault {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for Runtime {
+		frame_system<T>,
+		orml_tokens<T>, orml_currencies<T>,
+		earn_vault<T>,
+	}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Runtime;
+
+pub type AccountId = u32;
+laminar_test_support::impl_mock_system!(Runtime, AccountId, TestEvent, ());
+pub type System = system::Module<Runtime>;
+
+type Amount = i128;
+
+impl orml_tokens::Trait for Runtime {
+	type Event = TestEvent;
+	type Balance = Balance;
+	type Amount = Amount;
+	type CurrencyId = CurrencyId;
+	type OnReceived = ();
+}
+
+parameter_types! {
+	pub const GetNativeCurrencyId: CurrencyId = CurrencyId::LAMI;
+	pub const GetLiquidityCurrencyId: CurrencyId = CurrencyId::AUSD;
+}
+
+type NativeCurrency = Currency<Runtime, GetNativeCurrencyId>;
+pub type LiquidityCurrency = Currency<Runtime, GetLiquidityCurrencyId>;
+
+impl orml_currencies::Trait for Runtime {
+	type Event = TestEvent;
+	type MultiCurrency = orml_tokens::Module<Runtime>;
+	type NativeCurrency = NativeCurrency;
+	type GetNativeCurrencyId = GetNativeCurrencyId;
+}
+
+parameter_types! {
+	pub MockPools: Vec<LiquidityPoolId> = vec![MOCK_POOL, ANOTHER_MOCK_POOL, THIRD_MOCK_POOL];
+}
+
+/// Liquidity of mock pools is the liquidity currency balance of the pool ID account. ALICE is the mock owner.
+pub type MockLiquidityPools = laminar_test_support::MockLiquidityPools<LiquidityCurrency, MockPools>;
+
+parameter_types! {
+	pub const InterestPeriod: u64 = 100;
+	pub const GetMaxPools: u32 = 2;
+	pub const GetMaxQueuedWithdrawals: u32 = 2;
+}
+
+impl Trait for Runtime {
+	type Event = TestEvent;
+	type LiquidityCurrency = LiquidityCurrency;
+	type LiquidityPools = MockLiquidityPools;
+	type PoolManager = MockPoolManager;
+	type InterestPeriod = InterestPeriod;
+	type GetMaxPools = GetMaxPools;
+	type GetMaxQueuedWithdrawals = GetMaxQueuedWithdrawals;
+	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
+}
+pub type EarnVault = Module<Runtime>;
+
+pub const ALICE: AccountId = 0;
+pub const BOB: AccountId = 1;
+/// Takes losses of mock pools.
+pub const TRADER: AccountId = 2;
+
+pub const MOCK_POOL: LiquidityPoolId = 100;
+pub const ANOTHER_MOCK_POOL: LiquidityPoolId = 101;
+pub const THIRD_MOCK_POOL: LiquidityPoolId = 102;
+
+pub struct ExtBuilder {
+	endowed_accounts: Vec<(AccountId, CurrencyId, Balance)>,
+}
+
+impl Default for ExtBuilder {
+	/// 10_000 for ALICE, BOB and the mock pools.
+	fn default() -> Self {
+		Self {
+			endowed_accounts: vec![
+				(ALICE, CurrencyId::AUSD, 10_000),
+				(BOB, CurrencyId::AUSD, 10_000),
+				(MOCK_POOL, CurrencyId::AUSD, 10_000),
+				(ANOTHER_MOCK_POOL, CurrencyId::AUSD, 10_000),
+			],
+		}
+	}
+}
+
+impl ExtBuilder {
+	pub fn pool_liquidity(mut self, pool_id: LiquidityPoolId, liquidity: Balance) -> Self {
+		self.endowed_accounts.retain(|(who, _, _)| *who != pool_id);
+		self.endowed_accounts.push((pool_id, CurrencyId::AUSD, liquidity));
+		self
+	}
+
+	pub fn build(self) -> sp_io::TestExternalities {
+		MockPoolManager::set_mock_can_withdraw(true);
+
+		let mut t = frame_system::GenesisConfig::default()
+			.build_storage::<Runtime>()
+			.unwrap();
+
+		orml_tokens::GenesisConfig::<Runtime> {
+			endowed_accounts: self.endowed_accounts,
+		}
+		.assimilate_storage(&mut t)
+		.unwrap();
+
+		let mut ext = sp_io::TestExternalities::new(t);
+		ext.execute_with(|| System::set_block_number(1));
+		ext
+	}
+}
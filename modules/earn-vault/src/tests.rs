@@ -0,0 +1,443 @@
+//! Unit tests for the earn vault module.
+
+#![cfg(test)]
+
+use super::*;
+use frame_support::{assert_noop, assert_ok};
+use mock::*;
+use orml_traits::BasicCurrency;
+
+fn approve(pool_id: LiquidityPoolId, weight: u32, interest_rate: Permill) -> DispatchResult {
+	EarnVault::approve_pool(
+		Origin::signed(UpdateOrigin::get()),
+		pool_id,
+		PoolConfig { weight, interest_rate },
+	)
+}
+
+fn deposit(who: AccountId, amount: Balance) -> DispatchResult {
+	EarnVault::deposit(Origin::signed(who), amount)
+}
+
+fn withdraw(who: AccountId, shares: Balance) -> DispatchResult {
+	EarnVault::withdraw(Origin::signed(who), shares)
+}
+
+fn rebalance() -> DispatchResult {
+	EarnVault::rebalance(Origin::signed(ALICE))
+}
+
+fn harvest(pool_id: LiquidityPoolId) -> DispatchResult {
+	EarnVault::harvest(Origin::signed(ALICE), pool_id)
+}
+
+fn balance(who: AccountId) -> Balance {
+	LiquidityCurrency::free_balance(&who)
+}
+
+fn principal(pool_id: LiquidityPoolId) -> Balance {
+	EarnVault::allocations(pool_id).map_or(0, |a| a.principal)
+}
+
+fn interest(pool_id: LiquidityPoolId) -> Balance {
+	EarnVault::allocations(pool_id).map_or(0, |a| a.interest)
+}
+
+#[test]
+fn deposit_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(deposit(BOB, 1000));
+		assert_eq!(EarnVault::shares(BOB), 1000);
+		assert_eq!(EarnVault::total_shares(), 1000);
+		assert_eq!(EarnVault::idle(), 1000);
+		assert_eq!(balance(BOB), 9000);
+		assert_eq!(balance(EarnVault::account_id()), 1000);
+		let event = TestEvent::earn_vault(RawEvent::Deposited(BOB, 1000, 1000));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_ok!(deposit(ALICE, 500));
+		assert_eq!(EarnVault::shares(ALICE), 500);
+		assert_eq!(EarnVault::total_shares(), 1500);
+		assert_eq!(EarnVault::total_assets(), 1500);
+	});
+}
+
+#[test]
+fn deposit_fails_if_zero() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(deposit(BOB, 0), Error::<Runtime>::ZeroAmount);
+	});
+}
+
+#[test]
+fn withdraw_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(deposit(BOB, 1000));
+		assert_ok!(withdraw(BOB, 400));
+		assert_eq!(EarnVault::shares(BOB), 600);
+		assert_eq!(EarnVault::total_shares(), 600);
+		assert_eq!(EarnVault::idle(), 600);
+		assert_eq!(balance(BOB), 9400);
+		let event = TestEvent::earn_vault(RawEvent::Withdrew(BOB, 400, 400));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn withdraw_fails_if_not_enough_shares() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(deposit(BOB, 1000));
+		assert_noop!(withdraw(BOB, 1001), Error::<Runtime>::InsufficientShares);
+		assert_noop!(withdraw(ALICE, 1), Error::<Runtime>::InsufficientShares);
+		assert_noop!(withdraw(BOB, 0), Error::<Runtime>::ZeroAmount);
+	});
+}
+
+#[test]
+fn approve_and_disapprove_pool_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		let config = PoolConfig {
+			weight: 1,
+			interest_rate: Permill::from_percent(10),
+		};
+		assert_noop!(
+			EarnVault::approve_pool(Origin::signed(ALICE), MOCK_POOL, config),
+			DispatchError::BadOrigin
+		);
+		assert_noop!(
+			EarnVault::approve_pool(Origin::signed(UpdateOrigin::get()), 999, config),
+			Error::<Runtime>::PoolNotFound
+		);
+
+		assert_ok!(EarnVault::approve_pool(
+			Origin::signed(UpdateOrigin::get()),
+			MOCK_POOL,
+			config
+		));
+		assert_eq!(EarnVault::pool_configs(MOCK_POOL), Some(config));
+		let event = TestEvent::earn_vault(RawEvent::PoolApproved(MOCK_POOL, config));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_ok!(EarnVault::disapprove_pool(
+			Origin::signed(UpdateOrigin::get()),
+			MOCK_POOL
+		));
+		assert_eq!(EarnVault::pool_configs(MOCK_POOL), None);
+		let event = TestEvent::earn_vault(RawEvent::PoolDisapproved(MOCK_POOL));
+		assert!(System::events().iter().any(|record| record.event == event));
+		assert_noop!(
+			EarnVault::disapprove_pool(Origin::signed(UpdateOrigin::get()), MOCK_POOL),
+			Error::<Runtime>::PoolNotApproved
+		);
+	});
+}
+
+#[test]
+fn approve_pool_fails_if_too_many_pools() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(approve(MOCK_POOL, 1, Permill::zero()));
+		assert_ok!(approve(ANOTHER_MOCK_POOL, 1, Permill::zero()));
+		assert_noop!(
+			approve(THIRD_MOCK_POOL, 1, Permill::zero()),
+			Error::<Runtime>::TooManyPools
+		);
+
+		// updating approved pools is fine
+		assert_ok!(approve(MOCK_POOL, 2, Permill::zero()));
+	});
+}
+
+#[test]
+fn rebalance_allocates_by_weights() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(approve(MOCK_POOL, 1, Permill::zero()));
+		assert_ok!(approve(ANOTHER_MOCK_POOL, 3, Permill::zero()));
+		assert_ok!(deposit(BOB, 1000));
+		assert_ok!(rebalance());
+
+		assert_eq!(principal(MOCK_POOL), 250);
+		assert_eq!(principal(ANOTHER_MOCK_POOL), 750);
+		assert_eq!(balance(MOCK_POOL), 10_250);
+		assert_eq!(balance(ANOTHER_MOCK_POOL), 10_750);
+		assert_eq!(EarnVault::idle(), 0);
+		assert_eq!(EarnVault::total_assets(), 1000);
+		let event = TestEvent::earn_vault(RawEvent::Allocated(MOCK_POOL, 250));
+		assert!(System::events().iter().any(|record| record.event == event));
+		let event = TestEvent::earn_vault(RawEvent::Allocated(ANOTHER_MOCK_POOL, 750));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		// reweighting moves allocations
+		assert_ok!(approve(MOCK_POOL, 3, Permill::zero()));
+		assert_ok!(approve(ANOTHER_MOCK_POOL, 1, Permill::zero()));
+		assert_ok!(rebalance());
+		assert_eq!(principal(MOCK_POOL), 750);
+		assert_eq!(principal(ANOTHER_MOCK_POOL), 250);
+		assert_eq!(EarnVault::idle(), 0);
+		let event = TestEvent::earn_vault(RawEvent::Deallocated(ANOTHER_MOCK_POOL, 500));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn rebalance_deallocates_disapproved_pools() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(approve(MOCK_POOL, 1, Permill::zero()));
+		assert_ok!(approve(ANOTHER_MOCK_POOL, 1, Permill::zero()));
+		assert_ok!(deposit(BOB, 1000));
+		assert_ok!(rebalance());
+
+		assert_ok!(EarnVault::disapprove_pool(
+			Origin::signed(UpdateOrigin::get()),
+			MOCK_POOL
+		));
+		assert_ok!(rebalance());
+		assert_eq!(EarnVault::allocations(MOCK_POOL), None);
+		assert_eq!(principal(ANOTHER_MOCK_POOL), 1000);
+		assert_eq!(balance(MOCK_POOL), 10_000);
+		assert_eq!(balance(ANOTHER_MOCK_POOL), 11_000);
+	});
+}
+
+#[test]
+fn queued_withdrawals_are_paid_on_rebalance() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(approve(MOCK_POOL, 1, Permill::zero()));
+		assert_ok!(approve(ANOTHER_MOCK_POOL, 3, Permill::zero()));
+		assert_ok!(deposit(BOB, 1000));
+		assert_ok!(rebalance());
+
+		// not enough idle balance
+		assert_ok!(withdraw(BOB, 500));
+		assert_eq!(EarnVault::withdrawal_queue(), vec![(BOB, 500)]);
+		assert_eq!(EarnVault::queued_shares(), 500);
+		assert_eq!(EarnVault::shares(BOB), 500);
+		assert_eq!(EarnVault::total_shares(), 1000);
+		assert_eq!(balance(BOB), 9000);
+		let event = TestEvent::earn_vault(RawEvent::WithdrawalQueued(BOB, 500));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_ok!(rebalance());
+		assert_eq!(EarnVault::withdrawal_queue(), vec![]);
+		assert_eq!(EarnVault::queued_shares(), 0);
+		assert_eq!(EarnVault::total_shares(), 500);
+		assert_eq!(balance(BOB), 9500);
+		let event = TestEvent::earn_vault(RawEvent::Withdrew(BOB, 500, 500));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		// the rest stays allocated by weights
+		assert_eq!(principal(MOCK_POOL), 125);
+		assert_eq!(principal(ANOTHER_MOCK_POOL), 375);
+		assert_eq!(EarnVault::idle(), 0);
+	});
+}
+
+#[test]
+fn withdrawals_queue_behind_queued_ones() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(approve(MOCK_POOL, 1, Permill::zero()));
+		assert_ok!(deposit(BOB, 1000));
+		assert_ok!(rebalance());
+		assert_ok!(withdraw(BOB, 600));
+
+		// idle balance from new deposits doesn't jump the queue
+		assert_ok!(deposit(ALICE, 100));
+		assert_ok!(withdraw(ALICE, 100));
+		assert_eq!(EarnVault::withdrawal_queue(), vec![(BOB, 600), (ALICE, 100)]);
+
+		assert_noop!(withdraw(BOB, 100), Error::<Runtime>::WithdrawalQueueFull);
+	});
+}
+
+#[test]
+fn withdrawals_stay_queued_if_pools_cannot_pay() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(approve(MOCK_POOL, 1, Permill::zero()));
+		assert_ok!(deposit(BOB, 1000));
+		assert_ok!(rebalance());
+		assert_ok!(withdraw(BOB, 600));
+
+		MockPoolManager::set_mock_can_withdraw(false);
+		assert_ok!(rebalance());
+		assert_eq!(EarnVault::withdrawal_queue(), vec![(BOB, 600)]);
+		assert_eq!(principal(MOCK_POOL), 1000);
+
+		MockPoolManager::set_mock_can_withdraw(true);
+		assert_ok!(rebalance());
+		assert_eq!(EarnVault::withdrawal_queue(), vec![]);
+		assert_eq!(balance(BOB), 9600);
+		assert_eq!(principal(MOCK_POOL), 400);
+	});
+}
+
+#[test]
+fn harvest_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(approve(MOCK_POOL, 1, Permill::from_percent(10)));
+		assert_ok!(deposit(BOB, 1000));
+		assert_ok!(rebalance());
+
+		System::set_block_number(51);
+		assert_ok!(harvest(MOCK_POOL));
+
+		// interest = 1000 * 10% * 50 / 100 = 50
+		let event = TestEvent::earn_vault(RawEvent::Harvested(MOCK_POOL, 50));
+		assert!(System::events().iter().any(|record| record.event == event));
+		assert_eq!(balance(MOCK_POOL), 10_000 + 1000 - 50);
+		assert_eq!(EarnVault::idle(), 50);
+		assert_eq!(interest(MOCK_POOL), 0);
+		assert_eq!(EarnVault::total_assets(), 1050);
+
+		// share price went up
+		assert_ok!(deposit(ALICE, 1050));
+		assert_eq!(EarnVault::shares(ALICE), 1000);
+	});
+}
+
+#[test]
+fn harvest_is_capped_by_pool_liquidity_above_principal() {
+	ExtBuilder::default()
+		.pool_liquidity(MOCK_POOL, 0)
+		.build()
+		.execute_with(|| {
+			assert_ok!(approve(MOCK_POOL, 1, Permill::from_percent(10)));
+			assert_ok!(deposit(BOB, 1000));
+			assert_ok!(rebalance());
+
+			System::set_block_number(51);
+			assert_ok!(harvest(MOCK_POOL));
+			assert_eq!(EarnVault::idle(), 0);
+			assert_eq!(interest(MOCK_POOL), 50);
+
+			assert_ok!(LiquidityCurrency::transfer(&ALICE, &MOCK_POOL, 30));
+			assert_ok!(harvest(MOCK_POOL));
+			assert_eq!(EarnVault::idle(), 30);
+			assert_eq!(interest(MOCK_POOL), 20);
+		});
+}
+
+#[test]
+fn share_price_includes_interest_not_harvested() {
+	ExtBuilder::default()
+		.pool_liquidity(MOCK_POOL, 0)
+		.build()
+		.execute_with(|| {
+			assert_ok!(approve(MOCK_POOL, 1, Permill::from_percent(10)));
+			assert_ok!(deposit(BOB, 1000));
+			assert_ok!(rebalance());
+
+			// the pool can't pay any interest yet
+			System::set_block_number(51);
+			assert_eq!(EarnVault::total_assets(), 1000);
+
+			assert_ok!(LiquidityCurrency::transfer(&ALICE, &MOCK_POOL, 100));
+			assert_eq!(EarnVault::total_assets(), 1050);
+
+			// deposits before harvest don't take the interest
+			assert_ok!(deposit(ALICE, 1050));
+			assert_eq!(EarnVault::shares(ALICE), 1000);
+			assert_eq!(interest(MOCK_POOL), 0);
+		});
+}
+
+#[test]
+fn harvest_fails_if_no_allocation_or_pool_cannot_pay() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(harvest(MOCK_POOL), Error::<Runtime>::NoAllocation);
+
+		assert_ok!(approve(MOCK_POOL, 1, Permill::from_percent(10)));
+		assert_ok!(deposit(BOB, 1000));
+		assert_ok!(rebalance());
+		System::set_block_number(51);
+		MockPoolManager::set_mock_can_withdraw(false);
+		assert_noop!(harvest(MOCK_POOL), DispatchError::Other("pool would be unsafe"));
+	});
+}
+
+#[test]
+fn interest_accrued_at_previous_rate_on_update() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(approve(MOCK_POOL, 1, Permill::from_percent(10)));
+		assert_ok!(deposit(BOB, 1000));
+		assert_ok!(rebalance());
+
+		System::set_block_number(51);
+		assert_ok!(approve(MOCK_POOL, 1, Permill::from_percent(20)));
+		assert_eq!(interest(MOCK_POOL), 50);
+
+		System::set_block_number(101);
+		assert_ok!(harvest(MOCK_POOL));
+		// 50 + 1000 * 20% * 50 / 100
+		let event = TestEvent::earn_vault(RawEvent::Harvested(MOCK_POOL, 150));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn pool_losses_are_realized_by_vault() {
+	ExtBuilder::default()
+		.pool_liquidity(MOCK_POOL, 0)
+		.build()
+		.execute_with(|| {
+			assert_ok!(approve(MOCK_POOL, 1, Permill::zero()));
+			assert_ok!(deposit(BOB, 1000));
+			assert_ok!(rebalance());
+
+			// the pool paid out 400 to traders
+			assert_ok!(LiquidityCurrency::transfer(&MOCK_POOL, &TRADER, 400));
+			assert_eq!(EarnVault::total_assets(), 600);
+
+			// new deposits at the lower share price
+			assert_ok!(deposit(ALICE, 600));
+			assert_eq!(EarnVault::shares(ALICE), 1000);
+
+			assert_ok!(rebalance());
+			let event = TestEvent::earn_vault(RawEvent::LossRealized(MOCK_POOL, 400));
+			assert!(System::events().iter().any(|record| record.event == event));
+			assert_eq!(principal(MOCK_POOL), 1200);
+			assert_eq!(EarnVault::total_assets(), 1200);
+		});
+}
+
+#[test]
+fn owner_cannot_withdraw_vault_allocation() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(approve(MOCK_POOL, 1, Permill::from_percent(10)));
+		assert_ok!(deposit(BOB, 1000));
+		assert_ok!(rebalance());
+
+		assert_ok!(EarnVault::ensure_can_withdraw(MOCK_POOL, 10_000));
+		assert_noop!(
+			EarnVault::ensure_can_withdraw(MOCK_POOL, 10_001),
+			Error::<Runtime>::CannotWithdrawVaultAllocation
+		);
+
+		// including interest owed
+		System::set_block_number(51);
+		assert_ok!(EarnVault::ensure_can_withdraw(MOCK_POOL, 9_950));
+		assert_noop!(
+			EarnVault::ensure_can_withdraw(MOCK_POOL, 9_951),
+			Error::<Runtime>::CannotWithdrawVaultAllocation
+		);
+
+		assert_ok!(EarnVault::ensure_can_withdraw(ANOTHER_MOCK_POOL, 10_000));
+	});
+}
+
+#[test]
+fn pool_with_allocation_cannot_be_removed() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert!(EarnVault::can_remove(MOCK_POOL));
+
+		assert_ok!(approve(MOCK_POOL, 1, Permill::zero()));
+		assert_ok!(deposit(BOB, 1000));
+		assert_ok!(rebalance());
+		assert!(!EarnVault::can_remove(MOCK_POOL));
+
+		assert_ok!(EarnVault::disapprove_pool(
+			Origin::signed(UpdateOrigin::get()),
+			MOCK_POOL
+		));
+		assert_ok!(rebalance());
+		assert!(EarnVault::can_remove(MOCK_POOL));
+	});
+}
@@ -0,0 +1,167 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage, ensure,
+	traits::{EnsureOrigin, Filter, GetCallMetadata},
+	IsSubType,
+};
+use frame_system as system;
+use orml_utilities::with_transaction_result;
+use sp_runtime::traits::{Saturating, Zero};
+use sp_std::{marker::PhantomData, prelude::Vec};
+
+use module_primitives::CurrencyId;
+
+mod mock;
+mod tests;
+
+pub trait Trait: frame_system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+
+	/// Required origin for pausing and unpausing calls, and halting and resuming markets.
+	type UpdateOrigin: EnsureOrigin<Self::Origin>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as EmergencyControl {
+		/// Paused calls, keyed by pallet name and function name. The value is the block number at which the pause
+		/// expires.
+		PausedCalls get(fn paused_calls): double_map hasher(blake2_128_concat) Vec<u8>, hasher(blake2_128_concat) Vec<u8> => Option<T::BlockNumber>;
+
+		/// Halted markets. Prices of halted currencies are unavailable until resumed.
+		HaltedMarkets get(fn halted_markets): map hasher(twox_64_concat) CurrencyId => bool;
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where
+		<T as frame_system::Trait>::BlockNumber,
+	{
+		/// Call paused: [pallet_name, function_name, expires_at]
+		CallPaused(Vec<u8>, Vec<u8>, BlockNumber),
+
+		/// Call unpaused: [pallet_name, function_name]
+		CallUnpaused(Vec<u8>, Vec<u8>),
+
+		/// Market halted: [currency_id]
+		MarketHalted(CurrencyId),
+
+		/// Market resumed: [currency_id]
+		MarketResumed(CurrencyId),
+	}
+}
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// Pause duration is zero.
+		ZeroDuration,
+		/// The call is not paused.
+		NotPaused,
+		/// The market is already halted.
+		MarketAlreadyHalted,
+		/// The market is not halted.
+		MarketNotHalted,
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		type Error = Error<T>;
+
+		fn deposit_event() = default;
+
+		/// Pause `function_name` of `pallet_name` for `duration` blocks. Pausing an already paused call overrides its
+		/// expiry.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn pause_call(origin, pallet_name: Vec<u8>, function_name: Vec<u8>, duration: T::BlockNumber) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				ensure!(!duration.is_zero(), Error::<T>::ZeroDuration);
+
+				let expires_at = <system::Module<T>>::block_number().saturating_add(duration);
+				PausedCalls::<T>::insert(&pallet_name, &function_name, expires_at);
+				Self::deposit_event(RawEvent::CallPaused(pallet_name, function_name, expires_at));
+				Ok(())
+			})?;
+		}
+
+		/// Unpause `function_name` of `pallet_name` before its pause expires.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn unpause_call(origin, pallet_name: Vec<u8>, function_name: Vec<u8>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				ensure!(PausedCalls::<T>::contains_key(&pallet_name, &function_name), Error::<T>::NotPaused);
+
+				PausedCalls::<T>::remove(&pallet_name, &function_name);
+				Self::deposit_event(RawEvent::CallUnpaused(pallet_name, function_name));
+				Ok(())
+			})?;
+		}
+
+		/// Halt the market of `currency_id`. Its price is unavailable until resumed.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn halt_market(origin, currency_id: CurrencyId) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				ensure!(!Self::halted_markets(currency_id), Error::<T>::MarketAlreadyHalted);
+
+				HaltedMarkets::insert(currency_id, true);
+				Self::deposit_event(RawEvent::MarketHalted(currency_id));
+				Ok(())
+			})?;
+		}
+
+		/// Resume the halted market of `currency_id`.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn resume_market(origin, currency_id: CurrencyId) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				ensure!(Self::halted_markets(currency_id), Error::<T>::MarketNotHalted);
+
+				HaltedMarkets::remove(currency_id);
+				Self::deposit_event(RawEvent::MarketResumed(currency_id));
+				Ok(())
+			})?;
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Whether `function_name` of `pallet_name` is paused at the current block.
+	pub fn is_paused(pallet_name: &[u8], function_name: &[u8]) -> bool {
+		Self::paused_calls(pallet_name, function_name)
+			.map_or(false, |expires_at| <system::Module<T>>::block_number() < expires_at)
+	}
+
+	/// Whether the market of `currency_id` is halted.
+	pub fn is_market_halted(currency_id: CurrencyId) -> bool {
+		Self::halted_markets(currency_id)
+	}
+}
+
+/// Call filter rejecting paused calls.
+///
+/// Calls of this module are never filtered, so that paused calls could always be unpaused.
+pub struct PausedCallFilter<T>(PhantomData<T>);
+impl<T: Trait> Filter<T::Call> for PausedCallFilter<T>
+where
+	T::Call: GetCallMetadata + IsSubType<Module<T>, T>,
+{
+	fn filter(call: &T::Call) -> bool {
+		if call.is_sub_type().is_some() {
+			return true;
+		}
+
+		let metadata = call.get_call_metadata();
+		!Module::<T>::is_paused(metadata.pallet_name.as_bytes(), metadata.function_name.as_bytes())
+	}
+}
@@ -0,0 +1,76 @@
+//! Mocks for the emergency-control module.
+
+#![cfg(test)]
+
+use frame_support::{impl_outer_dispatch, impl_outer_event, impl_outer_origin, ord_parameter_types};
+use frame_system as system;
+use system::EnsureSignedBy;
+
+use super::*;
+
+impl_outer_origin! {
+	pub enum Origin for Runtime {}
+}
+
+impl_outer_dispatch! {
+	pub enum Call for Runtime where origin: Origin {
+		frame_system::System,
+		emergency_control::EmergencyControl,
+	}
+}
+
+mod emergency_control {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for Runtime {
+		frame_system<T>,
+		emergency_control<T>,
+	}
+}
+
+ord_parameter_types! {
+	pub const UpdateOrigin: AccountId = 0;
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Runtime;
+
+type AccountId = u64;
+laminar_test_support::impl_mock_system!(Runtime, AccountId, TestEvent, (), Call, PausedCallFilter<Runtime>);
+pub type System = system::Module<Runtime>;
+
+impl Trait for Runtime {
+	type Event = TestEvent;
+	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
+}
+
+pub type EmergencyControl = Module<Runtime>;
+
+const ALICE_ACC_ID: AccountId = 0;
+pub fn alice() -> Origin {
+	Origin::signed(ALICE_ACC_ID)
+}
+
+const BOB_ACC_ID: AccountId = 1;
+pub fn bob() -> Origin {
+	Origin::signed(BOB_ACC_ID)
+}
+
+#[derive(Default)]
+pub struct ExtBuilder;
+
+impl ExtBuilder {
+	pub fn build(self) -> sp_io::TestExternalities {
+		let t = frame_system::GenesisConfig::default()
+			.build_storage::<Runtime>()
+			.unwrap()
+			.into();
+
+		let mut ext = sp_io::TestExternalities::new(t);
+		ext.execute_with(|| System::set_block_number(1));
+		ext
+	}
+}
@@ -0,0 +1,152 @@
+//! Unit tests for the emergency-control module.
+
+#![cfg(test)]
+
+use super::*;
+use mock::*;
+// The outer call, rather than the call of this module.
+use mock::Call;
+
+use frame_support::{assert_noop, assert_ok};
+use module_primitives::CurrencyId;
+use sp_runtime::traits::BadOrigin;
+
+fn remark_call() -> Call {
+	Call::System(frame_system::Call::remark(vec![]))
+}
+
+fn set_heap_pages_call() -> Call {
+	Call::System(frame_system::Call::set_heap_pages(1))
+}
+
+#[test]
+fn pause_call_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert!(PausedCallFilter::<Runtime>::filter(&remark_call()));
+
+		assert_ok!(EmergencyControl::pause_call(alice(), b"System".to_vec(), b"remark".to_vec(), 10));
+		assert_eq!(EmergencyControl::paused_calls(b"System".to_vec(), b"remark".to_vec()), Some(11));
+
+		let event = TestEvent::emergency_control(RawEvent::CallPaused(b"System".to_vec(), b"remark".to_vec(), 11));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert!(!PausedCallFilter::<Runtime>::filter(&remark_call()));
+		// other calls of the same pallet are not paused
+		assert!(PausedCallFilter::<Runtime>::filter(&set_heap_pages_call()));
+	});
+}
+
+#[test]
+fn pause_call_requires_update_origin() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			EmergencyControl::pause_call(bob(), b"System".to_vec(), b"remark".to_vec(), 10),
+			BadOrigin
+		);
+		assert_noop!(
+			EmergencyControl::unpause_call(bob(), b"System".to_vec(), b"remark".to_vec()),
+			BadOrigin
+		);
+	});
+}
+
+#[test]
+fn pause_call_fails_if_zero_duration() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			EmergencyControl::pause_call(alice(), b"System".to_vec(), b"remark".to_vec(), 0),
+			Error::<Runtime>::ZeroDuration
+		);
+	});
+}
+
+#[test]
+fn pause_expires() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(EmergencyControl::pause_call(alice(), b"System".to_vec(), b"remark".to_vec(), 10));
+
+		System::set_block_number(10);
+		assert!(!PausedCallFilter::<Runtime>::filter(&remark_call()));
+
+		System::set_block_number(11);
+		assert!(PausedCallFilter::<Runtime>::filter(&remark_call()));
+		assert!(!EmergencyControl::is_paused(b"System", b"remark"));
+	});
+}
+
+#[test]
+fn unpause_call_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			EmergencyControl::unpause_call(alice(), b"System".to_vec(), b"remark".to_vec()),
+			Error::<Runtime>::NotPaused
+		);
+
+		assert_ok!(EmergencyControl::pause_call(alice(), b"System".to_vec(), b"remark".to_vec(), 10));
+		assert_ok!(EmergencyControl::unpause_call(alice(), b"System".to_vec(), b"remark".to_vec()));
+		assert_eq!(EmergencyControl::paused_calls(b"System".to_vec(), b"remark".to_vec()), None);
+		assert!(PausedCallFilter::<Runtime>::filter(&remark_call()));
+
+		let event = TestEvent::emergency_control(RawEvent::CallUnpaused(b"System".to_vec(), b"remark".to_vec()));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn calls_of_this_module_cannot_be_paused() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(EmergencyControl::pause_call(
+			alice(),
+			b"EmergencyControl".to_vec(),
+			b"unpause_call".to_vec(),
+			10
+		));
+
+		let unpause = Call::EmergencyControl(crate::Call::unpause_call(b"System".to_vec(), b"remark".to_vec()));
+		assert!(PausedCallFilter::<Runtime>::filter(&unpause));
+	});
+}
+
+#[test]
+fn halt_market_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert!(!EmergencyControl::is_market_halted(CurrencyId::FEUR));
+
+		assert_ok!(EmergencyControl::halt_market(alice(), CurrencyId::FEUR));
+		assert!(EmergencyControl::is_market_halted(CurrencyId::FEUR));
+		assert!(!EmergencyControl::is_market_halted(CurrencyId::FJPY));
+
+		let event = TestEvent::emergency_control(RawEvent::MarketHalted(CurrencyId::FEUR));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_noop!(
+			EmergencyControl::halt_market(alice(), CurrencyId::FEUR),
+			Error::<Runtime>::MarketAlreadyHalted
+		);
+	});
+}
+
+#[test]
+fn resume_market_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			EmergencyControl::resume_market(alice(), CurrencyId::FEUR),
+			Error::<Runtime>::MarketNotHalted
+		);
+
+		assert_ok!(EmergencyControl::halt_market(alice(), CurrencyId::FEUR));
+		assert_ok!(EmergencyControl::resume_market(alice(), CurrencyId::FEUR));
+		assert!(!EmergencyControl::is_market_halted(CurrencyId::FEUR));
+
+		let event = TestEvent::emergency_control(RawEvent::MarketResumed(CurrencyId::FEUR));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn halt_market_requires_update_origin() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(EmergencyControl::halt_market(bob(), CurrencyId::FEUR), BadOrigin);
+		assert_noop!(EmergencyControl::resume_market(bob(), CurrencyId::FEUR), BadOrigin);
+	});
+}
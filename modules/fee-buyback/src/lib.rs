@@ -0,0 +1,239 @@
+//! Buyback and burn of the native currency with protocol fees.
+//!
+//! Every `BuybackPeriod` blocks, a governance-set share of the protocol fees collected in the treasury account is
+//! auctioned for the native currency, and the winning bid is burned. Auctions are ascending: each bid offers more
+//! native currency for the whole lot than the previous one, which is refunded. An auction ends when the next one
+//! starts; unsold lots are carried over to the next auction.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Decode, Encode};
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage, ensure,
+	traits::{EnsureOrigin, Get},
+	weights::Weight,
+};
+use sp_arithmetic::{
+	traits::{Saturating, Zero},
+	Permill,
+};
+use sp_runtime::{traits::AccountIdConversion, DispatchResult, ModuleId, RuntimeDebug};
+// FIXME: `pallet/frame-` prefix should be used for all pallet modules, but currently `frame_system`
+// would cause compiling error in `decl_module!` and `construct_runtime!`
+// #3295 https://github.com/paritytech/substrate/issues/3295
+use frame_system::{self as system, ensure_signed};
+
+use orml_traits::BasicCurrency;
+use orml_utilities::with_transaction_result;
+
+use module_primitives::Balance;
+
+mod mock;
+mod tests;
+
+const MODULE_ID: ModuleId = ModuleId(*b"lami/fbb");
+
+/// Computation weight of settling an auction and starting the next one, excluding storage accesses.
+const ROLL_OVER_WEIGHT: Weight = 50_000;
+/// Storage reads of settling an auction and starting the next one: auction, ratio and balances.
+const ROLL_OVER_READS: Weight = 7;
+/// Storage writes of settling an auction and starting the next one: auction, total burned and balances.
+const ROLL_OVER_WRITES: Weight = 7;
+
+pub trait Trait: frame_system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+
+	/// The currency protocol fees are collected in.
+	type FeeCurrency: BasicCurrency<Self::AccountId, Balance = Balance>;
+
+	/// The native currency bought back and burned.
+	type NativeCurrency: BasicCurrency<Self::AccountId, Balance = Balance>;
+
+	/// The account protocol fees are collected into.
+	type GetTreasuryAccountId: Get<Self::AccountId>;
+
+	/// Number of blocks between auction starts. `0` means no auctions.
+	type BuybackPeriod: Get<Self::BlockNumber>;
+
+	/// Minimum increment of a bid over the previous bid.
+	type MinBidIncrement: Get<Permill>;
+
+	/// Required origin for setting the buyback ratio.
+	type UpdateOrigin: EnsureOrigin<Self::Origin>;
+}
+
+/// Auction of protocol fees for the native currency.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct BuybackAuction<AccountId, BlockNumber> {
+	/// Protocol fees auctioned, held by the module account.
+	pub lot: Balance,
+	/// The highest bidder and the native currency bid, held by the module account.
+	pub bid: Option<(AccountId, Balance)>,
+	/// The block the auction ends at, when the next auction starts.
+	pub end: BlockNumber,
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as FeeBuyback {
+		/// Share of the protocol fees in the treasury account auctioned in each period.
+		BuybackRatio get(fn buyback_ratio): Permill;
+
+		/// The ongoing auction.
+		Auction get(fn auction): Option<BuybackAuction<T::AccountId, T::BlockNumber>>;
+
+		/// Total native currency burned.
+		TotalBurned get(fn total_burned): Balance;
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where
+		<T as frame_system::Trait>::AccountId,
+		<T as frame_system::Trait>::BlockNumber,
+	{
+		/// Buyback ratio set: [ratio]
+		BuybackRatioSet(Permill),
+
+		/// Auction started: [lot, fees_from_treasury, end]
+		AuctionStarted(Balance, Balance, BlockNumber),
+
+		/// Bid placed: [who, native_amount]
+		BidPlaced(AccountId, Balance),
+
+		/// Fees bought back and native currency burned: [who, fee_amount, native_amount]
+		BoughtBack(AccountId, Balance, Balance),
+
+		/// Auction ended without bids, and the lot is carried over: [lot]
+		AuctionUnsold(Balance),
+
+		/// Settling the auction and starting the next one failed, and is deferred to the next period.
+		AuctionDeferred,
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		type Error = Error<T>;
+
+		fn deposit_event() = default;
+
+		const BuybackPeriod: T::BlockNumber = T::BuybackPeriod::get();
+		const MinBidIncrement: Permill = T::MinBidIncrement::get();
+
+		/// Bid `amount` of the native currency for the lot of the ongoing auction.
+		///
+		/// The bid is held until outbid, or burned when the auction ends.
+		#[weight = 10_000]
+		pub fn bid(origin, #[compact] amount: Balance) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::do_bid(&who, amount)?;
+				Self::deposit_event(RawEvent::BidPlaced(who, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Set the share of the protocol fees in the treasury account auctioned in each period.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_buyback_ratio(origin, ratio: Permill) {
+			T::UpdateOrigin::ensure_origin(origin)?;
+			BuybackRatio::put(ratio);
+			Self::deposit_event(RawEvent::BuybackRatioSet(ratio));
+		}
+
+		fn on_initialize(now: T::BlockNumber) -> Weight {
+			let period = T::BuybackPeriod::get();
+			if period.is_zero() || !(now % period).is_zero() {
+				return 0;
+			}
+
+			if with_transaction_result(|| Self::roll_over_auction(now, period)).is_err() {
+				Self::deposit_event(RawEvent::AuctionDeferred);
+			}
+
+			ROLL_OVER_WEIGHT.saturating_add(T::DbWeight::get().reads_writes(ROLL_OVER_READS, ROLL_OVER_WRITES))
+		}
+	}
+}
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// No ongoing auction.
+		NoAuction,
+
+		/// Bid is not higher than the previous bid by the minimum increment.
+		BidTooLow,
+	}
+}
+
+// Dispatchable calls implementation
+impl<T: Trait> Module<T> {
+	fn do_bid(who: &T::AccountId, amount: Balance) -> DispatchResult {
+		let mut auction = Self::auction().ok_or(Error::<T>::NoAuction)?;
+
+		ensure!(!amount.is_zero(), Error::<T>::BidTooLow);
+		if let Some((_, previous)) = &auction.bid {
+			// min_bid = previous_bid * (1 + min_bid_increment)
+			let min_bid = previous.saturating_add(T::MinBidIncrement::get() * *previous);
+			ensure!(amount > min_bid, Error::<T>::BidTooLow);
+		}
+
+		T::NativeCurrency::transfer(who, &Self::account_id(), amount)?;
+		if let Some((bidder, previous)) = auction.bid.take() {
+			T::NativeCurrency::transfer(&Self::account_id(), &bidder, previous)?;
+		}
+
+		auction.bid = Some((who.clone(), amount));
+		<Auction<T>>::put(auction);
+
+		Ok(())
+	}
+}
+
+// Private methods
+impl<T: Trait> Module<T> {
+	pub fn account_id() -> T::AccountId {
+		MODULE_ID.into_account()
+	}
+
+	/// Settle the ongoing auction, and start the next one ending in `period`.
+	fn roll_over_auction(now: T::BlockNumber, period: T::BlockNumber) -> DispatchResult {
+		if let Some(auction) = <Auction<T>>::take() {
+			Self::settle_auction(auction)?;
+		}
+
+		let treasury = T::GetTreasuryAccountId::get();
+		let fees = Self::buyback_ratio() * T::FeeCurrency::free_balance(&treasury);
+		T::FeeCurrency::transfer(&treasury, &Self::account_id(), fees)?;
+
+		// unsold lots are still in the module account
+		let lot = T::FeeCurrency::free_balance(&Self::account_id());
+		if lot.is_zero() {
+			return Ok(());
+		}
+
+		let end = now.saturating_add(period);
+		<Auction<T>>::put(BuybackAuction { lot, bid: None, end });
+		Self::deposit_event(RawEvent::AuctionStarted(lot, fees, end));
+
+		Ok(())
+	}
+
+	/// Pay the lot to the highest bidder and burn the bid.
+	fn settle_auction(auction: BuybackAuction<T::AccountId, T::BlockNumber>) -> DispatchResult {
+		match auction.bid {
+			Some((who, amount)) => {
+				T::NativeCurrency::withdraw(&Self::account_id(), amount)?;
+				T::FeeCurrency::transfer(&Self::account_id(), &who, auction.lot)?;
+				TotalBurned::mutate(|burned| *burned = burned.saturating_add(amount));
+				Self::deposit_event(RawEvent::BoughtBack(who, auction.lot, amount));
+			}
+			None => Self::deposit_event(RawEvent::AuctionUnsold(auction.lot)),
+		}
+
+		Ok(())
+	}
+}
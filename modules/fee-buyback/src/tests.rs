@@ -0,0 +1,151 @@
+//! Unit tests for the fee buyback module.
+
+#![cfg(test)]
+
+use super::*;
+use frame_support::{assert_noop, assert_ok, traits::OnInitialize};
+use mock::*;
+use sp_runtime::DispatchError::BadOrigin;
+
+fn bid(who: AccountId, amount: Balance) -> DispatchResult {
+	FeeBuyback::bid(Origin::signed(who), amount)
+}
+
+fn run_to_block(n: u64) {
+	System::set_block_number(n);
+	<FeeBuyback as OnInitialize<u64>>::on_initialize(n);
+}
+
+fn auction(lot: Balance, bid: Option<(AccountId, Balance)>, end: u64) -> Option<BuybackAuction<AccountId, u64>> {
+	Some(BuybackAuction { lot, bid, end })
+}
+
+#[test]
+fn set_buyback_ratio_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			FeeBuyback::set_buyback_ratio(Origin::signed(ALICE), Permill::from_percent(10)),
+			BadOrigin
+		);
+
+		assert_ok!(FeeBuyback::set_buyback_ratio(
+			Origin::signed(UpdateOrigin::get()),
+			Permill::from_percent(10)
+		));
+		assert_eq!(FeeBuyback::buyback_ratio(), Permill::from_percent(10));
+		let event = TestEvent::fee_buyback(RawEvent::BuybackRatioSet(Permill::from_percent(10)));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn auction_starts_with_share_of_fees() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(FeeBuyback::set_buyback_ratio(
+			Origin::signed(UpdateOrigin::get()),
+			Permill::from_percent(10)
+		));
+
+		// not at period start
+		run_to_block(5);
+		assert_eq!(FeeBuyback::auction(), None);
+
+		run_to_block(10);
+		assert_eq!(FeeBuyback::auction(), auction(1_000, None, 20));
+		assert_eq!(FeeCurrency::free_balance(&TREASURY), 9_000);
+		assert_eq!(FeeCurrency::free_balance(&FeeBuyback::account_id()), 1_000);
+		let event = TestEvent::fee_buyback(RawEvent::AuctionStarted(1_000, 1_000, 20));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn no_auction_without_fees() {
+	ExtBuilder::default().treasury_fees(0).build().execute_with(|| {
+		assert_ok!(FeeBuyback::set_buyback_ratio(
+			Origin::signed(UpdateOrigin::get()),
+			Permill::from_percent(10)
+		));
+		run_to_block(10);
+		assert_eq!(FeeBuyback::auction(), None);
+		assert_noop!(bid(ALICE, 100), Error::<Runtime>::NoAuction);
+	});
+
+	ExtBuilder::default().build().execute_with(|| {
+		run_to_block(10);
+		assert_eq!(FeeBuyback::auction(), None);
+		assert_eq!(FeeCurrency::free_balance(&TREASURY), 10_000);
+	});
+}
+
+#[test]
+fn bid_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(FeeBuyback::set_buyback_ratio(
+			Origin::signed(UpdateOrigin::get()),
+			Permill::from_percent(10)
+		));
+		run_to_block(10);
+
+		assert_noop!(bid(ALICE, 0), Error::<Runtime>::BidTooLow);
+		assert_ok!(bid(ALICE, 100));
+		assert_eq!(NativeCurrency::free_balance(&ALICE), 900);
+		let event = TestEvent::fee_buyback(RawEvent::BidPlaced(ALICE, 100));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		// 10% min increment
+		assert_noop!(bid(BOB, 110), Error::<Runtime>::BidTooLow);
+		assert_ok!(bid(BOB, 111));
+
+		// outbid ALICE is refunded
+		assert_eq!(NativeCurrency::free_balance(&ALICE), 1_000);
+		assert_eq!(NativeCurrency::free_balance(&BOB), 889);
+		assert_eq!(NativeCurrency::free_balance(&FeeBuyback::account_id()), 111);
+		assert_eq!(FeeBuyback::auction(), auction(1_000, Some((BOB, 111)), 20));
+	});
+}
+
+#[test]
+fn winning_bid_is_burned() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(FeeBuyback::set_buyback_ratio(
+			Origin::signed(UpdateOrigin::get()),
+			Permill::from_percent(10)
+		));
+		run_to_block(10);
+		assert_ok!(bid(BOB, 100));
+
+		run_to_block(20);
+		assert_eq!(FeeCurrency::free_balance(&BOB), 1_000);
+		assert_eq!(NativeCurrency::free_balance(&BOB), 900);
+		assert_eq!(NativeCurrency::free_balance(&FeeBuyback::account_id()), 0);
+		assert_eq!(NativeCurrency::total_issuance(), 1_900);
+		assert_eq!(FeeBuyback::total_burned(), 100);
+		let event = TestEvent::fee_buyback(RawEvent::BoughtBack(BOB, 1_000, 100));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		// the next auction with 10% of the rest of the fees
+		assert_eq!(FeeBuyback::auction(), auction(900, None, 30));
+		let event = TestEvent::fee_buyback(RawEvent::AuctionStarted(900, 900, 30));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn unsold_lot_is_carried_over() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(FeeBuyback::set_buyback_ratio(
+			Origin::signed(UpdateOrigin::get()),
+			Permill::from_percent(10)
+		));
+		run_to_block(10);
+
+		run_to_block(20);
+		let event = TestEvent::fee_buyback(RawEvent::AuctionUnsold(1_000));
+		assert!(System::events().iter().any(|record| record.event == event));
+		assert_eq!(FeeBuyback::auction(), auction(1_900, None, 30));
+		let event = TestEvent::fee_buyback(RawEvent::AuctionStarted(1_900, 900, 30));
+		assert!(System::events().iter().any(|record| record.event == event));
+		assert_eq!(FeeBuyback::total_burned(), 0);
+	});
+}
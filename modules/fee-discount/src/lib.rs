@@ -0,0 +1,180 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Decode, Encode};
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage, ensure,
+	traits::{Currency, EnsureOrigin, Get, LockIdentifier, LockableCurrency, WithdrawReasons},
+};
+use frame_system::{self as system, ensure_signed};
+use orml_utilities::with_transaction_result;
+use sp_runtime::{
+	traits::{Saturating, Zero},
+	Permill, RuntimeDebug,
+};
+use sp_std::prelude::Vec;
+
+use module_primitives::Balance;
+use module_traits::FeeDiscount;
+
+mod mock;
+mod tests;
+
+const FEE_DISCOUNT_ID: LockIdentifier = *b"feedisct";
+
+/// Locked tokens of an account.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct LockInfo<BlockNumber> {
+	/// The locked amount.
+	pub amount: Balance,
+	/// The block number from which the locked amount could be unlocked.
+	pub unlockable_at: BlockNumber,
+}
+
+pub trait Trait: frame_system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+
+	/// The currency to be locked for fee discounts.
+	type Currency: LockableCurrency<Self::AccountId, Balance = Balance, Moment = Self::BlockNumber>;
+
+	/// The number of blocks locked tokens must stay locked after the last lock.
+	type MinLockPeriod: Get<Self::BlockNumber>;
+
+	/// Required origin for updating discount tiers.
+	type UpdateOrigin: EnsureOrigin<Self::Origin>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as FeeDiscount {
+		/// Locked tokens of accounts.
+		Locks get(fn locks): map hasher(twox_64_concat) T::AccountId => Option<LockInfo<T::BlockNumber>>;
+
+		/// Discount tiers: `(min_locked_amount, discount)`, in strictly ascending order of `min_locked_amount`.
+		DiscountTiers get(fn discount_tiers): Vec<(Balance, Permill)>;
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where
+		<T as frame_system::Trait>::AccountId,
+		<T as frame_system::Trait>::BlockNumber,
+	{
+		/// Tokens locked: [who, amount, unlockable_at]
+		Locked(AccountId, Balance, BlockNumber),
+
+		/// Tokens unlocked: [who, amount]
+		Unlocked(AccountId, Balance),
+
+		/// Discount tiers set: [tiers]
+		DiscountTiersSet(Vec<(Balance, Permill)>),
+	}
+}
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// Lock or unlock amount is zero.
+		ZeroAmount,
+		/// Free balance is not enough for the lock.
+		InsufficientBalance,
+		/// Locked amount is not enough for the unlock.
+		InsufficientLocked,
+		/// Locked tokens are still in the min lock period.
+		LockPeriodNotEnded,
+		/// Discount tiers are not in strictly ascending order of min locked amount.
+		InvalidDiscountTiers,
+		/// Number out of bound in calculation.
+		NumOutOfBound,
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		type Error = Error<T>;
+
+		fn deposit_event() = default;
+
+		const MinLockPeriod: T::BlockNumber = T::MinLockPeriod::get();
+
+		/// Lock `amount` of tokens for fee discounts. Locking restarts the min lock period of all locked tokens.
+		#[weight = 10_000]
+		pub fn lock(origin, #[compact] amount: Balance) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				ensure!(!amount.is_zero(), Error::<T>::ZeroAmount);
+
+				let locked = Self::locks(&who).map_or(Zero::zero(), |l| l.amount);
+				let new_locked = locked.checked_add(amount).ok_or(Error::<T>::NumOutOfBound)?;
+				ensure!(T::Currency::free_balance(&who) >= new_locked, Error::<T>::InsufficientBalance);
+
+				let unlockable_at = <system::Module<T>>::block_number().saturating_add(T::MinLockPeriod::get());
+				T::Currency::set_lock(FEE_DISCOUNT_ID, &who, new_locked, WithdrawReasons::all());
+				<Locks<T>>::insert(&who, LockInfo { amount: new_locked, unlockable_at });
+
+				Self::deposit_event(RawEvent::Locked(who, amount, unlockable_at));
+				Ok(())
+			})?;
+		}
+
+		/// Unlock `amount` of locked tokens. Only allowed after the min lock period ended.
+		#[weight = 10_000]
+		pub fn unlock(origin, #[compact] amount: Balance) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				ensure!(!amount.is_zero(), Error::<T>::ZeroAmount);
+
+				let lock = Self::locks(&who).ok_or(Error::<T>::InsufficientLocked)?;
+				let new_locked = lock.amount.checked_sub(amount).ok_or(Error::<T>::InsufficientLocked)?;
+				ensure!(
+					<system::Module<T>>::block_number() >= lock.unlockable_at,
+					Error::<T>::LockPeriodNotEnded
+				);
+
+				if new_locked.is_zero() {
+					T::Currency::remove_lock(FEE_DISCOUNT_ID, &who);
+					<Locks<T>>::remove(&who);
+				} else {
+					T::Currency::set_lock(FEE_DISCOUNT_ID, &who, new_locked, WithdrawReasons::all());
+					<Locks<T>>::insert(&who, LockInfo { amount: new_locked, ..lock });
+				}
+
+				Self::deposit_event(RawEvent::Unlocked(who, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Set discount tiers.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_discount_tiers(origin, tiers: Vec<(Balance, Permill)>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				ensure!(
+					tiers.windows(2).all(|w| w[0].0 < w[1].0),
+					Error::<T>::InvalidDiscountTiers
+				);
+
+				DiscountTiers::put(tiers.clone());
+				Self::deposit_event(RawEvent::DiscountTiersSet(tiers));
+				Ok(())
+			})?;
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// The discount of the highest tier whose min locked amount is reached by `locked`.
+	pub fn discount_of(locked: Balance) -> Permill {
+		Self::discount_tiers()
+			.into_iter()
+			.rev()
+			.find(|(min_locked, _)| locked >= *min_locked)
+			.map_or(Permill::zero(), |(_, discount)| discount)
+	}
+}
+
+impl<T: Trait> FeeDiscount<T::AccountId> for Module<T> {
+	fn fee_discount(who: &T::AccountId) -> Permill {
+		Self::locks(who).map_or(Permill::zero(), |l| Self::discount_of(l.amount))
+	}
+}
@@ -0,0 +1,104 @@
+//! Mocks for the fee-discount module.
+
+#![cfg(test)]
+
+use frame_support::{impl_outer_event, impl_outer_origin, ord_parameter_types, parameter_types};
+use frame_system as system;
+use system::EnsureSignedBy;
+
+use super::*;
+
+impl_outer_origin! {
+	pub enum Origin for Runtime {}
+}
+
+mod fee_discount {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for Runtime {
+		frame_system<T>,
+		pallet_balances<T>,
+		fee_discount<T>,
+	}
+}
+
+ord_parameter_types! {
+	pub const UpdateOrigin: AccountId = 0;
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Runtime;
+
+pub type AccountId = u64;
+laminar_test_support::impl_mock_system!(Runtime, AccountId, TestEvent, pallet_balances::AccountData<Balance>);
+pub type System = system::Module<Runtime>;
+
+parameter_types! {
+	pub const ExistentialDeposit: Balance = 1;
+}
+
+impl pallet_balances::Trait for Runtime {
+	type Balance = Balance;
+	type DustRemoval = ();
+	type Event = TestEvent;
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = frame_system::Module<Runtime>;
+}
+pub type Balances = pallet_balances::Module<Runtime>;
+
+parameter_types! {
+	pub const MinLockPeriod: u64 = 10;
+}
+
+impl Trait for Runtime {
+	type Event = TestEvent;
+	type Currency = Balances;
+	type MinLockPeriod = MinLockPeriod;
+	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
+}
+
+pub type FeeDiscountModule = Module<Runtime>;
+
+pub const ALICE: AccountId = 0;
+pub const BOB: AccountId = 1;
+
+pub fn alice() -> Origin {
+	Origin::signed(ALICE)
+}
+
+pub fn bob() -> Origin {
+	Origin::signed(BOB)
+}
+
+pub struct ExtBuilder {
+	endowed_accounts: Vec<(AccountId, Balance)>,
+}
+
+impl Default for ExtBuilder {
+	fn default() -> Self {
+		Self {
+			endowed_accounts: vec![(ALICE, 1_000), (BOB, 1_000)],
+		}
+	}
+}
+
+impl ExtBuilder {
+	pub fn build(self) -> sp_io::TestExternalities {
+		let mut t = frame_system::GenesisConfig::default()
+			.build_storage::<Runtime>()
+			.unwrap();
+
+		pallet_balances::GenesisConfig::<Runtime> {
+			balances: self.endowed_accounts,
+		}
+		.assimilate_storage(&mut t)
+		.unwrap();
+
+		let mut ext = sp_io::TestExternalities::new(t);
+		ext.execute_with(|| System::set_block_number(1));
+		ext
+	}
+}
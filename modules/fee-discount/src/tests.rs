@@ -0,0 +1,141 @@
+//! Unit tests for the fee-discount module.
+
+#![cfg(test)]
+
+use super::*;
+use mock::*;
+
+use frame_support::{
+	assert_noop, assert_ok,
+	traits::{Currency, ExistenceRequirement},
+};
+use sp_runtime::traits::BadOrigin;
+
+fn set_tiers() {
+	assert_ok!(FeeDiscountModule::set_discount_tiers(
+		alice(),
+		vec![(100, Permill::from_percent(10)), (500, Permill::from_percent(30))]
+	));
+}
+
+#[test]
+fn lock_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(FeeDiscountModule::lock(bob(), 100));
+		assert_eq!(
+			FeeDiscountModule::locks(BOB),
+			Some(LockInfo {
+				amount: 100,
+				unlockable_at: 11
+			})
+		);
+		assert_noop!(
+			<Balances as Currency<_>>::transfer(&BOB, &ALICE, 901, ExistenceRequirement::AllowDeath),
+			pallet_balances::Error::<Runtime, _>::LiquidityRestrictions
+		);
+
+		System::set_block_number(5);
+		assert_ok!(FeeDiscountModule::lock(bob(), 50));
+		assert_eq!(
+			FeeDiscountModule::locks(BOB),
+			Some(LockInfo {
+				amount: 150,
+				unlockable_at: 15
+			})
+		);
+
+		let event = TestEvent::fee_discount(RawEvent::Locked(BOB, 50, 15));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn lock_fails_if_insufficient_balance() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(FeeDiscountModule::lock(bob(), 0), Error::<Runtime>::ZeroAmount);
+		assert_noop!(
+			FeeDiscountModule::lock(bob(), 1_001),
+			Error::<Runtime>::InsufficientBalance
+		);
+		assert_ok!(FeeDiscountModule::lock(bob(), 600));
+		assert_noop!(
+			FeeDiscountModule::lock(bob(), 401),
+			Error::<Runtime>::InsufficientBalance
+		);
+	});
+}
+
+#[test]
+fn unlock_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(FeeDiscountModule::lock(bob(), 100));
+		assert_noop!(
+			FeeDiscountModule::unlock(bob(), 40),
+			Error::<Runtime>::LockPeriodNotEnded
+		);
+
+		System::set_block_number(11);
+		assert_noop!(
+			FeeDiscountModule::unlock(bob(), 101),
+			Error::<Runtime>::InsufficientLocked
+		);
+		assert_ok!(FeeDiscountModule::unlock(bob(), 40));
+		assert_eq!(
+			FeeDiscountModule::locks(BOB),
+			Some(LockInfo {
+				amount: 60,
+				unlockable_at: 11
+			})
+		);
+
+		assert_ok!(FeeDiscountModule::unlock(bob(), 60));
+		assert_eq!(FeeDiscountModule::locks(BOB), None);
+		assert_ok!(<Balances as Currency<_>>::transfer(
+			&BOB,
+			&ALICE,
+			1_000,
+			ExistenceRequirement::AllowDeath
+		));
+
+		let event = TestEvent::fee_discount(RawEvent::Unlocked(BOB, 60));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn set_discount_tiers_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		let tiers = vec![(100, Permill::from_percent(10)), (500, Permill::from_percent(30))];
+		assert_noop!(FeeDiscountModule::set_discount_tiers(bob(), tiers.clone()), BadOrigin);
+		assert_noop!(
+			FeeDiscountModule::set_discount_tiers(
+				alice(),
+				vec![(500, Permill::from_percent(30)), (100, Permill::from_percent(10))]
+			),
+			Error::<Runtime>::InvalidDiscountTiers
+		);
+
+		assert_ok!(FeeDiscountModule::set_discount_tiers(alice(), tiers.clone()));
+		assert_eq!(FeeDiscountModule::discount_tiers(), tiers);
+
+		let event = TestEvent::fee_discount(RawEvent::DiscountTiersSet(tiers));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn fee_discount_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		set_tiers();
+		assert_eq!(FeeDiscountModule::fee_discount(&BOB), Permill::zero());
+
+		assert_ok!(FeeDiscountModule::lock(bob(), 99));
+		assert_eq!(FeeDiscountModule::fee_discount(&BOB), Permill::zero());
+
+		assert_ok!(FeeDiscountModule::lock(bob(), 1));
+		assert_eq!(FeeDiscountModule::fee_discount(&BOB), Permill::from_percent(10));
+
+		assert_ok!(FeeDiscountModule::lock(bob(), 600));
+		assert_eq!(FeeDiscountModule::fee_discount(&BOB), Permill::from_percent(30));
+	});
+}
@@ -0,0 +1,495 @@
+//! Liquidity mining incentives.
+//!
+//! Reward schedules set by governance distribute the native currency per block to reward pools: margin liquidity
+//! pools, synthetic liquidity pools, and holders staking synthetic tokens. Each reward pool is shared by its stakers
+//! pro rata to their stakes. Liquidity pools stake their liquidity weighted by per-pool multipliers, and rewards of a
+//! liquidity pool are claimed by its owner. Rewards are paid out of the module account, funded by the treasury.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Decode, Encode};
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage, ensure,
+	traits::{EnsureOrigin, Get},
+};
+use sp_arithmetic::{
+	traits::{Saturating, UniqueSaturatedInto, Zero},
+	FixedPointNumber, FixedU128,
+};
+use sp_runtime::{traits::AccountIdConversion, DispatchError, DispatchResult, ModuleId, RuntimeDebug};
+use sp_std::{cmp, marker::PhantomData, prelude::*, result};
+// FIXME: `pallet/frame-` prefix should be used for all pallet modules, but currently `frame_system`
+// would cause compiling error in `decl_module!` and `construct_runtime!`
+// #3295 https://github.com/paritytech/substrate/issues/3295
+use frame_system::{self as system, ensure_signed};
+
+use orml_traits::{BasicCurrency, MultiCurrency};
+use orml_utilities::with_transaction_result;
+
+use module_primitives::{
+	arithmetic::{fixed_u128_mul_int_with_rounding, Rounding},
+	Balance, CurrencyId, LiquidityPoolId,
+};
+use module_traits::{LiquidityPools, OnLiquidityUpdate};
+
+mod mock;
+mod tests;
+
+const MODULE_ID: ModuleId = ModuleId(*b"lami/inc");
+
+pub trait Trait: frame_system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+
+	/// The currency rewards are paid in.
+	type RewardCurrency: BasicCurrency<Self::AccountId, Balance = Balance>;
+
+	/// The `MultiCurrency` implementation for staking synthetic tokens.
+	type MultiCurrency: MultiCurrency<Self::AccountId, Balance = Balance, CurrencyId = CurrencyId>;
+
+	/// Synthetic currency IDs could be staked.
+	type SyntheticCurrencyIds: Get<Vec<CurrencyId>>;
+
+	/// Margin liquidity pools, for owners claiming pool rewards.
+	type MarginLiquidityPools: LiquidityPools<Self::AccountId>;
+
+	/// Synthetic liquidity pools, for owners claiming pool rewards.
+	type SyntheticLiquidityPools: LiquidityPools<Self::AccountId>;
+
+	/// Required origin for setting reward schedules and pool multipliers.
+	type UpdateOrigin: EnsureOrigin<Self::Origin>;
+}
+
+/// Pools of rewards, shared by their stakers.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+pub enum RewardPool {
+	/// Margin liquidity pools, staking their liquidity.
+	MarginLiquidity,
+	/// Synthetic liquidity pools, staking their liquidity.
+	SyntheticLiquidity,
+	/// Holders staking the synthetic token.
+	SyntheticHolding(CurrencyId),
+}
+
+/// Stakers of reward pools.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
+pub enum Staker<AccountId> {
+	/// A liquidity pool, with rewards claimed by its owner.
+	Pool(LiquidityPoolId),
+	/// An account.
+	Account(AccountId),
+}
+
+/// Reward distribution of a reward pool.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct RewardSchedule<BlockNumber> {
+	/// The first block of the schedule.
+	pub start: BlockNumber,
+	/// The block the schedule ends at, exclusive.
+	pub end: BlockNumber,
+	/// Rewards distributed per block.
+	pub reward_per_block: Balance,
+}
+
+/// Reward accumulation of a reward pool.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct RewardPoolInfo<BlockNumber> {
+	/// Sum of all stakes.
+	pub total_stake: Balance,
+	/// Accumulated rewards per stake.
+	pub reward_per_stake: FixedU128,
+	/// The block rewards accumulated to.
+	pub last_update: BlockNumber,
+}
+
+/// Stake of a staker in a reward pool.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct StakeInfo {
+	/// Staked amount: liquidity of pools, or tokens of accounts.
+	pub amount: Balance,
+	/// Stake weighted by the pool multiplier.
+	pub stake: Balance,
+	/// Rewards per stake accumulated before the stake, not entitled.
+	pub reward_debt: Balance,
+	/// Rewards settled, but not claimed.
+	pub unclaimed: Balance,
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Incentives {
+		/// Reward schedules of reward pools.
+		RewardSchedules get(fn reward_schedules): map hasher(twox_64_concat) RewardPool => Option<RewardSchedule<T::BlockNumber>>;
+
+		/// Reward accumulations of reward pools.
+		RewardPools get(fn reward_pools): map hasher(twox_64_concat) RewardPool => RewardPoolInfo<T::BlockNumber>;
+
+		/// Stakes in reward pools.
+		Stakes get(fn stakes): double_map hasher(twox_64_concat) RewardPool, hasher(twox_64_concat) Staker<T::AccountId> => StakeInfo;
+
+		/// Reward multipliers of liquidity pools. Liquidity pools without a multiplier don't get rewards.
+		PoolMultipliers get(fn pool_multipliers): double_map hasher(twox_64_concat) RewardPool, hasher(twox_64_concat) LiquidityPoolId => FixedU128;
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where
+		<T as frame_system::Trait>::AccountId,
+		<T as frame_system::Trait>::BlockNumber,
+	{
+		/// Synthetic tokens staked: [who, currency_id, amount]
+		Staked(AccountId, CurrencyId, Balance),
+
+		/// Synthetic tokens unstaked: [who, currency_id, amount]
+		Unstaked(AccountId, CurrencyId, Balance),
+
+		/// Rewards claimed: [who, reward_pool, amount]
+		RewardsClaimed(AccountId, RewardPool, Balance),
+
+		/// Liquidity pool rewards claimed: [who, reward_pool, pool_id, amount]
+		PoolRewardsClaimed(AccountId, RewardPool, LiquidityPoolId, Balance),
+
+		/// Reward schedule set: [reward_pool, schedule]
+		RewardScheduleSet(RewardPool, Option<RewardSchedule<BlockNumber>>),
+
+		/// Pool multiplier set: [reward_pool, pool_id, multiplier]
+		PoolMultiplierSet(RewardPool, LiquidityPoolId, FixedU128),
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		type Error = Error<T>;
+
+		fn deposit_event() = default;
+
+		/// Stake `amount` of synthetic token `currency_id`, for rewards of holding it.
+		#[weight = 10_000]
+		pub fn stake(origin, currency_id: CurrencyId, #[compact] amount: Balance) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::do_stake(&who, currency_id, amount)?;
+				Self::deposit_event(RawEvent::Staked(who, currency_id, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Unstake `amount` of synthetic token `currency_id`.
+		#[weight = 10_000]
+		pub fn unstake(origin, currency_id: CurrencyId, #[compact] amount: Balance) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::do_unstake(&who, currency_id, amount)?;
+				Self::deposit_event(RawEvent::Unstaked(who, currency_id, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Claim rewards of the caller in `reward_pool`.
+		#[weight = 10_000]
+		pub fn claim_rewards(origin, reward_pool: RewardPool) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let amount = Self::do_claim(&who, reward_pool, &Staker::Account(who.clone()))?;
+				Self::deposit_event(RawEvent::RewardsClaimed(who, reward_pool, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Claim rewards of liquidity pool `pool_id` in `reward_pool`. Rewards not claimed before removing the pool
+		/// are forfeited.
+		///
+		/// May only be called from the pool owner.
+		#[weight = 10_000]
+		pub fn claim_pool_rewards(origin, reward_pool: RewardPool, #[compact] pool_id: LiquidityPoolId) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				ensure!(Self::is_owner(reward_pool, pool_id, &who)?, Error::<T>::NoPermission);
+				let amount = Self::do_claim(&who, reward_pool, &Staker::Pool(pool_id))?;
+				Self::deposit_event(RawEvent::PoolRewardsClaimed(who, reward_pool, pool_id, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Set the reward schedule of `reward_pool`, or remove it if `None`. Rewards accumulated so far are by the
+		/// previous schedule.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_reward_schedule(
+			origin,
+			reward_pool: RewardPool,
+			schedule: Option<RewardSchedule<T::BlockNumber>>,
+		) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				Self::do_set_reward_schedule(reward_pool, schedule)?;
+				Self::deposit_event(RawEvent::RewardScheduleSet(reward_pool, schedule));
+				Ok(())
+			})?;
+		}
+
+		/// Set the reward multiplier of liquidity pool `pool_id` in `reward_pool`.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_pool_multiplier(
+			origin,
+			reward_pool: RewardPool,
+			#[compact] pool_id: LiquidityPoolId,
+			multiplier: FixedU128,
+		) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				Self::do_set_pool_multiplier(reward_pool, pool_id, multiplier)?;
+				Self::deposit_event(RawEvent::PoolMultiplierSet(reward_pool, pool_id, multiplier));
+				Ok(())
+			})?;
+		}
+	}
+}
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// Caller doesn't have permission.
+		NoPermission,
+
+		/// The currency is not a valid synthetic currency.
+		NotValidSyntheticCurrencyId,
+
+		/// The reward pool is not of liquidity pools.
+		NotLiquidityRewardPool,
+
+		/// Liquidity pool not found.
+		PoolNotFound,
+
+		/// Invalid reward schedule.
+		InvalidRewardSchedule,
+
+		/// Staked amount is not enough.
+		InsufficientStake,
+
+		/// No rewards to claim.
+		NoRewards,
+
+		/// Not enough balance in the module account to pay rewards.
+		InsufficientRewardFund,
+
+		/// Number overflow in calculation.
+		NumOverflow,
+	}
+}
+
+type BalanceResult = result::Result<Balance, DispatchError>;
+
+// Dispatchable calls implementation
+impl<T: Trait> Module<T> {
+	fn do_stake(who: &T::AccountId, currency_id: CurrencyId, amount: Balance) -> DispatchResult {
+		Self::ensure_valid_currency(currency_id)?;
+
+		let reward_pool = RewardPool::SyntheticHolding(currency_id);
+		let staker = Staker::Account(who.clone());
+		let staked = Self::stakes(reward_pool, &staker)
+			.amount
+			.checked_add(amount)
+			.ok_or(Error::<T>::NumOverflow)?;
+
+		T::MultiCurrency::transfer(currency_id, who, &Self::account_id(), amount)?;
+		Self::set_stake(reward_pool, &staker, staked, staked);
+
+		Ok(())
+	}
+
+	fn do_unstake(who: &T::AccountId, currency_id: CurrencyId, amount: Balance) -> DispatchResult {
+		let reward_pool = RewardPool::SyntheticHolding(currency_id);
+		let staker = Staker::Account(who.clone());
+		let staked = Self::stakes(reward_pool, &staker)
+			.amount
+			.checked_sub(amount)
+			.ok_or(Error::<T>::InsufficientStake)?;
+
+		T::MultiCurrency::transfer(currency_id, &Self::account_id(), who, amount)?;
+		Self::set_stake(reward_pool, &staker, staked, staked);
+
+		Ok(())
+	}
+
+	fn do_claim(who: &T::AccountId, reward_pool: RewardPool, staker: &Staker<T::AccountId>) -> BalanceResult {
+		let stake = Self::stakes(reward_pool, staker);
+		Self::set_stake(reward_pool, staker, stake.amount, stake.stake);
+
+		let mut stake = Self::stakes(reward_pool, staker);
+		let amount = stake.unclaimed;
+		ensure!(!amount.is_zero(), Error::<T>::NoRewards);
+
+		T::RewardCurrency::transfer(&Self::account_id(), who, amount)
+			.map_err(|_| Error::<T>::InsufficientRewardFund)?;
+		stake.unclaimed = Zero::zero();
+		Self::put_stake(reward_pool, staker, stake);
+
+		Ok(amount)
+	}
+
+	fn do_set_reward_schedule(
+		reward_pool: RewardPool,
+		schedule: Option<RewardSchedule<T::BlockNumber>>,
+	) -> DispatchResult {
+		if let RewardPool::SyntheticHolding(currency_id) = reward_pool {
+			Self::ensure_valid_currency(currency_id)?;
+		}
+		if let Some(schedule) = schedule {
+			ensure!(schedule.start < schedule.end, Error::<T>::InvalidRewardSchedule);
+		}
+
+		Self::update_reward_pool(reward_pool);
+		<RewardSchedules<T>>::mutate(reward_pool, |s| *s = schedule);
+
+		Ok(())
+	}
+
+	fn do_set_pool_multiplier(
+		reward_pool: RewardPool,
+		pool_id: LiquidityPoolId,
+		multiplier: FixedU128,
+	) -> DispatchResult {
+		let liquidity = match reward_pool {
+			RewardPool::MarginLiquidity => {
+				ensure!(T::MarginLiquidityPools::pool_exists(pool_id), Error::<T>::PoolNotFound);
+				T::MarginLiquidityPools::liquidity(pool_id)
+			}
+			RewardPool::SyntheticLiquidity => {
+				ensure!(
+					T::SyntheticLiquidityPools::pool_exists(pool_id),
+					Error::<T>::PoolNotFound
+				);
+				T::SyntheticLiquidityPools::liquidity(pool_id)
+			}
+			RewardPool::SyntheticHolding(_) => return Err(Error::<T>::NotLiquidityRewardPool.into()),
+		};
+
+		PoolMultipliers::insert(reward_pool, pool_id, multiplier);
+		Self::update_pool_stake(reward_pool, pool_id, liquidity);
+
+		Ok(())
+	}
+}
+
+// Private methods
+impl<T: Trait> Module<T> {
+	pub fn account_id() -> T::AccountId {
+		MODULE_ID.into_account()
+	}
+
+	/// Rewards of `staker` in `reward_pool` not claimed yet, including rewards accumulated since the last update.
+	pub fn pending_rewards(reward_pool: RewardPool, staker: &Staker<T::AccountId>) -> Balance {
+		let info = Self::accumulated_reward_pool(reward_pool);
+		let stake = Self::stakes(reward_pool, staker);
+		stake.unclaimed.saturating_add(Self::accrued(&info, &stake))
+	}
+
+	fn ensure_valid_currency(currency_id: CurrencyId) -> DispatchResult {
+		ensure!(
+			T::SyntheticCurrencyIds::get().contains(&currency_id),
+			Error::<T>::NotValidSyntheticCurrencyId
+		);
+		Ok(())
+	}
+
+	fn is_owner(
+		reward_pool: RewardPool,
+		pool_id: LiquidityPoolId,
+		who: &T::AccountId,
+	) -> result::Result<bool, Error<T>> {
+		match reward_pool {
+			RewardPool::MarginLiquidity => Ok(T::MarginLiquidityPools::is_owner(pool_id, who)),
+			RewardPool::SyntheticLiquidity => Ok(T::SyntheticLiquidityPools::is_owner(pool_id, who)),
+			RewardPool::SyntheticHolding(_) => Err(Error::<T>::NotLiquidityRewardPool),
+		}
+	}
+
+	/// `reward_pool` with rewards accumulated to the current block.
+	///
+	/// reward_per_stake += reward_per_block * scheduled_blocks / total_stake
+	fn accumulated_reward_pool(reward_pool: RewardPool) -> RewardPoolInfo<T::BlockNumber> {
+		let mut info = Self::reward_pools(reward_pool);
+		let now = <system::Module<T>>::block_number();
+
+		if let Some(schedule) = Self::reward_schedules(reward_pool) {
+			let from = cmp::max(info.last_update, schedule.start);
+			let to = cmp::min(now, schedule.end);
+			if to > from && !info.total_stake.is_zero() {
+				let blocks: u128 = (to - from).unique_saturated_into();
+				let rewards = schedule.reward_per_block.saturating_mul(blocks);
+				// rounded down, in favor of the reward fund
+				if let Some(reward_per_stake) = FixedU128::checked_from_rational(rewards, info.total_stake) {
+					info.reward_per_stake = info.reward_per_stake.saturating_add(reward_per_stake);
+				}
+			}
+		}
+		info.last_update = now;
+
+		info
+	}
+
+	fn update_reward_pool(reward_pool: RewardPool) -> RewardPoolInfo<T::BlockNumber> {
+		let info = Self::accumulated_reward_pool(reward_pool);
+		<RewardPools<T>>::insert(reward_pool, info);
+		info
+	}
+
+	/// Rewards of `stake` accrued since its last settlement.
+	fn accrued(info: &RewardPoolInfo<T::BlockNumber>, stake: &StakeInfo) -> Balance {
+		Self::rewards_of(info, stake.stake).saturating_sub(stake.reward_debt)
+	}
+
+	fn rewards_of(info: &RewardPoolInfo<T::BlockNumber>, stake: Balance) -> Balance {
+		fixed_u128_mul_int_with_rounding(info.reward_per_stake, stake, Rounding::Down).unwrap_or_default()
+	}
+
+	/// Settle accrued rewards of `staker`, and update its stake.
+	fn set_stake(reward_pool: RewardPool, staker: &Staker<T::AccountId>, amount: Balance, new_stake: Balance) {
+		let mut info = Self::update_reward_pool(reward_pool);
+		let mut stake = Self::stakes(reward_pool, staker);
+
+		stake.unclaimed = stake.unclaimed.saturating_add(Self::accrued(&info, &stake));
+		info.total_stake = info.total_stake.saturating_sub(stake.stake).saturating_add(new_stake);
+		stake.amount = amount;
+		stake.stake = new_stake;
+		stake.reward_debt = Self::rewards_of(&info, new_stake);
+
+		<RewardPools<T>>::insert(reward_pool, info);
+		Self::put_stake(reward_pool, staker, stake);
+	}
+
+	fn put_stake(reward_pool: RewardPool, staker: &Staker<T::AccountId>, stake: StakeInfo) {
+		if stake.amount.is_zero() && stake.stake.is_zero() && stake.unclaimed.is_zero() {
+			<Stakes<T>>::remove(reward_pool, staker);
+		} else {
+			<Stakes<T>>::insert(reward_pool, staker, stake);
+		}
+	}
+
+	/// Update the stake of liquidity pool `pool_id` to its `liquidity` weighted by its multiplier.
+	fn update_pool_stake(reward_pool: RewardPool, pool_id: LiquidityPoolId, liquidity: Balance) {
+		let multiplier = Self::pool_multipliers(reward_pool, pool_id);
+		let stake = fixed_u128_mul_int_with_rounding(multiplier, liquidity, Rounding::Down).unwrap_or_default();
+		Self::set_stake(reward_pool, &Staker::Pool(pool_id), liquidity, stake);
+	}
+}
+
+/// Updates stakes of margin liquidity pools on liquidity updates.
+pub struct MarginLiquidityStakes<T>(PhantomData<T>);
+
+impl<T: Trait> OnLiquidityUpdate for MarginLiquidityStakes<T> {
+	fn on_liquidity_update(pool_id: LiquidityPoolId, liquidity: Balance) {
+		<Module<T>>::update_pool_stake(RewardPool::MarginLiquidity, pool_id, liquidity);
+	}
+}
+
+/// Updates stakes of synthetic liquidity pools on liquidity updates.
+pub struct SyntheticLiquidityStakes<T>(PhantomData<T>);
+
+impl<T: Trait> OnLiquidityUpdate for SyntheticLiquidityStakes<T> {
+	fn on_liquidity_update(pool_id: LiquidityPoolId, liquidity: Balance) {
+		<Module<T>>::update_pool_stake(RewardPool::SyntheticLiquidity, pool_id, liquidity);
+	}
+}
@@ -0,0 +1,225 @@
+//! Mocks for the incentives module.
+
+#![cfg(test)]
+
+use frame_support::{impl_outer_event, impl_outer_origin, ord_parameter_types, parameter_types};
+use frame_system as system;
+use sp_runtime::DispatchResult;
+use sp_std::{cell::RefCell, collections::btree_map::BTreeMap};
+use system::EnsureSignedBy;
+
+use orml_currencies::{BasicCurrencyAdapter, Currency};
+
+use module_traits::{LiquidityPools, OnLiquidityUpdate};
+
+use super::*;
+
+pub use module_primitives::{Balance, CurrencyId};
+
+impl_outer_origin! {
+	pub enum Origin for Runtime {}
+}
+
+ord_parameter_types! {
+	pub const UpdateOrigin: AccountId = 3;
+}
+
+mod incentives {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for Runtime {
+		frame_system<T>,
+		orml_tokens<T>, orml_currencies<T>, pallet_balances<T>,
+		incentives<T>,
+	}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Runtime;
+
+pub type AccountId = u32;
+laminar_test_support::impl_mock_system!(Runtime, AccountId, TestEvent, pallet_balances::AccountData<Balance>);
+pub type System = system::Module<Runtime>;
+
+type Amount = i128;
+
+impl orml_tokens::Trait for Runtime {
+	type Event = TestEvent;
+	type Balance = Balance;
+	type Amount = Amount;
+	type CurrencyId = CurrencyId;
+	type OnReceived = ();
+}
+
+parameter_types! {
+	pub const ExistentialDeposit: Balance = 1;
+}
+
+impl pallet_balances::Trait for Runtime {
+	type Balance = Balance;
+	type DustRemoval = ();
+	type Event = TestEvent;
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = frame_system::Module<Runtime>;
+}
+
+parameter_types! {
+	pub const GetNativeCurrencyId: CurrencyId = CurrencyId::LAMI;
+	pub const GetSyntheticCurrencyId: CurrencyId = CurrencyId::FEUR;
+	pub SyntheticCurrencyIds: Vec<CurrencyId> = vec![CurrencyId::FEUR];
+}
+
+pub type NativeCurrency = BasicCurrencyAdapter<pallet_balances::Module<Runtime>, Balance, Balance, Amount, u64>;
+pub type SyntheticCurrency = Currency<Runtime, GetSyntheticCurrencyId>;
+
+impl orml_currencies::Trait for Runtime {
+	type Event = TestEvent;
+	type MultiCurrency = orml_tokens::Module<Runtime>;
+	type NativeCurrency = NativeCurrency;
+	type GetNativeCurrencyId = GetNativeCurrencyId;
+}
+
+thread_local! {
+	static MARGIN_LIQUIDITIES: RefCell<BTreeMap<LiquidityPoolId, Balance>> = RefCell::new(BTreeMap::new());
+	static SYNTHETIC_LIQUIDITIES: RefCell<BTreeMap<LiquidityPoolId, Balance>> = RefCell::new(BTreeMap::new());
+}
+
+/// Mock margin liquidity pools, updating stakes on liquidity updates like base liquidity pools.
+pub struct MockMarginPools;
+impl MockMarginPools {
+	pub fn set_liquidity(pool_id: LiquidityPoolId, liquidity: Balance) {
+		MARGIN_LIQUIDITIES.with(|v| v.borrow_mut().insert(pool_id, liquidity));
+		MarginLiquidityStakes::<Runtime>::on_liquidity_update(pool_id, liquidity);
+	}
+}
+
+impl LiquidityPools<AccountId> for MockMarginPools {
+	fn all() -> Vec<LiquidityPoolId> {
+		unimplemented!()
+	}
+
+	/// ALICE is the mock owner
+	fn is_owner(_pool_id: LiquidityPoolId, who: &AccountId) -> bool {
+		who == &ALICE
+	}
+
+	fn pool_exists(pool_id: LiquidityPoolId) -> bool {
+		pool_id == MOCK_POOL || pool_id == ANOTHER_MOCK_POOL
+	}
+
+	fn liquidity(pool_id: LiquidityPoolId) -> Balance {
+		MARGIN_LIQUIDITIES.with(|v| v.borrow().get(&pool_id).copied().unwrap_or_default())
+	}
+
+	fn deposit_liquidity(_from: &AccountId, _pool_id: LiquidityPoolId, _amount: Balance) -> DispatchResult {
+		unimplemented!()
+	}
+
+	fn withdraw_liquidity(_to: &AccountId, _pool_id: LiquidityPoolId, _amount: Balance) -> DispatchResult {
+		unimplemented!()
+	}
+}
+
+/// Mock synthetic liquidity pools, updating stakes on liquidity updates like base liquidity pools.
+pub struct MockSyntheticPools;
+impl MockSyntheticPools {
+	pub fn set_liquidity(pool_id: LiquidityPoolId, liquidity: Balance) {
+		SYNTHETIC_LIQUIDITIES.with(|v| v.borrow_mut().insert(pool_id, liquidity));
+		SyntheticLiquidityStakes::<Runtime>::on_liquidity_update(pool_id, liquidity);
+	}
+}
+
+impl LiquidityPools<AccountId> for MockSyntheticPools {
+	fn all() -> Vec<LiquidityPoolId> {
+		unimplemented!()
+	}
+
+	/// BOB is the mock owner
+	fn is_owner(_pool_id: LiquidityPoolId, who: &AccountId) -> bool {
+		who == &BOB
+	}
+
+	fn pool_exists(pool_id: LiquidityPoolId) -> bool {
+		pool_id == MOCK_POOL
+	}
+
+	fn liquidity(pool_id: LiquidityPoolId) -> Balance {
+		SYNTHETIC_LIQUIDITIES.with(|v| v.borrow().get(&pool_id).copied().unwrap_or_default())
+	}
+
+	fn deposit_liquidity(_from: &AccountId, _pool_id: LiquidityPoolId, _amount: Balance) -> DispatchResult {
+		unimplemented!()
+	}
+
+	fn withdraw_liquidity(_to: &AccountId, _pool_id: LiquidityPoolId, _amount: Balance) -> DispatchResult {
+		unimplemented!()
+	}
+}
+
+impl Trait for Runtime {
+	type Event = TestEvent;
+	type RewardCurrency = NativeCurrency;
+	type MultiCurrency = orml_currencies::Module<Runtime>;
+	type SyntheticCurrencyIds = SyntheticCurrencyIds;
+	type MarginLiquidityPools = MockMarginPools;
+	type SyntheticLiquidityPools = MockSyntheticPools;
+	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
+}
+pub type Incentives = Module<Runtime>;
+
+pub const ALICE: AccountId = 0;
+pub const BOB: AccountId = 1;
+
+pub const MOCK_POOL: LiquidityPoolId = 100;
+pub const ANOTHER_MOCK_POOL: LiquidityPoolId = 101;
+
+/// Reward fund of the module account.
+pub const REWARD_FUND: Balance = 1_000_000;
+
+pub struct ExtBuilder {
+	reward_fund: Balance,
+}
+
+impl Default for ExtBuilder {
+	fn default() -> Self {
+		Self {
+			reward_fund: REWARD_FUND,
+		}
+	}
+}
+
+impl ExtBuilder {
+	pub fn reward_fund(mut self, reward_fund: Balance) -> Self {
+		self.reward_fund = reward_fund;
+		self
+	}
+
+	pub fn build(self) -> sp_io::TestExternalities {
+		let mut t = frame_system::GenesisConfig::default()
+			.build_storage::<Runtime>()
+			.unwrap();
+
+		pallet_balances::GenesisConfig::<Runtime> {
+			balances: vec![(Incentives::account_id(), self.reward_fund)]
+				.into_iter()
+				.filter(|(_, b)| *b > 0)
+				.collect(),
+		}
+		.assimilate_storage(&mut t)
+		.unwrap();
+
+		// 10_000 FEUR for ALICE and BOB
+		orml_tokens::GenesisConfig::<Runtime> {
+			endowed_accounts: vec![(ALICE, CurrencyId::FEUR, 10_000), (BOB, CurrencyId::FEUR, 10_000)],
+		}
+		.assimilate_storage(&mut t)
+		.unwrap();
+
+		let mut ext = sp_io::TestExternalities::new(t);
+		ext.execute_with(|| System::set_block_number(1));
+		ext
+	}
+}
@@ -0,0 +1,328 @@
+//! Unit tests for the incentives module.
+
+#![cfg(test)]
+
+use super::*;
+use frame_support::{assert_noop, assert_ok};
+use mock::*;
+use sp_runtime::DispatchError::BadOrigin;
+
+const HOLDING: RewardPool = RewardPool::SyntheticHolding(CurrencyId::FEUR);
+
+fn schedule(start: u64, end: u64, reward_per_block: Balance) -> Option<RewardSchedule<u64>> {
+	Some(RewardSchedule {
+		start,
+		end,
+		reward_per_block,
+	})
+}
+
+fn set_schedule(reward_pool: RewardPool, start: u64, end: u64, reward_per_block: Balance) -> DispatchResult {
+	Incentives::set_reward_schedule(
+		Origin::signed(UpdateOrigin::get()),
+		reward_pool,
+		schedule(start, end, reward_per_block),
+	)
+}
+
+fn set_multiplier(reward_pool: RewardPool, pool_id: LiquidityPoolId, multiplier: u128) -> DispatchResult {
+	Incentives::set_pool_multiplier(
+		Origin::signed(UpdateOrigin::get()),
+		reward_pool,
+		pool_id,
+		FixedU128::saturating_from_integer(multiplier),
+	)
+}
+
+fn stake(who: AccountId, amount: Balance) -> DispatchResult {
+	Incentives::stake(Origin::signed(who), CurrencyId::FEUR, amount)
+}
+
+fn unstake(who: AccountId, amount: Balance) -> DispatchResult {
+	Incentives::unstake(Origin::signed(who), CurrencyId::FEUR, amount)
+}
+
+fn account(who: AccountId) -> Staker<AccountId> {
+	Staker::Account(who)
+}
+
+fn pending(reward_pool: RewardPool, staker: Staker<AccountId>) -> Balance {
+	Incentives::pending_rewards(reward_pool, &staker)
+}
+
+fn reward_balance(who: AccountId) -> Balance {
+	NativeCurrency::free_balance(&who)
+}
+
+#[test]
+fn stake_and_unstake_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(stake(BOB, 1000));
+		assert_eq!(SyntheticCurrency::free_balance(&BOB), 9000);
+		assert_eq!(SyntheticCurrency::free_balance(&Incentives::account_id()), 1000);
+		assert_eq!(Incentives::stakes(HOLDING, account(BOB)).amount, 1000);
+		assert_eq!(Incentives::reward_pools(HOLDING).total_stake, 1000);
+		let event = TestEvent::incentives(RawEvent::Staked(BOB, CurrencyId::FEUR, 1000));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_ok!(unstake(BOB, 400));
+		assert_eq!(SyntheticCurrency::free_balance(&BOB), 9400);
+		assert_eq!(Incentives::stakes(HOLDING, account(BOB)).amount, 600);
+		assert_eq!(Incentives::reward_pools(HOLDING).total_stake, 600);
+		let event = TestEvent::incentives(RawEvent::Unstaked(BOB, CurrencyId::FEUR, 400));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_noop!(unstake(BOB, 601), Error::<Runtime>::InsufficientStake);
+
+		assert_ok!(unstake(BOB, 600));
+		assert_eq!(SyntheticCurrency::free_balance(&BOB), 10_000);
+		assert!(!<Stakes<Runtime>>::contains_key(HOLDING, account(BOB)));
+	});
+}
+
+#[test]
+fn should_not_stake_invalid_currency() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			Incentives::stake(Origin::signed(BOB), CurrencyId::AUSD, 1000),
+			Error::<Runtime>::NotValidSyntheticCurrencyId
+		);
+	});
+}
+
+#[test]
+fn rewards_are_shared_pro_rata() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(set_schedule(HOLDING, 2, 12, 100));
+		assert_ok!(stake(BOB, 1000));
+		assert_ok!(stake(ALICE, 3000));
+
+		// 3 blocks since the schedule start
+		System::set_block_number(5);
+		assert_eq!(pending(HOLDING, account(BOB)), 75);
+		assert_eq!(pending(HOLDING, account(ALICE)), 225);
+
+		assert_ok!(Incentives::claim_rewards(Origin::signed(BOB), HOLDING));
+		assert_eq!(reward_balance(BOB), 75);
+		assert_eq!(reward_balance(Incentives::account_id()), REWARD_FUND - 75);
+		assert_eq!(pending(HOLDING, account(BOB)), 0);
+		let event = TestEvent::incentives(RawEvent::RewardsClaimed(BOB, HOLDING, 75));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_noop!(
+			Incentives::claim_rewards(Origin::signed(BOB), HOLDING),
+			Error::<Runtime>::NoRewards
+		);
+	});
+}
+
+#[test]
+fn rewards_respect_schedule_end() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(set_schedule(HOLDING, 2, 12, 100));
+		assert_ok!(stake(BOB, 1000));
+
+		System::set_block_number(20);
+		assert_eq!(pending(HOLDING, account(BOB)), 1000);
+
+		assert_ok!(Incentives::claim_rewards(Origin::signed(BOB), HOLDING));
+		System::set_block_number(30);
+		assert_eq!(pending(HOLDING, account(BOB)), 0);
+	});
+}
+
+#[test]
+fn stake_changes_settle_rewards() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(set_schedule(HOLDING, 1, 100, 100));
+		assert_ok!(stake(BOB, 1000));
+
+		System::set_block_number(3);
+		assert_ok!(stake(ALICE, 1000));
+		assert_eq!(Incentives::stakes(HOLDING, account(BOB)).unclaimed, 0);
+
+		System::set_block_number(5);
+		assert_eq!(pending(HOLDING, account(BOB)), 300);
+		assert_eq!(pending(HOLDING, account(ALICE)), 100);
+
+		// unstaking all keeps unclaimed rewards
+		assert_ok!(unstake(BOB, 1000));
+		assert_eq!(Incentives::stakes(HOLDING, account(BOB)).unclaimed, 300);
+
+		System::set_block_number(7);
+		assert_eq!(pending(HOLDING, account(BOB)), 300);
+		assert_eq!(pending(HOLDING, account(ALICE)), 300);
+
+		assert_ok!(Incentives::claim_rewards(Origin::signed(BOB), HOLDING));
+		assert_eq!(reward_balance(BOB), 300);
+		assert!(!<Stakes<Runtime>>::contains_key(HOLDING, account(BOB)));
+	});
+}
+
+#[test]
+fn schedule_change_settles_at_previous_rate() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(set_schedule(HOLDING, 1, 100, 100));
+		assert_ok!(stake(BOB, 1000));
+
+		System::set_block_number(5);
+		assert_ok!(set_schedule(HOLDING, 1, 100, 10));
+		let event = TestEvent::incentives(RawEvent::RewardScheduleSet(HOLDING, schedule(1, 100, 10)));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		System::set_block_number(10);
+		assert_eq!(pending(HOLDING, account(BOB)), 450);
+
+		assert_ok!(Incentives::set_reward_schedule(
+			Origin::signed(UpdateOrigin::get()),
+			HOLDING,
+			None
+		));
+		assert_eq!(Incentives::reward_schedules(HOLDING), None);
+
+		System::set_block_number(20);
+		assert_eq!(pending(HOLDING, account(BOB)), 450);
+	});
+}
+
+#[test]
+fn should_fail_if_insufficient_reward_fund() {
+	ExtBuilder::default().reward_fund(50).build().execute_with(|| {
+		assert_ok!(set_schedule(HOLDING, 1, 100, 100));
+		assert_ok!(stake(BOB, 1000));
+
+		System::set_block_number(2);
+		assert_noop!(
+			Incentives::claim_rewards(Origin::signed(BOB), HOLDING),
+			Error::<Runtime>::InsufficientRewardFund
+		);
+	});
+}
+
+#[test]
+fn set_reward_schedule_should_work() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			Incentives::set_reward_schedule(Origin::signed(ALICE), HOLDING, schedule(1, 100, 100)),
+			BadOrigin
+		);
+		assert_noop!(
+			set_schedule(HOLDING, 10, 10, 100),
+			Error::<Runtime>::InvalidRewardSchedule
+		);
+		assert_noop!(
+			set_schedule(RewardPool::SyntheticHolding(CurrencyId::AUSD), 1, 100, 100),
+			Error::<Runtime>::NotValidSyntheticCurrencyId
+		);
+
+		assert_ok!(set_schedule(RewardPool::MarginLiquidity, 1, 100, 100));
+		assert_eq!(
+			Incentives::reward_schedules(RewardPool::MarginLiquidity),
+			schedule(1, 100, 100)
+		);
+	});
+}
+
+#[test]
+fn pool_rewards_are_weighted_by_multiplier() {
+	ExtBuilder::default().build().execute_with(|| {
+		let reward_pool = RewardPool::MarginLiquidity;
+		assert_ok!(set_schedule(reward_pool, 1, 100, 100));
+
+		// no rewards without multiplier
+		MockMarginPools::set_liquidity(MOCK_POOL, 1000);
+		MockMarginPools::set_liquidity(ANOTHER_MOCK_POOL, 2000);
+		assert_eq!(Incentives::stakes(reward_pool, Staker::Pool(MOCK_POOL)).stake, 0);
+		assert_eq!(Incentives::reward_pools(reward_pool).total_stake, 0);
+
+		assert_ok!(set_multiplier(reward_pool, MOCK_POOL, 2));
+		assert_ok!(set_multiplier(reward_pool, ANOTHER_MOCK_POOL, 1));
+		let event = TestEvent::incentives(RawEvent::PoolMultiplierSet(
+			reward_pool,
+			MOCK_POOL,
+			FixedU128::saturating_from_integer(2),
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+		assert_eq!(Incentives::stakes(reward_pool, Staker::Pool(MOCK_POOL)).stake, 2000);
+		assert_eq!(Incentives::reward_pools(reward_pool).total_stake, 4000);
+
+		System::set_block_number(3);
+		assert_eq!(pending(reward_pool, Staker::Pool(MOCK_POOL)), 100);
+		assert_eq!(pending(reward_pool, Staker::Pool(ANOTHER_MOCK_POOL)), 100);
+
+		// liquidity updates change the stake
+		MockMarginPools::set_liquidity(ANOTHER_MOCK_POOL, 6000);
+		System::set_block_number(5);
+		assert_eq!(pending(reward_pool, Staker::Pool(MOCK_POOL)), 150);
+		assert_eq!(pending(reward_pool, Staker::Pool(ANOTHER_MOCK_POOL)), 250);
+
+		assert_noop!(
+			Incentives::claim_pool_rewards(Origin::signed(BOB), reward_pool, MOCK_POOL),
+			Error::<Runtime>::NoPermission
+		);
+		assert_ok!(Incentives::claim_pool_rewards(
+			Origin::signed(ALICE),
+			reward_pool,
+			MOCK_POOL
+		));
+		assert_eq!(reward_balance(ALICE), 150);
+		let event = TestEvent::incentives(RawEvent::PoolRewardsClaimed(ALICE, reward_pool, MOCK_POOL, 150));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn removed_pool_stops_accruing() {
+	ExtBuilder::default().build().execute_with(|| {
+		let reward_pool = RewardPool::SyntheticLiquidity;
+		assert_ok!(set_schedule(reward_pool, 1, 100, 100));
+		assert_ok!(set_multiplier(reward_pool, MOCK_POOL, 1));
+		MockSyntheticPools::set_liquidity(MOCK_POOL, 1000);
+
+		System::set_block_number(3);
+		MockSyntheticPools::set_liquidity(MOCK_POOL, 0);
+		assert_eq!(Incentives::reward_pools(reward_pool).total_stake, 0);
+
+		System::set_block_number(10);
+		assert_eq!(pending(reward_pool, Staker::Pool(MOCK_POOL)), 200);
+
+		assert_ok!(Incentives::claim_pool_rewards(
+			Origin::signed(BOB),
+			reward_pool,
+			MOCK_POOL
+		));
+		assert_eq!(reward_balance(BOB), 200);
+		assert!(!<Stakes<Runtime>>::contains_key(reward_pool, Staker::Pool(MOCK_POOL)));
+	});
+}
+
+#[test]
+fn set_pool_multiplier_should_work() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			Incentives::set_pool_multiplier(
+				Origin::signed(ALICE),
+				RewardPool::MarginLiquidity,
+				MOCK_POOL,
+				FixedU128::one()
+			),
+			BadOrigin
+		);
+		assert_noop!(
+			set_multiplier(HOLDING, MOCK_POOL, 1),
+			Error::<Runtime>::NotLiquidityRewardPool
+		);
+		assert_noop!(
+			set_multiplier(RewardPool::MarginLiquidity, 102, 1),
+			Error::<Runtime>::PoolNotFound
+		);
+		assert_noop!(
+			set_multiplier(RewardPool::SyntheticLiquidity, ANOTHER_MOCK_POOL, 1),
+			Error::<Runtime>::PoolNotFound
+		);
+		assert_noop!(
+			Incentives::claim_pool_rewards(Origin::signed(ALICE), HOLDING, MOCK_POOL),
+			Error::<Runtime>::NotLiquidityRewardPool
+		);
+	});
+}
@@ -0,0 +1,122 @@
+//! Insurance fund covering trader shortfalls of margin trading.
+//!
+//! The fund is held by the module account. It is funded by the insurance cut of force-close penalties, slippage
+//! insurance premiums, and deposits from anyone. When a trader's negative balance can't be repaid by collateral, the
+//! shortfall is covered by the fund before it hits the liquidity of the pool. Governance may withdraw from the fund.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use frame_support::{decl_event, decl_module, decl_storage, traits::EnsureOrigin};
+use sp_runtime::{
+	traits::{AccountIdConversion, Saturating, Zero},
+	DispatchError, ModuleId,
+};
+use sp_std::{cmp, result};
+// FIXME: `pallet/frame-` prefix should be used for all pallet modules, but currently `frame_system`
+// would cause compiling error in `decl_module!` and `construct_runtime!`
+// #3295 https://github.com/paritytech/substrate/issues/3295
+use frame_system::{self as system, ensure_signed};
+
+use orml_traits::BasicCurrency;
+use orml_utilities::with_transaction_result;
+
+use module_primitives::{Balance, LiquidityPoolId};
+use module_traits::InsuranceFund;
+
+mod mock;
+mod tests;
+
+pub const MODULE_ID: ModuleId = ModuleId(*b"lami/ins");
+
+pub trait Trait: frame_system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+
+	/// The currency the fund is held in.
+	type Currency: BasicCurrency<Self::AccountId, Balance = Balance>;
+
+	/// Required origin for withdrawing from the fund.
+	type UpdateOrigin: EnsureOrigin<Self::Origin>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as InsuranceFund {
+		/// Total shortfalls covered by the fund, per liquidity pool.
+		CoveredShortfalls get(fn covered_shortfalls): map hasher(twox_64_concat) LiquidityPoolId => Balance;
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where
+		<T as frame_system::Trait>::AccountId,
+	{
+		/// Deposited into the fund: [who, amount]
+		Deposited(AccountId, Balance),
+
+		/// Withdrew from the fund: [dest, amount]
+		Withdrew(AccountId, Balance),
+
+		/// Shortfall in a liquidity pool covered by the fund: [pool_id, amount]
+		ShortfallCovered(LiquidityPoolId, Balance),
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		/// Deposit `amount` into the fund.
+		#[weight = 10_000]
+		pub fn deposit(origin, #[compact] amount: Balance) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				T::Currency::transfer(&who, &Self::account_id(), amount)?;
+				Self::deposit_event(RawEvent::Deposited(who, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Withdraw `amount` from the fund to `dest`.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn withdraw(origin, dest: T::AccountId, #[compact] amount: Balance) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				T::Currency::transfer(&Self::account_id(), &dest, amount)?;
+				Self::deposit_event(RawEvent::Withdrew(dest, amount));
+				Ok(())
+			})?;
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	pub fn account_id() -> T::AccountId {
+		MODULE_ID.into_account()
+	}
+
+	/// Balance of the fund.
+	pub fn balance() -> Balance {
+		T::Currency::free_balance(&Self::account_id())
+	}
+}
+
+impl<T: Trait> InsuranceFund<T::AccountId> for Module<T> {
+	fn cover_shortfall(
+		dest: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		amount: Balance,
+	) -> result::Result<Balance, DispatchError> {
+		let covered = cmp::min(amount, Self::balance());
+		if covered.is_zero() {
+			return Ok(Zero::zero());
+		}
+
+		T::Currency::transfer(&Self::account_id(), dest, covered)?;
+		CoveredShortfalls::mutate(pool_id, |c| *c = c.saturating_add(covered));
+		Self::deposit_event(RawEvent::ShortfallCovered(pool_id, covered));
+
+		Ok(covered)
+	}
+}
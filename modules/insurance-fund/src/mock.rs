@@ -0,0 +1,127 @@
+//! Mocks for the insurance fund module.
+
+#![cfg(test)]
+
+use frame_support::{impl_outer_event, impl_outer_origin, ord_parameter_types, parameter_types};
+use frame_system as system;
+use system::EnsureSignedBy;
+
+use orml_currencies::{BasicCurrencyAdapter, Currency};
+
+use super::*;
+
+pub use module_primitives::{Balance, CurrencyId, LiquidityPoolId};
+
+impl_outer_origin! {
+	pub enum Origin for Runtime {}
+}
+
+ord_parameter_types! {
+	pub const UpdateOrigin: AccountId = 3;
+}
+
+mod insurance_fund {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for Runtime {
+		frame_system<T>,
+		orml_tokens<T>, orml_currencies<T>, pallet_balances<T>,
+		insurance_fund<T>,
+	}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Runtime;
+
+pub type AccountId = u32;
+laminar_test_support::impl_mock_system!(Runtime, AccountId, TestEvent, pallet_balances::AccountData<Balance>);
+pub type System = system::Module<Runtime>;
+
+type Amount = i128;
+
+impl orml_tokens::Trait for Runtime {
+	type Event = TestEvent;
+	type Balance = Balance;
+	type Amount = Amount;
+	type CurrencyId = CurrencyId;
+	type OnReceived = ();
+}
+
+parameter_types! {
+	pub const ExistentialDeposit: Balance = 1;
+}
+
+impl pallet_balances::Trait for Runtime {
+	type Balance = Balance;
+	type DustRemoval = ();
+	type Event = TestEvent;
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = frame_system::Module<Runtime>;
+}
+
+parameter_types! {
+	pub const GetNativeCurrencyId: CurrencyId = CurrencyId::LAMI;
+	pub const GetFundCurrencyId: CurrencyId = CurrencyId::AUSD;
+}
+
+pub type NativeCurrency = BasicCurrencyAdapter<pallet_balances::Module<Runtime>, Balance, Balance, Amount, u64>;
+pub type FundCurrency = Currency<Runtime, GetFundCurrencyId>;
+
+impl orml_currencies::Trait for Runtime {
+	type Event = TestEvent;
+	type MultiCurrency = orml_tokens::Module<Runtime>;
+	type NativeCurrency = NativeCurrency;
+	type GetNativeCurrencyId = GetNativeCurrencyId;
+}
+
+pub const ALICE: AccountId = 0;
+pub const BOB: AccountId = 1;
+pub const MOCK_POOL: LiquidityPoolId = 100;
+
+impl Trait for Runtime {
+	type Event = TestEvent;
+	type Currency = FundCurrency;
+	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
+}
+pub type InsuranceFundModule = Module<Runtime>;
+
+pub struct ExtBuilder {
+	fund: Balance,
+}
+
+impl Default for ExtBuilder {
+	fn default() -> Self {
+		Self { fund: 1_000 }
+	}
+}
+
+impl ExtBuilder {
+	pub fn fund(mut self, fund: Balance) -> Self {
+		self.fund = fund;
+		self
+	}
+
+	pub fn build(self) -> sp_io::TestExternalities {
+		let mut t = frame_system::GenesisConfig::default()
+			.build_storage::<Runtime>()
+			.unwrap();
+
+		// 1_000 aUSD for ALICE and BOB
+		orml_tokens::GenesisConfig::<Runtime> {
+			endowed_accounts: vec![
+				(ALICE, CurrencyId::AUSD, 1_000),
+				(BOB, CurrencyId::AUSD, 1_000),
+				(InsuranceFundModule::account_id(), CurrencyId::AUSD, self.fund),
+			],
+		}
+		.assimilate_storage(&mut t)
+		.unwrap();
+
+		let mut ext = sp_io::TestExternalities::new(t);
+		ext.execute_with(|| System::set_block_number(1));
+		ext
+	}
+}
@@ -0,0 +1,74 @@
+//! Unit tests for the insurance fund module.
+
+#![cfg(test)]
+
+use super::*;
+use frame_support::{assert_noop, assert_ok};
+use mock::*;
+use sp_runtime::DispatchError::BadOrigin;
+
+fn cover_shortfall(amount: Balance) -> result::Result<Balance, DispatchError> {
+	<InsuranceFundModule as InsuranceFund<AccountId>>::cover_shortfall(&BOB, MOCK_POOL, amount)
+}
+
+#[test]
+fn deposit_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(InsuranceFundModule::deposit(Origin::signed(ALICE), 100));
+		assert_eq!(InsuranceFundModule::balance(), 1_100);
+		assert_eq!(FundCurrency::free_balance(&ALICE), 900);
+		let event = TestEvent::insurance_fund(RawEvent::Deposited(ALICE, 100));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn withdraw_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			InsuranceFundModule::withdraw(Origin::signed(ALICE), ALICE, 100),
+			BadOrigin
+		);
+
+		assert_ok!(InsuranceFundModule::withdraw(
+			Origin::signed(UpdateOrigin::get()),
+			ALICE,
+			100
+		));
+		assert_eq!(InsuranceFundModule::balance(), 900);
+		assert_eq!(FundCurrency::free_balance(&ALICE), 1_100);
+		let event = TestEvent::insurance_fund(RawEvent::Withdrew(ALICE, 100));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert!(InsuranceFundModule::withdraw(Origin::signed(UpdateOrigin::get()), ALICE, 1_000).is_err());
+		assert_eq!(InsuranceFundModule::balance(), 900);
+	});
+}
+
+#[test]
+fn cover_shortfall_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_eq!(cover_shortfall(400), Ok(400));
+		assert_eq!(InsuranceFundModule::balance(), 600);
+		assert_eq!(FundCurrency::free_balance(&BOB), 1_400);
+		assert_eq!(InsuranceFundModule::covered_shortfalls(MOCK_POOL), 400);
+		let event = TestEvent::insurance_fund(RawEvent::ShortfallCovered(MOCK_POOL, 400));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		// covered up to the fund balance
+		assert_eq!(cover_shortfall(1_000), Ok(600));
+		assert_eq!(InsuranceFundModule::balance(), 0);
+		assert_eq!(FundCurrency::free_balance(&BOB), 2_000);
+		assert_eq!(InsuranceFundModule::covered_shortfalls(MOCK_POOL), 1_000);
+	});
+}
+
+#[test]
+fn empty_fund_covers_nothing() {
+	ExtBuilder::default().fund(0).build().execute_with(|| {
+		assert_eq!(cover_shortfall(100), Ok(0));
+		assert_eq!(FundCurrency::free_balance(&BOB), 1_000);
+		assert_eq!(InsuranceFundModule::covered_shortfalls(MOCK_POOL), 0);
+		assert!(System::events().is_empty());
+	});
+}
@@ -16,7 +16,9 @@ use sp_runtime::{
 	DispatchResult, ModuleId, RuntimeDebug,
 };
 use sp_std::{prelude::*, result};
-use traits::{BaseLiquidityPoolManager, LiquidityPools, OnDisableLiquidityPool, OnRemoveLiquidityPool};
+use traits::{
+	BaseLiquidityPoolManager, LiquidityPools, OnDisableLiquidityPool, OnLiquidityUpdate, OnRemoveLiquidityPool,
+};
 
 mod mock;
 mod tests;
@@ -54,6 +56,9 @@ pub trait Trait<I: Instance = DefaultInstance>: system::Trait {
 	/// The receiver of the signal for when a liquidity pool is removed.
 	type OnRemoveLiquidityPool: OnRemoveLiquidityPool;
 
+	/// The receiver of the signal for when the liquidity of a pool is updated.
+	type OnLiquidityUpdate: OnLiquidityUpdate;
+
 	/// Required origin for updating protocol options.
 	type UpdateOrigin: EnsureOrigin<Self::Origin>;
 }
@@ -218,7 +223,9 @@ decl_module! {
 		}
 
 		/// Withdraw liquidity from a pool.
-		#[weight = 10_000]
+		///
+		/// Weight includes the pool manager checks of the withdrawal.
+		#[weight = 10_000 + T::PoolManager::ensure_can_withdraw_weight()]
 		pub fn withdraw_liquidity(origin, #[compact] pool_id: LiquidityPoolId, #[compact] amount: Balance) {
 			with_transaction_result(|| {
 				let who = ensure_signed(origin)?;
@@ -358,6 +365,7 @@ impl<T: Trait<I>, I: Instance> Module<T, I> {
 		if let Some(mut pool) = Self::pools(pool_id) {
 			pool.balance = balance;
 			<Pools<T, I>>::insert(&pool_id, pool);
+			T::OnLiquidityUpdate::on_liquidity_update(pool_id, balance);
 		}
 	}
 }
@@ -397,6 +405,7 @@ impl<T: Trait<I>, I: Instance> Module<T, I> {
 
 		<Pools<T, I>>::remove(&pool_id);
 
+		T::OnLiquidityUpdate::on_liquidity_update(pool_id, 0);
 		T::OnRemoveLiquidityPool::on_remove(pool_id);
 
 		Ok(())
@@ -2,17 +2,15 @@
 
 use super::*;
 
-use frame_support::{ord_parameter_types, parameter_types, weights::Weight};
+use frame_support::{ord_parameter_types, parameter_types};
 use frame_system::EnsureSignedBy;
-use sp_core::H256;
-use sp_runtime::{
-	testing::Header,
-	traits::{BlakeTwo256, Block as BlockT, IdentityLookup},
-	Perbill,
-};
+use sp_runtime::{testing::Header, traits::Block as BlockT};
+use sp_std::cell::RefCell;
 
 use primitives::{Balance, CurrencyId, LiquidityPoolId};
 
+use laminar_test_support::MockPoolManager;
+
 pub type BlockNumber = u64;
 pub type AccountId = u128;
 
@@ -25,39 +23,14 @@ pub use crate as base_liquidity_pools;
 // For testing the module, we construct most of a mock runtime. This means
 // first constructing a configuration type (`Runtime`) which `impl`s each of the
 // configuration traits of modules we want to use.
-parameter_types! {
-	pub const BlockHashCount: u64 = 250;
-	pub const MaximumBlockWeight: Weight = 1024;
-	pub const MaximumBlockLength: u32 = 2 * 1024;
-	pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
-}
-
-impl system::Trait for Runtime {
-	type Origin = Origin;
-	type Call = Call;
-	type Index = u64;
-	type BlockNumber = BlockNumber;
-	type Hash = H256;
-	type Hashing = BlakeTwo256;
-	type AccountId = AccountId;
-	type Lookup = IdentityLookup<Self::AccountId>;
-	type Header = Header;
-	type Event = Event;
-	type BlockHashCount = BlockHashCount;
-	type MaximumBlockWeight = MaximumBlockWeight;
-	type DbWeight = ();
-	type BlockExecutionWeight = ();
-	type ExtrinsicBaseWeight = ();
-	type MaximumExtrinsicWeight = MaximumBlockWeight;
-	type MaximumBlockLength = MaximumBlockLength;
-	type AvailableBlockRatio = AvailableBlockRatio;
-	type Version = ();
-	type ModuleToIndex = ();
-	type AccountData = pallet_balances::AccountData<Balance>;
-	type OnNewAccount = ();
-	type OnKilledAccount = ();
-	type BaseCallFilter = ();
-}
+laminar_test_support::impl_mock_system!(
+	Runtime,
+	AccountId,
+	Event,
+	pallet_balances::AccountData<Balance>,
+	Call,
+	()
+);
 
 parameter_types! {
 	pub const ExistentialDeposit: u128 = 50;
@@ -93,16 +66,6 @@ impl orml_tokens::Trait for Runtime {
 	type OnReceived = ();
 }
 
-pub struct PoolManager;
-impl BaseLiquidityPoolManager<LiquidityPoolId, Balance> for PoolManager {
-	fn can_remove(_pool_id: LiquidityPoolId) -> bool {
-		true
-	}
-	fn ensure_can_withdraw(_pool: LiquidityPoolId, _amount: Balance) -> DispatchResult {
-		Ok(())
-	}
-}
-
 pub struct DummyOnDisable;
 impl OnDisableLiquidityPool for DummyOnDisable {
 	fn on_disable(_: LiquidityPoolId) {}
@@ -113,6 +76,24 @@ impl OnRemoveLiquidityPool for DummyOnRemove {
 	fn on_remove(_: LiquidityPoolId) {}
 }
 
+thread_local! {
+	static LIQUIDITY_UPDATES: RefCell<Vec<(LiquidityPoolId, Balance)>> = RefCell::new(vec![]);
+}
+
+/// Records liquidity updates.
+pub struct MockOnLiquidityUpdate;
+impl MockOnLiquidityUpdate {
+	pub fn updates() -> Vec<(LiquidityPoolId, Balance)> {
+		LIQUIDITY_UPDATES.with(|v| v.borrow().clone())
+	}
+}
+
+impl OnLiquidityUpdate for MockOnLiquidityUpdate {
+	fn on_liquidity_update(pool_id: LiquidityPoolId, liquidity: Balance) {
+		LIQUIDITY_UPDATES.with(|v| v.borrow_mut().push((pool_id, liquidity)));
+	}
+}
+
 parameter_types! {
 	pub const Instance1ModuleId: ModuleId = ModuleId(*b"test/lp1");
 }
@@ -120,26 +101,28 @@ parameter_types! {
 impl Trait for Runtime {
 	type Event = Event;
 	type LiquidityCurrency = LiquidityCurrency;
-	type PoolManager = PoolManager;
+	type PoolManager = MockPoolManager;
 	type ExistentialDeposit = ExistentialDeposit;
 	type IdentityDeposit = IdentityDeposit;
 	type IdentityDepositCurrency = Balances;
 	type ModuleId = Instance1ModuleId;
 	type OnDisableLiquidityPool = DummyOnDisable;
 	type OnRemoveLiquidityPool = DummyOnRemove;
+	type OnLiquidityUpdate = ();
 	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
 }
 
 impl Trait<Instance1> for Runtime {
 	type Event = Event;
 	type LiquidityCurrency = LiquidityCurrency;
-	type PoolManager = PoolManager;
+	type PoolManager = MockPoolManager;
 	type ExistentialDeposit = ExistentialDeposit;
 	type IdentityDeposit = IdentityDeposit;
 	type IdentityDepositCurrency = Balances;
 	type ModuleId = Instance1ModuleId;
 	type OnDisableLiquidityPool = DummyOnDisable;
 	type OnRemoveLiquidityPool = DummyOnRemove;
+	type OnLiquidityUpdate = MockOnLiquidityUpdate;
 	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
 }
 pub type Instance1Module = Module<Runtime, Instance1>;
@@ -151,13 +134,14 @@ parameter_types! {
 impl Trait<Instance2> for Runtime {
 	type Event = Event;
 	type LiquidityCurrency = LiquidityCurrency;
-	type PoolManager = PoolManager;
+	type PoolManager = MockPoolManager;
 	type ExistentialDeposit = ExistentialDeposit;
 	type IdentityDeposit = IdentityDeposit;
 	type IdentityDepositCurrency = Balances;
 	type ModuleId = Instance2ModuleId;
 	type OnDisableLiquidityPool = DummyOnDisable;
 	type OnRemoveLiquidityPool = DummyOnRemove;
+	type OnLiquidityUpdate = ();
 	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
 }
 pub type Instance2Module = Module<Runtime, Instance2>;
@@ -62,6 +62,7 @@ fn should_remove_pool() {
 		assert_eq!(Instance1Module::pools(0), None);
 		assert_eq!(Instance1Module::liquidity(0), 0);
 		assert_eq!(<Instance1Module as LiquidityPools<AccountId>>::liquidity(0), 0);
+		assert_eq!(MockOnLiquidityUpdate::updates(), vec![(0, 1000), (0, 0)]);
 	})
 }
 
@@ -93,6 +94,7 @@ fn should_withdraw_liquidity() {
 			&BOB, 0, 100
 		));
 		assert_eq!(Instance1Module::liquidity(0), 400);
+		assert_eq!(MockOnLiquidityUpdate::updates(), vec![(0, 1000), (0, 500), (0, 400)]);
 	})
 }
 
@@ -6,32 +6,40 @@ mod tests;
 use codec::{Decode, Encode};
 use frame_support::{
 	decl_error, decl_event, decl_module, decl_storage, ensure,
-	storage::IterableStorageMap,
+	storage::{IterableStorageDoubleMap, IterableStorageMap},
 	traits::{EnsureOrigin, Get, UnixTime},
 	weights::Weight,
 	Parameter,
 };
 use frame_system::{self as system, ensure_signed};
 use primitives::{
-	arithmetic::fixed_i128_mul_signum, AccumulateConfig, Balance, Leverage, Leverages, LiquidityPoolId, TradingPair,
+	arithmetic::{fixed_i128_mul_signum, fixed_i128_mul_with_rounding, Rounding},
+	AccumulateConfig, Balance, Leverage, Leverages, LiquidityPoolId, TraderTier, TradingPair, TradingPairMetadata,
 };
-use sp_arithmetic::{FixedI128, FixedPointNumber};
+use sp_arithmetic::{FixedI128, FixedPointNumber, Permill};
 use sp_runtime::{
-	traits::{AtLeast32Bit, Saturating},
+	traits::{AtLeast32Bit, CheckedAdd, CheckedMul, Saturating},
 	DispatchResult, ModuleId, RuntimeDebug,
 };
-use sp_std::{cmp::max, prelude::*, result};
+use sp_std::{cmp::max, mem, prelude::*, result};
 
 use orml_utilities::with_transaction_result;
 
 use traits::{
-	LiquidityPools, MarginProtocolLiquidityPools, MarginProtocolLiquidityPoolsManager, OnDisableLiquidityPool,
-	OnRemoveLiquidityPool, OpenPositionError,
+	AuditLog, LiquidityPools, MarginProtocolLiquidityPools, MarginProtocolLiquidityPoolsManager, OnDisableLiquidityPool,
+	OnRemoveLiquidityPool, OpenPositionError, TradingHalt,
 };
 
 #[cfg(feature = "std")]
 use serde::{Deserialize, Serialize};
 
+/// Computation weight of `on_initialize`, excluding storage accesses.
+const ON_INITIALIZE_BASE_WEIGHT: Weight = 10_000;
+/// Computation weight of checking the accumulate config of a trading pair in `on_initialize`.
+const CHECK_ACCUMULATE_WEIGHT_PER_PAIR: Weight = 5_000;
+/// Computation weight of accumulating swap rate of a trading pair in a pool, excluding storage accesses.
+const ACCUMULATE_WEIGHT_PER_POOL: Weight = 40_000;
+
 #[derive(Clone, Encode, Decode, RuntimeDebug, Eq, PartialEq, Default)]
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 pub struct SwapRate {
@@ -39,6 +47,16 @@ pub struct SwapRate {
 	pub short: FixedI128,
 }
 
+/// Accumulated swap rate of a trading pair in a pool, at the time it was accumulated.
+#[derive(Clone, Encode, Decode, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct AccumulatedSwapRateSnapshot<Moment> {
+	/// The accumulate time, in seconds.
+	pub time: Moment,
+
+	/// The accumulated swap rate.
+	pub accumulated: SwapRate,
+}
+
 /// Trading pair option of margin liquidity pools.
 #[derive(Encode, Decode, RuntimeDebug, Eq, PartialEq, Default)]
 pub struct MarginTradingPairOption<Moment> {
@@ -98,6 +116,32 @@ pub struct MarginPoolTradingPairOption {
 	pub enabled_trades: Leverages,
 }
 
+/// Leveraged amount limits of opening a position of a trading pair in a liquidity pool.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct LeveragedAmountLimit {
+	/// Min leveraged amount, overriding the pool's `min_leveraged_amount`. The default min leveraged amount is
+	/// used instead if higher.
+	///
+	/// DEFAULT-NOTE: the pool's min leveraged amount.
+	pub min: Option<Balance>,
+
+	/// Max leveraged amount.
+	///
+	/// DEFAULT-NOTE: no max requirement.
+	pub max: Option<Balance>,
+}
+
+/// Risk threshold of the backstop pool.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct BackstopRiskThreshold {
+	/// The backstop pool takes no new positions once its ENP is at or below it.
+	pub enp: Permill,
+	/// The backstop pool takes no new positions once its ELL is at or below it.
+	pub ell: Permill,
+}
+
 pub const MODULE_ID: ModuleId = ModuleId(*b"lami/mlp");
 pub const ONE_MINUTE: u64 = 60;
 
@@ -114,6 +158,9 @@ pub trait Trait: frame_system::Trait {
 	/// Required origin for updating protocol options.
 	type UpdateOrigin: EnsureOrigin<Self::Origin>;
 
+	/// Audit log of privileged parameter changes.
+	type AuditLog: AuditLog<Self::Origin>;
+
 	/// Maximum swap rate.
 	type MaxSwapRate: Get<FixedI128>;
 
@@ -122,6 +169,15 @@ pub trait Trait: frame_system::Trait {
 
 	/// Type used for expressing timestamp.
 	type Moment: AtLeast32Bit + Parameter + Default + Copy + From<u64>;
+
+	/// The account ID of treasury, owning and funding the backstop pool.
+	type GetTreasuryAccountId: Get<Self::AccountId>;
+
+	/// Maximum number of accumulated swap rate snapshots kept per trading pair in a pool.
+	type MaxSwapRateHistory: Get<u32>;
+
+	/// Trading halts on unhealthy oracle feeds. Trading pairs of halted currencies are disabled.
+	type TradingHalt: TradingHalt;
 }
 
 decl_storage! {
@@ -138,6 +194,9 @@ decl_storage! {
 		pub PoolTradingPairOptions: double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) TradingPair => MarginPoolTradingPairOption;
 
 		/// The accumulated swap rate of trading pairs in liquidity pools.
+		///
+		/// A cumulative index: positions record it on open, and their swap is the index delta since then, so
+		/// accumulation never iterates over positions.
 		pub AccumulatedSwapRates get(fn accumulated_swap_rate): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) TradingPair => SwapRate;
 
 		/// The default minimum leveraged amount allowed to open a position.
@@ -145,6 +204,36 @@ decl_storage! {
 
 		/// The last time swap rate was accumulated.
 		pub LastAccumulateTime get(fn last_accumulate_time): T::Moment;
+
+		/// Recent accumulated swap rates of trading pairs in liquidity pools, oldest first.
+		///
+		/// At most `MaxSwapRateHistory` snapshots kept.
+		pub AccumulatedSwapRateHistory get(fn accumulated_swap_rate_history): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) TradingPair => Vec<AccumulatedSwapRateSnapshot<T::Moment>>;
+
+		/// The protocol-owned pool backstopping trading pairs without available private pools.
+		pub BackstopPool get(fn backstop_pool): Option<LiquidityPoolId>;
+
+		/// Risk threshold of the backstop pool.
+		pub BackstopRiskThresholds get(fn backstop_risk_threshold): BackstopRiskThreshold;
+
+		/// Metadata of trading pairs, like pip size and minimum price increment.
+		pub TradingPairMetadatas get(fn trading_pair_metadata): map hasher(twox_64_concat) TradingPair => Option<TradingPairMetadata>;
+
+		/// Max leverage value allowed for traders of a tier in a liquidity pool, set by pool owner, in addition to
+		/// enabled leverages.
+		pub TraderMaxLeverages get(fn trader_max_leverage): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) TraderTier => Option<u8>;
+
+		/// Commission rate of the notional value charged on opening and closing positions of a trading pair in a
+		/// liquidity pool, set by pool owner.
+		///
+		/// Getter is implemented manually to cap the commission with max commission.
+		pub PoolCommissions: double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) TradingPair => Permill;
+
+		/// Max commission rate of a trading pair. Commissions of pools are not capped if not set.
+		pub MaxCommissions get(fn max_commission): map hasher(twox_64_concat) TradingPair => Option<Permill>;
+
+		/// Leveraged amount limits of trading pairs in liquidity pools, set by pool owner.
+		pub LeveragedAmountLimits get(fn leveraged_amount_limit): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) TradingPair => LeveragedAmountLimit;
 	}
 
 	add_extra_genesis {
@@ -152,6 +241,12 @@ decl_storage! {
 
 		build(|config: &GenesisConfig<T>| {
 			config.margin_liquidity_config.iter().for_each(|(pair, max_spread, accumulate_config, swap_rate)| {
+				assert!(accumulate_config.frequency >= ONE_MINUTE.into(), "accumulate frequency too low");
+				assert!(
+					swap_rate.long.saturating_abs() <= T::MaxSwapRate::get()
+						&& swap_rate.short.saturating_abs() <= T::MaxSwapRate::get(),
+					"swap rate too high"
+				);
 				<TradingPairOptions<T>>::insert(&pair, MarginTradingPairOption {
 					enabled: true,
 					swap_rate: swap_rate.clone(),
@@ -180,6 +275,9 @@ decl_event!(
 		/// Accumulated swap rate updated: [pool_id, pair, accumulated_swap_rate]
 		AccumulatedSwapRateUpdated(LiquidityPoolId, TradingPair, SwapRate),
 
+		/// Accumulated swap rate would overflow, and is left unchanged: [pool_id, pair]
+		AccumulatedSwapRateOverflow(LiquidityPoolId, TradingPair),
+
 		/// Additional swap rate updated: [who, pool_id, additional_swap_rate]
 		AdditionalSwapRateUpdated(AccountId, LiquidityPoolId, FixedI128),
 
@@ -206,6 +304,36 @@ decl_event!(
 
 		/// Pool min leveraged amount set: [pool_id, min_leveraged_amount]
 		MinLeveragedAmountSet(LiquidityPoolId, Balance),
+
+		/// Backstop pool set: [pool_id]
+		BackstopPoolSet(Option<LiquidityPoolId>),
+
+		/// Trading pair option of the backstop pool set: [pair, bid, ask, enabled_leverages]
+		BackstopTradingPairSet(TradingPair, Balance, Balance, Leverages),
+
+		/// Backstop pool risk threshold set: [threshold]
+		BackstopRiskThresholdSet(BackstopRiskThreshold),
+
+		/// Liquidity deposited to the backstop pool from treasury: [pool_id, amount]
+		BackstopLiquidityDeposited(LiquidityPoolId, Balance),
+
+		/// Liquidity withdrew from the backstop pool to treasury: [pool_id, amount]
+		BackstopLiquidityWithdrew(LiquidityPoolId, Balance),
+
+		/// Trading pair metadata set: [pair, metadata]
+		TradingPairMetadataSet(TradingPair, Option<TradingPairMetadata>),
+
+		/// Trader max leverage set: [who, pool_id, tier, max_leverage]
+		TraderMaxLeverageSet(AccountId, LiquidityPoolId, TraderTier, Option<u8>),
+
+		/// Commission set: [who, pool_id, pair, commission]
+		CommissionSet(AccountId, LiquidityPoolId, TradingPair, Permill),
+
+		/// Max commission set: [pair, max_commission]
+		MaxCommissionSet(TradingPair, Option<Permill>),
+
+		/// Leveraged amount limit set: [who, pool_id, pair, limit]
+		LeveragedAmountLimitSet(AccountId, LiquidityPoolId, TradingPair, LeveragedAmountLimit),
 	}
 );
 
@@ -247,12 +375,13 @@ decl_module! {
 		#[weight = 10_000]
 		pub fn set_swap_rate(origin, pair: TradingPair, rate: SwapRate) {
 			with_transaction_result(|| {
-				T::UpdateOrigin::ensure_origin(origin)?;
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
 
 				ensure!(rate.long.saturating_abs() <= T::MaxSwapRate::get(), Error::<T>::SwapRateTooHigh);
 				ensure!(rate.short.saturating_abs() <= T::MaxSwapRate::get(), Error::<T>::SwapRateTooHigh);
 
-				<TradingPairOptions<T>>::mutate(&pair, |o| o.swap_rate = rate.clone());
+				let old = <TradingPairOptions<T>>::mutate(&pair, |o| mem::replace(&mut o.swap_rate, rate.clone()));
+				Self::record_change(origin, b"SwapRate", pair, old.encode(), rate.encode());
 
 				Self::deposit_event(RawEvent::SwapRateUpdated(pair, rate));
 
@@ -283,8 +412,9 @@ decl_module! {
 		#[weight = 10_000]
 		pub fn set_max_spread(origin, pair: TradingPair, #[compact] max_spread: Balance) {
 			with_transaction_result(|| {
-				T::UpdateOrigin::ensure_origin(origin)?;
-				<TradingPairOptions<T>>::mutate(&pair, |o| o.max_spread = Some(max_spread));
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = <TradingPairOptions<T>>::mutate(&pair, |o| o.max_spread.replace(max_spread));
+				Self::record_change(origin, b"MaxSpread", pair, old.encode(), Some(max_spread).encode());
 				Self::deposit_event(RawEvent::MaxSpreadUpdated(pair, max_spread));
 				Ok(())
 			})?;
@@ -296,14 +426,16 @@ decl_module! {
 		#[weight = 10_000]
 		pub fn set_accumulate_config(origin, pair: TradingPair, frequency: T::Moment, offset: T::Moment) {
 			with_transaction_result(|| {
-				T::UpdateOrigin::ensure_origin(origin)?;
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
 
 				ensure!(frequency >= ONE_MINUTE.into(), Error::<T>::FrequencyTooLow);
 
-				<TradingPairOptions<T>>::mutate(
+				let new = Some(AccumulateConfig { frequency, offset });
+				let old = <TradingPairOptions<T>>::mutate(
 					&pair,
-					|o| o.accumulate_config = Some(AccumulateConfig { frequency, offset })
+					|o| mem::replace(&mut o.accumulate_config, new.clone())
 				);
+				Self::record_change(origin, b"AccumulateConfig", pair, old.encode(), new.encode());
 
 				Self::deposit_event(RawEvent::AccumulateConfigSet(pair, frequency, offset));
 
@@ -311,14 +443,35 @@ decl_module! {
 			})?;
 		}
 
+		/// Set metadata of `pair`, or remove it if `None`.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_trading_pair_metadata(origin, pair: TradingPair, metadata: Option<TradingPairMetadata>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				if let Some(m) = metadata {
+					ensure!(
+						!m.pip_size.is_zero() && !m.min_increment.is_zero(),
+						Error::<T>::InvalidTradingPairMetadata
+					);
+				}
+				let old = TradingPairMetadatas::mutate(&pair, |m| mem::replace(m, metadata));
+				Self::record_change(origin, b"TradingPairMetadata", pair, old.encode(), metadata.encode());
+				Self::deposit_event(RawEvent::TradingPairMetadataSet(pair, metadata));
+				Ok(())
+			})?;
+		}
+
 		/// Enable a trading pair.
 		///
 		/// May only be called from `UpdateOrigin`.
 		#[weight = 10_000]
 		pub fn enable_trading_pair(origin, pair: TradingPair) {
 			with_transaction_result(|| {
-				T::UpdateOrigin::ensure_origin(origin)?;
-				<TradingPairOptions<T>>::mutate(&pair, |o| o.enabled = true);
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = <TradingPairOptions<T>>::mutate(&pair, |o| mem::replace(&mut o.enabled, true));
+				Self::record_change(origin, b"TradingPairEnabled", pair, old.encode(), true.encode());
 				Self::deposit_event(RawEvent::TradingPairEnabled(pair));
 				Ok(())
 			})?;
@@ -330,8 +483,9 @@ decl_module! {
 		#[weight = 10_000]
 		pub fn disable_trading_pair(origin, pair: TradingPair) {
 			with_transaction_result(|| {
-				T::UpdateOrigin::ensure_origin(origin)?;
-				<TradingPairOptions<T>>::mutate(&pair, |o| o.enabled = false);
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = <TradingPairOptions<T>>::mutate(&pair, |o| mem::replace(&mut o.enabled, false));
+				Self::record_change(origin, b"TradingPairEnabled", pair, old.encode(), false.encode());
 				Self::deposit_event(RawEvent::TradingPairDisabled(pair));
 				Ok(())
 			})?;
@@ -380,8 +534,16 @@ decl_module! {
 		#[weight = 10_000]
 		pub fn set_default_min_leveraged_amount(origin, #[compact] amount: Balance) {
 			with_transaction_result(|| {
-				T::UpdateOrigin::ensure_origin(origin)?;
-				DefaultMinLeveragedAmount::put(amount);
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = DefaultMinLeveragedAmount::mutate(|a| mem::replace(a, amount));
+				T::AuditLog::record(
+					origin,
+					b"MarginLiquidityPools",
+					b"DefaultMinLeveragedAmount",
+					vec![],
+					old.encode(),
+					amount.encode(),
+				);
 				Self::deposit_event(RawEvent::DefaultMinLeveragedAmountSet(amount));
 				Ok(())
 			})?;
@@ -404,12 +566,212 @@ decl_module! {
 			})?;
 		}
 
+		/// Set the max leverage value allowed for traders of `tier` in `pool_id`, or remove the cap if `None`.
+		///
+		/// May only be called from the pool owner.
+		#[weight = 10_000]
+		pub fn set_trader_max_leverage(origin, #[compact] pool_id: LiquidityPoolId, tier: TraderTier, max_leverage: Option<u8>) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				ensure!(Self::is_owner(pool_id, &who), Error::<T>::NoPermission);
+
+				match max_leverage {
+					Some(l) => TraderMaxLeverages::insert(pool_id, tier, l),
+					None => TraderMaxLeverages::remove(pool_id, tier),
+				}
+
+				Self::deposit_event(RawEvent::TraderMaxLeverageSet(who, pool_id, tier, max_leverage));
+
+				Ok(())
+			})?;
+		}
+
+		/// Set the commission rate of the notional value, charged on opening and closing positions of `pair` in
+		/// `pool_id`.
+		///
+		/// May only be called from the pool owner.
+		#[weight = 10_000]
+		pub fn set_commission(origin, #[compact] pool_id: LiquidityPoolId, pair: TradingPair, commission: Permill) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				ensure!(Self::is_owner(pool_id, &who), Error::<T>::NoPermission);
+
+				if commission == Permill::zero() {
+					PoolCommissions::remove(pool_id, pair);
+				} else {
+					PoolCommissions::insert(pool_id, pair, commission);
+				}
+
+				Self::deposit_event(RawEvent::CommissionSet(who, pool_id, pair, commission));
+
+				Ok(())
+			})?;
+		}
+
+		/// Set the max commission rate of `pair`, or remove the cap if `None`.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_max_commission(origin, pair: TradingPair, max_commission: Option<Permill>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Self::max_commission(pair);
+				match max_commission {
+					Some(c) => MaxCommissions::insert(pair, c),
+					None => MaxCommissions::remove(pair),
+				}
+				Self::record_change(origin, b"MaxCommission", pair, old.encode(), max_commission.encode());
+				Self::deposit_event(RawEvent::MaxCommissionSet(pair, max_commission));
+				Ok(())
+			})?;
+		}
+
+		/// Set the backstop pool, or unset it if `None`. The pool must be owned by treasury. Trading pairs of the
+		/// previous backstop pool are disabled.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_backstop_pool(origin, pool_id: Option<LiquidityPoolId>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Self::do_set_backstop_pool(pool_id)?;
+				T::AuditLog::record(
+					origin,
+					b"MarginLiquidityPools",
+					b"BackstopPool",
+					vec![],
+					old.encode(),
+					pool_id.encode(),
+				);
+				Self::deposit_event(RawEvent::BackstopPoolSet(pool_id));
+				Ok(())
+			})?;
+		}
+
+		/// Set spreads and enabled leverages of `pair` in the backstop pool, and enable the pair in it. Spreads are
+		/// capped by the max spread as in other pools.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_backstop_trading_pair(
+			origin,
+			pair: TradingPair,
+			#[compact] bid: Balance,
+			#[compact] ask: Balance,
+			enabled: Leverages,
+		) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let pool_id = Self::backstop_pool().ok_or(Error::<T>::NoBackstopPool)?;
+				ensure!(Self::is_trading_pair_enabled(pair), Error::<T>::TradingPairNotEnabled);
+
+				let new = MarginPoolTradingPairOption {
+					enabled: true,
+					bid_spread: Some(bid),
+					ask_spread: Some(ask),
+					enabled_trades: enabled,
+				};
+				let old = PoolTradingPairOptions::mutate(pool_id, pair, |o| mem::replace(o, new));
+				Self::record_change(
+					origin,
+					b"BackstopTradingPair",
+					pair,
+					old.encode(),
+					PoolTradingPairOptions::get(pool_id, pair).encode(),
+				);
+
+				Self::deposit_event(RawEvent::BackstopTradingPairSet(pair, bid, ask, enabled));
+				Ok(())
+			})?;
+		}
+
+		/// Set the risk threshold of the backstop pool.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_backstop_risk_threshold(origin, threshold: BackstopRiskThreshold) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = BackstopRiskThresholds::mutate(|t| mem::replace(t, threshold));
+				T::AuditLog::record(
+					origin,
+					b"MarginLiquidityPools",
+					b"BackstopRiskThreshold",
+					vec![],
+					old.encode(),
+					threshold.encode(),
+				);
+				Self::deposit_event(RawEvent::BackstopRiskThresholdSet(threshold));
+				Ok(())
+			})?;
+		}
+
+		/// Deposit `amount` of liquidity from treasury to the backstop pool.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn deposit_backstop_liquidity(origin, #[compact] amount: Balance) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				let pool_id = Self::backstop_pool().ok_or(Error::<T>::NoBackstopPool)?;
+				T::BaseLiquidityPools::deposit_liquidity(&T::GetTreasuryAccountId::get(), pool_id, amount)?;
+				Self::deposit_event(RawEvent::BackstopLiquidityDeposited(pool_id, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Withdraw `amount` of liquidity from the backstop pool to treasury.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn withdraw_backstop_liquidity(origin, #[compact] amount: Balance) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				let pool_id = Self::backstop_pool().ok_or(Error::<T>::NoBackstopPool)?;
+				T::BaseLiquidityPools::withdraw_liquidity(&T::GetTreasuryAccountId::get(), pool_id, amount)?;
+				Self::deposit_event(RawEvent::BackstopLiquidityWithdrew(pool_id, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Set the min and max leveraged amount to open a position of `pair` in `pool_id`.
+		///
+		/// May only be called from the pool owner.
+		#[weight = 10_000]
+		pub fn set_leveraged_amount_limit(origin, #[compact] pool_id: LiquidityPoolId, pair: TradingPair, limit: LeveragedAmountLimit) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				ensure!(Self::is_owner(pool_id, &who), Error::<T>::NoPermission);
+				if let (Some(min), Some(max)) = (limit.min, limit.max) {
+					ensure!(min <= max, Error::<T>::InvalidLeveragedAmountLimit);
+				}
+
+				if limit == LeveragedAmountLimit::default() {
+					LeveragedAmountLimits::remove(pool_id, pair);
+				} else {
+					LeveragedAmountLimits::insert(pool_id, pair, limit);
+				}
+
+				Self::deposit_event(RawEvent::LeveragedAmountLimitSet(who, pool_id, pair, limit));
+
+				Ok(())
+			})?;
+		}
+
 		fn on_initialize() -> Weight {
 			let now_as_mins: T::Moment = (T::UnixTime::now().as_secs() / ONE_MINUTE).into();
 			// Truncate seconds, keep minutes
 			let now_as_secs: T::Moment = now_as_mins * ONE_MINUTE.into();
 
+			let db_weight = T::DbWeight::get();
+			// `LastAccumulateTime` read
+			let mut weight = ON_INITIALIZE_BASE_WEIGHT.saturating_add(db_weight.reads(1));
+
 			<TradingPairOptions<T>>::iter().for_each(|(pair, option)| {
+				weight = weight
+					.saturating_add(CHECK_ACCUMULATE_WEIGHT_PER_PAIR)
+					.saturating_add(db_weight.reads(1));
+
 				if let Some(accumulate_config) = option.accumulate_config {
 					let frequency_as_mins = accumulate_config.frequency / ONE_MINUTE.into();
 					let offset_as_mins = accumulate_config.offset / ONE_MINUTE.into();
@@ -419,11 +781,20 @@ decl_module! {
 						&& <LastAccumulateTime<T>>::get() != now_as_secs
 					{
 						<LastAccumulateTime<T>>::set(now_as_secs);
-						Self::accumulate_rates(pair);
+						let pools_count = Self::accumulate_rates(pair, now_as_secs) as Weight;
+
+						// `all` pools read, and per pool: swap rate of pair and additional swap rate reads on both
+						// long and short, accumulated swap rate and history read and write, and event write
+						weight = weight
+							.saturating_add(db_weight.reads_writes(1, 1))
+							.saturating_add(pools_count.saturating_mul(
+								ACCUMULATE_WEIGHT_PER_POOL.saturating_add(db_weight.reads_writes(6, 3)),
+							));
 					}
 				}
 			});
-			10_000
+
+			weight
 		}
 	}
 }
@@ -442,6 +813,24 @@ decl_error! {
 
 		/// `frequency` of accumulate config is too low.
 		FrequencyTooLow,
+
+		/// Pool not found.
+		PoolNotFound,
+
+		/// Backstop pool must be owned by treasury.
+		NotTreasuryPool,
+
+		/// Backstop pool is not set.
+		NoBackstopPool,
+
+		/// Pip size or minimum increment of trading pair metadata is zero.
+		InvalidTradingPairMetadata,
+
+		/// Spread is not a multiple of the minimum price increment of the trading pair.
+		SpreadNotMultipleOfIncrement,
+
+		/// Min leveraged amount is above max.
+		InvalidLeveragedAmountLimit,
 	}
 }
 
@@ -457,12 +846,44 @@ impl<T: Trait> Module<T> {
 		Self::trading_pair_options(pair).accumulate_config
 	}
 
+	/// The next time swap rate of `pair` would be accumulated, in seconds. `None` if never.
+	pub fn next_accumulate_time(pair: TradingPair) -> Option<T::Moment> {
+		let accumulate_config = Self::accumulate_config(pair)?;
+		let frequency_as_mins = accumulate_config.frequency / ONE_MINUTE.into();
+		let offset_as_mins = accumulate_config.offset / ONE_MINUTE.into();
+		if frequency_as_mins == 0.into() || offset_as_mins >= frequency_as_mins {
+			return None;
+		}
+
+		let now_as_mins: T::Moment = (T::UnixTime::now().as_secs() / ONE_MINUTE).into();
+		let mut next_as_mins = now_as_mins - now_as_mins % frequency_as_mins + offset_as_mins;
+		if next_as_mins <= now_as_mins {
+			next_as_mins = next_as_mins.checked_add(&frequency_as_mins)?;
+		}
+		next_as_mins.checked_mul(&ONE_MINUTE.into())
+	}
+
 	pub fn swap_rate_of_pair(pair: TradingPair) -> SwapRate {
 		Self::trading_pair_options(pair).swap_rate
 	}
 
+	/// Whether `pair` is enabled, and none of its currencies is halted.
 	pub fn is_trading_pair_enabled(pair: TradingPair) -> bool {
 		Self::trading_pair_options(pair).enabled
+			&& !T::TradingHalt::is_trading_halted(pair.base)
+			&& !T::TradingHalt::is_trading_halted(pair.quote)
+	}
+
+	/// Metadata of all trading pairs, in display order.
+	pub fn trading_pair_metadatas() -> Vec<(TradingPair, TradingPairMetadata)> {
+		let mut metadatas: Vec<(TradingPair, TradingPairMetadata)> = TradingPairMetadatas::iter().collect();
+		metadatas.sort_by_key(|(pair, m)| (m.display_order, *pair));
+		metadatas
+	}
+
+	/// Record a change of trading pair option `parameter` into the audit log.
+	fn record_change(origin: T::Origin, parameter: &[u8], pair: TradingPair, old_value: Vec<u8>, new_value: Vec<u8>) {
+		T::AuditLog::record(origin, b"MarginLiquidityPools", parameter, pair.encode(), old_value, new_value);
 	}
 
 	// Pool margin option
@@ -478,6 +899,15 @@ impl<T: Trait> Module<T> {
 		max(pool_min_leveraged_amount, Self::default_min_leveraged_amount())
 	}
 
+	/// Min leveraged amount of `pair` in `pool_id`. The min of `pair` overrides the pool's, and
+	/// `default_min_leveraged_amount` will be used if higher.
+	pub fn min_leveraged_amount_of_pair(pool_id: LiquidityPoolId, pair: TradingPair) -> Balance {
+		let pool_min_leveraged_amount = Self::leveraged_amount_limit(pool_id, pair)
+			.min
+			.unwrap_or_else(|| Self::pool_options(pool_id).min_leveraged_amount);
+		max(pool_min_leveraged_amount, Self::default_min_leveraged_amount())
+	}
+
 	// Pool trading pair margin option
 
 	/// `PoolTradingPairOptions` getter. Bid/ask spread is capped by max spread.
@@ -494,6 +924,47 @@ impl<T: Trait> Module<T> {
 		PoolTradingPairOptions::get(pool_id, pair).enabled
 	}
 
+	/// The highest enabled leverage value of `pair` in `pool_id` on the long or short side, or `None` if none
+	/// enabled.
+	/// Commission rate of `pair` in `pool_id`, capped by the max commission.
+	pub fn commission(pool_id: LiquidityPoolId, pair: TradingPair) -> Permill {
+		let commission = PoolCommissions::get(pool_id, pair);
+		match Self::max_commission(pair) {
+			Some(max_commission) => commission.min(max_commission),
+			None => commission,
+		}
+	}
+
+	pub fn max_enabled_leverage(pool_id: LiquidityPoolId, pair: TradingPair, is_long: bool) -> Option<u8> {
+		Self::max_enabled_leverage_up_to(pool_id, pair, is_long, u8::max_value())
+	}
+
+	/// As `max_enabled_leverage`, capped by the max leverage allowed for traders of `tier` in `pool_id`.
+	pub fn max_enabled_leverage_of_tier(
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		is_long: bool,
+		tier: TraderTier,
+	) -> Option<u8> {
+		let cap = Self::trader_max_leverage(pool_id, tier).unwrap_or(u8::max_value());
+		Self::max_enabled_leverage_up_to(pool_id, pair, is_long, cap)
+	}
+
+	/// As `max_enabled_leverage`, capped by `cap`.
+	pub fn max_enabled_leverage_up_to(
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		is_long: bool,
+		cap: u8,
+	) -> Option<u8> {
+		let enabled_trades = Self::pool_trading_pair_options(pool_id, pair).enabled_trades;
+		(0..16u8)
+			.filter_map(|i| Leverage::decode(&mut &[i][..]).ok())
+			.filter(|l| l.is_long() == is_long && enabled_trades.contains(*l) && l.value() <= cap)
+			.map(|l| l.value())
+			.max()
+	}
+
 	pub fn is_pool_trading_pair_leverage_enabled(
 		pool_id: LiquidityPoolId,
 		pair: TradingPair,
@@ -550,8 +1021,11 @@ impl<T: Trait> MarginProtocolLiquidityPools<T::AccountId> for Module<T> {
 		let additional_swap_rate = Self::additional_swap_rate(pool_id);
 
 		let swap_rate = if is_long { swap_rate.long } else { swap_rate.short };
-		// adjust_swap = swap - abs(swap) * additional_swap_rate
-		let adjust_swap = swap_rate.saturating_sub(swap_rate.saturating_abs().saturating_mul(additional_swap_rate));
+		// adjust_swap = swap - abs(swap) * additional_swap_rate, rounded in favor of the pool
+		let abs_swap = swap_rate.saturating_abs();
+		let additional_swap = fixed_i128_mul_with_rounding(abs_swap, additional_swap_rate, Rounding::Up)
+			.unwrap_or_else(|| abs_swap.saturating_mul(additional_swap_rate));
+		let adjust_swap = swap_rate.saturating_sub(additional_swap);
 
 		if adjust_swap.saturating_abs() <= max_swap {
 			adjust_swap
@@ -588,11 +1062,28 @@ impl<T: Trait> MarginProtocolLiquidityPools<T::AccountId> for Module<T> {
 		if !Self::is_pool_trading_pair_enabled(pool_id, pair) {
 			return Err(OpenPositionError::TradingPairNotEnabledInPool);
 		}
-		if leveraged_amount < Self::min_leveraged_amount(pool_id) {
+		if leveraged_amount < Self::min_leveraged_amount_of_pair(pool_id, pair) {
 			return Err(OpenPositionError::BelowMinLeveragedAmount);
 		}
+		if Self::leveraged_amount_limit(pool_id, pair)
+			.max
+			.map_or(false, |max| leveraged_amount > max)
+		{
+			return Err(OpenPositionError::AboveMaxLeveragedAmount);
+		}
+		if Self::backstop_pool() == Some(pool_id) && !Self::is_backstop_active(pair) {
+			return Err(OpenPositionError::BackstopNotActive);
+		}
 		Ok(())
 	}
+
+	fn trader_max_leverage(pool_id: LiquidityPoolId, tier: TraderTier) -> Option<u8> {
+		Self::trader_max_leverage(pool_id, tier)
+	}
+
+	fn commission(pool_id: LiquidityPoolId, pair: TradingPair) -> Permill {
+		Self::commission(pool_id, pair)
+	}
 }
 
 // Dispatchable calls implementation
@@ -605,6 +1096,13 @@ impl<T: Trait> Module<T> {
 		ask: Balance,
 	) -> DispatchResult {
 		ensure!(Self::is_owner(pool_id, who), Error::<T>::NoPermission);
+		if let Some(m) = Self::trading_pair_metadata(pair) {
+			let increment = m.min_increment.into_inner();
+			ensure!(
+				bid % increment == 0 && ask % increment == 0,
+				Error::<T>::SpreadNotMultipleOfIncrement
+			);
+		}
 		PoolTradingPairOptions::mutate(pool_id, pair, |o| {
 			o.bid_spread = Some(bid);
 			o.ask_spread = Some(ask);
@@ -622,22 +1120,89 @@ impl<T: Trait> Module<T> {
 		PoolTradingPairOptions::mutate(pool_id, pair, |o| o.enabled_trades = enabled);
 		Ok(())
 	}
+
+	/// Set the backstop pool. Returns the previous one.
+	fn do_set_backstop_pool(pool_id: Option<LiquidityPoolId>) -> result::Result<Option<LiquidityPoolId>, Error<T>> {
+		if let Some(pool_id) = pool_id {
+			ensure!(T::BaseLiquidityPools::pool_exists(pool_id), Error::<T>::PoolNotFound);
+			ensure!(
+				T::BaseLiquidityPools::is_owner(pool_id, &T::GetTreasuryAccountId::get()),
+				Error::<T>::NotTreasuryPool
+			);
+		}
+
+		let old = BackstopPool::mutate(|p| mem::replace(p, pool_id));
+		if let Some(old) = old.filter(|old| Some(*old) != pool_id) {
+			// keep spreads for closing positions, but stop opening
+			let pairs: Vec<TradingPair> = PoolTradingPairOptions::iter_prefix(old).map(|(pair, _)| pair).collect();
+			for pair in pairs {
+				PoolTradingPairOptions::mutate(old, pair, |o| o.enabled = false);
+			}
+		}
+
+		Ok(old)
+	}
 }
 
 // Private methods
 impl<T: Trait> Module<T> {
-	fn accumulate_rates(pair: TradingPair) {
-		for pool_id in T::BaseLiquidityPools::all() {
+	/// The backstop pool takes new positions of `pair` iff no other pool has `pair` enabled and is safe, and the
+	/// backstop pool is above its risk threshold.
+	pub fn is_backstop_active(pair: TradingPair) -> bool {
+		let backstop = match Self::backstop_pool() {
+			Some(p) => p,
+			None => return false,
+		};
+
+		let threshold = Self::backstop_risk_threshold();
+		let is_backstop_safe = T::PoolManager::enp_and_ell(backstop).map_or(false, |(enp, ell)| {
+			enp > threshold.enp.into() && ell > threshold.ell.into()
+		});
+
+		is_backstop_safe
+			&& !T::BaseLiquidityPools::all().into_iter().any(|pool_id| {
+				pool_id != backstop
+					&& Self::is_pool_trading_pair_enabled(pool_id, pair)
+					&& T::PoolManager::is_pool_safe(pool_id)
+			})
+	}
+
+	/// Accumulate swap rates of `pair` in all pools, at time `now` in seconds. Returns the number of pools.
+	fn accumulate_rates(pair: TradingPair, now: T::Moment) -> usize {
+		let pools = T::BaseLiquidityPools::all();
+		let pools_count = pools.len();
+		let max_history = T::MaxSwapRateHistory::get() as usize;
+		for pool_id in pools {
 			let long_rate = Self::swap_rate(pool_id, pair, true);
 			let short_rate = Self::swap_rate(pool_id, pair, false);
 
-			let mut accumulated = Self::accumulated_swap_rate(pool_id, pair);
-			accumulated.long = accumulated.long.saturating_add(long_rate);
-			accumulated.short = accumulated.short.saturating_add(short_rate);
+			let accumulated = Self::accumulated_swap_rate(pool_id, pair);
+			let (long, short) = match (
+				accumulated.long.checked_add(&long_rate),
+				accumulated.short.checked_add(&short_rate),
+			) {
+				(Some(long), Some(short)) => (long, short),
+				_ => {
+					Self::deposit_event(RawEvent::AccumulatedSwapRateOverflow(pool_id, pair));
+					continue;
+				}
+			};
+			let accumulated = SwapRate { long, short };
 			AccumulatedSwapRates::insert(pool_id, pair, accumulated.clone());
+			<AccumulatedSwapRateHistory<T>>::mutate(pool_id, pair, |history| {
+				history.push(AccumulatedSwapRateSnapshot {
+					time: now,
+					accumulated: accumulated.clone(),
+				});
+				if history.len() > max_history {
+					let expired = history.len() - max_history;
+					history.drain(..expired);
+				}
+			});
 
 			Self::deposit_event(RawEvent::AccumulatedSwapRateUpdated(pool_id, pair, accumulated))
 		}
+		pools_count
 	}
 }
 
@@ -651,6 +1216,13 @@ impl<T: Trait> OnRemoveLiquidityPool for Module<T> {
 	fn on_remove(pool_id: LiquidityPoolId) {
 		PoolTradingPairOptions::remove_prefix(&pool_id);
 		AccumulatedSwapRates::remove_prefix(&pool_id);
+		<AccumulatedSwapRateHistory<T>>::remove_prefix(&pool_id);
 		PoolOptions::remove(&pool_id);
+		TraderMaxLeverages::remove_prefix(&pool_id);
+		PoolCommissions::remove_prefix(&pool_id);
+		LeveragedAmountLimits::remove_prefix(&pool_id);
+		if Self::backstop_pool() == Some(pool_id) {
+			BackstopPool::kill();
+		}
 	}
 }
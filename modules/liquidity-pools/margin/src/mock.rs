@@ -2,25 +2,24 @@
 
 use super::*;
 
-use frame_support::{impl_outer_origin, ord_parameter_types, parameter_types, traits::OnInitialize, weights::Weight};
+use frame_support::{impl_outer_origin, ord_parameter_types, parameter_types, traits::OnInitialize};
 use frame_system::EnsureSignedBy;
-use sp_core::H256;
-use sp_runtime::{
-	testing::Header,
-	traits::{BlakeTwo256, IdentityLookup},
-	Perbill,
-};
+use sp_arithmetic::traits::Bounded;
+use sp_std::{cell::RefCell, collections::btree_set::BTreeSet};
 
 use orml_currencies::Currency;
 
 use primitives::{Balance, CurrencyId, LiquidityPoolId};
-use traits::{BaseLiquidityPoolManager, MarginProtocolLiquidityPoolsManager};
+use traits::MarginProtocolLiquidityPoolsManager;
+
+use laminar_test_support::{MockPoolManager, MockTradingHalt};
 
 pub type BlockNumber = u64;
 pub type AccountId = u64;
 
 ord_parameter_types! {
 	pub const UpdateOrigin: AccountId = 0;
+	pub const TreasuryAccount: AccountId = 3;
 }
 
 impl_outer_origin! {
@@ -32,39 +31,7 @@ impl_outer_origin! {
 // configuration traits of modules we want to use.
 #[derive(Clone, Eq, PartialEq)]
 pub struct Runtime;
-parameter_types! {
-	pub const BlockHashCount: u64 = 250;
-	pub const MaximumBlockWeight: Weight = 1024;
-	pub const MaximumBlockLength: u32 = 2 * 1024;
-	pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
-}
-
-impl system::Trait for Runtime {
-	type Origin = Origin;
-	type Call = ();
-	type Index = u64;
-	type BlockNumber = BlockNumber;
-	type Hash = H256;
-	type Hashing = BlakeTwo256;
-	type AccountId = AccountId;
-	type Lookup = IdentityLookup<Self::AccountId>;
-	type Header = Header;
-	type Event = ();
-	type BlockHashCount = BlockHashCount;
-	type MaximumBlockWeight = MaximumBlockWeight;
-	type DbWeight = ();
-	type BlockExecutionWeight = ();
-	type ExtrinsicBaseWeight = ();
-	type MaximumExtrinsicWeight = MaximumBlockWeight;
-	type MaximumBlockLength = MaximumBlockLength;
-	type AvailableBlockRatio = AvailableBlockRatio;
-	type Version = ();
-	type ModuleToIndex = ();
-	type AccountData = pallet_balances::AccountData<Balance>;
-	type OnNewAccount = ();
-	type OnKilledAccount = ();
-	type BaseCallFilter = ();
-}
+laminar_test_support::impl_mock_system!(Runtime, AccountId, (), pallet_balances::AccountData<Balance>);
 
 pub type System = system::Module<Runtime>;
 
@@ -73,6 +40,7 @@ parameter_types! {
 	pub const GetNativeCurrencyId: CurrencyId = CurrencyId::LAMI;
 	pub const GetLiquidityCurrencyId: CurrencyId = CurrencyId::AUSD;
 	pub MaxSwap: FixedI128 = FixedI128::saturating_from_integer(2);
+	pub const MaxSwapRateHistory: u32 = 3;
 }
 
 impl pallet_balances::Trait for Runtime {
@@ -102,16 +70,6 @@ impl orml_tokens::Trait for Runtime {
 	type OnReceived = ();
 }
 
-pub struct PoolManager;
-impl BaseLiquidityPoolManager<LiquidityPoolId, Balance> for PoolManager {
-	fn can_remove(_pool_id: LiquidityPoolId) -> bool {
-		true
-	}
-	fn ensure_can_withdraw(_pool: LiquidityPoolId, _amount: Balance) -> DispatchResult {
-		Ok(())
-	}
-}
-
 parameter_types! {
 	pub const MarginLiquidityPoolsModuleId: ModuleId = MODULE_ID;
 	pub const IdentityDeposit: Balance = 1000;
@@ -122,22 +80,51 @@ pub type MarginInstance = module_base_liquidity_pools::Instance1;
 impl module_base_liquidity_pools::Trait<MarginInstance> for Runtime {
 	type Event = ();
 	type LiquidityCurrency = LiquidityCurrency;
-	type PoolManager = PoolManager;
+	type PoolManager = MockPoolManager;
 	type ExistentialDeposit = ExistentialDeposit;
 	type IdentityDeposit = IdentityDeposit;
 	type IdentityDepositCurrency = pallet_balances::Module<Self>;
 	type ModuleId = MarginLiquidityPoolsModuleId;
 	type OnDisableLiquidityPool = ModuleLiquidityPools;
 	type OnRemoveLiquidityPool = ModuleLiquidityPools;
+	type OnLiquidityUpdate = ();
 	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
 }
 pub type BaseLiquidityPools = module_base_liquidity_pools::Module<Runtime, MarginInstance>;
 
+thread_local! {
+	static UNSAFE_POOLS: RefCell<BTreeSet<LiquidityPoolId>> = RefCell::new(BTreeSet::new());
+	static ENP_AND_ELL: RefCell<(FixedI128, FixedI128)> = RefCell::new((FixedI128::max_value(), FixedI128::max_value()));
+}
+
 pub struct DummyPoolManager;
+impl DummyPoolManager {
+	pub fn set_pool_unsafe(pool_id: LiquidityPoolId, is_unsafe: bool) {
+		UNSAFE_POOLS.with(|v| {
+			if is_unsafe {
+				v.borrow_mut().insert(pool_id);
+			} else {
+				v.borrow_mut().remove(&pool_id);
+			}
+		});
+	}
+
+	pub fn set_enp_and_ell(enp: FixedI128, ell: FixedI128) {
+		ENP_AND_ELL.with(|v| *v.borrow_mut() = (enp, ell));
+	}
+}
 impl MarginProtocolLiquidityPoolsManager for DummyPoolManager {
 	fn ensure_can_enable_trading_pair(_pool_id: LiquidityPoolId, _pair: TradingPair) -> DispatchResult {
 		Ok(())
 	}
+
+	fn is_pool_safe(pool_id: LiquidityPoolId) -> bool {
+		UNSAFE_POOLS.with(|v| !v.borrow().contains(&pool_id))
+	}
+
+	fn enp_and_ell(_pool_id: LiquidityPoolId) -> Option<(FixedI128, FixedI128)> {
+		Some(ENP_AND_ELL.with(|v| *v.borrow()))
+	}
 }
 
 parameter_types! {
@@ -155,9 +142,13 @@ impl Trait for Runtime {
 	type BaseLiquidityPools = module_base_liquidity_pools::Module<Runtime, MarginInstance>;
 	type PoolManager = DummyPoolManager;
 	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
+	type AuditLog = ();
 	type MaxSwapRate = MaxSwap;
 	type UnixTime = Timestamp;
 	type Moment = u64;
+	type GetTreasuryAccountId = TreasuryAccount;
+	type MaxSwapRateHistory = MaxSwapRateHistory;
+	type TradingHalt = MockTradingHalt;
 }
 pub type ModuleLiquidityPools = Module<Runtime>;
 
@@ -170,7 +161,11 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
 		.into();
 
 	orml_tokens::GenesisConfig::<Runtime> {
-		endowed_accounts: vec![(ALICE, CurrencyId::AUSD, 100_000), (BOB, CurrencyId::AUSD, 100_000)],
+		endowed_accounts: vec![
+			(ALICE, CurrencyId::AUSD, 100_000),
+			(BOB, CurrencyId::AUSD, 100_000),
+			(TreasuryAccount::get(), CurrencyId::AUSD, 100_000),
+		],
 	}
 	.assimilate_storage(&mut t)
 	.unwrap();
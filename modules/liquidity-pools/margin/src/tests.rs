@@ -5,7 +5,10 @@ use mock::*;
 
 use frame_support::{assert_noop, assert_ok, traits::OnInitialize};
 
-use primitives::{CurrencyId, Leverage, Leverages};
+use orml_traits::BasicCurrency;
+use primitives::{CurrencyId, Leverage, Leverages, Price, TraderTier};
+use sp_arithmetic::traits::Bounded;
+use sp_runtime::DispatchError::BadOrigin;
 use traits::{LiquidityPools, MarginProtocolLiquidityPools};
 
 fn swap_rate(pair: TradingPair, is_long: bool) -> FixedI128 {
@@ -47,6 +50,10 @@ fn is_enabled_should_work() {
 			ModuleLiquidityPools::is_pool_trading_pair_leverage_enabled(0, pair, Leverage::ShortTen),
 			true
 		);
+
+		assert_eq!(ModuleLiquidityPools::max_enabled_leverage(0, pair, true), Some(5));
+		assert_eq!(ModuleLiquidityPools::max_enabled_leverage(0, pair, false), Some(10));
+		assert_eq!(ModuleLiquidityPools::max_enabled_leverage(1, pair, true), None);
 	});
 }
 
@@ -434,6 +441,41 @@ fn should_get_accumulated_swap() {
 	});
 }
 
+#[test]
+fn accumulated_swap_rate_unchanged_on_overflow() {
+	new_test_ext().execute_with(|| {
+		let pair = TradingPair {
+			base: CurrencyId::AUSD,
+			quote: CurrencyId::FEUR,
+		};
+		let rate = SwapRate {
+			long: FixedI128::saturating_from_rational(-1, 10), // -10%
+			short: FixedI128::saturating_from_rational(1, 10), // 10%
+		};
+		assert_ok!(ModuleLiquidityPools::set_accumulate_config(
+			Origin::signed(UpdateOrigin::get()),
+			pair,
+			1 * ONE_MINUTE,
+			0
+		));
+		assert_ok!(BaseLiquidityPools::create_pool(Origin::signed(ALICE)));
+		assert_ok!(ModuleLiquidityPools::set_swap_rate(
+			Origin::signed(UpdateOrigin::get()),
+			pair,
+			rate
+		));
+
+		let accumulated = SwapRate {
+			long: FixedI128::min_value(),
+			short: FixedI128::saturating_from_integer(0),
+		};
+		AccumulatedSwapRates::insert(0, pair, accumulated.clone());
+
+		execute_time(1 * ONE_MINUTE);
+		assert_eq!(ModuleLiquidityPools::accumulated_swap_rate(0, pair), accumulated);
+	});
+}
+
 #[test]
 fn ensure_can_open_position() {
 	new_test_ext().execute_with(|| {
@@ -551,6 +593,100 @@ fn should_update_accumulated_rate() {
 	});
 }
 
+#[test]
+fn should_keep_accumulated_swap_rate_history() {
+	new_test_ext().execute_with(|| {
+		let pair = TradingPair {
+			base: CurrencyId::AUSD,
+			quote: CurrencyId::FEUR,
+		};
+		let rate = SwapRate {
+			long: FixedI128::saturating_from_rational(-1, 100), // -1%
+			short: FixedI128::saturating_from_rational(1, 100), // 1%
+		};
+		let snapshot = |mins: u64, acc: i128| AccumulatedSwapRateSnapshot {
+			time: mins * ONE_MINUTE,
+			accumulated: SwapRate {
+				long: FixedI128::saturating_from_rational(-acc, 100),
+				short: FixedI128::saturating_from_rational(acc, 100),
+			},
+		};
+
+		assert_eq!(ModuleLiquidityPools::next_accumulate_time(pair), None);
+		assert_ok!(ModuleLiquidityPools::set_accumulate_config(
+			Origin::signed(UpdateOrigin::get()),
+			pair,
+			2 * ONE_MINUTE,
+			ONE_MINUTE
+		));
+		assert_ok!(BaseLiquidityPools::create_pool(Origin::signed(ALICE)));
+		assert_ok!(ModuleLiquidityPools::set_swap_rate(
+			Origin::signed(UpdateOrigin::get()),
+			pair,
+			rate
+		));
+		assert_eq!(ModuleLiquidityPools::next_accumulate_time(pair), Some(1 * ONE_MINUTE));
+
+		execute_time(1 * ONE_MINUTE);
+		assert_eq!(ModuleLiquidityPools::next_accumulate_time(pair), Some(3 * ONE_MINUTE));
+		assert_eq!(
+			ModuleLiquidityPools::accumulated_swap_rate_history(0, pair),
+			vec![snapshot(1, 1)]
+		);
+
+		execute_time(2 * ONE_MINUTE);
+		assert_eq!(ModuleLiquidityPools::next_accumulate_time(pair), Some(3 * ONE_MINUTE));
+		execute_time(3 * ONE_MINUTE);
+		execute_time(5 * ONE_MINUTE);
+		execute_time(7 * ONE_MINUTE);
+
+		// at most `MaxSwapRateHistory` kept
+		assert_eq!(
+			ModuleLiquidityPools::accumulated_swap_rate_history(0, pair),
+			vec![snapshot(3, 2), snapshot(5, 3), snapshot(7, 4)]
+		);
+
+		assert_ok!(BaseLiquidityPools::remove_pool(Origin::signed(ALICE), 0));
+		assert_eq!(ModuleLiquidityPools::accumulated_swap_rate_history(0, pair), vec![]);
+	});
+}
+
+#[test]
+fn on_initialize_weight_scales_with_pairs_and_pools() {
+	new_test_ext().execute_with(|| {
+		let pair = TradingPair {
+			base: CurrencyId::AUSD,
+			quote: CurrencyId::FEUR,
+		};
+		let on_initialize_at = |sec: u64| {
+			System::set_block_number(sec);
+			Timestamp::set_timestamp(sec * 1000);
+			<ModuleLiquidityPools as OnInitialize<u64>>::on_initialize(sec)
+		};
+
+		assert_eq!(on_initialize_at(1 * ONE_MINUTE), ON_INITIALIZE_BASE_WEIGHT);
+
+		assert_ok!(ModuleLiquidityPools::set_accumulate_config(
+			Origin::signed(UpdateOrigin::get()),
+			pair,
+			2 * ONE_MINUTE,
+			0
+		));
+		// not at accumulate time
+		assert_eq!(
+			on_initialize_at(3 * ONE_MINUTE),
+			ON_INITIALIZE_BASE_WEIGHT + CHECK_ACCUMULATE_WEIGHT_PER_PAIR
+		);
+
+		assert_ok!(BaseLiquidityPools::create_pool(Origin::signed(ALICE)));
+		assert_ok!(BaseLiquidityPools::create_pool(Origin::signed(BOB)));
+		assert_eq!(
+			on_initialize_at(4 * ONE_MINUTE),
+			ON_INITIALIZE_BASE_WEIGHT + CHECK_ACCUMULATE_WEIGHT_PER_PAIR + 2 * ACCUMULATE_WEIGHT_PER_POOL
+		);
+	});
+}
+
 #[test]
 fn should_enable_disable_trading_pairs() {
 	new_test_ext().execute_with(|| {
@@ -572,6 +708,27 @@ fn should_enable_disable_trading_pairs() {
 	})
 }
 
+#[test]
+fn trading_pair_of_halted_currency_is_disabled() {
+	new_test_ext().execute_with(|| {
+		let pair = TradingPair {
+			base: CurrencyId::AUSD,
+			quote: CurrencyId::FEUR,
+		};
+		assert_ok!(ModuleLiquidityPools::enable_trading_pair(
+			Origin::signed(UpdateOrigin::get()),
+			pair
+		));
+
+		MockTradingHalt::set_mock_halted(CurrencyId::FEUR, true);
+		assert!(!ModuleLiquidityPools::is_trading_pair_enabled(pair));
+		assert!(ModuleLiquidityPools::trading_pair_options(pair).enabled);
+
+		MockTradingHalt::set_mock_halted(CurrencyId::FEUR, false);
+		assert!(ModuleLiquidityPools::is_trading_pair_enabled(pair));
+	})
+}
+
 #[test]
 fn liquidity_provider_should_enable_disable_trading_pairs() {
 	new_test_ext().execute_with(|| {
@@ -656,3 +813,613 @@ fn should_set_min_leveraged_amount() {
 		);
 	})
 }
+
+#[test]
+fn should_set_leveraged_amount_limit() {
+	new_test_ext().execute_with(|| {
+		let pair = TradingPair {
+			base: CurrencyId::AUSD,
+			quote: CurrencyId::FEUR,
+		};
+		let ensure_can_open_position = |amount| {
+			<ModuleLiquidityPools as MarginProtocolLiquidityPools<AccountId>>::ensure_can_open_position(
+				0,
+				pair,
+				Leverage::ShortFive,
+				amount,
+			)
+		};
+		assert_ok!(BaseLiquidityPools::create_pool(Origin::signed(ALICE)));
+		assert_ok!(ModuleLiquidityPools::enable_trading_pair(
+			Origin::signed(UpdateOrigin::get()),
+			pair
+		));
+		assert_ok!(ModuleLiquidityPools::liquidity_pool_enable_trading_pair(
+			Origin::signed(ALICE),
+			0,
+			pair
+		));
+		assert_ok!(ModuleLiquidityPools::set_enabled_leverages(
+			Origin::signed(ALICE),
+			0,
+			pair,
+			Leverage::ShortFive.into(),
+		));
+		assert_ok!(ModuleLiquidityPools::set_min_leveraged_amount(
+			Origin::signed(ALICE),
+			0,
+			5
+		));
+
+		let limit = LeveragedAmountLimit {
+			min: Some(10),
+			max: Some(100),
+		};
+		assert_noop!(
+			ModuleLiquidityPools::set_leveraged_amount_limit(Origin::signed(BOB), 0, pair, limit),
+			Error::<Runtime>::NoPermission
+		);
+		assert_noop!(
+			ModuleLiquidityPools::set_leveraged_amount_limit(
+				Origin::signed(ALICE),
+				0,
+				pair,
+				LeveragedAmountLimit {
+					min: Some(101),
+					max: Some(100),
+				}
+			),
+			Error::<Runtime>::InvalidLeveragedAmountLimit
+		);
+
+		assert_ok!(ModuleLiquidityPools::set_leveraged_amount_limit(
+			Origin::signed(ALICE),
+			0,
+			pair,
+			limit
+		));
+		assert_eq!(ModuleLiquidityPools::leveraged_amount_limit(0, pair), limit);
+
+		// the pair's min overrides the pool's
+		assert_eq!(ModuleLiquidityPools::min_leveraged_amount_of_pair(0, pair), 10);
+		assert_noop!(ensure_can_open_position(9), OpenPositionError::BelowMinLeveragedAmount);
+		assert_ok!(ensure_can_open_position(10));
+		assert_ok!(ensure_can_open_position(100));
+		assert_noop!(
+			ensure_can_open_position(101),
+			OpenPositionError::AboveMaxLeveragedAmount
+		);
+
+		// the default min is used if higher
+		assert_ok!(ModuleLiquidityPools::set_default_min_leveraged_amount(
+			Origin::signed(UpdateOrigin::get()),
+			20
+		));
+		assert_eq!(ModuleLiquidityPools::min_leveraged_amount_of_pair(0, pair), 20);
+		assert_noop!(ensure_can_open_position(19), OpenPositionError::BelowMinLeveragedAmount);
+
+		// back to the pool's min, and no max
+		assert_ok!(ModuleLiquidityPools::set_default_min_leveraged_amount(
+			Origin::signed(UpdateOrigin::get()),
+			0
+		));
+		assert_ok!(ModuleLiquidityPools::set_leveraged_amount_limit(
+			Origin::signed(ALICE),
+			0,
+			pair,
+			Default::default()
+		));
+		assert!(!LeveragedAmountLimits::contains_key(0, pair));
+		assert_eq!(ModuleLiquidityPools::min_leveraged_amount_of_pair(0, pair), 5);
+		assert_ok!(ensure_can_open_position(1_000));
+	});
+}
+
+#[test]
+fn should_set_trader_max_leverage() {
+	new_test_ext().execute_with(|| {
+		let pair = TradingPair {
+			base: CurrencyId::AUSD,
+			quote: CurrencyId::FEUR,
+		};
+		let pool_id = 0;
+
+		// pool not created yet
+		assert_noop!(
+			ModuleLiquidityPools::set_trader_max_leverage(Origin::signed(ALICE), pool_id, TraderTier::Retail, Some(10)),
+			Error::<Runtime>::NoPermission
+		);
+
+		assert_ok!(BaseLiquidityPools::create_pool(Origin::signed(ALICE)));
+		assert_ok!(ModuleLiquidityPools::set_enabled_leverages(
+			Origin::signed(ALICE),
+			pool_id,
+			pair,
+			Leverage::LongTen | Leverage::LongTwenty | Leverage::ShortFive,
+		));
+		assert_eq!(
+			ModuleLiquidityPools::max_enabled_leverage_of_tier(pool_id, pair, true, TraderTier::Retail),
+			Some(20)
+		);
+
+		assert_ok!(ModuleLiquidityPools::set_trader_max_leverage(
+			Origin::signed(ALICE),
+			pool_id,
+			TraderTier::Retail,
+			Some(10)
+		));
+		assert_eq!(
+			ModuleLiquidityPools::trader_max_leverage(pool_id, TraderTier::Retail),
+			Some(10)
+		);
+		assert_eq!(
+			ModuleLiquidityPools::trader_max_leverage(pool_id, TraderTier::Professional),
+			None
+		);
+		assert_eq!(
+			ModuleLiquidityPools::max_enabled_leverage_of_tier(pool_id, pair, true, TraderTier::Retail),
+			Some(10)
+		);
+		assert_eq!(
+			ModuleLiquidityPools::max_enabled_leverage_of_tier(pool_id, pair, false, TraderTier::Retail),
+			Some(5)
+		);
+		assert_eq!(
+			ModuleLiquidityPools::max_enabled_leverage_of_tier(pool_id, pair, true, TraderTier::Professional),
+			Some(20)
+		);
+
+		// non pool owners cannot set trader max leverage
+		assert_noop!(
+			ModuleLiquidityPools::set_trader_max_leverage(Origin::signed(BOB), pool_id, TraderTier::Retail, None),
+			Error::<Runtime>::NoPermission
+		);
+
+		assert_ok!(ModuleLiquidityPools::set_trader_max_leverage(
+			Origin::signed(ALICE),
+			pool_id,
+			TraderTier::Retail,
+			None
+		));
+		assert_eq!(
+			ModuleLiquidityPools::trader_max_leverage(pool_id, TraderTier::Retail),
+			None
+		);
+
+		// removed with the pool
+		assert_ok!(ModuleLiquidityPools::set_trader_max_leverage(
+			Origin::signed(ALICE),
+			pool_id,
+			TraderTier::Professional,
+			Some(20)
+		));
+		assert_ok!(BaseLiquidityPools::remove_pool(Origin::signed(ALICE), pool_id));
+		assert_eq!(
+			ModuleLiquidityPools::trader_max_leverage(pool_id, TraderTier::Professional),
+			None
+		);
+	})
+}
+
+#[test]
+fn should_set_commission() {
+	new_test_ext().execute_with(|| {
+		let pair = TradingPair {
+			base: CurrencyId::AUSD,
+			quote: CurrencyId::FEUR,
+		};
+		let pool_id = 0;
+		let commission = Permill::from_rational_approximation(3, 1000u32);
+
+		// pool not created yet
+		assert_noop!(
+			ModuleLiquidityPools::set_commission(Origin::signed(ALICE), pool_id, pair, commission),
+			Error::<Runtime>::NoPermission
+		);
+
+		assert_ok!(BaseLiquidityPools::create_pool(Origin::signed(ALICE)));
+		assert_eq!(ModuleLiquidityPools::commission(pool_id, pair), Permill::zero());
+		assert_ok!(ModuleLiquidityPools::set_commission(
+			Origin::signed(ALICE),
+			pool_id,
+			pair,
+			commission
+		));
+		assert_eq!(ModuleLiquidityPools::commission(pool_id, pair), commission);
+		assert_eq!(
+			<ModuleLiquidityPools as MarginProtocolLiquidityPools<AccountId>>::commission(pool_id, pair),
+			commission
+		);
+
+		// non pool owners cannot set commission
+		assert_noop!(
+			ModuleLiquidityPools::set_commission(Origin::signed(BOB), pool_id, pair, Permill::zero()),
+			Error::<Runtime>::NoPermission
+		);
+
+		// capped by max commission
+		let max_commission = Permill::from_rational_approximation(1, 1000u32);
+		assert_noop!(
+			ModuleLiquidityPools::set_max_commission(Origin::signed(ALICE), pair, Some(max_commission)),
+			BadOrigin
+		);
+		assert_ok!(ModuleLiquidityPools::set_max_commission(
+			Origin::signed(UpdateOrigin::get()),
+			pair,
+			Some(max_commission)
+		));
+		assert_eq!(ModuleLiquidityPools::commission(pool_id, pair), max_commission);
+		assert_ok!(ModuleLiquidityPools::set_max_commission(
+			Origin::signed(UpdateOrigin::get()),
+			pair,
+			None
+		));
+		assert_eq!(ModuleLiquidityPools::commission(pool_id, pair), commission);
+
+		// removed with the pool
+		assert_ok!(BaseLiquidityPools::remove_pool(Origin::signed(ALICE), pool_id));
+		assert_eq!(ModuleLiquidityPools::commission(pool_id, pair), Permill::zero());
+	})
+}
+
+fn margin_liquidity_genesis(frequency: u64, swap_rate: SwapRate) -> Result<sp_runtime::Storage, String> {
+	let mut t = frame_system::GenesisConfig::default().build_storage::<Runtime>()?;
+	let pair = TradingPair {
+		base: CurrencyId::FEUR,
+		quote: CurrencyId::AUSD,
+	};
+	GenesisConfig::<Runtime> {
+		default_min_leveraged_amount: 10,
+		margin_liquidity_config: vec![(pair, 5, AccumulateConfig { frequency, offset: 0 }, swap_rate)],
+	}
+	.assimilate_storage(&mut t)?;
+	Ok(t)
+}
+
+#[test]
+fn genesis_config_works() {
+	let swap_rate = SwapRate {
+		long: FixedI128::saturating_from_rational(-1, 100),
+		short: FixedI128::saturating_from_rational(1, 100),
+	};
+	let t = margin_liquidity_genesis(ONE_MINUTE, swap_rate.clone()).unwrap();
+
+	sp_io::TestExternalities::new(t).execute_with(|| {
+		let pair = TradingPair {
+			base: CurrencyId::FEUR,
+			quote: CurrencyId::AUSD,
+		};
+		assert_eq!(ModuleLiquidityPools::default_min_leveraged_amount(), 10);
+		assert_eq!(
+			ModuleLiquidityPools::trading_pair_options(pair),
+			MarginTradingPairOption {
+				enabled: true,
+				max_spread: Some(5),
+				swap_rate,
+				accumulate_config: Some(AccumulateConfig {
+					frequency: ONE_MINUTE,
+					offset: 0
+				}),
+			}
+		);
+	});
+}
+
+#[test]
+#[should_panic(expected = "accumulate frequency too low")]
+fn genesis_config_fails_if_frequency_too_low() {
+	let _ = margin_liquidity_genesis(ONE_MINUTE - 1, SwapRate::default());
+}
+
+#[test]
+#[should_panic(expected = "swap rate too high")]
+fn genesis_config_fails_if_swap_rate_too_high() {
+	let swap_rate = SwapRate {
+		long: FixedI128::saturating_from_integer(-3),
+		short: FixedI128::saturating_from_integer(1),
+	};
+	let _ = margin_liquidity_genesis(ONE_MINUTE, swap_rate);
+}
+
+fn setup_backstop(pair: TradingPair) {
+	assert_ok!(BaseLiquidityPools::create_pool(Origin::signed(ALICE)));
+	assert_ok!(BaseLiquidityPools::create_pool(Origin::signed(TreasuryAccount::get())));
+	assert_ok!(ModuleLiquidityPools::enable_trading_pair(
+		Origin::signed(UpdateOrigin::get()),
+		pair
+	));
+	assert_ok!(ModuleLiquidityPools::set_backstop_pool(
+		Origin::signed(UpdateOrigin::get()),
+		Some(1)
+	));
+	assert_ok!(ModuleLiquidityPools::set_backstop_trading_pair(
+		Origin::signed(UpdateOrigin::get()),
+		pair,
+		10,
+		20,
+		Leverage::LongTwo.into(),
+	));
+}
+
+fn ensure_can_open_backstop_position(pair: TradingPair) -> result::Result<(), OpenPositionError> {
+	<ModuleLiquidityPools as MarginProtocolLiquidityPools<AccountId>>::ensure_can_open_position(
+		1,
+		pair,
+		Leverage::LongTwo,
+		0,
+	)
+}
+
+#[test]
+fn set_backstop_pool_should_work() {
+	new_test_ext().execute_with(|| {
+		let pair = TradingPair {
+			base: CurrencyId::AUSD,
+			quote: CurrencyId::FEUR,
+		};
+		assert_ok!(BaseLiquidityPools::create_pool(Origin::signed(ALICE)));
+		assert_ok!(BaseLiquidityPools::create_pool(Origin::signed(TreasuryAccount::get())));
+		assert_ok!(ModuleLiquidityPools::enable_trading_pair(
+			Origin::signed(UpdateOrigin::get()),
+			pair
+		));
+
+		assert_noop!(
+			ModuleLiquidityPools::set_backstop_trading_pair(
+				Origin::signed(UpdateOrigin::get()),
+				pair,
+				10,
+				20,
+				Leverage::LongTwo.into(),
+			),
+			Error::<Runtime>::NoBackstopPool
+		);
+		assert_noop!(
+			ModuleLiquidityPools::set_backstop_pool(Origin::signed(ALICE), Some(1)),
+			BadOrigin
+		);
+		assert_noop!(
+			ModuleLiquidityPools::set_backstop_pool(Origin::signed(UpdateOrigin::get()), Some(2)),
+			Error::<Runtime>::PoolNotFound
+		);
+		assert_noop!(
+			ModuleLiquidityPools::set_backstop_pool(Origin::signed(UpdateOrigin::get()), Some(0)),
+			Error::<Runtime>::NotTreasuryPool
+		);
+
+		assert_ok!(ModuleLiquidityPools::set_backstop_pool(
+			Origin::signed(UpdateOrigin::get()),
+			Some(1)
+		));
+		assert_eq!(ModuleLiquidityPools::backstop_pool(), Some(1));
+
+		assert_ok!(ModuleLiquidityPools::set_backstop_trading_pair(
+			Origin::signed(UpdateOrigin::get()),
+			pair,
+			10,
+			20,
+			Leverage::LongTwo.into(),
+		));
+		assert_eq!(
+			ModuleLiquidityPools::pool_trading_pair_options(1, pair),
+			MarginPoolTradingPairOption {
+				enabled: true,
+				bid_spread: Some(10),
+				ask_spread: Some(20),
+				enabled_trades: Leverage::LongTwo.into(),
+			}
+		);
+
+		// unset backstop pool disables trading pairs, but keeps spreads for closing positions
+		assert_ok!(ModuleLiquidityPools::set_backstop_pool(
+			Origin::signed(UpdateOrigin::get()),
+			None
+		));
+		assert_eq!(ModuleLiquidityPools::backstop_pool(), None);
+		assert!(!ModuleLiquidityPools::is_pool_trading_pair_enabled(1, pair));
+		assert_eq!(
+			<ModuleLiquidityPools as MarginProtocolLiquidityPools<AccountId>>::bid_spread(1, pair),
+			Some(10)
+		);
+	});
+}
+
+#[test]
+fn backstop_should_only_take_positions_without_available_pools() {
+	new_test_ext().execute_with(|| {
+		let pair = TradingPair {
+			base: CurrencyId::AUSD,
+			quote: CurrencyId::FEUR,
+		};
+		setup_backstop(pair);
+		assert!(ModuleLiquidityPools::is_backstop_active(pair));
+		assert_ok!(ensure_can_open_backstop_position(pair));
+
+		assert_ok!(ModuleLiquidityPools::liquidity_pool_enable_trading_pair(
+			Origin::signed(ALICE),
+			0,
+			pair
+		));
+		assert!(!ModuleLiquidityPools::is_backstop_active(pair));
+		assert_noop!(
+			ensure_can_open_backstop_position(pair),
+			OpenPositionError::BackstopNotActive
+		);
+
+		// unsafe pools are not available
+		DummyPoolManager::set_pool_unsafe(0, true);
+		assert_ok!(ensure_can_open_backstop_position(pair));
+		DummyPoolManager::set_pool_unsafe(0, false);
+		assert!(!ModuleLiquidityPools::is_backstop_active(pair));
+
+		// pools in wind-down are not available
+		assert_ok!(BaseLiquidityPools::disable_pool(Origin::signed(ALICE), 0));
+		assert_ok!(ensure_can_open_backstop_position(pair));
+
+		// other checks still apply
+		assert_noop!(
+			<ModuleLiquidityPools as MarginProtocolLiquidityPools<AccountId>>::ensure_can_open_position(
+				1,
+				pair,
+				Leverage::LongFive,
+				0,
+			),
+			OpenPositionError::LeverageNotAllowedInPool
+		);
+	});
+}
+
+#[test]
+fn backstop_should_respect_risk_threshold() {
+	new_test_ext().execute_with(|| {
+		let pair = TradingPair {
+			base: CurrencyId::AUSD,
+			quote: CurrencyId::FEUR,
+		};
+		setup_backstop(pair);
+
+		let threshold = BackstopRiskThreshold {
+			enp: Permill::from_percent(50),
+			ell: Permill::from_percent(20),
+		};
+		assert_noop!(
+			ModuleLiquidityPools::set_backstop_risk_threshold(Origin::signed(ALICE), threshold),
+			BadOrigin
+		);
+		assert_ok!(ModuleLiquidityPools::set_backstop_risk_threshold(
+			Origin::signed(UpdateOrigin::get()),
+			threshold
+		));
+		assert_eq!(ModuleLiquidityPools::backstop_risk_threshold(), threshold);
+
+		DummyPoolManager::set_enp_and_ell(FixedI128::saturating_from_rational(51, 100), FixedI128::max_value());
+		assert_ok!(ensure_can_open_backstop_position(pair));
+
+		DummyPoolManager::set_enp_and_ell(FixedI128::saturating_from_rational(50, 100), FixedI128::max_value());
+		assert_noop!(
+			ensure_can_open_backstop_position(pair),
+			OpenPositionError::BackstopNotActive
+		);
+
+		DummyPoolManager::set_enp_and_ell(FixedI128::max_value(), FixedI128::saturating_from_rational(20, 100));
+		assert_noop!(
+			ensure_can_open_backstop_position(pair),
+			OpenPositionError::BackstopNotActive
+		);
+	});
+}
+
+#[test]
+fn backstop_liquidity_should_work() {
+	new_test_ext().execute_with(|| {
+		let pair = TradingPair {
+			base: CurrencyId::AUSD,
+			quote: CurrencyId::FEUR,
+		};
+		assert_noop!(
+			ModuleLiquidityPools::deposit_backstop_liquidity(Origin::signed(UpdateOrigin::get()), 1000),
+			Error::<Runtime>::NoBackstopPool
+		);
+
+		setup_backstop(pair);
+		let treasury = TreasuryAccount::get();
+
+		assert_noop!(
+			ModuleLiquidityPools::deposit_backstop_liquidity(Origin::signed(ALICE), 1000),
+			BadOrigin
+		);
+		assert_ok!(ModuleLiquidityPools::deposit_backstop_liquidity(
+			Origin::signed(UpdateOrigin::get()),
+			1000
+		));
+		assert_eq!(BaseLiquidityPools::liquidity(1), 1000);
+		assert_eq!(LiquidityCurrency::free_balance(&treasury), 99_000);
+
+		assert_noop!(
+			ModuleLiquidityPools::withdraw_backstop_liquidity(Origin::signed(ALICE), 400),
+			BadOrigin
+		);
+		assert_ok!(ModuleLiquidityPools::withdraw_backstop_liquidity(
+			Origin::signed(UpdateOrigin::get()),
+			400
+		));
+		assert_eq!(BaseLiquidityPools::liquidity(1), 600);
+		assert_eq!(LiquidityCurrency::free_balance(&treasury), 99_400);
+
+		// removing the pool unsets backstop pool
+		assert_ok!(BaseLiquidityPools::remove_pool(Origin::signed(treasury), 1));
+		assert_eq!(ModuleLiquidityPools::backstop_pool(), None);
+	});
+}
+
+#[test]
+fn should_set_trading_pair_metadata() {
+	new_test_ext().execute_with(|| {
+		let eur_usd = TradingPair {
+			base: CurrencyId::FEUR,
+			quote: CurrencyId::AUSD,
+		};
+		let jpy_usd = TradingPair {
+			base: CurrencyId::FJPY,
+			quote: CurrencyId::AUSD,
+		};
+		let metadata = TradingPairMetadata {
+			pip_size: Price::saturating_from_rational(1, 10_000),
+			quote_precision: 5,
+			min_increment: Price::from_inner(20),
+			display_order: 2,
+		};
+
+		assert_noop!(
+			ModuleLiquidityPools::set_trading_pair_metadata(Origin::signed(ALICE), eur_usd, Some(metadata)),
+			BadOrigin
+		);
+		assert_noop!(
+			ModuleLiquidityPools::set_trading_pair_metadata(
+				Origin::signed(UpdateOrigin::get()),
+				eur_usd,
+				Some(TradingPairMetadata {
+					min_increment: Price::zero(),
+					..metadata
+				})
+			),
+			Error::<Runtime>::InvalidTradingPairMetadata
+		);
+
+		assert_ok!(ModuleLiquidityPools::set_trading_pair_metadata(
+			Origin::signed(UpdateOrigin::get()),
+			eur_usd,
+			Some(metadata)
+		));
+		let jpy_metadata = TradingPairMetadata {
+			display_order: 1,
+			..metadata
+		};
+		assert_ok!(ModuleLiquidityPools::set_trading_pair_metadata(
+			Origin::signed(UpdateOrigin::get()),
+			jpy_usd,
+			Some(jpy_metadata)
+		));
+		assert_eq!(ModuleLiquidityPools::trading_pair_metadata(eur_usd), Some(metadata));
+		assert_eq!(
+			ModuleLiquidityPools::trading_pair_metadatas(),
+			vec![(jpy_usd, jpy_metadata), (eur_usd, metadata)]
+		);
+
+		// spreads must be multiples of the minimum increment
+		assert_ok!(BaseLiquidityPools::create_pool(Origin::signed(ALICE)));
+		assert_noop!(
+			ModuleLiquidityPools::set_spread(Origin::signed(ALICE), 0, eur_usd, 80, 50),
+			Error::<Runtime>::SpreadNotMultipleOfIncrement
+		);
+		assert_ok!(ModuleLiquidityPools::set_spread(Origin::signed(ALICE), 0, eur_usd, 80, 60));
+
+		assert_ok!(ModuleLiquidityPools::set_trading_pair_metadata(
+			Origin::signed(UpdateOrigin::get()),
+			eur_usd,
+			None
+		));
+		assert_eq!(ModuleLiquidityPools::trading_pair_metadata(eur_usd), None);
+		assert_ok!(ModuleLiquidityPools::set_spread(Origin::signed(ALICE), 0, eur_usd, 80, 50));
+	});
+}
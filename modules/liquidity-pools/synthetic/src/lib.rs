@@ -10,7 +10,10 @@ use orml_utilities::with_transaction_result;
 use primitives::{Balance, CurrencyId, LiquidityPoolId};
 use sp_runtime::{DispatchResult, ModuleId, Permill, RuntimeDebug};
 use sp_std::prelude::*;
-use traits::{LiquidityPools, OnDisableLiquidityPool, OnRemoveLiquidityPool, SyntheticProtocolLiquidityPools};
+use traits::{
+	AuditLog, LiquidityPools, OnDisableLiquidityPool, OnRemoveLiquidityPool, SyntheticProtocolLiquidityPools,
+	TradingHalt,
+};
 
 /// Currency option in a pool of synthetic.
 #[derive(Encode, Decode, RuntimeDebug, Eq, PartialEq, Default)]
@@ -48,6 +51,12 @@ pub trait Trait: frame_system::Trait {
 
 	/// Required origin for updating protocol options.
 	type UpdateOrigin: EnsureOrigin<Self::Origin>;
+
+	/// Audit log of privileged parameter changes.
+	type AuditLog: AuditLog<Self::Origin>;
+
+	/// Trading halts on unhealthy oracle feeds. Minting of halted currencies is paused.
+	type TradingHalt: TradingHalt;
 }
 
 decl_storage! {
@@ -61,6 +70,16 @@ decl_storage! {
 		/// Maximum spread of a currency.
 		pub MaxSpread get(fn max_spread): map hasher(twox_64_concat) CurrencyId => Option<Balance>;
 	}
+
+	add_extra_genesis {
+		config(max_spreads): Vec<(CurrencyId, Balance)>;
+
+		build(|config: &GenesisConfig| {
+			config.max_spreads.iter().for_each(|(currency_id, max_spread)| {
+				MaxSpread::insert(currency_id, max_spread);
+			})
+		})
+	}
 }
 
 decl_event!(
@@ -133,8 +152,17 @@ decl_module! {
 		#[weight = 10_000]
 		pub fn set_min_additional_collateral_ratio(origin, #[compact] ratio: Permill) {
 			with_transaction_result(|| {
-				T::UpdateOrigin::ensure_origin(origin)?;
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Self::min_additional_collateral_ratio();
 				MinAdditionalCollateralRatio::put(ratio);
+				T::AuditLog::record(
+					origin,
+					b"SyntheticLiquidityPools",
+					b"MinAdditionalCollateralRatio",
+					vec![],
+					old.encode(),
+					ratio.encode(),
+				);
 				Self::deposit_event(RawEvent::MinAdditionalCollateralRatioSet(ratio));
 				Ok(())
 			})?;
@@ -164,8 +192,17 @@ decl_module! {
 		#[weight = 10_000]
 		pub fn set_max_spread(origin, currency_id: CurrencyId, #[compact] max_spread: Balance) {
 			with_transaction_result(|| {
-				T::UpdateOrigin::ensure_origin(origin)?;
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Self::max_spread(currency_id);
 				MaxSpread::insert(currency_id, max_spread);
+				T::AuditLog::record(
+					origin,
+					b"SyntheticLiquidityPools",
+					b"MaxSpread",
+					currency_id.encode(),
+					old.encode(),
+					Some(max_spread).encode(),
+				);
 				Self::deposit_event(RawEvent::MaxSpreadUpdated(currency_id, max_spread));
 				Ok(())
 			})?;
@@ -232,6 +269,7 @@ impl<T: Trait> SyntheticProtocolLiquidityPools<T::AccountId> for Module<T> {
 
 	fn can_mint(pool_id: LiquidityPoolId, currency_id: CurrencyId) -> bool {
 		Self::pool_currency_options(pool_id, currency_id).synthetic_enabled
+			&& !T::TradingHalt::is_trading_halted(currency_id)
 	}
 }
 
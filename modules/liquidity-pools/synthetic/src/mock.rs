@@ -2,18 +2,13 @@
 
 use super::*;
 
-use frame_support::{impl_outer_origin, ord_parameter_types, parameter_types, weights::Weight};
+use frame_support::{impl_outer_origin, ord_parameter_types, parameter_types};
 use frame_system::EnsureSignedBy;
-use sp_core::H256;
-use sp_runtime::{
-	testing::Header,
-	traits::{BlakeTwo256, IdentityLookup},
-	Perbill,
-};
 
 use orml_currencies::Currency;
 use primitives::{Balance, CurrencyId, LiquidityPoolId};
-use traits::BaseLiquidityPoolManager;
+
+use laminar_test_support::{MockPoolManager, MockTradingHalt};
 
 pub type BlockNumber = u64;
 pub type AccountId = u64;
@@ -31,39 +26,7 @@ impl_outer_origin! {
 // configuration traits of modules we want to use.
 #[derive(Clone, Eq, PartialEq)]
 pub struct Runtime;
-parameter_types! {
-	pub const BlockHashCount: u64 = 250;
-	pub const MaximumBlockWeight: Weight = 1024;
-	pub const MaximumBlockLength: u32 = 2 * 1024;
-	pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
-}
-
-impl system::Trait for Runtime {
-	type Origin = Origin;
-	type Call = ();
-	type Index = u64;
-	type BlockNumber = BlockNumber;
-	type Hash = H256;
-	type Hashing = BlakeTwo256;
-	type AccountId = AccountId;
-	type Lookup = IdentityLookup<Self::AccountId>;
-	type Header = Header;
-	type Event = ();
-	type BlockHashCount = BlockHashCount;
-	type MaximumExtrinsicWeight = MaximumBlockWeight;
-	type MaximumBlockWeight = MaximumBlockWeight;
-	type DbWeight = ();
-	type BlockExecutionWeight = ();
-	type ExtrinsicBaseWeight = ();
-	type MaximumBlockLength = MaximumBlockLength;
-	type AvailableBlockRatio = AvailableBlockRatio;
-	type Version = ();
-	type ModuleToIndex = ();
-	type AccountData = pallet_balances::AccountData<Balance>;
-	type OnNewAccount = ();
-	type OnKilledAccount = ();
-	type BaseCallFilter = ();
-}
+laminar_test_support::impl_mock_system!(Runtime, AccountId, (), pallet_balances::AccountData<Balance>);
 
 parameter_types! {
 	pub const ExistentialDeposit: u128 = 50;
@@ -98,17 +61,6 @@ impl orml_tokens::Trait for Runtime {
 	type OnReceived = ();
 }
 
-pub struct PoolManager;
-
-impl BaseLiquidityPoolManager<LiquidityPoolId, Balance> for PoolManager {
-	fn can_remove(_pool_id: LiquidityPoolId) -> bool {
-		true
-	}
-	fn ensure_can_withdraw(_pool: LiquidityPoolId, _amount: Balance) -> DispatchResult {
-		unimplemented!()
-	}
-}
-
 parameter_types! {
 	pub const SyntheticLiquidityPoolsModuleId: ModuleId = MODULE_ID;
 	pub const IdentityDeposit: Balance = 1000;
@@ -119,13 +71,14 @@ pub type SyntheticInstance = module_base_liquidity_pools::Instance1;
 impl module_base_liquidity_pools::Trait<SyntheticInstance> for Runtime {
 	type Event = ();
 	type LiquidityCurrency = LiquidityCurrency;
-	type PoolManager = PoolManager;
+	type PoolManager = MockPoolManager;
 	type ExistentialDeposit = ExistentialDeposit;
 	type IdentityDeposit = IdentityDeposit;
 	type IdentityDepositCurrency = pallet_balances::Module<Self>;
 	type ModuleId = SyntheticLiquidityPoolsModuleId;
 	type OnDisableLiquidityPool = ModuleLiquidityPools;
 	type OnRemoveLiquidityPool = ModuleLiquidityPools;
+	type OnLiquidityUpdate = ();
 	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
 }
 pub type BaseLiquidityPools = module_base_liquidity_pools::Module<Runtime, SyntheticInstance>;
@@ -134,6 +87,8 @@ impl Trait for Runtime {
 	type Event = ();
 	type BaseLiquidityPools = module_base_liquidity_pools::Module<Runtime, SyntheticInstance>;
 	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
+	type AuditLog = ();
+	type TradingHalt = MockTradingHalt;
 }
 pub type ModuleLiquidityPools = Module<Runtime>;
 
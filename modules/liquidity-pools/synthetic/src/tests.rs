@@ -317,3 +317,51 @@ fn should_set_synthetic_enabled() {
 		);
 	});
 }
+
+#[test]
+fn can_not_mint_halted_currency() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(BaseLiquidityPools::create_pool(Origin::signed(ALICE)));
+		assert_ok!(ModuleLiquidityPools::set_synthetic_enabled(
+			Origin::signed(ALICE),
+			0,
+			CurrencyId::FEUR,
+			true,
+		));
+
+		MockTradingHalt::set_mock_halted(CurrencyId::FEUR, true);
+		assert_eq!(
+			<ModuleLiquidityPools as SyntheticProtocolLiquidityPools<AccountId>>::can_mint(0, CurrencyId::FEUR),
+			false
+		);
+
+		MockTradingHalt::set_mock_halted(CurrencyId::FEUR, false);
+		assert_eq!(
+			<ModuleLiquidityPools as SyntheticProtocolLiquidityPools<AccountId>>::can_mint(0, CurrencyId::FEUR),
+			true
+		);
+	});
+}
+
+#[test]
+fn genesis_config_works() {
+	let mut t = frame_system::GenesisConfig::default()
+		.build_storage::<Runtime>()
+		.unwrap();
+	GenesisConfig {
+		min_additional_collateral_ratio: Permill::from_percent(10),
+		max_spreads: vec![(CurrencyId::FEUR, 10), (CurrencyId::FJPY, 20)],
+	}
+	.assimilate_storage(&mut t)
+	.unwrap();
+
+	sp_io::TestExternalities::new(t).execute_with(|| {
+		assert_eq!(
+			ModuleLiquidityPools::min_additional_collateral_ratio(),
+			Permill::from_percent(10)
+		);
+		assert_eq!(ModuleLiquidityPools::max_spread(CurrencyId::FEUR), Some(10));
+		assert_eq!(ModuleLiquidityPools::max_spread(CurrencyId::FJPY), Some(20));
+		assert_eq!(ModuleLiquidityPools::max_spread(CurrencyId::FBTC), None);
+	});
+}
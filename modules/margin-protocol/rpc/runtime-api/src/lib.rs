@@ -3,9 +3,10 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 use codec::{Codec, Decode, Encode};
-use module_primitives::LiquidityPoolId;
-use sp_arithmetic::FixedI128;
+use module_primitives::{Balance, CurrencyId, Leverage, LiquidityPoolId, Price, TradingPair, TradingPairMetadata};
+use sp_arithmetic::{FixedI128, FixedU128, Permill};
 use sp_core::RuntimeDebug;
+use sp_runtime::{traits::NumberFor, DispatchError};
 use sp_std::prelude::*;
 
 #[cfg(feature = "std")]
@@ -14,12 +15,37 @@ use serde::{Deserialize, Serialize};
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 #[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
 pub struct MarginTraderState {
+	/// Balance and collateral value, plus unrealized P&L and accumulated swap of cross margin positions, in USD.
 	pub equity: FixedI128,
 	pub margin_held: FixedI128,
+	/// Equity over leveraged debits of cross margin positions, max if there is none.
 	pub margin_level: FixedI128,
+	/// Equity minus margin held by cross margin positions.
 	pub free_margin: FixedI128,
 	pub unrealized_pl: FixedI128,
 }
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(MarginTraderState {
+	equity,
+	margin_held,
+	margin_level,
+	free_margin,
+	unrealized_pl
+});
+
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Eq, PartialEq, RuntimeDebug)]
+pub struct MarginCollateral {
+	pub currency_id: CurrencyId,
+	pub amount: Balance,
+	pub value: FixedI128,
+}
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(MarginCollateral {
+	currency_id,
+	amount,
+	value
+});
 
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 #[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
@@ -27,13 +53,186 @@ pub struct MarginPoolState {
 	pub enp: FixedI128,
 	pub ell: FixedI128,
 	pub required_deposit: FixedI128,
+	pub equity: FixedI128,
+	/// If both ENP and ELL are above the margin call thresholds, and the stop out thresholds.
+	pub above_margin_call: bool,
+	pub above_stop_out: bool,
+}
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(MarginPoolState {
+	enp,
+	ell,
+	required_deposit,
+	equity,
+	above_margin_call,
+	above_stop_out
+});
+
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
+pub struct MarginPositionSimulation {
+	pub open_price: FixedU128,
+	pub margin_held: FixedI128,
+	pub margin_level: FixedI128,
+	pub enp: FixedI128,
+	pub ell: FixedI128,
+}
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(MarginPositionSimulation {
+	open_price,
+	margin_held,
+	margin_level,
+	enp,
+	ell
+});
+
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
+pub struct MarginEnpEllSnapshot<BlockNumber> {
+	pub block_number: BlockNumber,
+	pub enp: FixedI128,
+	pub ell: FixedI128,
+}
+
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
+pub struct MarginAccumulatedSwapRate {
+	/// Accumulate time in seconds.
+	pub time: u64,
+	pub long: FixedI128,
+	pub short: FixedI128,
 }
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(MarginAccumulatedSwapRate { time, long, short });
+
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
+pub struct MarginSwapRates {
+	/// Swap rates added on each accumulation.
+	pub long: FixedI128,
+	pub short: FixedI128,
+	pub accumulated_long: FixedI128,
+	pub accumulated_short: FixedI128,
+	/// Recent accumulated swap rates, oldest first.
+	pub history: Vec<MarginAccumulatedSwapRate>,
+	/// Next accumulate time in seconds, or `None` if not accumulated.
+	pub next_accumulate_time: Option<u64>,
+}
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(MarginSwapRates {
+	long,
+	short,
+	accumulated_long,
+	accumulated_short,
+	history,
+	next_accumulate_time
+});
+
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
+pub struct MarginRiskThreshold {
+	pub margin_call: Permill,
+	pub stop_out: Permill,
+	pub warning: Permill,
+}
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(MarginRiskThreshold {
+	margin_call,
+	stop_out,
+	warning
+});
+
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
+pub struct MarginRiskParams {
+	pub trader_threshold: MarginRiskThreshold,
+	pub enp_threshold: MarginRiskThreshold,
+	pub ell_threshold: MarginRiskThreshold,
+	/// Highest enabled leverage values allowed for the trader's tier, or `None` if none enabled.
+	pub max_long_leverage: Option<u8>,
+	pub max_short_leverage: Option<u8>,
+	pub min_leveraged_amount: Balance,
+	/// Spreads with the trader's fee discount applied.
+	pub bid_spread: Option<Balance>,
+	pub ask_spread: Option<Balance>,
+	/// Number of positions could still be opened by the trader in the pool, and of the trading pair in the pool.
+	pub trader_positions_headroom: u32,
+	pub pool_positions_headroom: u32,
+}
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(MarginRiskParams {
+	trader_threshold,
+	enp_threshold,
+	ell_threshold,
+	max_long_leverage,
+	max_short_leverage,
+	min_leveraged_amount,
+	bid_spread,
+	ask_spread,
+	trader_positions_headroom,
+	pool_positions_headroom
+});
+
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
+pub struct MarginOpenInterest {
+	/// Total leveraged held amounts of long and short positions, both positive.
+	pub long: FixedI128,
+	pub short: FixedI128,
+	pub long_positions_count: u64,
+	pub short_positions_count: u64,
+}
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(MarginOpenInterest {
+	long,
+	short,
+	long_positions_count,
+	short_positions_count
+});
+
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
+pub struct MarginTradingVolume {
+	/// Start block number of the current period.
+	pub period_start: u64,
+	/// Notional volumes in the quote currency of the pair, of the current and the previous period.
+	pub opened: Balance,
+	pub closed: Balance,
+	pub previous_opened: Balance,
+	pub previous_closed: Balance,
+}
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(MarginTradingVolume {
+	period_start,
+	opened,
+	closed,
+	previous_opened,
+	previous_closed
+});
 
 sp_api::decl_runtime_apis! {
 	pub trait MarginProtocolApi<AccountId> where
 		AccountId: Codec,
 	{
 		fn trader_state(who: AccountId, pool_id: LiquidityPoolId) -> MarginTraderState;
+		fn trader_collaterals(who: AccountId, pool_id: LiquidityPoolId) -> Vec<MarginCollateral>;
 		fn pool_state(pool_id: LiquidityPoolId) -> Option<MarginPoolState>;
+		fn simulate_open_position(
+			who: AccountId,
+			pool_id: LiquidityPoolId,
+			pair: TradingPair,
+			leverage: Leverage,
+			leveraged_amount: Balance,
+			price: Price,
+		) -> Result<MarginPositionSimulation, DispatchError>;
+		fn check_stop_out(who: AccountId, pool_id: LiquidityPoolId) -> bool;
+		fn pool_enp_ell_snapshots(pool_id: LiquidityPoolId) -> Vec<MarginEnpEllSnapshot<NumberFor<Block>>>;
+		fn trading_pair_metadatas() -> Vec<(TradingPair, TradingPairMetadata)>;
+		fn swap_rates(pool_id: LiquidityPoolId, pair: TradingPair) -> MarginSwapRates;
+		fn risk_params(who: AccountId, pool_id: LiquidityPoolId, pair: TradingPair) -> MarginRiskParams;
+		fn open_interest(pair: TradingPair) -> MarginOpenInterest;
+		fn trading_volume(pool_id: LiquidityPoolId, pair: TradingPair) -> MarginTradingVolume;
+		/// Estimated oracle price at which the margin level of the owner of `position_id` reaches stop out.
+		fn estimated_liquidation_price(position_id: u64) -> Option<Price>;
 	}
 }
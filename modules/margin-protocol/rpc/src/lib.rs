@@ -2,9 +2,14 @@ use codec::Codec;
 use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
 use jsonrpc_derive::rpc;
 pub use margin_protocol_rpc_runtime_api::{
-	MarginPoolState, MarginProtocolApi as MarginProtocolRuntimeApi, MarginTraderState,
+	MarginAccumulatedSwapRate, MarginCollateral, MarginOpenInterest, MarginPoolState, MarginPositionSimulation,
+	MarginProtocolApi as MarginProtocolRuntimeApi, MarginRiskParams, MarginRiskThreshold, MarginSwapRates,
+	MarginTraderState, MarginTradingVolume,
+};
+use module_primitives::{
+	decimal::{ToDecimal, Value},
+	Balance, Leverage, LiquidityPoolId, Price, TradingPair,
 };
-use module_primitives::LiquidityPoolId;
 use sp_api::ProvideRuntimeApi;
 use sp_blockchain::HeaderBackend;
 use sp_runtime::{generic::BlockId, traits::Block as BlockT};
@@ -20,8 +25,111 @@ pub trait MarginProtocolApi<BlockHash, AccountId> {
 		at: Option<BlockHash>,
 	) -> Result<MarginTraderState>;
 
+	#[rpc(name = "margin_traderCollaterals")]
+	fn trader_collaterals(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		at: Option<BlockHash>,
+	) -> Result<Vec<MarginCollateral>>;
+
 	#[rpc(name = "margin_poolState")]
 	fn pool_state(&self, pool_id: LiquidityPoolId, at: Option<BlockHash>) -> Result<Option<MarginPoolState>>;
+
+	#[rpc(name = "margin_simulateOpenPosition")]
+	fn simulate_open_position(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		leverage: Leverage,
+		leveraged_amount: Balance,
+		price: Price,
+		at: Option<BlockHash>,
+	) -> Result<MarginPositionSimulation>;
+
+	#[rpc(name = "margin_checkStopOut")]
+	fn check_stop_out(&self, who: AccountId, pool_id: LiquidityPoolId, at: Option<BlockHash>) -> Result<bool>;
+
+	#[rpc(name = "margin_swapRates")]
+	fn swap_rates(&self, pool_id: LiquidityPoolId, pair: TradingPair, at: Option<BlockHash>) -> Result<MarginSwapRates>;
+
+	#[rpc(name = "margin_riskParams")]
+	fn risk_params(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		at: Option<BlockHash>,
+	) -> Result<MarginRiskParams>;
+
+	#[rpc(name = "margin_openInterest")]
+	fn open_interest(&self, pair: TradingPair, at: Option<BlockHash>) -> Result<MarginOpenInterest>;
+
+	#[rpc(name = "margin_tradingVolume")]
+	fn trading_volume(
+		&self,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		at: Option<BlockHash>,
+	) -> Result<MarginTradingVolume>;
+
+	#[rpc(name = "margin_estimatedLiquidationPrice")]
+	fn estimated_liquidation_price(&self, position_id: u64, at: Option<BlockHash>) -> Result<Option<Price>>;
+
+	// Variants of the methods above rendering fixed point values as `Decimal`s of inner value, decimal
+	// string and scaling factor.
+	#[rpc(name = "margin_traderStateDecimal")]
+	fn trader_state_decimal(&self, who: AccountId, pool_id: LiquidityPoolId, at: Option<BlockHash>) -> Result<Value>;
+
+	#[rpc(name = "margin_traderCollateralsDecimal")]
+	fn trader_collaterals_decimal(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		at: Option<BlockHash>,
+	) -> Result<Value>;
+
+	#[rpc(name = "margin_poolStateDecimal")]
+	fn pool_state_decimal(&self, pool_id: LiquidityPoolId, at: Option<BlockHash>) -> Result<Value>;
+
+	#[rpc(name = "margin_simulateOpenPositionDecimal")]
+	fn simulate_open_position_decimal(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		leverage: Leverage,
+		leveraged_amount: Balance,
+		price: Price,
+		at: Option<BlockHash>,
+	) -> Result<Value>;
+
+	#[rpc(name = "margin_swapRatesDecimal")]
+	fn swap_rates_decimal(&self, pool_id: LiquidityPoolId, pair: TradingPair, at: Option<BlockHash>) -> Result<Value>;
+
+	#[rpc(name = "margin_riskParamsDecimal")]
+	fn risk_params_decimal(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		at: Option<BlockHash>,
+	) -> Result<Value>;
+
+	#[rpc(name = "margin_openInterestDecimal")]
+	fn open_interest_decimal(&self, pair: TradingPair, at: Option<BlockHash>) -> Result<Value>;
+
+	#[rpc(name = "margin_tradingVolumeDecimal")]
+	fn trading_volume_decimal(
+		&self,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		at: Option<BlockHash>,
+	) -> Result<Value>;
+
+	#[rpc(name = "margin_estimatedLiquidationPriceDecimal")]
+	fn estimated_liquidation_price_decimal(&self, position_id: u64, at: Option<BlockHash>) -> Result<Value>;
 }
 
 /// A struct that implements the [`MarginProtocolApi`].
@@ -42,16 +150,29 @@ impl<C, B> MarginProtocol<C, B> {
 
 pub enum Error {
 	RuntimeError,
+	OpenPositionError,
+	DecimalError,
 }
 
 impl From<Error> for i64 {
 	fn from(e: Error) -> i64 {
 		match e {
 			Error::RuntimeError => 1,
+			Error::OpenPositionError => 2,
+			Error::DecimalError => 3,
 		}
 	}
 }
 
+/// Render `value` with fixed point values as `Decimal`s.
+fn to_decimal<T: ToDecimal>(value: Result<T>) -> Result<Value> {
+	value?.to_decimal().map_err(|e| RpcError {
+		code: ErrorCode::ServerError(Error::DecimalError.into()),
+		message: "Unable to render decimals.".into(),
+		data: Some(format!("{:?}", e).into()),
+	})
+}
+
 impl<C, Block, AccountId> MarginProtocolApi<<Block as BlockT>::Hash, AccountId> for MarginProtocol<C, Block>
 where
 	Block: BlockT,
@@ -78,6 +199,25 @@ where
 			.into()
 	}
 
+	fn trader_collaterals(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Vec<MarginCollateral>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+		api.trader_collaterals(&at, who, pool_id)
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(Error::RuntimeError.into()),
+				message: "Unable to get trader collaterals.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})
+			.into()
+	}
+
 	fn pool_state(
 		&self,
 		pool_id: LiquidityPoolId,
@@ -95,4 +235,215 @@ where
 			})
 			.into()
 	}
+
+	fn simulate_open_position(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		leverage: Leverage,
+		leveraged_amount: Balance,
+		price: Price,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<MarginPositionSimulation> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+		api.simulate_open_position(&at, who, pool_id, pair, leverage, leveraged_amount, price)
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(Error::RuntimeError.into()),
+				message: "Unable to simulate open position.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})?
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(Error::OpenPositionError.into()),
+				message: "Open position would fail.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})
+	}
+
+	fn check_stop_out(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<bool> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+		api.check_stop_out(&at, who, pool_id)
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(Error::RuntimeError.into()),
+				message: "Unable to check stop out.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})
+			.into()
+	}
+
+	fn swap_rates(
+		&self,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<MarginSwapRates> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+		api.swap_rates(&at, pool_id, pair)
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(Error::RuntimeError.into()),
+				message: "Unable to get swap rates.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})
+			.into()
+	}
+
+	fn risk_params(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<MarginRiskParams> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+		api.risk_params(&at, who, pool_id, pair)
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(Error::RuntimeError.into()),
+				message: "Unable to get risk params.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})
+			.into()
+	}
+
+	fn open_interest(&self, pair: TradingPair, at: Option<<Block as BlockT>::Hash>) -> Result<MarginOpenInterest> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+		api.open_interest(&at, pair)
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(Error::RuntimeError.into()),
+				message: "Unable to get open interest.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})
+			.into()
+	}
+
+	fn trading_volume(
+		&self,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<MarginTradingVolume> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+		api.trading_volume(&at, pool_id, pair)
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(Error::RuntimeError.into()),
+				message: "Unable to get trading volume.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})
+			.into()
+	}
+
+	fn estimated_liquidation_price(
+		&self,
+		position_id: u64,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Option<Price>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+		api.estimated_liquidation_price(&at, position_id)
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(Error::RuntimeError.into()),
+				message: "Unable to get estimated liquidation price.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})
+			.into()
+	}
+
+	fn trader_state_decimal(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Value> {
+		to_decimal(self.trader_state(who, pool_id, at))
+	}
+
+	fn trader_collaterals_decimal(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Value> {
+		to_decimal(self.trader_collaterals(who, pool_id, at))
+	}
+
+	fn pool_state_decimal(&self, pool_id: LiquidityPoolId, at: Option<<Block as BlockT>::Hash>) -> Result<Value> {
+		to_decimal(self.pool_state(pool_id, at))
+	}
+
+	fn simulate_open_position_decimal(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		leverage: Leverage,
+		leveraged_amount: Balance,
+		price: Price,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Value> {
+		to_decimal(self.simulate_open_position(who, pool_id, pair, leverage, leveraged_amount, price, at))
+	}
+
+	fn swap_rates_decimal(
+		&self,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Value> {
+		to_decimal(self.swap_rates(pool_id, pair, at))
+	}
+
+	fn risk_params_decimal(
+		&self,
+		who: AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Value> {
+		to_decimal(self.risk_params(who, pool_id, pair, at))
+	}
+
+	fn open_interest_decimal(&self, pair: TradingPair, at: Option<<Block as BlockT>::Hash>) -> Result<Value> {
+		to_decimal(self.open_interest(pair, at))
+	}
+
+	fn trading_volume_decimal(
+		&self,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Value> {
+		to_decimal(self.trading_volume(pool_id, pair, at))
+	}
+
+	fn estimated_liquidation_price_decimal(
+		&self,
+		position_id: u64,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Value> {
+		to_decimal(self.estimated_liquidation_price(position_id, at))
+	}
 }
@@ -1,11 +1,12 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 use codec::{Decode, Encode};
+use environmental::environmental;
 use frame_support::{
 	debug, decl_error, decl_event, decl_module, decl_storage, ensure,
 	traits::{EnsureOrigin, Get},
-	weights::DispatchClass,
-	IterableStorageDoubleMap, IterableStorageMap,
+	weights::{DispatchClass, Weight},
+	IterableStorageDoubleMap, IterableStorageMap, Parameter,
 };
 use sp_arithmetic::{
 	traits::{Bounded, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Saturating},
@@ -13,14 +14,16 @@ use sp_arithmetic::{
 };
 use sp_runtime::{
 	offchain::{
+		http,
+		storage::StorageValueRef,
 		storage_lock::{StorageLock, Time},
 		Duration,
 	},
-	traits::{AccountIdConversion, StaticLookup},
+	traits::{AccountIdConversion, BadOrigin, IdentifyAccount, StaticLookup, UniqueSaturatedInto, Verify},
 	transaction_validity::{
 		InvalidTransaction, TransactionPriority, TransactionSource, TransactionValidity, ValidTransaction,
 	},
-	DispatchError, DispatchResult, ModuleId, RuntimeDebug,
+	DispatchError, DispatchResult, KeyTypeId, ModuleId, RuntimeDebug,
 };
 // FIXME: `pallet/frame-` prefix should be used for all pallet modules, but currently `frame_system`
 // would cause compiling error in `decl_module!` and `construct_runtime!`
@@ -30,26 +33,88 @@ use frame_system::{
 	ensure_none, ensure_signed,
 	offchain::{SendTransactionTypes, SubmitTransaction},
 };
-use orml_traits::{BasicCurrency, PriceProvider};
+use orml_traits::{BasicCurrency, MultiCurrency, PriceProvider};
 use orml_utilities::with_transaction_result;
 use primitives::{
-	arithmetic::{fixed_i128_from_fixed_u128, fixed_i128_from_u128, fixed_i128_mul_signum, u128_from_fixed_i128},
-	Balance, CurrencyId, Leverage, LiquidityPoolId, Price, TradingPair,
+	arithmetic::{
+		balance_from_fixed_i128, balance_mul_div_with_rounding, fixed_i128_checked_abs, fixed_i128_div_with_rounding,
+		fixed_i128_from_balance, fixed_i128_from_fixed_u128, fixed_i128_mul_signum, fixed_i128_mul_with_rounding,
+		u128_from_fixed_i128, BalanceConversionError, Rounding,
+	},
+	Balance, CurrencyId, FeeSplit, Leverage, LiquidityPoolId, Price, RollingVolume, TraderTier, TradingPair,
 };
-use sp_std::{cmp, prelude::*, result};
+use sp_std::{cmp, collections::btree_map::BTreeMap, prelude::*, result};
 use traits::{
-	BaseLiquidityPoolManager, LiquidityPools, MarginProtocolLiquidityPools, MarginProtocolLiquidityPoolsManager,
-	OpenPositionError,
+	AuditLog, BaseLiquidityPoolManager, FeeDiscount, InsuranceFund, LiquidityPools, MarginPoolRisk,
+	MarginProtocolLiquidityPools, MarginProtocolLiquidityPoolsManager, MarketCalendar, OpenPositionError,
+	PoolLiabilities, PoolLiabilityAmounts, PriceStatus, PriceUnavailableReason, TraderTiers,
 };
 
 #[cfg(feature = "std")]
 use serde::{Deserialize, Serialize};
 
 mod mock;
+mod property_tests;
 mod tests;
 
 const MODULE_ID: ModuleId = ModuleId(*b"lami/mgn");
 
+/// Storage version of the module. Storage of older versions is migrated in `on_runtime_upgrade`.
+///
+/// 1: `warning` added to `RiskThreshold`.
+/// 2: `MarginHeld` added, as the sum of margin held by positions.
+const STORAGE_VERSION: u32 = 3;
+
+environmental!(price_cache: BTreeMap<(CurrencyId, CurrencyId), Price>);
+
+/// Computation weight of taking the ENP and ELL snapshot of a pool, excluding storage accesses.
+const ENP_ELL_SNAPSHOT_WEIGHT_PER_POOL: Weight = 10_000;
+/// Computation weight of a trading pair with open positions in ENP and ELL snapshots, excluding storage accesses.
+const ENP_ELL_SNAPSHOT_WEIGHT_PER_PAIR: Weight = 20_000;
+/// Storage reads of a trading pair with open positions in ENP and ELL snapshots: positions snapshot and accumulated
+/// swap rates, prices and spreads.
+const ENP_ELL_SNAPSHOT_READS_PER_PAIR: Weight = 8;
+/// Computation weight of closing a position of a pending stop out or force closure, excluding storage accesses.
+const PENDING_STOP_OUT_WEIGHT_PER_POSITION: Weight = 20_000;
+/// Storage reads of closing a position of a pending stop out or force closure, including the risk check.
+const PENDING_STOP_OUT_READS_PER_POSITION: Weight = 19;
+/// Storage writes of closing a position of a pending stop out or force closure.
+const PENDING_STOP_OUT_WRITES_PER_POSITION: Weight = 17;
+/// Computation weight of copying a position open or close of a leader for a follower, excluding storage accesses and
+/// risk checks.
+const COPY_WEIGHT_PER_FOLLOWER: Weight = 20_000;
+/// Storage reads of copying a position open or close of a leader for a follower: copy trading, balances, position,
+/// prices, spreads, accumulated swap rate and pool liquidity.
+const COPY_READS_PER_FOLLOWER: Weight = 10;
+/// Storage writes of copying a position open or close of a leader for a follower: position, trader and pool indexes,
+/// positions snapshot, balance, margin held, copied position leader and pool liquidity.
+const COPY_WRITES_PER_FOLLOWER: Weight = 8;
+/// Computation weight of an open position of a trader in equity and margin level calculations, excluding storage
+/// accesses.
+const TRADER_WEIGHT_PER_POSITION: Weight = 5_000;
+/// Storage reads of an open position of a trader in equity and margin level calculations: position and accumulated
+/// swap rate.
+const TRADER_READS_PER_POSITION: Weight = 2;
+/// Computation weight of ratcheting a trailing stop, excluding storage accesses.
+const TRAILING_STOP_WEIGHT: Weight = 10_000;
+/// Storage reads of ratcheting a trailing stop: trailing stop, position and price.
+const TRAILING_STOP_READS: Weight = 3;
+/// Storage writes of ratcheting a trailing stop: trailing stop and stop loss.
+const TRAILING_STOP_WRITES: Weight = 2;
+/// Computation weight of settling the accrued swap of a position, excluding storage accesses.
+const SWAP_SETTLEMENT_WEIGHT_PER_POSITION: Weight = 10_000;
+/// Storage reads of settling the accrued swap of a position: position, isolation, accumulated swap rate, price,
+/// balance and pool liquidity.
+const SWAP_SETTLEMENT_READS_PER_POSITION: Weight = 6;
+/// Storage writes of settling the accrued swap of a position: position, balance, margin held and pool liquidity.
+const SWAP_SETTLEMENT_WRITES_PER_POSITION: Weight = 4;
+/// Computation weight of checking the circuit breaker of a trading pair, excluding storage accesses.
+const CIRCUIT_BREAKER_WEIGHT_PER_PAIR: Weight = 10_000;
+/// Storage reads of checking the circuit breaker of a trading pair: reference price, trip and price.
+const CIRCUIT_BREAKER_READS_PER_PAIR: Weight = 3;
+/// Storage writes of checking the circuit breaker of a trading pair: reference price and trip.
+const CIRCUIT_BREAKER_WRITES_PER_PAIR: Weight = 2;
+
 pub trait Trait: frame_system::Trait + SendTransactionTypes<Call<Self>> {
 	/// The overarching event type.
 	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
@@ -57,15 +122,33 @@ pub trait Trait: frame_system::Trait + SendTransactionTypes<Call<Self>> {
 	/// The currency used for liquidity.
 	type LiquidityCurrency: BasicCurrency<Self::AccountId, Balance = Balance>;
 
+	/// Currencies that could be locked as collateral, like synthetic tokens.
+	type MultiCurrency: MultiCurrency<Self::AccountId, Balance = Balance, CurrencyId = CurrencyId>;
+
 	/// The `MarginProtocolLiquidityPools` implementation.
 	type LiquidityPools: MarginProtocolLiquidityPools<Self::AccountId>;
 
 	/// Provides market prices.
 	type PriceProvider: PriceProvider<CurrencyId, Price>;
 
+	/// Reasons of unavailable prices, to return granular price errors.
+	type PriceStatus: PriceStatus;
+
+	/// Market holidays. Positions cannot be opened in closed markets.
+	type MarketCalendar: MarketCalendar;
+
 	/// The account ID of treasury.
 	type GetTreasuryAccountId: Get<Self::AccountId>;
 
+	/// The account ID of the insurance fund, paying compensations of disputed stop outs.
+	type GetInsuranceFundAccountId: Get<Self::AccountId>;
+
+	/// Number of blocks after a trader stop out within which it could be disputed. `0` means no disputes.
+	type GetStopOutDisputeWindow: Get<Self::BlockNumber>;
+
+	/// Number of blocks per which slippage insurance premiums are charged. `0` means no premiums.
+	type GetSlippageInsurancePremiumPeriod: Get<Self::BlockNumber>;
+
 	/// Maximum number of positions one trader could open.
 	type GetTraderMaxOpenPositions: Get<usize>;
 
@@ -75,15 +158,71 @@ pub trait Trait: frame_system::Trait + SendTransactionTypes<Call<Self>> {
 	/// Required origin for updating protocol options.
 	type UpdateOrigin: EnsureOrigin<Self::Origin>;
 
+	/// Required origin for pausing and resuming trading, expected to act faster than `UpdateOrigin` in emergencies.
+	type PauseOrigin: EnsureOrigin<Self::Origin>;
+
+	/// Audit log of privileged parameter changes.
+	type AuditLog: AuditLog<Self::Origin>;
+
+	/// Trading fee discounts of traders, applied to spreads on opening and closing positions.
+	type FeeDiscount: FeeDiscount<Self::AccountId>;
+
+	/// Trader tiers, for the max leverage allowed by pools.
+	type TraderTiers: TraderTiers<Self::AccountId>;
+
 	/// A configuration for base priority of unsigned transactions.
 	///
 	/// This is exposed so that it can be tuned for particular runtime, when
 	/// multiple pallets send unsigned transactions.
 	type UnsignedPriority: Get<TransactionPriority>;
+
+	/// Interval of blocks to take ENP and ELL snapshots of liquidity pools. `0` means no snapshots.
+	type GetEnpEllSnapshotInterval: Get<Self::BlockNumber>;
+
+	/// Maximum number of ENP and ELL snapshots kept for a pool. The oldest one is dropped once exceeded.
+	type GetMaxEnpEllSnapshots: Get<u32>;
+
+	/// Number of blocks per which accrued swap of open positions is settled into balances. `0` means swap is only
+	/// realized on closing.
+	type GetSwapSettlementInterval: Get<Self::BlockNumber>;
+
+	/// Period of blocks of rolling trading volumes. `0` means volumes are never rolled over.
+	type GetVolumePeriod: Get<Self::BlockNumber>;
+
+	/// Maximum number of followers copying positions of a leader in a pool.
+	type GetMaxCopyFollowers: Get<u32>;
+
+	/// Maximum number of positions closed by a stop out or force closure extrinsic, and by `on_initialize` of each
+	/// block for pending ones and expired ones. Positions over the limit are closed in following blocks. Expired
+	/// pending orders are cancelled in `on_initialize` under the same limit.
+	type GetStopOutChunkSize: Get<u32>;
+
+	/// Maximum number of closed positions kept in the trade history of a trader. The oldest one is dropped once
+	/// exceeded.
+	type GetMaxTradeHistory: Get<u32>;
+
+	/// Maximum number of pending orders of a trader, across pools.
+	type GetTraderMaxPendingOrders: Get<u32>;
+
+	/// Liabilities of liquidity pools outside of margin positions, like written options, folded into pool ENP and
+	/// ELL.
+	type PoolLiabilities: PoolLiabilities;
+
+	/// Insurance fund covering negative balances of traders that collateral can't repay.
+	type InsuranceFund: InsuranceFund<Self::AccountId>;
+
+	/// Signature of RFQ quotes signed off-chain by pool owners, and of meta-transactions signed off-chain by
+	/// traders.
+	type QuoteSignature: Verify<Signer = Self::QuoteSigner> + Parameter;
+
+	/// Signer of RFQ quotes and meta-transactions, identified by the pool owner or trader account.
+	type QuoteSigner: IdentifyAccount<AccountId = Self::AccountId>;
 }
 
 pub type PositionId = u64;
 
+pub type OrderId = u64;
+
 /// Margin protocol Position.
 #[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
 pub struct Position<T: Trait> {
@@ -109,13 +248,126 @@ pub struct Position<T: Trait> {
 	/// Negative value if long position, positive if short.
 	leveraged_debits: FixedI128,
 
-	/// Accumulated swap rate on open position.
+	/// Accumulated swap rate index of the pool and trading pair on open position.
 	open_accumulated_swap_rate: FixedI128,
 
 	/// Margin held.
 	margin_held: FixedI128,
 }
 
+/// Execution data of a position closed on a trader stop out, kept for disputes.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct StopOutRecord<T: Trait> {
+	/// The closed position.
+	position: Position<T>,
+
+	/// Oracle price of the trading pair on closing.
+	price: Price,
+
+	/// Change of the trader's balance on closing.
+	realized: FixedI128,
+}
+
+/// A closed position, or the closed part of a partially closed one, in the trade history of its trader.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct TradeRecord<BlockNumber> {
+	/// Position ID.
+	pub position_id: PositionId,
+
+	/// Liquidity pool ID where the position was opened in.
+	pub pool: LiquidityPoolId,
+
+	/// Trading pair.
+	pub pair: TradingPair,
+
+	/// Leverage.
+	pub leverage: Leverage,
+
+	/// Leveraged held amount closed. Positive value if long position, negative if short.
+	pub leveraged_held: FixedI128,
+
+	/// Open price, without spread.
+	pub open_price: Price,
+
+	/// Close price, with spread.
+	pub close_price: Price,
+
+	/// Accumulated swap of the position in USD, which is included in `realized_pl`. Negative if paid.
+	pub swap: FixedI128,
+
+	/// Realized profit or loss in USD. Capped by the pool liquidity on profit, and by the trader's equity on loss.
+	pub realized_pl: FixedI128,
+
+	/// The block the position was opened at, or `None` if opened before trade history was recorded.
+	pub opened_at: Option<BlockNumber>,
+
+	/// The block the position was closed at.
+	pub closed_at: BlockNumber,
+}
+
+/// RFQ quote of a pool owner for a trader, signed off-chain and filled on-chain by the trader.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct Quote<AccountId, BlockNumber> {
+	/// The trader the quote is for.
+	pub trader: AccountId,
+
+	/// Liquidity pool ID where the position is opened in.
+	pub pool: LiquidityPoolId,
+
+	/// Trading pair.
+	pub pair: TradingPair,
+
+	/// Leverage.
+	pub leverage: Leverage,
+
+	/// Leveraged amount.
+	pub leveraged_amount: Balance,
+
+	/// Quoted open price, instead of the market price with spread.
+	pub price: Price,
+
+	/// The quote could only be filled before this block.
+	pub expiry: BlockNumber,
+
+	/// Unique among quotes of the pool, to prevent filling a quote more than once.
+	pub nonce: u64,
+}
+
+/// Trading intent of a trader in a meta-transaction.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
+pub enum TradingIntent {
+	/// Deposit liquidity, as in `deposit`.
+	Deposit { pool: LiquidityPoolId, amount: Balance },
+
+	/// Open a position, as in `open_position`.
+	OpenPosition {
+		pool: LiquidityPoolId,
+		pair: TradingPair,
+		leverage: Leverage,
+		leveraged_amount: Balance,
+		price: Price,
+	},
+
+	/// Close a position, as in `close_position`.
+	ClosePosition { position_id: PositionId, price: Price },
+}
+
+/// Trading intent signed off-chain by a trader, and submitted on-chain by a relayer paying the transaction fee.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct MetaTransaction<AccountId, BlockNumber> {
+	/// The trader who signed the meta-transaction.
+	pub trader: AccountId,
+
+	/// The trading intent.
+	pub intent: TradingIntent,
+
+	/// The meta-transaction could only be relayed before this block.
+	pub expiry: BlockNumber,
+
+	/// The next meta-transaction nonce of the trader, to prevent relaying a meta-transaction more than once.
+	pub nonce: u64,
+}
+
 /// Positions snapshot.
 #[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq, Default)]
 pub struct PositionsSnapshot {
@@ -139,6 +391,78 @@ pub struct LeveragedAmounts {
 	debits: FixedI128,
 }
 
+/// Notional trading volume of a trading pair in a pool, in the quote currency of the pair.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct TradingVolume {
+	/// Leveraged debits of opened positions.
+	pub opened: Balance,
+
+	/// Leveraged debits of closed positions.
+	pub closed: Balance,
+}
+
+/// Open interest of a trading pair across all pools.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct OpenInterest {
+	/// Total leveraged held amount of long positions.
+	pub long: FixedI128,
+
+	/// Total leveraged held amount of short positions, as a positive value.
+	pub short: FixedI128,
+
+	/// Long positions count.
+	pub long_positions_count: PositionId,
+
+	/// Short positions count.
+	pub short_positions_count: PositionId,
+}
+
+impl OpenInterest {
+	/// Add an opened `position`. Returns `None` if overflow.
+	fn add<T: Trait>(&mut self, position: &Position<T>) -> Option<()> {
+		self.increase(position)?;
+		if position.leverage.is_long() {
+			self.long_positions_count = self.long_positions_count.checked_add(1)?;
+		} else {
+			self.short_positions_count = self.short_positions_count.checked_add(1)?;
+		}
+		Some(())
+	}
+
+	/// Increase by an `opened` position netted into an open one, keeping the positions count. Returns `None` if
+	/// overflow.
+	fn increase<T: Trait>(&mut self, opened: &Position<T>) -> Option<()> {
+		if opened.leverage.is_long() {
+			self.long = self.long.checked_add(&opened.leveraged_held)?;
+		} else {
+			// short leveraged held is negative
+			self.short = self.short.checked_sub(&opened.leveraged_held)?;
+		}
+		Some(())
+	}
+
+	/// Remove a closed `position`.
+	///
+	/// Saturating, as open interest is informational and closing positions must not fail on it.
+	fn remove<T: Trait>(&mut self, position: &Position<T>) {
+		self.reduce(position);
+		if position.leverage.is_long() {
+			self.long_positions_count = self.long_positions_count.saturating_sub(1);
+		} else {
+			self.short_positions_count = self.short_positions_count.saturating_sub(1);
+		}
+	}
+
+	/// Reduce by the `closed` part of a position, keeping the positions count.
+	fn reduce<T: Trait>(&mut self, closed: &Position<T>) {
+		if closed.leverage.is_long() {
+			self.long = self.long.saturating_sub(closed.leveraged_held);
+		} else {
+			self.short = self.short.saturating_add(closed.leveraged_held);
+		}
+	}
+}
+
 /// Risk threshold.
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 #[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq, Default)]
@@ -148,6 +472,21 @@ pub struct RiskThreshold {
 
 	/// Stop out threshold.
 	pub stop_out: Permill,
+
+	/// Warning threshold, higher than margin call threshold. Reaching it only emits an event.
+	#[cfg_attr(feature = "std", serde(default))]
+	pub warning: Permill,
+}
+
+impl RiskThreshold {
+	/// Returns the higher one of each threshold.
+	fn max(self, other: Self) -> Self {
+		Self {
+			margin_call: cmp::max(self.margin_call, other.margin_call),
+			stop_out: cmp::max(self.stop_out, other.stop_out),
+			warning: cmp::max(self.warning, other.warning),
+		}
+	}
 }
 
 /// Risk threshold for a trading pair.
@@ -168,6 +507,299 @@ impl TradingPairRiskThreshold {
 	}
 }
 
+/// Penalty charged from a liquidity pool when force closing a position, per severity tier.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct ForceClosePenaltyTier {
+	/// The tier applies if pool's ENP or ELL is not higher than this threshold.
+	pub threshold: Permill,
+
+	/// Penalty rate, relative to the spread profit of the closed position.
+	pub penalty_rate: FixedU128,
+
+	/// The part of penalty compensated to the position owner, the rest goes to treasury.
+	pub trader_share: Permill,
+}
+
+impl Default for ForceClosePenaltyTier {
+	/// Penalty equal to the spread profit, all to treasury.
+	fn default() -> Self {
+		Self {
+			threshold: Permill::one(),
+			penalty_rate: FixedU128::one(),
+			trader_share: Permill::zero(),
+		}
+	}
+}
+
+/// Margin requirement of positions of a trading pair above a notional value.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct MarginTier {
+	/// The tier applies if the notional value in USD of the opened position is higher than this threshold.
+	pub notional_threshold: Balance,
+
+	/// Max leverage value the margin of positions in the tier is held at. Higher leverages hold margin at this
+	/// value.
+	pub max_leverage: u8,
+}
+
+/// Circuit breaker of a trading pair, halting new positions on extreme oracle price moves.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct CircuitBreaker<BlockNumber> {
+	/// The breaker trips if the oracle price moves more than this rate from the reference price.
+	pub max_price_move: Permill,
+
+	/// Blocks the reference price is kept for, before it's reset to the current price.
+	pub window: BlockNumber,
+
+	/// Blocks new positions are halted for after the breaker trips.
+	pub cool_down: BlockNumber,
+}
+
+/// Scope of a trading pause. Positions in paused scopes could only be closed.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+pub enum TradingPauseScope {
+	/// The whole protocol.
+	Protocol,
+
+	/// A liquidity pool.
+	Pool(LiquidityPoolId),
+
+	/// A trading pair, in all pools.
+	Pair(TradingPair),
+}
+
+/// Protocol options of a trader tier, applied on top of the options of pools and trading pairs.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct TraderTierOption {
+	/// Max leverage of new positions. No cap if `None`.
+	pub max_leverage: Option<u8>,
+
+	/// Risk threshold of traders of the tier. The higher one of each threshold applies if the trading pair risk
+	/// threshold is higher.
+	pub risk_threshold: Option<RiskThreshold>,
+}
+
+/// ENP and ELL snapshot of a liquidity pool.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct EnpEllSnapshot<BlockNumber> {
+	/// The block number the snapshot was taken at.
+	pub block_number: BlockNumber,
+
+	/// Equity to Net Position ratio.
+	pub enp: FixedI128,
+
+	/// Equity to Longest Leg ratio.
+	pub ell: FixedI128,
+}
+
+/// Limits of margin deposit and withdraw amounts in a period.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct VelocityLimit<BlockNumber> {
+	/// Deposit amount limit of a period. `None` means no limit.
+	pub max_deposit: Option<Balance>,
+
+	/// Withdraw amount limit of a period. `None` means no limit.
+	pub max_withdraw: Option<Balance>,
+
+	/// The period in blocks.
+	pub period: BlockNumber,
+}
+
+/// Deposited and withdrew amounts of an account in the current period.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct VelocityUsage<BlockNumber> {
+	/// The block number current period started at.
+	pub period_start: BlockNumber,
+
+	/// Deposited amount in current period.
+	pub deposited: Balance,
+
+	/// Withdrew amount in current period.
+	pub withdrew: Balance,
+}
+
+/// A temporary key authorized by a trader to open and close positions on the trader's behalf. Keys could never
+/// withdraw.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct TradingKey<BlockNumber> {
+	/// The key could only be used before this block.
+	pub expiry: BlockNumber,
+
+	/// Maximum cumulative notional of positions opened by the key, in USD.
+	pub notional_limit: Balance,
+
+	/// Cumulative notional of positions opened by the key, in USD.
+	pub notional_used: Balance,
+}
+
+/// Distance of a trailing stop from the best price since it is set.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+pub enum TrailingDistance {
+	/// Price distance, like pips.
+	Price(Price),
+
+	/// Percentage of the best price.
+	Percent(Permill),
+}
+
+/// Trailing stop of a position, whose stop loss price ratchets with favorable price moves.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct TrailingStop {
+	/// Distance of the stop loss price from the watermark.
+	pub distance: TrailingDistance,
+
+	/// The best oracle price since the trailing stop is set: the highest one for long positions, or the lowest one
+	/// for short positions.
+	pub watermark: Price,
+}
+
+impl TrailingStop {
+	/// The stop loss price, below the watermark for long positions, or above it for short positions.
+	fn stop_loss(&self, is_long: bool) -> Price {
+		match (self.distance, is_long) {
+			(TrailingDistance::Price(d), true) => self.watermark.saturating_sub(d),
+			(TrailingDistance::Price(d), false) => self.watermark.saturating_add(d),
+			(TrailingDistance::Percent(p), true) => self.watermark.saturating_mul((Permill::one() - p).into()),
+			(TrailingDistance::Percent(p), false) => {
+				self.watermark.saturating_mul(FixedU128::one().saturating_add(p.into()))
+			}
+		}
+	}
+}
+
+/// Margin mode of a trader in a liquidity pool, applied to positions opened in it.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+pub enum MarginMode {
+	/// Positions share the trader's equity, and are stopped out together.
+	Cross,
+
+	/// Only the position's own margin is at risk, and it's stopped out on its own margin level.
+	Isolated,
+}
+
+impl Default for MarginMode {
+	fn default() -> Self {
+		MarginMode::Cross
+	}
+}
+
+/// Reward of a keeper for a liquidation call, paid by the liquidated trader or pool.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+pub enum KeeperReward {
+	/// Flat amount per liquidation call which closes any position.
+	Flat(Balance),
+
+	/// Rate of the notional value of positions closed by the call.
+	Rate(Permill),
+}
+
+/// Trigger condition of a pending order, on the oracle price.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+pub enum OrderKind {
+	/// Triggered at the trigger price or better: at or below it for long positions, at or above it for short
+	/// positions.
+	Limit,
+
+	/// Triggered at the trigger price or worse: at or above it for long positions, at or below it for short
+	/// positions.
+	Stop,
+}
+
+/// Pending order to open a position once the oracle price satisfies its trigger.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct PendingOrder<AccountId> {
+	/// Owner.
+	pub owner: AccountId,
+
+	/// Liquidity pool ID to open the position in.
+	pub pool: LiquidityPoolId,
+
+	/// Trading pair.
+	pub pair: TradingPair,
+
+	/// Leverage.
+	pub leverage: Leverage,
+
+	/// Leveraged amount of the position.
+	pub leveraged_amount: Balance,
+
+	/// Trigger condition.
+	pub kind: OrderKind,
+
+	/// Oracle price the order is triggered at.
+	pub trigger_price: Price,
+
+	/// Price limit of opening the position, as in `open_position`.
+	pub price: Price,
+}
+
+/// Copy trading settings of a follower in a liquidity pool.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct CopyTrading<AccountId> {
+	/// The leader whose positions are copied.
+	pub leader: AccountId,
+
+	/// Maximum leverage of a copied position. Leader's positions with a higher leverage are not copied.
+	pub max_leverage: u8,
+
+	/// Maximum leveraged amount of a copied position. Copied amount is capped by it.
+	pub max_leveraged_amount: Balance,
+
+	/// Share of realized profit on copied positions paid to the leader. Fixed on following.
+	pub fee_share: Permill,
+}
+
+/// Projected outcome of opening a position.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct OpenPositionSimulation {
+	/// Open price, with spread applied.
+	pub open_price: Price,
+
+	/// Margin held by the new position.
+	pub margin_held: FixedI128,
+
+	/// Trader margin level after opening the position.
+	pub margin_level: FixedI128,
+
+	/// Liquidity pool ENP after opening the position.
+	pub enp: FixedI128,
+
+	/// Liquidity pool ELL after opening the position.
+	pub ell: FixedI128,
+}
+
+/// Effective risk parameters of a trader opening positions of a trading pair in a pool.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct RiskParams {
+	/// Trader risk threshold, the highest of the trading pair's, the trader's open trading pairs' in the pool and
+	/// the trader's tier's.
+	pub trader_threshold: RiskThreshold,
+
+	/// Pool ENP risk threshold, the highest of the trading pair's and the pool's open trading pairs'.
+	pub enp_threshold: RiskThreshold,
+
+	/// Pool ELL risk threshold, the highest of the trading pair's and the pool's open trading pairs'.
+	pub ell_threshold: RiskThreshold,
+
+	/// Bid spread with the trader's fee discount applied, or `None` if not set by pool owner.
+	pub bid_spread: Option<Balance>,
+
+	/// Ask spread with the trader's fee discount applied, or `None` if not set by pool owner.
+	pub ask_spread: Option<Balance>,
+
+	/// Number of positions the trader could still open in the pool.
+	pub trader_positions_headroom: u32,
+
+	/// Number of positions of the trading pair could still be opened in the pool.
+	pub pool_positions_headroom: u32,
+}
+
 decl_storage! {
 	trait Store for Module<T: Trait> as MarginProtocol {
 		/// Next available position ID.
@@ -177,16 +809,33 @@ decl_storage! {
 		Positions get(fn positions): map hasher(twox_64_concat) PositionId => Option<Position<T>>;
 
 		/// Positions existence check by traders and liquidity pool IDs.
+		///
+		/// Keyed by position ID, so that inserting and removing a position doesn't depend on the number of
+		/// positions of the trader.
 		PositionsByTrader get(fn positions_by_trader): double_map hasher(twox_64_concat) T::AccountId, hasher(twox_64_concat) (LiquidityPoolId, PositionId) => Option<()>;
 
 		/// Positions existence check by pools and trading pairs.
+		///
+		/// Keyed by position ID, so that inserting and removing a position doesn't depend on the number of
+		/// positions in the pool.
 		PositionsByPool get(fn positions_by_pool): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) (TradingPair, PositionId) => Option<()>;
 
+		/// Sum of margin held of all open positions, by traders and liquidity pool IDs.
+		MarginHeld get(fn margin_held): double_map hasher(twox_64_concat) T::AccountId, hasher(twox_64_concat) LiquidityPoolId => FixedI128;
+
 		/// Positions snapshots.
 		///
 		/// Used for performance improvement.
 		PositionsSnapshots get(fn pool_positions_snapshots): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) TradingPair => PositionsSnapshot;
 
+		/// Open interest of each trading pair across all pools.
+		OpenInterests get(fn open_interest): map hasher(twox_64_concat) TradingPair => OpenInterest;
+
+		/// Rolling trading volumes of trading pairs in liquidity pools.
+		///
+		/// Not rolled over until the next trade, see `trading_volume` for up to date volumes.
+		TradingVolumes get(fn trading_volumes): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) TradingPair => RollingVolume<T::BlockNumber, TradingVolume>;
+
 		/// Balance of a trader in a liquidity pool.
 		///
 		/// The balance value could be positive or negative:
@@ -210,60 +859,527 @@ decl_storage! {
 		///
 		/// DEFAULT-NOTE: `trader`, `enp`, and `ell` are all `None` by default.
 		RiskThresholds get(fn risk_thresholds): map hasher(twox_64_concat) TradingPair => TradingPairRiskThreshold;
-	}
 
-	add_extra_genesis {
-		config(risk_thresholds): Vec<(TradingPair, RiskThreshold, RiskThreshold, RiskThreshold)>;
-		build(|config: &GenesisConfig| {
-			config.risk_thresholds.iter().for_each(|(pair, trader, enp, ell)| {
-				RiskThresholds::insert(
-					pair,
-					TradingPairRiskThreshold::new(Some(*trader), Some(*enp), Some(*ell)),
-				);
-			})
-		})
-	}
-}
+		/// Historical ENP and ELL snapshots of a liquidity pool, from oldest to latest.
+		///
+		/// Taken every `GetEnpEllSnapshotInterval` blocks, at most `GetMaxEnpEllSnapshots` kept.
+		EnpEllSnapshots get(fn enp_ell_snapshots): map hasher(twox_64_concat) LiquidityPoolId => Vec<EnpEllSnapshot<T::BlockNumber>>;
 
-decl_event! {
-	pub enum Event<T> where
-		<T as frame_system::Trait>::AccountId,
-		LiquidityPoolId = LiquidityPoolId,
-		TradingPair = TradingPair,
-		Amount = Balance
-	{
-		/// Position opened: [who, position_id, pool_id, pair, leverage, leveraged_amount, open_price]
-		PositionOpened(AccountId, PositionId, LiquidityPoolId, TradingPair, Leverage, Amount, Price),
+		/// Positions of a stopping out trader in a pool not closed yet, in `trader_stop_out_order`.
+		///
+		/// Closed in chunks of at most `GetStopOutChunkSize` in following blocks, until the trader has no stop out
+		/// risk or all positions closed.
+		PendingTraderStopOuts get(fn pending_trader_stop_outs): double_map hasher(twox_64_concat) T::AccountId, hasher(twox_64_concat) LiquidityPoolId => Vec<PositionId>;
 
-		/// Position closed: [who, position_id, pool_id, close_price]
-		PositionClosed(AccountId, PositionId, LiquidityPoolId, Price),
+		/// Penalty tier and positions not closed yet of a force closing liquidity pool.
+		///
+		/// Closed in chunks of at most `GetStopOutChunkSize` in following blocks. The penalty tier is kept from the
+		/// force closure, so that all positions are penalized the same.
+		PendingPoolForceCloses get(fn pending_pool_force_closes): map hasher(twox_64_concat) LiquidityPoolId => Option<(ForceClosePenaltyTier, Vec<PositionId>)>;
 
-		/// Deposited: [who, pool_id, amount]
-		Deposited(AccountId, LiquidityPoolId, Amount),
+		/// Buffer above margin call thresholds, within which traders and pools are kept as risk candidates.
+		///
+		/// Risk candidates are not maintained if not set.
+		RiskCandidateBuffer get(fn risk_candidate_buffer): Option<Permill>;
 
-		/// Withdrew: [who, pool_id, amount]
-		Withdrew(AccountId, LiquidityPoolId, Amount),
+		/// Traders in pools whose margin level is within `RiskCandidateBuffer` of the margin call threshold, or
+		/// couldn't be evaluated.
+		///
+		/// Updated on trades and risk checks of the trader, and on `update_risk_candidacy` calls, so that only a small
+		/// set of traders needs to be checked for risks.
+		RiskCandidateTraders get(fn risk_candidate_traders): double_map hasher(twox_64_concat) T::AccountId, hasher(twox_64_concat) LiquidityPoolId => Option<()>;
 
-		/// Trader margin called: [who]
-		TraderMarginCalled(AccountId),
+		/// Pools whose ENP or ELL is within `RiskCandidateBuffer` of the margin call threshold, or couldn't be
+		/// evaluated.
+		///
+		/// Updated on trades in the pool, risk checks and ENP and ELL snapshots of the pool, and on
+		/// `update_risk_candidacy` calls.
+		RiskCandidatePools get(fn risk_candidate_pools): map hasher(twox_64_concat) LiquidityPoolId => Option<()>;
 
-		/// Trader became safe: [who]
-		TraderBecameSafe(AccountId),
+		/// Force closure penalty tiers of liquidity pools.
+		///
+		/// The matched tier with the lowest threshold applies. If none matched, the default tier applies.
+		ForceClosePenaltyTiers get(fn force_close_penalty_tiers): Vec<ForceClosePenaltyTier>;
 
-		/// Trader stopped out: [who]
-		TraderStoppedOut(AccountId),
+		/// Split of force closure penalties of a liquidity pool. All to the treasury if not set.
+		FeeSplits get(fn fee_splits): map hasher(twox_64_concat) LiquidityPoolId => Option<FeeSplit>;
 
-		/// Liquidity pool margin called: [pool_id]
-		LiquidityPoolMarginCalled(LiquidityPoolId),
+		/// Deposit and withdraw velocity limit of an account. Overrides `GlobalVelocityLimit` if set.
+		VelocityLimits get(fn velocity_limits): map hasher(twox_64_concat) T::AccountId => Option<VelocityLimit<T::BlockNumber>>;
 
-		/// Liquidity pool became safe: [pool_id]
+		/// Deposit and withdraw velocity limit of all accounts.
+		GlobalVelocityLimit get(fn global_velocity_limit): Option<VelocityLimit<T::BlockNumber>>;
+
+		/// Deposit and withdraw velocity usage of an account.
+		VelocityUsages get(fn velocity_usages): map hasher(twox_64_concat) T::AccountId => VelocityUsage<T::BlockNumber>;
+
+		/// Copy trading settings of a follower in a liquidity pool.
+		CopyTradings get(fn copy_tradings): double_map hasher(twox_64_concat) T::AccountId, hasher(twox_64_concat) LiquidityPoolId => Option<CopyTrading<T::AccountId>>;
+
+		/// Followers of a leader in a liquidity pool, at most `GetMaxCopyFollowers`.
+		CopyFollowers get(fn copy_followers): double_map hasher(twox_64_concat) T::AccountId, hasher(twox_64_concat) LiquidityPoolId => Vec<T::AccountId>;
+
+		/// Copied positions of a leader's position, in `(follower, position_id)`.
+		CopiedPositions get(fn copied_positions): map hasher(twox_64_concat) PositionId => Vec<(T::AccountId, PositionId)>;
+
+		/// The leader and fee share of a copied position.
+		CopiedPositionLeaders get(fn copied_position_leaders): map hasher(twox_64_concat) PositionId => Option<(T::AccountId, Permill)>;
+
+		/// Share of followers' realized profit on copied positions that a leader asks for.
+		LeaderFeeShares get(fn leader_fee_share): map hasher(twox_64_concat) T::AccountId => Permill;
+
+		/// Execution data of positions closed on trader stop outs, by the block number of closing and position ID.
+		///
+		/// Pruned once out of `GetStopOutDisputeWindow`.
+		StopOutRecords get(fn stop_out_records): double_map hasher(twox_64_concat) T::BlockNumber, hasher(twox_64_concat) PositionId => Option<StopOutRecord<T>>;
+
+		/// Stop outs disputed by traders, to be resolved by `UpdateOrigin`.
+		StopOutDisputes get(fn stop_out_disputes): map hasher(twox_64_concat) PositionId => Option<StopOutRecord<T>>;
+
+		/// Compensations of disputed stop outs, in `(trader, amount)`, to be claimed from the insurance fund.
+		StopOutCompensations get(fn stop_out_compensations): map hasher(twox_64_concat) PositionId => Option<(T::AccountId, Balance)>;
+
+		/// Slippage insurance premium rate of a trading pair, per `GetSlippageInsurancePremiumPeriod` of the notional
+		/// value of insured positions. Positions of pairs without a rate are not insured.
+		SlippageInsurancePremiumRates get(fn slippage_insurance_premium_rate): map hasher(twox_64_concat) TradingPair => Option<Permill>;
+
+		/// Traders opted in to slippage insurance in a pool.
+		SlippageInsurances get(fn slippage_insurance): double_map hasher(twox_64_concat) T::AccountId, hasher(twox_64_concat) LiquidityPoolId => Option<()>;
+
+		/// Insured positions, with the block and the premium rate they are insured at.
+		InsuredPositions get(fn insured_positions): map hasher(twox_64_concat) PositionId => Option<(T::BlockNumber, Permill)>;
+
+		/// Reward of keepers for signed liquidation calls. No reward if `None`.
+		KeeperRewardSetting get(fn keeper_reward): Option<KeeperReward>;
+
+		/// Stop loss prices of positions.
+		StopLosses get(fn stop_loss): map hasher(twox_64_concat) PositionId => Option<Price>;
+
+		/// Take profit prices of positions.
+		TakeProfits get(fn take_profit): map hasher(twox_64_concat) PositionId => Option<Price>;
+
+		/// Trailing stops of positions, which set their stop loss prices.
+		TrailingStops get(fn trailing_stop): map hasher(twox_64_concat) PositionId => Option<TrailingStop>;
+
+		/// Expiry blocks of positions, after which they are closed at market.
+		PositionExpiries get(fn position_expiry): map hasher(twox_64_concat) PositionId => Option<T::BlockNumber>;
+
+		/// Positions by expiry block, for closing them in `on_initialize`.
+		ExpiringPositions get(fn expiring_positions): double_map hasher(twox_64_concat) T::BlockNumber, hasher(twox_64_concat) PositionId => Option<()>;
+
+		/// The block open positions were opened at.
+		PositionOpenedAt get(fn position_opened_at): map hasher(twox_64_concat) PositionId => Option<T::BlockNumber>;
+
+		/// Closed positions of a trader, from oldest to latest. At most `GetMaxTradeHistory` kept.
+		TradeHistory get(fn trade_history): map hasher(twox_64_concat) T::AccountId => Vec<TradeRecord<T::BlockNumber>>;
+
+		/// Cumulative realized profit and loss of a trader in a pool, including swaps.
+		CumulativeRealizedPl get(fn cumulative_realized_pl): double_map hasher(twox_64_concat) T::AccountId, hasher(twox_64_concat) LiquidityPoolId => FixedI128;
+
+		/// Margin modes of traders in pools.
+		MarginModes get(fn margin_mode): double_map hasher(twox_64_concat) T::AccountId, hasher(twox_64_concat) LiquidityPoolId => MarginMode;
+
+		/// Positions opened in isolated margin mode.
+		IsolatedPositions get(fn isolated_position): map hasher(twox_64_concat) PositionId => Option<()>;
+
+		/// Traders opted in to position netting in a pool.
+		PositionNettings get(fn position_netting): double_map hasher(twox_64_concat) T::AccountId, hasher(twox_64_concat) LiquidityPoolId => Option<()>;
+
+		/// Next available pending order ID.
+		NextOrderId get(fn next_order_id): OrderId;
+
+		/// Pending orders, executed into positions once triggered.
+		PendingOrders get(fn pending_orders): map hasher(twox_64_concat) OrderId => Option<PendingOrder<T::AccountId>>;
+
+		/// Filled RFQ quotes, by pool and quote nonce.
+		FilledQuotes get(fn filled_quotes): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) u64 => Option<()>;
+
+		/// Trading keys authorized by a trader, by trader and key.
+		TradingKeys get(fn trading_keys): double_map hasher(twox_64_concat) T::AccountId, hasher(twox_64_concat) T::AccountId => Option<TradingKey<T::BlockNumber>>;
+
+		/// Next meta-transaction nonce of a trader.
+		MetaTransactionNonces get(fn meta_transaction_nonces): map hasher(twox_64_concat) T::AccountId => u64;
+
+		/// Number of meta-transactions a relayer is allowed to relay.
+		RelayerAllowances get(fn relayer_allowances): map hasher(twox_64_concat) T::AccountId => u32;
+
+		/// Haircuts of currencies accepted as collateral. A currency is accepted only if its haircut is set.
+		///
+		/// Collateral is valued at oracle price discounted by the haircut, and valued as zero once its currency is
+		/// no longer accepted.
+		CollateralHaircuts get(fn collateral_haircut): map hasher(twox_64_concat) CurrencyId => Option<Permill>;
+
+		/// Collateral locked by a trader in a liquidity pool, by `(pool_id, currency_id)`.
+		///
+		/// Counted in the trader's equity, and seized by the pool to repay the trader's negative balance once all
+		/// positions in the pool are closed.
+		Collaterals get(fn collaterals): double_map hasher(twox_64_concat) T::AccountId, hasher(twox_64_concat) (LiquidityPoolId, CurrencyId) => Balance;
+
+		/// Collateral seized by a liquidity pool from traders, claimable by the pool owner.
+		SeizedCollaterals get(fn seized_collaterals): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) CurrencyId => Balance;
+
+		/// Margin tiers of a trading pair by notional value of positions.
+		///
+		/// The matched tier with the highest threshold applies. If none matched, margin is held at the position
+		/// leverage.
+		MarginTiers get(fn margin_tiers): map hasher(twox_64_concat) TradingPair => Vec<MarginTier>;
+
+		/// Raw storage key of the last position settled in the current swap settlement pass, to continue from in the
+		/// next block.
+		///
+		/// A pass starts every `GetSwapSettlementInterval` blocks if none in progress, and settles positions in chunks
+		/// of at most `GetStopOutChunkSize` per block.
+		SwapSettlementCursor get(fn swap_settlement_cursor): Option<Vec<u8>>;
+
+		/// Risk threshold overrides of a trading pair in a liquidity pool.
+		///
+		/// An override only applies if the trading pair risk threshold is set, and may only tighten it.
+		PoolRiskThresholds get(fn pool_risk_thresholds): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) TradingPair => TradingPairRiskThreshold;
+
+		/// Circuit breakers of trading pairs.
+		CircuitBreakers get(fn circuit_breaker): map hasher(twox_64_concat) TradingPair => Option<CircuitBreaker<T::BlockNumber>>;
+
+		/// Reference oracle prices of circuit breakers, and the block numbers they are taken at.
+		CircuitBreakerReferences get(fn circuit_breaker_reference): map hasher(twox_64_concat) TradingPair => Option<(T::BlockNumber, Price)>;
+
+		/// Tripped circuit breakers, and the block numbers new positions are halted until.
+		///
+		/// Positions of a trading pair with a tripped circuit breaker could only be closed.
+		TrippedCircuitBreakers get(fn tripped_circuit_breaker): map hasher(twox_64_concat) TradingPair => Option<T::BlockNumber>;
+
+		/// Paused trading scopes, in close-only mode until resumed.
+		PausedTrading get(fn paused_trading): map hasher(twox_64_concat) TradingPauseScope => bool;
+
+		/// Fraction of each profitable position reduced by auto-deleveraging, before force closing an unsafe pool.
+		/// Pools are force closed right away if not set.
+		AutoDeleverageRate get(fn auto_deleverage_rate): Option<Permill>;
+
+		/// Options of trader tiers.
+		TraderTierOptions get(fn trader_tier_option): map hasher(twox_64_concat) TraderTier => TraderTierOption;
+
+		/// Open offers to transfer positions, and the accounts they are offered to.
+		PositionTransferOffers get(fn position_transfer_offer): map hasher(twox_64_concat) PositionId => Option<T::AccountId>;
+
+		/// Traders at warning risk in pools, so that `TraderWarned` is only emitted on reaching it.
+		WarnedTraders get(fn warned_traders): double_map hasher(twox_64_concat) T::AccountId, hasher(twox_64_concat) LiquidityPoolId => Option<()>;
+
+		/// Pools at warning risk, so that `LiquidityPoolWarned` is only emitted on reaching it.
+		WarnedPools get(fn warned_pools): map hasher(twox_64_concat) LiquidityPoolId => Option<()>;
+
+		/// Storage version, see `STORAGE_VERSION`.
+		StorageVersion get(fn storage_version): u32;
+
+		/// Raw storage key of the last trailing stop ratcheted, to continue from in the next block. The next block
+		/// starts from the first trailing stop if `None`.
+		TrailingStopsCursor get(fn trailing_stops_cursor): Option<Vec<u8>>;
+
+		/// Number of pending orders of a trader, at most `GetTraderMaxPendingOrders`.
+		TraderPendingOrders get(fn trader_pending_orders): map hasher(twox_64_concat) T::AccountId => u32;
+
+		/// Expiry blocks of pending orders, at which they are cancelled.
+		OrderExpiries get(fn order_expiry): map hasher(twox_64_concat) OrderId => Option<T::BlockNumber>;
+
+		/// Pending orders by expiry block, for cancelling them in `on_initialize`.
+		ExpiringOrders get(fn expiring_orders): double_map hasher(twox_64_concat) T::BlockNumber, hasher(twox_64_concat) OrderId => Option<()>;
+	}
+
+	add_extra_genesis {
+		config(risk_thresholds): Vec<(TradingPair, RiskThreshold, RiskThreshold, RiskThreshold)>;
+		config(force_close_penalty_tiers): Vec<ForceClosePenaltyTier>;
+		config(risk_candidate_buffer): Option<Permill>;
+		build(|config: &GenesisConfig| {
+			config.risk_thresholds.iter().for_each(|(pair, trader, enp, ell)| {
+				RiskThresholds::insert(
+					pair,
+					TradingPairRiskThreshold::new(Some(*trader), Some(*enp), Some(*ell)),
+				);
+			});
+			ForceClosePenaltyTiers::put(&config.force_close_penalty_tiers);
+			if let Some(buffer) = config.risk_candidate_buffer {
+				RiskCandidateBuffer::put(buffer);
+			}
+			StorageVersion::put(STORAGE_VERSION);
+		})
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where
+		<T as frame_system::Trait>::AccountId,
+		<T as frame_system::Trait>::BlockNumber,
+		LiquidityPoolId = LiquidityPoolId,
+		TradingPair = TradingPair,
+		CurrencyId = CurrencyId,
+		Amount = Balance
+	{
+		/// Position opened: [who, position_id, pool_id, pair, leverage, leveraged_amount, open_price, commission,
+		/// balance]
+		///
+		/// `open_price` is the execution price with spread, and `balance` the trader's balance after opening.
+		PositionOpened(AccountId, PositionId, LiquidityPoolId, TradingPair, Leverage, Amount, Price, Amount, FixedI128),
+
+		/// Position closed: [who, position_id, pool_id, close_price, commission, accumulated_swap, realized_pl,
+		/// balance]
+		///
+		/// `close_price` is the execution price with spread, `realized_pl` includes the accumulated swap, and
+		/// `balance` is the trader's balance after closing.
+		PositionClosed(AccountId, PositionId, LiquidityPoolId, Price, Amount, FixedI128, FixedI128, FixedI128),
+
+		/// Position partially closed: [who, position_id, pool_id, fraction, close_price, commission,
+		/// accumulated_swap, realized_pl, balance]
+		PositionPartiallyClosed(
+			AccountId,
+			PositionId,
+			LiquidityPoolId,
+			Permill,
+			Price,
+			Amount,
+			FixedI128,
+			FixedI128,
+			FixedI128,
+		),
+
+		/// Position increased by netting an opened position into it: [who, position_id, leveraged_amount, open_price,
+		/// commission, balance]
+		PositionIncreased(AccountId, PositionId, Amount, Price, Amount, FixedI128),
+
+		/// All positions of a trader in a pool closed: [who, pool_id, count]
+		AllPositionsClosed(AccountId, LiquidityPoolId, u32),
+
+		/// Position reversed: [who, position_id, new_position_id]
+		PositionReversed(AccountId, PositionId, PositionId),
+
+		/// Deposited: [who, pool_id, amount]
+		Deposited(AccountId, LiquidityPoolId, Amount),
+
+		/// Margin deposited to a position: [who, position_id, amount]
+		PositionMarginDeposited(AccountId, PositionId, Amount),
+
+		/// Position transferred: [who, position_id, dest]
+		PositionTransferred(AccountId, PositionId, AccountId),
+
+		/// Margin mode set: [who, pool_id, mode]
+		MarginModeSet(AccountId, LiquidityPoolId, MarginMode),
+
+		/// Position netting opted in or out: [who, pool_id, enabled]
+		PositionNettingSet(AccountId, LiquidityPoolId, bool),
+
+		/// Withdrew: [who, pool_id, amount]
+		Withdrew(AccountId, LiquidityPoolId, Amount),
+
+		/// Trader margin called: [who]
+		TraderMarginCalled(AccountId),
+
+		/// Trader became safe: [who]
+		TraderBecameSafe(AccountId),
+
+		/// Trader stopped out: [who]
+		TraderStoppedOut(AccountId),
+
+		/// Position closed in a trader stop out: [who, position_id, close_price]
+		PositionLiquidated(AccountId, PositionId, Price),
+
+		/// Isolated position stopped out: [who, position_id]
+		IsolatedPositionStoppedOut(AccountId, PositionId),
+
+		/// Liquidity pool margin called: [pool_id]
+		LiquidityPoolMarginCalled(LiquidityPoolId),
+
+		/// Liquidity pool became safe: [pool_id]
 		LiquidityPoolBecameSafe(LiquidityPoolId),
 
+		/// Keeper reward set: [reward]
+		KeeperRewardSet(Option<KeeperReward>),
+
+		/// Keeper rewarded for a liquidation call: [keeper, amount]
+		KeeperRewardPaid(AccountId, Amount),
+
 		/// Liquidity pool force closed: [pool_id]
 		LiquidityPoolForceClosed(LiquidityPoolId),
 
+		/// Trader stop out to be continued in following blocks: [who, pool_id, remaining_positions]
+		TraderStopOutPending(AccountId, LiquidityPoolId, u32),
+
+		/// Pending trader stop out finished: [who, pool_id]
+		TraderStopOutFinished(AccountId, LiquidityPoolId),
+
+		/// Liquidity pool force closure to be continued in following blocks: [pool_id, remaining_positions]
+		LiquidityPoolForceClosePending(LiquidityPoolId, u32),
+
+		/// Pending liquidity pool force closure finished: [pool_id]
+		LiquidityPoolForceCloseFinished(LiquidityPoolId),
+
+		/// Liquidity pool penalized on force closing a position: [pool_id, position_id, trader_compensation, penalty_amount]
+		LiquidityPoolPenalized(LiquidityPoolId, PositionId, Amount, Amount),
+
+		/// Force closure penalty tiers set: [tiers]
+		ForceClosePenaltyTiersSet(Vec<ForceClosePenaltyTier>),
+
+		/// Fee split of a liquidity pool set: [pool_id, split]
+		FeeSplitSet(LiquidityPoolId, Option<FeeSplit>),
+
+		/// Velocity limit of an account set: [who, limit]
+		VelocityLimitSet(AccountId, Option<VelocityLimit<BlockNumber>>),
+
+		/// Global velocity limit set: [limit]
+		GlobalVelocityLimitSet(Option<VelocityLimit<BlockNumber>>),
+
+		/// Risk candidate buffer set: [buffer]
+		RiskCandidateBufferSet(Option<Permill>),
+
+		/// Copy trading started: [follower, leader, pool_id]
+		CopyTradingStarted(AccountId, AccountId, LiquidityPoolId),
+
+		/// Copy trading stopped: [follower, leader, pool_id]
+		CopyTradingStopped(AccountId, AccountId, LiquidityPoolId),
+
+		/// Leader fee share set: [leader, fee_share]
+		LeaderFeeShareSet(AccountId, Permill),
+
+		/// Position copied: [follower, position_id, leader, leader_position_id]
+		PositionCopied(AccountId, PositionId, AccountId, PositionId),
+
+		/// Leader fee paid from realized profit of a copied position: [follower, leader, pool_id, amount]
+		LeaderFeePaid(AccountId, AccountId, LiquidityPoolId, Amount),
+
+		/// Trader reached warning risk threshold: [who, pool_id]
+		TraderWarned(AccountId, LiquidityPoolId),
+
+		/// Liquidity pool reached warning risk threshold: [pool_id]
+		LiquidityPoolWarned(LiquidityPoolId),
+
 		/// Trading pair risk threshold set: [pair, trader_risk_threshold, liquidity_pool_enp_threshold, liquidity_pool_ell_threshold]
 		TradingPairRiskThresholdSet(TradingPair, Option<RiskThreshold>, Option<RiskThreshold>, Option<RiskThreshold>),
+
+		/// Collateral deposited: [who, pool_id, currency_id, amount]
+		CollateralDeposited(AccountId, LiquidityPoolId, CurrencyId, Amount),
+
+		/// Collateral withdrew: [who, pool_id, currency_id, amount]
+		CollateralWithdrew(AccountId, LiquidityPoolId, CurrencyId, Amount),
+
+		/// Collateral seized to repay negative balance: [who, pool_id, currency_id, amount, repaid]
+		CollateralSeized(AccountId, LiquidityPoolId, CurrencyId, Amount, Amount),
+
+		/// Negative balance of a trader written off, with the loss absorbed by the pool: [who, pool_id, amount]
+		NegativeBalanceSocialized(AccountId, LiquidityPoolId, Amount),
+
+		/// Seized collateral claimed by pool owner: [who, pool_id, currency_id, amount]
+		SeizedCollateralClaimed(AccountId, LiquidityPoolId, CurrencyId, Amount),
+
+		/// Collateral haircut set: [currency_id, haircut]
+		CollateralHaircutSet(CurrencyId, Option<Permill>),
+
+		/// RFQ quote filled: [who, position_id, pool_id, nonce]
+		QuoteFilled(AccountId, PositionId, LiquidityPoolId, u64),
+
+		/// Meta-transaction relayed: [relayer, trader, nonce]
+		MetaTransactionRelayed(AccountId, AccountId, u64),
+
+		/// Relayer allowance set: [relayer, allowance]
+		RelayerAllowanceSet(AccountId, u32),
+
+		/// Trading key authorized: [who, key, expiry, notional_limit]
+		TradingKeyAuthorized(AccountId, AccountId, BlockNumber, Balance),
+
+		/// Trading key revoked: [who, key]
+		TradingKeyRevoked(AccountId, AccountId),
+
+		/// Stop out of a position disputed: [who, position_id]
+		StopOutDisputed(AccountId, PositionId),
+
+		/// Stop out dispute resolved: [position_id, compensation]
+		StopOutDisputeResolved(PositionId, Option<Amount>),
+
+		/// Stop out compensation claimed: [who, position_id, amount]
+		StopOutCompensationClaimed(AccountId, PositionId, Amount),
+
+		/// Slippage insurance premium rate set: [pair, rate]
+		SlippageInsurancePremiumRateSet(TradingPair, Option<Permill>),
+
+		/// Slippage insurance opted in or out: [who, pool_id, insured]
+		SlippageInsuranceSet(AccountId, LiquidityPoolId, bool),
+
+		/// Slippage insurance premium paid on closing a position: [who, position_id, amount]
+		SlippageInsurancePremiumPaid(AccountId, PositionId, Amount),
+
+		/// Slippage insurance claim paid on stopping out a position: [who, position_id, amount]
+		SlippageInsuranceClaimPaid(AccountId, PositionId, Amount),
+
+		/// Stop loss price of a position set: [who, position_id, price]
+		StopLossSet(AccountId, PositionId, Option<Price>),
+
+		/// Position closed as its stop loss price is reached: [who, position_id, fill_price]
+		StopLossTriggered(AccountId, PositionId, Price),
+
+		/// Take profit price of a position set: [who, position_id, price]
+		TakeProfitSet(AccountId, PositionId, Option<Price>),
+
+		/// Position closed as its take profit price is reached: [who, position_id, fill_price]
+		TakeProfitTriggered(AccountId, PositionId, Price),
+
+		/// Trailing stop of a position set: [who, position_id, distance]
+		TrailingStopSet(AccountId, PositionId, Option<TrailingDistance>),
+
+		/// Position opened with an expiry block: [who, position_id, expiry]
+		PositionExpirySet(AccountId, PositionId, BlockNumber),
+
+		/// Position closed at market on expiry: [who, position_id, close_price]
+		PositionExpired(AccountId, PositionId, Price),
+
+		/// Pending order placed: [who, order_id, pool_id, pair, leverage, leveraged_amount, kind, trigger_price]
+		OrderPlaced(AccountId, OrderId, LiquidityPoolId, TradingPair, Leverage, Amount, OrderKind, Price),
+
+		/// Pending order cancelled: [who, order_id]
+		OrderCancelled(AccountId, OrderId),
+
+		/// Pending order executed: [who, order_id, position_id]
+		OrderExecuted(AccountId, OrderId, PositionId),
+
+		/// Margin tiers of a trading pair set: [pair, tiers]
+		MarginTiersSet(TradingPair, Vec<MarginTier>),
+
+		/// Accrued swap of a position settled into the balance: [who, position_id, amount]. Positive if received by
+		/// the trader.
+		SwapSettled(AccountId, PositionId, FixedI128),
+
+		/// Risk threshold overrides of a trading pair in a liquidity pool set: [pool_id, pair, threshold]
+		PoolRiskThresholdSet(LiquidityPoolId, TradingPair, TradingPairRiskThreshold),
+
+		/// Circuit breaker of a trading pair set: [pair, circuit_breaker]
+		CircuitBreakerSet(TradingPair, Option<CircuitBreaker<BlockNumber>>),
+
+		/// Circuit breaker of a trading pair tripped: [pair, price, halted_until]
+		CircuitBreakerTripped(TradingPair, Price, BlockNumber),
+
+		/// Tripped circuit breaker of a trading pair reset: [pair]
+		CircuitBreakerReset(TradingPair),
+
+		/// Trading paused, positions could only be closed: [scope]
+		TradingPaused(TradingPauseScope),
+
+		/// Paused trading resumed: [scope]
+		TradingResumed(TradingPauseScope),
+
+		/// Auto-deleverage rate set: [rate]
+		AutoDeleverageRateSet(Option<Permill>),
+
+		/// Position auto-deleveraged on an unsafe pool: [who, position_id, pool_id, fraction, deleveraged_held]
+		PositionAutoDeleveraged(AccountId, PositionId, LiquidityPoolId, Permill, FixedI128),
+
+		/// Liquidity pool became safe by auto-deleveraging, without force closure: [pool_id, deleveraged_positions]
+		LiquidityPoolAutoDeleveraged(LiquidityPoolId, u32),
+
+		/// Trader tier option set: [tier, option]
+		TraderTierOptionSet(TraderTier, TraderTierOption),
+
+		/// Position transfer offered: [who, position_id, dest]
+		PositionTransferProposed(AccountId, PositionId, AccountId),
+
+		/// Pending order placed with an expiry block: [who, order_id, expiry]
+		OrderExpirySet(AccountId, OrderId, BlockNumber),
+
+		/// Pending order cancelled on expiry: [who, order_id]
+		OrderExpired(AccountId, OrderId),
 	}
 }
 
@@ -272,6 +1388,18 @@ decl_error! {
 		/// No price from provider.
 		NoPrice,
 
+		/// Price expired.
+		StalePrice,
+
+		/// Not enough oracle operators fed the price.
+		BelowOracleQuorum,
+
+		/// Market of the price is halted.
+		MarketHalted,
+
+		/// Market of the trading pair is closed.
+		MarketClosed,
+
 		/// Ask spread not set.
 		NoAskSpread,
 
@@ -287,6 +1415,21 @@ decl_error! {
 		/// Number out of bound in calculation.
 		NumOutOfBound,
 
+		/// Arithmetic overflow in profit and loss, balance or equity calculation.
+		ArithmeticOverflow,
+
+		/// Unrealized profit and loss overflow.
+		UnrealizedPlOverflow,
+
+		/// A negative amount converted to balance.
+		BalanceUnderflow,
+
+		/// A balance too large to convert to amount.
+		BalanceOverflow,
+
+		/// Accumulated swap rate overflow.
+		AccumulatedSwapRateOverflow,
+
 		/// Trader is not safe.
 		UnsafeTrader,
 
@@ -320,6 +1463,21 @@ decl_error! {
 		/// Position is not opened by caller.
 		PositionNotOpenedByTrader,
 
+		/// Close fraction must be more than zero and less than the whole position.
+		InvalidCloseFraction,
+
+		/// Expiry block must be after the current block.
+		InvalidExpiry,
+
+		/// More positions than a trader could open.
+		TooManyPositions,
+
+		/// Position not opened in isolated margin mode.
+		PositionNotIsolated,
+
+		/// Copy trading positions, of leaders or followers, can't be transferred.
+		CopyTradingPositionNotTransferable,
+
 		/// Leverage not allowed in pool,
 		LeverageNotAllowedInPool,
 
@@ -332,7 +1490,10 @@ decl_error! {
 		/// Leveraged amount is below mininum,
 		BelowMinLeveragedAmount,
 
-		/// Positions count reached maximum.
+		/// Backstop pool not active for the trading pair,
+		BackstopNotActive,
+
+		/// Open positions of the pool on the trading pair reached `GetPoolMaxOpenPositions`.
 		CannotOpenMorePosition,
 
 		/// Insufficient free margin.
@@ -340,6 +1501,147 @@ decl_error! {
 
 		/// Risk threshold not set.
 		NoRiskThreshold,
+
+		/// Deposit amount exceeded velocity limit.
+		DepositVelocityLimitExceeded,
+
+		/// Withdraw amount exceeded velocity limit.
+		WithdrawVelocityLimitExceeded,
+
+		/// Cannot copy positions of oneself.
+		CannotFollowSelf,
+
+		/// Already copying positions in the pool.
+		AlreadyCopyTrading,
+
+		/// Not copying positions in the pool.
+		NotCopyTrading,
+
+		/// Followers count of the leader reached maximum.
+		TooManyCopyFollowers,
+
+		/// Currency not accepted as collateral.
+		CollateralNotAccepted,
+
+		/// Insufficient collateral.
+		InsufficientCollateral,
+
+		/// Caller is not the pool owner.
+		NotPoolOwner,
+
+		/// Quote is for another trader.
+		QuoteNotForTrader,
+
+		/// Quote expired.
+		QuoteExpired,
+
+		/// Quote already filled.
+		QuoteAlreadyFilled,
+
+		/// Quote not signed by the pool owner.
+		InvalidQuoteSignature,
+
+		/// Meta-transaction expired.
+		MetaTransactionExpired,
+
+		/// Meta-transaction nonce is not the next nonce of the trader.
+		InvalidMetaTransactionNonce,
+
+		/// Meta-transaction not signed by the trader.
+		InvalidMetaTransactionSignature,
+
+		/// Relayer has no allowance left.
+		NoRelayerAllowance,
+
+		/// Trading key not authorized by the trader.
+		TradingKeyNotFound,
+
+		/// Trading key expired.
+		TradingKeyExpired,
+
+		/// Cumulative notional of positions opened by the trading key is over its limit.
+		TradingKeyLimitExceeded,
+
+		/// Stop out record not found, or out of dispute window.
+		StopOutRecordNotFound,
+
+		/// Stop out dispute not found.
+		StopOutDisputeNotFound,
+
+		/// Stop out compensation not found.
+		StopOutCompensationNotFound,
+
+		/// Stop loss price of the position not set.
+		StopLossNotSet,
+
+		/// Stop loss price of the position not reached.
+		StopLossNotReached,
+
+		/// Take profit price of the position not set.
+		TakeProfitNotSet,
+
+		/// Take profit price of the position not reached.
+		TakeProfitNotReached,
+
+		/// No available pending order ID.
+		NoAvailableOrderId,
+
+		/// Pending order not found.
+		OrderNotFound,
+
+		/// Pending order not placed by trader.
+		OrderNotPlacedByTrader,
+
+		/// Trigger price of the pending order not reached.
+		OrderNotTriggered,
+
+		/// Open positions of the trader in the pool reached `GetTraderMaxOpenPositions`.
+		TraderPositionsLimitReached,
+
+		/// The current block is past `valid_until` of the transaction.
+		TransactionOutdated,
+
+		/// Leverage is above the max leverage allowed by the pool for the trader's tier.
+		LeverageAboveTraderMax,
+
+		/// Max leverage of a margin tier is zero.
+		InvalidMarginTier,
+
+		/// Risk threshold override is looser than the trading pair risk threshold.
+		PoolRiskThresholdTooLoose,
+
+		/// Circuit breaker of the trading pair tripped, positions could only be closed.
+		CircuitBreakerTripped,
+
+		/// Trading of the protocol, pool or trading pair is paused, positions could only be closed.
+		TradingPaused,
+
+		/// Trading of the scope is already paused.
+		TradingAlreadyPaused,
+
+		/// Trading of the scope is not paused.
+		TradingNotPaused,
+
+		/// Leveraged amount is above maximum.
+		AboveMaxLeveragedAmount,
+
+		/// Leverage is above the max leverage of the trader's tier.
+		LeverageAboveTierMax,
+
+		/// The position transfer is not offered to the caller.
+		NoPositionTransferOffer,
+
+		/// The owner's balance would be negative after the margin held of the position is transferred.
+		InsufficientBalanceForTransfer,
+
+		/// Auto-deleverage rate is not between zero and one, both exclusive.
+		InvalidAutoDeleverageRate,
+
+		/// Pending orders of the trader reached `GetTraderMaxPendingOrders`.
+		TraderPendingOrdersLimitReached,
+
+		/// The pending order expired.
+		OrderExpired,
 	}
 }
 
@@ -350,6 +1652,28 @@ impl<T: Trait> From<OpenPositionError> for Error<T> {
 			OpenPositionError::TradingPairNotEnabled => Error::<T>::TradingPairNotEnabled,
 			OpenPositionError::TradingPairNotEnabledInPool => Error::<T>::TradingPairNotEnabledInPool,
 			OpenPositionError::BelowMinLeveragedAmount => Error::<T>::BelowMinLeveragedAmount,
+			OpenPositionError::BackstopNotActive => Error::<T>::BackstopNotActive,
+			OpenPositionError::AboveMaxLeveragedAmount => Error::<T>::AboveMaxLeveragedAmount,
+		}
+	}
+}
+
+impl<T: Trait> From<BalanceConversionError> for Error<T> {
+	fn from(error: BalanceConversionError) -> Self {
+		match error {
+			BalanceConversionError::Underflow => Error::<T>::BalanceUnderflow,
+			BalanceConversionError::Overflow => Error::<T>::BalanceOverflow,
+		}
+	}
+}
+
+impl<T: Trait> From<PriceUnavailableReason> for Error<T> {
+	fn from(reason: PriceUnavailableReason) -> Self {
+		match reason {
+			PriceUnavailableReason::NoPrice => Error::<T>::NoPrice,
+			PriceUnavailableReason::StalePrice => Error::<T>::StalePrice,
+			PriceUnavailableReason::BelowOracleQuorum => Error::<T>::BelowOracleQuorum,
+			PriceUnavailableReason::MarketHalted => Error::<T>::MarketHalted,
 		}
 	}
 }
@@ -361,12 +1685,30 @@ decl_module! {
 		fn deposit_event() = default;
 
 		const GetTreasuryAccountId: T::AccountId = T::GetTreasuryAccountId::get();
+		const GetInsuranceFundAccountId: T::AccountId = T::GetInsuranceFundAccountId::get();
+		const GetStopOutDisputeWindow: T::BlockNumber = T::GetStopOutDisputeWindow::get();
+		const GetSlippageInsurancePremiumPeriod: T::BlockNumber = T::GetSlippageInsurancePremiumPeriod::get();
 		const GetTraderMaxOpenPositions: u32 = T::GetTraderMaxOpenPositions::get() as u32;
 		const GetPoolMaxOpenPositions: u32 = T::GetPoolMaxOpenPositions::get() as u32;
 		const UnsignedPriority: TransactionPriority = T::UnsignedPriority::get();
+		const GetEnpEllSnapshotInterval: T::BlockNumber = T::GetEnpEllSnapshotInterval::get();
+		const GetMaxEnpEllSnapshots: u32 = T::GetMaxEnpEllSnapshots::get();
+		const GetSwapSettlementInterval: T::BlockNumber = T::GetSwapSettlementInterval::get();
+		const GetMaxCopyFollowers: u32 = T::GetMaxCopyFollowers::get();
+		const GetStopOutChunkSize: u32 = T::GetStopOutChunkSize::get();
+		const GetMaxTradeHistory: u32 = T::GetMaxTradeHistory::get();
+		const GetTraderMaxPendingOrders: u32 = T::GetTraderMaxPendingOrders::get();
 
 		/// Open a position in `pool_id`.
-		#[weight = 20_000]
+		///
+		/// `price` bounds the open price against slippage: the max ask price for longs, or the min bid price for
+		/// shorts. Opening fails with `MarketPriceTooHigh` or `MarketPriceTooLow` if the market moved past it. If
+		/// `valid_until` is set, opening fails after that block, so that a stale transaction doesn't open late.
+		///
+		/// Weight scales with the max open positions of a trader, which are counted against the limit and iterated
+		/// in equity and margin level calculations.
+		#[weight = 20_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ Module::<T>::copy_followers_weight() + Module::<T>::warnings_weight()]
 		pub fn open_position(
 			origin,
 			#[compact] pool_id: LiquidityPoolId,
@@ -374,179 +1716,1465 @@ decl_module! {
 			leverage: Leverage,
 			#[compact] leveraged_amount: Balance,
 			price: Price,
+			valid_until: Option<T::BlockNumber>,
 		) {
 			with_transaction_result(|| {
 				let who = ensure_signed(origin)?;
-				Self::do_open_position(&who, pool_id, pair, leverage, leveraged_amount, price)?;
+				Self::ensure_valid_until(valid_until)?;
+				Self::with_price_cache(|| {
+					Self::do_open_position(&who, pool_id, pair, leverage, leveraged_amount, price, None)
+				})?;
+				Self::update_risk_candidates(&who, pool_id);
 				Ok(())
 			})?;
 		}
 
-		/// Close position by id.
-		#[weight = 20_000]
-		pub fn close_position(origin, #[compact] position_id: PositionId, price: Price) {
+		/// Open a position in `pool_id` as in `open_position`, closed at market by the protocol after `expiry` block.
+		#[weight = 30_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ Module::<T>::copy_followers_weight() + Module::<T>::warnings_weight()]
+		pub fn open_position_with_expiry(
+			origin,
+			#[compact] pool_id: LiquidityPoolId,
+			pair: TradingPair,
+			leverage: Leverage,
+			#[compact] leveraged_amount: Balance,
+			price: Price,
+			expiry: T::BlockNumber,
+		) {
 			with_transaction_result(|| {
 				let who = ensure_signed(origin)?;
-				Self::do_close_position(&who, position_id, Some(price))?;
+				ensure!(expiry > <frame_system::Module<T>>::block_number(), Error::<T>::InvalidExpiry);
+				let (id, _) = Self::with_price_cache(|| {
+					Self::do_open_position(&who, pool_id, pair, leverage, leveraged_amount, price, Some(expiry))
+				})?;
+				Self::update_risk_candidates(&who, pool_id);
+				Self::deposit_event(RawEvent::PositionExpirySet(who, id, expiry));
 				Ok(())
 			})?;
 		}
 
-		/// Deposit liquidity to caller's account.
-		#[weight = 10_000]
-		pub fn deposit(origin, #[compact] pool_id: LiquidityPoolId, #[compact] amount: Balance) {
-			with_transaction_result(|| {
+		/// Fill an RFQ quote signed by `signer`, the owner of the quoted pool, opening a position at the quoted price.
+		///
+		/// The posted spread doesn't apply, but other open position checks do.
+		#[weight = 30_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ Module::<T>::warnings_weight()]
+		pub fn fill_quote(
+			origin,
+			quote: Quote<T::AccountId, T::BlockNumber>,
+			signer: T::AccountId,
+			signature: T::QuoteSignature,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let pool_id = quote.pool;
+				Self::with_price_cache(|| Self::do_fill_quote(&who, quote, &signer, signature))?;
+				Self::update_risk_candidates(&who, pool_id);
+				Ok(())
+			})?;
+		}
+
+		/// Relay a meta-transaction signed by its trader, paying the transaction fee for the trader.
+		///
+		/// Uses up one of the relayer's allowance. Weight covers the heaviest trading intent.
+		#[weight = 30_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ Module::<T>::copy_followers_weight() + Module::<T>::warnings_weight()]
+		pub fn relay(
+			origin,
+			meta_transaction: MetaTransaction<T::AccountId, T::BlockNumber>,
+			signature: T::QuoteSignature,
+		) {
+			with_transaction_result(|| {
+				let relayer = ensure_signed(origin)?;
+				Self::do_relay(&relayer, meta_transaction, signature)
+			})?;
+		}
+
+		/// Close position by id.
+		///
+		/// `price` bounds the close price against slippage: the min bid price for longs, or the max ask price for
+		/// shorts. If `valid_until` is set, closing fails after that block.
+		///
+		/// Weight scales with the max open positions of a trader, which are iterated in equity and margin level
+		/// calculations.
+		#[weight = 20_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ Module::<T>::copy_followers_weight() + Module::<T>::warnings_weight()]
+		pub fn close_position(
+			origin,
+			#[compact] position_id: PositionId,
+			price: Price,
+			valid_until: Option<T::BlockNumber>,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::ensure_valid_until(valid_until)?;
+				let pool_id = Self::pool_of_closable_position(position_id)?;
+				Self::with_price_cache(|| Self::do_close_position(&who, position_id, Some(price)))?;
+				Self::update_risk_candidates(&who, pool_id);
+				Ok(())
+			})?;
+		}
+
+		/// Close several positions by id, each bounded by its price as in `close_position`.
+		///
+		/// Prices are read once for all positions, and risk candidacy updated once per pool. Weight scales with the
+		/// number of positions, each as in `close_position`.
+		#[weight = (20_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ Module::<T>::copy_followers_weight() + Module::<T>::warnings_weight())
+			.saturating_mul(positions.len() as Weight)]
+		pub fn close_positions(origin, positions: Vec<(PositionId, Price)>) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				ensure!(
+					positions.len() <= T::GetTraderMaxOpenPositions::get(),
+					Error::<T>::TooManyPositions
+				);
+				let pool_ids = Self::with_price_cache(|| Self::do_close_positions(&who, &positions))?;
+				for pool_id in pool_ids {
+					Self::update_risk_candidates(&who, pool_id);
+				}
+				Ok(())
+			})?;
+		}
+
+		/// Close all positions of caller in `pool_id` at the market price, atomically.
+		///
+		/// Weight scales with the max open positions of a trader, each closed as in `close_position`.
+		#[weight = (20_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ Module::<T>::copy_followers_weight() + Module::<T>::warnings_weight())
+			.saturating_mul(T::GetTraderMaxOpenPositions::get() as Weight)]
+		pub fn close_all_positions(origin, #[compact] pool_id: LiquidityPoolId) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let count = Self::with_price_cache(|| Self::do_close_all_positions(&who, pool_id))?;
+				Self::update_risk_candidates(&who, pool_id);
+				Self::deposit_event(RawEvent::AllPositionsClosed(who, pool_id, count));
+				Ok(())
+			})?;
+		}
+
+		/// Close a position by id, and open one of the same leveraged amount in the opposite direction, atomically.
+		///
+		/// `price` bounds both the close and the open, which are at the same side of the spread. Weight scales as in
+		/// `close_position`.
+		#[weight = 40_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ 2 * Module::<T>::copy_followers_weight() + 2 * Module::<T>::warnings_weight()]
+		pub fn reverse_position(origin, #[compact] position_id: PositionId, price: Price) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let pool_id = Self::pool_of_closable_position(position_id)?;
+				let new_position_id = Self::with_price_cache(|| Self::do_reverse_position(&who, position_id, price))?;
+				Self::update_risk_candidates(&who, pool_id);
+				Self::deposit_event(RawEvent::PositionReversed(who, position_id, new_position_id));
+				Ok(())
+			})?;
+		}
+
+		/// Close `fraction` of a position by id, keeping the rest open.
+		///
+		/// Held, debits and margin held amounts are reduced proportionally, and the profit or loss of the closed
+		/// part is realized. Weight scales as in `close_position`.
+		#[weight = 20_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ Module::<T>::copy_followers_weight() + Module::<T>::warnings_weight()]
+		pub fn close_position_partial(
+			origin,
+			#[compact] position_id: PositionId,
+			fraction: Permill,
+			price: Price,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let pool_id = Self::pool_of_closable_position(position_id)?;
+				Self::with_price_cache(|| Self::do_close_position_partial(&who, position_id, fraction, Some(price)))?;
+				Self::update_risk_candidates(&who, pool_id);
+				Ok(())
+			})?;
+		}
+
+		/// Authorize `key` to open and close positions of the caller before `expiry`, opening at most
+		/// `notional_limit` of cumulative notional in USD. Replaces the existing authorization of `key`.
+		#[weight = 10_000]
+		pub fn authorize_trading_key(
+			origin,
+			key: <T::Lookup as StaticLookup>::Source,
+			expiry: T::BlockNumber,
+			#[compact] notional_limit: Balance,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let key = T::Lookup::lookup(key)?;
+				let trading_key = TradingKey {
+					expiry,
+					notional_limit,
+					notional_used: 0,
+				};
+				<TradingKeys<T>>::insert(&who, &key, trading_key);
+				Self::deposit_event(RawEvent::TradingKeyAuthorized(who, key, expiry, notional_limit));
+				Ok(())
+			})?;
+		}
+
+		/// Revoke the authorization of `key` to trade for the caller.
+		#[weight = 10_000]
+		pub fn revoke_trading_key(origin, key: <T::Lookup as StaticLookup>::Source) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let key = T::Lookup::lookup(key)?;
+				ensure!(<TradingKeys<T>>::contains_key(&who, &key), Error::<T>::TradingKeyNotFound);
+				<TradingKeys<T>>::remove(&who, &key);
+				Self::deposit_event(RawEvent::TradingKeyRevoked(who, key));
+				Ok(())
+			})?;
+		}
+
+		/// Open a position in `pool_id` for `trader`, with a trading key of the trader.
+		#[weight = 30_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ Module::<T>::copy_followers_weight() + Module::<T>::warnings_weight()]
+		pub fn open_position_with_key(
+			origin,
+			trader: <T::Lookup as StaticLookup>::Source,
+			#[compact] pool_id: LiquidityPoolId,
+			pair: TradingPair,
+			leverage: Leverage,
+			#[compact] leveraged_amount: Balance,
+			price: Price,
+		) {
+			with_transaction_result(|| {
+				let key = ensure_signed(origin)?;
+				let trader = T::Lookup::lookup(trader)?;
+				Self::with_price_cache(|| {
+					Self::do_open_position_with_key(&key, &trader, pool_id, pair, leverage, leveraged_amount, price)
+				})?;
+				Self::update_risk_candidates(&trader, pool_id);
+				Ok(())
+			})?;
+		}
+
+		/// Close position by id for `trader`, with a trading key of the trader.
+		#[weight = 20_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ Module::<T>::copy_followers_weight() + Module::<T>::warnings_weight()]
+		pub fn close_position_with_key(
+			origin,
+			trader: <T::Lookup as StaticLookup>::Source,
+			#[compact] position_id: PositionId,
+			price: Price,
+		) {
+			with_transaction_result(|| {
+				let key = ensure_signed(origin)?;
+				let trader = T::Lookup::lookup(trader)?;
+				Self::ensure_trading_key(&key, &trader)?;
+				let pool_id = Self::pool_of_closable_position(position_id)?;
+				Self::with_price_cache(|| Self::do_close_position(&trader, position_id, Some(price)))?;
+				Self::update_risk_candidates(&trader, pool_id);
+				Ok(())
+			})?;
+		}
+
+		/// Set the stop loss price of `position_id`, or `None` to cancel.
+		///
+		/// The position is closed at the market price once the oracle price reaches the stop loss price: falls to
+		/// it for long positions, or rises to it for short positions.
+		#[weight = 10_000]
+		pub fn set_stop_loss(origin, #[compact] position_id: PositionId, price: Option<Price>) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::do_set_stop_loss(&who, position_id, price)?;
+				Self::deposit_event(RawEvent::StopLossSet(who, position_id, price));
+				Ok(())
+			})?;
+		}
+
+		/// Set the take profit price of `position_id`, or `None` to cancel.
+		///
+		/// The position is closed at the market price once the oracle price reaches the take profit price: rises
+		/// to it for long positions, or falls to it for short positions.
+		#[weight = 10_000]
+		pub fn set_take_profit(origin, #[compact] position_id: PositionId, price: Option<Price>) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::do_set_take_profit(&who, position_id, price)?;
+				Self::deposit_event(RawEvent::TakeProfitSet(who, position_id, price));
+				Ok(())
+			})?;
+		}
+
+		/// Set a trailing stop of `distance` on `position_id`, or `None` to cancel it and its stop loss price.
+		///
+		/// The stop loss price is kept at `distance` from the best oracle price since the trailing stop is set, and
+		/// ratchets with favorable price moves each block. Setting a stop loss price cancels the trailing stop.
+		#[weight = 10_000]
+		pub fn set_trailing_stop(origin, #[compact] position_id: PositionId, distance: Option<TrailingDistance>) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::with_price_cache(|| Self::do_set_trailing_stop(&who, position_id, distance))?;
+				Self::deposit_event(RawEvent::TrailingStopSet(who, position_id, distance));
+				Ok(())
+			})?;
+		}
+
+		/// Place a pending order to open a position in `pool_id` once the oracle price satisfies `kind` on
+		/// `trigger_price`.
+		///
+		/// `price` is the price limit of opening the position, as in `open_position`. Open position checks apply on
+		/// execution, not on placing. A trader has at most `GetTraderMaxPendingOrders` pending orders.
+		#[weight = 10_000]
+		pub fn place_order(
+			origin,
+			#[compact] pool_id: LiquidityPoolId,
+			pair: TradingPair,
+			leverage: Leverage,
+			#[compact] leveraged_amount: Balance,
+			kind: OrderKind,
+			trigger_price: Price,
+			price: Price,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let order = PendingOrder {
+					owner: who.clone(),
+					pool: pool_id,
+					pair,
+					leverage,
+					leveraged_amount,
+					kind,
+					trigger_price,
+					price,
+				};
+				let order_id = Self::do_place_order(order, None)?;
+				Self::deposit_event(RawEvent::OrderPlaced(
+					who,
+					order_id,
+					pool_id,
+					pair,
+					leverage,
+					leveraged_amount,
+					kind,
+					trigger_price,
+				));
+				Ok(())
+			})?;
+		}
+
+		/// Cancel a pending order.
+		#[weight = 10_000]
+		pub fn cancel_order(origin, #[compact] order_id: OrderId) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::do_cancel_order(&who, order_id)?;
+				Self::deposit_event(RawEvent::OrderCancelled(who, order_id));
+				Ok(())
+			})?;
+		}
+
+		/// Deposit liquidity to caller's account.
+		///
+		/// Other currencies could fund the margin as collateral, see `deposit_collateral`.
+		#[weight = 10_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()]
+		pub fn deposit(origin, #[compact] pool_id: LiquidityPoolId, #[compact] amount: Balance) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::do_deposit(&who, pool_id, amount)?;
+				Self::update_risk_candidates(&who, pool_id);
+				Self::deposit_event(RawEvent::Deposited(who, pool_id, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Deposit liquidity to caller's account as margin of `position_id`.
+		///
+		/// Equity gains `amount` as in `deposit`, but it's held by the position, so free margin is unchanged.
+		#[weight = 10_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()]
+		pub fn deposit_position_margin(origin, #[compact] position_id: PositionId, #[compact] amount: Balance) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let pool_id = Self::do_deposit_position_margin(&who, position_id, amount)?;
+				Self::update_risk_candidates(&who, pool_id);
+				Self::deposit_event(RawEvent::PositionMarginDeposited(who, position_id, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Offer to transfer `position_id` of caller, and the margin it holds, to `dest`. The transfer happens once
+		/// `dest` accepts it. A new offer replaces the previous one.
+		#[weight = 10_000]
+		pub fn propose_position_transfer(origin, #[compact] position_id: PositionId, dest: T::AccountId) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+				ensure!(position.owner == who, Error::<T>::PositionNotOpenedByTrader);
+				Self::ensure_position_transferable(position_id)?;
+
+				<PositionTransferOffers<T>>::insert(position_id, &dest);
+				Self::deposit_event(RawEvent::PositionTransferProposed(who, position_id, dest));
+				Ok(())
+			})?;
+		}
+
+		/// Set the margin mode of caller in `pool_id`, applied to positions opened afterwards.
+		///
+		/// Margin held by isolated positions is excluded from the trader's equity, and the loss of closing one is
+		/// capped at its margin held.
+		#[weight = 10_000]
+		pub fn set_margin_mode(origin, #[compact] pool_id: LiquidityPoolId, mode: MarginMode) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				<MarginModes<T>>::insert(&who, pool_id, mode);
+				Self::deposit_event(RawEvent::MarginModeSet(who, pool_id, mode));
+				Ok(())
+			})?;
+		}
+
+		/// Opt in or out of position netting in `pool_id`.
+		///
+		/// While opted in, opening a position of the same trading pair and leverage as an open position of caller
+		/// in the pool increases the open position instead, at the weighted average open price.
+		#[weight = 10_000]
+		pub fn set_position_netting(origin, #[compact] pool_id: LiquidityPoolId, enabled: bool) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				if enabled {
+					<PositionNettings<T>>::insert(&who, pool_id, ());
+				} else {
+					<PositionNettings<T>>::remove(&who, pool_id);
+				}
+				Self::deposit_event(RawEvent::PositionNettingSet(who, pool_id, enabled));
+				Ok(())
+			})?;
+		}
+
+		/// Withdraw liquidity from caller's account.
+		///
+		/// Collateral value counts in free margin but isn't withdrawable as liquidity, see `withdraw_collateral`.
+		#[weight = 10_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ Module::<T>::warnings_weight()]
+		pub fn withdraw(origin, #[compact] pool_id: LiquidityPoolId, #[compact] amount: Balance) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::do_withdraw(&who, pool_id, amount)?;
+				Self::update_risk_candidates(&who, pool_id);
+				Self::deposit_event(RawEvent::Withdrew(who, pool_id, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Lock `amount` of `currency_id` as collateral in `pool_id`.
+		///
+		/// Collateral is counted in equity at oracle price discounted by the currency's haircut, but couldn't be
+		/// withdrawn as liquidity.
+		#[weight = 10_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()]
+		pub fn deposit_collateral(
+			origin,
+			#[compact] pool_id: LiquidityPoolId,
+			currency_id: CurrencyId,
+			#[compact] amount: Balance,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::do_deposit_collateral(&who, pool_id, currency_id, amount)?;
+				Self::update_risk_candidates(&who, pool_id);
+				Self::deposit_event(RawEvent::CollateralDeposited(who, pool_id, currency_id, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Unlock `amount` of `currency_id` collateral in `pool_id`, if free margin covers its collateral value.
+		///
+		/// Weight scales with the max open positions of a trader, which are iterated in free margin calculation.
+		#[weight = 10_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ Module::<T>::warnings_weight()]
+		pub fn withdraw_collateral(
+			origin,
+			#[compact] pool_id: LiquidityPoolId,
+			currency_id: CurrencyId,
+			#[compact] amount: Balance,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::with_price_cache(|| Self::do_withdraw_collateral(&who, pool_id, currency_id, amount))?;
+				Self::update_risk_candidates(&who, pool_id);
+				Self::deposit_event(RawEvent::CollateralWithdrew(who, pool_id, currency_id, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Claim all collateral of `currency_id` seized by `pool_id`.
+		///
+		/// May only be called by the pool owner.
+		#[weight = 10_000]
+		pub fn claim_seized_collateral(origin, #[compact] pool_id: LiquidityPoolId, currency_id: CurrencyId) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let amount = Self::do_claim_seized_collateral(&who, pool_id, currency_id)?;
+				Self::deposit_event(RawEvent::SeizedCollateralClaimed(who, pool_id, currency_id, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Set the collateral haircut of `currency_id`. `None` stops accepting the currency as collateral.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_collateral_haircut(origin, currency_id: CurrencyId, haircut: Option<Permill>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Self::collateral_haircut(currency_id);
+				match haircut {
+					Some(h) => CollateralHaircuts::insert(currency_id, h),
+					None => CollateralHaircuts::remove(currency_id),
+				}
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"CollateralHaircuts",
+					currency_id.encode(),
+					old.encode(),
+					haircut.encode(),
+				);
+				Self::deposit_event(RawEvent::CollateralHaircutSet(currency_id, haircut));
+				Ok(())
+			})?;
+		}
+
+		/// Copy positions of `leader` in `pool_id`, proportionally to the balances of the caller and the leader.
+		///
+		/// Leader's positions with a leverage higher than `max_leverage` are not copied, and copied leveraged
+		/// amount is capped by `max_leveraged_amount`.
+		#[weight = 10_000]
+		pub fn follow(
+			origin,
+			leader: <T::Lookup as StaticLookup>::Source,
+			#[compact] pool_id: LiquidityPoolId,
+			max_leverage: u8,
+			#[compact] max_leveraged_amount: Balance,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let leader = T::Lookup::lookup(leader)?;
+				Self::do_follow(&who, &leader, pool_id, max_leverage, max_leveraged_amount)?;
+				Self::deposit_event(RawEvent::CopyTradingStarted(who, leader, pool_id));
+				Ok(())
+			})?;
+		}
+
+		/// Stop copying positions in `pool_id`. Copied positions are kept open.
+		#[weight = 10_000]
+		pub fn unfollow(origin, #[compact] pool_id: LiquidityPoolId) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let leader = Self::do_unfollow(&who, pool_id)?;
+				Self::deposit_event(RawEvent::CopyTradingStopped(who, leader, pool_id));
+				Ok(())
+			})?;
+		}
+
+		/// Set the share of followers' realized profit on copied positions paid to caller. Only applies to
+		/// followers following afterwards.
+		#[weight = 10_000]
+		pub fn set_leader_fee_share(origin, #[compact] fee_share: Permill) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				<LeaderFeeShares<T>>::insert(&who, fee_share);
+				Self::deposit_event(RawEvent::LeaderFeeShareSet(who, fee_share));
+				Ok(())
+			})?;
+		}
+
+		/// Margin call a trader.
+		///
+		/// May only be called from none origin. Would fail if the trader is still safe.
+		#[weight = (
+			20_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight(),
+			DispatchClass::Operational
+		)]
+		pub fn trader_margin_call(
+			origin,
+			who: <T::Lookup as StaticLookup>::Source,
+			#[compact] pool_id: LiquidityPoolId
+		) {
+			with_transaction_result(|| {
+				ensure_none(origin)?;
+				let who = T::Lookup::lookup(who)?;
+
+				Self::do_trader_margin_call(&who, pool_id)?;
+				Self::update_risk_candidates(&who, pool_id);
+				Self::deposit_event(RawEvent::TraderMarginCalled(who));
+
+				Ok(())
+			})?;
+		}
+
+		/// Remove trader's margin-called status.
+		///
+		/// May only be called from none origin. Would fail if the trader is not safe yet.
+		#[weight = 20_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()]
+		pub fn trader_become_safe(
+			origin,
+			who: <T::Lookup as StaticLookup>::Source,
+			#[compact] pool_id: LiquidityPoolId
+		) {
+			with_transaction_result(|| {
+				ensure_none(origin)?;
+				let who = T::Lookup::lookup(who)?;
+
+				Self::do_trader_become_safe(&who, pool_id)?;
+				Self::update_risk_candidates(&who, pool_id);
+				Self::deposit_event(RawEvent::TraderBecameSafe(who));
+
+				Ok(())
+			})?;
+		}
+
+		/// Stop out a trader.
+		///
+		/// May be called from none origin, or signed by a keeper, who is paid the keeper reward by the trader. Would
+		/// fail if stop out threshold not reached.
+		///
+		/// Weight scales with the max open positions of a trader to sort, and the stop out chunk size to close.
+		#[weight = (
+			30_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+				+ Module::<T>::stop_out_chunk_weight(),
+			DispatchClass::Operational
+		)]
+		pub fn trader_stop_out(
+			origin,
+			who: <T::Lookup as StaticLookup>::Source,
+			#[compact] pool_id: LiquidityPoolId
+		) {
+			with_transaction_result(|| {
+				let keeper = Self::ensure_keeper(origin)?;
+				let who = T::Lookup::lookup(who)?;
+
+				Self::with_price_cache(|| -> DispatchResult {
+					let keeper = match keeper {
+						Some(k) => k,
+						None => return Self::do_trader_stop_out(&who, pool_id),
+					};
+					let notional = Self::notional_of_trader(&who, pool_id)?;
+					let positions = Self::trader_positions_count(&who, pool_id);
+					Self::do_trader_stop_out(&who, pool_id)?;
+					let liquidated = notional.saturating_sub(Self::notional_of_trader(&who, pool_id)?);
+					let closed = positions.saturating_sub(Self::trader_positions_count(&who, pool_id));
+					Self::pay_trader_keeper_reward(&keeper, &who, pool_id, liquidated, closed)
+				})?;
+				Self::deposit_event(RawEvent::TraderStoppedOut(who));
+
+				Ok(())
+			})?;
+		}
+
+		/// Stop out an isolated position.
+		///
+		/// May only be called from none origin. Would fail if its margin level didn't reach the stop out threshold.
+		#[weight = (
+			20_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+				+ Module::<T>::copy_followers_weight() + Module::<T>::warnings_weight(),
+			DispatchClass::Operational
+		)]
+		pub fn isolated_position_stop_out(origin, #[compact] position_id: PositionId) {
+			with_transaction_result(|| {
+				ensure_none(origin)?;
+				let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+				Self::with_price_cache(|| Self::do_isolated_position_stop_out(position_id, &position))?;
+				Self::update_risk_candidates(&position.owner, position.pool);
+				Self::deposit_event(RawEvent::IsolatedPositionStoppedOut(position.owner, position_id));
+				Ok(())
+			})?;
+		}
+
+		/// Close `position_id` as its stop loss price is reached.
+		///
+		/// May only be called from none origin.
+		#[weight = (
+			20_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+				+ Module::<T>::copy_followers_weight() + Module::<T>::warnings_weight(),
+			DispatchClass::Operational
+		)]
+		pub fn trigger_stop_loss(origin, #[compact] position_id: PositionId) {
+			with_transaction_result(|| {
+				ensure_none(origin)?;
+				let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+				let fill_price = Self::with_price_cache(|| Self::do_trigger_stop_loss(position_id, &position))?;
+				Self::update_risk_candidates(&position.owner, position.pool);
+				Self::deposit_event(RawEvent::StopLossTriggered(position.owner, position_id, fill_price));
+				Ok(())
+			})?;
+		}
+
+		/// Close `position_id` as its take profit price is reached.
+		///
+		/// May only be called from none origin.
+		#[weight = (
+			20_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+				+ Module::<T>::copy_followers_weight() + Module::<T>::warnings_weight(),
+			DispatchClass::Operational
+		)]
+		pub fn trigger_take_profit(origin, #[compact] position_id: PositionId) {
+			with_transaction_result(|| {
+				ensure_none(origin)?;
+				let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+				let fill_price = Self::with_price_cache(|| Self::do_trigger_take_profit(position_id, &position))?;
+				Self::update_risk_candidates(&position.owner, position.pool);
+				Self::deposit_event(RawEvent::TakeProfitTriggered(position.owner, position_id, fill_price));
+				Ok(())
+			})?;
+		}
+
+		/// Execute `order_id` into a position as its trigger price is reached.
+		///
+		/// May only be called from none origin.
+		#[weight = (
+			30_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+				+ Module::<T>::copy_followers_weight() + Module::<T>::warnings_weight(),
+			DispatchClass::Operational
+		)]
+		pub fn execute_order(origin, #[compact] order_id: OrderId) {
+			with_transaction_result(|| {
+				ensure_none(origin)?;
+				let order = Self::pending_orders(order_id).ok_or(Error::<T>::OrderNotFound)?;
+				let position_id = Self::with_price_cache(|| Self::do_execute_order(order_id, &order))?;
+				Self::update_risk_candidates(&order.owner, order.pool);
+				Self::deposit_event(RawEvent::OrderExecuted(order.owner, order_id, position_id));
+				Ok(())
+			})?;
+		}
+
+		/// Margin call a liquidity pool.
+		///
+		/// May only be called from none origin. Would fail if the pool still safe.
+		#[weight = (20_000 + Module::<T>::pool_risk_weight(), DispatchClass::Operational)]
+		pub fn liquidity_pool_margin_call(origin, #[compact] pool: LiquidityPoolId) {
+			with_transaction_result(|| {
+				ensure_none(origin)?;
+				Self::do_liquidity_pool_margin_call(pool)?;
+				Self::update_pool_risk_candidates(pool);
+				Self::deposit_event(RawEvent::LiquidityPoolMarginCalled(pool));
+				Ok(())
+			})?;
+		}
+
+		/// Remove a pool's margin-called status.
+		///
+		/// May only be called from none origin. Would fail if the pool is not safe yet.
+		#[weight = 20_000 + Module::<T>::pool_risk_weight()]
+		pub fn liquidity_pool_become_safe(origin, #[compact] pool: LiquidityPoolId) {
+			with_transaction_result(|| {
+				ensure_none(origin)?;
+				Self::do_liquidity_pool_become_safe(pool)?;
+				Self::update_pool_risk_candidates(pool);
+				Self::deposit_event(RawEvent::LiquidityPoolBecameSafe(pool));
+				Ok(())
+			})?;
+		}
+
+		/// Force close a liquidity pool.
+		///
+		/// If `AutoDeleverageRate` is set, profitable positions are reduced first, the most profitable first, until
+		/// the pool is safe. The remaining positions are force closed if it's still unsafe.
+		///
+		/// May be called from none origin, or signed by a keeper, who is paid the keeper reward by the pool. Would fail
+		/// if pool ENP or ELL thresholds not reached.
+		///
+		/// Weight scales with the max open positions of a pool to collect, and the stop out chunk size to close.
+		#[weight = (
+			30_000 + Module::<T>::pool_positions_weight() + Module::<T>::stop_out_chunk_weight()
+				+ Module::<T>::pool_risk_weight(),
+			DispatchClass::Operational
+		)]
+		pub fn liquidity_pool_force_close(origin, #[compact] pool: LiquidityPoolId) {
+			with_transaction_result(|| {
+				let keeper = Self::ensure_keeper(origin)?;
+				let force_closed = Self::with_price_cache(|| -> result::Result<bool, DispatchError> {
+					let keeper = match keeper {
+						Some(k) => k,
+						None => return Self::do_liquidity_pool_force_close(pool),
+					};
+					let notional = Self::notional_of_pool(pool)?;
+					let positions = Self::pool_positions_count(pool);
+					let force_closed = Self::do_liquidity_pool_force_close(pool)?;
+					let liquidated = notional.saturating_sub(Self::notional_of_pool(pool)?);
+					let closed = positions.saturating_sub(Self::pool_positions_count(pool));
+					Self::pay_pool_keeper_reward(&keeper, pool, liquidated, closed)?;
+					Ok(force_closed)
+				})?;
+				if force_closed {
+					Self::deposit_event(RawEvent::LiquidityPoolForceClosed(pool));
+				}
+				Ok(())
+			})?;
+		}
+
+		/// Re-evaluate risk candidacy of `who` in `pool_id`, and of the pool.
+		///
+		/// Risk candidacy is updated on trades, but not on price changes. Keepers could call this to track traders
+		/// and pools whose risk changed with prices.
+		#[weight = 10_000 + Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()]
+		pub fn update_risk_candidacy(
+			origin,
+			who: <T::Lookup as StaticLookup>::Source,
+			#[compact] pool_id: LiquidityPoolId
+		) {
+			with_transaction_result(|| {
+				ensure_signed(origin)?;
+				let who = T::Lookup::lookup(who)?;
+				Self::update_risk_candidates(&who, pool_id);
+				Ok(())
+			})?;
+		}
+
+		/// Set risk thresholds of a trading pair.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_trading_pair_risk_threshold(
+			origin,
+			pair: TradingPair,
+			trader: Option<RiskThreshold>,
+			enp: Option<RiskThreshold>,
+			ell: Option<RiskThreshold>
+		) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+
+				let old = Self::risk_thresholds(pair);
+				RiskThresholds::mutate(pair, |r| {
+					if trader.is_some() {
+						r.trader = trader;
+					}
+					if enp.is_some() {
+						r.enp = enp;
+					}
+					if ell.is_some() {
+						r.ell = ell;
+					}
+				});
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"RiskThresholds",
+					pair.encode(),
+					old.encode(),
+					Self::risk_thresholds(pair).encode(),
+				);
+
+				Self::deposit_event(RawEvent::TradingPairRiskThresholdSet(pair, trader, enp, ell));
+
+				Ok(())
+			})?;
+		}
+
+		/// Set force closure penalty tiers of liquidity pools.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_force_close_penalty_tiers(origin, tiers: Vec<ForceClosePenaltyTier>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Self::force_close_penalty_tiers();
+				ForceClosePenaltyTiers::put(tiers.clone());
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"ForceClosePenaltyTiers",
+					vec![],
+					old.encode(),
+					tiers.encode(),
+				);
+				Self::deposit_event(RawEvent::ForceClosePenaltyTiersSet(tiers));
+				Ok(())
+			})?;
+		}
+
+		/// Set the fraction of each profitable position reduced by auto-deleveraging of unsafe pools. `None` to force
+		/// close unsafe pools right away.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_auto_deleverage_rate(origin, rate: Option<Permill>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				if let Some(r) = rate {
+					ensure!(r > Permill::zero() && r < Permill::one(), Error::<T>::InvalidAutoDeleverageRate);
+				}
+				let old = Self::auto_deleverage_rate();
+				AutoDeleverageRate::set(rate);
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"AutoDeleverageRate",
+					vec![],
+					old.encode(),
+					rate.encode(),
+				);
+				Self::deposit_event(RawEvent::AutoDeleverageRateSet(rate));
+				Ok(())
+			})?;
+		}
+
+		/// Set the split of force closure penalties of a liquidity pool. `None` to send all to the treasury.
+		///
+		/// There is no keeper on force closure, and the keeper share goes to the treasury. The pool owner share is
+		/// not charged from the pool.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_fee_split(origin, #[compact] pool_id: LiquidityPoolId, split: Option<FeeSplit>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Self::fee_splits(pool_id);
+				match split {
+					Some(s) => FeeSplits::insert(pool_id, s),
+					None => FeeSplits::remove(pool_id),
+				}
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"FeeSplits",
+					pool_id.encode(),
+					old.encode(),
+					split.encode(),
+				);
+				Self::deposit_event(RawEvent::FeeSplitSet(pool_id, split));
+				Ok(())
+			})?;
+		}
+
+		/// Set the number of meta-transactions `relayer` is allowed to relay.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_relayer_allowance(origin, relayer: <T::Lookup as StaticLookup>::Source, allowance: u32) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let relayer = T::Lookup::lookup(relayer)?;
+				let old = Self::relayer_allowances(&relayer);
+				<RelayerAllowances<T>>::insert(&relayer, allowance);
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"RelayerAllowances",
+					relayer.encode(),
+					old.encode(),
+					allowance.encode(),
+				);
+				Self::deposit_event(RawEvent::RelayerAllowanceSet(relayer, allowance));
+				Ok(())
+			})?;
+		}
+
+		/// Set deposit and withdraw velocity limit of an account. `None` to remove.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_velocity_limit(
+			origin,
+			who: <T::Lookup as StaticLookup>::Source,
+			limit: Option<VelocityLimit<T::BlockNumber>>,
+		) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let who = T::Lookup::lookup(who)?;
+				let old = Self::velocity_limits(&who);
+				match limit {
+					Some(l) => <VelocityLimits<T>>::insert(&who, l),
+					None => <VelocityLimits<T>>::remove(&who),
+				}
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"VelocityLimits",
+					who.encode(),
+					old.encode(),
+					limit.encode(),
+				);
+				Self::deposit_event(RawEvent::VelocityLimitSet(who, limit));
+				Ok(())
+			})?;
+		}
+
+		/// Set deposit and withdraw velocity limit of all accounts. `None` to remove.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_global_velocity_limit(origin, limit: Option<VelocityLimit<T::BlockNumber>>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Self::global_velocity_limit();
+				match limit {
+					Some(l) => <GlobalVelocityLimit<T>>::put(l),
+					None => <GlobalVelocityLimit<T>>::kill(),
+				}
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"GlobalVelocityLimit",
+					vec![],
+					old.encode(),
+					limit.encode(),
+				);
+				Self::deposit_event(RawEvent::GlobalVelocityLimitSet(limit));
+				Ok(())
+			})?;
+		}
+
+		/// Set the buffer above margin call thresholds to keep traders and pools as risk candidates. `None` to stop
+		/// maintaining risk candidates.
+		///
+		/// Existing candidates are kept on change, and re-evaluated on next update.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_risk_candidate_buffer(origin, buffer: Option<Permill>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Self::risk_candidate_buffer();
+				match buffer {
+					Some(b) => RiskCandidateBuffer::put(b),
+					None => RiskCandidateBuffer::kill(),
+				}
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"RiskCandidateBuffer",
+					vec![],
+					old.encode(),
+					buffer.encode(),
+				);
+				Self::deposit_event(RawEvent::RiskCandidateBufferSet(buffer));
+				Ok(())
+			})?;
+		}
+
+		/// Dispute the stop out of `position_id` closed at block `stopped_out_at`, within the dispute window.
+		#[weight = 10_000]
+		pub fn dispute_stop_out(origin, stopped_out_at: T::BlockNumber, #[compact] position_id: PositionId) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let record = Self::stop_out_records(stopped_out_at, position_id)
+					.ok_or(Error::<T>::StopOutRecordNotFound)?;
+				ensure!(record.position.owner == who, Error::<T>::PositionNotOpenedByTrader);
+				<StopOutRecords<T>>::remove(stopped_out_at, position_id);
+				<StopOutDisputes<T>>::insert(position_id, record);
+				Self::deposit_event(RawEvent::StopOutDisputed(who, position_id));
+				Ok(())
+			})?;
+		}
+
+		/// Resolve the stop out dispute of `position_id`. If `compensation` is set, the trader could claim it
+		/// from the insurance fund.
+		///
+		/// May only be called from `UpdateOrigin`, once the stop out is proven to be caused by an invalid oracle
+		/// round.
+		#[weight = 10_000]
+		pub fn resolve_stop_out_dispute(origin, #[compact] position_id: PositionId, compensation: Option<Balance>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let record = <StopOutDisputes<T>>::take(position_id).ok_or(Error::<T>::StopOutDisputeNotFound)?;
+				if let Some(amount) = compensation {
+					<StopOutCompensations<T>>::insert(position_id, (record.position.owner, amount));
+				}
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"StopOutCompensations",
+					position_id.encode(),
+					None::<Balance>.encode(),
+					compensation.encode(),
+				);
+				Self::deposit_event(RawEvent::StopOutDisputeResolved(position_id, compensation));
+				Ok(())
+			})?;
+		}
+
+		/// Claim the compensation of the disputed stop out of `position_id` from the insurance fund.
+		#[weight = 10_000]
+		pub fn claim_stop_out_compensation(origin, #[compact] position_id: PositionId) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let (trader, amount) = Self::stop_out_compensations(position_id)
+					.ok_or(Error::<T>::StopOutCompensationNotFound)?;
+				ensure!(trader == who, Error::<T>::PositionNotOpenedByTrader);
+				<StopOutCompensations<T>>::remove(position_id);
+				T::LiquidityCurrency::transfer(&T::GetInsuranceFundAccountId::get(), &who, amount)?;
+				Self::deposit_event(RawEvent::StopOutCompensationClaimed(who, position_id, amount));
+				Ok(())
+			})?;
+		}
+
+		/// Opt in or out of slippage insurance in `pool_id`. Positions opened while opted in, of trading pairs with
+		/// a premium rate, are insured until closed.
+		///
+		/// On stop outs, the insurance fund covers the shortfall of the trader's equity below the stop out
+		/// threshold, in proportion to the notional value of the insured positions closed. Premiums are charged on
+		/// closing insured positions.
+		#[weight = 10_000]
+		pub fn set_slippage_insurance(origin, #[compact] pool_id: LiquidityPoolId, insured: bool) {
+			with_transaction_result(|| {
 				let who = ensure_signed(origin)?;
-				Self::do_deposit(&who, pool_id, amount)?;
-				Self::deposit_event(RawEvent::Deposited(who, pool_id, amount));
+				if insured {
+					<SlippageInsurances<T>>::insert(&who, pool_id, ());
+				} else {
+					<SlippageInsurances<T>>::remove(&who, pool_id);
+				}
+				Self::deposit_event(RawEvent::SlippageInsuranceSet(who, pool_id, insured));
 				Ok(())
 			})?;
 		}
 
-		/// Withdraw liquidity from caller's account.
+		/// Set the slippage insurance premium rate of `pair`, per `GetSlippageInsurancePremiumPeriod` of the notional
+		/// value of insured positions. `None` to stop insuring new positions of `pair`.
+		///
+		/// Positions already insured keep the rate they are insured at.
+		///
+		/// May only be called from `UpdateOrigin`.
 		#[weight = 10_000]
-		pub fn withdraw(origin, #[compact] pool_id: LiquidityPoolId, #[compact] amount: Balance) {
+		pub fn set_slippage_insurance_premium_rate(origin, pair: TradingPair, rate: Option<Permill>) {
 			with_transaction_result(|| {
-				let who = ensure_signed(origin)?;
-				Self::do_withdraw(&who, pool_id, amount)?;
-				Self::deposit_event(RawEvent::Withdrew(who, pool_id, amount));
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Self::slippage_insurance_premium_rate(pair);
+				match rate {
+					Some(r) => SlippageInsurancePremiumRates::insert(pair, r),
+					None => SlippageInsurancePremiumRates::remove(pair),
+				}
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"SlippageInsurancePremiumRates",
+					pair.encode(),
+					old.encode(),
+					rate.encode(),
+				);
+				Self::deposit_event(RawEvent::SlippageInsurancePremiumRateSet(pair, rate));
 				Ok(())
 			})?;
 		}
 
-		/// Margin call a trader.
+		/// Set margin tiers of `pair`, so that positions above notional thresholds hold more margin. Empty to hold
+		/// margin at the position leverage.
 		///
-		/// May only be called from none origin. Would fail if the trader is still safe.
-		#[weight = (20_000, DispatchClass::Operational)]
-		pub fn trader_margin_call(
-			origin,
-			who: <T::Lookup as StaticLookup>::Source,
-			#[compact] pool_id: LiquidityPoolId
-		) {
+		/// Positions already opened keep their margin held.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_margin_tiers(origin, pair: TradingPair, tiers: Vec<MarginTier>) {
 			with_transaction_result(|| {
-				ensure_none(origin)?;
-				let who = T::Lookup::lookup(who)?;
-
-				Self::do_trader_margin_call(&who, pool_id)?;
-				Self::deposit_event(RawEvent::TraderMarginCalled(who));
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				ensure!(tiers.iter().all(|t| t.max_leverage > 0), Error::<T>::InvalidMarginTier);
+				let old = Self::margin_tiers(pair);
+				if tiers.is_empty() {
+					MarginTiers::remove(pair);
+				} else {
+					MarginTiers::insert(pair, tiers.clone());
+				}
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"MarginTiers",
+					pair.encode(),
+					old.encode(),
+					tiers.encode(),
+				);
+				Self::deposit_event(RawEvent::MarginTiersSet(pair, tiers));
+				Ok(())
+			})?;
+		}
 
+		/// Set the reward of keepers for signed liquidation calls. `None` for no reward.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_keeper_reward(origin, reward: Option<KeeperReward>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Self::keeper_reward();
+				match reward {
+					Some(r) => KeeperRewardSetting::put(r),
+					None => KeeperRewardSetting::kill(),
+				}
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"KeeperRewardSetting",
+					vec![],
+					old.encode(),
+					reward.encode(),
+				);
+				Self::deposit_event(RawEvent::KeeperRewardSet(reward));
 				Ok(())
 			})?;
 		}
 
-		/// Remove trader's margin-called status.
+		/// Set risk threshold overrides of `pair` in `pool_id`. Overrides of `None` fall back to the trading pair
+		/// risk threshold.
 		///
-		/// May only be called from none origin. Would fail if the trader is not safe yet.
-		#[weight = 20_000]
-		pub fn trader_become_safe(
+		/// Overrides may only be tighter than the trading pair risk threshold. May only be called from
+		/// `UpdateOrigin` or by the pool owner.
+		#[weight = 10_000]
+		pub fn set_pool_risk_threshold(
 			origin,
-			who: <T::Lookup as StaticLookup>::Source,
-			#[compact] pool_id: LiquidityPoolId
+			#[compact] pool_id: LiquidityPoolId,
+			pair: TradingPair,
+			threshold: TradingPairRiskThreshold
 		) {
 			with_transaction_result(|| {
-				ensure_none(origin)?;
-				let who = T::Lookup::lookup(who)?;
+				if let Err(origin) = T::UpdateOrigin::try_origin(origin.clone()) {
+					let who = ensure_signed(origin)?;
+					ensure!(T::LiquidityPools::is_owner(pool_id, &who), Error::<T>::NotPoolOwner);
+				}
 
-				Self::do_trader_become_safe(&who, pool_id)?;
-				Self::deposit_event(RawEvent::TraderBecameSafe(who));
+				let global = Self::risk_thresholds(pair);
+				for &(over, base) in [
+					(threshold.trader, global.trader),
+					(threshold.enp, global.enp),
+					(threshold.ell, global.ell),
+				].iter() {
+					if let Some(over) = over {
+						let base = base.ok_or(Error::<T>::NoRiskThreshold)?;
+						ensure!(over.max(base) == over, Error::<T>::PoolRiskThresholdTooLoose);
+					}
+				}
 
+				let old = Self::pool_risk_thresholds(pool_id, pair);
+				if threshold == TradingPairRiskThreshold::default() {
+					PoolRiskThresholds::remove(pool_id, pair);
+				} else {
+					PoolRiskThresholds::insert(pool_id, pair, threshold);
+				}
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"PoolRiskThresholds",
+					(pool_id, pair).encode(),
+					old.encode(),
+					threshold.encode(),
+				);
+				Self::deposit_event(RawEvent::PoolRiskThresholdSet(pool_id, pair, threshold));
 				Ok(())
 			})?;
 		}
 
-		/// Stop out a trader.
+		/// Set the circuit breaker of `pair`. `None` to remove, which also resets a tripped one.
 		///
-		/// May only be called from none origin. Would fail if stop out threshold not reached.
-		#[weight = (30_000, DispatchClass::Operational)]
-		pub fn trader_stop_out(
-			origin,
-			who: <T::Lookup as StaticLookup>::Source,
-			#[compact] pool_id: LiquidityPoolId
-		) {
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_circuit_breaker(origin, pair: TradingPair, breaker: Option<CircuitBreaker<T::BlockNumber>>) {
 			with_transaction_result(|| {
-				ensure_none(origin)?;
-				let who = T::Lookup::lookup(who)?;
-
-				Self::do_trader_stop_out(&who, pool_id)?;
-				Self::deposit_event(RawEvent::TraderStoppedOut(who));
-
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Self::circuit_breaker(pair);
+				match breaker {
+					Some(b) => <CircuitBreakers<T>>::insert(pair, b),
+					None => {
+						<CircuitBreakers<T>>::remove(pair);
+						<CircuitBreakerReferences<T>>::remove(pair);
+						<TrippedCircuitBreakers<T>>::remove(pair);
+					}
+				}
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"CircuitBreakers",
+					pair.encode(),
+					old.encode(),
+					breaker.encode(),
+				);
+				Self::deposit_event(RawEvent::CircuitBreakerSet(pair, breaker));
 				Ok(())
 			})?;
 		}
 
-		/// Margin call a liquidity pool.
+		/// Reset the tripped circuit breaker of `pair` before its cool down ends, and take the current oracle price
+		/// as the reference.
 		///
-		/// May only be called from none origin. Would fail if the pool still safe.
-		#[weight = (20_000, DispatchClass::Operational)]
-		pub fn liquidity_pool_margin_call(origin, #[compact] pool: LiquidityPoolId) {
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn reset_circuit_breaker(origin, pair: TradingPair) {
 			with_transaction_result(|| {
-				ensure_none(origin)?;
-				Self::do_liquidity_pool_margin_call(pool)?;
-				Self::deposit_event(RawEvent::LiquidityPoolMarginCalled(pool));
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = <TrippedCircuitBreakers<T>>::take(pair);
+				<CircuitBreakerReferences<T>>::remove(pair);
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"TrippedCircuitBreakers",
+					pair.encode(),
+					old.encode(),
+					None::<T::BlockNumber>.encode(),
+				);
+				Self::deposit_event(RawEvent::CircuitBreakerReset(pair));
 				Ok(())
 			})?;
 		}
 
-		/// Remove a pool's margin-called status.
+		/// Pause trading of `scope`, the whole protocol, a pool or a trading pair. Positions in the scope could only
+		/// be closed until resumed.
 		///
-		/// May only be called from none origin. Would fail if the pool is not safe yet.
-		#[weight = 20_000]
-		pub fn liquidity_pool_become_safe(origin, #[compact] pool: LiquidityPoolId) {
+		/// May only be called from `PauseOrigin`.
+		#[weight = 10_000]
+		pub fn pause_trading(origin, scope: TradingPauseScope) {
 			with_transaction_result(|| {
-				ensure_none(origin)?;
-				Self::do_liquidity_pool_become_safe(pool)?;
-				Self::deposit_event(RawEvent::LiquidityPoolBecameSafe(pool));
+				T::PauseOrigin::ensure_origin(origin.clone())?;
+				ensure!(!Self::paused_trading(scope), Error::<T>::TradingAlreadyPaused);
+
+				PausedTrading::insert(scope, true);
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"PausedTrading",
+					scope.encode(),
+					false.encode(),
+					true.encode(),
+				);
+				Self::deposit_event(RawEvent::TradingPaused(scope));
 				Ok(())
 			})?;
 		}
 
-		/// Force close a liquidity pool.
+		/// Resume paused trading of `scope`.
 		///
-		/// May only be called from none origin. Would fail if pool ENP or ELL thresholds not reached.
-		#[weight = (30_000, DispatchClass::Operational)]
-		pub fn liquidity_pool_force_close(origin, #[compact] pool: LiquidityPoolId) {
+		/// May only be called from `PauseOrigin`.
+		#[weight = 10_000]
+		pub fn resume_trading(origin, scope: TradingPauseScope) {
 			with_transaction_result(|| {
-				ensure_none(origin)?;
-				Self::do_liquidity_pool_force_close(pool)?;
-				Self::deposit_event(RawEvent::LiquidityPoolForceClosed(pool));
+				T::PauseOrigin::ensure_origin(origin.clone())?;
+				ensure!(Self::paused_trading(scope), Error::<T>::TradingNotPaused);
+
+				PausedTrading::remove(scope);
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"PausedTrading",
+					scope.encode(),
+					true.encode(),
+					false.encode(),
+				);
+				Self::deposit_event(RawEvent::TradingResumed(scope));
 				Ok(())
 			})?;
 		}
 
-		/// Set risk thresholds of a trading pair.
+		/// Set the option of trader `tier`. The default option removes it.
 		///
 		/// May only be called from `UpdateOrigin`.
 		#[weight = 10_000]
-		pub fn set_trading_pair_risk_threshold(
+		pub fn set_trader_tier_option(origin, tier: TraderTier, option: TraderTierOption) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+
+				let old = Self::trader_tier_option(tier);
+				if option == TraderTierOption::default() {
+					TraderTierOptions::remove(tier);
+				} else {
+					TraderTierOptions::insert(tier, option);
+				}
+				T::AuditLog::record(
+					origin,
+					b"MarginProtocol",
+					b"TraderTierOptions",
+					tier.encode(),
+					old.encode(),
+					option.encode(),
+				);
+				Self::deposit_event(RawEvent::TraderTierOptionSet(tier, option));
+				Ok(())
+			})?;
+		}
+
+		/// Accept the offer to transfer `position_id`, and the margin it holds, to caller.
+		///
+		/// Both the owner and caller must be safe after the transfer, and the owner's balance can't be negative
+		/// after the margin held leaves. Weight scales with the max open positions of a trader, which are iterated
+		/// in margin level calculations and warning checks of both accounts.
+		#[weight = 20_000 + 2 * Module::<T>::trader_positions_weight() + Module::<T>::pool_risk_weight()
+			+ 2 * Module::<T>::warnings_weight()]
+		pub fn accept_position_transfer(origin, #[compact] position_id: PositionId) {
+			with_transaction_result(|| {
+				let dest = ensure_signed(origin)?;
+				ensure!(
+					Self::position_transfer_offer(position_id).as_ref() == Some(&dest),
+					Error::<T>::NoPositionTransferOffer
+				);
+				let who = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?.owner;
+
+				let pool_id = Self::with_price_cache(|| Self::do_transfer_position(&who, position_id, &dest))?;
+				<PositionTransferOffers<T>>::remove(position_id);
+				Self::update_risk_candidates(&who, pool_id);
+				Self::update_risk_candidates(&dest, pool_id);
+				Self::deposit_event(RawEvent::PositionTransferred(who, position_id, dest));
+				Ok(())
+			})?;
+		}
+
+		/// Place a pending order as in `place_order`, cancelled by the protocol at `expiry` block if not executed
+		/// by then.
+		#[weight = 10_000]
+		pub fn place_order_with_expiry(
 			origin,
+			#[compact] pool_id: LiquidityPoolId,
 			pair: TradingPair,
-			trader: Option<RiskThreshold>,
-			enp: Option<RiskThreshold>,
-			ell: Option<RiskThreshold>
+			leverage: Leverage,
+			#[compact] leveraged_amount: Balance,
+			kind: OrderKind,
+			trigger_price: Price,
+			price: Price,
+			expiry: T::BlockNumber,
 		) {
 			with_transaction_result(|| {
-				T::UpdateOrigin::ensure_origin(origin)?;
+				let who = ensure_signed(origin)?;
+				ensure!(expiry > <frame_system::Module<T>>::block_number(), Error::<T>::InvalidExpiry);
+				let order = PendingOrder {
+					owner: who.clone(),
+					pool: pool_id,
+					pair,
+					leverage,
+					leveraged_amount,
+					kind,
+					trigger_price,
+					price,
+				};
+				let order_id = Self::do_place_order(order, Some(expiry))?;
+				Self::deposit_event(RawEvent::OrderPlaced(
+					who.clone(),
+					order_id,
+					pool_id,
+					pair,
+					leverage,
+					leveraged_amount,
+					kind,
+					trigger_price,
+				));
+				Self::deposit_event(RawEvent::OrderExpirySet(who, order_id, expiry));
+				Ok(())
+			})?;
+		}
 
-				RiskThresholds::mutate(pair, |r| {
-					if trader.is_some() {
-						r.trader = trader;
-					}
-					if enp.is_some() {
-						r.enp = enp;
-					}
-					if ell.is_some() {
-						r.ell = ell;
-					}
-				});
+		fn on_runtime_upgrade() -> Weight {
+			Self::migrate_storage()
+		}
 
-				Self::deposit_event(RawEvent::TradingPairRiskThresholdSet(pair, trader, enp, ell));
+		fn on_finalize() {
+			#[cfg(feature = "state-checks")]
+			Self::check_state().expect("margin protocol state is consistent; qed");
+		}
 
-				Ok(())
-			})?;
+		fn on_initialize(now: T::BlockNumber) -> Weight {
+			Self::with_price_cache(|| {
+				let weight = Self::process_pending_stop_outs()
+					.saturating_add(Self::prune_stop_out_records(now))
+					.saturating_add(Self::ratchet_trailing_stops())
+					.saturating_add(Self::close_expired_positions(now))
+					.saturating_add(Self::cancel_expired_orders(now))
+					.saturating_add(Self::settle_swaps(now))
+					.saturating_add(Self::check_circuit_breakers(now));
+
+				let interval = T::GetEnpEllSnapshotInterval::get();
+				if interval == 0u32.into() || now % interval != 0u32.into() {
+					return weight;
+				}
+
+				weight.saturating_add(Self::take_enp_ell_snapshots(now))
+			})
 		}
 
 		fn offchain_worker(block_number: T::BlockNumber) {
-			if let Err(error) = Self::offchain_worker(block_number) {
+			if let Err(error) = Self::with_price_cache(|| Self::offchain_worker(block_number)) {
 				match error {
 					OffchainErr::NotValidator | OffchainErr::OffchainLock => {
 						debug::native::info!(
@@ -566,35 +3194,324 @@ decl_module! {
 					}
 				};
 			}
+
+			if let Err(error) = Self::offchain_notify(block_number) {
+				debug::native::error!(
+					target: TAG,
+					"Notification: {:?} [block_number = {:?}]",
+					error,
+					block_number,
+				);
+			}
 		}
 	}
 }
 
 // Storage getters
 impl<T: Trait> Module<T> {
-	pub fn trader_risk_threshold(pair: TradingPair) -> Option<RiskThreshold> {
-		Self::risk_thresholds(pair).trader
+	/// Trader risk threshold of `pair` in `pool_id`, the pool override if any, or the trading pair's.
+	pub fn trader_risk_threshold(pool_id: LiquidityPoolId, pair: TradingPair) -> Option<RiskThreshold> {
+		Self::resolve_risk_threshold(
+			Self::risk_thresholds(pair).trader,
+			Self::pool_risk_thresholds(pool_id, pair).trader,
+		)
+	}
+
+	/// Pool ENP risk threshold of `pair` in `pool_id`, the pool override if any, or the trading pair's.
+	pub fn liquidity_pool_enp_threshold(pool_id: LiquidityPoolId, pair: TradingPair) -> Option<RiskThreshold> {
+		Self::resolve_risk_threshold(
+			Self::risk_thresholds(pair).enp,
+			Self::pool_risk_thresholds(pool_id, pair).enp,
+		)
 	}
 
-	pub fn liquidity_pool_enp_threshold(pair: TradingPair) -> Option<RiskThreshold> {
-		Self::risk_thresholds(pair).enp
+	/// Pool ELL risk threshold of `pair` in `pool_id`, the pool override if any, or the trading pair's.
+	pub fn liquidity_pool_ell_threshold(pool_id: LiquidityPoolId, pair: TradingPair) -> Option<RiskThreshold> {
+		Self::resolve_risk_threshold(
+			Self::risk_thresholds(pair).ell,
+			Self::pool_risk_thresholds(pool_id, pair).ell,
+		)
 	}
 
-	pub fn liquidity_pool_ell_threshold(pair: TradingPair) -> Option<RiskThreshold> {
-		Self::risk_thresholds(pair).ell
+	/// An override only applies on top of a trading pair risk threshold, and never loosens it.
+	fn resolve_risk_threshold(pair: Option<RiskThreshold>, pool: Option<RiskThreshold>) -> Option<RiskThreshold> {
+		pair.map(|threshold| pool.map_or(threshold, |p| threshold.max(p)))
 	}
 }
 
 // Dispatchable calls implementation
 impl<T: Trait> Module<T> {
+	/// Ensure `origin` is none, or signed by a keeper. Returns the keeper if signed.
+	fn ensure_keeper(origin: T::Origin) -> result::Result<Option<T::AccountId>, BadOrigin> {
+		match origin.into() {
+			Ok(frame_system::RawOrigin::Signed(keeper)) => Ok(Some(keeper)),
+			Ok(frame_system::RawOrigin::None) => Ok(None),
+			_ => Err(BadOrigin),
+		}
+	}
+
+	/// Ensure the current block is not past `valid_until`, if set.
+	fn ensure_valid_until(valid_until: Option<T::BlockNumber>) -> DispatchResult {
+		if let Some(valid_until) = valid_until {
+			ensure!(
+				<frame_system::Module<T>>::block_number() <= valid_until,
+				Error::<T>::TransactionOutdated
+			);
+		}
+		Ok(())
+	}
+
+	/// Open a position, or increase the nettable position of `who`. Positions with an `expiry` are never netted.
+	///
+	/// Returns `(position_id, open_price)`.
 	fn do_open_position(
 		who: &T::AccountId,
 		pool_id: LiquidityPoolId,
 		pair: TradingPair,
 		leverage: Leverage,
 		leveraged_amount: Balance,
-		price: Price,
-	) -> DispatchResult {
+		price: Price,
+		expiry: Option<T::BlockNumber>,
+	) -> result::Result<(PositionId, Price), DispatchError> {
+		let (position, open_price) = Self::new_position(who, pool_id, pair, leverage, leveraged_amount, price)?;
+		let commission = Self::charge_open_commission(who, &position)?;
+		let nettable = match expiry {
+			Some(_) => None,
+			None => Self::nettable_position(who, pool_id, pair, leverage),
+		};
+		let id = if let Some(id) = nettable {
+			Self::net_position(who, id, position)?;
+			Self::deposit_event(RawEvent::PositionIncreased(
+				who.clone(),
+				id,
+				leveraged_amount,
+				open_price,
+				commission,
+				Self::balances(who, pool_id),
+			));
+			id
+		} else {
+			let id = Self::insert_position(who, pool_id, pair, position)?;
+			Self::deposit_event(RawEvent::PositionOpened(
+				who.clone(),
+				id,
+				pool_id,
+				pair,
+				leverage,
+				leveraged_amount,
+				open_price,
+				commission,
+				Self::balances(who, pool_id),
+			));
+			if let Some(expiry) = expiry {
+				Self::put_position_expiry(id, expiry);
+			}
+			id
+		};
+		Self::emit_warnings(who, pool_id);
+
+		Self::copy_open_position(who, id, pool_id, pair, leverage, leveraged_amount, price);
+
+		Ok((id, open_price))
+	}
+
+	fn do_open_position_with_key(
+		key: &T::AccountId,
+		trader: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		leverage: Leverage,
+		leveraged_amount: Balance,
+		price: Price,
+	) -> DispatchResult {
+		let mut trading_key = Self::ensure_trading_key(key, trader)?;
+
+		// only the opened amount is charged, even if netted into an existing position
+		let (_, open_price) = Self::do_open_position(trader, pool_id, pair, leverage, leveraged_amount, price, None)?;
+		let leveraged_held = fixed_i128_from_balance(leveraged_amount).map_err(Error::<T>::from)?;
+		let debits = fixed_i128_mul_with_rounding(leveraged_held, fixed_i128_from_fixed_u128(open_price), Rounding::Up)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let notional = balance_from_fixed_i128(Self::usd_value_with_rounding(pair.quote, debits, Rounding::Up)?)
+			.map_err(Error::<T>::from)?;
+
+		trading_key.notional_used = trading_key
+			.notional_used
+			.checked_add(notional)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		ensure!(
+			trading_key.notional_used <= trading_key.notional_limit,
+			Error::<T>::TradingKeyLimitExceeded
+		);
+		<TradingKeys<T>>::insert(trader, key, trading_key);
+
+		Ok(())
+	}
+
+	fn do_fill_quote(
+		who: &T::AccountId,
+		quote: Quote<T::AccountId, T::BlockNumber>,
+		signer: &T::AccountId,
+		signature: T::QuoteSignature,
+	) -> DispatchResult {
+		ensure!(quote.trader == *who, Error::<T>::QuoteNotForTrader);
+		ensure!(
+			<frame_system::Module<T>>::block_number() < quote.expiry,
+			Error::<T>::QuoteExpired
+		);
+		ensure!(
+			Self::filled_quotes(quote.pool, quote.nonce).is_none(),
+			Error::<T>::QuoteAlreadyFilled
+		);
+		ensure!(
+			T::LiquidityPools::is_owner(quote.pool, signer) && signature.verify(&quote.encode()[..], signer),
+			Error::<T>::InvalidQuoteSignature
+		);
+
+		let debits_price = fixed_i128_from_fixed_u128(quote.price);
+		let (position, open_price) = Self::new_position_at_price(
+			who,
+			quote.pool,
+			quote.pair,
+			quote.leverage,
+			quote.leveraged_amount,
+			debits_price,
+		)?;
+		let commission = Self::charge_open_commission(who, &position)?;
+		let id = Self::insert_position(who, quote.pool, quote.pair, position)?;
+		FilledQuotes::insert(quote.pool, quote.nonce, ());
+
+		Self::deposit_event(RawEvent::PositionOpened(
+			who.clone(),
+			id,
+			quote.pool,
+			quote.pair,
+			quote.leverage,
+			quote.leveraged_amount,
+			open_price,
+			commission,
+			Self::balances(who, quote.pool),
+		));
+		Self::deposit_event(RawEvent::QuoteFilled(who.clone(), id, quote.pool, quote.nonce));
+		Self::emit_warnings(who, quote.pool);
+
+		Ok(())
+	}
+
+	fn do_relay(
+		relayer: &T::AccountId,
+		meta_transaction: MetaTransaction<T::AccountId, T::BlockNumber>,
+		signature: T::QuoteSignature,
+	) -> DispatchResult {
+		let trader = &meta_transaction.trader;
+		ensure!(
+			<frame_system::Module<T>>::block_number() < meta_transaction.expiry,
+			Error::<T>::MetaTransactionExpired
+		);
+		ensure!(
+			meta_transaction.nonce == Self::meta_transaction_nonces(trader),
+			Error::<T>::InvalidMetaTransactionNonce
+		);
+		ensure!(
+			signature.verify(&meta_transaction.encode()[..], trader),
+			Error::<T>::InvalidMetaTransactionSignature
+		);
+
+		let allowance = Self::relayer_allowances(relayer);
+		ensure!(allowance > 0, Error::<T>::NoRelayerAllowance);
+		<RelayerAllowances<T>>::insert(relayer, allowance - 1);
+		<MetaTransactionNonces<T>>::insert(trader, meta_transaction.nonce.saturating_add(1));
+
+		let pool_id = match meta_transaction.intent {
+			TradingIntent::Deposit { pool, amount } => {
+				Self::do_deposit(trader, pool, amount)?;
+				Self::deposit_event(RawEvent::Deposited(trader.clone(), pool, amount));
+				pool
+			}
+			TradingIntent::OpenPosition {
+				pool,
+				pair,
+				leverage,
+				leveraged_amount,
+				price,
+			} => {
+				Self::with_price_cache(|| {
+					Self::do_open_position(trader, pool, pair, leverage, leveraged_amount, price, None)
+				})?;
+				pool
+			}
+			TradingIntent::ClosePosition { position_id, price } => {
+				let pool = Self::pool_of_closable_position(position_id)?;
+				Self::with_price_cache(|| Self::do_close_position(trader, position_id, Some(price)))?;
+				pool
+			}
+		};
+		Self::update_risk_candidates(trader, pool_id);
+
+		Self::deposit_event(RawEvent::MetaTransactionRelayed(
+			relayer.clone(),
+			trader.clone(),
+			meta_transaction.nonce,
+		));
+
+		Ok(())
+	}
+	/// Build a new position, and check if it could be opened. No storage change.
+	///
+	/// Returns `(position, open_price)`, or `Err` if the position cannot be opened.
+	fn new_position(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		leverage: Leverage,
+		leveraged_amount: Balance,
+		price: Price,
+	) -> result::Result<(Position<T>, Price), DispatchError> {
+		let discount = T::FeeDiscount::fee_discount(who);
+		let debits_price = {
+			if leverage.is_long() {
+				Self::ask_price(pool_id, pair, Some(price), discount)?
+			} else {
+				Self::bid_price(pool_id, pair, Some(price), discount)?
+			}
+		};
+		Self::new_position_at_price(who, pool_id, pair, leverage, leveraged_amount, debits_price)
+	}
+
+	fn ensure_market_open(pair: TradingPair) -> DispatchResult {
+		ensure!(!T::MarketCalendar::is_market_closed(pair), Error::<T>::MarketClosed);
+		Ok(())
+	}
+
+	/// The pool of `position_id`, if its market is open for the trader to close it. Liquidations and other
+	/// protocol-initiated closes don't go through this check.
+	fn pool_of_closable_position(position_id: PositionId) -> result::Result<LiquidityPoolId, DispatchError> {
+		let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+		Self::ensure_market_open(position.pair)?;
+		Ok(position.pool)
+	}
+
+	/// Build a new position with `debits_price` as open price, and check if it could be opened. No storage change.
+	fn new_position_at_price(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		leverage: Leverage,
+		leveraged_amount: Balance,
+		debits_price: FixedI128,
+	) -> result::Result<(Position<T>, Price), DispatchError> {
+		Self::ensure_market_open(pair)?;
+		ensure!(!Self::is_trading_paused(pool_id, pair), Error::<T>::TradingPaused);
+		ensure!(
+			!Self::is_circuit_breaker_tripped(pair),
+			Error::<T>::CircuitBreakerTripped
+		);
+		let tier = T::TraderTiers::trader_tier(who);
+		if let Some(max) = Self::trader_tier_option(tier).max_leverage {
+			ensure!(leverage.value() <= max, Error::<T>::LeverageAboveTierMax);
+		}
+		if let Some(max) = T::LiquidityPools::trader_max_leverage(pool_id, tier) {
+			ensure!(leverage.value() <= max, Error::<T>::LeverageAboveTraderMax);
+		}
 		Self::ensure_can_open_more_position(who, pool_id, pair)?;
 		ensure!(
 			Self::margin_called_traders(who, pool_id).is_none(),
@@ -606,30 +3523,23 @@ impl<T: Trait> Module<T> {
 		);
 
 		let (held_signum, debit_signum): (i128, i128) = if leverage.is_long() { (1, -1) } else { (-1, 1) };
-		let leveraged_held = fixed_i128_from_u128(leveraged_amount);
-		let debits_price = {
-			if leverage.is_long() {
-				Self::ask_price(pool_id, pair, Some(price))?
-			} else {
-				Self::bid_price(pool_id, pair, Some(price))?
-			}
-		};
-		let leveraged_debits = leveraged_held
-			.checked_mul(&debits_price)
+		let leveraged_held = fixed_i128_from_balance(leveraged_amount).map_err(Error::<T>::from)?;
+		// Debits paid by long positions round up, and debits received by short positions round down.
+		let debits_rounding = if leverage.is_long() { Rounding::Up } else { Rounding::Down };
+		let leveraged_debits = fixed_i128_mul_with_rounding(leveraged_held, debits_price, debits_rounding)
 			.ok_or(Error::<T>::NumOutOfBound)?;
-		let leveraged_held_in_usd = Self::usd_value(pair.quote, leveraged_debits)?;
-		T::LiquidityPools::ensure_can_open_position(
-			pool_id,
-			pair,
-			leverage,
-			u128_from_fixed_i128(leveraged_held_in_usd),
-		)
-		.map_err::<Error<T>, _>(|e| e.into())?;
+		let leveraged_held_in_usd = Self::usd_value_with_rounding(pair.quote, leveraged_debits, Rounding::Up)?;
+		let notional = balance_from_fixed_i128(leveraged_held_in_usd).map_err(Error::<T>::from)?;
+		T::LiquidityPools::ensure_can_open_position(pool_id, pair, leverage, notional)
+			.map_err::<Error<T>, _>(|e| e.into())?;
 
 		let margin_held = {
-			let leverage_value = FixedI128::saturating_from_integer(leverage.value());
-			leveraged_held_in_usd
-				.checked_div(&leverage_value)
+			let margin_leverage = match Self::margin_tier_max_leverage(pair, notional) {
+				Some(max) => cmp::min(leverage.value(), max),
+				None => leverage.value(),
+			};
+			let leverage_value = FixedI128::saturating_from_integer(margin_leverage);
+			fixed_i128_div_with_rounding(leveraged_held_in_usd, leverage_value, Rounding::Up)
 				.expect("leveraged value cannot be zero; qed")
 		};
 		let open_accumulated_swap_rate = T::LiquidityPools::accumulated_swap_rate(pool_id, pair, leverage.is_long());
@@ -644,44 +3554,213 @@ impl<T: Trait> Module<T> {
 			margin_held,
 		};
 
-		let free_margin = Self::free_margin(who, pool_id)?;
-		ensure!(free_margin >= margin_held, Error::<T>::InsufficientFreeMargin);
+		// an offsetting cross margin position only requires margin beyond the released hedged margin
+		let released_margin = if Self::margin_mode(who, pool_id) == MarginMode::Isolated {
+			FixedI128::zero()
+		} else {
+			Self::hedged_margin_of_trader(who, pool_id, Some(&position))?
+				.checked_sub(&Self::hedged_margin_of_trader(who, pool_id, None)?)
+				.ok_or(Error::<T>::NumOutOfBound)?
+		};
+		let free_margin = Self::free_margin(who, pool_id)?
+			.checked_add(&released_margin)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let required = Self::commission_of(pool_id, pair, leveraged_debits)?
+			.checked_add(&margin_held)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		ensure!(free_margin >= required, Error::<T>::InsufficientFreeMargin);
 		Self::ensure_trader_safe(who, pool_id, Action::OpenPosition(position.clone()))?;
 		Self::ensure_pool_safe(pool_id, Action::OpenPosition(position.clone()))?;
 
-		let id = Self::insert_position(who, pool_id, pair, position)?;
+		Ok((position, FixedU128::from_inner(u128_from_fixed_i128(debits_price))))
+	}
 
-		Self::deposit_event(RawEvent::PositionOpened(
+	/// Close `position_id` of `who`, at the market price with spread, bounded by `price` if set. Returns the market
+	/// price.
+	fn do_close_position(who: &T::AccountId, position_id: PositionId, price: Option<Price>) -> PriceResult {
+		let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+		ensure!(
+			<PositionsByTrader<T>>::contains_key(who, (position.pool, position_id)),
+			Error::<T>::PositionNotOpenedByTrader
+		);
+		let discount = T::FeeDiscount::fee_discount(who);
+		let (unrealized_pl, market_price) =
+			Self::unrealized_pl_and_market_price_of_position(&position, price, discount)?;
+		let accumulated_swap_rate = Self::accumulated_swap_rate_of_position(&position)?;
+		let unrealized = unrealized_pl
+			.checked_add(&accumulated_swap_rate)
+			.ok_or(Error::<T>::UnrealizedPlOverflow)?;
+
+		let realized = Self::realize_pl(who, position_id, &position, unrealized)?;
+		let commission = Self::charge_close_commission(who, &position, market_price)?;
+		Self::record_trade(
+			who,
+			position_id,
+			&position,
+			market_price,
+			accumulated_swap_rate,
+			realized,
+		)?;
+
+		let copied_positions = <CopiedPositions<T>>::take(position_id);
+
+		// Remove position storage operation.
+		Self::remove_position(who, position_id, &position)?;
+
+		let market_price = FixedU128::from_inner(u128_from_fixed_i128(market_price));
+		Self::deposit_event(RawEvent::PositionClosed(
 			who.clone(),
-			id,
-			pool_id,
-			pair,
-			leverage,
-			leveraged_amount,
-			FixedU128::from_inner(u128_from_fixed_i128(debits_price)),
+			position_id,
+			position.pool,
+			market_price,
+			commission,
+			accumulated_swap_rate,
+			realized,
+			Self::balances(who, position.pool),
 		));
+		Self::emit_warnings(who, position.pool);
 
-		Ok(())
+		// Follow leader's close. Copied positions that cannot be closed are kept open.
+		for (follower, id) in copied_positions {
+			let _ = with_transaction_result(|| Self::do_close_position(&follower, id, price));
+		}
+
+		Ok(market_price)
+	}
+
+	/// Close `positions` of `who`, and return the pools they were opened in.
+	fn do_close_positions(
+		who: &T::AccountId,
+		positions: &[(PositionId, Price)],
+	) -> result::Result<Vec<LiquidityPoolId>, DispatchError> {
+		let mut pool_ids = vec![];
+		for (position_id, price) in positions {
+			let pool_id = Self::pool_of_closable_position(*position_id)?;
+			Self::do_close_position(who, *position_id, Some(*price))?;
+			if !pool_ids.contains(&pool_id) {
+				pool_ids.push(pool_id);
+			}
+		}
+		Ok(pool_ids)
+	}
+
+	/// Close all positions of `who` in `pool_id`, and return the number closed.
+	fn do_close_all_positions(who: &T::AccountId, pool_id: LiquidityPoolId) -> result::Result<u32, DispatchError> {
+		let position_ids: Vec<PositionId> = <PositionsByTrader<T>>::iter_prefix(who)
+			.filter(|((p, _), _)| *p == pool_id)
+			.map(|((_, position_id), _)| position_id)
+			.collect();
+		for position_id in position_ids.iter() {
+			Self::pool_of_closable_position(*position_id)?;
+			Self::do_close_position(who, *position_id, None)?;
+		}
+		Ok(position_ids.len() as u32)
+	}
+
+	fn do_reverse_position(
+		who: &T::AccountId,
+		position_id: PositionId,
+		price: Price,
+	) -> result::Result<PositionId, DispatchError> {
+		let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+		let leveraged_amount = fixed_i128_checked_abs(position.leveraged_held)
+			.ok_or(Error::<T>::ArithmeticOverflow)
+			.and_then(|held| balance_from_fixed_i128(held).map_err(Error::<T>::from))?;
+		Self::do_close_position(who, position_id, Some(price))?;
+		Self::do_open_position(
+			who,
+			position.pool,
+			position.pair,
+			position.leverage.opposite(),
+			leveraged_amount,
+			price,
+			None,
+		)
+		.map(|(id, _)| id)
 	}
 
-	fn do_close_position(who: &T::AccountId, position_id: PositionId, price: Option<Price>) -> DispatchResult {
+	fn do_close_position_partial(
+		who: &T::AccountId,
+		position_id: PositionId,
+		fraction: Permill,
+		price: Option<Price>,
+	) -> PriceResult {
+		ensure!(
+			fraction > Permill::zero() && fraction < Permill::one(),
+			Error::<T>::InvalidCloseFraction
+		);
 		let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
 		ensure!(
 			<PositionsByTrader<T>>::contains_key(who, (position.pool, position_id)),
 			Error::<T>::PositionNotOpenedByTrader
 		);
-		let (unrealized_pl, market_price) = Self::unrealized_pl_and_market_price_of_position(&position, price)?;
-		let accumulated_swap_rate = Self::accumulated_swap_rate_of_position(&position)?;
+		let closed = Position::<T> {
+			leveraged_held: position
+				.leveraged_held
+				.checked_mul(&fraction.into())
+				.ok_or(Error::<T>::NumOutOfBound)?,
+			leveraged_debits: position
+				.leveraged_debits
+				.checked_mul(&fraction.into())
+				.ok_or(Error::<T>::NumOutOfBound)?,
+			margin_held: position
+				.margin_held
+				.checked_mul(&fraction.into())
+				.ok_or(Error::<T>::NumOutOfBound)?,
+			..position.clone()
+		};
+
+		let discount = T::FeeDiscount::fee_discount(who);
+		let (unrealized_pl, market_price) = Self::unrealized_pl_and_market_price_of_position(&closed, price, discount)?;
+		let accumulated_swap_rate = Self::accumulated_swap_rate_of_position(&closed)?;
 		let unrealized = unrealized_pl
 			.checked_add(&accumulated_swap_rate)
-			.ok_or(Error::<T>::NumOutOfBound)?;
+			.ok_or(Error::<T>::UnrealizedPlOverflow)?;
+		let realized = Self::realize_pl(who, position_id, &closed, unrealized)?;
+		let commission = Self::charge_close_commission(who, &closed, market_price)?;
+		Self::record_trade(who, position_id, &closed, market_price, accumulated_swap_rate, realized)?;
+
+		Self::reduce_position(who, position_id, &position, &closed)?;
+
+		let market_price = FixedU128::from_inner(u128_from_fixed_i128(market_price));
+		Self::deposit_event(RawEvent::PositionPartiallyClosed(
+			who.clone(),
+			position_id,
+			position.pool,
+			fraction,
+			market_price,
+			commission,
+			accumulated_swap_rate,
+			realized,
+			Self::balances(who, position.pool),
+		));
+		Self::emit_warnings(who, position.pool);
+
+		// Follow leader's partial close. Copied positions that cannot be closed are kept as is.
+		for (follower, id) in Self::copied_positions(position_id) {
+			let _ = with_transaction_result(|| Self::do_close_position_partial(&follower, id, fraction, price));
+		}
 
+		Ok(market_price)
+	}
+
+	/// Realize `unrealized` profit or loss of `position`, or of its closing part. Returns the realized amount, which
+	/// is negative on loss.
+	///
+	/// Positions must be still in storage, as trader's equity is used to cap the loss of cross positions.
+	fn realize_pl(
+		who: &T::AccountId,
+		position_id: PositionId,
+		position: &Position<T>,
+		unrealized: FixedI128,
+	) -> FixedI128Result {
 		if unrealized.is_positive() {
 			// Realize trader's profit.
 
-			let pool_liquidity = fixed_i128_from_u128(<T::LiquidityPools as LiquidityPools<T::AccountId>>::liquidity(
-				position.pool,
-			));
+			let pool_liquidity = fixed_i128_from_balance(
+				<T::LiquidityPools as LiquidityPools<T::AccountId>>::liquidity(position.pool),
+			)
+			.map_err(Error::<T>::from)?;
 			// Max realizable is the pool's liquidity.
 			let realizable = cmp::min(pool_liquidity, unrealized);
 
@@ -691,27 +3770,40 @@ impl<T: Trait> Module<T> {
 			// repayment.
 			let balance = Self::balances(who, position.pool);
 			if balance.is_negative() {
-				pool_withdraw = cmp::max(pool_withdraw.saturating_add(balance), FixedI128::zero());
+				let repaid = pool_withdraw
+					.checked_add(&balance)
+					.ok_or(Error::<T>::ArithmeticOverflow)?;
+				pool_withdraw = cmp::max(repaid, FixedI128::zero());
 			}
 			if !pool_withdraw.is_zero() {
 				<T::LiquidityPools as LiquidityPools<T::AccountId>>::withdraw_liquidity(
 					&Self::account_id(),
 					position.pool,
-					u128_from_fixed_i128(pool_withdraw),
+					balance_from_fixed_i128(pool_withdraw).map_err(Error::<T>::from)?,
 				)?;
 			}
 
-			Self::update_balance(who, position.pool, realizable);
+			Self::update_balance(who, position.pool, realizable)?;
+
+			if let Some((leader, fee_share)) = Self::copied_position_leaders(position_id) {
+				Self::pay_leader_fee(who, &leader, position.pool, realizable, fee_share)?;
+			}
+
+			Ok(realizable)
 		} else {
 			// Realize trader's loss.
 
-			let equity = Self::equity_of_trader(who, position.pool)?;
-			let unrealized_abs = unrealized.saturating_abs();
-			// Max realizable is the trader's equity excluding this lossy position.
-			let realizable = cmp::min(
-				cmp::max(equity.saturating_add(unrealized_abs), FixedI128::zero()),
-				unrealized_abs,
-			);
+			let unrealized_abs = fixed_i128_checked_abs(unrealized).ok_or(Error::<T>::ArithmeticOverflow)?;
+			let max_realizable = if Self::isolated_position(position_id).is_some() {
+				// Max realizable is the margin held by this isolated position.
+				position.margin_held
+			} else {
+				// Max realizable is the trader's equity excluding this lossy position.
+				Self::equity_of_trader(who, position.pool)?
+					.checked_add(&unrealized_abs)
+					.ok_or(Error::<T>::ArithmeticOverflow)?
+			};
+			let realizable = cmp::min(cmp::max(max_realizable, FixedI128::zero()), unrealized_abs);
 
 			// If trader has not enough balance to pay the loss, pool won't get full payment for now. Repayment
 			// will happen on close profitable positions later.
@@ -723,44 +3815,506 @@ impl<T: Trait> Module<T> {
 				<T::LiquidityPools as LiquidityPools<T::AccountId>>::deposit_liquidity(
 					&Self::account_id(),
 					position.pool,
-					u128_from_fixed_i128(pool_deposit),
+					balance_from_fixed_i128(pool_deposit).map_err(Error::<T>::from)?,
 				)?;
 			}
 
-			Self::update_balance(who, position.pool, fixed_i128_mul_signum(realizable, -1));
+			let realized = fixed_i128_mul_signum(realizable, -1);
+			Self::update_balance(who, position.pool, realized)?;
+
+			Ok(realized)
 		}
+	}
 
-		// Remove position storage operation.
-		Self::remove_position(who, position_id, &position)?;
+	/// Record the closing of `position`, or of its closed part, in the trade history of `who`, and add `realized` to
+	/// the cumulative realized profit and loss.
+	fn record_trade(
+		who: &T::AccountId,
+		position_id: PositionId,
+		position: &Position<T>,
+		close_price: FixedI128,
+		swap: FixedI128,
+		realized: FixedI128,
+	) -> DispatchResult {
+		<CumulativeRealizedPl<T>>::try_mutate(who, position.pool, |pl| -> DispatchResult {
+			*pl = pl.checked_add(&realized).ok_or(Error::<T>::NumOutOfBound)?;
+			Ok(())
+		})?;
 
-		Self::deposit_event(RawEvent::PositionClosed(
-			who.clone(),
+		let max_records = T::GetMaxTradeHistory::get() as usize;
+		if max_records == 0 {
+			return Ok(());
+		}
+		let open_price = fixed_i128_checked_abs(position.leveraged_debits)
+			.and_then(|debits| debits.checked_div(&fixed_i128_checked_abs(position.leveraged_held)?))
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let record = TradeRecord {
 			position_id,
-			position.pool,
-			FixedU128::from_inner(u128_from_fixed_i128(market_price)),
+			pool: position.pool,
+			pair: position.pair,
+			leverage: position.leverage,
+			leveraged_held: position.leveraged_held,
+			open_price: Price::from_inner(u128_from_fixed_i128(open_price)),
+			close_price: Price::from_inner(u128_from_fixed_i128(close_price)),
+			swap,
+			realized_pl: realized,
+			opened_at: Self::position_opened_at(position_id),
+			closed_at: <frame_system::Module<T>>::block_number(),
+		};
+		<TradeHistory<T>>::mutate(who, |records| {
+			records.push(record);
+			if records.len() > max_records {
+				let expired = records.len() - max_records;
+				records.drain(..expired);
+			}
+		});
+
+		Ok(())
+	}
+
+	fn do_set_stop_loss(who: &T::AccountId, position_id: PositionId, price: Option<Price>) -> DispatchResult {
+		let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+		ensure!(position.owner == *who, Error::<T>::PositionNotOpenedByTrader);
+		match price {
+			Some(p) => StopLosses::insert(position_id, p),
+			None => StopLosses::remove(position_id),
+		}
+		TrailingStops::remove(position_id);
+		Ok(())
+	}
+
+	fn do_trigger_stop_loss(position_id: PositionId, position: &Position<T>) -> PriceResult {
+		ensure!(
+			Self::is_stop_loss_reached(position_id, position)?,
+			Error::<T>::StopLossNotReached
+		);
+		Self::do_close_position(&position.owner, position_id, None)
+	}
+
+	fn do_set_trailing_stop(
+		who: &T::AccountId,
+		position_id: PositionId,
+		distance: Option<TrailingDistance>,
+	) -> DispatchResult {
+		let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+		ensure!(position.owner == *who, Error::<T>::PositionNotOpenedByTrader);
+		match distance {
+			Some(distance) => {
+				let watermark = Self::price(position.pair.base, position.pair.quote)?;
+				Self::put_trailing_stop(
+					position_id,
+					position.leverage.is_long(),
+					TrailingStop { distance, watermark },
+				);
+			}
+			None => {
+				TrailingStops::remove(position_id);
+				StopLosses::remove(position_id);
+			}
+		}
+		Ok(())
+	}
+
+	fn do_set_take_profit(who: &T::AccountId, position_id: PositionId, price: Option<Price>) -> DispatchResult {
+		let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+		ensure!(position.owner == *who, Error::<T>::PositionNotOpenedByTrader);
+		match price {
+			Some(p) => TakeProfits::insert(position_id, p),
+			None => TakeProfits::remove(position_id),
+		}
+		Ok(())
+	}
+
+	fn do_trigger_take_profit(position_id: PositionId, position: &Position<T>) -> PriceResult {
+		ensure!(
+			Self::is_take_profit_reached(position_id, position)?,
+			Error::<T>::TakeProfitNotReached
+		);
+		Self::do_close_position(&position.owner, position_id, None)
+	}
+
+	fn do_place_order(
+		order: PendingOrder<T::AccountId>,
+		expiry: Option<T::BlockNumber>,
+	) -> result::Result<OrderId, DispatchError> {
+		let order_id = Self::next_order_id();
+		ensure!(order_id != OrderId::max_value(), Error::<T>::NoAvailableOrderId);
+		let count = Self::trader_pending_orders(&order.owner);
+		ensure!(
+			count < T::GetTraderMaxPendingOrders::get(),
+			Error::<T>::TraderPendingOrdersLimitReached
+		);
+
+		<TraderPendingOrders<T>>::insert(&order.owner, count + 1);
+		<PendingOrders<T>>::insert(order_id, order);
+		if let Some(expiry) = expiry {
+			Self::put_order_expiry(order_id, expiry);
+		}
+		NextOrderId::mutate(|id| *id += 1);
+		Ok(order_id)
+	}
+
+	fn do_cancel_order(who: &T::AccountId, order_id: OrderId) -> DispatchResult {
+		let order = Self::pending_orders(order_id).ok_or(Error::<T>::OrderNotFound)?;
+		ensure!(order.owner == *who, Error::<T>::OrderNotPlacedByTrader);
+		Self::remove_order(order_id, &order);
+		Ok(())
+	}
+
+	fn do_execute_order(
+		order_id: OrderId,
+		order: &PendingOrder<T::AccountId>,
+	) -> result::Result<PositionId, DispatchError> {
+		ensure!(!Self::is_order_expired(order_id), Error::<T>::OrderExpired);
+		ensure!(Self::is_order_triggered(order)?, Error::<T>::OrderNotTriggered);
+		Self::remove_order(order_id, order);
+		Self::do_open_position(
+			&order.owner,
+			order.pool,
+			order.pair,
+			order.leverage,
+			order.leveraged_amount,
+			order.price,
+			None,
+		)
+		.map(|(id, _)| id)
+	}
+
+	fn do_follow(
+		who: &T::AccountId,
+		leader: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		max_leverage: u8,
+		max_leveraged_amount: Balance,
+	) -> DispatchResult {
+		ensure!(who != leader, Error::<T>::CannotFollowSelf);
+		ensure!(
+			!<CopyTradings<T>>::contains_key(who, pool_id),
+			Error::<T>::AlreadyCopyTrading
+		);
+
+		<CopyFollowers<T>>::try_mutate(leader, pool_id, |followers| -> DispatchResult {
+			ensure!(
+				followers.len() < T::GetMaxCopyFollowers::get() as usize,
+				Error::<T>::TooManyCopyFollowers
+			);
+			followers.push(who.clone());
+			Ok(())
+		})?;
+		<CopyTradings<T>>::insert(
+			who,
+			pool_id,
+			CopyTrading {
+				leader: leader.clone(),
+				max_leverage,
+				max_leveraged_amount,
+				fee_share: Self::leader_fee_share(leader),
+			},
+		);
+
+		Ok(())
+	}
+
+	/// Stop copy trading of `who` in `pool_id`. Returns the leader.
+	fn do_unfollow(who: &T::AccountId, pool_id: LiquidityPoolId) -> result::Result<T::AccountId, DispatchError> {
+		let copy_trading = <CopyTradings<T>>::take(who, pool_id).ok_or(Error::<T>::NotCopyTrading)?;
+		<CopyFollowers<T>>::mutate(&copy_trading.leader, pool_id, |followers| {
+			followers.retain(|f| f != who)
+		});
+		Ok(copy_trading.leader)
+	}
+
+	/// Ensure `key` is an unexpired trading key of `trader`, and return it.
+	fn ensure_trading_key(
+		key: &T::AccountId,
+		trader: &T::AccountId,
+	) -> result::Result<TradingKey<T::BlockNumber>, Error<T>> {
+		let trading_key = Self::trading_keys(trader, key).ok_or(Error::<T>::TradingKeyNotFound)?;
+		ensure!(
+			<frame_system::Module<T>>::block_number() < trading_key.expiry,
+			Error::<T>::TradingKeyExpired
+		);
+		Ok(trading_key)
+	}
+
+	/// Copy a position opened by `leader` for its followers. Followers who cannot open the copied position are
+	/// skipped.
+	fn copy_open_position(
+		leader: &T::AccountId,
+		leader_position_id: PositionId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		leverage: Leverage,
+		leveraged_amount: Balance,
+		price: Price,
+	) {
+		let leader_balance = Self::balances(leader, pool_id);
+		if !leader_balance.is_positive() {
+			return;
+		}
+
+		let mut copied_positions = vec![];
+		for follower in Self::copy_followers(leader, pool_id) {
+			let copy_trading = match Self::copy_tradings(&follower, pool_id) {
+				Some(c) => c,
+				None => continue,
+			};
+			if leverage.value() > copy_trading.max_leverage {
+				continue;
+			}
+
+			// leveraged_amount * follower_balance / leader_balance
+			let follower_balance = Self::balances(&follower, pool_id);
+			if !follower_balance.is_positive() {
+				continue;
+			}
+			let amount = FixedU128::checked_from_rational(
+				u128_from_fixed_i128(follower_balance),
+				u128_from_fixed_i128(leader_balance),
+			)
+			.and_then(|ratio| ratio.checked_mul_int(leveraged_amount))
+			.map(|amount| cmp::min(amount, copy_trading.max_leveraged_amount))
+			.unwrap_or_default();
+			if amount == 0 {
+				continue;
+			}
+
+			let opened = with_transaction_result(|| {
+				let (position, open_price) = Self::new_position(&follower, pool_id, pair, leverage, amount, price)?;
+				let commission = Self::charge_open_commission(&follower, &position)?;
+				let id = Self::insert_position(&follower, pool_id, pair, position)?;
+				Ok((id, open_price, commission))
+			});
+			if let Ok((id, open_price, commission)) = opened {
+				<CopiedPositionLeaders<T>>::insert(id, (leader.clone(), copy_trading.fee_share));
+				copied_positions.push((follower.clone(), id));
+
+				Self::deposit_event(RawEvent::PositionOpened(
+					follower.clone(),
+					id,
+					pool_id,
+					pair,
+					leverage,
+					amount,
+					open_price,
+					commission,
+					Self::balances(&follower, pool_id),
+				));
+				Self::deposit_event(RawEvent::PositionCopied(
+					follower.clone(),
+					id,
+					leader.clone(),
+					leader_position_id,
+				));
+				Self::update_risk_candidates(&follower, pool_id);
+				Self::emit_warnings(&follower, pool_id);
+			}
+		}
+
+		if !copied_positions.is_empty() {
+			<CopiedPositions<T>>::insert(leader_position_id, copied_positions);
+		}
+	}
+
+	/// Pay `fee_share` of `profit` realized on a copied position from `follower` to `leader`.
+	fn pay_leader_fee(
+		follower: &T::AccountId,
+		leader: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		profit: FixedI128,
+		fee_share: Permill,
+	) -> DispatchResult {
+		let fee = profit
+			.checked_mul(&fee_share.into())
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		if !fee.is_positive() {
+			return Ok(());
+		}
+
+		Self::update_balance(follower, pool_id, fixed_i128_mul_signum(fee, -1))?;
+		Self::update_balance(leader, pool_id, fee)?;
+		Self::deposit_event(RawEvent::LeaderFeePaid(
+			follower.clone(),
+			leader.clone(),
+			pool_id,
+			balance_from_fixed_i128(fee).map_err(Error::<T>::from)?,
 		));
 
 		Ok(())
 	}
 
-	fn do_deposit(who: &T::AccountId, pool_id: LiquidityPoolId, amount: Balance) -> DispatchResult {
-		T::LiquidityCurrency::transfer(who, &Self::account_id(), amount)?;
-		Self::update_balance(who, pool_id, fixed_i128_from_u128(amount));
+	fn do_deposit(who: &T::AccountId, pool_id: LiquidityPoolId, amount: Balance) -> DispatchResult {
+		Self::update_velocity_usage(who, amount, true)?;
+		T::LiquidityCurrency::transfer(who, &Self::account_id(), amount)?;
+		Self::update_balance(who, pool_id, fixed_i128_from_balance(amount).map_err(Error::<T>::from)?)?;
+
+		Ok(())
+	}
+
+	fn do_deposit_position_margin(
+		who: &T::AccountId,
+		position_id: PositionId,
+		amount: Balance,
+	) -> result::Result<LiquidityPoolId, DispatchError> {
+		let mut position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+		ensure!(position.owner == *who, Error::<T>::PositionNotOpenedByTrader);
+		Self::do_deposit(who, position.pool, amount)?;
+
+		let amount = fixed_i128_from_balance(amount).map_err(Error::<T>::from)?;
+		position.margin_held = position
+			.margin_held
+			.checked_add(&amount)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let margin_held = Self::margin_held(who, position.pool)
+			.checked_add(&amount)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		<MarginHeld<T>>::insert(who, position.pool, margin_held);
+		let pool_id = position.pool;
+		<Positions<T>>::insert(position_id, position);
+
+		Ok(pool_id)
+	}
+
+	fn do_transfer_position(
+		who: &T::AccountId,
+		position_id: PositionId,
+		dest: &T::AccountId,
+	) -> result::Result<LiquidityPoolId, DispatchError> {
+		let mut position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+		ensure!(position.owner == *who, Error::<T>::PositionNotOpenedByTrader);
+		Self::ensure_position_transferable(position_id)?;
+		let pool_id = position.pool;
+		let count = <PositionsByTrader<T>>::iter_prefix(dest)
+			.filter(|((p, _), _)| *p == pool_id)
+			.count();
+		ensure!(
+			count < T::GetTraderMaxOpenPositions::get(),
+			Error::<T>::TraderPositionsLimitReached
+		);
+
+		// orders and insurance are set up by the owner, and don't move with the position; the premium accrued is
+		// paid by the old owner
+		Self::pay_slippage_insurance_premium(who, position_id, &position)?;
+		StopLosses::remove(position_id);
+		TakeProfits::remove(position_id);
+		TrailingStops::remove(position_id);
+
+		// the margin held moves with the position, as balance backing it
+		let margin_held = position.margin_held;
+		ensure!(
+			Self::balances(who, pool_id) >= margin_held,
+			Error::<T>::InsufficientBalanceForTransfer
+		);
+		Self::update_balance(who, pool_id, fixed_i128_mul_signum(margin_held, -1))?;
+		Self::update_balance(dest, pool_id, margin_held)?;
+		let who_margin_held = Self::margin_held(who, pool_id)
+			.checked_sub(&margin_held)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let dest_margin_held = Self::margin_held(dest, pool_id)
+			.checked_add(&margin_held)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		if who_margin_held.is_zero() {
+			<MarginHeld<T>>::remove(who, pool_id);
+		} else {
+			<MarginHeld<T>>::insert(who, pool_id, who_margin_held);
+		}
+		<MarginHeld<T>>::insert(dest, pool_id, dest_margin_held);
+
+		<PositionsByTrader<T>>::remove(who, (pool_id, position_id));
+		<PositionsByTrader<T>>::insert(dest, (pool_id, position_id), ());
+		position.owner = dest.clone();
+		<Positions<T>>::insert(position_id, position);
+
+		Self::ensure_trader_safe(who, pool_id, Action::None)?;
+		Self::ensure_trader_safe(dest, pool_id, Action::None)?;
+		Self::emit_warnings(who, pool_id);
+		Self::emit_warnings(dest, pool_id);
+
+		Ok(pool_id)
+	}
+
+	/// Positions of copy trading, copied or copying, can't be transferred.
+	fn ensure_position_transferable(position_id: PositionId) -> DispatchResult {
+		ensure!(
+			Self::copied_position_leaders(position_id).is_none() && Self::copied_positions(position_id).is_empty(),
+			Error::<T>::CopyTradingPositionNotTransferable
+		);
+		Ok(())
+	}
+
+	fn do_withdraw(who: &T::AccountId, pool_id: LiquidityPoolId, amount: Balance) -> DispatchResult {
+		// Collateral backs positions only, and couldn't be withdrawn as liquidity.
+		let (free_margin, collateral_value) = Self::with_price_cache(|| -> DoubleFixedI128Result {
+			Ok((
+				Self::free_margin(who, pool_id)?,
+				Self::collateral_value_of_trader(who, pool_id)?,
+			))
+		})?;
+		let withdrawable = free_margin
+			.checked_sub(&collateral_value)
+			.ok_or(Error::<T>::ArithmeticOverflow)?;
+		let amount_fixedi128 = fixed_i128_from_balance(amount).map_err(Error::<T>::from)?;
+		ensure!(withdrawable >= amount_fixedi128, Error::<T>::InsufficientFreeMargin);
+		Self::update_velocity_usage(who, amount, false)?;
+
+		T::LiquidityCurrency::transfer(&Self::account_id(), who, amount)?;
+		Self::update_balance(who, pool_id, fixed_i128_mul_signum(amount_fixedi128, -1))?;
+		Self::emit_warnings(who, pool_id);
 
 		Ok(())
 	}
 
-	fn do_withdraw(who: &T::AccountId, pool_id: LiquidityPoolId, amount: Balance) -> DispatchResult {
+	fn do_deposit_collateral(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		amount: Balance,
+	) -> DispatchResult {
+		ensure!(
+			Self::collateral_haircut(currency_id).is_some(),
+			Error::<T>::CollateralNotAccepted
+		);
+		T::MultiCurrency::transfer(currency_id, who, &Self::account_id(), amount)?;
+		<Collaterals<T>>::try_mutate(who, (pool_id, currency_id), |c| -> DispatchResult {
+			*c = c.checked_add(amount).ok_or(Error::<T>::ArithmeticOverflow)?;
+			Ok(())
+		})
+	}
+
+	fn do_withdraw_collateral(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		amount: Balance,
+	) -> DispatchResult {
+		let collateral = Self::collaterals(who, (pool_id, currency_id));
+		let remaining = collateral
+			.checked_sub(amount)
+			.ok_or(Error::<T>::InsufficientCollateral)?;
 		let free_margin = Self::free_margin(who, pool_id)?;
-		let amount_fixedi128 = fixed_i128_from_u128(amount);
-		ensure!(free_margin >= amount_fixedi128, Error::<T>::InsufficientFreeMargin);
+		ensure!(
+			free_margin >= Self::collateral_value(currency_id, amount)?,
+			Error::<T>::InsufficientFreeMargin
+		);
 
-		T::LiquidityCurrency::transfer(&Self::account_id(), who, amount)?;
-		Self::update_balance(who, pool_id, fixed_i128_mul_signum(amount_fixedi128, -1));
+		Self::set_collateral(who, pool_id, currency_id, remaining);
+		T::MultiCurrency::transfer(currency_id, &Self::account_id(), who, amount)?;
+		Self::emit_warnings(who, pool_id);
 
 		Ok(())
 	}
 
+	fn do_claim_seized_collateral(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+	) -> result::Result<Balance, DispatchError> {
+		ensure!(T::LiquidityPools::is_owner(pool_id, who), Error::<T>::NotPoolOwner);
+		let amount = SeizedCollaterals::take(pool_id, currency_id);
+		T::MultiCurrency::transfer(currency_id, &Self::account_id(), who, amount)?;
+		Ok(amount)
+	}
+
 	fn do_trader_margin_call(who: &T::AccountId, pool_id: LiquidityPoolId) -> DispatchResult {
 		if !Self::is_trader_margin_called(who, pool_id) {
 			if Self::ensure_trader_safe(who, pool_id, Action::None).is_err() {
@@ -788,44 +4342,194 @@ impl<T: Trait> Module<T> {
 		match risk {
 			Risk::StopOut => {
 				// To stop out a trader:
-				//   1. Close the position with the biggest loss.
+				//   1. Close the first position in `trader_stop_out_order`.
 				//   2. Repeat step 1 until no stop out risk, or all positions of this trader has been closed.
+				//
+				// At most `GetStopOutChunkSize` positions are closed here, the rest in following blocks.
 
-				let mut positions: Vec<(PositionId, FixedI128)> = <PositionsByTrader<T>>::iter_prefix(who)
-					.filter_map(|((_, position_id), _)| {
-						let position = Self::positions(position_id)?;
-						if position.pool != pool_id {
-							return None;
-						}
+				let positions = Self::trader_stop_out_order(who, pool_id);
+				let mut budget = T::GetStopOutChunkSize::get();
+				Self::continue_trader_stop_out(who, pool_id, positions, &mut budget)?;
+				Ok(())
+			}
+			_ => Err(Error::<T>::NotReachedRiskThreshold.into()),
+		}
+	}
 
-						let unrealized_pl = Self::unrealized_pl_of_position(&position).ok()?;
-						let accumulated_swap_rate = Self::accumulated_swap_rate_of_position(&position).ok()?;
-						let unrealized = unrealized_pl.checked_add(&accumulated_swap_rate)?;
-						Some((position_id, unrealized))
-					})
-					.collect();
-				positions.sort_by(|x, y| x.1.cmp(&y.1));
-
-				for (id, _) in positions {
-					let _ = Self::do_close_position(who, id, None);
-					let new_risk = Self::check_trader(who, pool_id, Action::None)?;
-					match new_risk {
-						Risk::StopOut => {}
-						_ => break,
-					}
+	/// Cross margin positions of `who` in `pool_id`, in the order to close on a stop out: the biggest unrealized loss,
+	/// including accumulated swap, first. Ties are broken by the biggest notional value, and then by the latest
+	/// opened.
+	///
+	/// Positions whose loss or notional value couldn't be evaluated, like on unavailable prices, are excluded.
+	fn trader_stop_out_order(who: &T::AccountId, pool_id: LiquidityPoolId) -> Vec<PositionId> {
+		let mut positions: Vec<(FixedI128, FixedI128, PositionId)> = <PositionsByTrader<T>>::iter_prefix(who)
+			.filter(|((_, position_id), _)| !IsolatedPositions::contains_key(position_id))
+			.filter_map(|((_, position_id), _)| {
+				let position = Self::positions(position_id)?;
+				if position.pool != pool_id {
+					return None;
 				}
 
-				if Self::ensure_trader_safe(who, pool_id, Action::None).is_ok()
-					&& Self::is_trader_margin_called(who, pool_id)
-				{
-					<MarginCalledTraders<T>>::remove(who, pool_id);
+				let unrealized_pl = Self::unrealized_pl_of_position(&position).ok()?;
+				let accumulated_swap_rate = Self::accumulated_swap_rate_of_position(&position).ok()?;
+				let unrealized = unrealized_pl.checked_add(&accumulated_swap_rate)?;
+				let notional = Self::notional_of_position(&position).ok()?;
+				Some((unrealized, notional, position_id))
+			})
+			.collect();
+		positions.sort_by(|(pl_a, notional_a, id_a), (pl_b, notional_b, id_b)| {
+			pl_a.cmp(pl_b).then(notional_b.cmp(notional_a)).then(id_b.cmp(id_a))
+		});
+
+		positions.into_iter().map(|(_, _, id)| id).collect()
+	}
+
+	/// Close `positions` of a stopping out trader in order, until no stop out risk, all closed, or `budget` used up.
+	///
+	/// Positions not closed are kept in `PendingTraderStopOuts` if the trader still has stop out risk. Returns
+	/// `true` if the stop out finished.
+	fn continue_trader_stop_out(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		positions: Vec<PositionId>,
+		budget: &mut u32,
+	) -> result::Result<bool, DispatchError> {
+		let mut positions = positions.into_iter().peekable();
+		let mut risk = Self::check_trader(who, pool_id, Action::None)?;
+		while risk == Risk::StopOut && *budget > 0 {
+			let id = match positions.next() {
+				Some(id) => id,
+				None => break,
+			};
+			*budget -= 1;
+			if let Some(position) = Self::positions(id) {
+				let balance = Self::balances(who, pool_id);
+				let claim = Self::slippage_insurance_claim(who, pool_id, id, &position);
+				if let Ok(price) = Self::do_close_position(who, id, None) {
+					Self::record_stop_out(id, position, balance);
+					Self::pay_slippage_insurance_claim(who, pool_id, id, claim);
+					Self::deposit_event(RawEvent::PositionLiquidated(who.clone(), id, price));
 				}
-				Ok(())
 			}
-			_ => Err(Error::<T>::NotReachedRiskThreshold.into()),
+			risk = Self::check_trader(who, pool_id, Action::None)?;
+		}
+
+		if risk == Risk::StopOut && positions.peek().is_some() {
+			let remaining: Vec<PositionId> = positions.collect();
+			Self::deposit_event(RawEvent::TraderStopOutPending(who.clone(), pool_id, remaining.len() as u32));
+			<PendingTraderStopOuts<T>>::insert(who, pool_id, remaining);
+			Self::update_risk_candidates(who, pool_id);
+			return Ok(false);
+		}
+
+		<PendingTraderStopOuts<T>>::remove(who, pool_id);
+		if Self::ensure_trader_safe(who, pool_id, Action::None).is_ok() && Self::is_trader_margin_called(who, pool_id) {
+			<MarginCalledTraders<T>>::remove(who, pool_id);
+		}
+		Self::update_risk_candidates(who, pool_id);
+		Ok(true)
+	}
+
+	fn do_isolated_position_stop_out(position_id: PositionId, position: &Position<T>) -> DispatchResult {
+		ensure!(
+			Self::isolated_position(position_id).is_some(),
+			Error::<T>::PositionNotIsolated
+		);
+		ensure!(
+			Self::is_isolated_position_stop_out(position)?,
+			Error::<T>::NotReachedRiskThreshold
+		);
+		let balance = Self::balances(&position.owner, position.pool);
+		Self::do_close_position(&position.owner, position_id, None)?;
+		Self::record_stop_out(position_id, position.clone(), balance);
+		Ok(())
+	}
+
+	/// Record execution data of `position` closed on a trader stop out, with the trader's balance before closing.
+	fn record_stop_out(id: PositionId, position: Position<T>, balance_before: FixedI128) {
+		if T::GetStopOutDisputeWindow::get() == 0u32.into() {
+			return;
+		}
+
+		let price = match Self::price(position.pair.base, position.pair.quote) {
+			Ok(p) => p,
+			Err(_) => return,
+		};
+		let realized = Self::balances(&position.owner, position.pool).saturating_sub(balance_before);
+		let now = <frame_system::Module<T>>::block_number();
+		<StopOutRecords<T>>::insert(
+			now,
+			id,
+			StopOutRecord {
+				position,
+				price,
+				realized,
+			},
+		);
+	}
+
+	/// Slippage insurance claim of `position` of a stopping out trader, if insured: the shortfall of the trader's
+	/// equity below the stop out threshold, in proportion to the notional value of the position.
+	///
+	/// claim = notional_usd * (stop_out_threshold - margin_level)
+	fn slippage_insurance_claim(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		position_id: PositionId,
+		position: &Position<T>,
+	) -> Balance {
+		if !<InsuredPositions<T>>::contains_key(position_id) {
+			return 0;
+		}
+
+		let stop_out: FixedI128 = Self::risk_threshold_of_trader(who, pool_id).stop_out.into();
+		Self::margin_level(who, pool_id)
+			.ok()
+			.and_then(|margin_level| stop_out.checked_sub(&margin_level))
+			.filter(|shortfall| shortfall.is_positive())
+			.and_then(|shortfall| {
+				let notional = fixed_i128_checked_abs(position.leveraged_debits)?;
+				let notional_usd = Self::usd_value(position.pair.quote, notional).ok()?;
+				notional_usd.checked_mul(&shortfall)
+			})
+			.and_then(|claim| balance_from_fixed_i128(claim).ok())
+			.unwrap_or(0)
+	}
+
+	/// Pay slippage insurance `claim` of a stopped out position from the insurance fund to the trader's balance,
+	/// up to the fund's balance.
+	fn pay_slippage_insurance_claim(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		position_id: PositionId,
+		claim: Balance,
+	) {
+		let fund = T::GetInsuranceFundAccountId::get();
+		let amount = cmp::min(claim, T::LiquidityCurrency::free_balance(&fund));
+		if amount == 0 {
+			return;
+		}
+
+		let paid = with_transaction_result(|| {
+			T::LiquidityCurrency::transfer(&fund, &Self::account_id(), amount)?;
+			Self::update_balance(who, pool_id, fixed_i128_from_balance(amount).map_err(Error::<T>::from)?)
+		});
+		if paid.is_ok() {
+			Self::deposit_event(RawEvent::SlippageInsuranceClaimPaid(who.clone(), position_id, amount));
 		}
 	}
 
+	/// Remove stop out records out of the dispute window.
+	fn prune_stop_out_records(now: T::BlockNumber) -> Weight {
+		let window = T::GetStopOutDisputeWindow::get();
+		if window == 0u32.into() || now <= window {
+			return 0;
+		}
+
+		<StopOutRecords<T>>::remove_prefix(now - window - 1u32.into());
+		T::DbWeight::get().writes(1)
+	}
+
 	fn do_liquidity_pool_margin_call(pool: LiquidityPoolId) -> DispatchResult {
 		if !Self::is_pool_margin_called(&pool) {
 			if Self::ensure_pool_safe(pool, Action::None).is_err() {
@@ -848,21 +4552,435 @@ impl<T: Trait> Module<T> {
 		Ok(())
 	}
 
-	fn do_liquidity_pool_force_close(pool: LiquidityPoolId) -> DispatchResult {
+	/// Auto-deleverage and force close `pool`. Returns `false` if auto-deleveraging made the pool safe, and no
+	/// position was force closed.
+	fn do_liquidity_pool_force_close(pool: LiquidityPoolId) -> result::Result<bool, DispatchError> {
 		match Self::check_pool(pool, Action::None) {
 			Ok(Risk::StopOut) => {
-				PositionsByPool::iter_prefix(pool).for_each(|((_, position_id), _)| {
-					let _ = Self::liquidity_pool_close_position(pool, position_id);
-				});
+				let mut budget = T::GetStopOutChunkSize::get();
+				if let Some(rate) = Self::auto_deleverage_rate() {
+					if Self::auto_deleverage_pool(pool, rate, &mut budget)? {
+						return Ok(false);
+					}
+				}
+
+				let (enp, ell) = Self::enp_and_ell_with_action(pool, Action::None)?;
+				let penalty_tier = Self::force_close_penalty_tier(cmp::min(enp, ell));
+				let positions = PositionsByPool::iter_prefix(pool)
+					.map(|((_, position_id), _)| position_id)
+					.collect();
+				Self::continue_liquidity_pool_force_close(pool, penalty_tier, positions, &mut budget);
+				Ok(true)
+			}
+			_ => Err(Error::<T>::NotReachedRiskThreshold.into()),
+		}
+	}
+
+	/// Reduce profitable positions of `pool` by `rate`, the most profitable first, until the pool is safe or `budget`
+	/// used up. Ties are broken by position ID, the earliest opened first. Positions that cannot be reduced are
+	/// skipped.
+	///
+	/// Returns `true` if the pool became safe.
+	fn auto_deleverage_pool(
+		pool: LiquidityPoolId,
+		rate: Permill,
+		budget: &mut u32,
+	) -> result::Result<bool, DispatchError> {
+		let mut profitable: Vec<(FixedI128, PositionId, Position<T>)> = PositionsByPool::iter_prefix(pool)
+			.filter_map(|((_, position_id), _)| {
+				let position = Self::positions(position_id)?;
+				let unrealized_pl = Self::unrealized_pl_of_position(&position).ok()?;
+				if unrealized_pl.is_positive() {
+					Some((unrealized_pl, position_id, position))
+				} else {
+					None
+				}
+			})
+			.collect();
+		profitable.sort_by(|(pl_a, id_a, _), (pl_b, id_b, _)| pl_b.cmp(pl_a).then(id_a.cmp(id_b)));
+
+		let mut deleveraged = 0u32;
+		for (_, position_id, position) in profitable {
+			if *budget == 0 {
+				break;
+			}
+			*budget -= 1;
 
-				if Self::ensure_pool_safe(pool, Action::None).is_ok() && Self::is_pool_margin_called(&pool) {
+			let held = position
+				.leveraged_held
+				.checked_mul(&rate.into())
+				.ok_or(Error::<T>::NumOutOfBound)?;
+			if with_transaction_result(|| {
+				Self::do_close_position_partial(&position.owner, position_id, rate, None).map(|_| ())
+			})
+			.is_err()
+			{
+				continue;
+			}
+			deleveraged += 1;
+			Self::deposit_event(RawEvent::PositionAutoDeleveraged(
+				position.owner,
+				position_id,
+				pool,
+				rate,
+				held,
+			));
+
+			if Self::ensure_pool_safe(pool, Action::None).is_ok() {
+				if Self::is_pool_margin_called(&pool) {
 					MarginCalledPools::remove(pool);
 				}
-				Ok(())
+				Self::update_pool_risk_candidates(pool);
+				Self::deposit_event(RawEvent::LiquidityPoolAutoDeleveraged(pool, deleveraged));
+				return Ok(true);
+			}
+		}
+		Ok(false)
+	}
+
+	/// Pay the keeper reward for liquidating `liquidated` notional value of `who`'s positions, `closed` of them
+	/// closed, from the trader's balance to `keeper`, up to the trader's balance.
+	fn pay_trader_keeper_reward(
+		keeper: &T::AccountId,
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		liquidated: FixedI128,
+		closed: u64,
+	) -> DispatchResult {
+		let reward =
+			fixed_i128_from_balance(Self::keeper_reward_amount(liquidated, closed)?).map_err(Error::<T>::from)?;
+		let paid = cmp::min(reward, cmp::max(Self::balances(who, pool_id), FixedI128::zero()));
+		if paid.is_zero() {
+			return Ok(());
+		}
+		let amount = balance_from_fixed_i128(paid).map_err(Error::<T>::from)?;
+		T::LiquidityCurrency::transfer(&Self::account_id(), keeper, amount)?;
+		Self::update_balance(who, pool_id, fixed_i128_mul_signum(paid, -1))?;
+		Self::deposit_event(RawEvent::KeeperRewardPaid(keeper.clone(), amount));
+		Ok(())
+	}
+
+	/// Pay the keeper reward for liquidating `liquidated` notional value of positions in `pool`, `closed` of them
+	/// closed, from the pool's liquidity to `keeper`, up to the liquidity.
+	fn pay_pool_keeper_reward(
+		keeper: &T::AccountId,
+		pool: LiquidityPoolId,
+		liquidated: FixedI128,
+		closed: u64,
+	) -> DispatchResult {
+		let liquidity = <T::LiquidityPools as LiquidityPools<T::AccountId>>::liquidity(pool);
+		let amount = cmp::min(Self::keeper_reward_amount(liquidated, closed)?, liquidity);
+		if amount == 0 {
+			return Ok(());
+		}
+		<T::LiquidityPools as LiquidityPools<T::AccountId>>::withdraw_liquidity(keeper, pool, amount)?;
+		Self::deposit_event(RawEvent::KeeperRewardPaid(keeper.clone(), amount));
+		Ok(())
+	}
+
+	/// Keeper reward for liquidating `liquidated` notional value with `closed` positions closed, in USD. The flat
+	/// reward is only paid if any position was closed, so that calls liquidating nothing aren't rewarded.
+	fn keeper_reward_amount(liquidated: FixedI128, closed: u64) -> result::Result<Balance, DispatchError> {
+		match Self::keeper_reward() {
+			None => Ok(0),
+			Some(KeeperReward::Flat(amount)) => Ok(if closed > 0 { amount } else { 0 }),
+			Some(KeeperReward::Rate(rate)) => {
+				let reward = liquidated
+					.checked_mul(&rate.into())
+					.ok_or(Error::<T>::ArithmeticOverflow)?;
+				balance_from_fixed_i128(reward).map_err(|e| Error::<T>::from(e).into())
 			}
-			_ => Err(Error::<T>::NotReachedRiskThreshold.into()),
 		}
 	}
+
+	/// Close `positions` of a force closing pool with `penalty_tier`, until all closed or `budget` used up.
+	///
+	/// Positions not closed are kept in `PendingPoolForceCloses`. Returns `true` if the force closure finished.
+	fn continue_liquidity_pool_force_close(
+		pool: LiquidityPoolId,
+		penalty_tier: ForceClosePenaltyTier,
+		positions: Vec<PositionId>,
+		budget: &mut u32,
+	) -> bool {
+		let mut positions = positions.into_iter();
+		positions.by_ref().take(*budget as usize).for_each(|position_id| {
+			*budget -= 1;
+			let _ = Self::liquidity_pool_close_position(pool, position_id, &penalty_tier);
+		});
+
+		let remaining: Vec<PositionId> = positions.collect();
+		if !remaining.is_empty() {
+			Self::deposit_event(RawEvent::LiquidityPoolForceClosePending(pool, remaining.len() as u32));
+			PendingPoolForceCloses::insert(pool, (penalty_tier, remaining));
+			Self::update_pool_risk_candidates(pool);
+			return false;
+		}
+
+		PendingPoolForceCloses::remove(pool);
+		if Self::ensure_pool_safe(pool, Action::None).is_ok() && Self::is_pool_margin_called(&pool) {
+			MarginCalledPools::remove(pool);
+		}
+		Self::update_pool_risk_candidates(pool);
+		true
+	}
+
+	/// Continue pending force closures and stop outs, closing at most `GetStopOutChunkSize` positions in total.
+	/// Returns the weight consumed.
+	///
+	/// Force closures go first, as an unsafe pool puts all of its traders at risk.
+	fn process_pending_stop_outs() -> Weight {
+		let chunk_size = T::GetStopOutChunkSize::get();
+		let mut budget = chunk_size;
+
+		// each pending entry has at least one position to close, so no more than `budget` entries are needed
+		let pools: Vec<_> = PendingPoolForceCloses::iter().take(budget as usize).collect();
+		for (pool, (penalty_tier, positions)) in pools {
+			if budget == 0 {
+				break;
+			}
+			if Self::continue_liquidity_pool_force_close(pool, penalty_tier, positions, &mut budget) {
+				Self::deposit_event(RawEvent::LiquidityPoolForceCloseFinished(pool));
+			}
+		}
+
+		let traders: Vec<_> = <PendingTraderStopOuts<T>>::iter().take(budget as usize).collect();
+		for (who, pool_id, positions) in traders {
+			if budget == 0 {
+				break;
+			}
+			let finished = with_transaction_result(|| Self::continue_trader_stop_out(&who, pool_id, positions, &mut budget));
+			if let Ok(true) = finished {
+				Self::deposit_event(RawEvent::TraderStopOutFinished(who, pool_id));
+			}
+		}
+
+		let closed = Weight::from(chunk_size - budget);
+		T::DbWeight::get()
+			.reads(2)
+			.saturating_add(closed.saturating_mul(Self::stop_out_position_weight()))
+	}
+
+	/// Cancel pending orders expiring at `now`, at most `GetStopOutChunkSize`. Orders over the limit are deferred to
+	/// the next block.
+	fn cancel_expired_orders(now: T::BlockNumber) -> Weight {
+		let order_ids: Vec<OrderId> = <ExpiringOrders<T>>::iter_prefix(now).map(|(id, _)| id).collect();
+		let chunk_size = T::GetStopOutChunkSize::get() as usize;
+		let next = now.saturating_add(1u32.into());
+		for (i, order_id) in order_ids.iter().enumerate() {
+			let order = match Self::pending_orders(order_id) {
+				Some(o) => o,
+				None => continue,
+			};
+			if i < chunk_size {
+				Self::remove_order(*order_id, &order);
+				Self::deposit_event(RawEvent::OrderExpired(order.owner, *order_id));
+			} else {
+				Self::put_order_expiry(*order_id, next);
+			}
+		}
+		<ExpiringOrders<T>>::remove_prefix(now);
+
+		// an order, its expiry and the trader's order count are read; cancelled ones write all of them and the
+		// expiry index, deferred ones the expiry and its index
+		let count = order_ids.len() as Weight;
+		let cancelled = cmp::min(count, chunk_size as Weight);
+		let deferred = count.saturating_sub(cancelled);
+		let reads = count.saturating_mul(2).saturating_add(cancelled).saturating_add(1);
+		let writes = cancelled.saturating_mul(4).saturating_add(deferred.saturating_mul(3));
+		T::DbWeight::get().reads_writes(reads, writes)
+	}
+
+	/// Close positions expiring at `now` at market, at most `GetStopOutChunkSize`. Positions over the limit, or failed
+	/// to close, are deferred to the next block.
+	fn close_expired_positions(now: T::BlockNumber) -> Weight {
+		let position_ids: Vec<PositionId> = <ExpiringPositions<T>>::iter_prefix(now).map(|(id, _)| id).collect();
+		let chunk_size = T::GetStopOutChunkSize::get() as usize;
+		let next = now.saturating_add(1u32.into());
+		for (i, position_id) in position_ids.iter().enumerate() {
+			let position = match Self::positions(position_id) {
+				Some(p) => p,
+				None => continue,
+			};
+			if i < chunk_size {
+				let closed = with_transaction_result(|| Self::do_close_position(&position.owner, *position_id, None));
+				if let Ok(price) = closed {
+					Self::update_risk_candidates(&position.owner, position.pool);
+					Self::deposit_event(RawEvent::PositionExpired(position.owner, *position_id, price));
+					continue;
+				}
+			}
+			Self::put_position_expiry(*position_id, next);
+		}
+		<ExpiringPositions<T>>::remove_prefix(now);
+
+		let count = position_ids.len() as Weight;
+		let closed = cmp::min(count, chunk_size as Weight);
+		T::DbWeight::get()
+			.reads_writes(count.saturating_add(1), count.saturating_sub(closed).saturating_mul(3))
+			.saturating_add(closed.saturating_mul(Self::stop_out_position_weight()))
+	}
+
+	/// Start a swap settlement pass of all open positions if `now` is on `GetSwapSettlementInterval` and no pass is in
+	/// progress, and settle the next positions of the pass, at most `GetStopOutChunkSize`. The rest are settled in
+	/// following blocks, continuing from `SwapSettlementCursor`.
+	fn settle_swaps(now: T::BlockNumber) -> Weight {
+		let prefix = <Positions<T> as StoragePrefixedMap<Position<T>>>::final_prefix();
+		let cursor = match Self::swap_settlement_cursor() {
+			Some(c) => c,
+			None => {
+				let interval = T::GetSwapSettlementInterval::get();
+				if interval == 0u32.into() || now % interval != 0u32.into() {
+					return T::DbWeight::get().reads(1);
+				}
+				prefix.to_vec()
+			}
+		};
+
+		let (position_ids, cursor) =
+			Self::map_keys_after::<PositionId>(&prefix, Some(cursor), T::GetStopOutChunkSize::get());
+		for position_id in position_ids.iter() {
+			let _ = with_transaction_result(|| Self::settle_swap(*position_id));
+		}
+		match cursor {
+			Some(c) => SwapSettlementCursor::put(c),
+			None => SwapSettlementCursor::kill(),
+		}
+
+		// a storage key read per position, and the cursor read and write
+		let settled = position_ids.len() as Weight;
+		let position_weight = SWAP_SETTLEMENT_WEIGHT_PER_POSITION.saturating_add(T::DbWeight::get().reads_writes(
+			SWAP_SETTLEMENT_READS_PER_POSITION.saturating_add(1),
+			SWAP_SETTLEMENT_WRITES_PER_POSITION,
+		));
+		T::DbWeight::get()
+			.reads_writes(1, 1)
+			.saturating_add(settled.saturating_mul(position_weight))
+	}
+
+	/// Settle the accrued swap of `position_id` into the balance of its owner, and reset its open accumulated swap
+	/// rate. Returns `false` if left unsettled, as the pool liquidity can't pay it, or the owner's balance can't
+	/// cover it.
+	///
+	/// The margin held of an isolated position changes by the settled amount, so that its equity is kept.
+	fn settle_swap(position_id: PositionId) -> result::Result<bool, DispatchError> {
+		let mut position = match Self::positions(position_id) {
+			Some(p) => p,
+			None => return Ok(false),
+		};
+		let swap = Self::accumulated_swap_rate_of_position(&position)?;
+		if swap.is_zero() {
+			return Ok(false);
+		}
+
+		let who = position.owner.clone();
+		let is_isolated = Self::isolated_position(position_id).is_some();
+		let balance = Self::balances(&who, position.pool);
+		if swap.is_positive() {
+			// As realizing profit, a negative balance is repaid by withdrawing less.
+			let pool_withdraw = if balance.is_negative() {
+				cmp::max(
+					swap.checked_add(&balance).ok_or(Error::<T>::ArithmeticOverflow)?,
+					FixedI128::zero(),
+				)
+			} else {
+				swap
+			};
+			let amount = balance_from_fixed_i128(pool_withdraw).map_err(Error::<T>::from)?;
+			if amount > <T::LiquidityPools as LiquidityPools<T::AccountId>>::liquidity(position.pool) {
+				return Ok(false);
+			}
+			if amount > 0 {
+				<T::LiquidityPools as LiquidityPools<T::AccountId>>::withdraw_liquidity(
+					&Self::account_id(),
+					position.pool,
+					amount,
+				)?;
+			}
+		} else {
+			let paid = fixed_i128_mul_signum(swap, -1);
+			if balance < paid || (is_isolated && position.margin_held < paid) {
+				return Ok(false);
+			}
+			<T::LiquidityPools as LiquidityPools<T::AccountId>>::deposit_liquidity(
+				&Self::account_id(),
+				position.pool,
+				balance_from_fixed_i128(paid).map_err(Error::<T>::from)?,
+			)?;
+		}
+		Self::update_balance(&who, position.pool, swap)?;
+
+		if is_isolated {
+			position.margin_held = position
+				.margin_held
+				.checked_add(&swap)
+				.ok_or(Error::<T>::NumOutOfBound)?;
+			<MarginHeld<T>>::try_mutate(&who, position.pool, |margin_held| -> DispatchResult {
+				*margin_held = margin_held.checked_add(&swap).ok_or(Error::<T>::NumOutOfBound)?;
+				Ok(())
+			})?;
+		}
+		position.open_accumulated_swap_rate =
+			T::LiquidityPools::accumulated_swap_rate(position.pool, position.pair, position.leverage.is_long());
+		<Positions<T>>::insert(position_id, position);
+
+		Self::deposit_event(RawEvent::SwapSettled(who, position_id, swap));
+
+		Ok(true)
+	}
+
+	/// Weight of closing a position in a stop out or force closure.
+	fn stop_out_position_weight() -> Weight {
+		PENDING_STOP_OUT_WEIGHT_PER_POSITION
+			.saturating_add(T::DbWeight::get().reads_writes(
+				PENDING_STOP_OUT_READS_PER_POSITION,
+				PENDING_STOP_OUT_WRITES_PER_POSITION,
+			))
+			.saturating_add(Self::pool_risk_weight())
+			.saturating_add(Self::warnings_weight())
+			.saturating_add(Self::copy_followers_weight())
+	}
+
+	/// Worst case weight of closing positions in a stop out or force closure call, at most `GetStopOutChunkSize`.
+	fn stop_out_chunk_weight() -> Weight {
+		Weight::from(T::GetStopOutChunkSize::get()).saturating_mul(Self::stop_out_position_weight())
+	}
+
+	/// Worst case weight of iterating open positions of a trader, at most `GetTraderMaxOpenPositions`.
+	fn trader_positions_weight() -> Weight {
+		let position_weight =
+			TRADER_WEIGHT_PER_POSITION.saturating_add(T::DbWeight::get().reads(TRADER_READS_PER_POSITION));
+		(T::GetTraderMaxOpenPositions::get() as Weight).saturating_mul(position_weight)
+	}
+
+	/// Worst case weight of collecting open position ids of a pool, at most `GetPoolMaxOpenPositions`.
+	fn pool_positions_weight() -> Weight {
+		T::DbWeight::get().reads(T::GetPoolMaxOpenPositions::get() as Weight)
+	}
+
+	/// Worst case weight of a pool risk check, on top of its positions snapshots: liabilities outside of margin
+	/// positions.
+	fn pool_risk_weight() -> Weight {
+		T::PoolLiabilities::liabilities_weight()
+	}
+
+	/// Worst case weight of the warning risk checks of a trader and a pool after a trade.
+	fn warnings_weight() -> Weight {
+		T::DbWeight::get()
+			.reads_writes(2, 2)
+			.saturating_add(Self::trader_positions_weight())
+			.saturating_add(Self::pool_risk_weight())
+	}
+
+	/// Worst case weight of copying a position open or close of a leader, for at most `GetMaxCopyFollowers`
+	/// followers, including their risk checks.
+	fn copy_followers_weight() -> Weight {
+		let follower_weight = COPY_WEIGHT_PER_FOLLOWER
+			.saturating_add(T::DbWeight::get().reads_writes(COPY_READS_PER_FOLLOWER, COPY_WRITES_PER_FOLLOWER))
+			.saturating_add(Self::trader_positions_weight())
+			.saturating_add(Self::pool_risk_weight())
+			.saturating_add(Self::warnings_weight());
+		(T::GetMaxCopyFollowers::get() as Weight).saturating_mul(follower_weight)
+	}
 }
 
 // Storage helpers
@@ -871,6 +4989,29 @@ impl<T: Trait> Module<T> {
 		MODULE_ID.into_account()
 	}
 
+	/// At most `limit` keys of the `twox_64_concat` map with `prefix`, after the raw storage key `cursor`, or from
+	/// the first key if `None`.
+	///
+	/// Returns the keys, and the raw storage key of the last one to continue from. The returned cursor is `None`
+	/// once the end of the map is reached.
+	fn map_keys_after<K: Decode>(prefix: &[u8], cursor: Option<Vec<u8>>, limit: u32) -> (Vec<K>, Option<Vec<u8>>) {
+		let mut raw_key = cursor.unwrap_or_else(|| prefix.to_vec());
+		let mut keys = vec![];
+		while keys.len() < limit as usize {
+			match sp_io::storage::next_key(&raw_key) {
+				Some(next) if next.starts_with(prefix) => {
+					// raw key = prefix ++ twox_64(key) ++ key
+					if let Some(key) = next.get(prefix.len() + 8..).and_then(|mut k| K::decode(&mut k).ok()) {
+						keys.push(key);
+					}
+					raw_key = next;
+				}
+				_ => return (keys, None),
+			}
+		}
+		(keys, Some(raw_key))
+	}
+
 	fn insert_position(
 		who: &T::AccountId,
 		pool_id: LiquidityPoolId,
@@ -896,26 +5037,139 @@ impl<T: Trait> Module<T> {
 			} else {
 				snapshot.positions_count = snapshot.positions_count + 1;
 				snapshot.short.held = snapshot
-					.long
+					.short
 					.held
 					.checked_add(&position.leveraged_held)
 					.ok_or(Error::<T>::NumOutOfBound)?;
 				snapshot.short.debits = snapshot
-					.long
+					.short
 					.debits
 					.checked_add(&position.leveraged_debits)
 					.ok_or(Error::<T>::NumOutOfBound)?;
 			}
 			Ok(())
 		})?;
+		OpenInterests::try_mutate(pair, |open_interest| -> DispatchResult {
+			open_interest.add(&position).ok_or(Error::<T>::NumOutOfBound)?;
+			Ok(())
+		})?;
+		Self::accumulate_trading_volume(pool_id, pair, position.leveraged_debits, true);
+
+		let margin_held = Self::margin_held(who, pool_id)
+			.checked_add(&position.margin_held)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+
+		NextPositionId::mutate(|id| *id += 1);
+
+		<MarginHeld<T>>::insert(who, pool_id, margin_held);
+		<Positions<T>>::insert(id, position);
+		<PositionsByTrader<T>>::insert(who, (pool_id, id), ());
+		PositionsByPool::insert(pool_id, (pair, id), ());
+		<PositionOpenedAt<T>>::insert(id, <frame_system::Module<T>>::block_number());
+		if Self::margin_mode(who, pool_id) == MarginMode::Isolated {
+			IsolatedPositions::insert(id, ());
+		}
+		if Self::slippage_insurance(who, pool_id).is_some() {
+			if let Some(rate) = Self::slippage_insurance_premium_rate(pair) {
+				<InsuredPositions<T>>::insert(id, (<frame_system::Module<T>>::block_number(), rate));
+			}
+		}
+
+		Ok(id)
+	}
+
+	/// Net an `opened` position into the open position `position_id` of `who`, summing amounts and margin held.
+	///
+	/// Debits are summed, so the open price becomes the debits-weighted average. The open accumulated swap rate is
+	/// weighted the same way, keeping the accumulated swap rate of both parts.
+	fn net_position(who: &T::AccountId, position_id: PositionId, opened: Position<T>) -> DispatchResult {
+		let mut position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+
+		// (open_rate * debits + opened_rate * opened_debits) / (debits + opened_debits)
+		let leveraged_debits = position
+			.leveraged_debits
+			.checked_add(&opened.leveraged_debits)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let open_accumulated_swap_rate = position
+			.open_accumulated_swap_rate
+			.checked_mul(&position.leveraged_debits)
+			.and_then(|r| {
+				opened
+					.open_accumulated_swap_rate
+					.checked_mul(&opened.leveraged_debits)
+					.and_then(|o| r.checked_add(&o))
+			})
+			.and_then(|r| r.checked_div(&leveraged_debits))
+			.ok_or(Error::<T>::NumOutOfBound)?;
+
+		position.leveraged_held = position
+			.leveraged_held
+			.checked_add(&opened.leveraged_held)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		position.leveraged_debits = leveraged_debits;
+		position.open_accumulated_swap_rate = open_accumulated_swap_rate;
+		position.margin_held = position
+			.margin_held
+			.checked_add(&opened.margin_held)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+
+		PositionsSnapshots::try_mutate(position.pool, position.pair, |snapshot| -> DispatchResult {
+			let amounts = if position.leverage.is_long() {
+				&mut snapshot.long
+			} else {
+				&mut snapshot.short
+			};
+			amounts.held = amounts
+				.held
+				.checked_add(&opened.leveraged_held)
+				.ok_or(Error::<T>::NumOutOfBound)?;
+			amounts.debits = amounts
+				.debits
+				.checked_add(&opened.leveraged_debits)
+				.ok_or(Error::<T>::NumOutOfBound)?;
+			Ok(())
+		})?;
+		OpenInterests::try_mutate(position.pair, |open_interest| -> DispatchResult {
+			open_interest.increase(&opened).ok_or(Error::<T>::NumOutOfBound)?;
+			Ok(())
+		})?;
+		Self::accumulate_trading_volume(position.pool, position.pair, opened.leveraged_debits, true);
+
+		let margin_held = Self::margin_held(who, position.pool)
+			.checked_add(&opened.margin_held)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		<MarginHeld<T>>::insert(who, position.pool, margin_held);
+
+		// The position before netting pays its premium now, and the netted one on closing, both since now.
+		if let Some((_, rate)) = Self::insured_positions(position_id) {
+			let before = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+			Self::pay_slippage_insurance_premium(who, position_id, &before)?;
+			<InsuredPositions<T>>::insert(position_id, (<frame_system::Module<T>>::block_number(), rate));
+		}
 
-		NextPositionId::mutate(|id| *id += 1);
+		<Positions<T>>::insert(position_id, position);
 
-		<Positions<T>>::insert(id, position);
-		<PositionsByTrader<T>>::insert(who, (pool_id, id), ());
-		PositionsByPool::insert(pool_id, (pair, id), ());
+		Ok(())
+	}
 
-		Ok(id)
+	/// Add notional `leveraged_debits` of an opened or closed position to the trading volume of `pair` in
+	/// `pool_id`, rolling over first if needed.
+	fn accumulate_trading_volume(
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		leveraged_debits: FixedI128,
+		opened: bool,
+	) {
+		let notional =
+			u128_from_fixed_i128(fixed_i128_checked_abs(leveraged_debits).unwrap_or_else(FixedI128::max_value));
+		<TradingVolumes<T>>::mutate(pool_id, pair, |volumes| {
+			volumes.roll_over(<frame_system::Module<T>>::block_number(), T::GetVolumePeriod::get());
+			if opened {
+				volumes.current.opened = volumes.current.opened.saturating_add(notional);
+			} else {
+				volumes.current.closed = volumes.current.closed.saturating_add(notional);
+			}
+		});
 	}
 
 	fn remove_position(
@@ -925,7 +5179,26 @@ impl<T: Trait> Module<T> {
 	) -> result::Result<(), DispatchError> {
 		<Positions<T>>::remove(position_id);
 		<PositionsByTrader<T>>::remove(who, (position.pool, position_id));
+		let margin_held = Self::margin_held(who, position.pool)
+			.checked_sub(&position.margin_held)
+			.expect("margin held can't overflow; qed");
+		if margin_held.is_zero() {
+			<MarginHeld<T>>::remove(who, position.pool);
+		} else {
+			<MarginHeld<T>>::insert(who, position.pool, margin_held);
+		}
 		PositionsByPool::remove(position.pool, (position.pair, position_id));
+		<CopiedPositions<T>>::remove(position_id);
+		<CopiedPositionLeaders<T>>::remove(position_id);
+		StopLosses::remove(position_id);
+		TakeProfits::remove(position_id);
+		TrailingStops::remove(position_id);
+		IsolatedPositions::remove(position_id);
+		<PositionOpenedAt<T>>::remove(position_id);
+		<PositionTransferOffers<T>>::remove(position_id);
+		if let Some(expiry) = <PositionExpiries<T>>::take(position_id) {
+			<ExpiringPositions<T>>::remove(expiry, position_id);
+		}
 
 		PositionsSnapshots::mutate(position.pool, position.pair, |snapshot| {
 			if position.leverage.is_long() {
@@ -954,23 +5227,328 @@ impl<T: Trait> Module<T> {
 					.expect("pool amount can't overflow; qed");
 			}
 		});
+		OpenInterests::mutate(position.pair, |open_interest| open_interest.remove(position));
+		Self::accumulate_trading_volume(position.pool, position.pair, position.leveraged_debits, false);
+		Self::pay_slippage_insurance_premium(who, position_id, position)?;
 
-		// reset trader's equity to $0
+		// repay negative balance by seizing collateral, and reset the rest of trader's equity to $0
 		let has_position = <PositionsByTrader<T>>::iter_prefix(who).any(|((p, _), _)| p == position.pool);
 
 		if !has_position && Self::balances(who, position.pool).is_negative() {
-			<Balances<T>>::remove(who, position.pool);
+			Self::seize_collateral(who, position.pool)?;
+			let balance = Self::balances(who, position.pool);
+			if balance.is_negative() {
+				<Balances<T>>::remove(who, position.pool);
+				let shortfall = fixed_i128_checked_abs(balance)
+					.and_then(|b| balance_from_fixed_i128(b).ok())
+					.unwrap_or_default();
+
+				// the pool never received the unpaid loss; it's paid by the insurance fund as far as possible, and
+				// writing off the rest socializes it to the pool
+				let covered = T::InsuranceFund::cover_shortfall(&Self::account_id(), position.pool, shortfall)?;
+				if covered != 0 {
+					<T::LiquidityPools as LiquidityPools<T::AccountId>>::deposit_liquidity(
+						&Self::account_id(),
+						position.pool,
+						covered,
+					)?;
+				}
+				let socialized = shortfall.saturating_sub(covered);
+				if socialized != 0 {
+					Self::deposit_event(RawEvent::NegativeBalanceSocialized(
+						who.clone(),
+						position.pool,
+						socialized,
+					));
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Reduce `position` by its `closed` part, keeping the rest open.
+	fn reduce_position(
+		who: &T::AccountId,
+		position_id: PositionId,
+		position: &Position<T>,
+		closed: &Position<T>,
+	) -> DispatchResult {
+		let remaining = Position::<T> {
+			leveraged_held: position
+				.leveraged_held
+				.checked_sub(&closed.leveraged_held)
+				.ok_or(Error::<T>::NumOutOfBound)?,
+			leveraged_debits: position
+				.leveraged_debits
+				.checked_sub(&closed.leveraged_debits)
+				.ok_or(Error::<T>::NumOutOfBound)?,
+			margin_held: position
+				.margin_held
+				.checked_sub(&closed.margin_held)
+				.ok_or(Error::<T>::NumOutOfBound)?,
+			..position.clone()
+		};
+		<Positions<T>>::insert(position_id, remaining);
+		<MarginHeld<T>>::mutate(who, position.pool, |margin_held| {
+			*margin_held = margin_held
+				.checked_sub(&closed.margin_held)
+				.expect("margin held can't overflow; qed");
+		});
+
+		PositionsSnapshots::mutate(position.pool, position.pair, |snapshot| {
+			let amounts = if position.leverage.is_long() {
+				&mut snapshot.long
+			} else {
+				&mut snapshot.short
+			};
+			amounts.held = amounts
+				.held
+				.checked_sub(&closed.leveraged_held)
+				.expect("pool amount can't overflow; qed");
+			amounts.debits = amounts
+				.debits
+				.checked_sub(&closed.leveraged_debits)
+				.expect("pool amount can't overflow; qed");
+		});
+		OpenInterests::mutate(position.pair, |open_interest| open_interest.reduce(closed));
+		Self::accumulate_trading_volume(position.pool, position.pair, closed.leveraged_debits, false);
+
+		// The closed part pays its premium now, and the rest on closing, both since insured.
+		if let Some(insured) = Self::insured_positions(position_id) {
+			Self::pay_slippage_insurance_premium(who, position_id, closed)?;
+			<InsuredPositions<T>>::insert(position_id, insured);
+		}
+
+		Ok(())
+	}
+
+	/// Put `expiry` of `position_id`, replacing the existing one if any.
+	fn put_order_expiry(order_id: OrderId, expiry: T::BlockNumber) {
+		if let Some(existing) = <OrderExpiries<T>>::get(order_id) {
+			<ExpiringOrders<T>>::remove(existing, order_id);
+		}
+		<OrderExpiries<T>>::insert(order_id, expiry);
+		<ExpiringOrders<T>>::insert(expiry, order_id, ());
+	}
+
+	/// Remove the pending order `order_id`, and its expiry.
+	fn remove_order(order_id: OrderId, order: &PendingOrder<T::AccountId>) {
+		<PendingOrders<T>>::remove(order_id);
+		let count = Self::trader_pending_orders(&order.owner).saturating_sub(1);
+		if count == 0 {
+			<TraderPendingOrders<T>>::remove(&order.owner);
+		} else {
+			<TraderPendingOrders<T>>::insert(&order.owner, count);
+		}
+		if let Some(expiry) = <OrderExpiries<T>>::take(order_id) {
+			<ExpiringOrders<T>>::remove(expiry, order_id);
+		}
+	}
+
+	fn put_position_expiry(position_id: PositionId, expiry: T::BlockNumber) {
+		if let Some(existing) = <PositionExpiries<T>>::get(position_id) {
+			<ExpiringPositions<T>>::remove(existing, position_id);
+		}
+		<PositionExpiries<T>>::insert(position_id, expiry);
+		<ExpiringPositions<T>>::insert(expiry, position_id, ());
+	}
+
+	/// Put `trailing_stop` of `position_id`, and its stop loss price.
+	fn put_trailing_stop(position_id: PositionId, is_long: bool, trailing_stop: TrailingStop) {
+		StopLosses::insert(position_id, trailing_stop.stop_loss(is_long));
+		TrailingStops::insert(position_id, trailing_stop);
+	}
+
+	/// Commission of `pair` in `pool_id` in USD, on `notional` value in the quote currency.
+	///
+	/// commission = notional_usd * commission_rate
+	fn commission_of(pool_id: LiquidityPoolId, pair: TradingPair, notional: FixedI128) -> FixedI128Result {
+		let rate = T::LiquidityPools::commission(pool_id, pair);
+		Self::usd_value(pair.quote, notional)?
+			.checked_mul(&rate.into())
+			.ok_or(Error::<T>::NumOutOfBound.into())
+	}
+
+	/// Charge the commission on opening `position`, on its notional value at the open price.
+	fn charge_open_commission(who: &T::AccountId, position: &Position<T>) -> result::Result<Balance, DispatchError> {
+		let notional = fixed_i128_checked_abs(position.leveraged_debits).ok_or(Error::<T>::NumOutOfBound)?;
+		Self::charge_commission(who, position.pool, position.pair, notional)
+	}
+
+	/// Charge the commission on closing `position`, or its closed part, on its notional value at `close_price`.
+	fn charge_close_commission(
+		who: &T::AccountId,
+		position: &Position<T>,
+		close_price: FixedI128,
+	) -> result::Result<Balance, DispatchError> {
+		let notional = fixed_i128_checked_abs(position.leveraged_held)
+			.and_then(|held| held.checked_mul(&close_price))
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		Self::charge_commission(who, position.pool, position.pair, notional)
+	}
+
+	/// Charge the commission of `pool_id` on `notional` value in the quote currency of `pair`, from the trader's
+	/// balance to the pool liquidity, up to the trader's balance. Returns the charged amount.
+	fn charge_commission(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		notional: FixedI128,
+	) -> result::Result<Balance, DispatchError> {
+		let commission = Self::commission_of(pool_id, pair, notional)?;
+		let paid = cmp::min(commission, cmp::max(Self::balances(who, pool_id), FixedI128::zero()));
+		if paid.is_zero() {
+			return Ok(0);
+		}
+		let amount = balance_from_fixed_i128(paid).map_err(Error::<T>::from)?;
+		<T::LiquidityPools as LiquidityPools<T::AccountId>>::deposit_liquidity(&Self::account_id(), pool_id, amount)?;
+		Self::update_balance(who, pool_id, fixed_i128_mul_signum(paid, -1))?;
+
+		Ok(amount)
+	}
+
+	/// Charge the slippage insurance premium of `position`, if insured, from the trader's balance to the insurance
+	/// fund, up to the trader's balance. Started premium periods are charged in full.
+	///
+	/// premium = notional_usd * premium_rate * periods
+	fn pay_slippage_insurance_premium(
+		who: &T::AccountId,
+		position_id: PositionId,
+		position: &Position<T>,
+	) -> DispatchResult {
+		let (insured_at, rate) = match <InsuredPositions<T>>::take(position_id) {
+			Some(insured) => insured,
+			None => return Ok(()),
+		};
+		let period = T::GetSlippageInsurancePremiumPeriod::get();
+		if period == 0u32.into() {
+			return Ok(());
+		}
+
+		let elapsed = <frame_system::Module<T>>::block_number().saturating_sub(insured_at);
+		let periods: u128 = (elapsed.saturating_add(period - 1u32.into()) / period).unique_saturated_into();
+		let notional = fixed_i128_checked_abs(position.leveraged_debits).ok_or(Error::<T>::ArithmeticOverflow)?;
+		let premium = Self::usd_value(position.pair.quote, notional)?
+			.checked_mul(&rate.into())
+			.and_then(|p| p.checked_mul(&FixedI128::saturating_from_integer(periods)))
+			.ok_or(Error::<T>::ArithmeticOverflow)?;
+
+		let paid = cmp::min(premium, cmp::max(Self::balances(who, position.pool), FixedI128::zero()));
+		if paid.is_zero() {
+			return Ok(());
+		}
+		let amount = balance_from_fixed_i128(paid).map_err(Error::<T>::from)?;
+		T::LiquidityCurrency::transfer(&Self::account_id(), &T::GetInsuranceFundAccountId::get(), amount)?;
+		Self::update_balance(who, position.pool, fixed_i128_mul_signum(paid, -1))?;
+		Self::deposit_event(RawEvent::SlippageInsurancePremiumPaid(who.clone(), position_id, amount));
+
+		Ok(())
+	}
+
+	/// Seize collateral of `who` in `pool_id` to repay the negative balance, at collateral value.
+	///
+	/// Collaterals with higher haircuts are seized first. Seized amount is rounded up, and the rest of the collateral
+	/// is kept.
+	fn seize_collateral(who: &T::AccountId, pool_id: LiquidityPoolId) -> DispatchResult {
+		let mut collaterals: Vec<(CurrencyId, Balance)> = <Collaterals<T>>::iter_prefix(who)
+			.filter(|((p, _), _)| *p == pool_id)
+			.map(|((_, currency_id), amount)| (currency_id, amount))
+			.collect();
+		collaterals.sort_by_key(|&(currency_id, _)| {
+			let haircut = Self::collateral_haircut(currency_id);
+			(cmp::Reverse(haircut), currency_id)
+		});
+
+		for (currency_id, amount) in collaterals {
+			let balance = Self::balances(who, pool_id);
+			if !balance.is_negative() {
+				break;
+			}
+			let debt = fixed_i128_mul_signum(balance, -1);
+			let value = Self::collateral_value(currency_id, amount)?;
+			if value.is_zero() {
+				continue;
+			}
+
+			// seized = amount * debt / value, if collateral value covers the debt
+			let (seized, repaid) = if value > debt {
+				let seized = balance_mul_div_with_rounding(
+					amount,
+					balance_from_fixed_i128(debt).map_err(Error::<T>::from)?,
+					balance_from_fixed_i128(value).map_err(Error::<T>::from)?,
+					Rounding::Up,
+				)
+				.ok_or(Error::<T>::NumOutOfBound)?;
+				(cmp::min(seized, amount), debt)
+			} else {
+				(amount, value)
+			};
+
+			Self::set_collateral(who, pool_id, currency_id, amount - seized);
+			SeizedCollaterals::try_mutate(pool_id, currency_id, |c| -> DispatchResult {
+				*c = c.checked_add(seized).ok_or(Error::<T>::ArithmeticOverflow)?;
+				Ok(())
+			})?;
+			Self::update_balance(who, pool_id, repaid)?;
+
+			let repaid = balance_from_fixed_i128(repaid).map_err(Error::<T>::from)?;
+			Self::deposit_event(RawEvent::CollateralSeized(who.clone(), pool_id, currency_id, seized, repaid));
 		}
 
 		Ok(())
 	}
 
+	fn set_collateral(who: &T::AccountId, pool_id: LiquidityPoolId, currency_id: CurrencyId, amount: Balance) {
+		if amount == 0 {
+			<Collaterals<T>>::remove(who, (pool_id, currency_id));
+		} else {
+			<Collaterals<T>>::insert(who, (pool_id, currency_id), amount);
+		}
+	}
+
 	/// Update `who` balance in `pool_id` by `amount`.
 	///
 	/// Note this function guarantees op, don't use in possible no-op scenario.
-	fn update_balance(who: &T::AccountId, pool_id: LiquidityPoolId, amount: FixedI128) {
-		let new_balance = Self::balances(who, pool_id).saturating_add(amount);
+	fn update_balance(who: &T::AccountId, pool_id: LiquidityPoolId, amount: FixedI128) -> DispatchResult {
+		let new_balance = Self::balances(who, pool_id)
+			.checked_add(&amount)
+			.ok_or(Error::<T>::ArithmeticOverflow)?;
 		<Balances<T>>::insert(who, pool_id, new_balance);
+		Ok(())
+	}
+
+	/// Add deposit or withdraw `amount` to velocity usage of `who`, and ensure velocity limit not exceeded.
+	fn update_velocity_usage(who: &T::AccountId, amount: Balance, is_deposit: bool) -> DispatchResult {
+		let limit = match Self::velocity_limits(who).or_else(Self::global_velocity_limit) {
+			Some(l) => l,
+			None => return Ok(()),
+		};
+
+		let now = <frame_system::Module<T>>::block_number();
+		let mut usage = Self::velocity_usages(who);
+		if now >= usage.period_start.saturating_add(limit.period) {
+			usage = VelocityUsage {
+				period_start: now,
+				deposited: 0,
+				withdrew: 0,
+			};
+		}
+
+		if is_deposit {
+			usage.deposited = usage.deposited.saturating_add(amount);
+			if let Some(max) = limit.max_deposit {
+				ensure!(usage.deposited <= max, Error::<T>::DepositVelocityLimitExceeded);
+			}
+		} else {
+			usage.withdrew = usage.withdrew.saturating_add(amount);
+			if let Some(max) = limit.max_withdraw {
+				ensure!(usage.withdrew <= max, Error::<T>::WithdrawVelocityLimitExceeded);
+			}
+		}
+		<VelocityUsages<T>>::insert(who, usage);
+
+		Ok(())
 	}
 
 	fn ensure_can_open_more_position(who: &T::AccountId, pool: LiquidityPoolId, pair: TradingPair) -> DispatchResult {
@@ -983,7 +5561,7 @@ impl<T: Trait> Module<T> {
 			.count();
 		ensure!(
 			count < T::GetTraderMaxOpenPositions::get(),
-			Error::<T>::CannotOpenMorePosition
+			Error::<T>::TraderPositionsLimitReached
 		);
 		Ok(())
 	}
@@ -992,21 +5570,48 @@ impl<T: Trait> Module<T> {
 type PriceResult = result::Result<Price, DispatchError>;
 type FixedI128Result = result::Result<FixedI128, DispatchError>;
 type DoubleFixedI128Result = result::Result<(FixedI128, FixedI128), DispatchError>;
+type PoolRiskAmountsResult = result::Result<PoolRiskAmounts, DispatchError>;
 
 // Price helpers
 impl<T: Trait> Module<T> {
-	/// The price from oracle.
+	/// Run `f` with a price cache, so that each price is read from oracle at most once within `f`. Nested calls
+	/// share the outermost cache.
+	///
+	/// Only for calls within which oracle prices don't change, like an extrinsic or a block hook.
+	fn with_price_cache<R>(f: impl FnOnce() -> R) -> R {
+		if price_cache::with(|_| ()).is_some() {
+			return f();
+		}
+		let mut cache = BTreeMap::new();
+		price_cache::using(&mut cache, f)
+	}
+
+	/// The price from oracle, or from the price cache if in `with_price_cache`.
 	fn price(base: CurrencyId, quote: CurrencyId) -> PriceResult {
-		T::PriceProvider::get_price(base, quote).ok_or(Error::<T>::NoPrice.into())
+		if let Some(Some(price)) = price_cache::with(|cache| cache.get(&(base, quote)).copied()) {
+			return Ok(price);
+		}
+
+		let price = T::PriceProvider::get_price(base, quote).ok_or_else(|| Self::price_error(base, quote))?;
+		price_cache::with(|cache| cache.insert((base, quote), price));
+		Ok(price)
+	}
+
+	/// The error of an unavailable price, by the reason of `base` price, or `quote` price if `base` price is
+	/// available.
+	fn price_error(base: CurrencyId, quote: CurrencyId) -> Error<T> {
+		T::PriceStatus::unavailable_reason(base)
+			.or_else(|| T::PriceStatus::unavailable_reason(quote))
+			.map_or(Error::<T>::NoPrice, Into::into)
 	}
 
-	/// ask_price = price + ask_spread
-	fn ask_price(pool: LiquidityPoolId, pair: TradingPair, max: Option<Price>) -> FixedI128Result {
+	/// ask_price = price + ask_spread * (1 - discount)
+	fn ask_price(pool: LiquidityPoolId, pair: TradingPair, max: Option<Price>, discount: Permill) -> FixedI128Result {
 		let price = Self::price(pair.base, pair.quote)?;
 		let spread = T::LiquidityPools::ask_spread(pool, pair)
 			.ok_or(Error::<T>::NoAskSpread)
-			.map(Price::from_inner)?;
-		let ask_price: Price = price.saturating_add(spread);
+			.map(|s| Price::from_inner(s.saturating_sub(discount * s)))?;
+		let ask_price: Price = price.checked_add(&spread).ok_or(Error::<T>::ArithmeticOverflow)?;
 
 		if let Some(m) = max {
 			if ask_price > m {
@@ -1017,13 +5622,13 @@ impl<T: Trait> Module<T> {
 		Ok(fixed_i128_from_fixed_u128(ask_price))
 	}
 
-	/// bid_price = price - bid_spread
-	fn bid_price(pool: LiquidityPoolId, pair: TradingPair, min: Option<Price>) -> FixedI128Result {
+	/// bid_price = price - bid_spread * (1 - discount)
+	fn bid_price(pool: LiquidityPoolId, pair: TradingPair, min: Option<Price>, discount: Permill) -> FixedI128Result {
 		let price = Self::price(pair.base, pair.quote)?;
 		let spread = T::LiquidityPools::bid_spread(pool, pair)
 			.ok_or(Error::<T>::NoBidSpread)
-			.map(Price::from_inner)?;
-		let bid_price = price.saturating_sub(spread);
+			.map(|s| Price::from_inner(s.saturating_sub(discount * s)))?;
+		let bid_price = price.checked_sub(&spread).ok_or(Error::<T>::ArithmeticOverflow)?;
 
 		if let Some(m) = min {
 			if bid_price < m {
@@ -1042,6 +5647,15 @@ impl<T: Trait> Module<T> {
 		};
 		amount.checked_mul(&price).ok_or(Error::<T>::NumOutOfBound.into())
 	}
+
+	/// usd_value = amount * price, rounded by `rounding`
+	fn usd_value_with_rounding(currency_id: CurrencyId, amount: FixedI128, rounding: Rounding) -> FixedI128Result {
+		let price = {
+			let p = Self::price(currency_id, CurrencyId::AUSD)?;
+			fixed_i128_from_fixed_u128(p)
+		};
+		fixed_i128_mul_with_rounding(amount, price, rounding).ok_or(Error::<T>::NumOutOfBound.into())
+	}
 }
 
 // Trader helpers
@@ -1050,164 +5664,446 @@ impl<T: Trait> Module<T> {
 	///
 	/// unrealized_pl_of_position = (curr_price - open_price) * leveraged_held * to_usd_price
 	fn unrealized_pl_of_position(position: &Position<T>) -> FixedI128Result {
-		let (unrealized, _) = Self::unrealized_pl_and_market_price_of_position(position, None)?;
+		let (unrealized, _) = Self::unrealized_pl_and_market_price_of_position(position, None, Permill::zero())?;
 		Ok(unrealized)
 	}
 
 	/// Returns `Ok((unrealized_pl, market_price))` of a given position. If `price`, market price
-	/// must fit this bound, else returns `None`.
+	/// must fit this bound, else returns `None`. `discount` is applied to the spread.
 	fn unrealized_pl_and_market_price_of_position(
 		position: &Position<T>,
 		price: Option<Price>,
+		discount: Permill,
 	) -> result::Result<(FixedI128, FixedI128), DispatchError> {
-		// open_price = abs(leveraged_debits / leveraged_held)
-		let open_price = position
-			.leveraged_debits
-			.checked_div(&position.leveraged_held)
-			.expect("ensured safe on open position")
-			.saturating_abs();
+		// open_price = abs(leveraged_debits / leveraged_held), rounded up for long positions and down for short
+		// positions, so that the unrealized profit is never rounded in the trader's favor.
+		let open_price_rounding = if position.leverage.is_long() {
+			Rounding::Up
+		} else {
+			Rounding::Down
+		};
+		let open_price = fixed_i128_checked_abs(position.leveraged_debits)
+			.and_then(|debits| Some((debits, fixed_i128_checked_abs(position.leveraged_held)?)))
+			.and_then(|(debits, held)| fixed_i128_div_with_rounding(debits, held, open_price_rounding))
+			.expect("ensured safe on open position");
 		let curr_price = {
 			if position.leverage.is_long() {
-				Self::bid_price(position.pool, position.pair, price)?
+				Self::bid_price(position.pool, position.pair, price, discount)?
 			} else {
-				Self::ask_price(position.pool, position.pair, price)?
+				Self::ask_price(position.pool, position.pair, price, discount)?
 			}
 		};
 		let price_delta = curr_price
 			.checked_sub(&open_price)
 			.expect("Non-negative integers sub can't overflow; qed");
-		let unrealized = position
-			.leveraged_held
-			.checked_mul(&price_delta)
-			.ok_or(Error::<T>::NumOutOfBound)?;
-		let usd_value = Self::usd_value(position.pair.quote, unrealized)?;
+		let unrealized = fixed_i128_mul_with_rounding(position.leveraged_held, price_delta, Rounding::Down)
+			.ok_or(Error::<T>::UnrealizedPlOverflow)?;
+		let usd_value = Self::usd_value_with_rounding(position.pair.quote, unrealized, Rounding::Down)?;
 
 		Ok((usd_value, curr_price))
 	}
 
-	/// unrealized_pl_of_pool = pool_per_pair_long_unrealized + pool_per_pair_short_unrealized
-	fn unrealized_pl_of_pool(pool_id: LiquidityPoolId) -> FixedI128Result {
-		PositionsSnapshots::iter_prefix(pool_id).try_fold(FixedI128::zero(), |unrealized, (pair, pool)| {
-			let long_unrealized = {
-				let curr_price = Self::bid_price(pool_id, pair, None)?;
-				let base_in_quote = pool
-					.long
-					.held
-					.checked_mul(&curr_price)
-					.ok_or(Error::<T>::NumOutOfBound)?;
-				let profit_in_quote = base_in_quote
-					.checked_add(&pool.long.debits)
-					.ok_or(Error::<T>::NumOutOfBound)?;
-				Self::usd_value(pair.quote, profit_in_quote)
-			}?;
-
-			let short_unrealized = {
-				let curr_price = Self::ask_price(pool_id, pair, None)?;
-				let base_in_quote = pool
-					.short
-					.held
-					.checked_mul(&curr_price)
-					.ok_or(Error::<T>::NumOutOfBound)?;
-				let profit_in_quote = base_in_quote
-					.checked_add(&pool.short.debits)
-					.ok_or(Error::<T>::NumOutOfBound)?;
-				Self::usd_value(pair.quote, profit_in_quote)
-			}?;
+	/// Cross margin positions of a given trader in a pool.
+	fn cross_positions_of_trader(who: &T::AccountId, pool_id: LiquidityPoolId) -> impl Iterator<Item = Position<T>> {
+		<PositionsByTrader<T>>::iter_prefix(who)
+			.filter(move |((p, position_id), _)| *p == pool_id && !IsolatedPositions::contains_key(position_id))
+			.filter_map(|((_, position_id), _)| Self::positions(position_id))
+	}
 
-			let sum = long_unrealized
-				.checked_add(&short_unrealized)
-				.ok_or(Error::<T>::NumOutOfBound)?;
-			let new_unrealized = unrealized.checked_add(&sum).ok_or(Error::<T>::NumOutOfBound)?;
-			Ok(new_unrealized)
-		})
+	/// Notional value of a position in USD.
+	fn notional_of_position(position: &Position<T>) -> FixedI128Result {
+		let debits = fixed_i128_checked_abs(position.leveraged_debits).ok_or(Error::<T>::NumOutOfBound)?;
+		Self::usd_value(position.pair.quote, debits)
 	}
 
-	/// Unrealized profit and loss of a given trader in a pool(USD value). It is the sum of
-	/// unrealized profit and loss of all positions opened by a trader.
-	pub fn unrealized_pl_of_trader(who: &T::AccountId, pool_id: LiquidityPoolId) -> FixedI128Result {
+	/// Total notional value of the positions of `who` in `pool_id`, in USD.
+	fn notional_of_trader(who: &T::AccountId, pool_id: LiquidityPoolId) -> FixedI128Result {
 		<PositionsByTrader<T>>::iter_prefix(who)
+			.filter(|((p, _), _)| *p == pool_id)
 			.filter_map(|((_, position_id), _)| Self::positions(position_id))
-			.filter(|p| p.pool == pool_id)
 			.try_fold(FixedI128::zero(), |acc, p| {
-				let unrealized = Self::unrealized_pl_of_position(&p)?;
-				acc.checked_add(&unrealized).ok_or(Error::<T>::NumOutOfBound.into())
+				acc.checked_add(&Self::notional_of_position(&p)?)
+					.ok_or(Error::<T>::NumOutOfBound.into())
 			})
 	}
 
-	/// Sum of all margin held of a given trader in a pool.
-	pub fn margin_held(who: &T::AccountId, pool_id: LiquidityPoolId) -> FixedI128 {
+	/// Number of open positions of `who` in `pool_id`.
+	fn trader_positions_count(who: &T::AccountId, pool_id: LiquidityPoolId) -> u64 {
 		<PositionsByTrader<T>>::iter_prefix(who)
-			.filter_map(|((_, position_id), _)| Self::positions(position_id))
-			.filter(|p| p.pool == pool_id)
-			.fold(FixedI128::zero(), |acc, p| {
-				acc.checked_add(&p.margin_held)
-					.expect("margin held cannot overflow; qed")
+			.filter(|((p, _), _)| *p == pool_id)
+			.count() as u64
+	}
+
+	/// The open position of `who` in `pool_id` a new position of `pair` and `leverage` nets into, if opted in to
+	/// position netting.
+	///
+	/// Copy trading positions, of leaders or followers, expiring positions and positions of a different margin mode
+	/// are not netted.
+	fn nettable_position(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		leverage: Leverage,
+	) -> Option<PositionId> {
+		Self::position_netting(who, pool_id)?;
+		let isolated = Self::margin_mode(who, pool_id) == MarginMode::Isolated;
+		<PositionsByTrader<T>>::iter_prefix(who)
+			.filter(|((p, _), _)| *p == pool_id)
+			.map(|((_, position_id), _)| position_id)
+			.find(|position_id| {
+				IsolatedPositions::contains_key(position_id) == isolated
+					&& Self::copied_position_leaders(position_id).is_none()
+					&& Self::copied_positions(position_id).is_empty()
+					&& !<PositionExpiries<T>>::contains_key(position_id)
+					&& Self::positions(position_id).map_or(false, |p| p.pair == pair && p.leverage == leverage)
 			})
 	}
 
-	/// Accumulated swap rate of a position(USD value).
+	/// Unrealized profit and loss of a given trader in a pool(USD value). It is the sum of
+	/// unrealized profit and loss of all cross margin positions opened by a trader.
+	pub fn unrealized_pl_of_trader(who: &T::AccountId, pool_id: LiquidityPoolId) -> FixedI128Result {
+		Self::cross_positions_of_trader(who, pool_id).try_fold(FixedI128::zero(), |acc, p| {
+			let unrealized = Self::unrealized_pl_of_position(&p)?;
+			acc.checked_add(&unrealized)
+				.ok_or(Error::<T>::UnrealizedPlOverflow.into())
+		})
+	}
+
+	/// Accumulated swap rate of a position(USD value), accrued lazily from the swap rate index.
 	///
 	/// accumulated_swap_rate_of_position =
-	///   (current_accumulated - open_accumulated) * leveraged_held
+	///   (current_accumulated - open_accumulated) * abs(leveraged_debits)
 	fn accumulated_swap_rate_of_position(position: &Position<T>) -> FixedI128Result {
 		let rate = T::LiquidityPools::accumulated_swap_rate(position.pool, position.pair, position.leverage.is_long())
 			.checked_sub(&position.open_accumulated_swap_rate)
-			.ok_or(Error::<T>::NumOutOfBound)?;
-		let accumulated_swap_rate = position
-			.leveraged_debits
-			.saturating_abs()
-			.checked_mul(&rate)
-			.ok_or(Error::<T>::NumOutOfBound)?;
+			.ok_or(Error::<T>::AccumulatedSwapRateOverflow)?;
+		let accumulated_swap_rate = fixed_i128_checked_abs(position.leveraged_debits)
+			.and_then(|debits| fixed_i128_mul_with_rounding(debits, rate, Rounding::Down))
+			.ok_or(Error::<T>::AccumulatedSwapRateOverflow)?;
 
-		let usd_value = Self::usd_value(position.pair.quote, accumulated_swap_rate)?;
+		let usd_value = Self::usd_value_with_rounding(position.pair.quote, accumulated_swap_rate, Rounding::Down)?;
 		return Ok(usd_value);
 	}
 
-	/// Accumulated swap of all open positions of a given trader(USD value) in a pool.
+	/// Accumulated swap of all open cross margin positions of a given trader(USD value) in a pool.
 	fn accumulated_swap_rate_of_trader(who: &T::AccountId, pool_id: LiquidityPoolId) -> FixedI128Result {
+		Self::cross_positions_of_trader(who, pool_id).try_fold(FixedI128::zero(), |acc, p| {
+			let rate_of_p = Self::accumulated_swap_rate_of_position(&p)?;
+			acc.checked_add(&rate_of_p)
+				.ok_or(Error::<T>::AccumulatedSwapRateOverflow.into())
+		})
+	}
+
+	/// Margin held by isolated positions of a given trader in a pool.
+	fn isolated_margin_of_trader(who: &T::AccountId, pool_id: LiquidityPoolId) -> FixedI128Result {
 		<PositionsByTrader<T>>::iter_prefix(who)
+			.filter(|((p, position_id), _)| *p == pool_id && IsolatedPositions::contains_key(position_id))
 			.filter_map(|((_, position_id), _)| Self::positions(position_id))
-			.filter(|p| p.pool == pool_id)
 			.try_fold(FixedI128::zero(), |acc, p| {
-				let rate_of_p = Self::accumulated_swap_rate_of_position(&p)?;
-				acc.checked_add(&rate_of_p).ok_or(Error::<T>::NumOutOfBound.into())
+				acc.checked_add(&p.margin_held).ok_or(Error::<T>::NumOutOfBound.into())
 			})
 	}
 
-	/// equity_of_trader = balance + unrealized_pl + accumulated_swap_rate
+	/// Margin released by hedging of cross margin positions of a given trader in a pool, as if `new_position` was
+	/// opened.
+	///
+	/// Offsetting long and short positions of a pair require max(long_margin, short_margin) instead of the sum, so
+	/// min(long_margin, short_margin) of each pair is released.
+	fn hedged_margin_of_trader(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		new_position: Option<&Position<T>>,
+	) -> FixedI128Result {
+		let mut margins: BTreeMap<TradingPair, (FixedI128, FixedI128)> = BTreeMap::new();
+		for p in Self::cross_positions_of_trader(who, pool_id).chain(new_position.cloned()) {
+			let (long, short) = margins.entry(p.pair).or_default();
+			let margin = if p.leverage.is_long() { long } else { short };
+			*margin = margin.checked_add(&p.margin_held).ok_or(Error::<T>::NumOutOfBound)?;
+		}
+		margins.values().try_fold(FixedI128::zero(), |acc, (long, short)| {
+			acc.checked_add(cmp::min(long, short))
+				.ok_or(Error::<T>::NumOutOfBound.into())
+		})
+	}
+
+	/// equity_of_trader = balance - isolated_margin + unrealized_pl + accumulated_swap_rate + collateral_value
+	///
+	/// Unrealized profit and loss, and accumulated swap rate are of cross margin positions.
 	pub fn equity_of_trader(who: &T::AccountId, pool_id: LiquidityPoolId) -> FixedI128Result {
-		let unrealized = Self::unrealized_pl_of_trader(who, pool_id)?;
-		let with_unrealized = Self::balances(who, pool_id)
-			.checked_add(&unrealized)
-			.ok_or(Error::<T>::NumOutOfBound)?;
-		let accumulated_swap_rate = Self::accumulated_swap_rate_of_trader(who, pool_id)?;
-		with_unrealized
-			.checked_add(&accumulated_swap_rate)
+		Self::with_price_cache(|| {
+			let unrealized = Self::unrealized_pl_of_trader(who, pool_id)?;
+			let with_unrealized = Self::balances(who, pool_id)
+				.checked_sub(&Self::isolated_margin_of_trader(who, pool_id)?)
+				.and_then(|b| b.checked_add(&unrealized))
+				.ok_or(Error::<T>::NumOutOfBound)?;
+			let accumulated_swap_rate = Self::accumulated_swap_rate_of_trader(who, pool_id)?;
+			let collateral_value = Self::collateral_value_of_trader(who, pool_id)?;
+			with_unrealized
+				.checked_add(&accumulated_swap_rate)
+				.and_then(|e| e.checked_add(&collateral_value))
+				.ok_or(Error::<T>::NumOutOfBound.into())
+		})
+	}
+
+	/// collateral_value_of_trader = sum(collateral_value)
+	pub fn collateral_value_of_trader(who: &T::AccountId, pool_id: LiquidityPoolId) -> FixedI128Result {
+		<Collaterals<T>>::iter_prefix(who)
+			.filter(|((p, _), _)| *p == pool_id)
+			.try_fold(FixedI128::zero(), |acc, ((_, currency_id), amount)| {
+				let value = Self::collateral_value(currency_id, amount)?;
+				acc.checked_add(&value).ok_or(Error::<T>::NumOutOfBound.into())
+			})
+	}
+
+	/// Collaterals of `who` in `pool_id` as `(currency_id, amount, collateral_value)`, ordered by currency ID.
+	pub fn collaterals_of_trader(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+	) -> result::Result<Vec<(CurrencyId, Balance, FixedI128)>, DispatchError> {
+		let mut collaterals: Vec<(CurrencyId, Balance)> = <Collaterals<T>>::iter_prefix(who)
+			.filter(|((p, _), _)| *p == pool_id)
+			.map(|((_, currency_id), amount)| (currency_id, amount))
+			.collect();
+		collaterals.sort_by_key(|(currency_id, _)| *currency_id);
+		Self::with_price_cache(|| {
+			collaterals
+				.into_iter()
+				.map(|(currency_id, amount)| Ok((currency_id, amount, Self::collateral_value(currency_id, amount)?)))
+				.collect()
+		})
+	}
+
+	/// collateral_value = amount * price * (1 - haircut), rounded down
+	///
+	/// Zero if the currency is no longer accepted as collateral.
+	fn collateral_value(currency_id: CurrencyId, amount: Balance) -> FixedI128Result {
+		let haircut = match Self::collateral_haircut(currency_id) {
+			Some(h) => h,
+			None => return Ok(FixedI128::zero()),
+		};
+		let amount = fixed_i128_from_balance(amount).map_err(Error::<T>::from)?;
+		let value = Self::usd_value_with_rounding(currency_id, amount, Rounding::Down)?;
+		fixed_i128_mul_with_rounding(value, Permill::one().saturating_sub(haircut).into(), Rounding::Down)
+			.ok_or(Error::<T>::NumOutOfBound.into())
+	}
+
+	/// Free margin of a given trader in a pool.
+	///
+	/// free_margin = equity - (cross_margin_held - hedged_margin)
+	pub fn free_margin(who: &T::AccountId, pool_id: LiquidityPoolId) -> FixedI128Result {
+		let equity = Self::equity_of_trader(who, pool_id)?;
+		let hedged_margin = Self::hedged_margin_of_trader(who, pool_id, None)?;
+		let cross_margin_held = Self::margin_held(who, pool_id)
+			.checked_sub(&Self::isolated_margin_of_trader(who, pool_id)?)
+			.and_then(|m| m.checked_sub(&hedged_margin))
+			.ok_or(Error::<T>::ArithmeticOverflow)?;
+		equity
+			.checked_sub(&cross_margin_held)
+			.ok_or(Error::<T>::ArithmeticOverflow.into())
+	}
+
+	/// Margin level of a given trader in a pool.
+	pub fn margin_level(who: &T::AccountId, pool_id: LiquidityPoolId) -> FixedI128Result {
+		Self::margin_level_with_new_position(who, pool_id, None)
+	}
+
+	/// Margin level of a given trader in a pool, as if `new_position` was opened.
+	fn margin_level_with_new_position(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		new_position: Option<&Position<T>>,
+	) -> FixedI128Result {
+		Self::with_price_cache(|| {
+			let mut equity = Self::equity_of_trader(who, pool_id)?;
+			if let Some(p) = new_position {
+				let unrealized_pl = Self::unrealized_pl_of_position(p)?;
+				equity = equity.checked_add(&unrealized_pl).ok_or(Error::<T>::NumOutOfBound)?;
+			}
+			let leveraged_debits_in_usd = Self::cross_positions_of_trader(who, pool_id)
+				.chain(new_position.cloned())
+				.try_fold::<_, _, FixedI128Result>(FixedI128::zero(), |acc, p| {
+					let debits = fixed_i128_checked_abs(p.leveraged_debits).ok_or(Error::<T>::ArithmeticOverflow)?;
+					let debits_in_usd = Self::usd_value(p.pair.quote, debits)?;
+					acc.checked_add(&debits_in_usd).ok_or(Error::<T>::NumOutOfBound.into())
+				})?;
+
+			Ok(equity
+				.checked_div(&leveraged_debits_in_usd)
+				// if no leveraged held, margin level is max
+				.unwrap_or(FixedI128::max_value()))
+		})
+	}
+
+	/// Equity of an isolated position.
+	///
+	/// isolated_equity = margin_held + unrealized_pl + accumulated_swap_rate
+	fn isolated_equity_of_position(position: &Position<T>) -> FixedI128Result {
+		Self::unrealized_pl_of_position(position)?
+			.checked_add(&Self::accumulated_swap_rate_of_position(position)?)
+			.and_then(|e| e.checked_add(&position.margin_held))
 			.ok_or(Error::<T>::NumOutOfBound.into())
 	}
 
-	/// Free margin of a given trader in a pool.
-	pub fn free_margin(who: &T::AccountId, pool_id: LiquidityPoolId) -> FixedI128Result {
-		let equity = Self::equity_of_trader(who, pool_id)?;
-		let margin_held = Self::margin_held(who, pool_id);
-		Ok(equity.saturating_sub(margin_held))
+	/// Margin level of an isolated position.
+	///
+	/// isolated_margin_level = (margin_held + unrealized_pl + accumulated_swap_rate) / abs(leveraged_debits_in_usd)
+	pub fn isolated_margin_level(position: &Position<T>) -> FixedI128Result {
+		Self::with_price_cache(|| {
+			let equity = Self::isolated_equity_of_position(position)?;
+			let debits = fixed_i128_checked_abs(position.leveraged_debits).ok_or(Error::<T>::ArithmeticOverflow)?;
+			let debits_in_usd = Self::usd_value(position.pair.quote, debits)?;
+			Ok(equity.checked_div(&debits_in_usd).unwrap_or(FixedI128::max_value()))
+		})
+	}
+
+	/// Return `true` if the margin level of an isolated position reached the stop out threshold of its pair.
+	fn is_isolated_position_stop_out(position: &Position<T>) -> result::Result<bool, DispatchError> {
+		let stop_out = Self::risk_threshold_of_isolated_position(position).stop_out;
+		Ok(Self::isolated_margin_level(position)? <= stop_out.into())
+	}
+
+	/// Ratchet the watermarks and stop loss prices of trailing stops with favorable oracle price moves.
+	///
+	/// At most `GetStopOutChunkSize` trailing stops are ratcheted per block, continuing from `TrailingStopsCursor`.
+	fn ratchet_trailing_stops() -> Weight {
+		let prefix = <TrailingStops as StoragePrefixedMap<TrailingStop>>::final_prefix();
+		let (position_ids, cursor) =
+			Self::map_keys_after::<PositionId>(&prefix, Self::trailing_stops_cursor(), T::GetStopOutChunkSize::get());
+		for position_id in position_ids.iter() {
+			let trailing_stop = match Self::trailing_stop(position_id) {
+				Some(t) => t,
+				None => continue,
+			};
+			let position = match Self::positions(position_id) {
+				Some(p) => p,
+				None => continue,
+			};
+			let price = match Self::price(position.pair.base, position.pair.quote) {
+				Ok(p) => p,
+				Err(_) => continue,
+			};
+			let is_long = position.leverage.is_long();
+			let is_favorable = if is_long {
+				price > trailing_stop.watermark
+			} else {
+				price < trailing_stop.watermark
+			};
+			if is_favorable {
+				Self::put_trailing_stop(
+					*position_id,
+					is_long,
+					TrailingStop {
+						watermark: price,
+						..trailing_stop
+					},
+				);
+			}
+		}
+		match cursor {
+			Some(c) => TrailingStopsCursor::put(c),
+			None => TrailingStopsCursor::kill(),
+		}
+
+		// a storage key read per trailing stop, and the cursor read and write
+		let count = position_ids.len() as Weight;
+		let reads = TRAILING_STOP_READS.saturating_add(1).saturating_mul(count);
+		let writes = TRAILING_STOP_WRITES.saturating_mul(count);
+		TRAILING_STOP_WEIGHT
+			.saturating_mul(count)
+			.saturating_add(T::DbWeight::get().reads_writes(reads.saturating_add(2), writes.saturating_add(1)))
+	}
+
+	/// Check circuit breakers of trading pairs against oracle prices. A breaker trips if the price moved more than
+	/// `max_price_move` from its reference price within `window`, and the price becomes the new reference.
+	///
+	/// The reference price is taken on the first check, and once `window` passed since it's taken.
+	fn check_circuit_breakers(now: T::BlockNumber) -> Weight {
+		let breakers: Vec<(TradingPair, CircuitBreaker<T::BlockNumber>)> = <CircuitBreakers<T>>::iter().collect();
+		for (pair, breaker) in breakers.iter() {
+			if Self::tripped_circuit_breaker(pair).map_or(false, |until| until <= now) {
+				<TrippedCircuitBreakers<T>>::remove(pair);
+			}
+			let price = match Self::price(pair.base, pair.quote) {
+				Ok(p) => p,
+				Err(_) => continue,
+			};
+			match Self::circuit_breaker_reference(pair) {
+				Some((since, reference)) if now.saturating_sub(since) < breaker.window => {
+					let price_move = if price > reference {
+						price.saturating_sub(reference)
+					} else {
+						reference.saturating_sub(price)
+					};
+					if price_move > reference.saturating_mul(breaker.max_price_move.into()) {
+						let until = now.saturating_add(breaker.cool_down);
+						<TrippedCircuitBreakers<T>>::insert(pair, until);
+						<CircuitBreakerReferences<T>>::insert(pair, (now, price));
+						Self::deposit_event(RawEvent::CircuitBreakerTripped(*pair, price, until));
+					}
+				}
+				_ => <CircuitBreakerReferences<T>>::insert(pair, (now, price)),
+			}
+		}
+
+		let count = breakers.len() as Weight;
+		CIRCUIT_BREAKER_WEIGHT_PER_PAIR
+			.saturating_mul(count)
+			.saturating_add(T::DbWeight::get().reads_writes(
+				CIRCUIT_BREAKER_READS_PER_PAIR.saturating_mul(count).saturating_add(1),
+				CIRCUIT_BREAKER_WRITES_PER_PAIR.saturating_mul(count),
+			))
+	}
+
+	/// Returns `true` if trading of the protocol, `pool_id` or `pair` is paused.
+	pub fn is_trading_paused(pool_id: LiquidityPoolId, pair: TradingPair) -> bool {
+		[
+			TradingPauseScope::Protocol,
+			TradingPauseScope::Pool(pool_id),
+			TradingPauseScope::Pair(pair),
+		]
+		.iter()
+		.any(|scope| Self::paused_trading(scope))
+	}
+
+	/// Returns `true` if the circuit breaker of `pair` is tripped and still cooling down.
+	pub fn is_circuit_breaker_tripped(pair: TradingPair) -> bool {
+		Self::tripped_circuit_breaker(pair).map_or(false, |until| until > <frame_system::Module<T>>::block_number())
+	}
+
+	/// Return `true` if the oracle price reached the stop loss price of `position`.
+	fn is_stop_loss_reached(position_id: PositionId, position: &Position<T>) -> result::Result<bool, DispatchError> {
+		let stop_loss = Self::stop_loss(position_id).ok_or(Error::<T>::StopLossNotSet)?;
+		let price = Self::price(position.pair.base, position.pair.quote)?;
+		if position.leverage.is_long() {
+			Ok(price <= stop_loss)
+		} else {
+			Ok(price >= stop_loss)
+		}
+	}
+
+	/// Return `true` if the oracle price reached the take profit price of `position`.
+	fn is_take_profit_reached(position_id: PositionId, position: &Position<T>) -> result::Result<bool, DispatchError> {
+		let take_profit = Self::take_profit(position_id).ok_or(Error::<T>::TakeProfitNotSet)?;
+		let price = Self::price(position.pair.base, position.pair.quote)?;
+		if position.leverage.is_long() {
+			Ok(price >= take_profit)
+		} else {
+			Ok(price <= take_profit)
+		}
 	}
 
-	/// Margin level of a given trader in a pool.
-	pub fn margin_level(who: &T::AccountId, pool_id: LiquidityPoolId) -> FixedI128Result {
-		let equity = Self::equity_of_trader(who, pool_id)?;
-		let leveraged_debits_in_usd = <PositionsByTrader<T>>::iter_prefix(who)
-			.filter_map(|((_, position_id), _)| Self::positions(position_id))
-			.filter(|p| p.pool == pool_id)
-			.try_fold::<_, _, FixedI128Result>(FixedI128::zero(), |acc, p| {
-				let debits_in_usd = Self::usd_value(p.pair.quote, p.leveraged_debits.saturating_abs())?;
-				acc.checked_add(&debits_in_usd).ok_or(Error::<T>::NumOutOfBound.into())
-			})?;
+	/// Return `true` if `order_id` reached its expiry block, and is only waiting to be cancelled.
+	fn is_order_expired(order_id: OrderId) -> bool {
+		Self::order_expiry(order_id).map_or(false, |expiry| <frame_system::Module<T>>::block_number() >= expiry)
+	}
 
-		Ok(equity
-			.checked_div(&leveraged_debits_in_usd)
-			// if no leveraged held, margin level is max
-			.unwrap_or(FixedI128::max_value()))
+	/// Return `true` if the oracle price satisfies the trigger of `order`.
+	fn is_order_triggered(order: &PendingOrder<T::AccountId>) -> result::Result<bool, DispatchError> {
+		let price = Self::price(order.pair.base, order.pair.quote)?;
+		match (order.kind, order.leverage.is_long()) {
+			(OrderKind::Limit, true) | (OrderKind::Stop, false) => Ok(price <= order.trigger_price),
+			(OrderKind::Limit, false) | (OrderKind::Stop, true) => Ok(price >= order.trigger_price),
+		}
 	}
 
 	/// Ensure a trader is safe.
@@ -1216,7 +6112,7 @@ impl<T: Trait> Module<T> {
 	fn ensure_trader_safe(who: &T::AccountId, pool_id: LiquidityPoolId, action: Action<T>) -> DispatchResult {
 		let risk = Self::check_trader(who, pool_id, action)?;
 		match risk {
-			Risk::None => Ok(()),
+			Risk::None | Risk::Warning => Ok(()),
 			_ => Err(Error::<T>::UnsafeTrader.into()),
 		}
 	}
@@ -1228,7 +6124,7 @@ impl<T: Trait> Module<T> {
 		let margin_level = Self::margin_level(who, pool_id)?;
 
 		let new_pair_risk_threshold = match action.clone() {
-			Action::OpenPosition(p) => Self::trader_risk_threshold(p.pair).unwrap_or_default(),
+			Action::OpenPosition(p) => Self::trader_risk_threshold(pool_id, p.pair).unwrap_or_default(),
 			_ => RiskThreshold::default(),
 		};
 
@@ -1239,12 +6135,44 @@ impl<T: Trait> Module<T> {
 			<= cmp::max(trader_threshold.margin_call.into(), new_pair_risk_threshold.margin_call).into()
 		{
 			Risk::MarginCall
+		} else if margin_level <= cmp::max(trader_threshold.warning, new_pair_risk_threshold.warning).into() {
+			Risk::Warning
 		} else {
 			Risk::None
 		};
 
 		Ok(risk)
 	}
+
+	/// Emit warning events if the trader or the pool reached warning risk threshold, and wasn't at warning risk
+	/// before.
+	fn emit_warnings(who: &T::AccountId, pool_id: LiquidityPoolId) {
+		let is_trader_warned = match Self::check_trader(who, pool_id, Action::None) {
+			Ok(Risk::Warning) => true,
+			_ => false,
+		};
+		if is_trader_warned != Self::warned_traders(who, pool_id).is_some() {
+			if is_trader_warned {
+				<WarnedTraders<T>>::insert(who, pool_id, ());
+				Self::deposit_event(RawEvent::TraderWarned(who.clone(), pool_id));
+			} else {
+				<WarnedTraders<T>>::remove(who, pool_id);
+			}
+		}
+
+		let is_pool_warned = match Self::check_pool(pool_id, Action::None) {
+			Ok(Risk::Warning) => true,
+			_ => false,
+		};
+		if is_pool_warned != Self::warned_pools(pool_id).is_some() {
+			if is_pool_warned {
+				WarnedPools::insert(pool_id, ());
+				Self::deposit_event(RawEvent::LiquidityPoolWarned(pool_id));
+			} else {
+				WarnedPools::remove(pool_id);
+			}
+		}
+	}
 }
 
 #[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
@@ -1252,79 +6180,192 @@ enum Action<T: Trait> {
 	None,
 	Withdraw(Balance),
 	OpenPosition(Position<T>),
+	AddLiabilities(TradingPair, PoolLiabilityAmounts),
 }
 
 #[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
 enum Risk {
 	None,
+	Warning,
 	MarginCall,
 	StopOut,
 }
 
+/// Equity, net position and longest leg of a liquidity pool(USD value).
+#[derive(Clone, Copy, RuntimeDebug, Eq, PartialEq, Default)]
+struct PoolRiskAmounts {
+	equity: FixedI128,
+	net_position: FixedI128,
+	longest_leg: FixedI128,
+}
+
 // Liquidity pool helpers
 impl<T: Trait> Module<T> {
-	/// equity_of_pool = liquidity - all_unrealized_pl - all_accumulated_swap_rate
-	/// In order to optimize the algorithm, ignore all_accumulated_swap_rate
-	fn equity_of_pool(pool: LiquidityPoolId) -> FixedI128Result {
-		let liquidity = {
-			let l = <T::LiquidityPools as LiquidityPools<T::AccountId>>::liquidity(pool);
-			fixed_i128_from_u128(l)
-		};
+	/// Number of open positions in `pool`.
+	fn pool_positions_count(pool: LiquidityPoolId) -> u64 {
+		PositionsSnapshots::iter_prefix(pool).fold(0, |num, (_, snapshot)| num + snapshot.positions_count)
+	}
+
+	/// Total notional value of the positions in `pool`, in USD.
+	fn notional_of_pool(pool: LiquidityPoolId) -> FixedI128Result {
+		PositionsByPool::iter_prefix(pool)
+			.filter_map(|((_, position_id), _)| Self::positions(position_id))
+			.try_fold(FixedI128::zero(), |acc, p| {
+				acc.checked_add(&Self::notional_of_position(&p)?)
+					.ok_or(Error::<T>::NumOutOfBound.into())
+			})
+	}
+
+	/// Equity, net position and longest leg of a liquidity pool, with `new_position` opened, in one pass over
+	/// the pool's positions snapshots. Liabilities outside of margin positions are included.
+	///
+	/// equity = liquidity - all_unrealized_pl - all_accumulated_swap_rate - liabilities_unrealized_loss
+	/// In order to optimize the algorithm, ignore all_accumulated_swap_rate. `new_position` is only counted in
+	/// net position and longest leg, as its unrealized PL is not realizable yet.
+	fn pool_risk_amounts(pool: LiquidityPoolId, new_position: Option<&Position<T>>) -> PoolRiskAmountsResult {
+		Self::with_price_cache(|| {
+			let liquidity = {
+				let l = <T::LiquidityPools as LiquidityPools<T::AccountId>>::liquidity(pool);
+				fixed_i128_from_balance(l).map_err(Error::<T>::from)?
+			};
+
+			let amounts = PositionsSnapshots::iter_prefix(pool).try_fold(
+				PoolRiskAmounts {
+					equity: liquidity,
+					..Default::default()
+				},
+				|amounts, (pair, snapshot)| -> PoolRiskAmountsResult {
+					let unrealized_pl = Self::unrealized_pl_of_pool_pair(pool, pair, &snapshot)?;
+					let amounts = PoolRiskAmounts {
+						equity: amounts
+							.equity
+							.checked_sub(&unrealized_pl)
+							.ok_or(Error::<T>::NumOutOfBound)?,
+						..amounts
+					};
+					Self::add_exposure(amounts, pair, &snapshot)
+				},
+			)?;
+			let amounts = Self::add_liabilities(amounts, T::PoolLiabilities::liabilities(pool)?)?;
+
+			match new_position {
+				Some(p) => {
+					let leveraged_amounts = LeveragedAmounts {
+						held: p.leveraged_held,
+						debits: p.leveraged_debits,
+					};
+					let snapshot = if p.leverage.is_long() {
+						PositionsSnapshot {
+							positions_count: 1,
+							long: leveraged_amounts,
+							short: Default::default(),
+						}
+					} else {
+						PositionsSnapshot {
+							positions_count: 1,
+							long: Default::default(),
+							short: leveraged_amounts,
+						}
+					};
+					Self::add_exposure(amounts, p.pair, &snapshot)
+				}
+				None => Ok(amounts),
+			}
+		})
+	}
 
-		let unrealized_pl = Self::unrealized_pl_of_pool(pool)?;
+	/// Unrealized PL of `pair` positions in `pool`(USD value), by its positions snapshot.
+	///
+	/// unrealized_pl = long_held * bid_price + long_debits + short_held * ask_price + short_debits
+	fn unrealized_pl_of_pool_pair(
+		pool: LiquidityPoolId,
+		pair: TradingPair,
+		snapshot: &PositionsSnapshot,
+	) -> FixedI128Result {
+		let long_unrealized = {
+			let curr_price = Self::bid_price(pool, pair, None, Permill::zero())?;
+			let base_in_quote = snapshot
+				.long
+				.held
+				.checked_mul(&curr_price)
+				.ok_or(Error::<T>::NumOutOfBound)?;
+			let profit_in_quote = base_in_quote
+				.checked_add(&snapshot.long.debits)
+				.ok_or(Error::<T>::NumOutOfBound)?;
+			Self::usd_value(pair.quote, profit_in_quote)
+		}?;
+
+		let short_unrealized = {
+			let curr_price = Self::ask_price(pool, pair, None, Permill::zero())?;
+			let base_in_quote = snapshot
+				.short
+				.held
+				.checked_mul(&curr_price)
+				.ok_or(Error::<T>::NumOutOfBound)?;
+			let profit_in_quote = base_in_quote
+				.checked_add(&snapshot.short.debits)
+				.ok_or(Error::<T>::NumOutOfBound)?;
+			Self::usd_value(pair.quote, profit_in_quote)
+		}?;
 
-		liquidity
-			.checked_sub(&unrealized_pl)
+		long_unrealized
+			.checked_add(&short_unrealized)
 			.ok_or(Error::<T>::NumOutOfBound.into())
 	}
 
-	/// Returns `(net_position, longest_leg)` of a liquidity pool.
-	fn net_position_and_longest_leg(pool: LiquidityPoolId, new_position: Option<Position<T>>) -> DoubleFixedI128Result {
-		PositionsSnapshots::iter_prefix(pool)
-			.map(|(pair, pool)| (pair, pool))
-			.chain(new_position.map_or(vec![], |p| {
-				let info = if p.leverage.is_long() {
-					PositionsSnapshot {
-						positions_count: 1,
-						long: LeveragedAmounts {
-							held: p.leveraged_held,
-							debits: p.leveraged_debits,
-						},
-						short: LeveragedAmounts {
-							held: FixedI128::zero(),
-							debits: FixedI128::zero(),
-						},
-					}
-				} else {
-					PositionsSnapshot {
-						positions_count: 1,
-						long: LeveragedAmounts {
-							held: FixedI128::zero(),
-							debits: FixedI128::zero(),
-						},
-						short: LeveragedAmounts {
-							held: p.leveraged_held,
-							debits: p.leveraged_debits,
-						},
-					}
-				};
-				vec![(p.pair, info)]
-			}))
-			.try_fold((FixedI128::zero(), FixedI128::zero()), |(net, max), (pair, pool)| {
-				let new_net = pool
-					.long
-					.held
-					.checked_add(&pool.short.held)
-					.ok_or(Error::<T>::NumOutOfBound)?;
-				let net_in_usd = Self::usd_value(pair.base, new_net.saturating_abs())?;
+	/// Add net position and longest leg of `pair` positions, by its positions snapshot, to `amounts`.
+	fn add_exposure(
+		amounts: PoolRiskAmounts,
+		pair: TradingPair,
+		snapshot: &PositionsSnapshot,
+	) -> PoolRiskAmountsResult {
+		let base_price = fixed_i128_from_fixed_u128(Self::price(pair.base, CurrencyId::AUSD)?);
+
+		let net = snapshot
+			.long
+			.held
+			.checked_add(&snapshot.short.held)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let net_in_usd = net
+			.saturating_abs()
+			.checked_mul(&base_price)
+			.ok_or(Error::<T>::NumOutOfBound)?;
 
-				let new_max = cmp::max(pool.long.held, pool.short.held.saturating_abs());
-				let max_in_usd = Self::usd_value(pair.base, new_max.saturating_abs())?;
+		let max = cmp::max(snapshot.long.held, snapshot.short.held.saturating_abs());
+		let max_in_usd = max
+			.saturating_abs()
+			.checked_mul(&base_price)
+			.ok_or(Error::<T>::NumOutOfBound)?;
 
-				let new_net = net.checked_add(&net_in_usd).ok_or(Error::<T>::NumOutOfBound)?;
-				let new_max = max.checked_add(&max_in_usd).ok_or(Error::<T>::NumOutOfBound)?;
-				Ok((new_net, new_max))
-			})
+		Ok(PoolRiskAmounts {
+			net_position: amounts
+				.net_position
+				.checked_add(&net_in_usd)
+				.ok_or(Error::<T>::NumOutOfBound)?,
+			longest_leg: amounts
+				.longest_leg
+				.checked_add(&max_in_usd)
+				.ok_or(Error::<T>::NumOutOfBound)?,
+			..amounts
+		})
+	}
+
+	/// Add liabilities outside of margin positions to `amounts`.
+	fn add_liabilities(amounts: PoolRiskAmounts, liabilities: PoolLiabilityAmounts) -> PoolRiskAmountsResult {
+		Ok(PoolRiskAmounts {
+			equity: amounts
+				.equity
+				.checked_sub(&liabilities.unrealized_loss)
+				.ok_or(Error::<T>::NumOutOfBound)?,
+			net_position: amounts
+				.net_position
+				.checked_add(&liabilities.net_position)
+				.ok_or(Error::<T>::NumOutOfBound)?,
+			longest_leg: amounts
+				.longest_leg
+				.checked_add(&liabilities.longest_leg)
+				.ok_or(Error::<T>::NumOutOfBound)?,
+		})
 	}
 
 	/// ENP and ELL after performing action.
@@ -1332,16 +6373,23 @@ impl<T: Trait> Module<T> {
 	/// ENP - Equity to Net Position ratio of a liquidity pool.
 	/// ELL - Equity to Longest Leg ratio of a liquidity pool.
 	fn enp_and_ell_with_action(pool: LiquidityPoolId, action: Action<T>) -> DoubleFixedI128Result {
-		let equity = Self::equity_of_pool(pool)?;
-		let new_position = match action.clone() {
+		let new_position = match &action {
 			Action::OpenPosition(p) => Some(p),
 			_ => None,
 		};
-		let (net_position, longest_leg) = Self::net_position_and_longest_leg(pool, new_position)?;
+		let amounts = Self::pool_risk_amounts(pool, new_position)?;
+		let PoolRiskAmounts {
+			equity,
+			net_position,
+			longest_leg,
+		} = match action {
+			Action::AddLiabilities(_, liabilities) => Self::add_liabilities(amounts, liabilities)?,
+			_ => amounts,
+		};
 
 		let equity = match action {
 			Action::Withdraw(amount) => equity
-				.checked_sub(&fixed_i128_from_u128(amount))
+				.checked_sub(&fixed_i128_from_balance(amount).map_err(Error::<T>::from)?)
 				.ok_or(Error::<T>::NumOutOfBound)?,
 			_ => equity,
 		};
@@ -1363,7 +6411,7 @@ impl<T: Trait> Module<T> {
 	/// Return `Ok` if ensured safe, or `Err` if not.
 	fn ensure_pool_safe(pool: LiquidityPoolId, action: Action<T>) -> DispatchResult {
 		match Self::check_pool(pool, action.clone()) {
-			Ok(Risk::None) => Ok(()),
+			Ok(Risk::None) | Ok(Risk::Warning) => Ok(()),
 			_ => match action {
 				Action::None => Err(Error::<T>::UnsafePool.into()),
 				_ => Err(Error::<T>::PoolWouldBeUnsafe.into()),
@@ -1377,8 +6425,12 @@ impl<T: Trait> Module<T> {
 	fn check_pool(pool_id: LiquidityPoolId, action: Action<T>) -> Result<Risk, DispatchError> {
 		let (new_pair_enp_threshold, new_pair_ell_threshold) = match action.clone() {
 			Action::OpenPosition(p) => (
-				Self::liquidity_pool_enp_threshold(p.pair).unwrap_or_default(),
-				Self::liquidity_pool_ell_threshold(p.pair).unwrap_or_default(),
+				Self::liquidity_pool_enp_threshold(pool_id, p.pair).unwrap_or_default(),
+				Self::liquidity_pool_ell_threshold(pool_id, p.pair).unwrap_or_default(),
+			),
+			Action::AddLiabilities(pair, _) => (
+				Self::liquidity_pool_enp_threshold(pool_id, pair).unwrap_or_default(),
+				Self::liquidity_pool_ell_threshold(pool_id, pair).unwrap_or_default(),
 			),
 			_ => (RiskThreshold::default(), RiskThreshold::default()),
 		};
@@ -1393,6 +6445,10 @@ impl<T: Trait> Module<T> {
 			|| ell <= cmp::max(ell_threshold.margin_call, new_pair_ell_threshold.margin_call).into()
 		{
 			return Ok(Risk::MarginCall);
+		} else if enp <= cmp::max(enp_threshold.warning, new_pair_enp_threshold.warning).into()
+			|| ell <= cmp::max(ell_threshold.warning, new_pair_ell_threshold.warning).into()
+		{
+			return Ok(Risk::Warning);
 		}
 		Ok(Risk::None)
 	}
@@ -1400,164 +6456,724 @@ impl<T: Trait> Module<T> {
 	/// Force closure position to liquidate liquidity pool based on opened positions.
 	///
 	/// Return `Ok` if closure success, or `Err` if not.
-	fn liquidity_pool_close_position(pool: LiquidityPoolId, position_id: PositionId) -> DispatchResult {
+	fn liquidity_pool_close_position(
+		pool: LiquidityPoolId,
+		position_id: PositionId,
+		penalty_tier: &ForceClosePenaltyTier,
+	) -> DispatchResult {
 		let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
 
 		let spread = {
 			if position.leverage.is_long() {
 				T::LiquidityPools::bid_spread(pool, position.pair)
 					.ok_or(Error::<T>::NoBidSpread)
-					.map(fixed_i128_from_u128)?
+					.and_then(|s| fixed_i128_from_balance(s).map_err(Error::<T>::from))?
 			} else {
 				T::LiquidityPools::ask_spread(pool, position.pair)
 					.ok_or(Error::<T>::NoAskSpread)
-					.map(fixed_i128_from_u128)?
+					.and_then(|s| fixed_i128_from_balance(s).map_err(Error::<T>::from))?
 			}
 		};
 
-		let spread_profit = position
-			.leveraged_held
-			.checked_mul(&spread)
+		// Spread profit of both long and short positions is positive.
+		let spread_profit = fixed_i128_checked_abs(position.leveraged_held)
+			.and_then(|held| held.checked_mul(&spread))
 			.ok_or(Error::<T>::NumOutOfBound)?;
 
 		let spread_profit_in_usd = Self::usd_value(position.pair.quote, spread_profit)?;
-		let penalty = spread_profit_in_usd;
+		let penalty = spread_profit_in_usd
+			.checked_mul(&fixed_i128_from_fixed_u128(penalty_tier.penalty_rate))
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let trader_compensation = penalty
+			.checked_mul(&penalty_tier.trader_share.into())
+			.ok_or(Error::<T>::NumOutOfBound)?;
 		let sub_amount = spread_profit_in_usd
 			.checked_add(&penalty)
+			.and_then(|v| v.checked_sub(&trader_compensation))
 			.ok_or(Error::<T>::NumOutOfBound)?;
 
 		Self::do_close_position(&position.owner, position_id, None)?;
 
+		// Compensate the trader first.
+		let compensated = cmp::min(
+			<T::LiquidityPools as LiquidityPools<T::AccountId>>::liquidity(position.pool),
+			balance_from_fixed_i128(trader_compensation).map_err(Error::<T>::from)?,
+		);
+		if compensated > 0 {
+			// If negative balance, the trader owes pool and then repay, same as realizing profit.
+			let compensated_fixed = fixed_i128_from_balance(compensated).map_err(Error::<T>::from)?;
+			let balance = Self::balances(&position.owner, position.pool);
+			let pool_withdraw = if balance.is_negative() {
+				let repaid = compensated_fixed
+					.checked_add(&balance)
+					.ok_or(Error::<T>::ArithmeticOverflow)?;
+				cmp::max(repaid, FixedI128::zero())
+			} else {
+				compensated_fixed
+			};
+			if !pool_withdraw.is_zero() {
+				<T::LiquidityPools as LiquidityPools<T::AccountId>>::withdraw_liquidity(
+					&Self::account_id(),
+					position.pool,
+					balance_from_fixed_i128(pool_withdraw).map_err(Error::<T>::from)?,
+				)?;
+			}
+			Self::update_balance(&position.owner, position.pool, compensated_fixed)?;
+		}
+
 		let realized = cmp::min(
 			<T::LiquidityPools as LiquidityPools<T::AccountId>>::liquidity(position.pool),
-			u128_from_fixed_i128(sub_amount),
+			balance_from_fixed_i128(sub_amount).map_err(Error::<T>::from)?,
 		);
+		let split = Self::fee_splits(position.pool)
+			.unwrap_or_else(FeeSplit::all_to_treasury)
+			.split(realized);
+		// No keeper on force closure, and the pool owner share stays in the pool.
 		<T::LiquidityPools as LiquidityPools<T::AccountId>>::withdraw_liquidity(
 			&T::GetTreasuryAccountId::get(),
 			position.pool,
-			realized,
+			split.treasury.saturating_add(split.keeper),
 		)?;
+		<T::LiquidityPools as LiquidityPools<T::AccountId>>::withdraw_liquidity(
+			&T::GetInsuranceFundAccountId::get(),
+			position.pool,
+			split.insurance_fund,
+		)?;
+
+		Self::deposit_event(RawEvent::LiquidityPoolPenalized(pool, position_id, compensated, realized));
+
+		Ok(())
+	}
+
+	/// Force closure penalty tier of liquidity pool, by the lower one of its ENP and ELL.
+	fn force_close_penalty_tier(enp_or_ell: FixedI128) -> ForceClosePenaltyTier {
+		Self::force_close_penalty_tiers()
+			.into_iter()
+			.filter(|tier| enp_or_ell <= tier.threshold.into())
+			.min_by_key(|tier| tier.threshold)
+			.unwrap_or_default()
+	}
+
+	/// Max leverage value of the margin tier of `pair` matching `notional`, or `None` if none matched.
+	fn margin_tier_max_leverage(pair: TradingPair, notional: Balance) -> Option<u8> {
+		Self::margin_tiers(pair)
+			.into_iter()
+			.filter(|tier| notional > tier.notional_threshold)
+			.max_by_key(|tier| tier.notional_threshold)
+			.map(|tier| tier.max_leverage)
+	}
+
+	/// Return risk threshold of trader based on opened positions after performing an action.
+	///
+	/// Return `RiskThreshold` or `Default` value.
+	fn risk_threshold_of_trader(who: &T::AccountId, pool_id: LiquidityPoolId) -> RiskThreshold {
+		<PositionsByTrader<T>>::iter_prefix(who)
+			.filter(|((p, _), _)| *p == pool_id)
+			.fold(vec![], |mut v, ((_, position_id), _)| {
+				if let Some(position) = Self::positions(position_id) {
+					if !v.contains(&position.pair) {
+						v.push(position.pair);
+					}
+				}
+				v
+			})
+			.iter()
+			.filter_map(|pair| Self::trader_risk_threshold(pool_id, *pair))
+			.fold(Self::risk_threshold_of_tier(who), RiskThreshold::max)
+	}
+
+	/// Return risk threshold of the tier of `who`, or `Default` value if not set.
+	fn risk_threshold_of_tier(who: &T::AccountId) -> RiskThreshold {
+		Self::trader_tier_option(T::TraderTiers::trader_tier(who))
+			.risk_threshold
+			.unwrap_or_default()
+	}
+
+	/// Return risk threshold of an isolated position, the higher one of its trading pair and its owner's tier.
+	fn risk_threshold_of_isolated_position(position: &Position<T>) -> RiskThreshold {
+		Self::trader_risk_threshold(position.pool, position.pair)
+			.unwrap_or_default()
+			.max(Self::risk_threshold_of_tier(&position.owner))
+	}
+
+	/// Return risk threshold of liquidity pool based on opened positions after performing an
+	/// action.
+	///
+	/// Return `RiskThreshold` or `Default` value.
+	fn enp_and_ell_risk_threshold_of_pool(pool_id: LiquidityPoolId) -> (RiskThreshold, RiskThreshold) {
+		PositionsSnapshots::iter_prefix(pool_id)
+			.fold(vec![], |mut v, (pair, _)| {
+				if !v.contains(&pair) {
+					v.push(pair);
+				}
+				v
+			})
+			.iter()
+			.filter_map(|pair| {
+				let enp = Self::liquidity_pool_enp_threshold(pool_id, *pair)?;
+				let ell = Self::liquidity_pool_ell_threshold(pool_id, *pair)?;
+				Some((enp, ell))
+			})
+			.fold(
+				(RiskThreshold::default(), RiskThreshold::default()),
+				|(max_enp, max_ell), (enp, ell)| (max_enp.max(enp), max_ell.max(ell)),
+			)
+	}
+
+	pub fn enp_and_ell(pool: LiquidityPoolId) -> Option<(FixedI128, FixedI128)> {
+		if <T::LiquidityPools as LiquidityPools<T::AccountId>>::pool_exists(pool) {
+			let result = Self::enp_and_ell_with_action(pool, Action::None).ok()?;
+			return Some(result);
+		}
+		None
+	}
+
+	/// Equity of a liquidity pool, or `None` if the pool doesn't exist or prices are unavailable.
+	pub fn equity_of_pool(pool: LiquidityPoolId) -> Option<FixedI128> {
+		if <T::LiquidityPools as LiquidityPools<T::AccountId>>::pool_exists(pool) {
+			return Self::pool_risk_amounts(pool, None).ok().map(|a| a.equity);
+		}
+		None
+	}
+
+	/// Returns `(above_margin_call, above_stop_out)`, if ENP and ELL of a liquidity pool are above the margin call
+	/// thresholds, and above the stop out thresholds. `None` if the pool doesn't exist or prices are unavailable.
+	pub fn pool_above_risk_thresholds(pool: LiquidityPoolId) -> Option<(bool, bool)> {
+		if !<T::LiquidityPools as LiquidityPools<T::AccountId>>::pool_exists(pool) {
+			return None;
+		}
+		match Self::check_pool(pool, Action::None).ok()? {
+			Risk::None | Risk::Warning => Some((true, true)),
+			Risk::MarginCall => Some((false, true)),
+			Risk::StopOut => Some((false, false)),
+		}
+	}
+
+	/// Returns required deposit amount to make pool safe.
+	pub fn pool_required_deposit(pool: LiquidityPoolId) -> Option<FixedI128> {
+		let PoolRiskAmounts {
+			equity,
+			net_position,
+			longest_leg,
+		} = Self::pool_risk_amounts(pool, None).ok()?;
+		let (enp_threshold, ell_threshold) = Self::enp_and_ell_risk_threshold_of_pool(pool);
+		let required_equity = {
+			let for_enp = net_position
+				.checked_mul(&enp_threshold.margin_call.into())
+				.expect("ENP margin call threshold < 1; qed");
+			let for_ell = longest_leg
+				.checked_mul(&ell_threshold.margin_call.into())
+				.expect("ELL margin call threshold < 1; qed");
+			cmp::max(for_enp, for_ell)
+		};
+		let gap = required_equity.checked_sub(&equity)?;
+
+		if gap.is_positive() {
+			Some(gap)
+		} else {
+			Some(FixedI128::zero())
+		}
+	}
+
+	/// Update risk candidacy of `who` in `pool_id`, and of the pool. No-op if `RiskCandidateBuffer` is not set.
+	fn update_risk_candidates(who: &T::AccountId, pool_id: LiquidityPoolId) {
+		if let Some(buffer) = Self::risk_candidate_buffer() {
+			// margin level is max if no positions, so traders without positions are removed
+			let threshold = Self::risk_threshold_of_trader(who, pool_id);
+			let is_candidate = Self::margin_level(who, pool_id)
+				.map_or(true, |level| level <= Self::with_buffer(threshold.margin_call, buffer));
+			if is_candidate {
+				<RiskCandidateTraders<T>>::insert(who, pool_id, ());
+			} else {
+				<RiskCandidateTraders<T>>::remove(who, pool_id);
+			}
+
+			Self::update_pool_risk_candidates(pool_id);
+		}
+	}
+
+	/// Update risk candidacy of `pool`. No-op if `RiskCandidateBuffer` is not set.
+	fn update_pool_risk_candidates(pool: LiquidityPoolId) {
+		if let Some(buffer) = Self::risk_candidate_buffer() {
+			let is_candidate = Self::enp_and_ell_with_action(pool, Action::None)
+				.map_or(true, |(enp, ell)| Self::is_pool_within_buffer(pool, enp, ell, buffer));
+			Self::set_pool_risk_candidacy(pool, is_candidate);
+		}
+	}
+
+	fn set_pool_risk_candidacy(pool: LiquidityPoolId, is_candidate: bool) {
+		if is_candidate {
+			RiskCandidatePools::insert(pool, ());
+		} else {
+			RiskCandidatePools::remove(pool);
+		}
+	}
+
+	/// Whether `enp` or `ell` of `pool` is within `buffer` of the margin call threshold.
+	fn is_pool_within_buffer(pool: LiquidityPoolId, enp: FixedI128, ell: FixedI128, buffer: Permill) -> bool {
+		let (enp_threshold, ell_threshold) = Self::enp_and_ell_risk_threshold_of_pool(pool);
+		enp <= Self::with_buffer(enp_threshold.margin_call, buffer)
+			|| ell <= Self::with_buffer(ell_threshold.margin_call, buffer)
+	}
+
+	fn with_buffer(threshold: Permill, buffer: Permill) -> FixedI128 {
+		FixedI128::from(threshold)
+			.checked_add(&buffer.into())
+			.unwrap_or(FixedI128::max_value())
+	}
+
+	/// Take ENP and ELL snapshots of all pools with open positions. Returns the weight consumed.
+	fn take_enp_ell_snapshots(now: T::BlockNumber) -> Weight {
+		let mut snapshots_read: Weight = 0;
+		let mut pools: Vec<LiquidityPoolId> = PositionsSnapshots::iter()
+			.inspect(|_| snapshots_read += 1)
+			.filter(|(_, _, snapshot)| snapshot.positions_count > 0)
+			.map(|(pool, _, _)| pool)
+			.collect();
+		let pairs_count = pools.len() as Weight;
+		pools.sort();
+		pools.dedup(); // dedup works as unique for sorted vec, so we sort first
+
+		let max_snapshots = T::GetMaxEnpEllSnapshots::get() as usize;
+		let buffer = Self::risk_candidate_buffer();
+		let mut count: Weight = 0;
+		for pool in pools {
+			if let Some((enp, ell)) = Self::enp_and_ell(pool) {
+				if let Some(buffer) = buffer {
+					Self::set_pool_risk_candidacy(pool, Self::is_pool_within_buffer(pool, enp, ell, buffer));
+				}
+				<EnpEllSnapshots<T>>::mutate(pool, |snapshots| {
+					snapshots.push(EnpEllSnapshot {
+						block_number: now,
+						enp,
+						ell,
+					});
+					if snapshots.len() > max_snapshots {
+						let expired = snapshots.len() - max_snapshots;
+						snapshots.drain(..expired);
+					}
+				});
+				count += 1;
+			}
+		}
+
+		// per pool: pool existence and liquidity reads, snapshots read and write, risk candidacy write
+		let db_weight = T::DbWeight::get();
+		let pools_weight = ENP_ELL_SNAPSHOT_WEIGHT_PER_POOL.saturating_add(db_weight.reads_writes(3, 2));
+		let pairs_weight =
+			ENP_ELL_SNAPSHOT_WEIGHT_PER_PAIR.saturating_add(db_weight.reads(ENP_ELL_SNAPSHOT_READS_PER_PAIR));
+		let pools_weight = pools_weight.saturating_add(Self::pool_risk_weight());
+		db_weight
+			.reads(snapshots_read.saturating_add(1))
+			.saturating_add(count.saturating_mul(pools_weight))
+			.saturating_add(pairs_count.saturating_mul(pairs_weight))
+	}
+}
+
+// Storage migrations
+impl<T: Trait> Module<T> {
+	/// Migrate storage of older versions to `STORAGE_VERSION`. Returns the weight consumed.
+	fn migrate_storage() -> Weight {
+		let version = Self::storage_version();
+		if version >= STORAGE_VERSION {
+			return T::DbWeight::get().reads(1);
+		}
+
+		let mut weight = T::DbWeight::get().reads_writes(1, 1);
+		if version < 1 {
+			weight = weight.saturating_add(Self::migrate_risk_thresholds());
+		}
+		if version < 2 {
+			weight = weight.saturating_add(Self::migrate_margin_held());
+		}
+		if version < 3 {
+			weight = weight.saturating_add(Self::migrate_trader_pending_orders());
+		}
+		StorageVersion::put(STORAGE_VERSION);
+		weight
+	}
+
+	/// Add `warning` to risk thresholds of trading pairs, as zero so that no warning is emitted until set.
+	fn migrate_risk_thresholds() -> Weight {
+		#[derive(Decode)]
+		struct OldRiskThreshold {
+			margin_call: Permill,
+			stop_out: Permill,
+		}
+		#[derive(Decode)]
+		struct OldTradingPairRiskThreshold {
+			trader: Option<OldRiskThreshold>,
+			enp: Option<OldRiskThreshold>,
+			ell: Option<OldRiskThreshold>,
+		}
+
+		let upgrade = |old: Option<OldRiskThreshold>| {
+			old.map(|t| RiskThreshold {
+				margin_call: t.margin_call,
+				stop_out: t.stop_out,
+				warning: Permill::zero(),
+			})
+		};
+		let translated = sp_std::cell::Cell::new(0 as Weight);
+		RiskThresholds::translate::<OldTradingPairRiskThreshold, _>(|_, old| {
+			translated.set(translated.get() + 1);
+			Some(TradingPairRiskThreshold::new(
+				upgrade(old.trader),
+				upgrade(old.enp),
+				upgrade(old.ell),
+			))
+		});
+		T::DbWeight::get().reads_writes(translated.get(), translated.get())
+	}
+
+	/// Fill `MarginHeld` by margin held of open positions.
+	fn migrate_margin_held() -> Weight {
+		let mut positions_read: Weight = 0;
+		let mut margin_held: BTreeMap<(T::AccountId, LiquidityPoolId), FixedI128> = BTreeMap::new();
+		for (_, position) in <Positions<T>>::iter() {
+			positions_read += 1;
+			let held = margin_held
+				.entry((position.owner, position.pool))
+				.or_insert_with(FixedI128::zero);
+			*held = held.saturating_add(position.margin_held);
+		}
+
+		let count = margin_held.len() as Weight;
+		for ((who, pool_id), held) in margin_held {
+			<MarginHeld<T>>::insert(who, pool_id, held);
+		}
+		T::DbWeight::get().reads_writes(positions_read, count)
+	}
+
+	/// Fill `TraderPendingOrders` by counting pending orders of each trader.
+	fn migrate_trader_pending_orders() -> Weight {
+		let mut orders_read: Weight = 0;
+		let mut counts: BTreeMap<T::AccountId, u32> = BTreeMap::new();
+		for (_, order) in <PendingOrders<T>>::iter() {
+			orders_read += 1;
+			*counts.entry(order.owner).or_insert(0) += 1;
+		}
+
+		let count = counts.len() as Weight;
+		for (who, orders) in counts {
+			<TraderPendingOrders<T>>::insert(who, orders);
+		}
+		T::DbWeight::get().reads_writes(orders_read, count)
+	}
+}
+
+// RPC methods
+impl<T: Trait> Module<T> {
+	/// Simulate opening a position, without any storage change.
+	///
+	/// Returns the projected outcome, or the error `open_position` would fail with. Like `open_position`, each
+	/// price is read once.
+	pub fn simulate_open_position(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		leverage: Leverage,
+		leveraged_amount: Balance,
+		price: Price,
+	) -> result::Result<OpenPositionSimulation, DispatchError> {
+		Self::with_price_cache(|| {
+			let (position, open_price) = Self::new_position(who, pool_id, pair, leverage, leveraged_amount, price)?;
+			let margin_level = Self::margin_level_with_new_position(who, pool_id, Some(&position))?;
+			let (enp, ell) = Self::enp_and_ell_with_action(pool_id, Action::OpenPosition(position.clone()))?;
+
+			Ok(OpenPositionSimulation {
+				open_price,
+				margin_held: position.margin_held,
+				margin_level,
+				enp,
+				ell,
+			})
+		})
+	}
+
+	/// Returns effective risk parameters of `who` opening positions of `pair` in `pool_id`.
+	pub fn risk_params(who: &T::AccountId, pool_id: LiquidityPoolId, pair: TradingPair) -> RiskParams {
+		let trader_threshold = Self::risk_threshold_of_trader(who, pool_id)
+			.max(Self::trader_risk_threshold(pool_id, pair).unwrap_or_default());
+		let (mut enp_threshold, mut ell_threshold) = Self::enp_and_ell_risk_threshold_of_pool(pool_id);
+		// pool thresholds apply only if both ENP and ELL thresholds are set
+		if let (Some(enp), Some(ell)) = (
+			Self::liquidity_pool_enp_threshold(pool_id, pair),
+			Self::liquidity_pool_ell_threshold(pool_id, pair),
+		) {
+			enp_threshold = enp_threshold.max(enp);
+			ell_threshold = ell_threshold.max(ell);
+		}
+
+		let discount = T::FeeDiscount::fee_discount(who);
+		let discounted = |spread: Balance| spread.saturating_sub(discount * spread);
+
+		let trader_positions = <PositionsByTrader<T>>::iter_prefix(who)
+			.filter(|((p, _), _)| *p == pool_id)
+			.count();
+		let pool_positions = Self::pool_positions_snapshots(pool_id, pair).positions_count as usize;
+
+		RiskParams {
+			trader_threshold,
+			enp_threshold,
+			ell_threshold,
+			bid_spread: T::LiquidityPools::bid_spread(pool_id, pair).map(discounted),
+			ask_spread: T::LiquidityPools::ask_spread(pool_id, pair).map(discounted),
+			trader_positions_headroom: T::GetTraderMaxOpenPositions::get().saturating_sub(trader_positions) as u32,
+			pool_positions_headroom: T::GetPoolMaxOpenPositions::get().saturating_sub(pool_positions) as u32,
+		}
+	}
 
-		Ok(())
+	/// Trading volumes of `pair` in `pool_id`, rolled over to the current period.
+	pub fn trading_volume(pool_id: LiquidityPoolId, pair: TradingPair) -> RollingVolume<T::BlockNumber, TradingVolume> {
+		let mut volumes = Self::trading_volumes(pool_id, pair);
+		volumes.roll_over(<frame_system::Module<T>>::block_number(), T::GetVolumePeriod::get());
+		volumes
 	}
 
-	/// Return risk threshold of trader based on opened positions after performing an action.
+	/// Estimated oracle price of the position's pair, at which the owner's margin level would reach the stop out
+	/// threshold, with other prices, balances and positions unchanged.
 	///
-	/// Return `RiskThreshold` or `Default` value.
-	fn risk_threshold_of_trader(who: &T::AccountId, pool_id: LiquidityPoolId) -> RiskThreshold {
-		let (trader_margin_call, trader_stop_out) = <PositionsByTrader<T>>::iter_prefix(who)
-			.filter(|((p, _), _)| *p == pool_id)
-			.fold(vec![], |mut v, ((_, position_id), _)| {
-				if let Some(position) = Self::positions(position_id) {
-					if !v.contains(&position.pair) {
-						v.push(position.pair);
-					}
-				}
-				v
-			})
-			.iter()
-			.filter_map(|pair| Self::trader_risk_threshold(*pair))
-			.map(|v| (v.margin_call, v.stop_out))
-			.fold((Permill::zero(), Permill::zero()), |max, v| {
-				(cmp::max(max.0, v.0), cmp::max(max.1, v.1))
-			});
+	/// Cross margin positions are liquidated with the trader's margin level, isolated positions with their own.
+	///
+	/// liquidation_price = price + (stop_out * leveraged_debits_in_usd - equity) / leveraged_held_in_usd
+	///
+	/// Returns `None` if the position doesn't exist, prices are unavailable, or the price would be non-positive.
+	pub fn estimated_liquidation_price(position_id: PositionId) -> Option<Price> {
+		let position = Self::positions(position_id)?;
+		Self::with_price_cache(|| -> result::Result<Price, DispatchError> {
+			let (equity, debits_in_usd, stop_out) = if Self::isolated_position(position_id).is_some() {
+				let stop_out = Self::risk_threshold_of_isolated_position(&position).stop_out;
+				(
+					Self::isolated_equity_of_position(&position)?,
+					Self::notional_of_position(&position)?,
+					stop_out,
+				)
+			} else {
+				let debits_in_usd = Self::cross_positions_of_trader(&position.owner, position.pool)
+					.try_fold::<_, _, FixedI128Result>(FixedI128::zero(), |acc, p| {
+						acc.checked_add(&Self::notional_of_position(&p)?)
+							.ok_or(Error::<T>::NumOutOfBound.into())
+					})?;
+				let stop_out = Self::risk_threshold_of_trader(&position.owner, position.pool).stop_out;
+				(
+					Self::equity_of_trader(&position.owner, position.pool)?,
+					debits_in_usd,
+					stop_out,
+				)
+			};
+
+			let held_in_usd = Self::usd_value(position.pair.quote, position.leveraged_held)?;
+			let price_delta = FixedI128::from(stop_out)
+				.checked_mul(&debits_in_usd)
+				.and_then(|d| d.checked_sub(&equity))
+				.and_then(|d| d.checked_div(&held_in_usd))
+				.ok_or(Error::<T>::NumOutOfBound)?;
+			let price = fixed_i128_from_fixed_u128(Self::price(position.pair.base, position.pair.quote)?)
+				.checked_add(&price_delta)
+				.filter(|p| p.is_positive())
+				.ok_or(Error::<T>::NumOutOfBound)?;
+
+			Ok(Price::from_inner(u128_from_fixed_i128(price)))
+		})
+		.ok()
+	}
 
-		RiskThreshold {
-			margin_call: trader_margin_call,
-			stop_out: trader_stop_out,
+	/// Returns if `trader_stop_out` would succeed for `who` in `pool_id`.
+	///
+	/// Note stopping out a trader is not rewarded.
+	pub fn can_stop_out_trader(who: &T::AccountId, pool_id: LiquidityPoolId) -> bool {
+		match Self::check_trader(who, pool_id, Action::None) {
+			Ok(Risk::StopOut) => true,
+			_ => false,
 		}
 	}
+}
 
-	/// Return risk threshold of liquidity pool based on opened positions after performing an
-	/// action.
+// State checks
+#[cfg(any(feature = "state-checks", test))]
+impl<T: Trait> Module<T> {
+	/// Check accounting invariants of the protocol storage. Returns the first one violated.
 	///
-	/// Return `RiskThreshold` or `Default` value.
-	fn enp_and_ell_risk_threshold_of_pool(pool_id: LiquidityPoolId) -> (RiskThreshold, RiskThreshold) {
-		let (enp_margin_call, enp_stop_out, ell_margin_call, ell_stop_out) = PositionsSnapshots::iter_prefix(pool_id)
-			.fold(vec![], |mut v, (pair, _)| {
-				if !v.contains(&pair) {
-					v.push(pair);
-				}
-				v
-			})
-			.iter()
-			.filter_map(|pair| {
-				let enp = Self::liquidity_pool_enp_threshold(*pair)?;
-				let ell = Self::liquidity_pool_ell_threshold(*pair)?;
-				Some((enp.margin_call, enp.stop_out, ell.margin_call, ell.stop_out))
-			})
-			.fold(
-				(Permill::zero(), Permill::zero(), Permill::zero(), Permill::zero()),
-				|max, v| {
-					(
-						cmp::max(max.0, v.0),
-						cmp::max(max.1, v.1),
-						cmp::max(max.2, v.2),
-						cmp::max(max.3, v.3),
-					)
-				},
+	/// - The module account holds at least the sum of positive trader balances. Negative balances are owed to
+	///   pools, and not held by the module account.
+	/// - Positions are indexed by trader and by pool, and each index entry points to a matching position.
+	/// - Margin held of traders, positions count of pool snapshots, and open interests match opened positions.
+	/// - Positions are opened in existing pools. Pool liquidity is unsigned, so settlements that would make it
+	///   negative fail instead.
+	pub fn check_state() -> result::Result<(), &'static str> {
+		let next_position_id = Self::next_position_id();
+		let mut margin_held: BTreeMap<(T::AccountId, LiquidityPoolId), FixedI128> = BTreeMap::new();
+		let mut snapshots: BTreeMap<(LiquidityPoolId, TradingPair), PositionsSnapshot> = BTreeMap::new();
+		let mut open_interests: BTreeMap<TradingPair, OpenInterest> = BTreeMap::new();
+		for (id, position) in <Positions<T>>::iter() {
+			ensure!(id < next_position_id, "position id not less than next position id");
+			ensure!(
+				<PositionsByTrader<T>>::contains_key(&position.owner, (position.pool, id)),
+				"position not indexed by trader"
+			);
+			ensure!(
+				PositionsByPool::contains_key(position.pool, (position.pair, id)),
+				"position not indexed by pool"
+			);
+			ensure!(
+				T::LiquidityPools::pool_exists(position.pool),
+				"position opened in nonexistent pool"
 			);
 
-		(
-			RiskThreshold {
-				margin_call: enp_margin_call,
-				stop_out: enp_stop_out,
-			},
-			RiskThreshold {
-				margin_call: ell_margin_call,
-				stop_out: ell_stop_out,
-			},
-		)
-	}
+			let held = margin_held
+				.entry((position.owner.clone(), position.pool))
+				.or_insert_with(FixedI128::zero);
+			*held = held.checked_add(&position.margin_held).ok_or("margin held overflow")?;
+			let snapshot = snapshots.entry((position.pool, position.pair)).or_default();
+			snapshot.positions_count += 1;
+			let amounts = if position.leverage.is_long() {
+				&mut snapshot.long
+			} else {
+				&mut snapshot.short
+			};
+			amounts.held = amounts
+				.held
+				.checked_add(&position.leveraged_held)
+				.ok_or("snapshot overflow")?;
+			amounts.debits = amounts
+				.debits
+				.checked_add(&position.leveraged_debits)
+				.ok_or("snapshot overflow")?;
+			open_interests
+				.entry(position.pair)
+				.or_default()
+				.add(&position)
+				.ok_or("open interest overflow")?;
+		}
 
-	pub fn enp_and_ell(pool: LiquidityPoolId) -> Option<(FixedI128, FixedI128)> {
-		if <T::LiquidityPools as LiquidityPools<T::AccountId>>::pool_exists(pool) {
-			let result = Self::enp_and_ell_with_action(pool, Action::None).ok()?;
-			return Some(result);
+		for (who, (pool_id, id), _) in <PositionsByTrader<T>>::iter() {
+			let position = Self::positions(id).ok_or("trader index of nonexistent position")?;
+			ensure!(
+				position.owner == who && position.pool == pool_id,
+				"trader index mismatches position"
+			);
+		}
+		for (pool_id, (pair, id), _) in PositionsByPool::iter() {
+			let position = Self::positions(id).ok_or("pool index of nonexistent position")?;
+			ensure!(
+				position.pool == pool_id && position.pair == pair,
+				"pool index mismatches position"
+			);
 		}
-		None
-	}
 
-	/// Returns required deposit amount to make pool safe.
-	pub fn pool_required_deposit(pool: LiquidityPoolId) -> Option<FixedI128> {
-		let (net_position, longest_leg) = Self::net_position_and_longest_leg(pool, None).ok()?;
-		let (enp_threshold, ell_threshold) = Self::enp_and_ell_risk_threshold_of_pool(pool);
-		let required_equity = {
-			let for_enp = net_position
-				.checked_mul(&enp_threshold.margin_call.into())
-				.expect("ENP margin call threshold < 1; qed");
-			let for_ell = longest_leg
-				.checked_mul(&ell_threshold.margin_call.into())
-				.expect("ELL margin call threshold < 1; qed");
-			cmp::max(for_enp, for_ell)
-		};
-		let equity = Self::equity_of_pool(pool).ok()?;
-		let gap = required_equity.checked_sub(&equity)?;
+		for (who, pool_id, held) in <MarginHeld<T>>::iter() {
+			ensure!(
+				margin_held.get(&(who, pool_id)).copied().unwrap_or_else(FixedI128::zero) == held,
+				"margin held mismatches positions"
+			);
+		}
+		for ((who, pool_id), held) in margin_held {
+			ensure!(Self::margin_held(who, pool_id) == held, "margin held mismatches positions");
+		}
 
-		if gap.is_positive() {
-			Some(gap)
-		} else {
-			Some(FixedI128::zero())
+		for (pool_id, pair, snapshot) in PositionsSnapshots::iter() {
+			let expected = snapshots.get(&(pool_id, pair)).cloned().unwrap_or_default();
+			ensure!(
+				expected.positions_count == snapshot.positions_count,
+				"snapshot positions count mismatches positions"
+			);
+			ensure!(
+				expected.long == snapshot.long && expected.short == snapshot.short,
+				"snapshot amounts mismatch positions"
+			);
+		}
+		for ((pool_id, pair), expected) in snapshots {
+			let snapshot = Self::pool_positions_snapshots(pool_id, pair);
+			ensure!(
+				snapshot.positions_count == expected.positions_count,
+				"snapshot positions count mismatches positions"
+			);
+			ensure!(
+				snapshot.long == expected.long && snapshot.short == expected.short,
+				"snapshot amounts mismatch positions"
+			);
+		}
+
+		for (pair, open_interest) in OpenInterests::iter() {
+			ensure!(
+				open_interests.get(&pair).cloned().unwrap_or_default() == open_interest,
+				"open interest mismatches positions"
+			);
+		}
+		for (pair, open_interest) in open_interests {
+			ensure!(
+				Self::open_interest(pair) == open_interest,
+				"open interest mismatches positions"
+			);
+		}
+
+		let mut pending_orders: BTreeMap<T::AccountId, u32> = BTreeMap::new();
+		for (_, order) in <PendingOrders<T>>::iter() {
+			*pending_orders.entry(order.owner).or_insert(0) += 1;
+		}
+		for (who, count) in <TraderPendingOrders<T>>::iter() {
+			ensure!(
+				pending_orders.get(&who).copied().unwrap_or(0) == count,
+				"pending orders count mismatches orders"
+			);
+		}
+		for (who, count) in pending_orders {
+			ensure!(
+				Self::trader_pending_orders(who) == count,
+				"pending orders count mismatches orders"
+			);
+		}
+
+		let positive_balances = <Balances<T>>::iter()
+			.map(|(_, _, balance)| cmp::max(balance, FixedI128::zero()))
+			.try_fold(FixedI128::zero(), |acc, balance| acc.checked_add(&balance))
+			.ok_or("trader balances overflow")?;
+		let module_balance = fixed_i128_from_balance(T::LiquidityCurrency::free_balance(&Self::account_id()))
+			.map_err(|_| "module account balance overflow")?;
+		ensure!(
+			module_balance >= positive_balances,
+			"module account balance less than trader balances"
+		);
+
+		let mut collaterals: BTreeMap<CurrencyId, Balance> = BTreeMap::new();
+		let locked = <Collaterals<T>>::iter().map(|(_, (_, currency_id), amount)| (currency_id, amount));
+		let seized = SeizedCollaterals::iter().map(|(_, currency_id, amount)| (currency_id, amount));
+		for (currency_id, amount) in locked.chain(seized) {
+			let total = collaterals.entry(currency_id).or_insert(0);
+			*total = total.checked_add(amount).ok_or("collaterals overflow")?;
 		}
+		for (currency_id, total) in collaterals {
+			ensure!(
+				T::MultiCurrency::free_balance(currency_id, &Self::account_id()) >= total,
+				"module account balance less than collaterals"
+			);
+		}
+
+		Ok(())
 	}
 }
 
 impl<T: Trait> BaseLiquidityPoolManager<LiquidityPoolId, Balance> for Module<T> {
 	/// Returns if `pool` has liability in margin protocol.
 	fn can_remove(pool: LiquidityPoolId) -> bool {
-		PositionsSnapshots::iter_prefix(pool).fold(0, |num, (_, snapshot)| num + snapshot.positions_count) == 0
+		Self::pool_positions_count(pool) == 0 && !T::PoolLiabilities::has_liabilities(pool)
 	}
 
 	fn ensure_can_withdraw(pool_id: LiquidityPoolId, amount: Balance) -> DispatchResult {
 		Self::ensure_pool_safe(pool_id, Action::Withdraw(amount))
 	}
+
+	fn ensure_can_withdraw_weight() -> Weight {
+		Self::pool_risk_weight()
+	}
 }
 
 impl<T: Trait> MarginProtocolLiquidityPoolsManager for Module<T> {
 	fn ensure_can_enable_trading_pair(pool_id: LiquidityPoolId, pair: TradingPair) -> DispatchResult {
-		Self::trader_risk_threshold(pair).ok_or(Error::<T>::NoRiskThreshold)?;
-		let enp_threshold = Self::liquidity_pool_enp_threshold(pair).ok_or(Error::<T>::NoRiskThreshold)?;
-		let ell_threshold = Self::liquidity_pool_ell_threshold(pair).ok_or(Error::<T>::NoRiskThreshold)?;
+		Self::trader_risk_threshold(pool_id, pair).ok_or(Error::<T>::NoRiskThreshold)?;
+		let enp_threshold = Self::liquidity_pool_enp_threshold(pool_id, pair).ok_or(Error::<T>::NoRiskThreshold)?;
+		let ell_threshold = Self::liquidity_pool_ell_threshold(pool_id, pair).ok_or(Error::<T>::NoRiskThreshold)?;
 
 		let (enp, ell) = Self::enp_and_ell_with_action(pool_id, Action::None)?;
 		if enp <= enp_threshold.stop_out.into() || ell <= ell_threshold.stop_out.into() {
@@ -1567,6 +7183,32 @@ impl<T: Trait> MarginProtocolLiquidityPoolsManager for Module<T> {
 		}
 		Ok(())
 	}
+
+	fn is_pool_safe(pool_id: LiquidityPoolId) -> bool {
+		!Self::is_pool_margin_called(&pool_id)
+			&& match Self::check_pool(pool_id, Action::None) {
+				Ok(Risk::None) | Ok(Risk::Warning) => true,
+				_ => false,
+			}
+	}
+
+	fn enp_and_ell(pool_id: LiquidityPoolId) -> Option<(FixedI128, FixedI128)> {
+		Self::enp_and_ell(pool_id)
+	}
+}
+
+impl<T: Trait> MarginPoolRisk for Module<T> {
+	fn ensure_pool_safe_with(
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		liabilities: PoolLiabilityAmounts,
+	) -> DispatchResult {
+		Self::ensure_pool_safe(pool_id, Action::AddLiabilities(pair, liabilities))
+	}
+
+	fn pool_risk_weight() -> Weight {
+		Self::pool_risk_weight()
+	}
 }
 
 /// Error which may occur while executing the off-chain code.
@@ -1576,11 +7218,25 @@ enum OffchainErr {
 	SubmitTransaction,
 	NotValidator,
 	CheckFail,
+	InvalidNotificationEndpoint,
+	NoNotificationKey,
+	SignNotification,
+	SendNotification,
 }
 
 // constant for offchain worker
 const LOCK_DURATION: u64 = 40_000; // 40 sec
 const OFFCHAIN_WORKER_LOCK: &[u8] = b"laminar/margin-protocol/offchain-worker-lock";
+/// Interval of blocks to check all traders and pools, instead of risk candidates only.
+const OFFCHAIN_FULL_SCAN_INTERVAL: u32 = 10;
+const NOTIFICATION_TIMEOUT: u64 = 10_000; // 10 sec
+/// Offchain local storage key of the notification HTTPS endpoint, a SCALE encoded `Vec<u8>`.
+pub const NOTIFICATION_ENDPOINT: &[u8] = b"laminar/margin-protocol/notification-endpoint";
+/// Offchain local storage key of the accounts to notify, a SCALE encoded `Vec<AccountId>`.
+pub const NOTIFICATION_ACCOUNTS: &[u8] = b"laminar/margin-protocol/notification-accounts";
+const NOTIFIED_RISK_PREFIX: &[u8] = b"laminar/margin-protocol/notified-risk/";
+/// Key type of the ed25519 key signing notifications.
+pub const NOTIFICATION_KEY_TYPE: KeyTypeId = KeyTypeId(*b"lmnt");
 #[cfg(feature = "std")]
 const TAG: &str = "MARGIN_PROTOCOL_OFFCHAIN_WORKER";
 
@@ -1591,6 +7247,10 @@ impl sp_std::fmt::Debug for OffchainErr {
 			OffchainErr::SubmitTransaction => write!(fmt, "Failed to submit transaction"),
 			OffchainErr::NotValidator => write!(fmt, "Not validator"),
 			OffchainErr::CheckFail => write!(fmt, "Check fail"),
+			OffchainErr::InvalidNotificationEndpoint => write!(fmt, "Notification endpoint is not a valid HTTPS URL"),
+			OffchainErr::NoNotificationKey => write!(fmt, "No notification signing key"),
+			OffchainErr::SignNotification => write!(fmt, "Failed to sign notification"),
+			OffchainErr::SendNotification => write!(fmt, "Failed to send notification"),
 		}
 	}
 }
@@ -1615,6 +7275,28 @@ impl<T: Trait> Module<T> {
 		pools
 	}
 
+	/// Get a list of risk candidate and margin called traders.
+	fn get_candidate_traders() -> Vec<(T::AccountId, LiquidityPoolId)> {
+		let mut traders: Vec<(T::AccountId, LiquidityPoolId)> = <RiskCandidateTraders<T>>::iter()
+			.chain(<MarginCalledTraders<T>>::iter())
+			.map(|(who, pool_id, _)| (who, pool_id))
+			.collect();
+		traders.sort();
+		traders.dedup(); // dedup works as unique for sorted vec, so we sort first
+		traders
+	}
+
+	/// Get a list of risk candidate and margin called pools.
+	fn get_candidate_pools() -> Vec<LiquidityPoolId> {
+		let mut pools: Vec<LiquidityPoolId> = RiskCandidatePools::iter()
+			.chain(MarginCalledPools::iter())
+			.map(|(pool, _)| pool)
+			.collect();
+		pools.sort();
+		pools.dedup(); // dedup works as unique for sorted vec, so we sort first
+		pools
+	}
+
 	fn offchain_worker(block_number: T::BlockNumber) -> Result<(), OffchainErr> {
 		// check if we are a potential validator
 		if !sp_io::offchain::is_validator() {
@@ -1628,8 +7310,31 @@ impl<T: Trait> Module<T> {
 
 		debug::native::trace!(target: TAG, "Started [block_number = {:?}]", block_number);
 
-		for (trader, pool_id) in Self::get_traders() {
-			match Self::check_trader(&trader, pool_id, Action::None).map_err(|_| OffchainErr::CheckFail)? {
+		// Risk candidacy isn't updated on price changes, check all regularly for the ones out of buffer.
+		let full_scan = Self::risk_candidate_buffer().is_none()
+			|| block_number % OFFCHAIN_FULL_SCAN_INTERVAL.into() == 0u32.into();
+		let (traders, pools) = if full_scan {
+			(Self::get_traders(), Self::get_pools())
+		} else {
+			(Self::get_candidate_traders(), Self::get_candidate_pools())
+		};
+
+		for (trader, pool_id) in traders {
+			// a trader failing the check, like on missing prices, shouldn't block checking the others
+			let risk = match Self::check_trader(&trader, pool_id, Action::None) {
+				Ok(risk) => risk,
+				Err(_) => {
+					debug::native::warn!(
+						target: TAG,
+						"Trader check failed [trader = {:?}, pool_id = {:?}, block_number = {:?}]",
+						trader,
+						pool_id,
+						block_number
+					);
+					continue;
+				}
+			};
+			match risk {
 				Risk::StopOut => {
 					let who = T::Lookup::unlookup(trader.clone());
 					let call = Call::<T>::trader_stop_out(who, pool_id);
@@ -1656,7 +7361,7 @@ impl<T: Trait> Module<T> {
 						);
 					}
 				}
-				Risk::None => {
+				Risk::None | Risk::Warning => {
 					if Self::is_trader_margin_called(&trader, pool_id) {
 						let who = T::Lookup::unlookup(trader.clone());
 						let call = Call::<T>::trader_become_safe(who, pool_id);
@@ -1675,8 +7380,85 @@ impl<T: Trait> Module<T> {
 			guard.extend_lock().map_err(|_| OffchainErr::OffchainLock)?;
 		}
 
-		for pool_id in Self::get_pools() {
-			match Self::check_pool(pool_id, Action::None).map_err(|_| OffchainErr::CheckFail)? {
+		for (position_id, _) in IsolatedPositions::iter() {
+			if Self::should_stop_out_isolated_position(position_id) {
+				let call = Call::<T>::isolated_position_stop_out(position_id);
+				SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into())
+					.map_err(|_| OffchainErr::SubmitTransaction)?;
+				debug::native::trace!(
+					target: TAG,
+					"Isolated position stopped out [position_id = {:?}, block_number = {:?}]",
+					position_id,
+					block_number
+				);
+			}
+
+			guard.extend_lock().map_err(|_| OffchainErr::OffchainLock)?;
+		}
+
+		for (position_id, _) in StopLosses::iter() {
+			if Self::should_trigger_stop_loss(position_id) {
+				let call = Call::<T>::trigger_stop_loss(position_id);
+				SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into())
+					.map_err(|_| OffchainErr::SubmitTransaction)?;
+				debug::native::trace!(
+					target: TAG,
+					"Stop loss triggered [position_id = {:?}, block_number = {:?}]",
+					position_id,
+					block_number
+				);
+			}
+
+			guard.extend_lock().map_err(|_| OffchainErr::OffchainLock)?;
+		}
+
+		for (position_id, _) in TakeProfits::iter() {
+			if Self::should_trigger_take_profit(position_id) {
+				let call = Call::<T>::trigger_take_profit(position_id);
+				SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into())
+					.map_err(|_| OffchainErr::SubmitTransaction)?;
+				debug::native::trace!(
+					target: TAG,
+					"Take profit triggered [position_id = {:?}, block_number = {:?}]",
+					position_id,
+					block_number
+				);
+			}
+
+			guard.extend_lock().map_err(|_| OffchainErr::OffchainLock)?;
+		}
+
+		for (order_id, _) in <PendingOrders<T>>::iter() {
+			if Self::should_execute_order(order_id) {
+				let call = Call::<T>::execute_order(order_id);
+				SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into())
+					.map_err(|_| OffchainErr::SubmitTransaction)?;
+				debug::native::trace!(
+					target: TAG,
+					"Order executed [order_id = {:?}, block_number = {:?}]",
+					order_id,
+					block_number
+				);
+			}
+
+			guard.extend_lock().map_err(|_| OffchainErr::OffchainLock)?;
+		}
+
+		for pool_id in pools {
+			// a pool failing the check, like on missing prices, shouldn't block checking the others
+			let risk = match Self::check_pool(pool_id, Action::None) {
+				Ok(risk) => risk,
+				Err(_) => {
+					debug::native::warn!(
+						target: TAG,
+						"Liquidity pool check failed [pool_id = {:?}, block_number = {:?}]",
+						pool_id,
+						block_number
+					);
+					continue;
+				}
+			};
+			match risk {
 				Risk::StopOut => {
 					let call = Call::<T>::liquidity_pool_force_close(pool_id);
 					SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into())
@@ -1701,7 +7483,7 @@ impl<T: Trait> Module<T> {
 						);
 					}
 				}
-				Risk::None => {
+				Risk::None | Risk::Warning => {
 					if Self::is_pool_margin_called(&pool_id) {
 						let call = Call::<T>::liquidity_pool_become_safe(pool_id);
 						SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into())
@@ -1725,6 +7507,138 @@ impl<T: Trait> Module<T> {
 		// drop `guard` and unlock implicitly at end of scope.
 	}
 
+	/// Notify the locally configured accounts of their risks, if `NOTIFICATION_ENDPOINT` is set.
+	///
+	/// For each pool a configured account has positions in, a signed JSON payload is POSTed to the endpoint once
+	/// its risk changes to warning, margin call or stop out. The signature of the payload is sent in the
+	/// `X-Laminar-Signature` header, signed by the first `NOTIFICATION_KEY_TYPE` key in the keystore.
+	fn offchain_notify(block_number: T::BlockNumber) -> Result<(), OffchainErr> {
+		let endpoint = match StorageValueRef::persistent(NOTIFICATION_ENDPOINT).get::<Vec<u8>>() {
+			Some(Some(endpoint)) => endpoint,
+			_ => return Ok(()),
+		};
+		let endpoint = sp_std::str::from_utf8(&endpoint).map_err(|_| OffchainErr::InvalidNotificationEndpoint)?;
+		if !endpoint.starts_with("https://") {
+			return Err(OffchainErr::InvalidNotificationEndpoint);
+		}
+		let accounts = StorageValueRef::persistent(NOTIFICATION_ACCOUNTS)
+			.get::<Vec<T::AccountId>>()
+			.flatten()
+			.unwrap_or_default();
+
+		for trader in accounts {
+			let mut pools: Vec<LiquidityPoolId> = <PositionsByTrader<T>>::iter_prefix(&trader)
+				.map(|((pool_id, _), _)| pool_id)
+				.collect();
+			pools.sort();
+			pools.dedup();
+
+			for pool_id in pools {
+				// failures are retried on later blocks, without holding up the other notifications
+				let risk = match Self::check_trader(&trader, pool_id, Action::None) {
+					Ok(risk) => risk,
+					Err(_) => {
+						debug::native::error!(
+							target: TAG,
+							"Notification: {:?} [trader = {:?}, pool_id = {:?}, block_number = {:?}]",
+							OffchainErr::CheckFail,
+							trader,
+							pool_id,
+							block_number,
+						);
+						continue;
+					}
+				};
+				let notified_key = [NOTIFIED_RISK_PREFIX, &(&trader, pool_id).encode()[..]].concat();
+				let notified = StorageValueRef::persistent(&notified_key);
+				if notified.get::<Risk>().flatten().as_ref() == Some(&risk) {
+					continue;
+				}
+
+				if risk != Risk::None {
+					let payload = Self::notification_payload(block_number, &trader, pool_id, &risk);
+					if let Err(error) = Self::send_notification(endpoint, payload) {
+						debug::native::error!(
+							target: TAG,
+							"Notification: {:?} [trader = {:?}, pool_id = {:?}, block_number = {:?}]",
+							error,
+							trader,
+							pool_id,
+							block_number,
+						);
+						continue;
+					}
+					debug::native::trace!(
+						target: TAG,
+						"Trader notified [trader = {:?}, pool_id = {:?}, block_number = {:?}]",
+						trader,
+						pool_id,
+						block_number
+					);
+				}
+				notified.set(&risk);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// JSON payload of a notification:
+	/// `{"block_number":1,"trader":"0x..","pool_id":0,"risk":"margin_call"}`, where `trader` is SCALE encoded.
+	fn notification_payload(
+		block_number: T::BlockNumber,
+		trader: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		risk: &Risk,
+	) -> Vec<u8> {
+		let risk: &[u8] = match risk {
+			Risk::None => b"none",
+			Risk::Warning => b"warning",
+			Risk::MarginCall => b"margin_call",
+			Risk::StopOut => b"stop_out",
+		};
+		let mut payload = Vec::new();
+		payload.extend_from_slice(b"{\"block_number\":");
+		payload.extend_from_slice(&decimal_encode(block_number.unique_saturated_into()));
+		payload.extend_from_slice(b",\"trader\":\"");
+		payload.extend_from_slice(&hex_encode(&trader.encode()));
+		payload.extend_from_slice(b"\",\"pool_id\":");
+		payload.extend_from_slice(&decimal_encode(pool_id.into()));
+		payload.extend_from_slice(b",\"risk\":\"");
+		payload.extend_from_slice(risk);
+		payload.extend_from_slice(b"\"}");
+		payload
+	}
+
+	fn send_notification(endpoint: &str, payload: Vec<u8>) -> Result<(), OffchainErr> {
+		let public_key = sp_io::crypto::ed25519_public_keys(NOTIFICATION_KEY_TYPE)
+			.into_iter()
+			.next()
+			.ok_or(OffchainErr::NoNotificationKey)?;
+		let signature = sp_io::crypto::ed25519_sign(NOTIFICATION_KEY_TYPE, &public_key, &payload)
+			.ok_or(OffchainErr::SignNotification)?;
+		let public_key = hex_encode(public_key.as_ref());
+		let signature = hex_encode(signature.as_ref());
+
+		let deadline = sp_io::offchain::timestamp().add(Duration::from_millis(NOTIFICATION_TIMEOUT));
+		let pending = http::Request::post(endpoint, vec![payload])
+			.add_header("Content-Type", "application/json")
+			.add_header("X-Laminar-Public-Key", sp_std::str::from_utf8(&public_key).expect("hex is utf8; qed"))
+			.add_header("X-Laminar-Signature", sp_std::str::from_utf8(&signature).expect("hex is utf8; qed"))
+			.deadline(deadline)
+			.send()
+			.map_err(|_| OffchainErr::SendNotification)?;
+		let response = pending
+			.try_wait(deadline)
+			.map_err(|_| OffchainErr::SendNotification)?
+			.map_err(|_| OffchainErr::SendNotification)?;
+		if response.code < 200 || response.code >= 300 {
+			return Err(OffchainErr::SendNotification);
+		}
+
+		Ok(())
+	}
+
 	fn is_trader_margin_called(who: &T::AccountId, pool_id: LiquidityPoolId) -> bool {
 		<MarginCalledTraders<T>>::contains_key(&who, pool_id)
 	}
@@ -1746,6 +7660,55 @@ impl<T: Trait> Module<T> {
 			_ => Ok(false),
 		}
 	}
+
+	fn should_stop_out_isolated_position(position_id: PositionId) -> bool {
+		Self::positions(position_id).map_or(false, |position| {
+			Self::is_isolated_position_stop_out(&position) == Ok(true)
+		})
+	}
+
+	fn should_trigger_stop_loss(position_id: PositionId) -> bool {
+		Self::positions(position_id).map_or(false, |position| {
+			Self::is_stop_loss_reached(position_id, &position) == Ok(true)
+		})
+	}
+
+	fn should_trigger_take_profit(position_id: PositionId) -> bool {
+		Self::positions(position_id).map_or(false, |position| {
+			Self::is_take_profit_reached(position_id, &position) == Ok(true)
+		})
+	}
+
+	fn should_execute_order(order_id: OrderId) -> bool {
+		!Self::is_order_expired(order_id)
+			&& Self::pending_orders(order_id).map_or(false, |order| Self::is_order_triggered(&order) == Ok(true))
+	}
+}
+
+/// `0x` prefixed lowercase hex of `data`.
+fn hex_encode(data: &[u8]) -> Vec<u8> {
+	const HEX: &[u8; 16] = b"0123456789abcdef";
+	let mut hex = Vec::with_capacity(2 + data.len() * 2);
+	hex.extend_from_slice(b"0x");
+	for byte in data {
+		hex.push(HEX[(byte >> 4) as usize]);
+		hex.push(HEX[(byte & 0xf) as usize]);
+	}
+	hex
+}
+
+/// Decimal digits of `n`.
+fn decimal_encode(mut n: u64) -> Vec<u8> {
+	let mut digits = Vec::new();
+	loop {
+		digits.push(b'0' + (n % 10) as u8);
+		n /= 10;
+		if n == 0 {
+			break;
+		}
+	}
+	digits.reverse();
+	digits
 }
 
 impl<T: Trait> frame_support::unsigned::ValidateUnsigned for Module<T> {
@@ -1791,6 +7754,50 @@ impl<T: Trait> frame_support::unsigned::ValidateUnsigned for Module<T> {
 				}
 				InvalidTransaction::Stale.into()
 			}
+			Call::isolated_position_stop_out(position_id) => {
+				if Self::should_stop_out_isolated_position(*position_id) {
+					return ValidTransaction::with_tag_prefix("margin_protocol/isolated_position_stop_out")
+						.priority(T::UnsignedPriority::get())
+						.and_provides(position_id)
+						.longevity(64_u64)
+						.propagate(true)
+						.build();
+				}
+				InvalidTransaction::Stale.into()
+			}
+			Call::trigger_stop_loss(position_id) => {
+				if Self::should_trigger_stop_loss(*position_id) {
+					return ValidTransaction::with_tag_prefix("margin_protocol/trigger_stop_loss")
+						.priority(T::UnsignedPriority::get())
+						.and_provides(position_id)
+						.longevity(64_u64)
+						.propagate(true)
+						.build();
+				}
+				InvalidTransaction::Stale.into()
+			}
+			Call::trigger_take_profit(position_id) => {
+				if Self::should_trigger_take_profit(*position_id) {
+					return ValidTransaction::with_tag_prefix("margin_protocol/trigger_take_profit")
+						.priority(T::UnsignedPriority::get())
+						.and_provides(position_id)
+						.longevity(64_u64)
+						.propagate(true)
+						.build();
+				}
+				InvalidTransaction::Stale.into()
+			}
+			Call::execute_order(order_id) => {
+				if Self::should_execute_order(*order_id) {
+					return ValidTransaction::with_tag_prefix("margin_protocol/execute_order")
+						.priority(T::UnsignedPriority::get())
+						.and_provides(order_id)
+						.longevity(64_u64)
+						.propagate(true)
+						.build();
+				}
+				InvalidTransaction::Stale.into()
+			}
 			Call::liquidity_pool_margin_call(pool_id) => {
 				if Self::is_pool_margin_called(pool_id) {
 					return InvalidTransaction::Stale.into();
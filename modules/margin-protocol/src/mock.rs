@@ -5,21 +5,27 @@
 use frame_support::{impl_outer_dispatch, impl_outer_event, impl_outer_origin, ord_parameter_types, parameter_types};
 use frame_system as system;
 use frame_system::EnsureSignedBy;
-use orml_traits::{DataProvider, DefaultPriceProvider};
-use primitives::{Balance, CurrencyId, LiquidityPoolId, TradingPair};
+use orml_traits::DefaultPriceProvider;
+use primitives::{Balance, CurrencyId, LiquidityPoolId, TraderTier, TradingPair};
 use sp_core::H256;
 use sp_runtime::{
-	testing::{Header, TestXt},
+	testing::{Header, TestSignature, TestXt, UintAuthorityId},
 	traits::IdentityLookup,
 	Perbill,
 };
 use sp_std::{cell::RefCell, collections::btree_map::BTreeMap};
-use traits::{LiquidityPools, OpenPositionError};
+use traits::{InsuranceFund, LiquidityPools, OpenPositionError, PoolLiabilities, PoolLiabilityAmounts};
+
+pub use laminar_test_support::{
+	MockBuilder, MockFeeDiscount, MockLiquidities, MockMarketCalendar, MockPriceStatus, MockPrices, EUR_JPY_PAIR,
+	EUR_USD_PAIR, JPY_USD_PAIR,
+};
 
 use super::*;
 
 ord_parameter_types! {
 	pub const UpdateOrigin: AccountId = 0;
+	pub const PauseOrigin: AccountId = 6;
 }
 
 impl_outer_origin! {
@@ -110,6 +116,7 @@ impl pallet_balances::Trait for Runtime {
 pub type NativeCurrency =
 	orml_currencies::BasicCurrencyAdapter<pallet_balances::Module<Runtime>, Balance, Balance, Amount, u64>;
 pub type LiquidityCurrency = orml_currencies::Currency<Runtime, GetLiquidityCurrencyId>;
+pub type Currencies = orml_currencies::Module<Runtime>;
 impl orml_currencies::Trait for Runtime {
 	type Event = TestEvent;
 	type MultiCurrency = orml_tokens::Module<Runtime>;
@@ -117,35 +124,11 @@ impl orml_currencies::Trait for Runtime {
 	type GetNativeCurrencyId = GetNativeCurrencyId;
 }
 
-thread_local! {
-	static PRICES: RefCell<BTreeMap<CurrencyId, Price>> = RefCell::new(BTreeMap::new());
-}
-
-pub struct MockPrices;
-impl MockPrices {
-	pub fn set_mock_price(currency_id: CurrencyId, price: Option<Price>) {
-		if let Some(p) = price {
-			PRICES.with(|v| v.borrow_mut().insert(currency_id, p));
-		} else {
-			PRICES.with(|v| v.borrow_mut().remove(&currency_id));
-		}
-	}
-
-	fn prices(currency_id: CurrencyId) -> Option<Price> {
-		PRICES.with(|v| v.borrow_mut().get(&currency_id).map(|p| *p))
-	}
-}
-
-impl DataProvider<CurrencyId, Price> for MockPrices {
-	fn get(key: &CurrencyId) -> Option<Price> {
-		Self::prices(*key)
-	}
-}
-
 thread_local! {
 	static SPREAD: RefCell<Permill> = RefCell::new(Permill::zero());
 	static ACC_SWAP_RATES: RefCell<BTreeMap<TradingPair, FixedI128>> = RefCell::new(BTreeMap::new());
-	static LIQUIDITIES: RefCell<BTreeMap<LiquidityPoolId, Balance>> = RefCell::new(BTreeMap::new());
+	static TRADER_MAX_LEVERAGE: RefCell<Option<u8>> = RefCell::new(None);
+	static COMMISSION: RefCell<Permill> = RefCell::new(Permill::zero());
 }
 
 pub const MOCK_LIQUIDITY_LOCK_ACCOUNT: u64 = 1000;
@@ -169,11 +152,27 @@ impl MockLiquidityPools {
 	}
 
 	pub fn liquidity(pool: LiquidityPoolId) -> Balance {
-		LIQUIDITIES.with(|v| v.borrow_mut().get(&pool).map(|l| *l)).unwrap()
+		MockLiquidities::liquidity(pool)
 	}
 
 	pub fn set_mock_liquidity(pool: LiquidityPoolId, liquidity: Balance) {
-		LIQUIDITIES.with(|v| v.borrow_mut().insert(pool, liquidity));
+		MockLiquidities::set_mock_liquidity(pool, liquidity);
+	}
+
+	pub fn trader_max_leverage() -> Option<u8> {
+		TRADER_MAX_LEVERAGE.with(|v| *v.borrow())
+	}
+
+	pub fn set_mock_trader_max_leverage(max_leverage: Option<u8>) {
+		TRADER_MAX_LEVERAGE.with(|v| *v.borrow_mut() = max_leverage);
+	}
+
+	pub fn commission() -> Permill {
+		COMMISSION.with(|v| *v.borrow())
+	}
+
+	pub fn set_mock_commission(commission: Permill) {
+		COMMISSION.with(|v| *v.borrow_mut() = commission);
 	}
 }
 impl LiquidityPools<AccountId> for MockLiquidityPools {
@@ -181,8 +180,8 @@ impl LiquidityPools<AccountId> for MockLiquidityPools {
 		unimplemented!()
 	}
 
-	fn is_owner(_pool_id: LiquidityPoolId, _who: &AccountId) -> bool {
-		unimplemented!()
+	fn is_owner(pool_id: LiquidityPoolId, who: &AccountId) -> bool {
+		pool_id == MOCK_POOL && *who == POOL_OWNER
 	}
 
 	fn pool_exists(pool_id: LiquidityPoolId) -> bool {
@@ -208,16 +207,12 @@ impl LiquidityPools<AccountId> for MockLiquidityPools {
 
 impl MarginProtocolLiquidityPools<AccountId> for MockLiquidityPools {
 	fn bid_spread(_pool_id: LiquidityPoolId, pair: TradingPair) -> Option<Balance> {
-		let base_price = MockPrices::prices(pair.base)?;
-		let quote_price = MockPrices::prices(pair.quote)?;
-		let price = base_price.checked_div(&quote_price).unwrap();
+		let price = MockPrices::pair_price(pair)?;
 		Some(Self::spread().mul_ceil(price.into_inner()))
 	}
 
 	fn ask_spread(_pool_id: LiquidityPoolId, pair: TradingPair) -> Option<Balance> {
-		let base_price = MockPrices::prices(pair.base)?;
-		let quote_price = MockPrices::prices(pair.quote)?;
-		let price = base_price.checked_div(&quote_price).unwrap();
+		let price = MockPrices::pair_price(pair)?;
 		Some(Self::spread().mul_ceil(price.into_inner()))
 	}
 
@@ -237,6 +232,83 @@ impl MarginProtocolLiquidityPools<AccountId> for MockLiquidityPools {
 	) -> result::Result<(), OpenPositionError> {
 		Ok(())
 	}
+
+	fn trader_max_leverage(_pool_id: LiquidityPoolId, _tier: TraderTier) -> Option<u8> {
+		Self::trader_max_leverage()
+	}
+
+	fn commission(_pool_id: LiquidityPoolId, _pair: TradingPair) -> Permill {
+		Self::commission()
+	}
+}
+
+thread_local! {
+	static POOL_LIABILITIES: RefCell<Option<PoolLiabilityAmounts>> = RefCell::new(None);
+}
+
+/// Mock liabilities outside of margin positions, the same for all pools.
+pub struct MockPoolLiabilities;
+impl MockPoolLiabilities {
+	pub fn set_mock_liabilities(liabilities: Option<PoolLiabilityAmounts>) {
+		POOL_LIABILITIES.with(|v| *v.borrow_mut() = liabilities);
+	}
+}
+
+impl PoolLiabilities for MockPoolLiabilities {
+	fn liabilities(_pool_id: LiquidityPoolId) -> result::Result<PoolLiabilityAmounts, DispatchError> {
+		Ok(POOL_LIABILITIES.with(|v| *v.borrow()).unwrap_or_default())
+	}
+
+	fn has_liabilities(_pool_id: LiquidityPoolId) -> bool {
+		POOL_LIABILITIES.with(|v| v.borrow().is_some())
+	}
+
+	fn liabilities_weight() -> Weight {
+		0
+	}
+}
+
+thread_local! {
+	static INSURANCE_FUND_BUDGET: RefCell<Balance> = RefCell::new(0);
+}
+
+/// Mock insurance fund, covering shortfalls from the insurance fund account up to the mock budget.
+pub struct MockInsuranceFund;
+impl MockInsuranceFund {
+	pub fn set_mock_budget(budget: Balance) {
+		INSURANCE_FUND_BUDGET.with(|v| *v.borrow_mut() = budget);
+	}
+}
+
+impl InsuranceFund<AccountId> for MockInsuranceFund {
+	fn cover_shortfall(
+		dest: &AccountId,
+		_pool_id: LiquidityPoolId,
+		amount: Balance,
+	) -> result::Result<Balance, DispatchError> {
+		let covered = cmp::min(amount, INSURANCE_FUND_BUDGET.with(|v| *v.borrow()));
+		LiquidityCurrency::transfer(&INSURANCE_FUND, dest, covered)?;
+		INSURANCE_FUND_BUDGET.with(|v| *v.borrow_mut() -= covered);
+		Ok(covered)
+	}
+}
+
+thread_local! {
+	static TRADER_TIERS: RefCell<BTreeMap<AccountId, TraderTier>> = RefCell::new(BTreeMap::new());
+}
+
+/// Mock trader tiers. Traders not set are retail.
+pub struct MockTraderTiers;
+impl MockTraderTiers {
+	pub fn set_mock_tier(who: AccountId, tier: TraderTier) {
+		TRADER_TIERS.with(|v| v.borrow_mut().insert(who, tier));
+	}
+}
+
+impl TraderTiers<AccountId> for MockTraderTiers {
+	fn trader_tier(who: &AccountId) -> TraderTier {
+		TRADER_TIERS.with(|v| v.borrow().get(who).copied().unwrap_or_default())
+	}
 }
 
 pub type Extrinsic = TestXt<Call, ()>;
@@ -252,43 +324,63 @@ parameter_types! {
 	pub const GetTraderMaxOpenPositions: usize = 200;
 	pub const GetPoolMaxOpenPositions: usize = 1000;
 	pub const GetTreasuryAccountId: AccountId = TREASURY_ACCOUNT;
+	pub const GetInsuranceFundAccountId: AccountId = INSURANCE_FUND;
+	pub const GetStopOutDisputeWindow: u64 = 5;
+	pub const GetSlippageInsurancePremiumPeriod: u64 = 10;
 	pub const UnsignedPriority: u64 = 1 << 20;
+	pub const GetEnpEllSnapshotInterval: u64 = 10;
+	pub const GetMaxEnpEllSnapshots: u32 = 2;
+	pub const GetSwapSettlementInterval: u64 = 100;
+	pub const GetMaxCopyFollowers: u32 = 2;
+	pub const GetStopOutChunkSize: u32 = 2;
+	pub const GetMaxTradeHistory: u32 = 2;
+	pub const GetTraderMaxPendingOrders: u32 = 2;
+	pub const GetVolumePeriod: u64 = 10;
 }
 
 impl Trait for Runtime {
 	type Event = TestEvent;
 	type LiquidityCurrency = LiquidityCurrency;
+	type MultiCurrency = Currencies;
 	type LiquidityPools = MockLiquidityPools;
 	type PriceProvider = DefaultPriceProvider<CurrencyId, MockPrices>;
+	type PriceStatus = MockPriceStatus;
+	type MarketCalendar = MockMarketCalendar;
 	type GetTreasuryAccountId = GetTreasuryAccountId;
+	type GetInsuranceFundAccountId = GetInsuranceFundAccountId;
+	type GetStopOutDisputeWindow = GetStopOutDisputeWindow;
+	type GetSlippageInsurancePremiumPeriod = GetSlippageInsurancePremiumPeriod;
 	type GetTraderMaxOpenPositions = GetTraderMaxOpenPositions;
 	type GetPoolMaxOpenPositions = GetPoolMaxOpenPositions;
 	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
+	type PauseOrigin = EnsureSignedBy<PauseOrigin, AccountId>;
+	type AuditLog = ();
+	type FeeDiscount = MockFeeDiscount;
+	type TraderTiers = MockTraderTiers;
 	type UnsignedPriority = UnsignedPriority;
+	type GetEnpEllSnapshotInterval = GetEnpEllSnapshotInterval;
+	type GetMaxEnpEllSnapshots = GetMaxEnpEllSnapshots;
+	type GetSwapSettlementInterval = GetSwapSettlementInterval;
+	type GetMaxCopyFollowers = GetMaxCopyFollowers;
+	type GetStopOutChunkSize = GetStopOutChunkSize;
+	type GetMaxTradeHistory = GetMaxTradeHistory;
+	type GetTraderMaxPendingOrders = GetTraderMaxPendingOrders;
+	type GetVolumePeriod = GetVolumePeriod;
+	type PoolLiabilities = MockPoolLiabilities;
+	type InsuranceFund = MockInsuranceFund;
+	type QuoteSignature = TestSignature;
+	type QuoteSigner = UintAuthorityId;
 }
 pub type MarginProtocol = Module<Runtime>;
 
 pub const ALICE: AccountId = 0;
 pub const BOB: AccountId = 1;
 pub const TREASURY_ACCOUNT: AccountId = 3;
+pub const POOL_OWNER: AccountId = 4;
+pub const INSURANCE_FUND: AccountId = 5;
 pub const MOCK_POOL: LiquidityPoolId = 100;
 pub const MOCK_POOL_1: LiquidityPoolId = 101;
 
-pub const EUR_USD_PAIR: TradingPair = TradingPair {
-	base: CurrencyId::FEUR,
-	quote: CurrencyId::AUSD,
-};
-
-pub const JPY_USD_PAIR: TradingPair = TradingPair {
-	base: CurrencyId::FJPY,
-	quote: CurrencyId::AUSD,
-};
-
-pub const EUR_JPY_PAIR: TradingPair = TradingPair {
-	base: CurrencyId::FEUR,
-	quote: CurrencyId::FJPY,
-};
-
 /// Print status of a trader, only for unit tests debugging purpose.
 pub fn print_trader_summary(who: &AccountId, pool_id: LiquidityPoolId, name: Option<&'static str>) {
 	println!("------------------------------");
@@ -323,9 +415,8 @@ pub fn print_bob_summary() {
 pub struct ExtBuilder {
 	endowed_accounts: Vec<(AccountId, CurrencyId, Balance)>,
 	spread: Permill,
-	prices: Vec<(CurrencyId, Price)>,
 	swap_rates: Vec<(TradingPair, FixedI128)>,
-	pool_liquidities: Vec<(LiquidityPoolId, Balance)>,
+	mocks: MockBuilder,
 }
 
 impl Default for ExtBuilder {
@@ -334,9 +425,8 @@ impl Default for ExtBuilder {
 		Self {
 			endowed_accounts: vec![],
 			spread: Permill::from_rational_approximation(1, 1000u32),
-			prices: vec![(CurrencyId::AUSD, FixedU128::saturating_from_rational(1, 1))],
 			swap_rates: vec![],
-			pool_liquidities: vec![],
+			mocks: MockBuilder::default(),
 		}
 	}
 }
@@ -347,6 +437,11 @@ impl ExtBuilder {
 		self
 	}
 
+	pub fn balance(mut self, who: AccountId, currency_id: CurrencyId, balance: Balance) -> Self {
+		self.endowed_accounts.push((who, currency_id, balance));
+		self
+	}
+
 	pub fn module_balance(mut self, balance: FixedI128) -> Self {
 		self.endowed_accounts.push((
 			MarginProtocol::account_id(),
@@ -363,8 +458,8 @@ impl ExtBuilder {
 
 	/// `price`: rational(x, y)
 	pub fn price(mut self, currency_id: CurrencyId, price: (u128, u128)) -> Self {
-		self.prices
-			.push((currency_id, FixedU128::saturating_from_rational(price.0, price.1)));
+		self.mocks
+			.price(currency_id, FixedU128::saturating_from_rational(price.0, price.1));
 		self
 	}
 
@@ -374,23 +469,19 @@ impl ExtBuilder {
 	}
 
 	pub fn pool_liquidity(mut self, pool: LiquidityPoolId, liquidity: Balance) -> Self {
-		self.pool_liquidities.push((pool, liquidity));
+		self.mocks.pool_liquidity(pool, liquidity);
 		self.endowed_accounts
 			.push((MOCK_LIQUIDITY_LOCK_ACCOUNT, CurrencyId::AUSD, liquidity));
 		self
 	}
 
 	fn set_mocks(&self) {
-		self.prices
-			.iter()
-			.for_each(|(c, p)| MockPrices::set_mock_price(*c, Some(*p)));
+		self.mocks.set_mocks();
 		MockLiquidityPools::set_mock_spread(self.spread);
+		MockPoolLiabilities::set_mock_liabilities(None);
 		self.swap_rates
 			.iter()
 			.for_each(|(p, r)| MockLiquidityPools::set_mock_accumulated_swap_rate(*p, *r));
-		self.pool_liquidities
-			.iter()
-			.for_each(|(p, l)| MockLiquidityPools::set_mock_liquidity(*p, *l));
 	}
 
 	pub fn build(self) -> sp_io::TestExternalities {
@@ -421,6 +512,8 @@ impl ExtBuilder {
 					RiskThreshold::default(),
 				),
 			],
+			force_close_penalty_tiers: vec![],
+			risk_candidate_buffer: None,
 		}
 		.assimilate_storage(&mut t)
 		.unwrap();
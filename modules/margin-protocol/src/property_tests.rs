@@ -0,0 +1,184 @@
+//! Property-based tests of the margin protocol math, on randomly generated prices, spreads and positions.
+
+#![cfg(test)]
+
+use super::*;
+use mock::*;
+
+use laminar_test_support::open_position_amounts_at_mock_prices;
+use primitives::Leverage;
+use proptest::prelude::*;
+
+const ONE: u128 = 1_000_000_000_000_000_000;
+const TRADER_BALANCE: i128 = 1_000_000_000_000;
+const POOL_LIQUIDITY: Balance = 1_000_000_000_000 * ONE;
+
+/// Run `f` with EUR/USD price `price_cent / 100` and `spread` in parts per million.
+fn with_market<R>(price_cent: u128, spread: u32, f: impl FnOnce() -> R) -> R {
+	ExtBuilder::default()
+		.spread(Permill::from_parts(spread))
+		.price(CurrencyId::FEUR, (price_cent, 100))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, POOL_LIQUIDITY)
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, FixedI128::saturating_from_integer(TRADER_BALANCE));
+			f()
+		})
+}
+
+fn set_price(price_cent: u128) {
+	MockPrices::set_mock_price(
+		CurrencyId::FEUR,
+		Some(FixedU128::saturating_from_rational(price_cent, 100)),
+	);
+}
+
+/// A position of `amount` EUR opened at the current price.
+fn new_position(leverage: Leverage, amount: u128) -> Position<Runtime> {
+	let price = if leverage.is_long() {
+		Price::max_value()
+	} else {
+		Price::zero()
+	};
+	MarginProtocol::new_position(&ALICE, MOCK_POOL, EUR_USD_PAIR, leverage, amount * ONE, price)
+		.expect("market is safe")
+		.0
+}
+
+/// Spread of EUR/USD at `price_cent / 100`, as the mock liquidity pools charge.
+fn spread_at(price_cent: u128, spread: u32) -> FixedI128 {
+	let price = FixedU128::saturating_from_rational(price_cent, 100);
+	FixedI128::from_inner(Permill::from_parts(spread).mul_ceil(price.into_inner()) as i128)
+}
+
+fn leverage_strategy() -> impl Strategy<Value = (Leverage, Leverage)> {
+	prop_oneof![
+		Just((Leverage::LongTwo, Leverage::ShortTwo)),
+		Just((Leverage::LongTen, Leverage::ShortTen)),
+		Just((Leverage::LongTwenty, Leverage::ShortTwenty)),
+	]
+}
+
+proptest! {
+	#![proptest_config(ProptestConfig::with_cases(64))]
+
+	#[test]
+	fn round_trip_is_never_profitable(
+		price_cent in 1u128..1_000_000,
+		spread in 0u32..50_000,
+		amount in 1u128..1_000_000,
+		(long, short) in leverage_strategy(),
+	) {
+		with_market(price_cent, spread, || {
+			for leverage in &[long, short] {
+				let position = new_position(*leverage, amount);
+				let unrealized_pl = MarginProtocol::unrealized_pl_of_position(&position).unwrap();
+				prop_assert!(unrealized_pl <= FixedI128::zero());
+			}
+			Ok(())
+		})?;
+	}
+
+	#[test]
+	fn new_position_amounts_match_realistic_positions(
+		price_cent in 1u128..1_000_000,
+		spread in 0u32..50_000,
+		amount in 1u128..1_000_000,
+		(long, short) in leverage_strategy(),
+	) {
+		with_market(price_cent, spread, || {
+			for leverage in &[long, short] {
+				let position = new_position(*leverage, amount);
+				let expected = open_position_amounts_at_mock_prices(
+					EUR_USD_PAIR,
+					*leverage,
+					amount * ONE,
+					Permill::from_parts(spread),
+				);
+				prop_assert_eq!(position.leveraged_held, expected.leveraged_held);
+				prop_assert_eq!(position.leveraged_debits, expected.leveraged_debits);
+				prop_assert_eq!(position.margin_held, expected.margin_held);
+			}
+			Ok(())
+		})?;
+	}
+
+	#[test]
+	fn mirrored_positions_lose_spread_cost(
+		open_price_cent in 1u128..1_000_000,
+		close_price_cent in 1u128..1_000_000,
+		spread in 0u32..50_000,
+		amount in 1u128..1_000_000,
+		(long, short) in leverage_strategy(),
+	) {
+		with_market(open_price_cent, spread, || {
+			let long_position = new_position(long, amount);
+			let short_position = new_position(short, amount);
+
+			set_price(close_price_cent);
+			let unrealized_pl = MarginProtocol::unrealized_pl_of_position(&long_position)
+				.unwrap()
+				.checked_add(&MarginProtocol::unrealized_pl_of_position(&short_position).unwrap())
+				.unwrap();
+
+			// Both sides pay the spread on open and on close.
+			let spread_cost = spread_at(open_price_cent, spread)
+				.checked_add(&spread_at(close_price_cent, spread))
+				.and_then(|s| s.checked_mul(&FixedI128::saturating_from_integer(2 * amount as i128)))
+				.unwrap();
+			let tolerance = FixedI128::from_inner(10 * (amount as i128 + 1));
+
+			prop_assert!(unrealized_pl <= FixedI128::zero());
+			let diff = unrealized_pl.checked_add(&spread_cost).unwrap();
+			prop_assert!(diff.saturating_abs() <= tolerance, "diff {:?} over tolerance {:?}", diff, tolerance);
+			Ok(())
+		})?;
+	}
+
+	#[test]
+	fn position_pl_is_bounded_by_debits(
+		open_price_cent in 1u128..1_000_000,
+		close_price_cent in 1u128..1_000_000,
+		spread in 0u32..50_000,
+		amount in 1u128..1_000_000,
+		(long, short) in leverage_strategy(),
+	) {
+		with_market(open_price_cent, spread, || {
+			let long_position = new_position(long, amount);
+			let short_position = new_position(short, amount);
+
+			set_price(close_price_cent);
+			// A long position loses at most its debits, and a short position gains at most its debits.
+			let long_pl = MarginProtocol::unrealized_pl_of_position(&long_position).unwrap();
+			prop_assert!(long_pl >= long_position.leveraged_debits);
+			let short_pl = MarginProtocol::unrealized_pl_of_position(&short_position).unwrap();
+			prop_assert!(short_pl <= short_position.leveraged_debits);
+			Ok(())
+		})?;
+	}
+
+	#[test]
+	fn equity_never_exceeds_balance_and_max_profit(
+		open_price_cent in 1u128..1_000_000,
+		close_price_cent in 1u128..1_000_000,
+		spread in 0u32..50_000,
+		amounts in prop::collection::vec(1u128..1_000_000, 1..5),
+		(_, short) in leverage_strategy(),
+	) {
+		with_market(open_price_cent, spread, || {
+			let mut max_profit = FixedI128::zero();
+			for amount in amounts {
+				let position = new_position(short, amount);
+				max_profit = max_profit.checked_add(&position.leveraged_debits).unwrap();
+				MarginProtocol::insert_position(&ALICE, MOCK_POOL, EUR_USD_PAIR, position).unwrap();
+			}
+
+			set_price(close_price_cent);
+			let equity = MarginProtocol::equity_of_trader(&ALICE, MOCK_POOL).unwrap();
+			let balance = MarginProtocol::balances(ALICE, MOCK_POOL);
+			prop_assert!(equity <= balance.checked_add(&max_profit).unwrap());
+			Ok(())
+		})?;
+	}
+}
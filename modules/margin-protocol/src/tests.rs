@@ -5,27 +5,31 @@
 use super::*;
 use mock::*;
 
-use frame_support::{assert_noop, assert_ok};
+use frame_support::{
+	assert_noop, assert_ok,
+	traits::{OnInitialize, OnRuntimeUpgrade},
+	weights::GetDispatchInfo,
+};
 use primitives::Leverage;
-use sp_core::offchain::{
-	testing::{TestOffchainExt, TestTransactionPoolExt},
-	OffchainExt, TransactionPoolExt,
+use sp_core::{
+	offchain::{
+		testing::{PendingRequest, TestOffchainExt, TestTransactionPoolExt},
+		OffchainExt, TransactionPoolExt,
+	},
+	testing::KeyStore,
+	traits::KeystoreExt,
 };
+use sp_runtime::{testing::TestSignature, traits::BadOrigin};
 
-// `n` is a natural currency amount by cent, with 2 fractional digits precision
-fn fixedi128_saturating_from_integer_currency_cent(n: i128) -> FixedI128 {
-	FixedI128::from_inner(n * 1_000_000_000_000_000_0)
-}
-
-// `b` is a natural currency amount by cent, with 2 fractional digits precision
-fn balance_saturating_from_integer_currency_cent(b: u128) -> Balance {
-	b * 1_000_000_000_000_000_0
-}
+use laminar_test_support::{
+	balance_saturating_from_integer_currency_cent, fixedi128_saturating_from_integer_currency_cent,
+};
 
 fn risk_threshold(margin_call_percent: u32, stop_out_percent: u32) -> RiskThreshold {
 	RiskThreshold {
 		margin_call: Permill::from_percent(margin_call_percent),
 		stop_out: Permill::from_percent(stop_out_percent),
+		warning: Permill::zero(),
 	}
 }
 
@@ -116,7 +120,7 @@ fn unrealized_pl_of_long_position_works() {
 		.execute_with(|| {
 			assert_eq!(
 				MarginProtocol::unrealized_pl_of_position(&eur_jpy_long()),
-				Ok(FixedI128::from_inner(-1073545454545441750827)),
+				Ok(FixedI128::from_inner(-1073545454545441750828)),
 			);
 		});
 }
@@ -132,7 +136,7 @@ fn unrealized_pl_of_short_position_works() {
 		.execute_with(|| {
 			assert_eq!(
 				MarginProtocol::unrealized_pl_of_position(&eur_jpy_short()),
-				Ok(FixedI128::from_inner(1470999999999987140173)),
+				Ok(FixedI128::from_inner(1470999999999987140172)),
 			);
 		});
 }
@@ -152,98 +156,87 @@ fn unrealized_pl_of_trader_sums_all_positions() {
 			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 1), ());
 			assert_eq!(
 				MarginProtocol::unrealized_pl_of_trader(&ALICE, MOCK_POOL),
-				Ok(FixedI128::from_inner(397454545454545389346))
+				Ok(FixedI128::from_inner(397454545454545389344))
 			);
 		});
 }
 
+#[test]
+fn unrealized_pl_never_rounds_in_trader_favor() {
+	// EUR/USD prices with recurring decimals
+	let prices = [(1409, 1070), (1419, 1060), (10, 3), (7, 11), (13_331, 9_997)];
+	let amounts = [1, 3, 7, 1_000_01, 3_333_33];
+	for &price in prices.iter() {
+		ExtBuilder::default()
+			.spread(Permill::zero())
+			// USD/JPY = 107
+			.price(CurrencyId::FJPY, (1, 107))
+			.price(CurrencyId::FEUR, price)
+			.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+			.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+			.build()
+			.execute_with(|| {
+				<Balances<Runtime>>::insert(
+					ALICE,
+					MOCK_POOL,
+					fixedi128_saturating_from_integer_currency_cent(100_000_00),
+				);
+				for &amount in amounts.iter() {
+					for &(leverage, price_bound) in [
+						(Leverage::LongTwenty, Price::max_value()),
+						(Leverage::ShortTwenty, Price::zero()),
+					]
+					.iter()
+					{
+						let id = MarginProtocol::next_position_id();
+						assert_ok!(MarginProtocol::open_position(
+							Origin::signed(ALICE),
+							MOCK_POOL,
+							EUR_JPY_PAIR,
+							leverage,
+							balance_saturating_from_integer_currency_cent(amount),
+							price_bound,
+							None,
+						));
+
+						// without spread or price change, closing right after opening never makes profit
+						let position = MarginProtocol::positions(id).unwrap();
+						assert!(MarginProtocol::unrealized_pl_of_position(&position).unwrap() <= FixedI128::zero());
+					}
+				}
+			});
+	}
+}
+
 #[test]
 fn margin_held_sums_all_margin_held() {
 	ExtBuilder::default().build().execute_with(|| {
-		<Positions<Runtime>>::insert(0, eur_jpy_long());
-		<Positions<Runtime>>::insert(1, eur_jpy_short());
-		<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
-		<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 1), ());
+		assert_ok!(MarginProtocol::insert_position(
+			&ALICE,
+			MOCK_POOL,
+			EUR_JPY_PAIR,
+			eur_jpy_long()
+		));
+		assert_ok!(MarginProtocol::insert_position(
+			&ALICE,
+			MOCK_POOL,
+			EUR_JPY_PAIR,
+			eur_jpy_short()
+		));
 		assert_eq!(
 			MarginProtocol::margin_held(&ALICE, MOCK_POOL),
 			fixedi128_saturating_from_integer_currency_cent(13_278_00)
 		);
-	});
-}
-
-fn eur_usd_long_1() -> Position<Runtime> {
-	let open_rate =
-		FixedI128::saturating_from_integer(1).saturating_add(FixedI128::saturating_from_rational(36_87, 100_000_00));
-	Position {
-		owner: ALICE,
-		pool: MOCK_POOL,
-		pair: EUR_USD_PAIR,
-		leverage: Leverage::LongFive,
-		leveraged_held: FixedI128::saturating_from_integer(100_000),
-		leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-120_420_30),
-		open_accumulated_swap_rate: open_rate,
-		margin_held: fixedi128_saturating_from_integer_currency_cent(24_084_00),
-	}
-}
-
-fn eur_usd_long_2() -> Position<Runtime> {
-	let open_rate =
-		FixedI128::saturating_from_integer(1).saturating_add(FixedI128::saturating_from_rational(18_43, 100_000_00));
-	Position {
-		owner: ALICE,
-		pool: MOCK_POOL,
-		pair: EUR_USD_PAIR,
-		leverage: Leverage::LongTwenty,
-		leveraged_held: FixedI128::saturating_from_integer(100_000),
-		leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-119_419_30),
-		open_accumulated_swap_rate: open_rate,
-		margin_held: fixedi128_saturating_from_integer_currency_cent(5_971_00),
-	}
-}
-
-fn eur_usd_short_1() -> Position<Runtime> {
-	let open_rate =
-		FixedI128::saturating_from_integer(1).saturating_add(FixedI128::saturating_from_rational(10_96, 100_000_00));
-	Position {
-		owner: ALICE,
-		pool: MOCK_POOL,
-		pair: EUR_USD_PAIR,
-		leverage: Leverage::ShortTen,
-		leveraged_held: FixedI128::saturating_from_integer(-100_000),
-		leveraged_debits: fixedi128_saturating_from_integer_currency_cent(119_780_10),
-		open_accumulated_swap_rate: open_rate,
-		margin_held: fixedi128_saturating_from_integer_currency_cent(11_978_00),
-	}
-}
 
-fn eur_usd_short_2() -> Position<Runtime> {
-	let open_rate =
-		FixedI128::saturating_from_integer(1).saturating_add(FixedI128::saturating_from_rational(3_65, 100_000_00));
-	Position {
-		owner: ALICE,
-		pool: MOCK_POOL,
-		pair: EUR_USD_PAIR,
-		leverage: Leverage::ShortFifty,
-		leveraged_held: FixedI128::saturating_from_integer(-200_000),
-		leveraged_debits: fixedi128_saturating_from_integer_currency_cent(237_362_40),
-		open_accumulated_swap_rate: open_rate,
-		margin_held: fixedi128_saturating_from_integer_currency_cent(4_747_00),
-	}
-}
+		assert_ok!(MarginProtocol::remove_position(&ALICE, 0, &eur_jpy_long()));
+		assert_eq!(
+			MarginProtocol::margin_held(&ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(6_687_00)
+		);
 
-fn jpy_usd_long_1() -> Position<Runtime> {
-	let open_rate =
-		FixedI128::saturating_from_integer(1).saturating_add(FixedI128::saturating_from_rational(36_87, 100_000_00));
-	Position {
-		owner: ALICE,
-		pool: MOCK_POOL,
-		pair: JPY_USD_PAIR,
-		leverage: Leverage::LongFive,
-		leveraged_held: FixedI128::saturating_from_integer(100_000),
-		leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-120_420_30),
-		open_accumulated_swap_rate: open_rate,
-		margin_held: fixedi128_saturating_from_integer_currency_cent(24_084_00),
-	}
+		assert_ok!(MarginProtocol::remove_position(&ALICE, 1, &eur_jpy_short()));
+		assert!(!<MarginHeld<Runtime>>::contains_key(ALICE, MOCK_POOL));
+	});
 }
 
 #[test]
@@ -274,6 +267,31 @@ fn accumulated_swap_rate_of_short_position_works() {
 		});
 }
 
+#[test]
+fn accumulated_swap_rate_accrues_lazily_from_index() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (1, 1))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.build()
+		.execute_with(|| {
+			<Positions<Runtime>>::insert(0, eur_usd_long_1());
+
+			// accumulating twice only moves the index, not the position
+			for &rate in [-1, -2].iter() {
+				let index = MockLiquidityPools::accumulated_swap_rate(EUR_USD_PAIR);
+				let rate = FixedI128::saturating_from_rational(rate, 1000);
+				MockLiquidityPools::set_mock_accumulated_swap_rate(EUR_USD_PAIR, index.saturating_add(rate));
+			}
+			assert_eq!(MarginProtocol::positions(0), Some(eur_usd_long_1()));
+
+			// (1 - 0.003 - 1.0003687) * 120_420.3
+			assert_eq!(
+				MarginProtocol::accumulated_swap_rate_of_position(&eur_usd_long_1()),
+				Ok(FixedI128::from_inner(-405659864610000000000))
+			);
+		});
+}
+
 #[test]
 fn accumulated_swap_rate_of_trader_sums_all_positions() {
 	ExtBuilder::default()
@@ -319,6 +337,47 @@ fn equity_of_trader_works() {
 		});
 }
 
+#[test]
+fn equity_of_trader_reads_each_price_once() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(120_000_00),
+			);
+			<Positions<Runtime>>::insert(0, eur_usd_long_1());
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+
+			MockPrices::reset_reads();
+			assert!(MarginProtocol::equity_of_trader(&ALICE, MOCK_POOL).is_ok());
+			let one_position_reads = MockPrices::reads();
+			assert!(one_position_reads > 0);
+
+			<Positions<Runtime>>::insert(1, eur_usd_long_2());
+			<Positions<Runtime>>::insert(2, eur_usd_short_1());
+			<Positions<Runtime>>::insert(3, eur_usd_short_2());
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 1), ());
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 2), ());
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 3), ());
+
+			// prices of the same pair are read once, no matter how many positions
+			MockPrices::reset_reads();
+			assert!(MarginProtocol::equity_of_trader(&ALICE, MOCK_POOL).is_ok());
+			assert_eq!(MockPrices::reads(), one_position_reads);
+
+			// no cache across calls
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(1, 1)));
+			assert_ne!(
+				MarginProtocol::equity_of_trader(&ALICE, MOCK_POOL),
+				Ok(FixedI128::from_inner(116614700431840000000000))
+			);
+		});
+}
+
 #[test]
 fn margin_level_works() {
 	ExtBuilder::default()
@@ -434,7 +493,7 @@ fn equity_of_pool_works() {
 			);
 			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot);
 			assert_eq!(
-				MarginProtocol::equity_of_pool(MOCK_POOL),
+				MarginProtocol::pool_risk_amounts(MOCK_POOL, None).map(|a| a.equity),
 				Ok(FixedI128::from_inner(103297_100000000000000000))
 			);
 		});
@@ -506,6 +565,181 @@ fn enp_and_ell_with_new_position_works() {
 		});
 }
 
+fn liabilities(unrealized_loss_cent: i128, net_position_cent: i128, longest_leg_cent: i128) -> PoolLiabilityAmounts {
+	PoolLiabilityAmounts {
+		unrealized_loss: fixedi128_saturating_from_integer_currency_cent(unrealized_loss_cent),
+		net_position: fixedi128_saturating_from_integer_currency_cent(net_position_cent),
+		longest_leg: fixedi128_saturating_from_integer_currency_cent(longest_leg_cent),
+	}
+}
+
+#[test]
+fn enp_and_ell_include_pool_liabilities() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			MockPoolLiabilities::set_mock_liabilities(Some(liabilities(20_000_00, 160_000_00, 80_000_00)));
+
+			// enp = (100_000 - 20_000) / 160_000, ell = (100_000 - 20_000) / 80_000
+			assert_eq!(
+				MarginProtocol::enp_and_ell_with_action(MOCK_POOL, Action::None),
+				Ok((
+					FixedI128::saturating_from_rational(1, 2),
+					FixedI128::saturating_from_integer(1)
+				))
+			);
+
+			// enp = (100_000 - 40_000) / 320_000, ell = (100_000 - 40_000) / 160_000
+			assert_eq!(
+				MarginProtocol::enp_and_ell_with_action(
+					MOCK_POOL,
+					Action::AddLiabilities(EUR_USD_PAIR, liabilities(20_000_00, 160_000_00, 80_000_00))
+				),
+				Ok((
+					FixedI128::saturating_from_rational(3, 16),
+					FixedI128::saturating_from_rational(3, 8)
+				))
+			);
+		});
+}
+
+#[test]
+fn ensure_pool_safe_with_liabilities_applies_pair_threshold() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(40, 10));
+			MockPoolLiabilities::set_mock_liabilities(Some(liabilities(20_000_00, 160_000_00, 80_000_00)));
+
+			// enp = 80_000 / 200_000
+			assert_noop!(
+				MarginProtocol::ensure_pool_safe_with(MOCK_POOL, EUR_USD_PAIR, liabilities(0, 40_000_00, 40_000_00)),
+				Error::<Runtime>::PoolWouldBeUnsafe
+			);
+			// enp = 80_000 / 180_000
+			assert_ok!(MarginProtocol::ensure_pool_safe_with(
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				liabilities(0, 20_000_00, 20_000_00)
+			));
+			// no threshold set for EUR/JPY
+			assert_ok!(MarginProtocol::ensure_pool_safe_with(
+				MOCK_POOL,
+				EUR_JPY_PAIR,
+				liabilities(0, 40_000_00, 40_000_00)
+			));
+		});
+}
+
+#[test]
+fn enp_and_ell_reads_each_price_once() {
+	ExtBuilder::default()
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.price(CurrencyId::FEUR, (12, 10))
+		.build()
+		.execute_with(|| {
+			let snapshot = positions_snapshot(
+				2,
+				eur_usd_long_1().leveraged_held,
+				eur_usd_long_1().leveraged_debits,
+				eur_usd_short_1().leveraged_held,
+				eur_usd_short_1().leveraged_debits,
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot);
+
+			MockPrices::reset_reads();
+			assert!(MarginProtocol::enp_and_ell_with_action(MOCK_POOL, Action::None).is_ok());
+			let reads = MockPrices::reads();
+			assert!(reads > 0);
+
+			// equity, net position and longest leg share prices, and so does a new position of the same pair
+			MockPrices::reset_reads();
+			assert!(
+				MarginProtocol::enp_and_ell_with_action(MOCK_POOL, Action::OpenPosition(eur_usd_long_2())).is_ok()
+			);
+			assert_eq!(MockPrices::reads(), reads);
+
+			MockPrices::reset_reads();
+			assert!(MarginProtocol::pool_required_deposit(MOCK_POOL).is_some());
+			assert_eq!(MockPrices::reads(), reads);
+		});
+}
+
+#[test]
+fn enp_ell_snapshots_works() {
+	ExtBuilder::default()
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.price(CurrencyId::FEUR, (12, 10))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.build()
+		.execute_with(|| {
+			<MarginProtocol as OnInitialize<u64>>::on_initialize(10);
+			assert!(MarginProtocol::enp_ell_snapshots(MOCK_POOL).is_empty());
+
+			<Positions<Runtime>>::insert(0, eur_usd_long_1());
+			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+			let snapshot = positions_snapshot(
+				1,
+				eur_usd_long_1().leveraged_held,
+				eur_usd_long_1().leveraged_debits,
+				FixedI128::zero(),
+				FixedI128::zero(),
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot);
+			let (enp, ell) = MarginProtocol::enp_and_ell(MOCK_POOL).unwrap();
+			let snapshot_at = |block_number| EnpEllSnapshot { block_number, enp, ell };
+
+			// not at snapshot interval
+			<MarginProtocol as OnInitialize<u64>>::on_initialize(15);
+			assert!(MarginProtocol::enp_ell_snapshots(MOCK_POOL).is_empty());
+
+			<MarginProtocol as OnInitialize<u64>>::on_initialize(20);
+			assert_eq!(MarginProtocol::enp_ell_snapshots(MOCK_POOL), vec![snapshot_at(20)]);
+
+			<MarginProtocol as OnInitialize<u64>>::on_initialize(30);
+			<MarginProtocol as OnInitialize<u64>>::on_initialize(40);
+			// the oldest one dropped
+			assert_eq!(MarginProtocol::enp_ell_snapshots(MOCK_POOL), vec![snapshot_at(30), snapshot_at(40)]);
+			assert!(MarginProtocol::enp_ell_snapshots(MOCK_POOL_1).is_empty());
+		});
+}
+
+#[test]
+fn enp_ell_snapshots_weight_scales_with_pools_and_pairs() {
+	ExtBuilder::default()
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.price(CurrencyId::FEUR, (12, 10))
+		.price(CurrencyId::FJPY, (1, 107))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.build()
+		.execute_with(|| {
+			assert_eq!(<MarginProtocol as OnInitialize<u64>>::on_initialize(10), 0);
+
+			let snapshot = positions_snapshot(
+				1,
+				eur_usd_long_1().leveraged_held,
+				eur_usd_long_1().leveraged_debits,
+				FixedI128::zero(),
+				FixedI128::zero(),
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot.clone());
+			PositionsSnapshots::insert(MOCK_POOL, EUR_JPY_PAIR, snapshot);
+
+			// not at snapshot interval
+			assert_eq!(<MarginProtocol as OnInitialize<u64>>::on_initialize(15), 0);
+
+			assert_eq!(
+				<MarginProtocol as OnInitialize<u64>>::on_initialize(20),
+				ENP_ELL_SNAPSHOT_WEIGHT_PER_POOL + 2 * ENP_ELL_SNAPSHOT_WEIGHT_PER_PAIR
+			);
+		});
+}
+
 #[test]
 fn enp_and_ell_without_position_with_liquidity_works() {
 	ExtBuilder::default()
@@ -695,15 +929,19 @@ fn ensure_pool_safe_works() {
 }
 
 #[test]
-fn trader_margin_call_should_work() {
+fn pool_equity_and_risk_thresholds_works() {
 	ExtBuilder::default()
 		.spread(Permill::zero())
 		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
 		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100))
 		.build()
 		.execute_with(|| {
-			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(5, 3));
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
+			assert_eq!(MarginProtocol::equity_of_pool(MOCK_POOL_1), None);
+			assert_eq!(MarginProtocol::pool_above_risk_thresholds(MOCK_POOL_1), None);
+
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(99, 0));
+			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(99, 0));
 			let position: Position<Runtime> = Position {
 				owner: ALICE,
 				pool: MOCK_POOL,
@@ -714,34 +952,96 @@ fn trader_margin_call_should_work() {
 				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
 				margin_held: fixedi128_saturating_from_integer_currency_cent(100),
 			};
+			<Positions<Runtime>>::insert(0, position.clone());
+			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+			let snapshot = positions_snapshot(
+				1,
+				position.leveraged_held,
+				position.leveraged_debits,
+				FixedI128::saturating_from_integer(0),
+				FixedI128::saturating_from_integer(0),
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot);
 
+			// ENP 100%, ELL 100%
 			assert_eq!(
-				MarginProtocol::margin_level(&ALICE, MOCK_POOL),
-				Ok(FixedI128::max_value())
+				MarginProtocol::equity_of_pool(MOCK_POOL),
+				Some(fixedi128_saturating_from_integer_currency_cent(100))
 			);
-
-			// without position
-			assert_noop!(
-				MarginProtocol::trader_margin_call(Origin::none(), ALICE, MOCK_POOL),
-				Error::<Runtime>::SafeTrader
+			assert_eq!(
+				MarginProtocol::pool_above_risk_thresholds(MOCK_POOL),
+				Some((true, true))
 			);
 
-			<Positions<Runtime>>::insert(0, position);
-			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 50));
 			assert_eq!(
-				MarginProtocol::margin_level(&ALICE, MOCK_POOL),
-				Ok(FixedI128::saturating_from_integer(1))
+				MarginProtocol::pool_above_risk_thresholds(MOCK_POOL),
+				Some((false, true))
 			);
 
-			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(1, 20)));
+			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 100));
 			assert_eq!(
-				MarginProtocol::margin_level(&ALICE, MOCK_POOL),
-				Ok(FixedI128::saturating_from_rational(5, 100))
+				MarginProtocol::pool_above_risk_thresholds(MOCK_POOL),
+				Some((false, false))
 			);
 
-			assert_ok!(MarginProtocol::trader_margin_call(Origin::none(), ALICE, MOCK_POOL));
-		});
-}
+			// 10 cents unrealized profit of the position is a loss of the pool
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(11, 10)));
+			assert_eq!(
+				MarginProtocol::equity_of_pool(MOCK_POOL),
+				Some(fixedi128_saturating_from_integer_currency_cent(90))
+			);
+		});
+}
+
+#[test]
+fn trader_margin_call_should_work() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.build()
+		.execute_with(|| {
+			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(5, 3));
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
+			let position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				leverage: Leverage::LongTwo,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(100),
+			};
+
+			assert_eq!(
+				MarginProtocol::margin_level(&ALICE, MOCK_POOL),
+				Ok(FixedI128::max_value())
+			);
+
+			// without position
+			assert_noop!(
+				MarginProtocol::trader_margin_call(Origin::none(), ALICE, MOCK_POOL),
+				Error::<Runtime>::SafeTrader
+			);
+
+			<Positions<Runtime>>::insert(0, position);
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			assert_eq!(
+				MarginProtocol::margin_level(&ALICE, MOCK_POOL),
+				Ok(FixedI128::saturating_from_integer(1))
+			);
+
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(1, 20)));
+			assert_eq!(
+				MarginProtocol::margin_level(&ALICE, MOCK_POOL),
+				Ok(FixedI128::saturating_from_rational(5, 100))
+			);
+
+			assert_ok!(MarginProtocol::trader_margin_call(Origin::none(), ALICE, MOCK_POOL));
+		});
+}
 
 #[test]
 fn trader_become_safe_should_work() {
@@ -852,631 +1152,4782 @@ fn trader_stop_out_should_work() {
 		});
 }
 
-#[test]
-fn trader_stop_out_close_bigger_loss_position() {
+fn stop_out_ext() -> sp_io::TestExternalities {
 	ExtBuilder::default()
+		.module_balance(fixedi128_saturating_from_integer_currency_cent(100))
+		.balance(INSURANCE_FUND, CurrencyId::AUSD, balance_saturating_from_integer_currency_cent(100))
 		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100))
 		.spread(Permill::zero())
 		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
 		.price(CurrencyId::FEUR, (1, 1))
 		.build()
-		.execute_with(|| {
-			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(70, 60));
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
-			let loss_position: Position<Runtime> = Position {
-				owner: ALICE,
-				pool: MOCK_POOL,
-				pair: EUR_USD_PAIR,
-				leverage: Leverage::LongTwo,
-				leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
-				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
-				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
-				margin_held: fixedi128_saturating_from_integer_currency_cent(100),
-			};
+}
 
-			let bigger_loss_position: Position<Runtime> = Position {
-				owner: ALICE,
-				pool: MOCK_POOL,
-				pair: EUR_USD_PAIR,
-				leverage: Leverage::LongTwo,
-				leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
-				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
-				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
-				margin_held: fixedi128_saturating_from_integer_currency_cent(150),
-			};
+/// Stop out a position of Alice with 97 cents loss.
+fn stop_out_alice() -> Position<Runtime> {
+	stop_out_alice_by(Origin::none())
+}
 
-			<Positions<Runtime>>::insert(0, loss_position.clone());
-			<Positions<Runtime>>::insert(1, bigger_loss_position.clone());
-			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
-			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 1), ());
+/// Stop out a position of Alice with 97 cents loss, from `origin`.
+fn stop_out_alice_by(origin: Origin) -> Position<Runtime> {
+	set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(5, 3));
+	<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
+	let position: Position<Runtime> = Position {
+		owner: ALICE,
+		pool: MOCK_POOL,
+		pair: EUR_USD_PAIR,
+		leverage: Leverage::LongTwo,
+		leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
+		leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
+		open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+		margin_held: fixedi128_saturating_from_integer_currency_cent(100),
+	};
+	<Positions<Runtime>>::insert(0, position.clone());
+	<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+	PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+	<MarginHeld<Runtime>>::insert(ALICE, MOCK_POOL, position.margin_held);
+	PositionsSnapshots::insert(
+		MOCK_POOL,
+		EUR_USD_PAIR,
+		positions_snapshot(
+			1,
+			position.leveraged_held,
+			position.leveraged_debits,
+			FixedI128::saturating_from_integer(0),
+			FixedI128::saturating_from_integer(0),
+		),
+	);
 
-			let snapshot = positions_snapshot(
-				2,
-				loss_position
-					.leveraged_held
-					.checked_add(&bigger_loss_position.leveraged_held)
-					.unwrap(),
-				loss_position
-					.leveraged_debits
-					.checked_add(&bigger_loss_position.leveraged_debits)
-					.unwrap(),
-				FixedI128::saturating_from_integer(0),
-				FixedI128::saturating_from_integer(0),
-			);
-			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot);
+	MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(3, 100)));
+	assert_ok!(MarginProtocol::trader_stop_out(origin, ALICE, MOCK_POOL));
+	assert!(MarginProtocol::positions(0).is_none());
+	position
+}
 
-			assert_ok!(MarginProtocol::trader_stop_out(Origin::none(), ALICE, MOCK_POOL));
+#[test]
+fn keeper_is_rewarded_for_trader_stop_out() {
+	stop_out_ext().execute_with(|| {
+		let reward = Some(KeeperReward::Rate(Permill::from_percent(2)));
+		assert_noop!(
+			MarginProtocol::set_keeper_reward(Origin::signed(BOB), reward),
+			BadOrigin
+		);
+		assert_ok!(MarginProtocol::set_keeper_reward(
+			Origin::signed(UpdateOrigin::get()),
+			reward
+		));
+		let event = TestEvent::margin_protocol(RawEvent::KeeperRewardSet(reward));
+		assert!(System::events().iter().any(|record| record.event == event));
 
-			// position with bigger loss is closed
-			assert!(<PositionsByTrader<Runtime>>::contains_key(ALICE, (MOCK_POOL, 0)));
-			assert!(!<PositionsByTrader<Runtime>>::contains_key(ALICE, (MOCK_POOL, 1)));
-		});
+		// 2% of 100 cents notional
+		stop_out_alice_by(Origin::signed(BOB));
+		let amount = balance_saturating_from_integer_currency_cent(2);
+		assert_eq!(LiquidityCurrency::free_balance(&BOB), amount);
+		assert_eq!(
+			MarginProtocol::balances(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(1)
+		);
+		let event = TestEvent::margin_protocol(RawEvent::KeeperRewardPaid(BOB, amount));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
 }
 
 #[test]
-fn liquidity_pool_margin_call_and_become_safe_work() {
-	ExtBuilder::default()
-		.spread(Permill::zero())
-		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
-		.price(CurrencyId::FEUR, (1, 1))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100))
-		.build()
-		.execute_with(|| {
-			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(99, 0));
-			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(99, 0));
-			let position: Position<Runtime> = Position {
-				owner: ALICE,
-				pool: MOCK_POOL,
-				pair: EUR_USD_PAIR,
-				leverage: Leverage::LongTwo,
-				leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
-				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
-				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
-				margin_held: fixedi128_saturating_from_integer_currency_cent(100),
-			};
+fn no_keeper_reward_for_unsigned_trader_stop_out() {
+	stop_out_ext().execute_with(|| {
+		assert_ok!(MarginProtocol::set_keeper_reward(
+			Origin::signed(UpdateOrigin::get()),
+			Some(KeeperReward::Flat(balance_saturating_from_integer_currency_cent(2)))
+		));
+		stop_out_alice();
+		assert_eq!(
+			MarginProtocol::balances(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(3)
+		);
+	});
+}
 
-			<Positions<Runtime>>::insert(0, position.clone());
-			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
-			let snapshot = positions_snapshot(
-				1,
-				position.leveraged_held,
-				position.leveraged_debits,
-				FixedI128::saturating_from_integer(0),
-				FixedI128::saturating_from_integer(0),
-			);
-			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot);
-			assert_eq!(
-				MarginProtocol::enp_and_ell_with_action(MOCK_POOL, Action::None),
-				Ok((
-					FixedI128::saturating_from_integer(1),
-					FixedI128::saturating_from_integer(1)
-				))
-			);
+#[test]
+fn disputed_stop_out_could_be_compensated() {
+	stop_out_ext().execute_with(|| {
+		let position = stop_out_alice();
+		let record = StopOutRecord {
+			position,
+			price: FixedU128::saturating_from_rational(3, 100),
+			realized: fixedi128_saturating_from_integer_currency_cent(-97),
+		};
+		assert_eq!(MarginProtocol::stop_out_records(1, 0), Some(record.clone()));
 
-			// ENP 100% > 99%, ELL 100% > 99%, safe
-			assert_noop!(
-				MarginProtocol::liquidity_pool_margin_call(Origin::none(), MOCK_POOL),
-				Error::<Runtime>::SafePool
-			);
-			assert_ok!(MarginProtocol::liquidity_pool_become_safe(Origin::none(), MOCK_POOL));
+		assert_noop!(
+			MarginProtocol::dispute_stop_out(Origin::signed(BOB), 1, 0),
+			Error::<Runtime>::PositionNotOpenedByTrader
+		);
+		assert_ok!(MarginProtocol::dispute_stop_out(Origin::signed(ALICE), 1, 0));
+		assert_eq!(MarginProtocol::stop_out_records(1, 0), None);
+		assert_eq!(MarginProtocol::stop_out_disputes(0), Some(record));
+		let event = TestEvent::margin_protocol(RawEvent::StopOutDisputed(ALICE, 0));
+		assert!(System::events().iter().any(|record| record.event == event));
 
-			// ENP 100% == 100%, unsafe
-			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 0));
-			assert_ok!(MarginProtocol::liquidity_pool_margin_call(Origin::none(), MOCK_POOL));
-			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolMarginCalled(MOCK_POOL));
-			assert!(System::events().iter().any(|record| record.event == event));
+		let compensation = balance_saturating_from_integer_currency_cent(97);
+		assert_noop!(
+			MarginProtocol::resolve_stop_out_dispute(Origin::signed(BOB), 0, Some(compensation)),
+			BadOrigin
+		);
+		assert_ok!(MarginProtocol::resolve_stop_out_dispute(
+			Origin::signed(UpdateOrigin::get()),
+			0,
+			Some(compensation)
+		));
+		assert_eq!(MarginProtocol::stop_out_disputes(0), None);
+		assert_eq!(MarginProtocol::stop_out_compensations(0), Some((ALICE, compensation)));
+		let event = TestEvent::margin_protocol(RawEvent::StopOutDisputeResolved(0, Some(compensation)));
+		assert!(System::events().iter().any(|record| record.event == event));
 
-			assert_noop!(
-				MarginProtocol::liquidity_pool_become_safe(Origin::none(), MOCK_POOL),
-				Error::<Runtime>::UnsafePool
-			);
+		assert_noop!(
+			MarginProtocol::claim_stop_out_compensation(Origin::signed(BOB), 0),
+			Error::<Runtime>::PositionNotOpenedByTrader
+		);
+		assert_ok!(MarginProtocol::claim_stop_out_compensation(Origin::signed(ALICE), 0));
+		assert_eq!(LiquidityCurrency::free_balance(&ALICE), compensation);
+		assert_eq!(
+			LiquidityCurrency::free_balance(&INSURANCE_FUND),
+			balance_saturating_from_integer_currency_cent(3)
+		);
+		assert_eq!(MarginProtocol::stop_out_compensations(0), None);
+		let event = TestEvent::margin_protocol(RawEvent::StopOutCompensationClaimed(ALICE, 0, compensation));
+		assert!(System::events().iter().any(|record| record.event == event));
 
-			// ENP 100% > 99%, ELL 100% > 99%, safe
-			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(99, 0));
-			assert_ok!(MarginProtocol::liquidity_pool_margin_call(Origin::none(), MOCK_POOL));
-			assert_ok!(MarginProtocol::liquidity_pool_become_safe(Origin::none(), MOCK_POOL));
-			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolBecameSafe(MOCK_POOL));
-			assert!(System::events().iter().any(|record| record.event == event));
-		});
+		assert_noop!(
+			MarginProtocol::claim_stop_out_compensation(Origin::signed(ALICE), 0),
+			Error::<Runtime>::StopOutCompensationNotFound
+		);
+	});
 }
 
 #[test]
-fn liquidity_pool_force_close_works() {
-	ExtBuilder::default()
-		.spread(Permill::from_rational_approximation(1, 100u32))
-		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
-		.price(CurrencyId::FEUR, (1, 1))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(10_000_00))
-		.build()
-		.execute_with(|| {
-			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(0, 99));
-			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(0, 99));
-			<Balances<Runtime>>::insert(
-				ALICE,
-				MOCK_POOL,
-				fixedi128_saturating_from_integer_currency_cent(10_000_00),
-			);
-			assert_ok!(MarginProtocol::open_position(
-				Origin::signed(ALICE),
-				MOCK_POOL,
-				EUR_USD_PAIR,
-				Leverage::LongTwenty,
-				balance_saturating_from_integer_currency_cent(10_000_00),
-				Price::saturating_from_integer(2)
-			));
-
-			assert_eq!(
-				MarginProtocol::balances(ALICE, MOCK_POOL),
-				fixedi128_saturating_from_integer_currency_cent(10_000_00)
-			);
+fn stop_out_cannot_be_disputed_out_of_window() {
+	stop_out_ext().execute_with(|| {
+		stop_out_alice();
 
-			// ENP 100% > 99%, ELL 100% > 99%, safe
-			assert_noop!(
-				MarginProtocol::liquidity_pool_force_close(Origin::none(), MOCK_POOL),
-				Error::<Runtime>::NotReachedRiskThreshold
-			);
+		// stopped out at block 1, within window until block 6
+		MarginProtocol::on_initialize(6);
+		assert!(MarginProtocol::stop_out_records(1, 0).is_some());
+		MarginProtocol::on_initialize(7);
+		assert_eq!(MarginProtocol::stop_out_records(1, 0), None);
 
-			// Open position spread is 100
-			// Current price is 2, close position spread is 200.
-			// So liquidity remain 300. Total penalty is 200*2 = 400.
-			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(2, 1)));
-			// ENP 50% < 99%, unsafe
-			assert_ok!(MarginProtocol::liquidity_pool_force_close(Origin::none(), MOCK_POOL));
+		assert_noop!(
+			MarginProtocol::dispute_stop_out(Origin::signed(ALICE), 1, 0),
+			Error::<Runtime>::StopOutRecordNotFound
+		);
+		assert_noop!(
+			MarginProtocol::resolve_stop_out_dispute(Origin::signed(UpdateOrigin::get()), 0, None),
+			Error::<Runtime>::StopOutDisputeNotFound
+		);
+	});
+}
 
-			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolForceClosed(MOCK_POOL));
-			assert!(System::events().iter().any(|record| record.event == event));
+fn insured_eur_usd_long() -> Position<Runtime> {
+	assert_ok!(MarginProtocol::set_slippage_insurance_premium_rate(
+		Origin::signed(UpdateOrigin::get()),
+		EUR_USD_PAIR,
+		Some(Permill::from_percent(1))
+	));
+	assert_ok!(MarginProtocol::set_slippage_insurance(
+		Origin::signed(ALICE),
+		MOCK_POOL,
+		true
+	));
+	<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
+	let position: Position<Runtime> = Position {
+		owner: ALICE,
+		pool: MOCK_POOL,
+		pair: EUR_USD_PAIR,
+		leverage: Leverage::LongTwo,
+		leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
+		leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
+		open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+		margin_held: fixedi128_saturating_from_integer_currency_cent(50),
+	};
+	assert_eq!(
+		MarginProtocol::insert_position(&ALICE, MOCK_POOL, EUR_USD_PAIR, position.clone()),
+		Ok(0)
+	);
+	assert_eq!(
+		MarginProtocol::insured_positions(0),
+		Some((1, Permill::from_percent(1)))
+	);
+	position
+}
 
-			assert_eq!(
-				MarginProtocol::balances(ALICE, MOCK_POOL),
-				fixedi128_saturating_from_integer_currency_cent(19_700_00)
-			);
-			assert_eq!(MockLiquidityPools::liquidity(MOCK_POOL), 0);
-			assert_eq!(
-				LiquidityCurrency::total_balance(&TREASURY_ACCOUNT),
-				300_000000000000000000
-			);
+#[test]
+fn set_slippage_insurance_works() {
+	stop_out_ext().execute_with(|| {
+		assert_noop!(
+			MarginProtocol::set_slippage_insurance_premium_rate(
+				Origin::signed(ALICE),
+				EUR_USD_PAIR,
+				Some(Permill::from_percent(1))
+			),
+			BadOrigin
+		);
+		assert_ok!(MarginProtocol::set_slippage_insurance_premium_rate(
+			Origin::signed(UpdateOrigin::get()),
+			EUR_USD_PAIR,
+			Some(Permill::from_percent(1))
+		));
+		assert_eq!(
+			MarginProtocol::slippage_insurance_premium_rate(EUR_USD_PAIR),
+			Some(Permill::from_percent(1))
+		);
+		let event = TestEvent::margin_protocol(RawEvent::SlippageInsurancePremiumRateSet(
+			EUR_USD_PAIR,
+			Some(Permill::from_percent(1)),
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_ok!(MarginProtocol::set_slippage_insurance(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			true
+		));
+		assert_eq!(MarginProtocol::slippage_insurance(ALICE, MOCK_POOL), Some(()));
+		let event = TestEvent::margin_protocol(RawEvent::SlippageInsuranceSet(ALICE, MOCK_POOL, true));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_ok!(MarginProtocol::set_slippage_insurance(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			false
+		));
+		assert_eq!(MarginProtocol::slippage_insurance(ALICE, MOCK_POOL), None);
+	});
+}
+
+#[test]
+fn slippage_insurance_premium_is_charged_on_close() {
+	stop_out_ext().execute_with(|| {
+		insured_eur_usd_long();
+
+		// 3 started premium periods, at 1 cent each
+		System::set_block_number(25);
+		assert_ok!(MarginProtocol::close_position(
+			Origin::signed(ALICE),
+			0,
+			Price::zero(),
+			None
+		));
+		assert_eq!(MarginProtocol::insured_positions(0), None);
+		assert_eq!(
+			MarginProtocol::balances(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(97)
+		);
+		assert_eq!(
+			LiquidityCurrency::free_balance(&INSURANCE_FUND),
+			balance_saturating_from_integer_currency_cent(103)
+		);
+		let event = TestEvent::margin_protocol(RawEvent::SlippageInsurancePremiumPaid(
+			ALICE,
+			0,
+			balance_saturating_from_integer_currency_cent(3),
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn slippage_insurance_covers_stop_out_shortfall() {
+	stop_out_ext().execute_with(|| {
+		set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(5, 3));
+		insured_eur_usd_long();
+
+		// gap move to 1 cent equity, below the 3 cents at stop out threshold
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(1, 100)));
+		assert_ok!(MarginProtocol::trader_stop_out(Origin::none(), ALICE, MOCK_POOL));
+		assert_eq!(MarginProtocol::positions(0), None);
+
+		assert_eq!(
+			MarginProtocol::balances(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(3)
+		);
+		assert_eq!(
+			LiquidityCurrency::free_balance(&INSURANCE_FUND),
+			balance_saturating_from_integer_currency_cent(98)
+		);
+		let event = TestEvent::margin_protocol(RawEvent::SlippageInsuranceClaimPaid(
+			ALICE,
+			0,
+			balance_saturating_from_integer_currency_cent(2),
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+fn eur_usd_long_with_stop_loss() {
+	<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
+	let position: Position<Runtime> = Position {
+		owner: ALICE,
+		pool: MOCK_POOL,
+		pair: EUR_USD_PAIR,
+		leverage: Leverage::LongTwo,
+		leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
+		leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
+		open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+		margin_held: fixedi128_saturating_from_integer_currency_cent(50),
+	};
+	assert_eq!(
+		MarginProtocol::insert_position(&ALICE, MOCK_POOL, EUR_USD_PAIR, position),
+		Ok(0)
+	);
+	assert_ok!(MarginProtocol::set_stop_loss(
+		Origin::signed(ALICE),
+		0,
+		Some(Price::saturating_from_rational(95, 100))
+	));
+}
+
+#[test]
+fn stop_loss_works() {
+	stop_out_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		let stop_loss = Price::saturating_from_rational(95, 100);
+		assert_eq!(MarginProtocol::stop_loss(0), Some(stop_loss));
+		let event = TestEvent::margin_protocol(RawEvent::StopLossSet(ALICE, 0, Some(stop_loss)));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_noop!(
+			MarginProtocol::set_stop_loss(Origin::signed(BOB), 0, None),
+			Error::<Runtime>::PositionNotOpenedByTrader
+		);
+		assert_noop!(MarginProtocol::trigger_stop_loss(Origin::signed(ALICE), 0), BadOrigin);
+		assert_noop!(
+			MarginProtocol::trigger_stop_loss(Origin::none(), 0),
+			Error::<Runtime>::StopLossNotReached
+		);
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(90, 100)));
+		assert_ok!(MarginProtocol::trigger_stop_loss(Origin::none(), 0));
+		assert_eq!(MarginProtocol::positions(0), None);
+		assert_eq!(MarginProtocol::stop_loss(0), None);
+		assert_eq!(
+			MarginProtocol::balances(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(90)
+		);
+		let event = TestEvent::margin_protocol(RawEvent::StopLossTriggered(
+			ALICE,
+			0,
+			Price::saturating_from_rational(90, 100),
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn stop_loss_could_be_cancelled() {
+	stop_out_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		assert_ok!(MarginProtocol::set_stop_loss(Origin::signed(ALICE), 0, None));
+		assert_eq!(MarginProtocol::stop_loss(0), None);
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(90, 100)));
+		assert_noop!(
+			MarginProtocol::trigger_stop_loss(Origin::none(), 0),
+			Error::<Runtime>::StopLossNotSet
+		);
+	});
+}
+
+#[test]
+fn take_profit_works() {
+	stop_out_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		let take_profit = Price::saturating_from_rational(110, 100);
+		assert_noop!(
+			MarginProtocol::set_take_profit(Origin::signed(BOB), 0, Some(take_profit)),
+			Error::<Runtime>::PositionNotOpenedByTrader
+		);
+		assert_noop!(
+			MarginProtocol::trigger_take_profit(Origin::none(), 0),
+			Error::<Runtime>::TakeProfitNotSet
+		);
+		assert_ok!(MarginProtocol::set_take_profit(
+			Origin::signed(ALICE),
+			0,
+			Some(take_profit)
+		));
+		assert_eq!(MarginProtocol::take_profit(0), Some(take_profit));
+		let event = TestEvent::margin_protocol(RawEvent::TakeProfitSet(ALICE, 0, Some(take_profit)));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_noop!(
+			MarginProtocol::trigger_take_profit(Origin::none(), 0),
+			Error::<Runtime>::TakeProfitNotReached
+		);
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(110, 100)));
+		assert_ok!(MarginProtocol::trigger_take_profit(Origin::none(), 0));
+		assert_eq!(MarginProtocol::positions(0), None);
+		assert_eq!(MarginProtocol::take_profit(0), None);
+		assert_eq!(MarginProtocol::stop_loss(0), None);
+		assert_eq!(
+			MarginProtocol::balances(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(110)
+		);
+		let event = TestEvent::margin_protocol(RawEvent::TakeProfitTriggered(ALICE, 0, take_profit));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn trailing_stop_works() {
+	stop_out_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		let distance = TrailingDistance::Percent(Permill::from_percent(10));
+		assert_noop!(
+			MarginProtocol::set_trailing_stop(Origin::signed(BOB), 0, Some(distance)),
+			Error::<Runtime>::PositionNotOpenedByTrader
+		);
+		assert_ok!(MarginProtocol::set_trailing_stop(
+			Origin::signed(ALICE),
+			0,
+			Some(distance)
+		));
+		assert_eq!(
+			MarginProtocol::trailing_stop(0),
+			Some(TrailingStop {
+				distance,
+				watermark: Price::saturating_from_integer(1),
+			})
+		);
+		assert_eq!(
+			MarginProtocol::stop_loss(0),
+			Some(Price::saturating_from_rational(90, 100))
+		);
+		let event = TestEvent::margin_protocol(RawEvent::TrailingStopSet(ALICE, 0, Some(distance)));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		// ratchets with favorable price moves
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(120, 100)));
+		MarginProtocol::on_initialize(2);
+		assert_eq!(
+			MarginProtocol::trailing_stop(0).map(|t| t.watermark),
+			Some(Price::saturating_from_rational(120, 100))
+		);
+		assert_eq!(
+			MarginProtocol::stop_loss(0),
+			Some(Price::saturating_from_rational(108, 100))
+		);
+
+		// not with unfavorable ones
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(110, 100)));
+		MarginProtocol::on_initialize(3);
+		assert_eq!(
+			MarginProtocol::stop_loss(0),
+			Some(Price::saturating_from_rational(108, 100))
+		);
+		assert_noop!(
+			MarginProtocol::trigger_stop_loss(Origin::none(), 0),
+			Error::<Runtime>::StopLossNotReached
+		);
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(105, 100)));
+		assert_ok!(MarginProtocol::trigger_stop_loss(Origin::none(), 0));
+		assert_eq!(MarginProtocol::positions(0), None);
+		assert_eq!(MarginProtocol::trailing_stop(0), None);
+		assert_eq!(MarginProtocol::stop_loss(0), None);
+	});
+}
+
+#[test]
+fn trailing_stops_are_ratcheted_in_chunks() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (1, 1))
+		.build()
+		.execute_with(|| {
+			let distance = TrailingDistance::Percent(Permill::from_percent(10));
+			for id in 0..3 {
+				<Positions<Runtime>>::insert(id, eur_usd_long_1());
+				TrailingStops::insert(
+					id,
+					TrailingStop {
+						distance,
+						watermark: Price::saturating_from_integer(1),
+					},
+				);
+			}
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(120, 100)));
+			let ratcheted = || {
+				(0..3)
+					.filter(|id| MarginProtocol::stop_loss(id) == Some(Price::saturating_from_rational(108, 100)))
+					.count()
+			};
+
+			// `GetStopOutChunkSize` is 2
+			MarginProtocol::ratchet_trailing_stops();
+			assert_eq!(ratcheted(), 2);
+			assert!(MarginProtocol::trailing_stops_cursor().is_some());
+
+			MarginProtocol::ratchet_trailing_stops();
+			assert_eq!(ratcheted(), 3);
+			assert_eq!(MarginProtocol::trailing_stops_cursor(), None);
 		});
 }
 
 #[test]
-fn open_long_position_works() {
+fn trailing_stop_could_be_cancelled() {
+	stop_out_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		let distance = TrailingDistance::Price(Price::saturating_from_rational(5, 100));
+		assert_ok!(MarginProtocol::set_trailing_stop(
+			Origin::signed(ALICE),
+			0,
+			Some(distance)
+		));
+		assert_eq!(
+			MarginProtocol::stop_loss(0),
+			Some(Price::saturating_from_rational(95, 100))
+		);
+
+		// setting a stop loss price cancels the trailing stop
+		assert_ok!(MarginProtocol::set_stop_loss(
+			Origin::signed(ALICE),
+			0,
+			Some(Price::saturating_from_rational(90, 100))
+		));
+		assert_eq!(MarginProtocol::trailing_stop(0), None);
+		assert_eq!(
+			MarginProtocol::stop_loss(0),
+			Some(Price::saturating_from_rational(90, 100))
+		);
+
+		assert_ok!(MarginProtocol::set_trailing_stop(
+			Origin::signed(ALICE),
+			0,
+			Some(distance)
+		));
+		assert_ok!(MarginProtocol::set_trailing_stop(Origin::signed(ALICE), 0, None));
+		assert_eq!(MarginProtocol::trailing_stop(0), None);
+		assert_eq!(MarginProtocol::stop_loss(0), None);
+	});
+}
+
+#[test]
+fn position_expiry_works() {
+	order_ext().execute_with(|| {
+		<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
+		let amount = balance_saturating_from_integer_currency_cent(100);
+		let price = Price::saturating_from_integer(2);
+		let now = System::block_number();
+		assert_noop!(
+			MarginProtocol::open_position_with_expiry(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwo,
+				amount,
+				price,
+				now
+			),
+			Error::<Runtime>::InvalidExpiry
+		);
+
+		assert_ok!(MarginProtocol::open_position_with_expiry(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			Leverage::LongTwo,
+			amount,
+			price,
+			now + 5
+		));
+		assert_eq!(MarginProtocol::position_expiry(0), Some(now + 5));
+		let event = TestEvent::margin_protocol(RawEvent::PositionExpirySet(ALICE, 0, now + 5));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		MarginProtocol::on_initialize(now + 4);
+		assert!(MarginProtocol::positions(0).is_some());
+
+		MarginProtocol::on_initialize(now + 5);
+		assert_eq!(MarginProtocol::positions(0), None);
+		assert_eq!(MarginProtocol::position_expiry(0), None);
+		assert_eq!(MarginProtocol::expiring_positions(now + 5, 0), None);
+		let event = TestEvent::margin_protocol(RawEvent::PositionExpired(ALICE, 0, Price::saturating_from_integer(1)));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn circuit_breaker_works() {
 	ExtBuilder::default()
-		// USD/JPY = 107
-		.price(CurrencyId::FJPY, (1, 107))
-		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
-		.price(CurrencyId::FEUR, (1409, 1070))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.spread(Permill::zero())
+		.price(CurrencyId::FEUR, (1, 1))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
 		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.module_balance(fixedi128_saturating_from_integer_currency_cent(1_000_00))
 		.build()
 		.execute_with(|| {
 			<Balances<Runtime>>::insert(
 				ALICE,
 				MOCK_POOL,
-				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+				fixedi128_saturating_from_integer_currency_cent(1_000_00),
 			);
-			assert_ok!(MarginProtocol::open_position(
-				Origin::signed(ALICE),
-				MOCK_POOL,
-				EUR_JPY_PAIR,
-				Leverage::LongTwenty,
-				balance_saturating_from_integer_currency_cent(100_000_00),
-				Price::saturating_from_integer(142)
-			));
+			let open_position = || {
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					Leverage::LongTen,
+					balance_saturating_from_integer_currency_cent(100_00),
+					Price::max_value(),
+					None,
+				)
+			};
+			let on_initialize = |n: u64| {
+				System::set_block_number(n);
+				<MarginProtocol as OnInitialize<u64>>::on_initialize(n);
+			};
 
-			let position = {
-				let mut p = eur_jpy_long();
-				// with higher precision level
-				p.leveraged_debits = FixedI128::from_inner(-14104090000000000732600000);
-				p.margin_held = FixedI128::from_inner(6590696261682242990274);
-				p
+			let breaker = CircuitBreaker {
+				max_price_move: Permill::from_percent(10),
+				window: 10,
+				cool_down: 5,
 			};
-			let id = 0;
-			assert_eq!(MarginProtocol::positions(id), Some(position));
-			assert_eq!(MarginProtocol::positions_by_trader(ALICE, (MOCK_POOL, id)), Some(()));
+			assert_noop!(
+				MarginProtocol::set_circuit_breaker(Origin::signed(ALICE), EUR_USD_PAIR, Some(breaker)),
+				BadOrigin
+			);
+			assert_ok!(MarginProtocol::set_circuit_breaker(
+				Origin::signed(UpdateOrigin::get()),
+				EUR_USD_PAIR,
+				Some(breaker)
+			));
+			let event = TestEvent::margin_protocol(RawEvent::CircuitBreakerSet(EUR_USD_PAIR, Some(breaker)));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			// reference price taken on the first check
+			on_initialize(1);
 			assert_eq!(
-				MarginProtocol::positions_by_pool(MOCK_POOL, (EUR_JPY_PAIR, id)),
-				Some(())
+				MarginProtocol::circuit_breaker_reference(EUR_USD_PAIR),
+				Some((1, Price::saturating_from_integer(1)))
 			);
-			assert_eq!(MarginProtocol::next_position_id(), 1);
 
-			let event = TestEvent::margin_protocol(RawEvent::PositionOpened(
-				ALICE,
-				0,
-				MOCK_POOL,
-				EUR_JPY_PAIR,
-				Leverage::LongTwenty,
-				balance_saturating_from_integer_currency_cent(100_000_00),
-				// price: 141.0409
-				Price::from_inner(141040900000000007326),
+			// moved by 10%, not tripped
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(110, 100)));
+			on_initialize(2);
+			assert!(!MarginProtocol::is_circuit_breaker_tripped(EUR_USD_PAIR));
+
+			// moved by 20%, halted until block 8
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(120, 100)));
+			on_initialize(3);
+			assert_eq!(MarginProtocol::tripped_circuit_breaker(EUR_USD_PAIR), Some(8));
+			let event = TestEvent::margin_protocol(RawEvent::CircuitBreakerTripped(
+				EUR_USD_PAIR,
+				Price::saturating_from_rational(120, 100),
+				8,
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
+			assert_noop!(open_position(), Error::<Runtime>::CircuitBreakerTripped);
+
+			// halt ends after the cool down
+			on_initialize(8);
+			assert_eq!(MarginProtocol::tripped_circuit_breaker(EUR_USD_PAIR), None);
+			assert_ok!(open_position());
+
+			// moves out of the window are not compared
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(200, 100)));
+			on_initialize(13);
+			assert!(!MarginProtocol::is_circuit_breaker_tripped(EUR_USD_PAIR));
+			assert_eq!(
+				MarginProtocol::circuit_breaker_reference(EUR_USD_PAIR),
+				Some((13, Price::saturating_from_integer(2)))
+			);
+
+			// governance override
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(100, 100)));
+			on_initialize(14);
+			assert!(MarginProtocol::is_circuit_breaker_tripped(EUR_USD_PAIR));
+			assert_ok!(MarginProtocol::reset_circuit_breaker(
+				Origin::signed(UpdateOrigin::get()),
+				EUR_USD_PAIR
 			));
+			assert!(!MarginProtocol::is_circuit_breaker_tripped(EUR_USD_PAIR));
+			assert_eq!(MarginProtocol::circuit_breaker_reference(EUR_USD_PAIR), None);
+			let event = TestEvent::margin_protocol(RawEvent::CircuitBreakerReset(EUR_USD_PAIR));
 			assert!(System::events().iter().any(|record| record.event == event));
+			assert_ok!(open_position());
 		});
 }
 
 #[test]
-fn open_short_position_works() {
+fn pause_trading_works() {
 	ExtBuilder::default()
-		// USD/JPY = 106
-		.price(CurrencyId::FJPY, (1, 106))
-		// EUR/JPY = 141.9 => EUR/USD = 141.9/106
-		.price(CurrencyId::FEUR, (1419, 1060))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.spread(Permill::zero())
+		.price(CurrencyId::FEUR, (1, 1))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
 		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.module_balance(fixedi128_saturating_from_integer_currency_cent(1_000_00))
 		.build()
 		.execute_with(|| {
 			<Balances<Runtime>>::insert(
 				ALICE,
 				MOCK_POOL,
-				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+				fixedi128_saturating_from_integer_currency_cent(1_000_00),
 			);
-			assert_ok!(MarginProtocol::open_position(
-				Origin::signed(ALICE),
-				MOCK_POOL,
-				EUR_JPY_PAIR,
-				Leverage::ShortTwenty,
-				balance_saturating_from_integer_currency_cent(100_000_00),
-				Price::saturating_from_integer(141)
-			));
-
-			let position = {
-				let mut p = eur_jpy_short();
-				// with higher precision level
-				p.leveraged_debits = FixedI128::from_inner(14175810000000000585500000);
-				p.margin_held = FixedI128::from_inner(6686702830188679240621);
-				p
+			let open_position = || {
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					Leverage::LongTen,
+					balance_saturating_from_integer_currency_cent(100_00),
+					Price::max_value(),
+					None,
+				)
 			};
-			assert_eq!(MarginProtocol::positions(0), Some(position));
+			let pause = |scope| MarginProtocol::pause_trading(Origin::signed(PauseOrigin::get()), scope);
+			let resume = |scope| MarginProtocol::resume_trading(Origin::signed(PauseOrigin::get()), scope);
+			assert_ok!(open_position());
+
+			assert_noop!(
+				MarginProtocol::pause_trading(Origin::signed(UpdateOrigin::get()), TradingPauseScope::Protocol),
+				BadOrigin
+			);
+			assert_noop!(resume(TradingPauseScope::Protocol), Error::<Runtime>::TradingNotPaused);
+
+			// close-only mode in paused scopes
+			for &scope in [
+				TradingPauseScope::Protocol,
+				TradingPauseScope::Pool(MOCK_POOL),
+				TradingPauseScope::Pair(EUR_USD_PAIR),
+			]
+			.iter()
+			{
+				assert_ok!(pause(scope));
+				assert!(MarginProtocol::paused_trading(scope));
+				let event = TestEvent::margin_protocol(RawEvent::TradingPaused(scope));
+				assert!(System::events().iter().any(|record| record.event == event));
+				assert_noop!(pause(scope), Error::<Runtime>::TradingAlreadyPaused);
+				assert_noop!(open_position(), Error::<Runtime>::TradingPaused);
+
+				assert_ok!(resume(scope));
+				assert!(!MarginProtocol::paused_trading(scope));
+				let event = TestEvent::margin_protocol(RawEvent::TradingResumed(scope));
+				assert!(System::events().iter().any(|record| record.event == event));
+				assert_ok!(open_position());
+			}
+
+			// other pools and pairs are not affected
+			assert_ok!(pause(TradingPauseScope::Pool(MOCK_POOL_1)));
+			assert_ok!(pause(TradingPauseScope::Pair(EUR_JPY_PAIR)));
+			assert_ok!(open_position());
+
+			// positions could still be closed
+			assert_ok!(pause(TradingPauseScope::Protocol));
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				0,
+				Price::zero(),
+				None
+			));
+			assert_eq!(MarginProtocol::positions(0), None);
 		});
 }
 
 #[test]
-fn open_position_fails_if_trader_margin_called() {
+fn swap_settlement_works() {
 	ExtBuilder::default()
-		// USD/JPY = 107
-		.price(CurrencyId::FJPY, (1, 107))
-		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
-		.price(CurrencyId::FEUR, (1409, 1070))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.spread(Permill::zero())
+		.price(CurrencyId::FEUR, (1, 1))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
 		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.module_balance(fixedi128_saturating_from_integer_currency_cent(1_000_00))
 		.build()
 		.execute_with(|| {
 			<Balances<Runtime>>::insert(
 				ALICE,
 				MOCK_POOL,
-				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+				fixedi128_saturating_from_integer_currency_cent(1_000_00),
 			);
-			<MarginCalledTraders<Runtime>>::insert(ALICE, MOCK_POOL, ());
-			assert_noop!(
-				MarginProtocol::open_position(
+			let open_position = |leverage| {
+				assert_ok!(MarginProtocol::open_position(
 					Origin::signed(ALICE),
 					MOCK_POOL,
-					EUR_JPY_PAIR,
-					Leverage::LongTwenty,
-					balance_saturating_from_integer_currency_cent(100_000_00),
-					Price::saturating_from_integer(142)
-				),
-				Error::<Runtime>::MarginCalledTrader
+					EUR_USD_PAIR,
+					leverage,
+					balance_saturating_from_integer_currency_cent(1_000_00),
+					Price::max_value(),
+					None,
+				));
+			};
+			open_position(Leverage::LongTen);
+			open_position(Leverage::LongFive);
+			assert_ok!(MarginProtocol::set_margin_mode(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				MarginMode::Isolated
+			));
+			open_position(Leverage::LongTwo);
+			let balance = || MarginProtocol::balances(ALICE, MOCK_POOL);
+
+			// 1% swap rate accrued, 10 dollars received per position
+			MockLiquidityPools::set_mock_accumulated_swap_rate(
+				EUR_USD_PAIR,
+				FixedI128::saturating_from_rational(101, 100),
+			);
+
+			// not at settlement interval
+			MarginProtocol::on_initialize(99);
+			assert_eq!(balance(), fixedi128_saturating_from_integer_currency_cent(1_000_00));
+
+			// settled in chunks
+			MarginProtocol::on_initialize(100);
+			assert_eq!(balance(), fixedi128_saturating_from_integer_currency_cent(1_020_00));
+			assert!(MarginProtocol::swap_settlement_cursor().is_some());
+
+			MarginProtocol::on_initialize(101);
+			assert_eq!(balance(), fixedi128_saturating_from_integer_currency_cent(1_030_00));
+			assert_eq!(MarginProtocol::swap_settlement_cursor(), None);
+			assert_eq!(
+				MockLiquidityPools::liquidity(MOCK_POOL),
+				balance_saturating_from_integer_currency_cent(99_970_00)
+			);
+			assert_eq!(
+				MarginProtocol::positions(0).unwrap().open_accumulated_swap_rate,
+				FixedI128::saturating_from_rational(101, 100)
+			);
+			let event = TestEvent::margin_protocol(RawEvent::SwapSettled(
+				ALICE,
+				0,
+				fixedi128_saturating_from_integer_currency_cent(10_00),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			// the isolated position keeps its equity with more margin held
+			assert_eq!(
+				MarginProtocol::positions(2).unwrap().margin_held,
+				fixedi128_saturating_from_integer_currency_cent(510_00)
+			);
+			assert_eq!(
+				MarginProtocol::margin_held(ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(810_00)
+			);
+
+			// nothing accrued since settled
+			MarginProtocol::on_initialize(200);
+			MarginProtocol::on_initialize(201);
+			assert_eq!(balance(), fixedi128_saturating_from_integer_currency_cent(1_030_00));
+
+			// swap rate index down by 2%, 20 dollars paid per position
+			MockLiquidityPools::set_mock_accumulated_swap_rate(
+				EUR_USD_PAIR,
+				FixedI128::saturating_from_rational(99, 100),
+			);
+			MarginProtocol::on_initialize(300);
+			MarginProtocol::on_initialize(301);
+			assert_eq!(balance(), fixedi128_saturating_from_integer_currency_cent(970_00));
+			assert_eq!(
+				MockLiquidityPools::liquidity(MOCK_POOL),
+				balance_saturating_from_integer_currency_cent(100_030_00)
+			);
+			assert_eq!(
+				MarginProtocol::positions(2).unwrap().margin_held,
+				fixedi128_saturating_from_integer_currency_cent(490_00)
+			);
+			assert_eq!(
+				MarginProtocol::margin_held(ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(790_00)
+			);
+		});
+}
+
+fn order_ext() -> sp_io::TestExternalities {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		.price(CurrencyId::FEUR, (1, 1))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(1_000_00))
+		.build()
+}
+
+fn place_eur_usd_order(leverage: Leverage, kind: OrderKind, trigger_price: Price) {
+	<Balances<Runtime>>::insert(
+		ALICE,
+		MOCK_POOL,
+		fixedi128_saturating_from_integer_currency_cent(100_00),
+	);
+	assert_ok!(MarginProtocol::place_order(
+		Origin::signed(ALICE),
+		MOCK_POOL,
+		EUR_USD_PAIR,
+		leverage,
+		balance_saturating_from_integer_currency_cent(1_000_00),
+		kind,
+		trigger_price,
+		Price::saturating_from_integer(2),
+	));
+}
+
+#[test]
+fn limit_order_works() {
+	order_ext().execute_with(|| {
+		let trigger_price = Price::saturating_from_rational(90, 100);
+		place_eur_usd_order(Leverage::LongTwenty, OrderKind::Limit, trigger_price);
+		assert_eq!(MarginProtocol::next_order_id(), 1);
+		assert_eq!(
+			MarginProtocol::pending_orders(0),
+			Some(PendingOrder {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				leverage: Leverage::LongTwenty,
+				leveraged_amount: balance_saturating_from_integer_currency_cent(1_000_00),
+				kind: OrderKind::Limit,
+				trigger_price,
+				price: Price::saturating_from_integer(2),
+			})
+		);
+		let event = TestEvent::margin_protocol(RawEvent::OrderPlaced(
+			ALICE,
+			0,
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			Leverage::LongTwenty,
+			balance_saturating_from_integer_currency_cent(1_000_00),
+			OrderKind::Limit,
+			trigger_price,
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_noop!(MarginProtocol::execute_order(Origin::signed(ALICE), 0), BadOrigin);
+		assert_noop!(
+			MarginProtocol::execute_order(Origin::none(), 0),
+			Error::<Runtime>::OrderNotTriggered
+		);
+		assert_noop!(
+			MarginProtocol::execute_order(Origin::none(), 1),
+			Error::<Runtime>::OrderNotFound
+		);
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(trigger_price));
+		assert_ok!(MarginProtocol::execute_order(Origin::none(), 0));
+		assert_eq!(MarginProtocol::pending_orders(0), None);
+		assert_eq!(MarginProtocol::positions(0).map(|p| p.owner), Some(ALICE));
+		let event = TestEvent::margin_protocol(RawEvent::OrderExecuted(ALICE, 0, 0));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn stop_order_works() {
+	order_ext().execute_with(|| {
+		place_eur_usd_order(
+			Leverage::ShortTwenty,
+			OrderKind::Stop,
+			Price::saturating_from_rational(90, 100),
+		);
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(95, 100)));
+		assert_noop!(
+			MarginProtocol::execute_order(Origin::none(), 0),
+			Error::<Runtime>::OrderNotTriggered
+		);
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(85, 100)));
+		assert_ok!(MarginProtocol::execute_order(Origin::none(), 0));
+		assert_eq!(MarginProtocol::pending_orders(0), None);
+		assert_eq!(
+			MarginProtocol::positions(0).map(|p| p.leverage),
+			Some(Leverage::ShortTwenty)
+		);
+	});
+}
+
+#[test]
+fn order_could_be_cancelled() {
+	order_ext().execute_with(|| {
+		place_eur_usd_order(
+			Leverage::LongTwenty,
+			OrderKind::Limit,
+			Price::saturating_from_rational(90, 100),
+		);
+		assert_noop!(
+			MarginProtocol::cancel_order(Origin::signed(BOB), 0),
+			Error::<Runtime>::OrderNotPlacedByTrader
+		);
+		assert_ok!(MarginProtocol::cancel_order(Origin::signed(ALICE), 0));
+		assert_eq!(MarginProtocol::pending_orders(0), None);
+		let event = TestEvent::margin_protocol(RawEvent::OrderCancelled(ALICE, 0));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_noop!(
+			MarginProtocol::cancel_order(Origin::signed(ALICE), 0),
+			Error::<Runtime>::OrderNotFound
+		);
+	});
+}
+
+#[test]
+fn place_order_fails_if_too_many_pending_orders() {
+	order_ext().execute_with(|| {
+		let trigger_price = Price::saturating_from_rational(90, 100);
+		place_eur_usd_order(Leverage::LongTwenty, OrderKind::Limit, trigger_price);
+		place_eur_usd_order(Leverage::LongTwenty, OrderKind::Limit, trigger_price);
+		assert_eq!(MarginProtocol::trader_pending_orders(ALICE), 2);
+		assert_noop!(
+			MarginProtocol::place_order(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(1_000_00),
+				OrderKind::Limit,
+				trigger_price,
+				Price::saturating_from_integer(2),
+			),
+			Error::<Runtime>::TraderPendingOrdersLimitReached
+		);
+
+		assert_ok!(MarginProtocol::cancel_order(Origin::signed(ALICE), 0));
+		assert_eq!(MarginProtocol::trader_pending_orders(ALICE), 1);
+		place_eur_usd_order(Leverage::LongTwenty, OrderKind::Limit, trigger_price);
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(trigger_price));
+		assert_ok!(MarginProtocol::execute_order(Origin::none(), 1));
+		assert_ok!(MarginProtocol::cancel_order(Origin::signed(ALICE), 2));
+		assert!(!<TraderPendingOrders<Runtime>>::contains_key(ALICE));
+	});
+}
+
+#[test]
+fn order_expiry_works() {
+	order_ext().execute_with(|| {
+		<Balances<Runtime>>::insert(
+			ALICE,
+			MOCK_POOL,
+			fixedi128_saturating_from_integer_currency_cent(100_00),
+		);
+		let amount = balance_saturating_from_integer_currency_cent(1_000_00);
+		let trigger_price = Price::saturating_from_rational(90, 100);
+		let price = Price::saturating_from_integer(2);
+		let now = System::block_number();
+		assert_noop!(
+			MarginProtocol::place_order_with_expiry(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwenty,
+				amount,
+				OrderKind::Limit,
+				trigger_price,
+				price,
+				now
+			),
+			Error::<Runtime>::InvalidExpiry
+		);
+
+		assert_ok!(MarginProtocol::place_order_with_expiry(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			Leverage::LongTwenty,
+			amount,
+			OrderKind::Limit,
+			trigger_price,
+			price,
+			now + 5
+		));
+		assert_eq!(MarginProtocol::order_expiry(0), Some(now + 5));
+		let event = TestEvent::margin_protocol(RawEvent::OrderExpirySet(ALICE, 0, now + 5));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		MarginProtocol::on_initialize(now + 4);
+		assert!(MarginProtocol::pending_orders(0).is_some());
+
+		// expired orders can't be executed, even if not cancelled yet
+		System::set_block_number(now + 5);
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(trigger_price));
+		assert_noop!(
+			MarginProtocol::execute_order(Origin::none(), 0),
+			Error::<Runtime>::OrderExpired
+		);
+
+		MarginProtocol::on_initialize(now + 5);
+		assert_eq!(MarginProtocol::pending_orders(0), None);
+		assert_eq!(MarginProtocol::order_expiry(0), None);
+		assert_eq!(MarginProtocol::expiring_orders(now + 5, 0), None);
+		assert_eq!(MarginProtocol::trader_pending_orders(ALICE), 0);
+		let event = TestEvent::margin_protocol(RawEvent::OrderExpired(ALICE, 0));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn isolated_positions_are_excluded_from_cross_margin() {
+	stop_out_ext().execute_with(|| {
+		assert_eq!(MarginProtocol::margin_mode(ALICE, MOCK_POOL), MarginMode::Cross);
+		assert_ok!(MarginProtocol::set_margin_mode(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			MarginMode::Isolated
+		));
+		assert_eq!(MarginProtocol::margin_mode(ALICE, MOCK_POOL), MarginMode::Isolated);
+		let event = TestEvent::margin_protocol(RawEvent::MarginModeSet(ALICE, MOCK_POOL, MarginMode::Isolated));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		eur_usd_long_with_stop_loss();
+		assert_eq!(MarginProtocol::isolated_position(0), Some(()));
+
+		// margin held by the isolated position is excluded
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(90, 100)));
+		assert_eq!(
+			MarginProtocol::equity_of_trader(&ALICE, MOCK_POOL),
+			Ok(fixedi128_saturating_from_integer_currency_cent(50))
+		);
+		assert_eq!(
+			MarginProtocol::free_margin(&ALICE, MOCK_POOL),
+			Ok(fixedi128_saturating_from_integer_currency_cent(50))
+		);
+		assert_eq!(
+			MarginProtocol::margin_level(&ALICE, MOCK_POOL),
+			Ok(FixedI128::max_value())
+		);
+
+		// (50 - 10) / 100 = 40%
+		let position = MarginProtocol::positions(0).unwrap();
+		assert_eq!(
+			MarginProtocol::isolated_margin_level(&position),
+			Ok(FixedI128::saturating_from_rational(40, 100))
+		);
+	});
+}
+
+#[test]
+fn isolated_position_stop_out_works() {
+	stop_out_ext().execute_with(|| {
+		set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(5, 3));
+		eur_usd_long_with_stop_loss();
+		assert_noop!(
+			MarginProtocol::isolated_position_stop_out(Origin::none(), 0),
+			Error::<Runtime>::PositionNotIsolated
+		);
+		IsolatedPositions::insert(0, ());
+
+		assert_noop!(
+			MarginProtocol::isolated_position_stop_out(Origin::signed(ALICE), 0),
+			BadOrigin
+		);
+		assert_noop!(
+			MarginProtocol::isolated_position_stop_out(Origin::none(), 0),
+			Error::<Runtime>::NotReachedRiskThreshold
+		);
+
+		// 55 cents loss, isolated margin level = (50 - 55) / 100 = -5%
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(45, 100)));
+		assert_ok!(MarginProtocol::isolated_position_stop_out(Origin::none(), 0));
+		assert_eq!(MarginProtocol::positions(0), None);
+		assert_eq!(MarginProtocol::isolated_position(0), None);
+
+		// loss capped at the margin held by the position
+		assert_eq!(
+			MarginProtocol::balances(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(50)
+		);
+		assert_eq!(
+			MockLiquidityPools::liquidity(MOCK_POOL),
+			balance_saturating_from_integer_currency_cent(150)
+		);
+		let event = TestEvent::margin_protocol(RawEvent::IsolatedPositionStoppedOut(ALICE, 0));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn open_position_emits_warnings() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		.price(CurrencyId::FEUR, (12, 10))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(1_000_00))
+		.build()
+		.execute_with(|| {
+			let threshold = |warning| RiskThreshold {
+				margin_call: Permill::from_percent(5),
+				stop_out: Permill::from_percent(3),
+				warning: Permill::from_percent(warning),
+			};
+			set_trader_risk_threshold(EUR_USD_PAIR, threshold(10));
+			set_enp_risk_threshold(EUR_USD_PAIR, threshold(90));
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100_00));
+
+			// margin_level = 100 / 1200 = 8.3%, enp = 1000 / 1200 = 83.3%
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(1_000_00),
+				Price::saturating_from_integer(2),
+				None,
+			));
+
+			let trader_warned = TestEvent::margin_protocol(RawEvent::TraderWarned(ALICE, MOCK_POOL));
+			assert!(System::events().iter().any(|record| record.event == trader_warned));
+			let pool_warned = TestEvent::margin_protocol(RawEvent::LiquidityPoolWarned(MOCK_POOL));
+			assert!(System::events().iter().any(|record| record.event == pool_warned));
+
+			// warning is not enforced
+			assert_noop!(
+				MarginProtocol::trader_margin_call(Origin::none(), ALICE, MOCK_POOL),
+				Error::<Runtime>::SafeTrader
+			);
+			assert_noop!(
+				MarginProtocol::liquidity_pool_margin_call(Origin::none(), MOCK_POOL),
+				Error::<Runtime>::SafePool
 			);
+
+			// not warned again while still at warning risk
+			System::reset_events();
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(10_00),
+				Price::saturating_from_integer(2),
+				None,
+			));
+			assert!(!System::events().iter().any(|record| record.event == trader_warned));
+			assert!(!System::events().iter().any(|record| record.event == pool_warned));
+			assert_eq!(MarginProtocol::warned_traders(ALICE, MOCK_POOL), Some(()));
+			assert_eq!(MarginProtocol::warned_pools(MOCK_POOL), Some(()));
 		});
 }
 
 #[test]
-fn open_position_fails_if_pool_margin_called() {
-	ExtBuilder::default()
-		// USD/JPY = 107
-		.price(CurrencyId::FJPY, (1, 107))
-		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
-		.price(CurrencyId::FEUR, (1409, 1070))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
-		.build()
-		.execute_with(|| {
-			<Balances<Runtime>>::insert(
-				ALICE,
-				MOCK_POOL,
-				fixedi128_saturating_from_integer_currency_cent(10_000_00),
-			);
-			MarginCalledPools::insert(MOCK_POOL, ());
-			assert_noop!(
-				MarginProtocol::open_position(
-					Origin::signed(ALICE),
-					MOCK_POOL,
-					EUR_JPY_PAIR,
-					Leverage::LongTwenty,
-					balance_saturating_from_integer_currency_cent(100_000_00),
-					Price::saturating_from_integer(142)
-				),
-				Error::<Runtime>::MarginCalledPool
-			);
-		});
+fn migrate_risk_thresholds_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		StorageVersion::kill();
+		let old_threshold =
+			|margin_call, stop_out| (Permill::from_percent(margin_call), Permill::from_percent(stop_out));
+		let old = (
+			Some(old_threshold(5, 3)),
+			None::<(Permill, Permill)>,
+			Some(old_threshold(10, 8)),
+		);
+		frame_support::storage::unhashed::put(&RiskThresholds::hashed_key_for(EUR_USD_PAIR), &old);
+
+		<MarginProtocol as OnRuntimeUpgrade>::on_runtime_upgrade();
+
+		let threshold = |margin_call, stop_out| RiskThreshold {
+			margin_call: Permill::from_percent(margin_call),
+			stop_out: Permill::from_percent(stop_out),
+			warning: Permill::zero(),
+		};
+		let migrated = TradingPairRiskThreshold::new(Some(threshold(5, 3)), None, Some(threshold(10, 8)));
+		assert_eq!(MarginProtocol::risk_thresholds(EUR_USD_PAIR), migrated);
+		assert_eq!(MarginProtocol::storage_version(), STORAGE_VERSION);
+
+		// migrated only once
+		<MarginProtocol as OnRuntimeUpgrade>::on_runtime_upgrade();
+		assert_eq!(MarginProtocol::risk_thresholds(EUR_USD_PAIR), migrated);
+	});
+}
+
+#[test]
+fn migrate_margin_held_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		StorageVersion::put(1);
+		<Positions<Runtime>>::insert(0, eur_jpy_long());
+		<Positions<Runtime>>::insert(1, eur_jpy_short());
+		<Positions<Runtime>>::insert(
+			2,
+			Position {
+				owner: BOB,
+				..eur_jpy_long()
+			},
+		);
+
+		<MarginProtocol as OnRuntimeUpgrade>::on_runtime_upgrade();
+
+		assert_eq!(
+			MarginProtocol::margin_held(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(13_278_00)
+		);
+		assert_eq!(
+			MarginProtocol::margin_held(BOB, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(6_591_00)
+		);
+		assert_eq!(MarginProtocol::storage_version(), STORAGE_VERSION);
+	});
+}
+
+#[test]
+fn migrate_trader_pending_orders_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		StorageVersion::put(2);
+		let order = PendingOrder {
+			owner: ALICE,
+			pool: MOCK_POOL,
+			pair: EUR_USD_PAIR,
+			leverage: Leverage::LongTwenty,
+			leveraged_amount: balance_saturating_from_integer_currency_cent(1_000_00),
+			kind: OrderKind::Limit,
+			trigger_price: Price::saturating_from_rational(90, 100),
+			price: Price::saturating_from_integer(2),
+		};
+		<PendingOrders<Runtime>>::insert(0, order.clone());
+		<PendingOrders<Runtime>>::insert(1, order.clone());
+		<PendingOrders<Runtime>>::insert(2, PendingOrder { owner: BOB, ..order });
+
+		<MarginProtocol as OnRuntimeUpgrade>::on_runtime_upgrade();
+
+		assert_eq!(MarginProtocol::trader_pending_orders(ALICE), 2);
+		assert_eq!(MarginProtocol::trader_pending_orders(BOB), 1);
+		assert_eq!(MarginProtocol::storage_version(), STORAGE_VERSION);
+	});
+}
+
+#[test]
+fn can_stop_out_trader_works() {
+	ExtBuilder::default()
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100))
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.build()
+		.execute_with(|| {
+			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(5, 3));
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
+			let position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				leverage: Leverage::LongTwo,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(100),
+			};
+			assert!(!MarginProtocol::can_stop_out_trader(&ALICE, MOCK_POOL));
+
+			<Positions<Runtime>>::insert(0, position);
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			assert!(!MarginProtocol::can_stop_out_trader(&ALICE, MOCK_POOL));
+
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(3, 100)));
+			assert!(MarginProtocol::can_stop_out_trader(&ALICE, MOCK_POOL));
+
+			assert_ok!(MarginProtocol::trader_stop_out(Origin::none(), ALICE, MOCK_POOL));
+			assert!(!MarginProtocol::can_stop_out_trader(&ALICE, MOCK_POOL));
+		});
+}
+
+#[test]
+fn estimated_liquidation_price_works() {
+	stop_out_ext().execute_with(|| {
+		assert_eq!(MarginProtocol::estimated_liquidation_price(0), None);
+
+		set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(5, 3));
+		eur_usd_long_with_stop_loss();
+
+		// cross margin: 100 + (price - 1) * 100 = 3% * 100
+		assert_eq!(
+			MarginProtocol::estimated_liquidation_price(0),
+			Some(Price::saturating_from_rational(3, 100))
+		);
+
+		// unchanged by price moves
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(90, 100)));
+		assert_eq!(
+			MarginProtocol::estimated_liquidation_price(0),
+			Some(Price::saturating_from_rational(3, 100))
+		);
+
+		// isolated margin: 50 + (price - 1) * 100 = 3% * 100
+		IsolatedPositions::insert(0, ());
+		assert_eq!(
+			MarginProtocol::estimated_liquidation_price(0),
+			Some(Price::saturating_from_rational(53, 100))
+		);
+
+		// a non-positive price is never reached
+		<Positions<Runtime>>::mutate(0, |p| {
+			p.as_mut().unwrap().margin_held = fixedi128_saturating_from_integer_currency_cent(200)
+		});
+		assert_eq!(MarginProtocol::estimated_liquidation_price(0), None);
+	});
+}
+
+#[test]
+fn trader_stop_out_close_bigger_loss_position() {
+	ExtBuilder::default()
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100))
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.build()
+		.execute_with(|| {
+			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(70, 60));
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
+			let loss_position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				leverage: Leverage::LongTwo,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(100),
+			};
+
+			let bigger_loss_position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				leverage: Leverage::LongTwo,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(150),
+			};
+
+			<Positions<Runtime>>::insert(0, loss_position.clone());
+			<Positions<Runtime>>::insert(1, bigger_loss_position.clone());
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 1), ());
+
+			let snapshot = positions_snapshot(
+				2,
+				loss_position
+					.leveraged_held
+					.checked_add(&bigger_loss_position.leveraged_held)
+					.unwrap(),
+				loss_position
+					.leveraged_debits
+					.checked_add(&bigger_loss_position.leveraged_debits)
+					.unwrap(),
+				FixedI128::saturating_from_integer(0),
+				FixedI128::saturating_from_integer(0),
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot);
+
+			assert_ok!(MarginProtocol::trader_stop_out(Origin::none(), ALICE, MOCK_POOL));
+
+			// position with bigger loss is closed
+			assert!(<PositionsByTrader<Runtime>>::contains_key(ALICE, (MOCK_POOL, 0)));
+			assert!(!<PositionsByTrader<Runtime>>::contains_key(ALICE, (MOCK_POOL, 1)));
+			let event = TestEvent::margin_protocol(RawEvent::PositionLiquidated(
+				ALICE,
+				1,
+				Price::saturating_from_integer(1),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
+			let event = TestEvent::margin_protocol(RawEvent::PositionLiquidated(
+				ALICE,
+				0,
+				Price::saturating_from_integer(1),
+			));
+			assert!(!System::events().iter().any(|record| record.event == event));
+		});
+}
+
+#[test]
+fn trader_stop_out_order_works() {
+	ExtBuilder::default()
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100))
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.build()
+		.execute_with(|| {
+			let position = |pool, held, debits| -> Position<Runtime> {
+				Position {
+					owner: ALICE,
+					pool,
+					pair: EUR_USD_PAIR,
+					leverage: Leverage::LongTwo,
+					leveraged_held: fixedi128_saturating_from_integer_currency_cent(held),
+					leveraged_debits: fixedi128_saturating_from_integer_currency_cent(debits),
+					open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+					margin_held: fixedi128_saturating_from_integer_currency_cent(100),
+				}
+			};
+			let positions = vec![
+				// no loss, notional 100
+				position(MOCK_POOL, 100, -100),
+				// no loss, notional 200
+				position(MOCK_POOL, 200, -200),
+				// loss 50, notional 150
+				position(MOCK_POOL, 100, -150),
+				// no loss, notional 100
+				position(MOCK_POOL, 100, -100),
+				// in another pool
+				position(MOCK_POOL_1, 100, -200),
+			];
+			for (id, p) in positions.into_iter().enumerate() {
+				<PositionsByTrader<Runtime>>::insert(ALICE, (p.pool, id as PositionId), ());
+				<Positions<Runtime>>::insert(id as PositionId, p);
+			}
+
+			// the biggest loss, then the biggest notional, then the latest opened
+			assert_eq!(
+				MarginProtocol::trader_stop_out_order(&ALICE, MOCK_POOL),
+				vec![2, 1, 3, 0]
+			);
+		});
+}
+
+#[test]
+fn trader_stop_out_continues_in_following_blocks() {
+	ExtBuilder::default()
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100))
+		.module_balance(fixedi128_saturating_from_integer_currency_cent(100))
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.build()
+		.execute_with(|| {
+			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(5, 3));
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
+			let position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				leverage: Leverage::LongTwo,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(100),
+			};
+			for id in 0..3 {
+				<Positions<Runtime>>::insert(id, position.clone());
+				<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, id), ());
+			}
+			let snapshot = positions_snapshot(
+				3,
+				fixedi128_saturating_from_integer_currency_cent(300),
+				fixedi128_saturating_from_integer_currency_cent(-300),
+				FixedI128::zero(),
+				FixedI128::zero(),
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot);
+
+			// each position loses 97, trader is stopped out until all positions closed
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(3, 100)));
+			assert_ok!(MarginProtocol::trader_stop_out(Origin::none(), ALICE, MOCK_POOL));
+
+			// only `GetStopOutChunkSize` positions closed
+			let remaining: Vec<PositionId> = <PositionsByTrader<Runtime>>::iter_prefix(ALICE)
+				.map(|((_, id), _)| id)
+				.collect();
+			// equal losses and notional values, the latest opened closed first
+			assert_eq!(remaining, vec![0]);
+			assert_eq!(MarginProtocol::pending_trader_stop_outs(ALICE, MOCK_POOL), remaining);
+			let event = TestEvent::margin_protocol(RawEvent::TraderStopOutPending(ALICE, MOCK_POOL, 1));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			assert_eq!(
+				<MarginProtocol as OnInitialize<u64>>::on_initialize(1),
+				PENDING_STOP_OUT_WEIGHT_PER_POSITION
+			);
+			assert_eq!(<PositionsByTrader<Runtime>>::iter_prefix(ALICE).count(), 0);
+			assert!(!<PendingTraderStopOuts<Runtime>>::contains_key(ALICE, MOCK_POOL));
+			let event = TestEvent::margin_protocol(RawEvent::TraderStopOutFinished(ALICE, MOCK_POOL));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			// nothing pending
+			assert_eq!(<MarginProtocol as OnInitialize<u64>>::on_initialize(2), 0);
+		});
+}
+
+#[test]
+fn liquidity_pool_margin_call_and_become_safe_work() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100))
+		.build()
+		.execute_with(|| {
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(99, 0));
+			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(99, 0));
+			let position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				leverage: Leverage::LongTwo,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(100),
+			};
+
+			<Positions<Runtime>>::insert(0, position.clone());
+			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+			let snapshot = positions_snapshot(
+				1,
+				position.leveraged_held,
+				position.leveraged_debits,
+				FixedI128::saturating_from_integer(0),
+				FixedI128::saturating_from_integer(0),
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot);
+			assert_eq!(
+				MarginProtocol::enp_and_ell_with_action(MOCK_POOL, Action::None),
+				Ok((
+					FixedI128::saturating_from_integer(1),
+					FixedI128::saturating_from_integer(1)
+				))
+			);
+
+			// ENP 100% > 99%, ELL 100% > 99%, safe
+			assert_noop!(
+				MarginProtocol::liquidity_pool_margin_call(Origin::none(), MOCK_POOL),
+				Error::<Runtime>::SafePool
+			);
+			assert_ok!(MarginProtocol::liquidity_pool_become_safe(Origin::none(), MOCK_POOL));
+
+			// ENP 100% == 100%, unsafe
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 0));
+			assert_ok!(MarginProtocol::liquidity_pool_margin_call(Origin::none(), MOCK_POOL));
+			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolMarginCalled(MOCK_POOL));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			assert_noop!(
+				MarginProtocol::liquidity_pool_become_safe(Origin::none(), MOCK_POOL),
+				Error::<Runtime>::UnsafePool
+			);
+
+			// ENP 100% > 99%, ELL 100% > 99%, safe
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(99, 0));
+			assert_ok!(MarginProtocol::liquidity_pool_margin_call(Origin::none(), MOCK_POOL));
+			assert_ok!(MarginProtocol::liquidity_pool_become_safe(Origin::none(), MOCK_POOL));
+			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolBecameSafe(MOCK_POOL));
+			assert!(System::events().iter().any(|record| record.event == event));
+		});
+}
+
+#[test]
+fn liquidity_pool_force_close_works() {
+	ExtBuilder::default()
+		.spread(Permill::from_rational_approximation(1, 100u32))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(10_000_00))
+		.build()
+		.execute_with(|| {
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(0, 99));
+			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(0, 99));
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(10_000_00),
+				Price::saturating_from_integer(2),
+				None
+			));
+
+			assert_eq!(
+				MarginProtocol::balances(ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(10_000_00)
+			);
+
+			// ENP 100% > 99%, ELL 100% > 99%, safe
+			assert_noop!(
+				MarginProtocol::liquidity_pool_force_close(Origin::none(), MOCK_POOL),
+				Error::<Runtime>::NotReachedRiskThreshold
+			);
+
+			// Open position spread is 100
+			// Current price is 2, close position spread is 200.
+			// So liquidity remain 300. Total penalty is 200*2 = 400.
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(2, 1)));
+			// ENP 50% < 99%, unsafe
+			assert_ok!(MarginProtocol::liquidity_pool_force_close(Origin::none(), MOCK_POOL));
+
+			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolForceClosed(MOCK_POOL));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			assert_eq!(
+				MarginProtocol::balances(ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(19_700_00)
+			);
+			assert_eq!(MockLiquidityPools::liquidity(MOCK_POOL), 0);
+			assert_eq!(
+				LiquidityCurrency::total_balance(&TREASURY_ACCOUNT),
+				300_000000000000000000
+			);
+		});
+}
+
+#[test]
+fn liquidity_pool_force_close_with_penalty_tier_works() {
+	ExtBuilder::default()
+		.spread(Permill::from_rational_approximation(1, 100u32))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(10_000_00))
+		.build()
+		.execute_with(|| {
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(0, 99));
+			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(0, 99));
+			let tiers = vec![
+				ForceClosePenaltyTier {
+					threshold: Permill::from_percent(60),
+					penalty_rate: FixedU128::saturating_from_integer(1),
+					trader_share: Permill::from_percent(50),
+				},
+				ForceClosePenaltyTier {
+					threshold: Permill::from_percent(40),
+					penalty_rate: FixedU128::saturating_from_integer(2),
+					trader_share: Permill::from_percent(100),
+				},
+			];
+			assert_noop!(
+				MarginProtocol::set_force_close_penalty_tiers(Origin::signed(BOB), tiers.clone()),
+				BadOrigin
+			);
+			assert_ok!(MarginProtocol::set_force_close_penalty_tiers(
+				Origin::signed(UpdateOrigin::get()),
+				tiers.clone()
+			));
+			let event = TestEvent::margin_protocol(RawEvent::ForceClosePenaltyTiersSet(tiers));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(10_000_00),
+				Price::saturating_from_integer(2),
+				None
+			));
+
+			// Same as `liquidity_pool_force_close_works`, spread profit on close is 200, and liquidity remain 300.
+			// ENP 50% matches the 60% threshold tier, penalty is 200, half of it compensated to trader.
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(2, 1)));
+			assert_ok!(MarginProtocol::liquidity_pool_force_close(Origin::none(), MOCK_POOL));
+
+			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolPenalized(
+				MOCK_POOL,
+				0,
+				balance_saturating_from_integer_currency_cent(100_00),
+				balance_saturating_from_integer_currency_cent(200_00),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			assert_eq!(
+				MarginProtocol::balances(ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(19_800_00)
+			);
+			assert_eq!(MockLiquidityPools::liquidity(MOCK_POOL), 0);
+			assert_eq!(
+				LiquidityCurrency::total_balance(&TREASURY_ACCOUNT),
+				balance_saturating_from_integer_currency_cent(200_00)
+			);
+		});
+}
+
+#[test]
+fn liquidity_pool_force_close_penalizes_short_positions() {
+	ExtBuilder::default()
+		.spread(Permill::from_rational_approximation(1, 100u32))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(5_000_00))
+		.build()
+		.execute_with(|| {
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(0, 99));
+			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(0, 99));
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::ShortTwenty,
+				balance_saturating_from_integer_currency_cent(10_000_00),
+				Price::zero(),
+				None
+			));
+
+			// Sold at 0.99 and bought back at 0.505: profit 4850. Spread profit on close is 50, and the default
+			// tier penalty is 50, all to treasury.
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(1, 2)));
+			assert_ok!(MarginProtocol::liquidity_pool_force_close(Origin::none(), MOCK_POOL));
+
+			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolPenalized(
+				MOCK_POOL,
+				0,
+				0,
+				balance_saturating_from_integer_currency_cent(100_00),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			assert_eq!(
+				MarginProtocol::balances(ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(14_850_00)
+			);
+			assert_eq!(
+				MockLiquidityPools::liquidity(MOCK_POOL),
+				balance_saturating_from_integer_currency_cent(50_00)
+			);
+			assert_eq!(
+				LiquidityCurrency::total_balance(&TREASURY_ACCOUNT),
+				balance_saturating_from_integer_currency_cent(100_00)
+			);
+		});
+}
+
+#[test]
+fn set_auto_deleverage_rate_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		let rate = Some(Permill::from_percent(50));
+		assert_noop!(
+			MarginProtocol::set_auto_deleverage_rate(Origin::signed(BOB), rate),
+			BadOrigin
+		);
+		for &invalid in [Permill::zero(), Permill::one()].iter() {
+			assert_noop!(
+				MarginProtocol::set_auto_deleverage_rate(Origin::signed(UpdateOrigin::get()), Some(invalid)),
+				Error::<Runtime>::InvalidAutoDeleverageRate
+			);
+		}
+
+		assert_ok!(MarginProtocol::set_auto_deleverage_rate(
+			Origin::signed(UpdateOrigin::get()),
+			rate
+		));
+		assert_eq!(MarginProtocol::auto_deleverage_rate(), rate);
+		let event = TestEvent::margin_protocol(RawEvent::AutoDeleverageRateSet(rate));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_ok!(MarginProtocol::set_auto_deleverage_rate(
+			Origin::signed(UpdateOrigin::get()),
+			None
+		));
+		assert_eq!(MarginProtocol::auto_deleverage_rate(), None);
+	});
+}
+
+#[test]
+fn liquidity_pool_auto_deleverage_works() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(10_000_00))
+		.build()
+		.execute_with(|| {
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(50, 50));
+			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(50, 50));
+			assert_ok!(MarginProtocol::set_auto_deleverage_rate(
+				Origin::signed(UpdateOrigin::get()),
+				Some(Permill::from_percent(50))
+			));
+			for (who, amount) in [(BOB, 5_000_00), (ALICE, 10_000_00)].iter() {
+				<Balances<Runtime>>::insert(
+					who,
+					MOCK_POOL,
+					fixedi128_saturating_from_integer_currency_cent(10_000_00),
+				);
+				assert_ok!(MarginProtocol::open_position(
+					Origin::signed(*who),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(*amount),
+					Price::saturating_from_integer(2),
+					None
+				));
+			}
+
+			// BOB's profit 1000 and ALICE's 2000. Equity 7000, net position 18000, ENP 38.9% <= 50%.
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(12, 10)));
+			assert_ok!(MarginProtocol::liquidity_pool_force_close(Origin::none(), MOCK_POOL));
+
+			// Halving ALICE's position realizes 1000. Equity 7000, net position 12000, ENP 58.3% > 50%.
+			let event = TestEvent::margin_protocol(RawEvent::PositionAutoDeleveraged(
+				ALICE,
+				1,
+				MOCK_POOL,
+				Permill::from_percent(50),
+				FixedI128::saturating_from_integer(5_000),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
+			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolAutoDeleveraged(MOCK_POOL, 1));
+			assert!(System::events().iter().any(|record| record.event == event));
+			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolForceClosed(MOCK_POOL));
+			assert!(!System::events().iter().any(|record| record.event == event));
+
+			assert_eq!(
+				MarginProtocol::balances(ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(11_000_00)
+			);
+			assert_eq!(
+				MarginProtocol::balances(BOB, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(10_000_00)
+			);
+			assert_eq!(
+				MockLiquidityPools::liquidity(MOCK_POOL),
+				balance_saturating_from_integer_currency_cent(9_000_00)
+			);
+			assert!(MarginProtocol::positions(0).is_some());
+			assert!(MarginProtocol::positions(1).is_some());
+
+			assert_noop!(
+				MarginProtocol::liquidity_pool_force_close(Origin::none(), MOCK_POOL),
+				Error::<Runtime>::NotReachedRiskThreshold
+			);
+		});
+}
+
+#[test]
+fn no_flat_keeper_reward_if_no_position_closed() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(10_000_00))
+		.build()
+		.execute_with(|| {
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(50, 50));
+			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(50, 50));
+			assert_ok!(MarginProtocol::set_auto_deleverage_rate(
+				Origin::signed(UpdateOrigin::get()),
+				Some(Permill::from_percent(50))
+			));
+			assert_ok!(MarginProtocol::set_keeper_reward(
+				Origin::signed(UpdateOrigin::get()),
+				Some(KeeperReward::Flat(balance_saturating_from_integer_currency_cent(2)))
+			));
+			for (who, amount) in [(BOB, 5_000_00), (ALICE, 10_000_00)].iter() {
+				<Balances<Runtime>>::insert(
+					who,
+					MOCK_POOL,
+					fixedi128_saturating_from_integer_currency_cent(10_000_00),
+				);
+				assert_ok!(MarginProtocol::open_position(
+					Origin::signed(*who),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(*amount),
+					Price::saturating_from_integer(2),
+					None
+				));
+			}
+
+			// auto-deleveraging makes the pool safe, without closing any position
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(12, 10)));
+			assert_ok!(MarginProtocol::liquidity_pool_force_close(
+				Origin::signed(POOL_OWNER),
+				MOCK_POOL
+			));
+			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolAutoDeleveraged(MOCK_POOL, 1));
+			assert!(System::events().iter().any(|record| record.event == event));
+			assert!(MarginProtocol::positions(0).is_some());
+			assert!(MarginProtocol::positions(1).is_some());
+
+			assert_eq!(LiquidityCurrency::free_balance(&POOL_OWNER), 0);
+			assert_eq!(
+				MockLiquidityPools::liquidity(MOCK_POOL),
+				balance_saturating_from_integer_currency_cent(9_000_00)
+			);
+			let event = TestEvent::margin_protocol(RawEvent::KeeperRewardPaid(
+				POOL_OWNER,
+				balance_saturating_from_integer_currency_cent(2),
+			));
+			assert!(!System::events().iter().any(|record| record.event == event));
+		});
+}
+
+#[test]
+fn liquidity_pool_auto_deleverage_falls_back_to_force_close() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(10_000_00))
+		.build()
+		.execute_with(|| {
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(50, 50));
+			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(50, 50));
+			assert_ok!(MarginProtocol::set_auto_deleverage_rate(
+				Origin::signed(UpdateOrigin::get()),
+				Some(Permill::from_percent(50))
+			));
+			for (who, amount) in [(BOB, 5_000_00), (ALICE, 10_000_00)].iter() {
+				<Balances<Runtime>>::insert(
+					who,
+					MOCK_POOL,
+					fixedi128_saturating_from_integer_currency_cent(10_000_00),
+				);
+				assert_ok!(MarginProtocol::open_position(
+					Origin::signed(*who),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(*amount),
+					Price::saturating_from_integer(2),
+					None
+				));
+			}
+
+			// BOB's profit 3000 and ALICE's 6000. Equity 1000, net position 24000, ENP 4.2% <= 50%.
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(16, 10)));
+			assert_ok!(MarginProtocol::liquidity_pool_force_close(Origin::none(), MOCK_POOL));
+
+			// Both halved, ENP 8.3% still <= 50%. The stop out chunk is used up, and the rest is force closed in
+			// following blocks.
+			for (who, position_id, held) in [(ALICE, 1, 5_000), (BOB, 0, 2_500)].iter() {
+				let event = TestEvent::margin_protocol(RawEvent::PositionAutoDeleveraged(
+					*who,
+					*position_id,
+					MOCK_POOL,
+					Permill::from_percent(50),
+					FixedI128::saturating_from_integer(*held),
+				));
+				assert!(System::events().iter().any(|record| record.event == event));
+			}
+			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolForceClosePending(MOCK_POOL, 2));
+			assert!(System::events().iter().any(|record| record.event == event));
+			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolForceClosed(MOCK_POOL));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			<MarginProtocol as OnInitialize<u64>>::on_initialize(2);
+			assert_eq!(MarginProtocol::positions(0), None);
+			assert_eq!(MarginProtocol::positions(1), None);
+			assert_eq!(
+				MockLiquidityPools::liquidity(MOCK_POOL),
+				balance_saturating_from_integer_currency_cent(1_000_00)
+			);
+		});
+}
+
+#[test]
+fn liquidity_pool_force_close_penalty_is_split() {
+	ExtBuilder::default()
+		.spread(Permill::from_rational_approximation(1, 100u32))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(5_000_00))
+		.build()
+		.execute_with(|| {
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(0, 99));
+			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(0, 99));
+			let split = FeeSplit {
+				pool_owner: 2,
+				insurance_fund: 1,
+				treasury: 0,
+				keeper: 1,
+			};
+			assert_noop!(
+				MarginProtocol::set_fee_split(Origin::signed(BOB), MOCK_POOL, Some(split)),
+				BadOrigin
+			);
+			assert_ok!(MarginProtocol::set_fee_split(
+				Origin::signed(UpdateOrigin::get()),
+				MOCK_POOL,
+				Some(split)
+			));
+			assert_eq!(MarginProtocol::fee_splits(MOCK_POOL), Some(split));
+			let event = TestEvent::margin_protocol(RawEvent::FeeSplitSet(MOCK_POOL, Some(split)));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::ShortTwenty,
+				balance_saturating_from_integer_currency_cent(10_000_00),
+				Price::zero(),
+				None
+			));
+
+			// Same as `liquidity_pool_force_close_penalizes_short_positions`, 100 realized from the pool. Half of it
+			// stays in the pool, a quarter to the insurance fund, and the keeper quarter to the treasury.
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(1, 2)));
+			assert_ok!(MarginProtocol::liquidity_pool_force_close(Origin::none(), MOCK_POOL));
+
+			assert_eq!(
+				MockLiquidityPools::liquidity(MOCK_POOL),
+				balance_saturating_from_integer_currency_cent(100_00)
+			);
+			assert_eq!(
+				LiquidityCurrency::total_balance(&TREASURY_ACCOUNT),
+				balance_saturating_from_integer_currency_cent(25_00)
+			);
+			assert_eq!(
+				LiquidityCurrency::total_balance(&INSURANCE_FUND),
+				balance_saturating_from_integer_currency_cent(25_00)
+			);
+
+			assert_ok!(MarginProtocol::set_fee_split(
+				Origin::signed(UpdateOrigin::get()),
+				MOCK_POOL,
+				None
+			));
+			assert_eq!(MarginProtocol::fee_splits(MOCK_POOL), None);
+		});
+}
+
+#[test]
+fn liquidity_pool_force_close_continues_in_following_blocks() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(10_000_00))
+		.build()
+		.execute_with(|| {
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(0, 99));
+			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(0, 99));
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			for _ in 0..3 {
+				assert_ok!(MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(3_000_00),
+					Price::saturating_from_integer(2),
+					None
+				));
+			}
+
+			// ENP 1000 / 18000 < 99%, unsafe
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(2, 1)));
+			assert_ok!(MarginProtocol::liquidity_pool_force_close(Origin::none(), MOCK_POOL));
+
+			// only `GetStopOutChunkSize` positions closed
+			let remaining: Vec<PositionId> = PositionsByPool::iter_prefix(MOCK_POOL)
+				.map(|((_, id), _)| id)
+				.collect();
+			assert_eq!(remaining.len(), 1);
+			let (_, pending) = MarginProtocol::pending_pool_force_closes(MOCK_POOL).unwrap();
+			assert_eq!(pending, remaining);
+			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolForceClosePending(MOCK_POOL, 1));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			assert_eq!(
+				<MarginProtocol as OnInitialize<u64>>::on_initialize(1),
+				PENDING_STOP_OUT_WEIGHT_PER_POSITION
+			);
+			assert_eq!(PositionsByPool::iter_prefix(MOCK_POOL).count(), 0);
+			assert_eq!(MarginProtocol::pending_pool_force_closes(MOCK_POOL), None);
+			let event = TestEvent::margin_protocol(RawEvent::LiquidityPoolForceCloseFinished(MOCK_POOL));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			// profit of 3000 realized for each position
+			assert_eq!(
+				MarginProtocol::balances(ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(19_000_00)
+			);
+		});
+}
+
+#[test]
+fn close_position_and_stop_out_weights_scale_with_positions() {
+	// no storage access weights in mock
+	let trader_positions = GetTraderMaxOpenPositions::get() as Weight * TRADER_WEIGHT_PER_POSITION;
+	let stop_out_chunk = Weight::from(GetStopOutChunkSize::get()) * PENDING_STOP_OUT_WEIGHT_PER_POSITION;
+
+	assert_eq!(
+		Call::<Runtime>::close_position(0, Price::zero(), None)
+			.get_dispatch_info()
+			.weight,
+		20_000 + trader_positions
+	);
+	assert_eq!(
+		Call::<Runtime>::trader_stop_out(ALICE, MOCK_POOL)
+			.get_dispatch_info()
+			.weight,
+		30_000 + trader_positions + stop_out_chunk
+	);
+	assert_eq!(
+		Call::<Runtime>::liquidity_pool_force_close(MOCK_POOL)
+			.get_dispatch_info()
+			.weight,
+		30_000 + stop_out_chunk
+	);
+}
+
+#[test]
+fn open_position_and_withdraw_weights_scale_with_trader_positions() {
+	// no storage access weights in mock
+	let trader_positions = GetTraderMaxOpenPositions::get() as Weight * TRADER_WEIGHT_PER_POSITION;
+
+	assert_eq!(
+		Call::<Runtime>::open_position(MOCK_POOL, EUR_USD_PAIR, Leverage::LongTwo, 1, Price::zero(), None)
+			.get_dispatch_info()
+			.weight,
+		20_000 + trader_positions
+	);
+	assert_eq!(
+		Call::<Runtime>::withdraw(MOCK_POOL, 1).get_dispatch_info().weight,
+		10_000 + trader_positions
+	);
+}
+
+#[test]
+fn open_long_position_works() {
+	ExtBuilder::default()
+		// USD/JPY = 107
+		.price(CurrencyId::FJPY, (1, 107))
+		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
+		.price(CurrencyId::FEUR, (1409, 1070))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_JPY_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(100_000_00),
+				Price::saturating_from_integer(142),
+				None
+			));
+
+			let position = {
+				let mut p = eur_jpy_long();
+				// with higher precision level
+				p.leveraged_debits = FixedI128::from_inner(-14104090000000000732600000);
+				p.margin_held = FixedI128::from_inner(6590696261682242990275);
+				p
+			};
+			let id = 0;
+			assert_eq!(MarginProtocol::positions(id), Some(position));
+			assert_eq!(MarginProtocol::positions_by_trader(ALICE, (MOCK_POOL, id)), Some(()));
+			assert_eq!(
+				MarginProtocol::positions_by_pool(MOCK_POOL, (EUR_JPY_PAIR, id)),
+				Some(())
+			);
+			assert_eq!(MarginProtocol::next_position_id(), 1);
+
+			let event = TestEvent::margin_protocol(RawEvent::PositionOpened(
+				ALICE,
+				0,
+				MOCK_POOL,
+				EUR_JPY_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(100_000_00),
+				// price: 141.0409
+				Price::from_inner(141040900000000007326),
+				0,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
+		});
+}
+
+#[test]
+fn open_position_with_fee_discount_works() {
+	ExtBuilder::default()
+		// USD/JPY = 107
+		.price(CurrencyId::FJPY, (1, 107))
+		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
+		.price(CurrencyId::FEUR, (1409, 1070))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			MockFeeDiscount::set_mock_fee_discount(Permill::from_percent(50));
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_JPY_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(100_000_00),
+				Price::saturating_from_integer(142),
+				None
+			));
+
+			let event = TestEvent::margin_protocol(RawEvent::PositionOpened(
+				ALICE,
+				0,
+				MOCK_POOL,
+				EUR_JPY_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(100_000_00),
+				// price: 140.97045, half of the spread discounted
+				Price::from_inner(140970450000000007326),
+				0,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
+		});
+}
+
+#[test]
+fn open_short_position_works() {
+	ExtBuilder::default()
+		// USD/JPY = 106
+		.price(CurrencyId::FJPY, (1, 106))
+		// EUR/JPY = 141.9 => EUR/USD = 141.9/106
+		.price(CurrencyId::FEUR, (1419, 1060))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_JPY_PAIR,
+				Leverage::ShortTwenty,
+				balance_saturating_from_integer_currency_cent(100_000_00),
+				Price::saturating_from_integer(141),
+				None
+			));
+
+			let position = {
+				let mut p = eur_jpy_short();
+				// with higher precision level
+				p.leveraged_debits = FixedI128::from_inner(14175810000000000585500000);
+				p.margin_held = FixedI128::from_inner(6686702830188679240621);
+				p
+			};
+			assert_eq!(MarginProtocol::positions(0), Some(position));
+		});
+}
+
+#[test]
+fn simulate_open_position_works() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		// EUR/USD = 1.2
+		.price(CurrencyId::FEUR, (12, 10))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+
+			let simulation = MarginProtocol::simulate_open_position(
+				&ALICE,
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(1_000_00),
+				Price::saturating_from_integer(2),
+			)
+			.unwrap();
+			assert_eq!(simulation.open_price, Price::saturating_from_rational(12, 10));
+			assert_eq!(simulation.margin_held, fixedi128_saturating_from_integer_currency_cent(60_00));
+
+			// no storage change
+			assert_eq!(MarginProtocol::next_position_id(), 0);
+			assert_eq!(MarginProtocol::margin_held(&ALICE, MOCK_POOL), FixedI128::zero());
+
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(1_000_00),
+				Price::saturating_from_integer(2),
+				None,
+			));
+			assert_eq!(MarginProtocol::margin_held(&ALICE, MOCK_POOL), simulation.margin_held);
+			assert_eq!(MarginProtocol::margin_level(&ALICE, MOCK_POOL), Ok(simulation.margin_level));
+			assert_eq!(MarginProtocol::enp_and_ell(MOCK_POOL), Some((simulation.enp, simulation.ell)));
+
+			// each price is read once, as in computing the margin level
+			MockPrices::reset_reads();
+			assert!(MarginProtocol::margin_level(&ALICE, MOCK_POOL).is_ok());
+			let reads = MockPrices::reads();
+			MockPrices::reset_reads();
+			assert!(MarginProtocol::simulate_open_position(
+				&ALICE,
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(1_000_00),
+				Price::saturating_from_integer(2),
+			)
+			.is_ok());
+			assert_eq!(MockPrices::reads(), reads);
+		});
+}
+
+#[test]
+fn simulate_open_position_returns_open_position_error() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(1_00));
+			assert_eq!(
+				MarginProtocol::simulate_open_position(
+					&ALICE,
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(1_000_00),
+					Price::saturating_from_integer(2),
+				),
+				Err(Error::<Runtime>::InsufficientFreeMargin.into())
+			);
+			assert_eq!(
+				MarginProtocol::simulate_open_position(
+					&ALICE,
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(1_000_00),
+					Price::saturating_from_integer(1),
+				),
+				Err(Error::<Runtime>::MarketPriceTooHigh.into())
+			);
+		});
+}
+
+fn eur_jpy_long_quote() -> Quote<u64, u64> {
+	Quote {
+		trader: ALICE,
+		pool: MOCK_POOL,
+		pair: EUR_JPY_PAIR,
+		leverage: Leverage::LongTwenty,
+		leveraged_amount: balance_saturating_from_integer_currency_cent(100_000_00),
+		price: Price::saturating_from_integer(141),
+		expiry: 10,
+		nonce: 0,
+	}
+}
+
+fn quote_ext() -> sp_io::TestExternalities {
+	ExtBuilder::default()
+		// USD/JPY = 107
+		.price(CurrencyId::FJPY, (1, 107))
+		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
+		.price(CurrencyId::FEUR, (1409, 1070))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+}
+
+#[test]
+fn fill_quote_works() {
+	quote_ext().execute_with(|| {
+		<Balances<Runtime>>::insert(
+			ALICE,
+			MOCK_POOL,
+			fixedi128_saturating_from_integer_currency_cent(10_000_00),
+		);
+		let quote = eur_jpy_long_quote();
+		let signature = TestSignature(POOL_OWNER, quote.encode());
+		assert_ok!(MarginProtocol::fill_quote(
+			Origin::signed(ALICE),
+			quote.clone(),
+			POOL_OWNER,
+			signature.clone()
+		));
+
+		// opened at quoted price, without spread
+		let position = MarginProtocol::positions(0).unwrap();
+		assert_eq!(
+			position.leveraged_held,
+			fixedi128_saturating_from_integer_currency_cent(100_000_00)
+		);
+		assert_eq!(
+			position.leveraged_debits,
+			fixedi128_saturating_from_integer_currency_cent(-14_100_000_00)
+		);
+		assert_eq!(MarginProtocol::positions_by_trader(ALICE, (MOCK_POOL, 0)), Some(()));
+		assert_eq!(MarginProtocol::filled_quotes(MOCK_POOL, 0), Some(()));
+
+		let event = TestEvent::margin_protocol(RawEvent::PositionOpened(
+			ALICE,
+			0,
+			MOCK_POOL,
+			EUR_JPY_PAIR,
+			Leverage::LongTwenty,
+			balance_saturating_from_integer_currency_cent(100_000_00),
+			Price::saturating_from_integer(141),
+			0,
+			fixedi128_saturating_from_integer_currency_cent(10_000_00),
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+		let event = TestEvent::margin_protocol(RawEvent::QuoteFilled(ALICE, 0, MOCK_POOL, 0));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_noop!(
+			MarginProtocol::fill_quote(Origin::signed(ALICE), quote, POOL_OWNER, signature),
+			Error::<Runtime>::QuoteAlreadyFilled
+		);
+	});
+}
+
+#[test]
+fn fill_quote_fails_if_invalid_quote() {
+	quote_ext().execute_with(|| {
+		<Balances<Runtime>>::insert(
+			ALICE,
+			MOCK_POOL,
+			fixedi128_saturating_from_integer_currency_cent(10_000_00),
+		);
+		let quote = eur_jpy_long_quote();
+		let signature = TestSignature(POOL_OWNER, quote.encode());
+
+		assert_noop!(
+			MarginProtocol::fill_quote(Origin::signed(BOB), quote.clone(), POOL_OWNER, signature.clone()),
+			Error::<Runtime>::QuoteNotForTrader
+		);
+
+		// not signed by pool owner
+		assert_noop!(
+			MarginProtocol::fill_quote(
+				Origin::signed(ALICE),
+				quote.clone(),
+				ALICE,
+				TestSignature(ALICE, quote.encode())
+			),
+			Error::<Runtime>::InvalidQuoteSignature
+		);
+
+		// quote changed after signed
+		let mut changed = quote.clone();
+		changed.price = Price::saturating_from_integer(100);
+		assert_noop!(
+			MarginProtocol::fill_quote(Origin::signed(ALICE), changed, POOL_OWNER, signature.clone()),
+			Error::<Runtime>::InvalidQuoteSignature
+		);
+
+		System::set_block_number(10);
+		assert_noop!(
+			MarginProtocol::fill_quote(Origin::signed(ALICE), quote, POOL_OWNER, signature),
+			Error::<Runtime>::QuoteExpired
+		);
+	});
+}
+
+fn meta_transaction(intent: TradingIntent, nonce: u64) -> (MetaTransaction<u64, u64>, TestSignature) {
+	let meta_transaction = MetaTransaction {
+		trader: ALICE,
+		intent,
+		expiry: 10,
+		nonce,
+	};
+	let signature = TestSignature(ALICE, meta_transaction.encode());
+	(meta_transaction, signature)
+}
+
+fn eur_jpy_long_intent() -> TradingIntent {
+	TradingIntent::OpenPosition {
+		pool: MOCK_POOL,
+		pair: EUR_JPY_PAIR,
+		leverage: Leverage::LongTwenty,
+		leveraged_amount: balance_saturating_from_integer_currency_cent(100_000_00),
+		price: Price::saturating_from_integer(142),
+	}
+}
+
+#[test]
+fn relay_works() {
+	quote_ext().execute_with(|| {
+		<Balances<Runtime>>::insert(
+			ALICE,
+			MOCK_POOL,
+			fixedi128_saturating_from_integer_currency_cent(10_000_00),
+		);
+		assert_noop!(
+			MarginProtocol::set_relayer_allowance(Origin::signed(BOB), BOB, 2),
+			BadOrigin
+		);
+		assert_ok!(MarginProtocol::set_relayer_allowance(
+			Origin::signed(UpdateOrigin::get()),
+			BOB,
+			2
+		));
+		let event = TestEvent::margin_protocol(RawEvent::RelayerAllowanceSet(BOB, 2));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		let (open, signature) = meta_transaction(eur_jpy_long_intent(), 0);
+		assert_ok!(MarginProtocol::relay(
+			Origin::signed(BOB),
+			open.clone(),
+			signature.clone()
+		));
+
+		// opened by the trader, relayed by BOB
+		assert_eq!(MarginProtocol::positions_by_trader(ALICE, (MOCK_POOL, 0)), Some(()));
+		assert_eq!(MarginProtocol::meta_transaction_nonces(ALICE), 1);
+		assert_eq!(MarginProtocol::relayer_allowances(BOB), 1);
+		let event = TestEvent::margin_protocol(RawEvent::MetaTransactionRelayed(BOB, ALICE, 0));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		// replay
+		assert_noop!(
+			MarginProtocol::relay(Origin::signed(BOB), open, signature),
+			Error::<Runtime>::InvalidMetaTransactionNonce
+		);
+
+		let (close, signature) = meta_transaction(
+			TradingIntent::ClosePosition {
+				position_id: 0,
+				price: Price::zero(),
+			},
+			1,
+		);
+		assert_ok!(MarginProtocol::relay(Origin::signed(BOB), close, signature));
+		assert_eq!(MarginProtocol::positions(0), None);
+		assert_eq!(MarginProtocol::meta_transaction_nonces(ALICE), 2);
+		assert_eq!(MarginProtocol::relayer_allowances(BOB), 0);
+
+		let (open, signature) = meta_transaction(eur_jpy_long_intent(), 2);
+		assert_noop!(
+			MarginProtocol::relay(Origin::signed(BOB), open, signature),
+			Error::<Runtime>::NoRelayerAllowance
+		);
+	});
+}
+
+#[test]
+fn relay_deposit_works() {
+	ExtBuilder::default().alice_balance(1000).build().execute_with(|| {
+		assert_ok!(MarginProtocol::set_relayer_allowance(
+			Origin::signed(UpdateOrigin::get()),
+			BOB,
+			1
+		));
+		let (deposit, signature) = meta_transaction(
+			TradingIntent::Deposit {
+				pool: MOCK_POOL,
+				amount: 500,
+			},
+			0,
+		);
+		assert_ok!(MarginProtocol::relay(Origin::signed(BOB), deposit, signature));
+
+		assert_eq!(LiquidityCurrency::free_balance(&ALICE), 500);
+		assert_eq!(MarginProtocol::balances(&ALICE, MOCK_POOL), FixedI128::from_inner(500));
+		let event = TestEvent::margin_protocol(RawEvent::Deposited(ALICE, MOCK_POOL, 500));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn relay_fails_if_invalid_meta_transaction() {
+	quote_ext().execute_with(|| {
+		<Balances<Runtime>>::insert(
+			ALICE,
+			MOCK_POOL,
+			fixedi128_saturating_from_integer_currency_cent(10_000_00),
+		);
+		let (open, signature) = meta_transaction(eur_jpy_long_intent(), 0);
+
+		// no allowance
+		assert_noop!(
+			MarginProtocol::relay(Origin::signed(BOB), open.clone(), signature.clone()),
+			Error::<Runtime>::NoRelayerAllowance
+		);
+		assert_ok!(MarginProtocol::set_relayer_allowance(
+			Origin::signed(UpdateOrigin::get()),
+			BOB,
+			1
+		));
+
+		// not signed by the trader
+		assert_noop!(
+			MarginProtocol::relay(Origin::signed(BOB), open.clone(), TestSignature(BOB, open.encode())),
+			Error::<Runtime>::InvalidMetaTransactionSignature
+		);
+
+		// meta-transaction changed after signed
+		let mut changed = open.clone();
+		changed.expiry = 20;
+		assert_noop!(
+			MarginProtocol::relay(Origin::signed(BOB), changed, signature.clone()),
+			Error::<Runtime>::InvalidMetaTransactionSignature
+		);
+
+		// nonce not used yet
+		let (skipped, skipped_signature) = meta_transaction(eur_jpy_long_intent(), 1);
+		assert_noop!(
+			MarginProtocol::relay(Origin::signed(BOB), skipped, skipped_signature),
+			Error::<Runtime>::InvalidMetaTransactionNonce
+		);
+
+		System::set_block_number(10);
+		assert_noop!(
+			MarginProtocol::relay(Origin::signed(BOB), open, signature),
+			Error::<Runtime>::MetaTransactionExpired
+		);
+	});
+}
+
+fn open_eur_jpy_long_with_key(key: AccountId, trader: AccountId, leveraged_amount: Balance) -> DispatchResult {
+	MarginProtocol::open_position_with_key(
+		Origin::signed(key),
+		trader,
+		MOCK_POOL,
+		EUR_JPY_PAIR,
+		Leverage::LongTwenty,
+		leveraged_amount,
+		Price::saturating_from_integer(142),
+	)
+}
+
+#[test]
+fn trading_key_works() {
+	quote_ext().execute_with(|| {
+		<Balances<Runtime>>::insert(
+			ALICE,
+			MOCK_POOL,
+			fixedi128_saturating_from_integer_currency_cent(10_000_00),
+		);
+		let limit = balance_saturating_from_integer_currency_cent(100_000_00);
+		assert_ok!(MarginProtocol::authorize_trading_key(
+			Origin::signed(ALICE),
+			BOB,
+			10,
+			limit
+		));
+		let event = TestEvent::margin_protocol(RawEvent::TradingKeyAuthorized(ALICE, BOB, 10, limit));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		// notional of 100_000 EUR is about 131_800 USD
+		assert_noop!(
+			open_eur_jpy_long_with_key(BOB, ALICE, balance_saturating_from_integer_currency_cent(100_000_00)),
+			Error::<Runtime>::TradingKeyLimitExceeded
+		);
+
+		assert_ok!(open_eur_jpy_long_with_key(
+			BOB,
+			ALICE,
+			balance_saturating_from_integer_currency_cent(50_000_00)
+		));
+		assert_eq!(MarginProtocol::positions_by_trader(ALICE, (MOCK_POOL, 0)), Some(()));
+		let used = MarginProtocol::trading_keys(ALICE, BOB).unwrap().notional_used;
+		assert!(used > balance_saturating_from_integer_currency_cent(65_000_00));
+		assert!(used < balance_saturating_from_integer_currency_cent(67_000_00));
+
+		// cumulative notional over the limit
+		assert_noop!(
+			open_eur_jpy_long_with_key(BOB, ALICE, balance_saturating_from_integer_currency_cent(50_000_00)),
+			Error::<Runtime>::TradingKeyLimitExceeded
+		);
+
+		// not a key of the trader
+		assert_noop!(
+			open_eur_jpy_long_with_key(ALICE, BOB, balance_saturating_from_integer_currency_cent(1_00)),
+			Error::<Runtime>::TradingKeyNotFound
+		);
+		assert_noop!(
+			MarginProtocol::close_position(Origin::signed(BOB), 0, Price::zero(), None),
+			Error::<Runtime>::PositionNotOpenedByTrader
+		);
+
+		assert_ok!(MarginProtocol::close_position_with_key(
+			Origin::signed(BOB),
+			ALICE,
+			0,
+			Price::zero()
+		));
+		assert_eq!(MarginProtocol::positions(0), None);
+	});
+}
+
+#[test]
+fn trading_key_is_charged_opened_notional_of_netted_positions() {
+	quote_ext().execute_with(|| {
+		<Balances<Runtime>>::insert(
+			ALICE,
+			MOCK_POOL,
+			fixedi128_saturating_from_integer_currency_cent(10_000_00),
+		);
+		assert_ok!(MarginProtocol::set_position_netting(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			true
+		));
+		assert_ok!(MarginProtocol::authorize_trading_key(
+			Origin::signed(ALICE),
+			BOB,
+			10,
+			balance_saturating_from_integer_currency_cent(100_000_00)
+		));
+
+		assert_ok!(open_eur_jpy_long_with_key(
+			BOB,
+			ALICE,
+			balance_saturating_from_integer_currency_cent(20_000_00)
+		));
+		assert_ok!(open_eur_jpy_long_with_key(
+			BOB,
+			ALICE,
+			balance_saturating_from_integer_currency_cent(10_000_00)
+		));
+		assert_eq!(MarginProtocol::positions(1), None);
+
+		// notional of 30_000 EUR is about 39_540 USD, the netted position is not charged again
+		let used = MarginProtocol::trading_keys(ALICE, BOB).unwrap().notional_used;
+		assert!(used > balance_saturating_from_integer_currency_cent(39_000_00));
+		assert!(used < balance_saturating_from_integer_currency_cent(40_000_00));
+	});
+}
+
+#[test]
+fn trading_key_could_be_revoked_or_expire() {
+	quote_ext().execute_with(|| {
+		<Balances<Runtime>>::insert(
+			ALICE,
+			MOCK_POOL,
+			fixedi128_saturating_from_integer_currency_cent(10_000_00),
+		);
+		let limit = balance_saturating_from_integer_currency_cent(100_000_00);
+		assert_ok!(MarginProtocol::authorize_trading_key(
+			Origin::signed(ALICE),
+			BOB,
+			10,
+			limit
+		));
+
+		assert_ok!(MarginProtocol::revoke_trading_key(Origin::signed(ALICE), BOB));
+		assert_eq!(MarginProtocol::trading_keys(ALICE, BOB), None);
+		let event = TestEvent::margin_protocol(RawEvent::TradingKeyRevoked(ALICE, BOB));
+		assert!(System::events().iter().any(|record| record.event == event));
+		assert_noop!(
+			open_eur_jpy_long_with_key(BOB, ALICE, balance_saturating_from_integer_currency_cent(1_00)),
+			Error::<Runtime>::TradingKeyNotFound
+		);
+		assert_noop!(
+			MarginProtocol::revoke_trading_key(Origin::signed(ALICE), BOB),
+			Error::<Runtime>::TradingKeyNotFound
+		);
+
+		assert_ok!(MarginProtocol::authorize_trading_key(
+			Origin::signed(ALICE),
+			BOB,
+			10,
+			limit
+		));
+		System::set_block_number(10);
+		assert_noop!(
+			open_eur_jpy_long_with_key(BOB, ALICE, balance_saturating_from_integer_currency_cent(1_00)),
+			Error::<Runtime>::TradingKeyExpired
+		);
+		assert_noop!(
+			MarginProtocol::close_position_with_key(Origin::signed(BOB), ALICE, 0, Price::zero()),
+			Error::<Runtime>::TradingKeyExpired
+		);
+	});
+}
+
+#[test]
+fn open_position_fails_if_trader_margin_called() {
+	ExtBuilder::default()
+		// USD/JPY = 107
+		.price(CurrencyId::FJPY, (1, 107))
+		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
+		.price(CurrencyId::FEUR, (1409, 1070))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			<MarginCalledTraders<Runtime>>::insert(ALICE, MOCK_POOL, ());
+			assert_noop!(
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(100_000_00),
+					Price::saturating_from_integer(142),
+					None
+				),
+				Error::<Runtime>::MarginCalledTrader
+			);
+		});
+}
+
+#[test]
+fn open_position_fails_if_pool_margin_called() {
+	ExtBuilder::default()
+		// USD/JPY = 107
+		.price(CurrencyId::FJPY, (1, 107))
+		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
+		.price(CurrencyId::FEUR, (1409, 1070))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			MarginCalledPools::insert(MOCK_POOL, ());
+			assert_noop!(
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(100_000_00),
+					Price::saturating_from_integer(142),
+					None
+				),
+				Error::<Runtime>::MarginCalledPool
+			);
+		});
+}
+
+#[test]
+fn open_position_fails_if_no_base_price() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (1409, 1070))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_noop!(
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(100_000_00),
+					Price::saturating_from_integer(142),
+					None
+				),
+				Error::<Runtime>::NoPrice
+			);
+		});
+}
+
+#[test]
+fn open_position_fails_if_no_quote_price() {
+	ExtBuilder::default()
+		.price(CurrencyId::FJPY, (1, 107))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_noop!(
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(100_000_00),
+					Price::saturating_from_integer(142),
+					None
+				),
+				Error::<Runtime>::NoPrice
+			);
+		});
+}
+
+#[test]
+fn open_position_fails_with_price_unavailable_reason() {
+	ExtBuilder::default()
+		.price(CurrencyId::FJPY, (1, 107))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			let open_position = || {
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(100_000_00),
+					Price::saturating_from_integer(142),
+					None,
+				)
+			};
+
+			MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, Some(PriceUnavailableReason::StalePrice));
+			assert_noop!(open_position(), Error::<Runtime>::StalePrice);
+
+			MockPriceStatus::set_mock_unavailable_reason(
+				CurrencyId::FEUR,
+				Some(PriceUnavailableReason::BelowOracleQuorum),
+			);
+			assert_noop!(open_position(), Error::<Runtime>::BelowOracleQuorum);
+
+			// base reason comes first
+			MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FJPY, Some(PriceUnavailableReason::MarketHalted));
+			assert_noop!(open_position(), Error::<Runtime>::BelowOracleQuorum);
+
+			MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, None);
+			assert_noop!(open_position(), Error::<Runtime>::MarketHalted);
+		});
+}
+
+#[test]
+fn open_position_fails_if_market_closed() {
+	ExtBuilder::default()
+		.price(CurrencyId::FJPY, (1, 107))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			let open_position = || {
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(100_000_00),
+					Price::saturating_from_integer(142),
+					None,
+				)
+			};
+
+			MockMarketCalendar::set_mock_market_closed(EUR_JPY_PAIR, true);
+			assert_noop!(open_position(), Error::<Runtime>::MarketClosed);
+
+			MockMarketCalendar::set_mock_market_closed(EUR_JPY_PAIR, false);
+			assert_ok!(open_position());
+		});
+}
+
+#[test]
+fn close_position_fails_if_market_closed() {
+	ExtBuilder::default()
+		.price(CurrencyId::FJPY, (1, 107))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_JPY_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(100_000_00),
+				Price::saturating_from_integer(142),
+				None,
+			));
+
+			MockMarketCalendar::set_mock_market_closed(EUR_JPY_PAIR, true);
+			assert_noop!(
+				MarginProtocol::close_position(Origin::signed(ALICE), 0, Price::zero(), None),
+				Error::<Runtime>::MarketClosed
+			);
+			assert_noop!(
+				MarginProtocol::close_position_partial(
+					Origin::signed(ALICE),
+					0,
+					Permill::from_percent(50),
+					Price::zero()
+				),
+				Error::<Runtime>::MarketClosed
+			);
+			assert_noop!(
+				MarginProtocol::close_positions(Origin::signed(ALICE), vec![(0, Price::zero())]),
+				Error::<Runtime>::MarketClosed
+			);
+			assert_noop!(
+				MarginProtocol::close_all_positions(Origin::signed(ALICE), MOCK_POOL),
+				Error::<Runtime>::MarketClosed
+			);
+
+			MockMarketCalendar::set_mock_market_closed(EUR_JPY_PAIR, false);
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				0,
+				Price::zero(),
+				None
+			));
+			assert_eq!(MarginProtocol::positions(0), None);
+		});
+}
+
+#[test]
+fn open_position_fails_if_leverage_above_trader_max() {
+	ExtBuilder::default()
+		.price(CurrencyId::FJPY, (1, 107))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			let open_position = |leverage| {
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					leverage,
+					balance_saturating_from_integer_currency_cent(10_000_00),
+					Price::saturating_from_integer(142),
+					None,
+				)
+			};
+
+			MockLiquidityPools::set_mock_trader_max_leverage(Some(10));
+			assert_noop!(
+				open_position(Leverage::LongTwenty),
+				Error::<Runtime>::LeverageAboveTraderMax
+			);
+			assert_ok!(open_position(Leverage::LongTen));
+
+			MockLiquidityPools::set_mock_trader_max_leverage(None);
+			assert_ok!(open_position(Leverage::LongTwenty));
+		});
+}
+
+#[test]
+fn set_trader_tier_option_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		let option = TraderTierOption {
+			max_leverage: Some(10),
+			risk_threshold: Some(risk_threshold(50, 20)),
+		};
+		assert_noop!(
+			MarginProtocol::set_trader_tier_option(Origin::signed(BOB), TraderTier::Retail, option),
+			BadOrigin
+		);
+
+		assert_ok!(MarginProtocol::set_trader_tier_option(
+			Origin::signed(UpdateOrigin::get()),
+			TraderTier::Retail,
+			option
+		));
+		assert_eq!(MarginProtocol::trader_tier_option(TraderTier::Retail), option);
+		assert_eq!(
+			MarginProtocol::trader_tier_option(TraderTier::Professional),
+			TraderTierOption::default()
+		);
+		let event = TestEvent::margin_protocol(RawEvent::TraderTierOptionSet(TraderTier::Retail, option));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		// the default option removes it
+		assert_ok!(MarginProtocol::set_trader_tier_option(
+			Origin::signed(UpdateOrigin::get()),
+			TraderTier::Retail,
+			TraderTierOption::default()
+		));
+		assert!(!TraderTierOptions::contains_key(TraderTier::Retail));
+	});
+}
+
+#[test]
+fn open_position_fails_if_leverage_above_tier_max() {
+	ExtBuilder::default()
+		.price(CurrencyId::FJPY, (1, 107))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			let open_position = |leverage| {
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					leverage,
+					balance_saturating_from_integer_currency_cent(10_000_00),
+					Price::saturating_from_integer(142),
+					None,
+				)
+			};
+
+			assert_ok!(MarginProtocol::set_trader_tier_option(
+				Origin::signed(UpdateOrigin::get()),
+				TraderTier::Retail,
+				TraderTierOption {
+					max_leverage: Some(10),
+					risk_threshold: None,
+				}
+			));
+			assert_noop!(
+				open_position(Leverage::LongTwenty),
+				Error::<Runtime>::LeverageAboveTierMax
+			);
+			assert_ok!(open_position(Leverage::LongTen));
+
+			// professional traders are not capped by the retail tier
+			MockTraderTiers::set_mock_tier(ALICE, TraderTier::Professional);
+			assert_ok!(open_position(Leverage::LongTwenty));
+		});
+}
+
+#[test]
+fn tier_risk_threshold_applies() {
+	ExtBuilder::default().build().execute_with(|| {
+		set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(10, 5));
+		assert_ok!(MarginProtocol::set_trader_tier_option(
+			Origin::signed(UpdateOrigin::get()),
+			TraderTier::Retail,
+			TraderTierOption {
+				max_leverage: None,
+				risk_threshold: Some(risk_threshold(50, 3)),
+			}
+		));
+
+		// the higher one of each threshold of the trading pair and the tier applies
+		assert_eq!(
+			MarginProtocol::risk_params(&ALICE, MOCK_POOL, EUR_USD_PAIR).trader_threshold,
+			risk_threshold(50, 5)
+		);
+
+		MockTraderTiers::set_mock_tier(ALICE, TraderTier::Professional);
+		assert_eq!(
+			MarginProtocol::risk_params(&ALICE, MOCK_POOL, EUR_USD_PAIR).trader_threshold,
+			risk_threshold(10, 5)
+		);
+	});
+}
+
+#[test]
+fn commission_charged_on_open_and_close() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		.price(CurrencyId::FEUR, (1, 1))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.module_balance(fixedi128_saturating_from_integer_currency_cent(1_010_00))
+		.build()
+		.execute_with(|| {
+			MockLiquidityPools::set_mock_commission(Permill::from_rational_approximation(1, 1000u32));
+			let open_position = || {
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					Leverage::LongTen,
+					balance_saturating_from_integer_currency_cent(10_000_00),
+					Price::max_value(),
+					None,
+				)
+			};
+
+			// free margin must cover the commission besides the margin held
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(1_005_00),
+			);
+			assert_noop!(open_position(), Error::<Runtime>::InsufficientFreeMargin);
+
+			// 0.1% of 10_000 dollars notional
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(1_010_00),
+			);
+			assert_ok!(open_position());
+			assert_eq!(
+				MarginProtocol::balances(ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(1_000_00)
+			);
+			assert_eq!(
+				MockLiquidityPools::liquidity(MOCK_POOL),
+				balance_saturating_from_integer_currency_cent(100_010_00)
+			);
+			let event = TestEvent::margin_protocol(RawEvent::PositionOpened(
+				ALICE,
+				0,
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTen,
+				balance_saturating_from_integer_currency_cent(10_000_00),
+				Price::saturating_from_integer(1),
+				balance_saturating_from_integer_currency_cent(10_00),
+				fixedi128_saturating_from_integer_currency_cent(1_000_00),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			// 0.1% of 12_000 dollars notional at the close price, after realizing 2_000 dollars profit
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(12, 10)));
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				0,
+				Price::zero(),
+				None
+			));
+			assert_eq!(
+				MarginProtocol::balances(ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(2_988_00)
+			);
+			assert_eq!(
+				MockLiquidityPools::liquidity(MOCK_POOL),
+				balance_saturating_from_integer_currency_cent(98_022_00)
+			);
+			let event = TestEvent::margin_protocol(RawEvent::PositionClosed(
+				ALICE,
+				0,
+				MOCK_POOL,
+				Price::saturating_from_rational(12, 10),
+				balance_saturating_from_integer_currency_cent(12_00),
+				FixedI128::zero(),
+				fixedi128_saturating_from_integer_currency_cent(2_000_00),
+				fixedi128_saturating_from_integer_currency_cent(2_988_00),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
+		});
+}
+
+#[test]
+fn margin_tiers_work() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		.price(CurrencyId::FEUR, (1, 1))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			let tier = |threshold, max_leverage| MarginTier {
+				notional_threshold: balance_saturating_from_integer_currency_cent(threshold),
+				max_leverage,
+			};
+			let tiers = vec![tier(1_000_00, 5), tier(10_000_00, 2)];
+			assert_noop!(
+				MarginProtocol::set_margin_tiers(Origin::signed(BOB), EUR_USD_PAIR, tiers.clone()),
+				BadOrigin
+			);
+			assert_noop!(
+				MarginProtocol::set_margin_tiers(
+					Origin::signed(UpdateOrigin::get()),
+					EUR_USD_PAIR,
+					vec![tier(1_000_00, 0)]
+				),
+				Error::<Runtime>::InvalidMarginTier
+			);
+			assert_ok!(MarginProtocol::set_margin_tiers(
+				Origin::signed(UpdateOrigin::get()),
+				EUR_USD_PAIR,
+				tiers.clone()
+			));
+			assert_eq!(MarginProtocol::margin_tiers(EUR_USD_PAIR), tiers.clone());
+			let event = TestEvent::margin_protocol(RawEvent::MarginTiersSet(EUR_USD_PAIR, tiers));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			let open_position = |leverage, amount| {
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					leverage,
+					balance_saturating_from_integer_currency_cent(amount),
+					Price::max_value(),
+					None,
+				)
+			};
+			let margin_held = |id| MarginProtocol::positions(id).unwrap().margin_held;
+
+			// not above any threshold, held at the position leverage
+			assert_ok!(open_position(Leverage::LongTen, 1_000_00));
+			assert_eq!(margin_held(0), fixedi128_saturating_from_integer_currency_cent(100_00));
+
+			// above 1_000 dollars, held at 5x
+			assert_ok!(open_position(Leverage::LongTen, 5_000_00));
+			assert_eq!(
+				margin_held(1),
+				fixedi128_saturating_from_integer_currency_cent(1_000_00)
+			);
+
+			// leverage below the tier max is kept
+			assert_ok!(open_position(Leverage::LongTwo, 2_000_00));
+			assert_eq!(
+				margin_held(2),
+				fixedi128_saturating_from_integer_currency_cent(1_000_00)
+			);
+
+			// above 10_000 dollars, held at 2x, beyond the free margin
+			assert_noop!(
+				open_position(Leverage::LongTen, 20_000_00),
+				Error::<Runtime>::InsufficientFreeMargin
+			);
+
+			// no tiers
+			assert_ok!(MarginProtocol::set_margin_tiers(
+				Origin::signed(UpdateOrigin::get()),
+				EUR_USD_PAIR,
+				vec![]
+			));
+			assert!(!MarginTiers::contains_key(EUR_USD_PAIR));
+			assert_ok!(open_position(Leverage::LongTen, 20_000_00));
+			assert_eq!(
+				margin_held(3),
+				fixedi128_saturating_from_integer_currency_cent(2_000_00)
+			);
+		});
+}
+
+#[test]
+fn open_long_position_fails_if_market_price_too_high() {
+	ExtBuilder::default()
+		// USD/JPY = 107
+		.price(CurrencyId::FJPY, (1, 107))
+		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
+		.price(CurrencyId::FEUR, (1409, 1070))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_noop!(
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(100_000_00),
+					Price::saturating_from_integer(141),
+					None
+				),
+				Error::<Runtime>::MarketPriceTooHigh
+			);
+		});
+}
+
+#[test]
+fn open_and_close_position_fail_after_valid_until() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(10_00));
+			System::set_block_number(10);
+			let open = |valid_until| {
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					Leverage::LongTwo,
+					balance_saturating_from_integer_currency_cent(10_00),
+					Price::max_value(),
+					valid_until,
+				)
+			};
+
+			assert_noop!(open(Some(9)), Error::<Runtime>::TransactionOutdated);
+			assert_ok!(open(Some(10)));
+			assert!(MarginProtocol::positions(0).is_some());
+
+			System::set_block_number(11);
+			assert_noop!(
+				MarginProtocol::close_position(Origin::signed(ALICE), 0, Price::zero(), Some(10)),
+				Error::<Runtime>::TransactionOutdated
+			);
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				0,
+				Price::zero(),
+				Some(11)
+			));
+			assert_eq!(MarginProtocol::positions(0), None);
+		});
+}
+
+#[test]
+fn closed_positions_are_recorded_in_trade_history() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(100_00),
+			);
+			for block_number in 1..4 {
+				System::set_block_number(block_number);
+				assert_ok!(MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					Leverage::LongTwo,
+					balance_saturating_from_integer_currency_cent(10_00),
+					Price::max_value(),
+					None
+				));
+			}
+			assert_eq!(MarginProtocol::position_opened_at(2), Some(3));
+
+			System::set_block_number(5);
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(Price::saturating_from_rational(11, 10)));
+			for position_id in 0..3 {
+				assert_ok!(MarginProtocol::close_position(
+					Origin::signed(ALICE),
+					position_id,
+					Price::zero(),
+					None
+				));
+			}
+			assert_eq!(MarginProtocol::position_opened_at(2), None);
+
+			// only the latest 2 kept
+			let history = MarginProtocol::trade_history(ALICE);
+			assert_eq!(history.len(), 2);
+			assert_eq!(history[0].position_id, 1);
+			assert_eq!(
+				history[1],
+				TradeRecord {
+					position_id: 2,
+					pool: MOCK_POOL,
+					pair: EUR_USD_PAIR,
+					leverage: Leverage::LongTwo,
+					leveraged_held: fixedi128_saturating_from_integer_currency_cent(10_00),
+					open_price: Price::saturating_from_integer(1),
+					close_price: Price::saturating_from_rational(11, 10),
+					swap: FixedI128::zero(),
+					realized_pl: fixedi128_saturating_from_integer_currency_cent(1_00),
+					opened_at: Some(3),
+					closed_at: 5,
+				}
+			);
+			assert_eq!(
+				MarginProtocol::cumulative_realized_pl(ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(3_00)
+			);
+		});
+}
+
+#[test]
+fn open_short_position_fails_if_market_price_too_low() {
+	ExtBuilder::default()
+		// USD/JPY = 106
+		.price(CurrencyId::FJPY, (1, 106))
+		// EUR/JPY = 141.9 => EUR/USD = 141.9/106
+		.price(CurrencyId::FEUR, (1419, 1060))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_noop!(
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::ShortTwenty,
+					balance_saturating_from_integer_currency_cent(100_000_00),
+					Price::saturating_from_integer(142),
+					None
+				),
+				Error::<Runtime>::MarketPriceTooLow
+			);
+		});
+}
+
+#[test]
+fn open_position_fails_if_leveraged_debits_out_of_bound() {
+	ExtBuilder::default()
+		.price(CurrencyId::FJPY, (1, 1))
+		.price(CurrencyId::FEUR, (2, 1))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, Balance::max_value())
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, FixedI128::max_value());
+			assert_noop!(
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::LongTwenty,
+					u128::max_value() / 2 + 1,
+					Price::saturating_from_integer(142),
+					None
+				),
+				Error::<Runtime>::NumOutOfBound
+			);
+		});
+}
+
+#[test]
+fn open_position_fails_if_insufficient_free_margin() {
+	ExtBuilder::default()
+		.price(CurrencyId::FJPY, (1, 1))
+		.price(CurrencyId::FEUR, (2, 1))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, Balance::max_value())
+		.build()
+		.execute_with(|| {
+			assert_noop!(
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::LongTwo,
+					1,
+					Price::saturating_from_integer(142),
+					None
+				),
+				Error::<Runtime>::InsufficientFreeMargin
+			);
+		});
+}
+
+#[test]
+fn open_position_fails_if_would_reach_enp_threshold() {
+	ExtBuilder::default()
+		// USD/JPY = 107
+		.price(CurrencyId::FJPY, (1, 107))
+		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
+		.price(CurrencyId::FEUR, (1409, 1070))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(659_00))
+		.build()
+		.execute_with(|| {
+			set_enp_risk_threshold(EUR_JPY_PAIR, risk_threshold(10, 5));
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_noop!(
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(100_000_00),
+					Price::saturating_from_integer(142),
+					None
+				),
+				Error::<Runtime>::PoolWouldBeUnsafe
+			);
+		});
+}
+
+#[test]
+fn open_position_fails_if_would_reach_ell_threshold() {
+	ExtBuilder::default()
+		// USD/JPY = 107
+		.price(CurrencyId::FJPY, (1, 107))
+		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
+		.price(CurrencyId::FEUR, (1409, 1070))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(659_00))
+		.build()
+		.execute_with(|| {
+			set_ell_risk_threshold(EUR_JPY_PAIR, risk_threshold(10, 5));
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_noop!(
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(100_000_00),
+					Price::saturating_from_integer(142),
+					None
+				),
+				Error::<Runtime>::PoolWouldBeUnsafe
+			);
+		});
+}
+
+#[test]
+fn open_position_fails_if_run_out_of_position_id() {
+	ExtBuilder::default()
+		// USD/JPY = 107
+		.price(CurrencyId::FJPY, (1, 107))
+		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
+		.price(CurrencyId::FEUR, (1409, 1070))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			NextPositionId::put(PositionId::max_value());
+			assert_noop!(
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(100_000_00),
+					Price::saturating_from_integer(142),
+					None
+				),
+				Error::<Runtime>::NoAvailablePositionId
+			);
+		});
+}
+
+#[test]
+fn remove_position_only_removes_its_indexes() {
+	ExtBuilder::default().build().execute_with(|| {
+		for _ in 0..3 {
+			assert_ok!(MarginProtocol::insert_position(
+				&ALICE,
+				MOCK_POOL,
+				EUR_JPY_PAIR,
+				eur_jpy_long()
+			));
+		}
+
+		assert_ok!(MarginProtocol::remove_position(&ALICE, 1, &eur_jpy_long()));
+		assert_eq!(MarginProtocol::positions(1), None);
+		assert_eq!(MarginProtocol::positions_by_trader(ALICE, (MOCK_POOL, 1)), None);
+		assert_eq!(MarginProtocol::positions_by_pool(MOCK_POOL, (EUR_JPY_PAIR, 1)), None);
+		for &id in [0, 2].iter() {
+			assert_eq!(MarginProtocol::positions(id), Some(eur_jpy_long()));
+			assert_eq!(MarginProtocol::positions_by_trader(ALICE, (MOCK_POOL, id)), Some(()));
+			assert_eq!(MarginProtocol::positions_by_pool(MOCK_POOL, (EUR_JPY_PAIR, id)), Some(()));
+		}
+		assert_eq!(MarginProtocol::pool_positions_snapshots(MOCK_POOL, EUR_JPY_PAIR).positions_count, 2);
+	});
+}
+
+#[test]
+fn free_margin_cannot_be_used_across_pool() {
+	ExtBuilder::default()
+		// USD/JPY = 107
+		.price(CurrencyId::FJPY, (1, 107))
+		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
+		.price(CurrencyId::FEUR, (1409, 1070))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL_1,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			assert_noop!(
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					Leverage::LongTwenty,
+					balance_saturating_from_integer_currency_cent(100_000_00),
+					Price::saturating_from_integer(142),
+					None
+				),
+				Error::<Runtime>::InsufficientFreeMargin
+			);
+		});
+}
+
+#[test]
+fn hedged_positions_require_max_of_long_and_short_margin() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
+		.price(CurrencyId::FEUR, (1, 1))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(60_00));
+			let open = |leverage: Leverage, price: Price| {
+				MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					leverage,
+					balance_saturating_from_integer_currency_cent(100_00),
+					price,
+					None,
+				)
+			};
+
+			// margin held 50
+			assert_ok!(open(Leverage::LongTwo, Price::saturating_from_integer(2)));
+			assert_eq!(
+				MarginProtocol::free_margin(&ALICE, MOCK_POOL),
+				Ok(fixedi128_saturating_from_integer_currency_cent(10_00))
+			);
+
+			// offsetting short position, margin held 50 is released
+			assert_ok!(open(Leverage::ShortTwo, Price::saturating_from_rational(1, 2)));
+			assert_eq!(
+				MarginProtocol::margin_held(&ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(100_00)
+			);
+			assert_eq!(
+				MarginProtocol::free_margin(&ALICE, MOCK_POOL),
+				Ok(fixedi128_saturating_from_integer_currency_cent(10_00))
+			);
+
+			// not offsetting
+			assert_noop!(
+				open(Leverage::LongTwo, Price::saturating_from_integer(2)),
+				Error::<Runtime>::InsufficientFreeMargin
+			);
+		});
+}
+
+#[test]
+fn close_loss_position_works() {
+	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
+	ExtBuilder::default()
+		.module_balance(alice_initial)
+		// EUR/USD = 1.2
+		.price(CurrencyId::FEUR, (12, 10))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
+
+			let position = eur_usd_long_1();
+			let id = 0;
+			<Positions<Runtime>>::insert(id, position.clone());
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+
+			let snapshot = positions_snapshot(
+				1,
+				position.leveraged_held,
+				position.leveraged_debits,
+				FixedI128::saturating_from_integer(0),
+				FixedI128::saturating_from_integer(0),
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot.clone());
+
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				id,
+				Price::saturating_from_rational(11, 10),
+				None
+			));
+
+			// realized math
+			assert_eq!(
+				MarginProtocol::balances(ALICE, MOCK_POOL),
+				FixedI128::from_inner(9415301035390000000000)
+			);
+			assert_eq!(MockLiquidityPools::liquidity(MOCK_POOL), 100584698964610000000000);
+			assert_eq!(
+				LiquidityCurrency::free_balance(&MarginProtocol::account_id()),
+				9415301035390000000000
+			);
+
+			// position removed
+			assert!(MarginProtocol::positions(id).is_none());
+			assert_eq!(MarginProtocol::positions_by_trader(ALICE, (MOCK_POOL, id)), None);
+			assert_eq!(MarginProtocol::positions_by_pool(MOCK_POOL, (EUR_USD_PAIR, id)), None);
+
+			let event = TestEvent::margin_protocol(RawEvent::PositionClosed(
+				ALICE,
+				id,
+				MOCK_POOL,
+				Price::saturating_from_rational(11988, 10000),
+				0,
+				FixedI128::from_inner(-44398964610000000000),
+				FixedI128::from_inner(-584698964610000000000),
+				FixedI128::from_inner(9415301035390000000000),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
+		});
+}
+
+#[test]
+fn close_loss_position_realizing_part_on_not_enough_equity() {
+	ExtBuilder::default()
+		.module_balance(fixedi128_saturating_from_integer_currency_cent(1_00))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(1_000_00))
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (10, 1))
+		.build()
+		.execute_with(|| {
+			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 0));
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(1_00));
+			let position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				leverage: Leverage::LongTen,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(10_00),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100_00),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(1_00),
+			};
+			<Positions<Runtime>>::insert(0, position.clone());
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			let snapshot = positions_snapshot(
+				1,
+				position.leveraged_held,
+				position.leveraged_debits,
+				FixedI128::saturating_from_integer(0),
+				FixedI128::saturating_from_integer(0),
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot.clone());
+
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(Price::saturating_from_rational(1, 1)));
+
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				0,
+				Price::saturating_from_integer(0),
+				None
+			));
+			assert_eq!(
+				MockLiquidityPools::liquidity(MOCK_POOL),
+				balance_saturating_from_integer_currency_cent(1_001_00)
+			);
+			assert_eq!(MarginProtocol::balances(&ALICE, MOCK_POOL), FixedI128::zero());
+		});
+}
+
+#[test]
+fn close_loss_position_owning_and_repayment() {
+	ExtBuilder::default()
+		.module_balance(fixedi128_saturating_from_integer_currency_cent(2_00))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(1_000_00))
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.accumulated_swap_rate(JPY_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (10, 1))
+		.price(CurrencyId::FJPY, (10, 1))
+		.build()
+		.execute_with(|| {
+			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 0));
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(2_00));
+
+			// position with 90 dollars loss
+			let loss_position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				leverage: Leverage::LongTen,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(10_00),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100_00),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(1_00),
+			};
+			// position with 45 dollars profit
+			let profit_position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: JPY_USD_PAIR,
+				leverage: Leverage::LongFive,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(5_00),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-5_00),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(1_00),
+			};
+			<Positions<Runtime>>::insert(0, loss_position.clone());
+			<Positions<Runtime>>::insert(1, profit_position.clone());
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 1), ());
+
+			PositionsSnapshots::insert(
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				positions_snapshot(
+					1,
+					loss_position.leveraged_held,
+					loss_position.leveraged_debits,
+					FixedI128::saturating_from_integer(0),
+					FixedI128::saturating_from_integer(0),
+				),
+			);
+			PositionsSnapshots::insert(
+				MOCK_POOL,
+				JPY_USD_PAIR,
+				positions_snapshot(
+					1,
+					profit_position.leveraged_held,
+					profit_position.leveraged_debits,
+					FixedI128::saturating_from_integer(0),
+					FixedI128::saturating_from_integer(0),
+				),
+			);
+
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(Price::saturating_from_rational(1, 1)));
+			assert!(MarginProtocol::equity_of_trader(&ALICE, MOCK_POOL).unwrap() < FixedI128::zero());
+
+			// Balance: FixedI128(2.000000000000000000)
+			// Free margin: Ok(FixedI128(-45.000000000000000000))
+			// Unrealized PL: Ok(FixedI128(-45.000000000000000000))
+			// Equity: Ok(FixedI128(-43.000000000000000000))
+			// Margin level: Ok(FixedI128(-0.409523809523809523))
+
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				0,
+				Price::saturating_from_integer(0),
+				None
+			));
+
+			// realizable = $47; $2 paid to pool; -$45 owning.
+			assert_eq!(
+				MockLiquidityPools::liquidity(MOCK_POOL),
+				balance_saturating_from_integer_currency_cent(1_002_00)
+			);
+			assert_eq!(
+				MarginProtocol::balances(&ALICE, MOCK_POOL),
+				FixedI128::saturating_from_integer(-45),
+			);
+
+			// realizable = $45; $45 repayment.
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				1,
+				Price::saturating_from_integer(0),
+				None
+			));
+			assert_eq!(
+				MockLiquidityPools::liquidity(MOCK_POOL),
+				balance_saturating_from_integer_currency_cent(1_002_00)
+			);
+			assert_eq!(MarginProtocol::balances(&ALICE, MOCK_POOL), FixedI128::zero(),);
+		});
+}
+
+#[test]
+fn close_profit_position_works() {
+	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
+	ExtBuilder::default()
+		.module_balance(alice_initial)
+		// EUR/USD = 1.2
+		.price(CurrencyId::FEUR, (12, 10))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
+
+			let position = eur_usd_long_2();
+			let id = 0;
+			<Positions<Runtime>>::insert(id, position.clone());
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+			let snapshot = positions_snapshot(
+				1,
+				position.leveraged_held,
+				position.leveraged_debits,
+				FixedI128::saturating_from_integer(0),
+				FixedI128::saturating_from_integer(0),
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot.clone());
+			assert_eq!(
+				MarginProtocol::pool_positions_snapshots(MOCK_POOL, EUR_USD_PAIR),
+				snapshot
+			);
+
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				id,
+				Price::saturating_from_rational(11, 10),
+				None
+			));
+
+			let snapshot = positions_snapshot(
+				0,
+				FixedI128::saturating_from_integer(0),
+				FixedI128::saturating_from_integer(0),
+				FixedI128::saturating_from_integer(0),
+				FixedI128::saturating_from_integer(0),
+			);
+			assert_eq!(
+				MarginProtocol::pool_positions_snapshots(MOCK_POOL, EUR_USD_PAIR),
+				snapshot
+			);
+			assert_eq!(
+				MarginProtocol::enp_and_ell_with_action(MOCK_POOL, Action::None),
+				Ok((FixedI128::max_value(), FixedI128::max_value(),))
+			);
+			assert_eq!(
+				MarginProtocol::balances(ALICE, MOCK_POOL),
+				FixedI128::from_inner(10438691023010000000000)
+			);
+			assert_eq!(MockLiquidityPools::liquidity(MOCK_POOL), 99561308976990000000000);
+			assert_eq!(
+				LiquidityCurrency::free_balance(&MarginProtocol::account_id()),
+				10438691023010000000000
+			);
+		});
+}
+
+#[test]
+fn close_position_partial_works() {
+	stop_out_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		let half = Permill::from_percent(50);
+		let price = Price::saturating_from_integer(1);
+		assert_noop!(
+			MarginProtocol::close_position_partial(Origin::signed(BOB), 0, half, price),
+			Error::<Runtime>::PositionNotOpenedByTrader
+		);
+		assert_noop!(
+			MarginProtocol::close_position_partial(Origin::signed(ALICE), 0, Permill::zero(), price),
+			Error::<Runtime>::InvalidCloseFraction
+		);
+		assert_noop!(
+			MarginProtocol::close_position_partial(Origin::signed(ALICE), 0, Permill::one(), price),
+			Error::<Runtime>::InvalidCloseFraction
+		);
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(110, 100)));
+		assert_ok!(MarginProtocol::close_position_partial(
+			Origin::signed(ALICE),
+			0,
+			half,
+			price
+		));
+
+		// 50 cents closed with 5 cents profit
+		assert_eq!(
+			MarginProtocol::balances(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(105)
+		);
+		assert_eq!(
+			MockLiquidityPools::liquidity(MOCK_POOL),
+			balance_saturating_from_integer_currency_cent(95)
+		);
+		let position = MarginProtocol::positions(0).unwrap();
+		assert_eq!(
+			position.leveraged_held,
+			fixedi128_saturating_from_integer_currency_cent(50)
+		);
+		assert_eq!(
+			position.leveraged_debits,
+			fixedi128_saturating_from_integer_currency_cent(-50)
+		);
+		assert_eq!(
+			position.margin_held,
+			fixedi128_saturating_from_integer_currency_cent(25)
+		);
+		assert_eq!(
+			MarginProtocol::margin_held(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(25)
+		);
+		assert_eq!(
+			MarginProtocol::pool_positions_snapshots(MOCK_POOL, EUR_USD_PAIR),
+			positions_snapshot(
+				1,
+				fixedi128_saturating_from_integer_currency_cent(50),
+				fixedi128_saturating_from_integer_currency_cent(-50),
+				FixedI128::zero(),
+				FixedI128::zero(),
+			)
+		);
+		assert_eq!(
+			MarginProtocol::stop_loss(0),
+			Some(Price::saturating_from_rational(95, 100))
+		);
+		let event = TestEvent::margin_protocol(RawEvent::PositionPartiallyClosed(
+			ALICE,
+			0,
+			MOCK_POOL,
+			half,
+			Price::saturating_from_rational(110, 100),
+			0,
+			FixedI128::zero(),
+			fixedi128_saturating_from_integer_currency_cent(5),
+			fixedi128_saturating_from_integer_currency_cent(105),
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn close_positions_works() {
+	order_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		let position = MarginProtocol::positions(0).unwrap();
+		assert_eq!(
+			MarginProtocol::insert_position(&ALICE, MOCK_POOL, EUR_USD_PAIR, position),
+			Ok(1)
+		);
+		let price = Price::saturating_from_integer(1);
+		assert_noop!(
+			MarginProtocol::close_positions(Origin::signed(BOB), vec![(0, price), (1, price)]),
+			Error::<Runtime>::PositionNotOpenedByTrader
+		);
+		assert_noop!(
+			MarginProtocol::close_positions(Origin::signed(ALICE), vec![(0, price), (2, price)]),
+			Error::<Runtime>::PositionNotFound
+		);
+		assert_noop!(
+			MarginProtocol::close_positions(Origin::signed(ALICE), vec![(0, price); 201]),
+			Error::<Runtime>::TooManyPositions
+		);
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(110, 100)));
+		assert_ok!(MarginProtocol::close_positions(
+			Origin::signed(ALICE),
+			vec![(0, price), (1, price)]
+		));
+		assert_eq!(MarginProtocol::positions(0), None);
+		assert_eq!(MarginProtocol::positions(1), None);
+		assert_eq!(
+			MarginProtocol::balances(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(120)
+		);
+	});
+}
+
+#[test]
+fn close_all_positions_works() {
+	order_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		let position = MarginProtocol::positions(0).unwrap();
+		assert_eq!(
+			MarginProtocol::insert_position(&ALICE, MOCK_POOL, EUR_USD_PAIR, position.clone()),
+			Ok(1)
+		);
+		<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL + 1, 2), ());
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(110, 100)));
+		assert_ok!(MarginProtocol::close_all_positions(Origin::signed(ALICE), MOCK_POOL));
+		assert_eq!(MarginProtocol::positions(0), None);
+		assert_eq!(MarginProtocol::positions(1), None);
+		assert_eq!(
+			MarginProtocol::balances(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(120)
+		);
+		// positions in other pools are kept
+		assert!(<PositionsByTrader<Runtime>>::contains_key(ALICE, (MOCK_POOL + 1, 2)));
+		let event = TestEvent::margin_protocol(RawEvent::AllPositionsClosed(ALICE, MOCK_POOL, 2));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_ok!(MarginProtocol::close_all_positions(Origin::signed(BOB), MOCK_POOL));
+		let event = TestEvent::margin_protocol(RawEvent::AllPositionsClosed(BOB, MOCK_POOL, 0));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn position_netting_works() {
+	order_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(1000));
+		assert_ok!(MarginProtocol::set_position_netting(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			true
+		));
+		let event = TestEvent::margin_protocol(RawEvent::PositionNettingSet(ALICE, MOCK_POOL, true));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(110, 100)));
+		let amount = balance_saturating_from_integer_currency_cent(100);
+		let price = Price::saturating_from_integer(2);
+		assert_ok!(MarginProtocol::open_position(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			Leverage::LongTwo,
+			amount,
+			price,
+			None
+		));
+		assert_eq!(MarginProtocol::positions(1), None);
+		let position = MarginProtocol::positions(0).unwrap();
+		assert_eq!(
+			position.leveraged_held,
+			fixedi128_saturating_from_integer_currency_cent(200)
+		);
+		// open price (1 * 100 + 1.1 * 100) / 200 = 1.05
+		assert_eq!(
+			position.leveraged_debits,
+			fixedi128_saturating_from_integer_currency_cent(-210)
+		);
+		assert_eq!(
+			position.margin_held,
+			fixedi128_saturating_from_integer_currency_cent(105)
+		);
+		assert_eq!(
+			MarginProtocol::margin_held(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(105)
+		);
+		assert_eq!(PositionsSnapshots::get(MOCK_POOL, EUR_USD_PAIR).positions_count, 1);
+		let event = TestEvent::margin_protocol(RawEvent::PositionIncreased(
+			ALICE,
+			0,
+			amount,
+			Price::saturating_from_rational(110, 100),
+			0,
+			fixedi128_saturating_from_integer_currency_cent(1000),
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		// different leverage is not netted
+		assert_ok!(MarginProtocol::open_position(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			Leverage::LongFive,
+			amount,
+			price,
+			None
+		));
+		assert!(MarginProtocol::positions(1).is_some());
+
+		// opted out
+		assert_ok!(MarginProtocol::set_position_netting(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			false
+		));
+		assert_ok!(MarginProtocol::open_position(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			Leverage::LongTwo,
+			amount,
+			price,
+			None
+		));
+		assert!(MarginProtocol::positions(2).is_some());
+	});
+}
+
+#[test]
+fn expiring_positions_are_not_netted() {
+	order_ext().execute_with(|| {
+		<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(1000));
+		assert_ok!(MarginProtocol::set_position_netting(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			true
+		));
+		let amount = balance_saturating_from_integer_currency_cent(100);
+		let price = Price::saturating_from_integer(2);
+		let expiry = System::block_number() + 5;
+		let open_with_expiry = || {
+			MarginProtocol::open_position_with_expiry(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwo,
+				amount,
+				price,
+				expiry,
+			)
+		};
+
+		assert_ok!(open_with_expiry());
+		assert_eq!(MarginProtocol::position_expiry(0), Some(expiry));
+
+		// not netted into the expiring position
+		assert_ok!(MarginProtocol::open_position(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			Leverage::LongTwo,
+			amount,
+			price,
+			None
+		));
+		assert!(MarginProtocol::positions(1).is_some());
+		assert_eq!(MarginProtocol::position_expiry(1), None);
+
+		// expiring opens are not netted
+		assert_ok!(open_with_expiry());
+		assert!(MarginProtocol::positions(2).is_some());
+		assert_eq!(MarginProtocol::position_expiry(2), Some(expiry));
+		assert_eq!(MarginProtocol::position_expiry(1), None);
+		assert_eq!(
+			MarginProtocol::positions(1).unwrap().leveraged_held,
+			fixedi128_saturating_from_integer_currency_cent(100)
+		);
+	});
+}
+
+#[test]
+fn transfer_position_works() {
+	order_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(60, 50));
+		assert_noop!(
+			MarginProtocol::propose_position_transfer(Origin::signed(BOB), 0, ALICE),
+			Error::<Runtime>::PositionNotOpenedByTrader
+		);
+		// not offered yet
+		assert_noop!(
+			MarginProtocol::accept_position_transfer(Origin::signed(BOB), 0),
+			Error::<Runtime>::NoPositionTransferOffer
+		);
+
+		assert_ok!(MarginProtocol::propose_position_transfer(Origin::signed(ALICE), 0, BOB));
+		assert_eq!(MarginProtocol::position_transfer_offer(0), Some(BOB));
+		let event = TestEvent::margin_protocol(RawEvent::PositionTransferProposed(ALICE, 0, BOB));
+		assert!(System::events().iter().any(|record| record.event == event));
+		assert_noop!(
+			MarginProtocol::accept_position_transfer(Origin::signed(ALICE), 0),
+			Error::<Runtime>::NoPositionTransferOffer
+		);
+		// bob's margin level would be 50%
+		assert_noop!(
+			MarginProtocol::accept_position_transfer(Origin::signed(BOB), 0),
+			Error::<Runtime>::UnsafeTrader
+		);
+
+		<Balances<Runtime>>::insert(BOB, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(50));
+		assert_ok!(MarginProtocol::accept_position_transfer(Origin::signed(BOB), 0));
+		assert_eq!(MarginProtocol::positions(0).unwrap().owner, BOB);
+		assert_eq!(MarginProtocol::position_transfer_offer(0), None);
+		assert!(!<PositionsByTrader<Runtime>>::contains_key(ALICE, (MOCK_POOL, 0)));
+		assert!(<PositionsByTrader<Runtime>>::contains_key(BOB, (MOCK_POOL, 0)));
+		assert_eq!(
+			MarginProtocol::balances(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(50)
+		);
+		assert_eq!(MarginProtocol::margin_held(ALICE, MOCK_POOL), FixedI128::zero());
+		assert!(!<MarginHeld<Runtime>>::contains_key(ALICE, MOCK_POOL));
+		// the stop loss set by alice doesn't move with the position
+		assert_eq!(MarginProtocol::stop_loss(0), None);
+		assert_eq!(
+			MarginProtocol::balances(BOB, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(100)
+		);
+		assert_eq!(
+			MarginProtocol::margin_held(BOB, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(50)
+		);
+		let event = TestEvent::margin_protocol(RawEvent::PositionTransferred(ALICE, 0, BOB));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn transfer_position_fails_if_owner_balance_would_be_negative() {
+	order_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(40));
+		<Balances<Runtime>>::insert(BOB, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
+
+		assert_ok!(MarginProtocol::propose_position_transfer(Origin::signed(ALICE), 0, BOB));
+		// 50 margin held, with 40 balance
+		assert_noop!(
+			MarginProtocol::accept_position_transfer(Origin::signed(BOB), 0),
+			Error::<Runtime>::InsufficientBalanceForTransfer
+		);
+	});
 }
 
 #[test]
-fn open_position_fails_if_no_base_price() {
-	ExtBuilder::default()
-		.price(CurrencyId::FEUR, (1409, 1070))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
-		.build()
-		.execute_with(|| {
-			<Balances<Runtime>>::insert(
-				ALICE,
-				MOCK_POOL,
-				fixedi128_saturating_from_integer_currency_cent(10_000_00),
-			);
-			assert_noop!(
-				MarginProtocol::open_position(
-					Origin::signed(ALICE),
-					MOCK_POOL,
-					EUR_JPY_PAIR,
-					Leverage::LongTwenty,
-					balance_saturating_from_integer_currency_cent(100_000_00),
-					Price::saturating_from_integer(142)
-				),
-				Error::<Runtime>::NoPrice
-			);
-		});
+fn reverse_position_works() {
+	order_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		let price = Price::saturating_from_integer(1);
+		assert_noop!(
+			MarginProtocol::reverse_position(Origin::signed(BOB), 0, price),
+			Error::<Runtime>::PositionNotOpenedByTrader
+		);
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(110, 100)));
+		assert_ok!(MarginProtocol::reverse_position(Origin::signed(ALICE), 0, price));
+		assert_eq!(MarginProtocol::positions(0), None);
+		assert_eq!(
+			MarginProtocol::balances(ALICE, MOCK_POOL),
+			fixedi128_saturating_from_integer_currency_cent(110)
+		);
+
+		let position = MarginProtocol::positions(1).unwrap();
+		assert_eq!(position.leverage, Leverage::ShortTwo);
+		assert_eq!(
+			position.leveraged_held,
+			fixedi128_saturating_from_integer_currency_cent(-100)
+		);
+		assert_eq!(
+			position.leveraged_debits,
+			fixedi128_saturating_from_integer_currency_cent(110)
+		);
+		let event = TestEvent::margin_protocol(RawEvent::PositionReversed(ALICE, 0, 1));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
 }
 
 #[test]
-fn open_position_fails_if_no_quote_price() {
+fn close_position_fails_if_position_not_found() {
+	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
 	ExtBuilder::default()
-		.price(CurrencyId::FJPY, (1, 107))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.module_balance(alice_initial)
+		// EUR/USD = 1.2
+		.price(CurrencyId::FEUR, (12, 10))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
 		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
 		.build()
 		.execute_with(|| {
-			<Balances<Runtime>>::insert(
-				ALICE,
-				MOCK_POOL,
-				fixedi128_saturating_from_integer_currency_cent(10_000_00),
-			);
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
+
 			assert_noop!(
-				MarginProtocol::open_position(
-					Origin::signed(ALICE),
-					MOCK_POOL,
-					EUR_JPY_PAIR,
-					Leverage::LongTwenty,
-					balance_saturating_from_integer_currency_cent(100_000_00),
-					Price::saturating_from_integer(142)
-				),
-				Error::<Runtime>::NoPrice
+				MarginProtocol::close_position(Origin::signed(ALICE), 0, Price::saturating_from_rational(11, 10), None),
+				Error::<Runtime>::PositionNotFound
 			);
 		});
 }
 
 #[test]
-fn open_long_position_fails_if_market_price_too_high() {
+fn close_position_fails_if_position_not_opened_by_trader() {
+	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
 	ExtBuilder::default()
-		// USD/JPY = 107
-		.price(CurrencyId::FJPY, (1, 107))
-		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
-		.price(CurrencyId::FEUR, (1409, 1070))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.module_balance(alice_initial)
+		// EUR/USD = 1.2
+		.price(CurrencyId::FEUR, (12, 10))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
 		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
 		.build()
 		.execute_with(|| {
-			<Balances<Runtime>>::insert(
-				ALICE,
-				MOCK_POOL,
-				fixedi128_saturating_from_integer_currency_cent(10_000_00),
-			);
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
+
+			let position = eur_usd_long_1();
+			let id = 0;
+			<Positions<Runtime>>::insert(id, position);
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+
 			assert_noop!(
-				MarginProtocol::open_position(
-					Origin::signed(ALICE),
-					MOCK_POOL,
-					EUR_JPY_PAIR,
-					Leverage::LongTwenty,
-					balance_saturating_from_integer_currency_cent(100_000_00),
-					Price::saturating_from_integer(141)
-				),
-				Error::<Runtime>::MarketPriceTooHigh
+				MarginProtocol::close_position(Origin::signed(BOB), 0, Price::saturating_from_rational(11, 10), None),
+				Error::<Runtime>::PositionNotOpenedByTrader
 			);
 		});
 }
 
 #[test]
-fn open_short_position_fails_if_market_price_too_low() {
+fn close_position_fails_if_unrealized_out_of_bound() {
+	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
 	ExtBuilder::default()
-		// USD/JPY = 106
-		.price(CurrencyId::FJPY, (1, 106))
-		// EUR/JPY = 141.9 => EUR/USD = 141.9/106
-		.price(CurrencyId::FEUR, (1419, 1060))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.module_balance(alice_initial)
+		.price(CurrencyId::FEUR, (u128::max_value(), 1))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
 		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
 		.build()
 		.execute_with(|| {
-			<Balances<Runtime>>::insert(
-				ALICE,
-				MOCK_POOL,
-				fixedi128_saturating_from_integer_currency_cent(10_000_00),
-			);
-			assert_noop!(
-				MarginProtocol::open_position(
-					Origin::signed(ALICE),
-					MOCK_POOL,
-					EUR_JPY_PAIR,
-					Leverage::ShortTwenty,
-					balance_saturating_from_integer_currency_cent(100_000_00),
-					Price::saturating_from_integer(142)
-				),
-				Error::<Runtime>::MarketPriceTooLow
-			);
-		});
-}
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
+
+			let position = eur_usd_long_1();
+			let id = 0;
+			<Positions<Runtime>>::insert(id, position);
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
 
-#[test]
-fn open_position_fails_if_leveraged_debits_out_of_bound() {
-	ExtBuilder::default()
-		.price(CurrencyId::FJPY, (1, 1))
-		.price(CurrencyId::FEUR, (2, 1))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
-		.pool_liquidity(MOCK_POOL, Balance::max_value())
-		.build()
-		.execute_with(|| {
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, FixedI128::max_value());
 			assert_noop!(
-				MarginProtocol::open_position(
-					Origin::signed(ALICE),
-					MOCK_POOL,
-					EUR_JPY_PAIR,
-					Leverage::LongTwenty,
-					u128::max_value() / 2 + 1,
-					Price::saturating_from_integer(142)
-				),
-				Error::<Runtime>::NumOutOfBound
+				MarginProtocol::close_position(Origin::signed(ALICE), 0, Price::saturating_from_rational(11, 10), None),
+				Error::<Runtime>::UnrealizedPlOverflow
 			);
 		});
 }
 
-#[test]
-fn open_position_fails_if_insufficient_free_margin() {
-	ExtBuilder::default()
-		.price(CurrencyId::FJPY, (1, 1))
-		.price(CurrencyId::FEUR, (2, 1))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
-		.pool_liquidity(MOCK_POOL, Balance::max_value())
-		.build()
-		.execute_with(|| {
-			assert_noop!(
-				MarginProtocol::open_position(
-					Origin::signed(ALICE),
-					MOCK_POOL,
-					EUR_JPY_PAIR,
-					Leverage::LongTwo,
-					1,
-					Price::saturating_from_integer(142)
-				),
-				Error::<Runtime>::InsufficientFreeMargin
-			);
-		});
+fn max_leverage_position(leveraged_held: i128) -> Position<Runtime> {
+	Position {
+		owner: ALICE,
+		pool: MOCK_POOL,
+		pair: EUR_USD_PAIR,
+		leverage: Leverage::LongFifty,
+		leveraged_held: FixedI128::saturating_from_integer(leveraged_held),
+		leveraged_debits: FixedI128::saturating_from_integer(-leveraged_held),
+		open_accumulated_swap_rate: FixedI128::saturating_from_integer(0),
+		margin_held: FixedI128::saturating_from_rational(leveraged_held, 50),
+	}
 }
 
 #[test]
-fn open_position_fails_if_would_reach_enp_threshold() {
+fn unrealized_pl_of_position_at_numeric_boundary() {
 	ExtBuilder::default()
-		// USD/JPY = 107
-		.price(CurrencyId::FJPY, (1, 107))
-		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
-		.price(CurrencyId::FEUR, (1409, 1070))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(659_00))
+		.spread(Permill::zero())
+		// EUR/USD = 10^20, close to `FixedI128::max_value()`
+		.price(CurrencyId::FEUR, (100_000_000_000_000_000_000, 1))
 		.build()
 		.execute_with(|| {
-			set_enp_risk_threshold(EUR_JPY_PAIR, risk_threshold(10, 5));
-			<Balances<Runtime>>::insert(
-				ALICE,
-				MOCK_POOL,
-				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			// (10^20 - 1) * 1
+			assert_eq!(
+				MarginProtocol::unrealized_pl_of_position(&max_leverage_position(1)),
+				Ok(FixedI128::saturating_from_integer(99_999_999_999_999_999_999i128))
 			);
-			assert_noop!(
-				MarginProtocol::open_position(
-					Origin::signed(ALICE),
-					MOCK_POOL,
-					EUR_JPY_PAIR,
-					Leverage::LongTwenty,
-					balance_saturating_from_integer_currency_cent(100_000_00),
-					Price::saturating_from_integer(142)
-				),
-				Error::<Runtime>::PoolWouldBeUnsafe
+			// (10^20 - 1) * 2 > `FixedI128::max_value()`
+			assert_eq!(
+				MarginProtocol::unrealized_pl_of_position(&max_leverage_position(2)),
+				Err(Error::<Runtime>::UnrealizedPlOverflow.into())
 			);
 		});
 }
 
 #[test]
-fn open_position_fails_if_would_reach_ell_threshold() {
+fn accumulated_swap_rate_of_position_at_numeric_boundary() {
 	ExtBuilder::default()
-		// USD/JPY = 107
-		.price(CurrencyId::FJPY, (1, 107))
-		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
-		.price(CurrencyId::FEUR, (1409, 1070))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(659_00))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::max_value())
 		.build()
 		.execute_with(|| {
-			set_ell_risk_threshold(EUR_JPY_PAIR, risk_threshold(10, 5));
-			<Balances<Runtime>>::insert(
-				ALICE,
-				MOCK_POOL,
-				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			let mut position = max_leverage_position(1);
+			assert_eq!(
+				MarginProtocol::accumulated_swap_rate_of_position(&position),
+				Ok(FixedI128::max_value())
 			);
-			assert_noop!(
-				MarginProtocol::open_position(
-					Origin::signed(ALICE),
-					MOCK_POOL,
-					EUR_JPY_PAIR,
-					Leverage::LongTwenty,
-					balance_saturating_from_integer_currency_cent(100_000_00),
-					Price::saturating_from_integer(142)
-				),
-				Error::<Runtime>::PoolWouldBeUnsafe
+
+			position.open_accumulated_swap_rate = FixedI128::saturating_from_integer(-1);
+			assert_eq!(
+				MarginProtocol::accumulated_swap_rate_of_position(&position),
+				Err(Error::<Runtime>::AccumulatedSwapRateOverflow.into())
+			);
+
+			position.open_accumulated_swap_rate = FixedI128::saturating_from_integer(0);
+			position.leveraged_debits = FixedI128::saturating_from_integer(-2);
+			assert_eq!(
+				MarginProtocol::accumulated_swap_rate_of_position(&position),
+				Err(Error::<Runtime>::AccumulatedSwapRateOverflow.into())
 			);
 		});
 }
 
 #[test]
-fn open_position_fails_if_run_out_of_position_id() {
+fn close_position_fails_if_no_base_price() {
+	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
 	ExtBuilder::default()
-		// USD/JPY = 107
-		.price(CurrencyId::FJPY, (1, 107))
-		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
+		.module_balance(alice_initial)
 		.price(CurrencyId::FEUR, (1409, 1070))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
 		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
 		.build()
 		.execute_with(|| {
-			<Balances<Runtime>>::insert(
-				ALICE,
-				MOCK_POOL,
-				fixedi128_saturating_from_integer_currency_cent(10_000_00),
-			);
-			NextPositionId::put(PositionId::max_value());
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
+
+			let position = eur_jpy_long();
+			let id = 0;
+			<Positions<Runtime>>::insert(id, position);
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+
 			assert_noop!(
-				MarginProtocol::open_position(
+				MarginProtocol::close_position(
 					Origin::signed(ALICE),
-					MOCK_POOL,
-					EUR_JPY_PAIR,
-					Leverage::LongTwenty,
-					balance_saturating_from_integer_currency_cent(100_000_00),
-					Price::saturating_from_integer(142)
+					0,
+					Price::saturating_from_rational(1410, 1070),
+					None
 				),
-				Error::<Runtime>::NoAvailablePositionId
+				Error::<Runtime>::NoPrice
 			);
 		});
 }
 
 #[test]
-fn free_margin_cannot_be_used_across_pool() {
+fn close_position_fails_if_no_quote_price() {
+	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
 	ExtBuilder::default()
-		// USD/JPY = 107
+		.module_balance(alice_initial)
 		.price(CurrencyId::FJPY, (1, 107))
-		// EUR/JPY = 140.9 => EUR/USD = 140.9/107
-		.price(CurrencyId::FEUR, (1409, 1070))
-		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
 		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
 		.build()
 		.execute_with(|| {
-			<Balances<Runtime>>::insert(
-				ALICE,
-				MOCK_POOL_1,
-				fixedi128_saturating_from_integer_currency_cent(10_000_00),
-			);
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
+
+			let position = eur_jpy_long();
+			let id = 0;
+			<Positions<Runtime>>::insert(id, position);
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+
 			assert_noop!(
-				MarginProtocol::open_position(
+				MarginProtocol::close_position(
 					Origin::signed(ALICE),
-					MOCK_POOL,
-					EUR_JPY_PAIR,
-					Leverage::LongTwenty,
-					balance_saturating_from_integer_currency_cent(100_000_00),
-					Price::saturating_from_integer(142)
+					0,
+					Price::saturating_from_rational(1390, 1070),
+					None
 				),
-				Error::<Runtime>::InsufficientFreeMargin
+				Error::<Runtime>::NoPrice
 			);
 		});
 }
 
 #[test]
-fn close_loss_position_works() {
+fn close_long_position_fails_if_market_price_too_low() {
 	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
 	ExtBuilder::default()
 		.module_balance(alice_initial)
@@ -1490,555 +5941,993 @@ fn close_loss_position_works() {
 
 			let position = eur_usd_long_1();
 			let id = 0;
-			<Positions<Runtime>>::insert(id, position.clone());
+			<Positions<Runtime>>::insert(id, position);
 			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
 			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
 
-			let snapshot = positions_snapshot(
-				1,
-				position.leveraged_held,
-				position.leveraged_debits,
-				FixedI128::saturating_from_integer(0),
-				FixedI128::saturating_from_integer(0),
-			);
-			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot.clone());
-
-			assert_ok!(MarginProtocol::close_position(
-				Origin::signed(ALICE),
-				id,
-				Price::saturating_from_rational(11, 10)
-			));
-
-			// realized math
-			assert_eq!(
-				MarginProtocol::balances(ALICE, MOCK_POOL),
-				FixedI128::from_inner(9415301035390000000000)
-			);
-			assert_eq!(MockLiquidityPools::liquidity(MOCK_POOL), 100584698964610000000000);
-			assert_eq!(
-				LiquidityCurrency::free_balance(&MarginProtocol::account_id()),
-				9415301035390000000000
+			assert_noop!(
+				MarginProtocol::close_position(Origin::signed(ALICE), 0, Price::saturating_from_rational(12, 10), None),
+				Error::<Runtime>::MarketPriceTooLow
 			);
-
-			// position removed
-			assert!(MarginProtocol::positions(id).is_none());
-			assert_eq!(MarginProtocol::positions_by_trader(ALICE, (MOCK_POOL, id)), None);
-			assert_eq!(MarginProtocol::positions_by_pool(MOCK_POOL, (EUR_USD_PAIR, id)), None);
-
-			let event = TestEvent::margin_protocol(RawEvent::PositionClosed(
-				ALICE,
-				id,
-				MOCK_POOL,
-				Price::saturating_from_rational(11988, 10000),
-			));
-			assert!(System::events().iter().any(|record| record.event == event));
 		});
 }
 
 #[test]
-fn close_loss_position_realizing_part_on_not_enough_equity() {
+fn close_short_position_fails_if_market_price_too_high() {
+	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
 	ExtBuilder::default()
-		.module_balance(fixedi128_saturating_from_integer_currency_cent(1_00))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(1_000_00))
-		.spread(Permill::zero())
+		.module_balance(alice_initial)
+		// EUR/USD = 1.2
+		.price(CurrencyId::FEUR, (12, 10))
 		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
-		.price(CurrencyId::FEUR, (10, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
 		.build()
 		.execute_with(|| {
-			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 0));
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(1_00));
-			let position: Position<Runtime> = Position {
-				owner: ALICE,
-				pool: MOCK_POOL,
-				pair: EUR_USD_PAIR,
-				leverage: Leverage::LongTen,
-				leveraged_held: fixedi128_saturating_from_integer_currency_cent(10_00),
-				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100_00),
-				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
-				margin_held: fixedi128_saturating_from_integer_currency_cent(1_00),
-			};
-			<Positions<Runtime>>::insert(0, position.clone());
-			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
-			let snapshot = positions_snapshot(
-				1,
-				position.leveraged_held,
-				position.leveraged_debits,
-				FixedI128::saturating_from_integer(0),
-				FixedI128::saturating_from_integer(0),
-			);
-			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot.clone());
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
 
-			MockPrices::set_mock_price(CurrencyId::FEUR, Some(Price::saturating_from_rational(1, 1)));
+			let position = eur_usd_short_1();
+			let id = 0;
+			<Positions<Runtime>>::insert(id, position);
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
 
-			assert_ok!(MarginProtocol::close_position(
-				Origin::signed(ALICE),
-				0,
-				Price::saturating_from_integer(0)
-			));
-			assert_eq!(
-				MockLiquidityPools::liquidity(MOCK_POOL),
-				balance_saturating_from_integer_currency_cent(1_001_00)
+			assert_noop!(
+				MarginProtocol::close_position(Origin::signed(ALICE), 0, Price::saturating_from_rational(12, 10), None),
+				Error::<Runtime>::MarketPriceTooHigh
 			);
-			assert_eq!(MarginProtocol::balances(&ALICE, MOCK_POOL), FixedI128::zero());
 		});
 }
 
 #[test]
-fn close_loss_position_owning_and_repayment() {
+fn deposit_works() {
+	ExtBuilder::default().alice_balance(1000).build().execute_with(|| {
+		assert_eq!(LiquidityCurrency::free_balance(&ALICE), 1000);
+		assert_eq!(LiquidityCurrency::free_balance(&MarginProtocol::account_id()), 0);
+
+		assert_ok!(MarginProtocol::deposit(Origin::signed(ALICE), MOCK_POOL, 500));
+
+		assert_eq!(LiquidityCurrency::free_balance(&ALICE), 500);
+		assert_eq!(LiquidityCurrency::free_balance(&MarginProtocol::account_id()), 500);
+		assert_eq!(MarginProtocol::balances(&ALICE, MOCK_POOL), FixedI128::from_inner(500));
+
+		let event = TestEvent::margin_protocol(RawEvent::Deposited(ALICE, MOCK_POOL, 500));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn deposit_position_margin_works() {
 	ExtBuilder::default()
-		.module_balance(fixedi128_saturating_from_integer_currency_cent(2_00))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(1_000_00))
-		.spread(Permill::zero())
-		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
-		.accumulated_swap_rate(JPY_USD_PAIR, FixedI128::saturating_from_integer(1))
-		.price(CurrencyId::FEUR, (10, 1))
-		.price(CurrencyId::FJPY, (10, 1))
+		.alice_balance(balance_saturating_from_integer_currency_cent(10))
 		.build()
 		.execute_with(|| {
-			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 0));
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(2_00));
-
-			// position with 90 dollars loss
-			let loss_position: Position<Runtime> = Position {
-				owner: ALICE,
-				pool: MOCK_POOL,
-				pair: EUR_USD_PAIR,
-				leverage: Leverage::LongTen,
-				leveraged_held: fixedi128_saturating_from_integer_currency_cent(10_00),
-				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100_00),
-				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
-				margin_held: fixedi128_saturating_from_integer_currency_cent(1_00),
-			};
-			// position with 45 dollars profit
-			let profit_position: Position<Runtime> = Position {
-				owner: ALICE,
-				pool: MOCK_POOL,
-				pair: JPY_USD_PAIR,
-				leverage: Leverage::LongFive,
-				leveraged_held: fixedi128_saturating_from_integer_currency_cent(5_00),
-				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-5_00),
-				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
-				margin_held: fixedi128_saturating_from_integer_currency_cent(1_00),
-			};
-			<Positions<Runtime>>::insert(0, loss_position.clone());
-			<Positions<Runtime>>::insert(1, profit_position.clone());
-			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
-			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 1), ());
-
-			PositionsSnapshots::insert(
-				MOCK_POOL,
-				EUR_USD_PAIR,
-				positions_snapshot(
-					1,
-					loss_position.leveraged_held,
-					loss_position.leveraged_debits,
-					FixedI128::saturating_from_integer(0),
-					FixedI128::saturating_from_integer(0),
-				),
+			eur_usd_long_with_stop_loss();
+			let amount = balance_saturating_from_integer_currency_cent(10);
+			assert_noop!(
+				MarginProtocol::deposit_position_margin(Origin::signed(BOB), 0, amount),
+				Error::<Runtime>::PositionNotOpenedByTrader
 			);
-			PositionsSnapshots::insert(
-				MOCK_POOL,
-				JPY_USD_PAIR,
-				positions_snapshot(
-					1,
-					profit_position.leveraged_held,
-					profit_position.leveraged_debits,
-					FixedI128::saturating_from_integer(0),
-					FixedI128::saturating_from_integer(0),
-				),
+			assert_noop!(
+				MarginProtocol::deposit_position_margin(Origin::signed(ALICE), 1, amount),
+				Error::<Runtime>::PositionNotFound
 			);
 
-			MockPrices::set_mock_price(CurrencyId::FEUR, Some(Price::saturating_from_rational(1, 1)));
-			assert!(MarginProtocol::equity_of_trader(&ALICE, MOCK_POOL).unwrap() < FixedI128::zero());
-
-			// Balance: FixedI128(2.000000000000000000)
-			// Free margin: Ok(FixedI128(-45.000000000000000000))
-			// Unrealized PL: Ok(FixedI128(-45.000000000000000000))
-			// Equity: Ok(FixedI128(-43.000000000000000000))
-			// Margin level: Ok(FixedI128(-0.409523809523809523))
-
-			assert_ok!(MarginProtocol::close_position(
+			assert_ok!(MarginProtocol::deposit_position_margin(
 				Origin::signed(ALICE),
 				0,
-				Price::saturating_from_integer(0)
+				amount
 			));
-
-			// realizable = $47; $2 paid to pool; -$45 owning.
+			assert_eq!(LiquidityCurrency::free_balance(&ALICE), 0);
 			assert_eq!(
-				MockLiquidityPools::liquidity(MOCK_POOL),
-				balance_saturating_from_integer_currency_cent(1_002_00)
+				MarginProtocol::balances(ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(110)
 			);
 			assert_eq!(
-				MarginProtocol::balances(&ALICE, MOCK_POOL),
-				FixedI128::saturating_from_integer(-45),
+				MarginProtocol::positions(0).map(|p| p.margin_held),
+				Some(fixedi128_saturating_from_integer_currency_cent(60))
 			);
-
-			// realizable = $45; $45 repayment.
-			assert_ok!(MarginProtocol::close_position(
-				Origin::signed(ALICE),
-				1,
-				Price::saturating_from_integer(0)
-			));
 			assert_eq!(
-				MockLiquidityPools::liquidity(MOCK_POOL),
-				balance_saturating_from_integer_currency_cent(1_002_00)
+				MarginProtocol::margin_held(ALICE, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(60)
 			);
-			assert_eq!(MarginProtocol::balances(&ALICE, MOCK_POOL), FixedI128::zero(),);
+			let event = TestEvent::margin_protocol(RawEvent::PositionMarginDeposited(ALICE, 0, amount));
+			assert!(System::events().iter().any(|record| record.event == event));
 		});
 }
 
 #[test]
-fn close_profit_position_works() {
-	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
+fn deposit_fails_if_balance_overflow() {
+	ExtBuilder::default().alice_balance(1000).build().execute_with(|| {
+		<Balances<Runtime>>::insert(ALICE, MOCK_POOL, FixedI128::max_value());
+		assert_noop!(
+			MarginProtocol::deposit(Origin::signed(ALICE), MOCK_POOL, 500),
+			Error::<Runtime>::ArithmeticOverflow
+		);
+	});
+}
+
+#[test]
+fn deposit_fails_if_transfer_err() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			MarginProtocol::deposit(Origin::signed(ALICE), MOCK_POOL, 500),
+			orml_tokens::Error::<Runtime>::BalanceTooLow
+		);
+	});
+}
+
+#[test]
+fn withdraw_works() {
 	ExtBuilder::default()
-		.module_balance(alice_initial)
+		.module_balance(FixedI128::from_inner(1000))
+		.build()
+		.execute_with(|| {
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, FixedI128::from_inner(1000));
+			assert_ok!(MarginProtocol::withdraw(Origin::signed(ALICE), MOCK_POOL, 500));
+
+			let event = TestEvent::margin_protocol(RawEvent::Withdrew(ALICE, MOCK_POOL, 500));
+			assert!(System::events().iter().any(|record| record.event == event));
+		});
+}
+
+#[test]
+fn velocity_limit_works() {
+	ExtBuilder::default().alice_balance(1000).build().execute_with(|| {
+		System::set_block_number(1);
+		let global_limit = VelocityLimit {
+			max_deposit: Some(300),
+			max_withdraw: None,
+			period: 10,
+		};
+		let alice_limit = VelocityLimit {
+			max_deposit: None,
+			max_withdraw: Some(100),
+			period: 10,
+		};
+		assert_noop!(MarginProtocol::set_global_velocity_limit(Origin::signed(BOB), Some(global_limit)), BadOrigin);
+		assert_ok!(MarginProtocol::set_global_velocity_limit(Origin::signed(UpdateOrigin::get()), Some(global_limit)));
+		let event = TestEvent::margin_protocol(RawEvent::GlobalVelocityLimitSet(Some(global_limit)));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_ok!(MarginProtocol::deposit(Origin::signed(ALICE), MOCK_POOL, 200));
+		assert_noop!(
+			MarginProtocol::deposit(Origin::signed(ALICE), MOCK_POOL, 101),
+			Error::<Runtime>::DepositVelocityLimitExceeded
+		);
+		assert_ok!(MarginProtocol::deposit(Origin::signed(ALICE), MOCK_POOL, 100));
+
+		// new period
+		System::set_block_number(11);
+		assert_ok!(MarginProtocol::deposit(Origin::signed(ALICE), MOCK_POOL, 300));
+		assert_eq!(
+			MarginProtocol::velocity_usages(ALICE),
+			VelocityUsage {
+				period_start: 11,
+				deposited: 300,
+				withdrew: 0,
+			}
+		);
+
+		// account limit overrides global limit
+		assert_ok!(MarginProtocol::set_velocity_limit(Origin::signed(UpdateOrigin::get()), ALICE, Some(alice_limit)));
+		let event = TestEvent::margin_protocol(RawEvent::VelocityLimitSet(ALICE, Some(alice_limit)));
+		assert!(System::events().iter().any(|record| record.event == event));
+		assert_ok!(MarginProtocol::deposit(Origin::signed(ALICE), MOCK_POOL, 100));
+		assert_ok!(MarginProtocol::withdraw(Origin::signed(ALICE), MOCK_POOL, 100));
+		assert_noop!(
+			MarginProtocol::withdraw(Origin::signed(ALICE), MOCK_POOL, 1),
+			Error::<Runtime>::WithdrawVelocityLimitExceeded
+		);
+
+		// remove limits
+		assert_ok!(MarginProtocol::set_velocity_limit(Origin::signed(UpdateOrigin::get()), ALICE, None));
+		assert_ok!(MarginProtocol::set_global_velocity_limit(Origin::signed(UpdateOrigin::get()), None));
+		assert_ok!(MarginProtocol::withdraw(Origin::signed(ALICE), MOCK_POOL, 500));
+	});
+}
+
+#[test]
+fn copy_trading_works() {
+	ExtBuilder::default()
+		.spread(Permill::zero())
 		// EUR/USD = 1.2
 		.price(CurrencyId::FEUR, (12, 10))
 		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
 		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
 		.build()
 		.execute_with(|| {
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
+			<Balances<Runtime>>::insert(
+				ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_000_00),
+			);
+			<Balances<Runtime>>::insert(
+				BOB,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(5_000_00),
+			);
+			let max_leveraged_amount = balance_saturating_from_integer_currency_cent(400_00);
 
-			let position = eur_usd_long_2();
-			let id = 0;
-			<Positions<Runtime>>::insert(id, position.clone());
-			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
-			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
-			let snapshot = positions_snapshot(
-				1,
-				position.leveraged_held,
-				position.leveraged_debits,
-				FixedI128::saturating_from_integer(0),
-				FixedI128::saturating_from_integer(0),
+			assert_noop!(
+				MarginProtocol::follow(Origin::signed(ALICE), ALICE, MOCK_POOL, 20, max_leveraged_amount),
+				Error::<Runtime>::CannotFollowSelf
 			);
-			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot.clone());
-			assert_eq!(
-				MarginProtocol::pool_positions_snapshots(MOCK_POOL, EUR_USD_PAIR),
-				snapshot
+			assert_ok!(MarginProtocol::set_leader_fee_share(Origin::signed(ALICE), Permill::from_percent(10)));
+			assert_ok!(MarginProtocol::follow(Origin::signed(BOB), ALICE, MOCK_POOL, 20, max_leveraged_amount));
+			assert_noop!(
+				MarginProtocol::follow(Origin::signed(BOB), ALICE, MOCK_POOL, 20, max_leveraged_amount),
+				Error::<Runtime>::AlreadyCopyTrading
 			);
+			assert_eq!(MarginProtocol::copy_followers(ALICE, MOCK_POOL), vec![BOB]);
+			let event = TestEvent::margin_protocol(RawEvent::CopyTradingStarted(BOB, ALICE, MOCK_POOL));
+			assert!(System::events().iter().any(|record| record.event == event));
 
-			assert_ok!(MarginProtocol::close_position(
+			// leverage higher than max leverage, not copied
+			assert_ok!(MarginProtocol::open_position(
 				Origin::signed(ALICE),
-				id,
-				Price::saturating_from_rational(11, 10)
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongFifty,
+				balance_saturating_from_integer_currency_cent(1_000_00),
+				Price::saturating_from_integer(2),
+				None,
 			));
+			assert!(MarginProtocol::copied_positions(0).is_empty());
+			assert_eq!(MarginProtocol::next_position_id(), 1);
 
-			let snapshot = positions_snapshot(
-				0,
-				FixedI128::saturating_from_integer(0),
-				FixedI128::saturating_from_integer(0),
-				FixedI128::saturating_from_integer(0),
-				FixedI128::saturating_from_integer(0),
-			);
+			// copied amount 1_000 * 5_000 / 10_000 = 500, capped by max leveraged amount
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(1_000_00),
+				Price::saturating_from_integer(2),
+				None,
+			));
+			assert_eq!(MarginProtocol::copied_positions(1), vec![(BOB, 2)]);
 			assert_eq!(
-				MarginProtocol::pool_positions_snapshots(MOCK_POOL, EUR_USD_PAIR),
-				snapshot
+				MarginProtocol::positions(2).map(|p| (p.owner, p.leverage, p.leveraged_held)),
+				Some((
+					BOB,
+					Leverage::LongTwenty,
+					fixedi128_saturating_from_integer_currency_cent(400_00)
+				))
 			);
+			let event = TestEvent::margin_protocol(RawEvent::PositionCopied(BOB, 2, ALICE, 1));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			// EUR/USD = 1.3
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(Price::saturating_from_rational(13, 10)));
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				1,
+				Price::saturating_from_integer(1),
+				None
+			));
+			assert_eq!(MarginProtocol::positions(2), None);
+			assert!(MarginProtocol::copied_positions(1).is_empty());
+			assert_eq!(MarginProtocol::copied_position_leaders(2), None);
+
+			// BOB's profit 400 * (1.3 - 1.2) = 40, 10% paid to ALICE
+			let event = TestEvent::margin_protocol(RawEvent::LeaderFeePaid(
+				BOB,
+				ALICE,
+				MOCK_POOL,
+				balance_saturating_from_integer_currency_cent(4_00),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
 			assert_eq!(
-				MarginProtocol::enp_and_ell_with_action(MOCK_POOL, Action::None),
-				Ok((FixedI128::max_value(), FixedI128::max_value(),))
+				MarginProtocol::balances(BOB, MOCK_POOL),
+				fixedi128_saturating_from_integer_currency_cent(5_036_00)
 			);
+			// ALICE's profit 1_000 * (1.3 - 1.2) = 100, plus the fee
 			assert_eq!(
 				MarginProtocol::balances(ALICE, MOCK_POOL),
-				FixedI128::from_inner(10438691023010000000000)
+				fixedi128_saturating_from_integer_currency_cent(10_104_00)
 			);
-			assert_eq!(MockLiquidityPools::liquidity(MOCK_POOL), 99561308976990000000000);
-			assert_eq!(
-				LiquidityCurrency::free_balance(&MarginProtocol::account_id()),
-				10438691023010000000000
+
+			assert_ok!(MarginProtocol::unfollow(Origin::signed(BOB), MOCK_POOL));
+			assert_eq!(MarginProtocol::copy_tradings(BOB, MOCK_POOL), None);
+			assert!(MarginProtocol::copy_followers(ALICE, MOCK_POOL).is_empty());
+			assert_noop!(
+				MarginProtocol::unfollow(Origin::signed(BOB), MOCK_POOL),
+				Error::<Runtime>::NotCopyTrading
 			);
 		});
 }
 
 #[test]
-fn close_position_fails_if_position_not_found() {
-	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
+fn copied_positions_update_follower_risk_candidacy() {
 	ExtBuilder::default()
-		.module_balance(alice_initial)
-		// EUR/USD = 1.2
-		.price(CurrencyId::FEUR, (12, 10))
+		.spread(Permill::zero())
 		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
 		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
 		.build()
 		.execute_with(|| {
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
+			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(3, 1));
+			assert_ok!(MarginProtocol::set_risk_candidate_buffer(
+				Origin::signed(UpdateOrigin::get()),
+				Some(Permill::from_percent(5))
+			));
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(10_00));
+			<Balances<Runtime>>::insert(BOB, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(5_00));
+			assert_ok!(MarginProtocol::follow(
+				Origin::signed(BOB),
+				ALICE,
+				MOCK_POOL,
+				20,
+				balance_saturating_from_integer_currency_cent(1_000_00)
+			));
 
-			assert_noop!(
-				MarginProtocol::close_position(Origin::signed(ALICE), 0, Price::saturating_from_rational(11, 10)),
-				Error::<Runtime>::PositionNotFound
-			);
+			// copied amount 200 * 5 / 10 = 100
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(200_00),
+				Price::saturating_from_integer(100),
+				None,
+			));
+			assert_eq!(MarginProtocol::copied_positions(0), vec![(BOB, 1)]);
+			// margin level 5 / 100 = 5% <= 3% + 5%
+			assert!(<RiskCandidateTraders<Runtime>>::contains_key(BOB, MOCK_POOL));
 		});
 }
 
 #[test]
-fn close_position_fails_if_position_not_opened_by_trader() {
-	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
-	ExtBuilder::default()
-		.module_balance(alice_initial)
-		// EUR/USD = 1.2
-		.price(CurrencyId::FEUR, (12, 10))
-		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
-		.build()
-		.execute_with(|| {
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
-
-			let position = eur_usd_long_1();
-			let id = 0;
-			<Positions<Runtime>>::insert(id, position);
-			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
-			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+fn follow_fails_if_too_many_followers() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(MarginProtocol::follow(Origin::signed(BOB), ALICE, MOCK_POOL, 20, 100));
+		assert_ok!(MarginProtocol::follow(Origin::signed(4), ALICE, MOCK_POOL, 20, 100));
+		assert_noop!(
+			MarginProtocol::follow(Origin::signed(5), ALICE, MOCK_POOL, 20, 100),
+			Error::<Runtime>::TooManyCopyFollowers
+		);
 
-			assert_noop!(
-				MarginProtocol::close_position(Origin::signed(BOB), 0, Price::saturating_from_rational(11, 10)),
-				Error::<Runtime>::PositionNotOpenedByTrader
-			);
-		});
+		// following in another pool is not affected
+		assert_ok!(MarginProtocol::follow(Origin::signed(5), ALICE, MOCK_POOL_1, 20, 100));
+	});
 }
 
 #[test]
-fn close_position_fails_if_unrealized_out_of_bound() {
-	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
+fn trader_can_withdraw_unrealized_profit() {
 	ExtBuilder::default()
-		.module_balance(alice_initial)
-		.price(CurrencyId::FEUR, (u128::max_value(), 1))
+		.module_balance(fixedi128_saturating_from_integer_currency_cent(10_00))
+		.spread(Permill::zero())
 		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.price(CurrencyId::FEUR, (1, 1))
 		.build()
 		.execute_with(|| {
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
-
-			let position = eur_usd_long_1();
-			let id = 0;
-			<Positions<Runtime>>::insert(id, position);
+			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 0));
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
+			let position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				leverage: Leverage::LongTwo,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(10_00),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(50),
+			};
+			<Positions<Runtime>>::insert(0, position.clone());
 			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
-			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+			<MarginHeld<Runtime>>::insert(ALICE, MOCK_POOL, position.margin_held);
 
-			assert_noop!(
-				MarginProtocol::close_position(Origin::signed(ALICE), 0, Price::saturating_from_rational(11, 10)),
-				Error::<Runtime>::NumOutOfBound
+			assert_eq!(
+				MarginProtocol::free_margin(&ALICE, MOCK_POOL),
+				Ok(fixedi128_saturating_from_integer_currency_cent(9_50))
 			);
+			assert_ok!(MarginProtocol::withdraw(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				balance_saturating_from_integer_currency_cent(9_50)
+			));
 		});
 }
 
 #[test]
-fn close_position_fails_if_no_base_price() {
-	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
+fn withdraw_fails_if_amount_overflows() {
+	ExtBuilder::default().build().execute_with(|| {
+		<Balances<Runtime>>::insert(ALICE, MOCK_POOL, FixedI128::max_value());
+		assert_noop!(
+			MarginProtocol::withdraw(Origin::signed(ALICE), MOCK_POOL, Balance::max_value()),
+			Error::<Runtime>::BalanceOverflow
+		);
+	});
+}
+
+#[test]
+fn withdraw_fails_if_insufficient_free_margin() {
 	ExtBuilder::default()
-		.module_balance(alice_initial)
-		.price(CurrencyId::FEUR, (1409, 1070))
+		.spread(Permill::zero())
 		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.price(CurrencyId::FEUR, (1, 1))
 		.build()
 		.execute_with(|| {
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
-
-			let position = eur_jpy_long();
-			let id = 0;
-			<Positions<Runtime>>::insert(id, position);
+			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 0));
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
+			let position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				leverage: Leverage::LongTwo,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(100),
+			};
+			<Positions<Runtime>>::insert(0, position.clone());
 			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
-			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+			<MarginHeld<Runtime>>::insert(ALICE, MOCK_POOL, position.margin_held);
 
+			assert_eq!(
+				MarginProtocol::free_margin(&ALICE, MOCK_POOL),
+				Ok(fixedi128_saturating_from_integer_currency_cent(0))
+			);
 			assert_noop!(
-				MarginProtocol::close_position(Origin::signed(ALICE), 0, Price::saturating_from_rational(1410, 1070)),
-				Error::<Runtime>::NoPrice
+				MarginProtocol::withdraw(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					balance_saturating_from_integer_currency_cent(1)
+				),
+				Error::<Runtime>::InsufficientFreeMargin
 			);
 		});
 }
 
 #[test]
-fn close_position_fails_if_no_quote_price() {
-	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
+fn deposit_and_withdraw_collateral_works() {
 	ExtBuilder::default()
-		.module_balance(alice_initial)
-		.price(CurrencyId::FJPY, (1, 107))
-		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.balance(ALICE, CurrencyId::FEUR, 1000)
+		.price(CurrencyId::FEUR, (2, 1))
 		.build()
 		.execute_with(|| {
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
+			assert_noop!(
+				MarginProtocol::deposit_collateral(Origin::signed(ALICE), MOCK_POOL, CurrencyId::FEUR, 500),
+				Error::<Runtime>::CollateralNotAccepted
+			);
+			assert_noop!(
+				MarginProtocol::set_collateral_haircut(
+					Origin::signed(BOB),
+					CurrencyId::FEUR,
+					Some(Permill::from_percent(10))
+				),
+				BadOrigin
+			);
+			assert_ok!(MarginProtocol::set_collateral_haircut(
+				Origin::signed(UpdateOrigin::get()),
+				CurrencyId::FEUR,
+				Some(Permill::from_percent(10))
+			));
+			let event = TestEvent::margin_protocol(RawEvent::CollateralHaircutSet(
+				CurrencyId::FEUR,
+				Some(Permill::from_percent(10)),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
 
-			let position = eur_jpy_long();
-			let id = 0;
-			<Positions<Runtime>>::insert(id, position);
-			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
-			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+			assert_ok!(MarginProtocol::deposit_collateral(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				CurrencyId::FEUR,
+				500
+			));
+			assert_eq!(Currencies::free_balance(CurrencyId::FEUR, &ALICE), 500);
+			assert_eq!(
+				Currencies::free_balance(CurrencyId::FEUR, &MarginProtocol::account_id()),
+				500
+			);
+			assert_eq!(MarginProtocol::collaterals(ALICE, (MOCK_POOL, CurrencyId::FEUR)), 500);
+			let event =
+				TestEvent::margin_protocol(RawEvent::CollateralDeposited(ALICE, MOCK_POOL, CurrencyId::FEUR, 500));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			// 500 * 2 * (1 - 10%)
+			assert_eq!(
+				MarginProtocol::collateral_value_of_trader(&ALICE, MOCK_POOL),
+				Ok(FixedI128::from_inner(900))
+			);
+			assert_eq!(
+				MarginProtocol::equity_of_trader(&ALICE, MOCK_POOL),
+				Ok(FixedI128::from_inner(900))
+			);
+			assert_eq!(
+				MarginProtocol::collateral_value_of_trader(&ALICE, MOCK_POOL_1),
+				Ok(FixedI128::zero())
+			);
 
 			assert_noop!(
-				MarginProtocol::close_position(Origin::signed(ALICE), 0, Price::saturating_from_rational(1390, 1070)),
-				Error::<Runtime>::NoPrice
+				MarginProtocol::withdraw_collateral(Origin::signed(ALICE), MOCK_POOL, CurrencyId::FEUR, 501),
+				Error::<Runtime>::InsufficientCollateral
 			);
+			assert_ok!(MarginProtocol::withdraw_collateral(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				CurrencyId::FEUR,
+				200
+			));
+			assert_eq!(Currencies::free_balance(CurrencyId::FEUR, &ALICE), 700);
+			assert_eq!(MarginProtocol::collaterals(ALICE, (MOCK_POOL, CurrencyId::FEUR)), 300);
+			let event =
+				TestEvent::margin_protocol(RawEvent::CollateralWithdrew(ALICE, MOCK_POOL, CurrencyId::FEUR, 200));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			// collateral of not accepted currency is valued as zero
+			assert_ok!(MarginProtocol::set_collateral_haircut(
+				Origin::signed(UpdateOrigin::get()),
+				CurrencyId::FEUR,
+				None
+			));
+			assert_eq!(MarginProtocol::equity_of_trader(&ALICE, MOCK_POOL), Ok(FixedI128::zero()));
+			assert_ok!(MarginProtocol::withdraw_collateral(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				CurrencyId::FEUR,
+				300
+			));
+			assert!(!<Collaterals<Runtime>>::contains_key(ALICE, (MOCK_POOL, CurrencyId::FEUR)));
 		});
 }
 
 #[test]
-fn close_long_position_fails_if_market_price_too_low() {
-	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
+fn collateral_backs_margin_but_cannot_be_withdrawn_as_liquidity() {
 	ExtBuilder::default()
-		.module_balance(alice_initial)
-		// EUR/USD = 1.2
-		.price(CurrencyId::FEUR, (12, 10))
-		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.module_balance(FixedI128::from_inner(1000))
+		.balance(ALICE, CurrencyId::FEUR, 500)
+		.price(CurrencyId::FEUR, (2, 1))
 		.build()
 		.execute_with(|| {
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
+			CollateralHaircuts::insert(CurrencyId::FEUR, Permill::from_percent(10));
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, FixedI128::from_inner(1000));
+			assert_ok!(MarginProtocol::deposit_collateral(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				CurrencyId::FEUR,
+				500
+			));
+			<MarginHeld<Runtime>>::insert(ALICE, MOCK_POOL, FixedI128::from_inner(1200));
 
-			let position = eur_usd_long_1();
-			let id = 0;
-			<Positions<Runtime>>::insert(id, position);
-			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
-			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+			// 1000 + 900 - 1200
+			assert_eq!(MarginProtocol::free_margin(&ALICE, MOCK_POOL), Ok(FixedI128::from_inner(700)));
+			assert_noop!(
+				MarginProtocol::withdraw(Origin::signed(ALICE), MOCK_POOL, 1),
+				Error::<Runtime>::InsufficientFreeMargin
+			);
+			// collateral value 720 > free margin 700
+			assert_noop!(
+				MarginProtocol::withdraw_collateral(Origin::signed(ALICE), MOCK_POOL, CurrencyId::FEUR, 400),
+				Error::<Runtime>::InsufficientFreeMargin
+			);
+			assert_ok!(MarginProtocol::withdraw_collateral(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				CurrencyId::FEUR,
+				300
+			));
 
+			<MarginHeld<Runtime>>::remove(ALICE, MOCK_POOL);
 			assert_noop!(
-				MarginProtocol::close_position(Origin::signed(ALICE), 0, Price::saturating_from_rational(12, 10)),
-				Error::<Runtime>::MarketPriceTooLow
+				MarginProtocol::withdraw(Origin::signed(ALICE), MOCK_POOL, 1001),
+				Error::<Runtime>::InsufficientFreeMargin
 			);
+			assert_ok!(MarginProtocol::withdraw(Origin::signed(ALICE), MOCK_POOL, 1000));
 		});
 }
 
 #[test]
-fn close_short_position_fails_if_market_price_too_high() {
-	let alice_initial = fixedi128_saturating_from_integer_currency_cent(10_000_00);
+fn collateral_seized_to_repay_negative_balance() {
 	ExtBuilder::default()
-		.module_balance(alice_initial)
-		// EUR/USD = 1.2
-		.price(CurrencyId::FEUR, (12, 10))
+		.module_balance(fixedi128_saturating_from_integer_currency_cent(1_00))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(1_000_00))
+		.balance(ALICE, CurrencyId::FJPY, balance_saturating_from_integer_currency_cent(200_00))
+		.spread(Permill::zero())
 		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
-		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.price(CurrencyId::FEUR, (10, 1))
+		.price(CurrencyId::FJPY, (1, 1))
 		.build()
 		.execute_with(|| {
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, alice_initial);
+			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 0));
+			CollateralHaircuts::insert(CurrencyId::FJPY, Permill::from_percent(50));
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(1_00));
+			assert_ok!(MarginProtocol::deposit_collateral(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				CurrencyId::FJPY,
+				balance_saturating_from_integer_currency_cent(200_00)
+			));
 
-			let position = eur_usd_short_1();
-			let id = 0;
-			<Positions<Runtime>>::insert(id, position);
+			let position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				leverage: Leverage::LongTen,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(10_00),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100_00),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(1_00),
+			};
+			<Positions<Runtime>>::insert(0, position.clone());
 			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
-			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 0), ());
+			<MarginHeld<Runtime>>::insert(ALICE, MOCK_POOL, position.margin_held);
+			let snapshot = positions_snapshot(
+				1,
+				position.leveraged_held,
+				position.leveraged_debits,
+				FixedI128::saturating_from_integer(0),
+				FixedI128::saturating_from_integer(0),
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot);
+
+			// 90 dollars loss, 89 dollars owed after paying 1 dollar balance
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(Price::saturating_from_rational(1, 1)));
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				0,
+				Price::saturating_from_integer(0),
+				None
+			));
+			assert_eq!(
+				MockLiquidityPools::liquidity(MOCK_POOL),
+				balance_saturating_from_integer_currency_cent(1_001_00)
+			);
+
+			// 200 * 89 / 100 seized
+			assert_eq!(MarginProtocol::balances(&ALICE, MOCK_POOL), FixedI128::zero());
+			assert_eq!(
+				MarginProtocol::collaterals(ALICE, (MOCK_POOL, CurrencyId::FJPY)),
+				balance_saturating_from_integer_currency_cent(22_00)
+			);
+			assert_eq!(
+				MarginProtocol::seized_collaterals(MOCK_POOL, CurrencyId::FJPY),
+				balance_saturating_from_integer_currency_cent(178_00)
+			);
+			let event = TestEvent::margin_protocol(RawEvent::CollateralSeized(
+				ALICE,
+				MOCK_POOL,
+				CurrencyId::FJPY,
+				balance_saturating_from_integer_currency_cent(178_00),
+				balance_saturating_from_integer_currency_cent(89_00),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
 
 			assert_noop!(
-				MarginProtocol::close_position(Origin::signed(ALICE), 0, Price::saturating_from_rational(12, 10)),
-				Error::<Runtime>::MarketPriceTooHigh
+				MarginProtocol::claim_seized_collateral(Origin::signed(BOB), MOCK_POOL, CurrencyId::FJPY),
+				Error::<Runtime>::NotPoolOwner
+			);
+			assert_ok!(MarginProtocol::claim_seized_collateral(
+				Origin::signed(POOL_OWNER),
+				MOCK_POOL,
+				CurrencyId::FJPY
+			));
+			assert_eq!(
+				Currencies::free_balance(CurrencyId::FJPY, &POOL_OWNER),
+				balance_saturating_from_integer_currency_cent(178_00)
 			);
+			assert_eq!(MarginProtocol::seized_collaterals(MOCK_POOL, CurrencyId::FJPY), 0);
+			let event = TestEvent::margin_protocol(RawEvent::SeizedCollateralClaimed(
+				POOL_OWNER,
+				MOCK_POOL,
+				CurrencyId::FJPY,
+				balance_saturating_from_integer_currency_cent(178_00),
+			));
+			assert!(System::events().iter().any(|record| record.event == event));
 		});
 }
 
 #[test]
-fn deposit_works() {
-	ExtBuilder::default().alice_balance(1000).build().execute_with(|| {
-		assert_eq!(LiquidityCurrency::free_balance(&ALICE), 1000);
-		assert_eq!(LiquidityCurrency::free_balance(&MarginProtocol::account_id()), 0);
-
-		assert_ok!(MarginProtocol::deposit(Origin::signed(ALICE), MOCK_POOL, 500));
-
-		assert_eq!(LiquidityCurrency::free_balance(&ALICE), 500);
-		assert_eq!(LiquidityCurrency::free_balance(&MarginProtocol::account_id()), 500);
-		assert_eq!(MarginProtocol::balances(&ALICE, MOCK_POOL), FixedI128::from_inner(500));
+fn negative_balance_is_socialized_on_last_close() {
+	stop_out_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		// owed 50 cents from earlier closes
+		<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(-50));
 
-		let event = TestEvent::margin_protocol(RawEvent::Deposited(ALICE, MOCK_POOL, 500));
+		assert_ok!(MarginProtocol::close_position(
+			Origin::signed(ALICE),
+			0,
+			Price::saturating_from_integer(0),
+			None
+		));
+		assert_eq!(MarginProtocol::balances(ALICE, MOCK_POOL), FixedI128::zero());
+		assert_eq!(
+			MockLiquidityPools::liquidity(MOCK_POOL),
+			balance_saturating_from_integer_currency_cent(100)
+		);
+		let event = TestEvent::margin_protocol(RawEvent::NegativeBalanceSocialized(
+			ALICE,
+			MOCK_POOL,
+			balance_saturating_from_integer_currency_cent(50),
+		));
 		assert!(System::events().iter().any(|record| record.event == event));
 	});
 }
 
 #[test]
-fn deposit_fails_if_transfer_err() {
-	ExtBuilder::default().build().execute_with(|| {
-		assert_noop!(
-			MarginProtocol::deposit(Origin::signed(ALICE), MOCK_POOL, 500),
-			orml_tokens::Error::<Runtime>::BalanceTooLow
+fn negative_balance_is_covered_by_insurance_fund_first() {
+	stop_out_ext().execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		// owed 50 cents from earlier closes
+		<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(-50));
+		MockInsuranceFund::set_mock_budget(balance_saturating_from_integer_currency_cent(30));
+
+		assert_ok!(MarginProtocol::close_position(
+			Origin::signed(ALICE),
+			0,
+			Price::saturating_from_integer(0),
+			None
+		));
+		assert_eq!(MarginProtocol::balances(ALICE, MOCK_POOL), FixedI128::zero());
+		assert_eq!(
+			LiquidityCurrency::free_balance(&INSURANCE_FUND),
+			balance_saturating_from_integer_currency_cent(70)
+		);
+		assert_eq!(
+			MockLiquidityPools::liquidity(MOCK_POOL),
+			balance_saturating_from_integer_currency_cent(130)
 		);
+		let event = TestEvent::margin_protocol(RawEvent::NegativeBalanceSocialized(
+			ALICE,
+			MOCK_POOL,
+			balance_saturating_from_integer_currency_cent(20),
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
 	});
 }
 
 #[test]
-fn withdraw_works() {
+fn collateral_with_higher_haircut_seized_first() {
 	ExtBuilder::default()
-		.module_balance(FixedI128::from_inner(1000))
+		.module_balance(fixedi128_saturating_from_integer_currency_cent(1_00))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(1_000_00))
+		.balance(ALICE, CurrencyId::FJPY, balance_saturating_from_integer_currency_cent(200_00))
+		.balance(ALICE, CurrencyId::FAUD, balance_saturating_from_integer_currency_cent(200_00))
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (10, 1))
+		.price(CurrencyId::FJPY, (1, 1))
+		.price(CurrencyId::FAUD, (1, 1))
 		.build()
 		.execute_with(|| {
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, FixedI128::from_inner(1000));
-			assert_ok!(MarginProtocol::withdraw(Origin::signed(ALICE), MOCK_POOL, 500));
+			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 0));
+			CollateralHaircuts::insert(CurrencyId::FJPY, Permill::from_percent(50));
+			CollateralHaircuts::insert(CurrencyId::FAUD, Permill::from_percent(10));
+			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(1_00));
+			for currency_id in [CurrencyId::FAUD, CurrencyId::FJPY].iter() {
+				assert_ok!(MarginProtocol::deposit_collateral(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					*currency_id,
+					balance_saturating_from_integer_currency_cent(200_00)
+				));
+			}
+			assert_eq!(
+				MarginProtocol::collaterals_of_trader(&ALICE, MOCK_POOL),
+				Ok(vec![
+					(
+						CurrencyId::FJPY,
+						balance_saturating_from_integer_currency_cent(200_00),
+						fixedi128_saturating_from_integer_currency_cent(100_00)
+					),
+					(
+						CurrencyId::FAUD,
+						balance_saturating_from_integer_currency_cent(200_00),
+						fixedi128_saturating_from_integer_currency_cent(180_00)
+					),
+				])
+			);
 
-			let event = TestEvent::margin_protocol(RawEvent::Withdrew(ALICE, MOCK_POOL, 500));
-			assert!(System::events().iter().any(|record| record.event == event));
+			let position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				leverage: Leverage::LongTen,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(10_00),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100_00),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(1_00),
+			};
+			<Positions<Runtime>>::insert(0, position.clone());
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			<MarginHeld<Runtime>>::insert(ALICE, MOCK_POOL, position.margin_held);
+			let snapshot = positions_snapshot(
+				1,
+				position.leveraged_held,
+				position.leveraged_debits,
+				FixedI128::saturating_from_integer(0),
+				FixedI128::saturating_from_integer(0),
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot);
+
+			// 89 dollars owed, covered by the 50% haircut FJPY collateral
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(Price::saturating_from_rational(1, 1)));
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				0,
+				Price::saturating_from_integer(0),
+				None
+			));
+			assert_eq!(
+				MarginProtocol::seized_collaterals(MOCK_POOL, CurrencyId::FJPY),
+				balance_saturating_from_integer_currency_cent(178_00)
+			);
+			assert_eq!(MarginProtocol::seized_collaterals(MOCK_POOL, CurrencyId::FAUD), 0);
+			assert_eq!(
+				MarginProtocol::collaterals(ALICE, (MOCK_POOL, CurrencyId::FAUD)),
+				balance_saturating_from_integer_currency_cent(200_00)
+			);
 		});
 }
 
 #[test]
-fn trader_can_withdraw_unrealized_profit() {
+fn risk_params_works() {
 	ExtBuilder::default()
-		.module_balance(fixedi128_saturating_from_integer_currency_cent(10_00))
-		.spread(Permill::zero())
-		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.spread(Permill::from_percent(1))
+		.accumulated_swap_rate(EUR_JPY_PAIR, FixedI128::saturating_from_integer(1))
 		.price(CurrencyId::FEUR, (1, 1))
 		.build()
-		.execute_with(|| {
-			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 0));
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
+		.execute_with(|| {
+			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(10, 5));
+			set_trader_risk_threshold(EUR_JPY_PAIR, risk_threshold(20, 3));
+			assert_ok!(MarginProtocol::set_trading_pair_risk_threshold(
+				Origin::signed(UpdateOrigin::get()),
+				EUR_USD_PAIR,
+				None,
+				Some(risk_threshold(30, 10)),
+				Some(risk_threshold(40, 20))
+			));
+			MockFeeDiscount::set_mock_fee_discount(Permill::from_percent(50));
+
 			let position: Position<Runtime> = Position {
 				owner: ALICE,
 				pool: MOCK_POOL,
-				pair: EUR_USD_PAIR,
-				leverage: Leverage::LongTwo,
+				pair: EUR_JPY_PAIR,
+				leverage: Leverage::LongTen,
 				leveraged_held: fixedi128_saturating_from_integer_currency_cent(10_00),
-				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-1_000_00),
 				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
-				margin_held: fixedi128_saturating_from_integer_currency_cent(50),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(10_00),
 			};
-			<Positions<Runtime>>::insert(0, position);
+			<Positions<Runtime>>::insert(0, position.clone());
 			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			let snapshot = positions_snapshot(
+				1,
+				position.leveraged_held,
+				position.leveraged_debits,
+				FixedI128::saturating_from_integer(0),
+				FixedI128::saturating_from_integer(0),
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_JPY_PAIR, snapshot);
 
+			// highest thresholds of EUR/USD and open EUR/JPY apply, 1% spread discounted by half
 			assert_eq!(
-				MarginProtocol::free_margin(&ALICE, MOCK_POOL),
-				Ok(fixedi128_saturating_from_integer_currency_cent(9_50))
+				MarginProtocol::risk_params(&ALICE, MOCK_POOL, EUR_USD_PAIR),
+				RiskParams {
+					trader_threshold: risk_threshold(20, 5),
+					enp_threshold: risk_threshold(30, 10),
+					ell_threshold: risk_threshold(40, 20),
+					bid_spread: Some(5_000_000_000_000_000),
+					ask_spread: Some(5_000_000_000_000_000),
+					trader_positions_headroom: 199,
+					pool_positions_headroom: 1000,
+				}
+			);
+			assert_eq!(
+				MarginProtocol::risk_params(&ALICE, MOCK_POOL, EUR_JPY_PAIR).pool_positions_headroom,
+				999
+			);
+			assert_eq!(
+				MarginProtocol::risk_params(&BOB, MOCK_POOL, EUR_USD_PAIR).trader_threshold,
+				risk_threshold(10, 5)
 			);
-			assert_ok!(MarginProtocol::withdraw(
-				Origin::signed(ALICE),
-				MOCK_POOL,
-				balance_saturating_from_integer_currency_cent(9_50)
-			));
 		});
 }
 
 #[test]
-fn withdraw_fails_if_insufficient_free_margin() {
+fn risk_candidates_updated_on_trades() {
 	ExtBuilder::default()
 		.spread(Permill::zero())
 		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
 		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(200_00))
+		.alice_balance(balance_saturating_from_integer_currency_cent(10_00))
 		.build()
 		.execute_with(|| {
-			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(100, 0));
-			<Balances<Runtime>>::insert(ALICE, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100));
-			let position: Position<Runtime> = Position {
-				owner: ALICE,
-				pool: MOCK_POOL,
-				pair: EUR_USD_PAIR,
-				leverage: Leverage::LongTwo,
-				leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
-				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
-				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
-				margin_held: fixedi128_saturating_from_integer_currency_cent(100),
-			};
-			<Positions<Runtime>>::insert(0, position);
-			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
-
-			assert_eq!(
-				MarginProtocol::free_margin(&ALICE, MOCK_POOL),
-				Ok(fixedi128_saturating_from_integer_currency_cent(0))
+			set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(3, 1));
+			set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(10, 2));
+			set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(50, 20));
+			<Balances<Runtime>>::insert(
+				&ALICE,
+				MOCK_POOL,
+				fixedi128_saturating_from_integer_currency_cent(10_00),
 			);
+
+			// not maintained without buffer
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(200_00),
+				Price::saturating_from_integer(100),
+				None
+			));
+			assert!(!<RiskCandidateTraders<Runtime>>::contains_key(ALICE, MOCK_POOL));
+
 			assert_noop!(
-				MarginProtocol::withdraw(
-					Origin::signed(ALICE),
-					MOCK_POOL,
-					balance_saturating_from_integer_currency_cent(1)
-				),
-				Error::<Runtime>::InsufficientFreeMargin
+				MarginProtocol::set_risk_candidate_buffer(Origin::signed(BOB), Some(Permill::from_percent(5))),
+				BadOrigin
 			);
+			assert_ok!(MarginProtocol::set_risk_candidate_buffer(
+				Origin::signed(UpdateOrigin::get()),
+				Some(Permill::from_percent(5))
+			));
+			let event = TestEvent::margin_protocol(RawEvent::RiskCandidateBufferSet(Some(Permill::from_percent(5))));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			// margin level 5% <= 3% + 5%
+			assert_ok!(MarginProtocol::update_risk_candidacy(Origin::signed(BOB), ALICE, MOCK_POOL));
+			assert!(<RiskCandidateTraders<Runtime>>::contains_key(ALICE, MOCK_POOL));
+			// ENP 100% > 10% + 5%, ELL 100% > 50% + 5%
+			assert!(!RiskCandidatePools::contains_key(MOCK_POOL));
+
+			// margin level 20 / 200 = 10% > 3% + 5%
+			assert_ok!(MarginProtocol::deposit(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				balance_saturating_from_integer_currency_cent(10_00)
+			));
+			assert!(!<RiskCandidateTraders<Runtime>>::contains_key(ALICE, MOCK_POOL));
+
+			// price changes are not tracked until updated
+			// ENP and ELL (200 - 100) / 300 = 33.3% <= 50% + 5%
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(150, 100)));
+			assert!(!RiskCandidatePools::contains_key(MOCK_POOL));
+			assert_ok!(MarginProtocol::update_risk_candidacy(Origin::signed(BOB), ALICE, MOCK_POOL));
+			assert!(RiskCandidatePools::contains_key(MOCK_POOL));
+			assert!(!<RiskCandidateTraders<Runtime>>::contains_key(ALICE, MOCK_POOL));
 		});
 }
 
+#[test]
+fn genesis_config_works() {
+	let mut t = frame_system::GenesisConfig::default()
+		.build_storage::<Runtime>()
+		.unwrap();
+	let tier = ForceClosePenaltyTier {
+		threshold: Permill::from_percent(40),
+		penalty_rate: FixedU128::saturating_from_integer(2),
+		trader_share: Permill::from_percent(50),
+	};
+	GenesisConfig {
+		risk_thresholds: vec![(
+			EUR_USD_PAIR,
+			risk_threshold(3, 1),
+			risk_threshold(30, 10),
+			risk_threshold(20, 5),
+		)],
+		force_close_penalty_tiers: vec![tier],
+		risk_candidate_buffer: Some(Permill::from_percent(5)),
+	}
+	.assimilate_storage(&mut t)
+	.unwrap();
+
+	sp_io::TestExternalities::new(t).execute_with(|| {
+		assert_eq!(
+			MarginProtocol::risk_thresholds(EUR_USD_PAIR),
+			TradingPairRiskThreshold::new(
+				Some(risk_threshold(3, 1)),
+				Some(risk_threshold(30, 10)),
+				Some(risk_threshold(20, 5))
+			)
+		);
+		assert_eq!(MarginProtocol::risk_thresholds(EUR_JPY_PAIR), Default::default());
+		assert_eq!(MarginProtocol::force_close_penalty_tiers(), vec![tier]);
+		assert_eq!(MarginProtocol::risk_candidate_buffer(), Some(Permill::from_percent(5)));
+	});
+}
+
 #[test]
 fn offchain_worker_should_work() {
 	let mut ext = ExtBuilder::default()
@@ -2073,7 +6962,8 @@ fn offchain_worker_should_work() {
 			EUR_USD_PAIR,
 			Leverage::LongTwenty,
 			balance_saturating_from_integer_currency_cent(200_00),
-			Price::saturating_from_integer(100)
+			Price::saturating_from_integer(100),
+			None
 		));
 
 		assert_eq!(
@@ -2172,34 +7062,306 @@ fn offchain_worker_should_work() {
 		let liquidity_pool_force_close = pool_state.write().transactions.pop().unwrap();
 		assert!(pool_state.read().transactions.is_empty());
 
-		let tx = Extrinsic::decode(&mut &*liquidity_pool_force_close).unwrap();
+		let tx = Extrinsic::decode(&mut &*liquidity_pool_force_close).unwrap();
+
+		assert_eq!(tx.signature, None);
+		assert_eq!(
+			tx.call,
+			mock::Call::MarginProtocol(super::Call::liquidity_pool_force_close(MOCK_POOL))
+		);
+
+		// price goes down to EUR/USD 1.1/1
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(110, 100)));
+
+		MarginCalledPools::insert(MOCK_POOL, ());
+
+		assert_ok!(MarginProtocol::offchain_worker(1));
+
+		assert_eq!(pool_state.read().transactions.len(), 1);
+		let liquidity_pool_become_safe = pool_state.write().transactions.pop().unwrap();
+		assert!(pool_state.read().transactions.is_empty());
+
+		let tx = Extrinsic::decode(&mut &*liquidity_pool_become_safe).unwrap();
+
+		assert_eq!(tx.signature, None);
+		assert_eq!(
+			tx.call,
+			mock::Call::MarginProtocol(super::Call::liquidity_pool_become_safe(MOCK_POOL))
+		);
+
+		MarginCalledPools::remove(MOCK_POOL);
+	});
+}
+
+#[test]
+fn offchain_worker_skips_traders_failing_check() {
+	let mut ext = ExtBuilder::default()
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(200_00))
+		.build();
+
+	let (offchain, _state) = TestOffchainExt::new();
+	let (pool, pool_state) = TestTransactionPoolExt::new();
+	ext.register_extension(OffchainExt::new(offchain));
+	ext.register_extension(TransactionPoolExt::new(pool));
+
+	ext.execute_with(|| {
+		set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(3, 1));
+		<Balances<Runtime>>::insert(&BOB, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(10_00));
+		assert_ok!(MarginProtocol::open_position(
+			Origin::signed(BOB),
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			Leverage::LongTwenty,
+			balance_saturating_from_integer_currency_cent(200_00),
+			Price::saturating_from_integer(100),
+			None
+		));
+
+		// no JPY price to check alice
+		let position: Position<Runtime> = Position {
+			owner: ALICE,
+			pool: MOCK_POOL,
+			pair: EUR_JPY_PAIR,
+			leverage: Leverage::LongTwo,
+			leveraged_held: fixedi128_saturating_from_integer_currency_cent(100),
+			leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100),
+			open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+			margin_held: fixedi128_saturating_from_integer_currency_cent(50),
+		};
+		<Positions<Runtime>>::insert(1, position);
+		<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 1), ());
+		assert!(MarginProtocol::margin_level(&ALICE, MOCK_POOL).is_err());
+
+		// price goes down to EUR/USD 0.96/1, bob's margin level 1%
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(96, 100)));
+
+		assert_ok!(MarginProtocol::offchain_worker(1));
+		let stop_out = mock::Call::MarginProtocol(super::Call::trader_stop_out(BOB, MOCK_POOL));
+		assert!(pool_state
+			.read()
+			.transactions
+			.iter()
+			.any(|tx| Extrinsic::decode(&mut &**tx).unwrap().call == stop_out));
+	});
+}
+
+#[test]
+fn offchain_worker_skips_pools_failing_check() {
+	let mut ext = ExtBuilder::default()
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100))
+		.pool_liquidity(MOCK_POOL_1, balance_saturating_from_integer_currency_cent(100))
+		.build();
+
+	let (offchain, _state) = TestOffchainExt::new();
+	let (pool, pool_state) = TestTransactionPoolExt::new();
+	ext.register_extension(OffchainExt::new(offchain));
+	ext.register_extension(TransactionPoolExt::new(pool));
+
+	ext.execute_with(|| {
+		set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(10, 2));
+		set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(10, 2));
+		for (id, pool_id, pair) in vec![(0, MOCK_POOL, EUR_JPY_PAIR), (1, MOCK_POOL_1, EUR_USD_PAIR)] {
+			let position: Position<Runtime> = Position {
+				owner: ALICE,
+				pool: pool_id,
+				pair,
+				leverage: Leverage::LongTen,
+				leveraged_held: fixedi128_saturating_from_integer_currency_cent(100_00),
+				leveraged_debits: fixedi128_saturating_from_integer_currency_cent(-100_00),
+				open_accumulated_swap_rate: FixedI128::saturating_from_integer(1),
+				margin_held: fixedi128_saturating_from_integer_currency_cent(10_00),
+			};
+			<Positions<Runtime>>::insert(id, position.clone());
+			PositionsByPool::insert(pool_id, (pair, id), ());
+			PositionsSnapshots::insert(
+				pool_id,
+				pair,
+				positions_snapshot(
+					1,
+					position.leveraged_held,
+					position.leveraged_debits,
+					FixedI128::zero(),
+					FixedI128::zero(),
+				),
+			);
+		}
+
+		// no JPY price to check the first pool, ENP of the second 1%
+		assert_ok!(MarginProtocol::offchain_worker(1));
+		let force_close = mock::Call::MarginProtocol(super::Call::liquidity_pool_force_close(MOCK_POOL_1));
+		assert!(pool_state
+			.read()
+			.transactions
+			.iter()
+			.any(|tx| Extrinsic::decode(&mut &**tx).unwrap().call == force_close));
+	});
+}
+
+#[test]
+fn offchain_worker_checks_risk_candidates_only_between_full_scans() {
+	let mut ext = ExtBuilder::default()
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(200_00))
+		.build();
+
+	let (offchain, _state) = TestOffchainExt::new();
+	let (pool, pool_state) = TestTransactionPoolExt::new();
+	ext.register_extension(OffchainExt::new(offchain));
+	ext.register_extension(TransactionPoolExt::new(pool));
+
+	ext.execute_with(|| {
+		set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(3, 1));
+		set_enp_risk_threshold(EUR_USD_PAIR, risk_threshold(10, 2));
+		set_ell_risk_threshold(EUR_USD_PAIR, risk_threshold(50, 20));
+		assert_ok!(MarginProtocol::set_risk_candidate_buffer(
+			Origin::signed(UpdateOrigin::get()),
+			Some(Permill::from_percent(1))
+		));
+		<Balances<Runtime>>::insert(
+			&ALICE,
+			MOCK_POOL,
+			fixedi128_saturating_from_integer_currency_cent(10_00),
+		);
+
+		// margin level 5% > 3% + 1%, not a candidate
+		assert_ok!(MarginProtocol::open_position(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			Leverage::LongTwenty,
+			balance_saturating_from_integer_currency_cent(200_00),
+			Price::saturating_from_integer(100),
+			None
+		));
+		assert!(!<RiskCandidateTraders<Runtime>>::contains_key(ALICE, MOCK_POOL));
+
+		// price goes down EUR/USD 0.97/1, margin level 2%
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(97, 100)));
+
+		// not a candidate yet
+		assert_ok!(MarginProtocol::offchain_worker(1));
+		assert!(pool_state.read().transactions.is_empty());
+
+		// full scan
+		assert_ok!(MarginProtocol::offchain_worker(10));
+		assert_eq!(pool_state.read().transactions.len(), 1);
+		let trader_margin_call = pool_state.write().transactions.pop().unwrap();
+		let tx = Extrinsic::decode(&mut &*trader_margin_call).unwrap();
+		assert_eq!(
+			tx.call,
+			mock::Call::MarginProtocol(super::Call::trader_margin_call(ALICE, MOCK_POOL))
+		);
+
+		// candidate after update
+		assert_ok!(MarginProtocol::update_risk_candidacy(Origin::signed(BOB), ALICE, MOCK_POOL));
+		assert_ok!(MarginProtocol::offchain_worker(11));
+		assert_eq!(pool_state.read().transactions.len(), 1);
+		let trader_margin_call = pool_state.write().transactions.pop().unwrap();
+		let tx = Extrinsic::decode(&mut &*trader_margin_call).unwrap();
+		assert_eq!(
+			tx.call,
+			mock::Call::MarginProtocol(super::Call::trader_margin_call(ALICE, MOCK_POOL))
+		);
+	});
+}
+
+fn expect_notification(state: &mut sp_core::offchain::testing::OffchainState, id: u16, payload: &[u8]) {
+	let public_key = sp_io::crypto::ed25519_public_keys(NOTIFICATION_KEY_TYPE).remove(0);
+	let signature = sp_io::crypto::ed25519_sign(NOTIFICATION_KEY_TYPE, &public_key, payload).unwrap();
+	let hex_string = |data: &[u8]| String::from_utf8(hex_encode(data)).unwrap();
+	state.expect_request(
+		id,
+		PendingRequest {
+			method: "POST".into(),
+			uri: "https://example.com/notify".into(),
+			headers: vec![
+				("Content-Type".into(), "application/json".into()),
+				("X-Laminar-Public-Key".into(), hex_string(public_key.as_ref())),
+				("X-Laminar-Signature".into(), hex_string(signature.as_ref())),
+			],
+			body: payload.to_vec(),
+			response: Some(b"".to_vec()),
+			sent: true,
+			..Default::default()
+		},
+	);
+}
+
+#[test]
+fn offchain_notification_should_work() {
+	let mut ext = ExtBuilder::default()
+		.spread(Permill::zero())
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.price(CurrencyId::FEUR, (1, 1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(200_00))
+		.build();
+
+	let (offchain, state) = TestOffchainExt::new();
+	let keystore = KeyStore::new();
+	keystore
+		.write()
+		.ed25519_generate_new(NOTIFICATION_KEY_TYPE, None)
+		.unwrap();
+	ext.register_extension(OffchainExt::new(offchain));
+	ext.register_extension(KeystoreExt(keystore));
+
+	ext.execute_with(|| {
+		set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(3, 1));
+		<Balances<Runtime>>::insert(
+			&ALICE,
+			MOCK_POOL,
+			fixedi128_saturating_from_integer_currency_cent(10_00),
+		);
+		assert_ok!(MarginProtocol::open_position(
+			Origin::signed(ALICE),
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			Leverage::LongTwenty,
+			balance_saturating_from_integer_currency_cent(200_00),
+			Price::saturating_from_integer(100),
+			None
+		));
+
+		// not configured
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(97, 100)));
+		assert_ok!(MarginProtocol::offchain_notify(1));
 
-		assert_eq!(tx.signature, None);
+		StorageValueRef::persistent(NOTIFICATION_ENDPOINT).set(&b"http://example.com/notify".to_vec());
 		assert_eq!(
-			tx.call,
-			mock::Call::MarginProtocol(super::Call::liquidity_pool_force_close(MOCK_POOL))
+			MarginProtocol::offchain_notify(1),
+			Err(OffchainErr::InvalidNotificationEndpoint)
 		);
 
-		// price goes down to EUR/USD 1.1/1
-		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(110, 100)));
-
-		MarginCalledPools::insert(MOCK_POOL, ());
-
-		assert_ok!(MarginProtocol::offchain_worker(1));
+		StorageValueRef::persistent(NOTIFICATION_ENDPOINT).set(&b"https://example.com/notify".to_vec());
+		StorageValueRef::persistent(NOTIFICATION_ACCOUNTS).set(&vec![ALICE]);
 
-		assert_eq!(pool_state.read().transactions.len(), 1);
-		let liquidity_pool_become_safe = pool_state.write().transactions.pop().unwrap();
-		assert!(pool_state.read().transactions.is_empty());
+		// margin called
+		expect_notification(
+			&mut state.write(),
+			0,
+			br#"{"block_number":1,"trader":"0x0000000000000000","pool_id":100,"risk":"margin_call"}"#,
+		);
+		assert_ok!(MarginProtocol::offchain_notify(1));
 
-		let tx = Extrinsic::decode(&mut &*liquidity_pool_become_safe).unwrap();
+		// notified only once per risk change
+		assert_ok!(MarginProtocol::offchain_notify(2));
 
-		assert_eq!(tx.signature, None);
-		assert_eq!(
-			tx.call,
-			mock::Call::MarginProtocol(super::Call::liquidity_pool_become_safe(MOCK_POOL))
+		// price goes down to EUR/USD 0.96/1, stopped out
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(96, 100)));
+		expect_notification(
+			&mut state.write(),
+			1,
+			br#"{"block_number":3,"trader":"0x0000000000000000","pool_id":100,"risk":"stop_out"}"#,
 		);
-
-		MarginCalledPools::remove(MOCK_POOL);
+		assert_ok!(MarginProtocol::offchain_notify(3));
 	});
 }
 
@@ -2225,6 +7387,17 @@ fn liquidity_pool_manager_can_remove_works() {
 	});
 }
 
+#[test]
+fn liquidity_pool_manager_cannot_remove_pool_with_liabilities() {
+	ExtBuilder::default().build().execute_with(|| {
+		MockPoolLiabilities::set_mock_liabilities(Some(Default::default()));
+		assert!(!<MarginProtocol as BaseLiquidityPoolManager<
+			LiquidityPoolId,
+			Balance,
+		>>::can_remove(MOCK_POOL));
+	});
+}
+
 #[test]
 fn liquidity_pool_manager_get_required_deposit_works() {
 	ExtBuilder::default()
@@ -2327,9 +7500,10 @@ fn trader_open_positions_limit() {
 					EUR_USD_PAIR,
 					Leverage::LongTen,
 					balance_saturating_from_integer_currency_cent(10_00),
-					Price::saturating_from_integer(100)
+					Price::saturating_from_integer(100),
+					None
 				),
-				Error::<Runtime>::CannotOpenMorePosition
+				Error::<Runtime>::TraderPositionsLimitReached
 			);
 		});
 }
@@ -2369,6 +7543,7 @@ fn pool_open_positions_limit() {
 					Leverage::LongTen,
 					balance_saturating_from_integer_currency_cent(1_00),
 					Price::saturating_from_integer(100),
+					None,
 				);
 			}
 
@@ -2393,7 +7568,26 @@ fn pool_open_positions_limit() {
 					EUR_USD_PAIR,
 					Leverage::LongTen,
 					balance_saturating_from_integer_currency_cent(10_00),
-					Price::saturating_from_integer(100)
+					Price::saturating_from_integer(100),
+					None
+				),
+				Error::<Runtime>::TraderPositionsLimitReached
+			);
+
+			// reach the limit of open positions for a pool & pair
+			PositionsSnapshots::mutate(MOCK_POOL, EUR_USD_PAIR, |snapshot| {
+				snapshot.positions_count = <Runtime as Trait>::GetPoolMaxOpenPositions::get() as PositionId;
+			});
+			<Balances<Runtime>>::insert(&BOB, MOCK_POOL, fixedi128_saturating_from_integer_currency_cent(100_00));
+			assert_noop!(
+				MarginProtocol::open_position(
+					Origin::signed(BOB),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					Leverage::LongTen,
+					balance_saturating_from_integer_currency_cent(10_00),
+					Price::saturating_from_integer(100),
+					None
 				),
 				Error::<Runtime>::CannotOpenMorePosition
 			);
@@ -2410,15 +7604,15 @@ fn set_trading_pair_risk_threshold_works() {
 		.build()
 		.execute_with(|| {
 			assert_eq!(
-				MarginProtocol::trader_risk_threshold(EUR_USD_PAIR).unwrap(),
+				MarginProtocol::trader_risk_threshold(MOCK_POOL, EUR_USD_PAIR).unwrap(),
 				risk_threshold(0, 0)
 			);
 			assert_eq!(
-				MarginProtocol::liquidity_pool_ell_threshold(EUR_USD_PAIR).unwrap(),
+				MarginProtocol::liquidity_pool_ell_threshold(MOCK_POOL, EUR_USD_PAIR).unwrap(),
 				risk_threshold(0, 0)
 			);
 			assert_eq!(
-				MarginProtocol::liquidity_pool_enp_threshold(EUR_USD_PAIR).unwrap(),
+				MarginProtocol::liquidity_pool_enp_threshold(MOCK_POOL, EUR_USD_PAIR).unwrap(),
 				risk_threshold(0, 0)
 			);
 
@@ -2448,15 +7642,15 @@ fn set_trading_pair_risk_threshold_works() {
 			));
 			assert!(System::events().iter().any(|record| record.event == event));
 			assert_eq!(
-				MarginProtocol::trader_risk_threshold(EUR_USD_PAIR).unwrap(),
+				MarginProtocol::trader_risk_threshold(MOCK_POOL, EUR_USD_PAIR).unwrap(),
 				risk_threshold(1, 2)
 			);
 			assert_eq!(
-				MarginProtocol::liquidity_pool_enp_threshold(EUR_USD_PAIR).unwrap(),
+				MarginProtocol::liquidity_pool_enp_threshold(MOCK_POOL, EUR_USD_PAIR).unwrap(),
 				risk_threshold(0, 0)
 			);
 			assert_eq!(
-				MarginProtocol::liquidity_pool_ell_threshold(EUR_USD_PAIR).unwrap(),
+				MarginProtocol::liquidity_pool_ell_threshold(MOCK_POOL, EUR_USD_PAIR).unwrap(),
 				risk_threshold(0, 0)
 			);
 
@@ -2475,15 +7669,15 @@ fn set_trading_pair_risk_threshold_works() {
 			));
 			assert!(System::events().iter().any(|record| record.event == event));
 			assert_eq!(
-				MarginProtocol::trader_risk_threshold(EUR_USD_PAIR).unwrap(),
+				MarginProtocol::trader_risk_threshold(MOCK_POOL, EUR_USD_PAIR).unwrap(),
 				risk_threshold(1, 2)
 			);
 			assert_eq!(
-				MarginProtocol::liquidity_pool_enp_threshold(EUR_USD_PAIR).unwrap(),
+				MarginProtocol::liquidity_pool_enp_threshold(MOCK_POOL, EUR_USD_PAIR).unwrap(),
 				risk_threshold(3, 4)
 			);
 			assert_eq!(
-				MarginProtocol::liquidity_pool_ell_threshold(EUR_USD_PAIR).unwrap(),
+				MarginProtocol::liquidity_pool_ell_threshold(MOCK_POOL, EUR_USD_PAIR).unwrap(),
 				risk_threshold(0, 0)
 			);
 
@@ -2502,20 +7696,86 @@ fn set_trading_pair_risk_threshold_works() {
 			));
 			assert!(System::events().iter().any(|record| record.event == event));
 			assert_eq!(
-				MarginProtocol::trader_risk_threshold(EUR_USD_PAIR).unwrap(),
+				MarginProtocol::trader_risk_threshold(MOCK_POOL, EUR_USD_PAIR).unwrap(),
 				risk_threshold(1, 2)
 			);
 			assert_eq!(
-				MarginProtocol::liquidity_pool_enp_threshold(EUR_USD_PAIR).unwrap(),
+				MarginProtocol::liquidity_pool_enp_threshold(MOCK_POOL, EUR_USD_PAIR).unwrap(),
 				risk_threshold(3, 4)
 			);
 			assert_eq!(
-				MarginProtocol::liquidity_pool_ell_threshold(EUR_USD_PAIR).unwrap(),
+				MarginProtocol::liquidity_pool_ell_threshold(MOCK_POOL, EUR_USD_PAIR).unwrap(),
 				risk_threshold(5, 6)
 			);
 		});
 }
 
+#[test]
+fn pool_risk_threshold_overrides_work() {
+	ExtBuilder::default().build().execute_with(|| {
+		set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(5, 3));
+		let tighter = TradingPairRiskThreshold::new(Some(risk_threshold(10, 5)), None, None);
+
+		assert_noop!(
+			MarginProtocol::set_pool_risk_threshold(Origin::signed(ALICE), MOCK_POOL, EUR_USD_PAIR, tighter),
+			Error::<Runtime>::NotPoolOwner
+		);
+		assert_noop!(
+			MarginProtocol::set_pool_risk_threshold(
+				Origin::signed(POOL_OWNER),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				TradingPairRiskThreshold::new(Some(risk_threshold(10, 2)), None, None)
+			),
+			Error::<Runtime>::PoolRiskThresholdTooLoose
+		);
+		assert_noop!(
+			MarginProtocol::set_pool_risk_threshold(Origin::signed(POOL_OWNER), MOCK_POOL, JPY_USD_PAIR, tighter),
+			Error::<Runtime>::NoRiskThreshold
+		);
+
+		assert_ok!(MarginProtocol::set_pool_risk_threshold(
+			Origin::signed(POOL_OWNER),
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			tighter
+		));
+		let event = TestEvent::margin_protocol(RawEvent::PoolRiskThresholdSet(MOCK_POOL, EUR_USD_PAIR, tighter));
+		assert!(System::events().iter().any(|record| record.event == event));
+		assert_eq!(
+			MarginProtocol::trader_risk_threshold(MOCK_POOL, EUR_USD_PAIR),
+			Some(risk_threshold(10, 5))
+		);
+		assert_eq!(
+			MarginProtocol::risk_params(&ALICE, MOCK_POOL, EUR_USD_PAIR).trader_threshold,
+			risk_threshold(10, 5)
+		);
+		assert_eq!(
+			MarginProtocol::trader_risk_threshold(MOCK_POOL_1, EUR_USD_PAIR),
+			Some(risk_threshold(5, 3))
+		);
+
+		// the trading pair risk threshold applies if tightened above the override
+		set_trader_risk_threshold(EUR_USD_PAIR, risk_threshold(20, 3));
+		assert_eq!(
+			MarginProtocol::trader_risk_threshold(MOCK_POOL, EUR_USD_PAIR),
+			Some(risk_threshold(20, 5))
+		);
+
+		assert_ok!(MarginProtocol::set_pool_risk_threshold(
+			Origin::signed(UpdateOrigin::get()),
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			Default::default()
+		));
+		assert!(!PoolRiskThresholds::contains_key(MOCK_POOL, EUR_USD_PAIR));
+		assert_eq!(
+			MarginProtocol::trader_risk_threshold(MOCK_POOL, EUR_USD_PAIR),
+			Some(risk_threshold(20, 3))
+		);
+	});
+}
+
 #[test]
 fn ensure_can_enable_trading_pair_works() {
 	ExtBuilder::default()
@@ -2698,3 +7958,326 @@ fn risk_threshold_of_trader_is_per_pool() {
 			);
 		});
 }
+
+#[test]
+fn check_state_works() {
+	ExtBuilder::default()
+		.alice_balance(balance_saturating_from_integer_currency_cent(10_000_00))
+		.price(CurrencyId::FEUR, (12, 10))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			assert_ok!(MarginProtocol::deposit(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				balance_saturating_from_integer_currency_cent(1_000_00)
+			));
+			assert_ok!(MarginProtocol::check_state());
+
+			for &(leverage, price) in [
+				(Leverage::LongTwenty, Price::max_value()),
+				(Leverage::ShortTwenty, Price::zero()),
+			]
+			.iter()
+			{
+				assert_ok!(MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					leverage,
+					balance_saturating_from_integer_currency_cent(1_000_00),
+					price,
+					None,
+				));
+			}
+			assert_ok!(MarginProtocol::check_state());
+
+			MockPrices::set_mock_price(CurrencyId::FEUR, Some(Price::saturating_from_rational(11, 10)));
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				0,
+				Price::zero(),
+				None
+			));
+			assert_ok!(MarginProtocol::check_state());
+		});
+}
+
+#[test]
+fn open_interest_updated_on_open_and_close() {
+	ExtBuilder::default()
+		.alice_balance(balance_saturating_from_integer_currency_cent(10_000_00))
+		.price(CurrencyId::FEUR, (12, 10))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			assert_ok!(MarginProtocol::deposit(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				balance_saturating_from_integer_currency_cent(1_000_00)
+			));
+			for &(leverage, amount, price) in [
+				(Leverage::LongTwenty, 1_000_00, Price::max_value()),
+				(Leverage::LongTen, 500_00, Price::max_value()),
+				(Leverage::ShortTwenty, 2_000_00, Price::zero()),
+			]
+			.iter()
+			{
+				assert_ok!(MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					leverage,
+					balance_saturating_from_integer_currency_cent(amount),
+					price,
+					None,
+				));
+			}
+			assert_eq!(
+				MarginProtocol::open_interest(EUR_USD_PAIR),
+				OpenInterest {
+					long: fixedi128_saturating_from_integer_currency_cent(1_500_00),
+					short: fixedi128_saturating_from_integer_currency_cent(2_000_00),
+					long_positions_count: 2,
+					short_positions_count: 1,
+				}
+			);
+			assert_eq!(MarginProtocol::open_interest(EUR_JPY_PAIR), OpenInterest::default());
+
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				0,
+				Price::zero(),
+				None
+			));
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				2,
+				Price::max_value(),
+				None
+			));
+			assert_eq!(
+				MarginProtocol::open_interest(EUR_USD_PAIR),
+				OpenInterest {
+					long: fixedi128_saturating_from_integer_currency_cent(500_00),
+					short: FixedI128::zero(),
+					long_positions_count: 1,
+					short_positions_count: 0,
+				}
+			);
+			assert_ok!(MarginProtocol::check_state());
+		});
+}
+
+#[test]
+fn trading_volume_updated_on_open_and_close() {
+	ExtBuilder::default()
+		.alice_balance(balance_saturating_from_integer_currency_cent(10_000_00))
+		.price(CurrencyId::FEUR, (12, 10))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			assert_ok!(MarginProtocol::deposit(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				balance_saturating_from_integer_currency_cent(1_000_00)
+			));
+			let open = |leverage, price| {
+				assert_ok!(MarginProtocol::open_position(
+					Origin::signed(ALICE),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					leverage,
+					balance_saturating_from_integer_currency_cent(1_000_00),
+					price,
+					None,
+				));
+			};
+			let notional = |position_id| {
+				let position = MarginProtocol::positions(position_id).unwrap();
+				u128_from_fixed_i128(fixed_i128_checked_abs(position.leveraged_debits).unwrap())
+			};
+			let volumes = |period_start, opened, closed, previous| RollingVolume {
+				period_start,
+				current: TradingVolume { opened, closed },
+				previous,
+			};
+
+			open(Leverage::LongTwenty, Price::max_value());
+			open(Leverage::ShortTwenty, Price::zero());
+			let (long, short) = (notional(0), notional(1));
+			assert!(long > 0 && short > 0);
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				0,
+				Price::zero(),
+				None
+			));
+			assert_eq!(
+				MarginProtocol::trading_volume(MOCK_POOL, EUR_USD_PAIR),
+				volumes(0, long + short, long, TradingVolume::default())
+			);
+			assert_eq!(
+				MarginProtocol::trading_volume(MOCK_POOL, EUR_JPY_PAIR),
+				RollingVolume::default()
+			);
+
+			// rolled over on the next period
+			System::set_block_number(10);
+			let previous = TradingVolume {
+				opened: long + short,
+				closed: long,
+			};
+			assert_eq!(
+				MarginProtocol::trading_volume(MOCK_POOL, EUR_USD_PAIR),
+				volumes(10, 0, 0, previous.clone())
+			);
+			assert_ok!(MarginProtocol::close_position(
+				Origin::signed(ALICE),
+				1,
+				Price::max_value(),
+				None
+			));
+			assert_eq!(
+				MarginProtocol::trading_volume(MOCK_POOL, EUR_USD_PAIR),
+				volumes(10, 0, short, previous)
+			);
+
+			// not traded in the previous period
+			System::set_block_number(30);
+			assert_eq!(
+				MarginProtocol::trading_volume(MOCK_POOL, EUR_USD_PAIR),
+				volumes(30, 0, 0, TradingVolume::default())
+			);
+		});
+}
+
+#[test]
+fn check_state_fails_on_corrupted_state() {
+	ExtBuilder::default()
+		.alice_balance(balance_saturating_from_integer_currency_cent(10_000_00))
+		.price(CurrencyId::FEUR, (12, 10))
+		.accumulated_swap_rate(EUR_USD_PAIR, FixedI128::saturating_from_integer(1))
+		.pool_liquidity(MOCK_POOL, balance_saturating_from_integer_currency_cent(100_000_00))
+		.build()
+		.execute_with(|| {
+			assert_ok!(MarginProtocol::deposit(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				balance_saturating_from_integer_currency_cent(1_000_00)
+			));
+			assert_ok!(MarginProtocol::open_position(
+				Origin::signed(ALICE),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Leverage::LongTwenty,
+				balance_saturating_from_integer_currency_cent(1_000_00),
+				Price::max_value(),
+				None,
+			));
+			assert_ok!(MarginProtocol::check_state());
+
+			<PositionsByTrader<Runtime>>::remove(ALICE, (MOCK_POOL, 0));
+			assert_eq!(MarginProtocol::check_state(), Err("position not indexed by trader"));
+			<PositionsByTrader<Runtime>>::insert(BOB, (MOCK_POOL, 0), ());
+			assert_eq!(MarginProtocol::check_state(), Err("position not indexed by trader"));
+			<PositionsByTrader<Runtime>>::insert(ALICE, (MOCK_POOL, 0), ());
+			assert_eq!(MarginProtocol::check_state(), Err("trader index mismatches position"));
+			<PositionsByTrader<Runtime>>::remove(BOB, (MOCK_POOL, 0));
+
+			PositionsByPool::insert(MOCK_POOL, (EUR_USD_PAIR, 1), ());
+			assert_eq!(MarginProtocol::check_state(), Err("pool index of nonexistent position"));
+			PositionsByPool::remove(MOCK_POOL, (EUR_USD_PAIR, 1));
+
+			let open_interest = MarginProtocol::open_interest(EUR_USD_PAIR);
+			OpenInterests::remove(EUR_USD_PAIR);
+			assert_eq!(MarginProtocol::check_state(), Err("open interest mismatches positions"));
+			OpenInterests::insert(EUR_USD_PAIR, open_interest);
+
+			let snapshot = MarginProtocol::pool_positions_snapshots(MOCK_POOL, EUR_USD_PAIR);
+			let mut corrupted = snapshot.clone();
+			corrupted.short.held = snapshot.long.held;
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, corrupted);
+			assert_eq!(
+				MarginProtocol::check_state(),
+				Err("snapshot amounts mismatch positions")
+			);
+			PositionsSnapshots::insert(MOCK_POOL, EUR_USD_PAIR, snapshot);
+
+			<MarginHeld<Runtime>>::insert(ALICE, MOCK_POOL, FixedI128::zero());
+			assert_eq!(MarginProtocol::check_state(), Err("margin held mismatches positions"));
+			<MarginHeld<Runtime>>::remove(ALICE, MOCK_POOL);
+			assert_eq!(MarginProtocol::check_state(), Err("margin held mismatches positions"));
+		});
+}
+
+#[test]
+fn check_state_fails_if_module_account_less_than_balances() {
+	ExtBuilder::default()
+		.alice_balance(balance_saturating_from_integer_currency_cent(10_000_00))
+		.build()
+		.execute_with(|| {
+			assert_ok!(MarginProtocol::deposit(Origin::signed(ALICE), MOCK_POOL, 1_000));
+			<Balances<Runtime>>::insert(BOB, MOCK_POOL, FixedI128::from_inner(-1_000));
+			assert_ok!(MarginProtocol::check_state());
+
+			<Balances<Runtime>>::insert(BOB, MOCK_POOL, FixedI128::from_inner(1));
+			assert_eq!(
+				MarginProtocol::check_state(),
+				Err("module account balance less than trader balances")
+			);
+		});
+}
+
+#[test]
+fn offchain_worker_triggers_stop_loss() {
+	let mut ext = stop_out_ext();
+	let (offchain, _state) = TestOffchainExt::new();
+	let (pool, pool_state) = TestTransactionPoolExt::new();
+	ext.register_extension(OffchainExt::new(offchain));
+	ext.register_extension(TransactionPoolExt::new(pool));
+
+	ext.execute_with(|| {
+		eur_usd_long_with_stop_loss();
+		assert_ok!(MarginProtocol::offchain_worker(1));
+		assert!(pool_state.read().transactions.is_empty());
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(90, 100)));
+		assert_ok!(MarginProtocol::offchain_worker(2));
+		assert_eq!(pool_state.read().transactions.len(), 1);
+		let tx = pool_state.write().transactions.pop().unwrap();
+		let tx = Extrinsic::decode(&mut &*tx).unwrap();
+		assert_eq!(tx.signature, None);
+		assert_eq!(tx.call, mock::Call::MarginProtocol(super::Call::trigger_stop_loss(0)));
+	});
+}
+
+#[test]
+fn offchain_worker_executes_order() {
+	let mut ext = order_ext();
+	let (offchain, _state) = TestOffchainExt::new();
+	let (pool, pool_state) = TestTransactionPoolExt::new();
+	ext.register_extension(OffchainExt::new(offchain));
+	ext.register_extension(TransactionPoolExt::new(pool));
+
+	ext.execute_with(|| {
+		place_eur_usd_order(
+			Leverage::LongTwenty,
+			OrderKind::Limit,
+			Price::saturating_from_rational(90, 100),
+		);
+		assert_ok!(MarginProtocol::offchain_worker(1));
+		assert!(pool_state.read().transactions.is_empty());
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, Some(FixedU128::saturating_from_rational(90, 100)));
+		assert_ok!(MarginProtocol::offchain_worker(2));
+		assert_eq!(pool_state.read().transactions.len(), 1);
+		let tx = pool_state.write().transactions.pop().unwrap();
+		let tx = Extrinsic::decode(&mut &*tx).unwrap();
+		assert_eq!(tx.signature, None);
+		assert_eq!(tx.call, mock::Call::MarginProtocol(super::Call::execute_order(0)));
+	});
+}
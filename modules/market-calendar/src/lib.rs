@@ -0,0 +1,278 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Decode, Encode};
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage, ensure,
+	traits::{EnsureOrigin, Get, Time},
+	IterableStorageMap,
+};
+use orml_utilities::with_transaction_result;
+use sp_runtime::{
+	traits::{AtLeast32Bit, Saturating, Zero},
+	RuntimeDebug,
+};
+use sp_std::prelude::*;
+
+use module_primitives::{CurrencyId, TradingPair};
+
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+
+mod mock;
+mod tests;
+
+pub type MomentOf<T> = <<T as Trait>::Time as Time>::Moment;
+
+pub type ClosureId = u32;
+
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum ClosureKind {
+	/// Markets are closed for the whole day.
+	Holiday,
+	/// Markets close early.
+	HalfDay,
+}
+
+/// Period in which the markets of `pairs` are closed.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct MarketClosure<Moment> {
+	pub pairs: Vec<TradingPair>,
+	pub kind: ClosureKind,
+	/// Markets are closed from `start`, inclusive.
+	pub start: Moment,
+	/// Markets are reopened at `end`, exclusive.
+	pub end: Moment,
+}
+
+impl<Moment: PartialOrd> MarketClosure<Moment> {
+	fn is_active(&self, now: &Moment) -> bool {
+		self.start <= *now && *now < self.end
+	}
+}
+
+/// Weekly trading session of a trading pair, in offsets from the start of the week.
+///
+/// Weeks are aligned to the Unix epoch, which started on Thursday 00:00 UTC. A session with `close` before `open`
+/// spans over the start of the week.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct WeeklySchedule<Moment> {
+	/// Markets open at this offset, inclusive.
+	pub open: Moment,
+	/// Markets close at this offset, exclusive.
+	pub close: Moment,
+}
+
+impl<Moment: AtLeast32Bit + Copy> WeeklySchedule<Moment> {
+	/// Return the start of the ongoing weekly closure at `now`, or `None` if in the trading session.
+	fn closed_since(&self, now: Moment, week: Moment) -> Option<Moment> {
+		if week.is_zero() {
+			return None;
+		}
+		let offset = now % week;
+		let in_session = if self.open <= self.close {
+			self.open <= offset && offset < self.close
+		} else {
+			offset < self.close || self.open <= offset
+		};
+		if in_session {
+			return None;
+		}
+
+		let close = (now - offset).saturating_add(self.close);
+		Some(if close > now { close.saturating_sub(week) } else { close })
+	}
+}
+
+pub trait Trait: frame_system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+
+	/// Required origin for publishing and cancelling market closures.
+	type UpdateOrigin: EnsureOrigin<Self::Origin>;
+
+	/// Time used to check if markets are closed.
+	type Time: Time;
+
+	/// Max number of published closures not ended yet.
+	type MaxClosures: Get<u32>;
+
+	/// Length of a week in `Time` moments.
+	type WeekLength: Get<MomentOf<Self>>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as MarketCalendar {
+		/// Next market closure ID.
+		NextClosureId get(fn next_closure_id): ClosureId;
+
+		/// Published market closures. Ended closures are pruned on publishing new ones.
+		Closures get(fn closures): map hasher(twox_64_concat) ClosureId => Option<MarketClosure<MomentOf<T>>>;
+
+		/// Weekly trading sessions of trading pairs. Markets of pairs without a schedule are open all week.
+		WeeklySchedules get(fn weekly_schedule): map hasher(twox_64_concat) TradingPair => Option<WeeklySchedule<MomentOf<T>>>;
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where
+		Moment = MomentOf<T>,
+	{
+		/// Market closure published: [closure_id, closure]
+		ClosurePublished(ClosureId, MarketClosure<Moment>),
+
+		/// Market closure cancelled: [closure_id]
+		ClosureCancelled(ClosureId),
+
+		/// Weekly trading session of a trading pair set: [pair, schedule]
+		WeeklyScheduleSet(TradingPair, Option<WeeklySchedule<Moment>>),
+	}
+}
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// No trading pair is specified.
+		NoTradingPair,
+		/// The closure doesn't end after it starts, or has already ended.
+		InvalidClosurePeriod,
+		/// Reached the max number of closures.
+		TooManyClosures,
+		/// Closure ID overflow.
+		NoAvailableClosureId,
+		/// The closure is not found.
+		ClosureNotFound,
+		/// Open and close offsets of the schedule are the same, or not within a week.
+		InvalidSchedule,
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		type Error = Error<T>;
+
+		const MaxClosures: u32 = T::MaxClosures::get();
+
+		const WeekLength: MomentOf<T> = T::WeekLength::get();
+
+		fn deposit_event() = default;
+
+		/// Publish a closure of the markets of `pairs`, from `start` until `end`.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000 + T::DbWeight::get().reads_writes(T::MaxClosures::get().into(), 2)]
+		pub fn publish_closure(
+			origin,
+			pairs: Vec<TradingPair>,
+			kind: ClosureKind,
+			start: MomentOf<T>,
+			end: MomentOf<T>,
+		) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				ensure!(!pairs.is_empty(), Error::<T>::NoTradingPair);
+				ensure!(start < end && T::Time::now() < end, Error::<T>::InvalidClosurePeriod);
+
+				Self::prune_ended_closures();
+				ensure!(
+					(Closures::<T>::iter().count() as u32) < T::MaxClosures::get(),
+					Error::<T>::TooManyClosures
+				);
+
+				let id = Self::next_closure_id();
+				NextClosureId::put(id.checked_add(1).ok_or(Error::<T>::NoAvailableClosureId)?);
+				let closure = MarketClosure { pairs, kind, start, end };
+				Closures::<T>::insert(id, closure.clone());
+				Self::deposit_event(RawEvent::ClosurePublished(id, closure));
+				Ok(())
+			})?;
+		}
+
+		/// Cancel the closure of `closure_id`.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn cancel_closure(origin, closure_id: ClosureId) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				ensure!(Closures::<T>::contains_key(closure_id), Error::<T>::ClosureNotFound);
+
+				Closures::<T>::remove(closure_id);
+				Self::deposit_event(RawEvent::ClosureCancelled(closure_id));
+				Ok(())
+			})?;
+		}
+
+		/// Set the weekly trading session of `pair`. `None` to open its market all week.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_weekly_schedule(origin, pair: TradingPair, schedule: Option<WeeklySchedule<MomentOf<T>>>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				match schedule {
+					Some(s) => {
+						let week = T::WeekLength::get();
+						ensure!(
+							s.open != s.close && s.open < week && s.close < week,
+							Error::<T>::InvalidSchedule
+						);
+						WeeklySchedules::<T>::insert(pair, s);
+					}
+					None => WeeklySchedules::<T>::remove(pair),
+				}
+				Self::deposit_event(RawEvent::WeeklyScheduleSet(pair, schedule));
+				Ok(())
+			})?;
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Return the start of the ongoing closure of `pair`, or `None` if its market is open.
+	///
+	/// Markets out of the weekly trading session are closed since the session closed. If multiple ongoing closures
+	/// overlap, the earliest start is returned.
+	pub fn closed_since(pair: TradingPair) -> Option<MomentOf<T>> {
+		Self::earliest_active_start(|p| *p == pair)
+	}
+
+	/// Return the start of the ongoing closure of any pair with `currency_id` as base or quote, or `None` if all
+	/// of them are open.
+	///
+	/// Prices of closed markets are not fed, so price expiry could be relaxed to count from the closure start.
+	pub fn currency_closed_since(currency_id: CurrencyId) -> Option<MomentOf<T>> {
+		Self::earliest_active_start(|p| p.base == currency_id || p.quote == currency_id)
+	}
+
+	fn earliest_active_start(f: impl Fn(&TradingPair) -> bool) -> Option<MomentOf<T>> {
+		let now = T::Time::now();
+		let week = T::WeekLength::get();
+		let weekly_closures = WeeklySchedules::<T>::iter()
+			.filter(|(pair, _)| f(pair))
+			.filter_map(|(_, s)| s.closed_since(now, week));
+		Closures::<T>::iter()
+			.filter(|(_, c)| c.is_active(&now) && c.pairs.iter().any(&f))
+			.map(|(_, c)| c.start)
+			.chain(weekly_closures)
+			.min()
+	}
+
+	fn prune_ended_closures() {
+		let now = T::Time::now();
+		let ended: Vec<ClosureId> = Closures::<T>::iter()
+			.filter(|(_, c)| c.end <= now)
+			.map(|(id, _)| id)
+			.collect();
+		for id in ended {
+			Closures::<T>::remove(id);
+		}
+	}
+}
+
+impl<T: Trait> module_traits::MarketCalendar for Module<T> {
+	fn is_market_closed(pair: TradingPair) -> bool {
+		Self::closed_since(pair).is_some()
+	}
+}
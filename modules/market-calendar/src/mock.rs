@@ -0,0 +1,87 @@
+//! Mocks for the market-calendar module.
+
+#![cfg(test)]
+
+use frame_support::{impl_outer_event, impl_outer_origin, ord_parameter_types, parameter_types};
+use frame_system as system;
+use system::EnsureSignedBy;
+
+use super::*;
+
+impl_outer_origin! {
+	pub enum Origin for Runtime {}
+}
+
+mod market_calendar {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for Runtime {
+		frame_system<T>,
+		market_calendar<T>,
+	}
+}
+
+ord_parameter_types! {
+	pub const UpdateOrigin: AccountId = 0;
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Runtime;
+
+type AccountId = u64;
+laminar_test_support::impl_mock_system!(Runtime, AccountId, TestEvent, ());
+pub type System = system::Module<Runtime>;
+
+parameter_types! {
+	pub const MinimumPeriod: u64 = 5;
+}
+impl pallet_timestamp::Trait for Runtime {
+	type Moment = u64;
+	type OnTimestampSet = ();
+	type MinimumPeriod = MinimumPeriod;
+}
+pub type Timestamp = pallet_timestamp::Module<Runtime>;
+
+parameter_types! {
+	pub const MaxClosures: u32 = 2;
+	pub const WeekLength: u64 = 1000;
+}
+
+impl Trait for Runtime {
+	type Event = TestEvent;
+	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
+	type Time = Timestamp;
+	type MaxClosures = MaxClosures;
+	type WeekLength = WeekLength;
+}
+
+pub type MarketCalendar = Module<Runtime>;
+
+const ALICE_ACC_ID: AccountId = 0;
+pub fn alice() -> Origin {
+	Origin::signed(ALICE_ACC_ID)
+}
+
+const BOB_ACC_ID: AccountId = 1;
+pub fn bob() -> Origin {
+	Origin::signed(BOB_ACC_ID)
+}
+
+#[derive(Default)]
+pub struct ExtBuilder;
+
+impl ExtBuilder {
+	pub fn build(self) -> sp_io::TestExternalities {
+		let t = frame_system::GenesisConfig::default()
+			.build_storage::<Runtime>()
+			.unwrap()
+			.into();
+
+		let mut ext = sp_io::TestExternalities::new(t);
+		ext.execute_with(|| System::set_block_number(1));
+		ext
+	}
+}
@@ -0,0 +1,251 @@
+//! Unit tests for the market-calendar module.
+
+#![cfg(test)]
+
+use super::*;
+use mock::*;
+
+use frame_support::{assert_noop, assert_ok};
+use module_primitives::CurrencyId;
+use module_traits::MarketCalendar as _;
+use sp_runtime::traits::BadOrigin;
+
+const EUR_USD: TradingPair = TradingPair {
+	base: CurrencyId::FEUR,
+	quote: CurrencyId::AUSD,
+};
+
+const JPY_EUR: TradingPair = TradingPair {
+	base: CurrencyId::FJPY,
+	quote: CurrencyId::FEUR,
+};
+
+const XAU_USD: TradingPair = TradingPair {
+	base: CurrencyId::FXAU,
+	quote: CurrencyId::AUSD,
+};
+
+#[test]
+fn publish_closure_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		Timestamp::set_timestamp(100);
+		assert_ok!(MarketCalendar::publish_closure(
+			alice(),
+			vec![EUR_USD],
+			ClosureKind::Holiday,
+			200,
+			300
+		));
+
+		let closure = MarketClosure {
+			pairs: vec![EUR_USD],
+			kind: ClosureKind::Holiday,
+			start: 200,
+			end: 300,
+		};
+		assert_eq!(MarketCalendar::closures(0), Some(closure.clone()));
+		assert_eq!(MarketCalendar::next_closure_id(), 1);
+
+		let event = TestEvent::market_calendar(RawEvent::ClosurePublished(0, closure));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn publish_closure_fails_if_invalid() {
+	ExtBuilder::default().build().execute_with(|| {
+		Timestamp::set_timestamp(100);
+		assert_noop!(
+			MarketCalendar::publish_closure(bob(), vec![EUR_USD], ClosureKind::Holiday, 200, 300),
+			BadOrigin
+		);
+		assert_noop!(
+			MarketCalendar::publish_closure(alice(), vec![], ClosureKind::Holiday, 200, 300),
+			Error::<Runtime>::NoTradingPair
+		);
+		assert_noop!(
+			MarketCalendar::publish_closure(alice(), vec![EUR_USD], ClosureKind::Holiday, 300, 300),
+			Error::<Runtime>::InvalidClosurePeriod
+		);
+		assert_noop!(
+			MarketCalendar::publish_closure(alice(), vec![EUR_USD], ClosureKind::Holiday, 50, 100),
+			Error::<Runtime>::InvalidClosurePeriod
+		);
+	});
+}
+
+#[test]
+fn market_closed_during_closure() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(MarketCalendar::publish_closure(
+			alice(),
+			vec![EUR_USD, JPY_EUR],
+			ClosureKind::HalfDay,
+			200,
+			300
+		));
+
+		Timestamp::set_timestamp(199);
+		assert!(!MarketCalendar::is_market_closed(EUR_USD));
+		assert_eq!(MarketCalendar::currency_closed_since(CurrencyId::FEUR), None);
+
+		Timestamp::set_timestamp(200);
+		assert!(MarketCalendar::is_market_closed(EUR_USD));
+		assert!(MarketCalendar::is_market_closed(JPY_EUR));
+		assert!(!MarketCalendar::is_market_closed(XAU_USD));
+		assert_eq!(MarketCalendar::closed_since(EUR_USD), Some(200));
+		assert_eq!(MarketCalendar::currency_closed_since(CurrencyId::FEUR), Some(200));
+		assert_eq!(MarketCalendar::currency_closed_since(CurrencyId::FJPY), Some(200));
+		assert_eq!(MarketCalendar::currency_closed_since(CurrencyId::FXAU), None);
+
+		Timestamp::set_timestamp(300);
+		assert!(!MarketCalendar::is_market_closed(EUR_USD));
+		assert_eq!(MarketCalendar::currency_closed_since(CurrencyId::FEUR), None);
+	});
+}
+
+#[test]
+fn closed_since_returns_earliest_start_of_overlapping_closures() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(MarketCalendar::publish_closure(
+			alice(),
+			vec![EUR_USD],
+			ClosureKind::Holiday,
+			200,
+			400
+		));
+		assert_ok!(MarketCalendar::publish_closure(
+			alice(),
+			vec![EUR_USD],
+			ClosureKind::HalfDay,
+			100,
+			300
+		));
+
+		Timestamp::set_timestamp(250);
+		assert_eq!(MarketCalendar::closed_since(EUR_USD), Some(100));
+
+		Timestamp::set_timestamp(350);
+		assert_eq!(MarketCalendar::closed_since(EUR_USD), Some(200));
+	});
+}
+
+#[test]
+fn cancel_closure_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			MarketCalendar::cancel_closure(alice(), 0),
+			Error::<Runtime>::ClosureNotFound
+		);
+
+		assert_ok!(MarketCalendar::publish_closure(
+			alice(),
+			vec![EUR_USD],
+			ClosureKind::Holiday,
+			200,
+			300
+		));
+		assert_noop!(MarketCalendar::cancel_closure(bob(), 0), BadOrigin);
+		assert_ok!(MarketCalendar::cancel_closure(alice(), 0));
+		assert_eq!(MarketCalendar::closures(0), None);
+
+		Timestamp::set_timestamp(200);
+		assert!(!MarketCalendar::is_market_closed(EUR_USD));
+
+		let event = TestEvent::market_calendar(RawEvent::ClosureCancelled(0));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn ended_closures_are_pruned() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(MarketCalendar::publish_closure(
+			alice(),
+			vec![EUR_USD],
+			ClosureKind::Holiday,
+			200,
+			300
+		));
+		assert_ok!(MarketCalendar::publish_closure(
+			alice(),
+			vec![EUR_USD],
+			ClosureKind::Holiday,
+			400,
+			500
+		));
+		assert_noop!(
+			MarketCalendar::publish_closure(alice(), vec![EUR_USD], ClosureKind::Holiday, 600, 700),
+			Error::<Runtime>::TooManyClosures
+		);
+
+		Timestamp::set_timestamp(300);
+		assert_ok!(MarketCalendar::publish_closure(
+			alice(),
+			vec![EUR_USD],
+			ClosureKind::Holiday,
+			600,
+			700
+		));
+		assert_eq!(MarketCalendar::closures(0), None);
+		assert!(MarketCalendar::closures(1).is_some());
+		assert!(MarketCalendar::closures(2).is_some());
+	});
+}
+
+#[test]
+fn weekly_schedule_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		let schedule = WeeklySchedule { open: 100, close: 600 };
+		assert_noop!(
+			MarketCalendar::set_weekly_schedule(bob(), EUR_USD, Some(schedule)),
+			BadOrigin
+		);
+		assert_noop!(
+			MarketCalendar::set_weekly_schedule(alice(), EUR_USD, Some(WeeklySchedule { open: 100, close: 100 })),
+			Error::<Runtime>::InvalidSchedule
+		);
+		assert_noop!(
+			MarketCalendar::set_weekly_schedule(alice(), EUR_USD, Some(WeeklySchedule { open: 100, close: 1000 })),
+			Error::<Runtime>::InvalidSchedule
+		);
+
+		assert_ok!(MarketCalendar::set_weekly_schedule(alice(), EUR_USD, Some(schedule)));
+		assert_eq!(MarketCalendar::weekly_schedule(EUR_USD), Some(schedule));
+		let event = TestEvent::market_calendar(RawEvent::WeeklyScheduleSet(EUR_USD, Some(schedule)));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		Timestamp::set_timestamp(1050);
+		assert_eq!(MarketCalendar::closed_since(EUR_USD), Some(600));
+		Timestamp::set_timestamp(1100);
+		assert!(!MarketCalendar::is_market_closed(EUR_USD));
+		Timestamp::set_timestamp(1700);
+		assert_eq!(MarketCalendar::closed_since(EUR_USD), Some(1600));
+		assert!(!MarketCalendar::is_market_closed(XAU_USD));
+
+		// session over the start of the week
+		assert_ok!(MarketCalendar::set_weekly_schedule(
+			alice(),
+			JPY_EUR,
+			Some(WeeklySchedule { open: 800, close: 200 })
+		));
+		Timestamp::set_timestamp(1900);
+		assert!(!MarketCalendar::is_market_closed(JPY_EUR));
+		Timestamp::set_timestamp(2100);
+		assert!(!MarketCalendar::is_market_closed(JPY_EUR));
+		Timestamp::set_timestamp(2300);
+		assert_eq!(MarketCalendar::closed_since(JPY_EUR), Some(2200));
+		assert_eq!(MarketCalendar::currency_closed_since(CurrencyId::FJPY), Some(2200));
+		assert!(!MarketCalendar::is_market_closed(EUR_USD));
+		assert_eq!(MarketCalendar::currency_closed_since(CurrencyId::FEUR), Some(2200));
+
+		// the earliest of EUR/USD closed since 2600 and JPY/EUR since 2200
+		Timestamp::set_timestamp(2700);
+		assert_eq!(MarketCalendar::closed_since(EUR_USD), Some(2600));
+		assert_eq!(MarketCalendar::currency_closed_since(CurrencyId::FEUR), Some(2200));
+
+		assert_ok!(MarketCalendar::set_weekly_schedule(alice(), EUR_USD, None));
+		assert_eq!(MarketCalendar::weekly_schedule(EUR_USD), None);
+		assert!(!MarketCalendar::is_market_closed(EUR_USD));
+	});
+}
@@ -0,0 +1,554 @@
+//! Cash-settled FX options written by margin liquidity pools.
+//!
+//! Pools set option terms on enabled trading pairs. Buyers pay the premium, intrinsic value plus a rate of the
+//! notional value, into the pool liquidity. Options are settled by oracle prices at expiry, and the intrinsic value
+//! is paid out of the pool liquidity. Unsettled options are liabilities of the pool, folded into its ENP and ELL.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Decode, Encode};
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage, ensure,
+	traits::{EnsureOrigin, Get},
+	weights::Weight,
+};
+use sp_arithmetic::{
+	traits::{CheckedMul, Saturating, Zero},
+	FixedPointNumber, Permill,
+};
+use sp_runtime::{traits::One, DispatchError, RuntimeDebug};
+use sp_std::{cmp, prelude::*, result};
+// FIXME: `pallet/frame-` prefix should be used for all pallet modules, but currently `frame_system`
+// would cause compiling error in `decl_module!` and `construct_runtime!`
+// #3295 https://github.com/paritytech/substrate/issues/3295
+use frame_system::{self as system, ensure_signed};
+
+use orml_traits::PriceProvider;
+use orml_utilities::with_transaction_result;
+
+use module_primitives::{
+	arithmetic::fixed_i128_from_balance, Balance, CurrencyId, LiquidityPoolId, Price, TradingPair,
+};
+use module_traits::{
+	LiquidityPools, MarginPoolRisk, PoolLiabilities, PoolLiabilityAmounts, PriceStatus, PriceUnavailableReason,
+};
+
+mod mock;
+mod tests;
+
+/// Computation weight of settling an option, excluding storage accesses.
+const SETTLEMENT_WEIGHT_PER_OPTION: Weight = 20_000;
+/// Storage reads of settling an option: option, prices, pool liquidity and pool options.
+const SETTLEMENT_READS_PER_OPTION: Weight = 6;
+/// Storage writes of settling an option: option, pool options and the payout transfer.
+const SETTLEMENT_WRITES_PER_OPTION: Weight = 5;
+/// Computation weight of an option in pool liabilities, excluding storage accesses.
+const LIABILITIES_WEIGHT_PER_OPTION: Weight = 5_000;
+/// Storage reads of an option in pool liabilities: option and prices.
+const LIABILITIES_READS_PER_OPTION: Weight = 5;
+
+pub trait Trait: frame_system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+
+	/// The liquidity pools writing options. Premiums are paid into, and payouts are paid out of, pool liquidity.
+	type LiquidityPools: LiquidityPools<Self::AccountId>;
+
+	/// Safety checks of liquidity pools writing new options.
+	type PoolRisk: MarginPoolRisk;
+
+	/// Provides market prices.
+	type PriceProvider: PriceProvider<CurrencyId, Price>;
+
+	/// Reasons of unavailable prices, to return granular price errors.
+	type PriceStatus: PriceStatus;
+
+	/// Maximum number of options expiring at one block. It bounds the settlement work of `on_initialize`.
+	type GetMaxOptionsPerExpiry: Get<u32>;
+
+	/// Maximum number of unsettled options written by a pool.
+	type GetPoolMaxOptions: Get<u32>;
+
+	/// Required origin for enabling and disabling trading pairs.
+	type UpdateOrigin: EnsureOrigin<Self::Origin>;
+}
+
+pub type OptionId = u64;
+
+/// Kind of an option.
+#[derive(Encode, Decode, Clone, Copy, RuntimeDebug, Eq, PartialEq)]
+pub enum OptionKind {
+	/// Pays out the price rise above the strike price.
+	Call,
+	/// Pays out the price drop below the strike price.
+	Put,
+}
+
+/// Terms of options written by a liquidity pool on a trading pair.
+#[derive(Encode, Decode, Clone, Copy, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct OptionTerms<BlockNumber> {
+	/// Premium of calls on top of the intrinsic value, as a rate of the notional value.
+	pub call_premium: Permill,
+
+	/// Premium of puts on top of the intrinsic value, as a rate of the notional value.
+	pub put_premium: Permill,
+
+	/// Maximum number of blocks from buying to expiry.
+	pub max_duration: BlockNumber,
+}
+
+/// A cash-settled option bought from a liquidity pool.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct FxOption<AccountId, BlockNumber> {
+	/// Owner.
+	pub owner: AccountId,
+
+	/// Liquidity pool ID.
+	pub pool: LiquidityPoolId,
+
+	/// Trading pair.
+	pub pair: TradingPair,
+
+	/// Call or put.
+	pub kind: OptionKind,
+
+	/// Strike price of `pair.base` in `pair.quote`.
+	pub strike: Price,
+
+	/// Amount of `pair.base`.
+	pub amount: Balance,
+
+	/// The block at which the option is settled.
+	pub expiry: BlockNumber,
+
+	/// Premium paid, in USD.
+	pub premium: Balance,
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as OptionsProtocol {
+		/// Trading pairs options could be written on.
+		EnabledTradingPairs get(fn enabled_trading_pairs): map hasher(twox_64_concat) TradingPair => bool;
+
+		/// Option terms of liquidity pools. Pools only write options on trading pairs with terms set.
+		PoolOptionTerms get(fn pool_option_terms): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) TradingPair => Option<OptionTerms<T::BlockNumber>>;
+
+		/// Next available option ID.
+		NextOptionId get(fn next_option_id): OptionId;
+
+		/// Unsettled options.
+		Options get(fn options): map hasher(twox_64_concat) OptionId => Option<FxOption<T::AccountId, T::BlockNumber>>;
+
+		/// Unsettled options written by liquidity pools.
+		OptionsByPool get(fn options_by_pool): map hasher(twox_64_concat) LiquidityPoolId => Vec<OptionId>;
+
+		/// Options to be settled at a block.
+		OptionsByExpiry get(fn options_by_expiry): map hasher(twox_64_concat) T::BlockNumber => Vec<OptionId>;
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where
+		<T as frame_system::Trait>::AccountId,
+		<T as frame_system::Trait>::BlockNumber,
+	{
+		/// Trading pair enabled: [pair]
+		TradingPairEnabled(TradingPair),
+
+		/// Trading pair disabled: [pair]
+		TradingPairDisabled(TradingPair),
+
+		/// Option terms set, or removed if `None`: [who, pool_id, pair, terms]
+		OptionTermsSet(AccountId, LiquidityPoolId, TradingPair, Option<OptionTerms<BlockNumber>>),
+
+		/// Option bought: [who, option_id, pool_id, premium]
+		OptionBought(AccountId, OptionId, LiquidityPoolId, Balance),
+
+		/// Option settled: [owner, option_id, payout]
+		OptionSettled(AccountId, OptionId, Balance),
+
+		/// Option settlement deferred to a later block, as prices were unavailable: [option_id, block_number]
+		SettlementDeferred(OptionId, BlockNumber),
+	}
+}
+
+decl_error! {
+	/// Errors for the options protocol module.
+	pub enum Error for Module<T: Trait> {
+		/// Caller doesn't have permission.
+		NoPermission,
+		/// Pool not found.
+		PoolNotFound,
+		/// Trading pair not enabled.
+		TradingPairNotEnabled,
+		/// The pool doesn't write options on the trading pair.
+		OptionsNotOffered,
+		/// Option amount is zero.
+		ZeroAmount,
+		/// Expiry is not in the future, or exceeds the max duration.
+		InvalidExpiry,
+		/// Premium exceeds the max premium.
+		PremiumTooHigh,
+		/// Reached the max number of options expiring at one block.
+		TooManyOptionsAtExpiry,
+		/// Reached the max number of unsettled options of the pool.
+		TooManyPoolOptions,
+		/// No available option ID.
+		NoAvailableOptionId,
+		/// Option not found.
+		OptionNotFound,
+		/// Option not expired yet.
+		NotExpired,
+		/// No price has been fed.
+		NoPrice,
+		/// All fed prices have expired.
+		StalePrice,
+		/// Not enough oracle operators fed unexpired prices.
+		BelowOracleQuorum,
+		/// The market is halted.
+		MarketHalted,
+		/// Number out of bound in calculation.
+		NumOutOfBound,
+		/// A balance too large to convert to amount.
+		BalanceOverflow,
+	}
+}
+
+impl<T: Trait> From<PriceUnavailableReason> for Error<T> {
+	fn from(reason: PriceUnavailableReason) -> Self {
+		match reason {
+			PriceUnavailableReason::NoPrice => Error::<T>::NoPrice,
+			PriceUnavailableReason::StalePrice => Error::<T>::StalePrice,
+			PriceUnavailableReason::BelowOracleQuorum => Error::<T>::BelowOracleQuorum,
+			PriceUnavailableReason::MarketHalted => Error::<T>::MarketHalted,
+		}
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		type Error = Error<T>;
+
+		fn deposit_event() = default;
+
+		const GetMaxOptionsPerExpiry: u32 = T::GetMaxOptionsPerExpiry::get();
+		const GetPoolMaxOptions: u32 = T::GetPoolMaxOptions::get();
+
+		/// Enable options on `pair`.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn enable_trading_pair(origin, pair: TradingPair) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				EnabledTradingPairs::insert(pair, true);
+				Self::deposit_event(RawEvent::TradingPairEnabled(pair));
+				Ok(())
+			})?;
+		}
+
+		/// Disable options on `pair`. Unsettled options are still settled at expiry.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn disable_trading_pair(origin, pair: TradingPair) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				EnabledTradingPairs::remove(pair);
+				Self::deposit_event(RawEvent::TradingPairDisabled(pair));
+				Ok(())
+			})?;
+		}
+
+		/// Set option terms of `pair` in `pool_id`. `None` stops writing new options on `pair`.
+		///
+		/// May only be called from the pool owner.
+		#[weight = 10_000]
+		pub fn set_option_terms(
+			origin,
+			#[compact] pool_id: LiquidityPoolId,
+			pair: TradingPair,
+			terms: Option<OptionTerms<T::BlockNumber>>,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				ensure!(T::LiquidityPools::is_owner(pool_id, &who), Error::<T>::NoPermission);
+
+				if let Some(t) = terms {
+					ensure!(Self::enabled_trading_pairs(pair), Error::<T>::TradingPairNotEnabled);
+					<PoolOptionTerms<T>>::insert(pool_id, pair, t);
+				} else {
+					<PoolOptionTerms<T>>::remove(pool_id, pair);
+				}
+
+				Self::deposit_event(RawEvent::OptionTermsSet(who, pool_id, pair, terms));
+				Ok(())
+			})?;
+		}
+
+		/// Buy an option of `amount` of `pair.base` from `pool_id`, settled at `expiry`.
+		///
+		/// The premium is paid into the pool liquidity, and may not exceed `max_premium`. Weight includes the safety
+		/// checks of the pool.
+		#[weight = 30_000 + T::PoolRisk::pool_risk_weight()]
+		pub fn buy_option(
+			origin,
+			#[compact] pool_id: LiquidityPoolId,
+			pair: TradingPair,
+			kind: OptionKind,
+			strike: Price,
+			#[compact] amount: Balance,
+			expiry: T::BlockNumber,
+			#[compact] max_premium: Balance,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let (option_id, premium) =
+					Self::do_buy_option(&who, pool_id, pair, kind, strike, amount, expiry, max_premium)?;
+				Self::deposit_event(RawEvent::OptionBought(who, option_id, pool_id, premium));
+				Ok(())
+			})?;
+		}
+
+		fn on_initialize(now: T::BlockNumber) -> Weight {
+			let options = <OptionsByExpiry<T>>::take(now);
+			let mut deferred: Weight = 0;
+			let mut blocks_read: Weight = 0;
+			for option_id in options.iter() {
+				match with_transaction_result(|| Self::do_settle_option(*option_id)) {
+					Ok((owner, payout)) => Self::deposit_event(RawEvent::OptionSettled(owner, *option_id, payout)),
+					Err(_) => {
+						// prices unavailable, retry at the next block with room
+						let (block_number, read) = Self::defer_settlement(*option_id, now);
+						deferred += 1;
+						blocks_read = blocks_read.saturating_add(read);
+						Self::deposit_event(RawEvent::SettlementDeferred(*option_id, block_number));
+					}
+				}
+			}
+
+			let settled = options.len() as Weight;
+			T::DbWeight::get()
+				.reads_writes(blocks_read.saturating_add(1), deferred.saturating_add(1))
+				.saturating_add(settled.saturating_mul(
+					SETTLEMENT_WEIGHT_PER_OPTION.saturating_add(
+						T::DbWeight::get().reads_writes(SETTLEMENT_READS_PER_OPTION, SETTLEMENT_WRITES_PER_OPTION),
+					),
+				))
+		}
+	}
+}
+
+type BalanceResult = result::Result<Balance, DispatchError>;
+
+// Dispatchable calls implementation
+impl<T: Trait> Module<T> {
+	#[allow(clippy::too_many_arguments)]
+	fn do_buy_option(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		kind: OptionKind,
+		strike: Price,
+		amount: Balance,
+		expiry: T::BlockNumber,
+		max_premium: Balance,
+	) -> result::Result<(OptionId, Balance), DispatchError> {
+		ensure!(!amount.is_zero(), Error::<T>::ZeroAmount);
+		ensure!(T::LiquidityPools::pool_exists(pool_id), Error::<T>::PoolNotFound);
+		ensure!(Self::enabled_trading_pairs(pair), Error::<T>::TradingPairNotEnabled);
+		let terms = Self::pool_option_terms(pool_id, pair).ok_or(Error::<T>::OptionsNotOffered)?;
+
+		let now = <system::Module<T>>::block_number();
+		ensure!(
+			expiry > now && expiry - now <= terms.max_duration,
+			Error::<T>::InvalidExpiry
+		);
+		let mut options_at_expiry = Self::options_by_expiry(expiry);
+		ensure!(
+			(options_at_expiry.len() as u32) < T::GetMaxOptionsPerExpiry::get(),
+			Error::<T>::TooManyOptionsAtExpiry
+		);
+		let mut pool_options = Self::options_by_pool(pool_id);
+		ensure!(
+			(pool_options.len() as u32) < T::GetPoolMaxOptions::get(),
+			Error::<T>::TooManyPoolOptions
+		);
+
+		let mut option = FxOption {
+			owner: who.clone(),
+			pool: pool_id,
+			pair,
+			kind,
+			strike,
+			amount,
+			expiry,
+			premium: Zero::zero(),
+		};
+		let intrinsic_value = Self::intrinsic_value(&option)?;
+		let notional_value = Self::notional_value(pair, amount)?;
+
+		// premium = intrinsic_value + notional_value * premium_rate
+		let premium_rate = match kind {
+			OptionKind::Call => terms.call_premium,
+			OptionKind::Put => terms.put_premium,
+		};
+		let premium = intrinsic_value
+			.checked_add(premium_rate.mul_ceil(notional_value))
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		ensure!(premium <= max_premium, Error::<T>::PremiumTooHigh);
+		option.premium = premium;
+
+		T::LiquidityPools::deposit_liquidity(who, pool_id, premium)?;
+		T::PoolRisk::ensure_pool_safe_with(
+			pool_id,
+			pair,
+			Self::liabilities_of_option(intrinsic_value, notional_value)?,
+		)?;
+
+		let option_id = Self::next_option_id();
+		let next_option_id = option_id
+			.checked_add(One::one())
+			.ok_or(Error::<T>::NoAvailableOptionId)?;
+		NextOptionId::put(next_option_id);
+
+		<Options<T>>::insert(option_id, option);
+		options_at_expiry.push(option_id);
+		<OptionsByExpiry<T>>::insert(expiry, options_at_expiry);
+		pool_options.push(option_id);
+		OptionsByPool::insert(pool_id, pool_options);
+
+		Ok((option_id, premium))
+	}
+
+	/// Settle an expired option by current prices. The payout is capped by the pool liquidity.
+	///
+	/// Returns `(owner, payout)`.
+	fn do_settle_option(option_id: OptionId) -> result::Result<(T::AccountId, Balance), DispatchError> {
+		let option = Self::options(option_id).ok_or(Error::<T>::OptionNotFound)?;
+		ensure!(
+			<system::Module<T>>::block_number() >= option.expiry,
+			Error::<T>::NotExpired
+		);
+
+		let intrinsic_value = Self::intrinsic_value(&option)?;
+		let payout = cmp::min(intrinsic_value, T::LiquidityPools::liquidity(option.pool));
+		if !payout.is_zero() {
+			T::LiquidityPools::withdraw_liquidity(&option.owner, option.pool, payout)?;
+		}
+
+		<Options<T>>::remove(option_id);
+		OptionsByPool::mutate(option.pool, |ids| ids.retain(|id| *id != option_id));
+
+		Ok((option.owner, payout))
+	}
+
+	/// Reschedule the settlement of `option_id` to the first block after `now` with less than
+	/// `GetMaxOptionsPerExpiry` options.
+	///
+	/// Returns `(block_number, blocks_read)`.
+	fn defer_settlement(option_id: OptionId, now: T::BlockNumber) -> (T::BlockNumber, Weight) {
+		let max_options = T::GetMaxOptionsPerExpiry::get() as usize;
+		let mut block_number = now;
+		let mut blocks_read: Weight = 0;
+		loop {
+			block_number += One::one();
+			blocks_read += 1;
+			let mut options = Self::options_by_expiry(block_number);
+			if options.len() < max_options {
+				options.push(option_id);
+				<OptionsByExpiry<T>>::insert(block_number, options);
+				return (block_number, blocks_read);
+			}
+		}
+	}
+}
+
+// Pricing helpers
+impl<T: Trait> Module<T> {
+	fn price(base: CurrencyId, quote: CurrencyId) -> result::Result<Price, Error<T>> {
+		T::PriceProvider::get_price(base, quote).ok_or_else(|| {
+			T::PriceStatus::unavailable_reason(base)
+				.or_else(|| T::PriceStatus::unavailable_reason(quote))
+				.map_or(Error::<T>::NoPrice, Into::into)
+		})
+	}
+
+	/// Intrinsic value of an option at current prices, in USD.
+	///
+	/// call_intrinsic_value = max(0, price - strike) * amount * quote_usd_price
+	/// put_intrinsic_value = max(0, strike - price) * amount * quote_usd_price
+	fn intrinsic_value(option: &FxOption<T::AccountId, T::BlockNumber>) -> BalanceResult {
+		let price = Self::price(option.pair.base, option.pair.quote)?;
+		let in_the_money = match option.kind {
+			OptionKind::Call => price.saturating_sub(option.strike),
+			OptionKind::Put => option.strike.saturating_sub(price),
+		};
+		Self::usd_value_of_quote(option.pair.quote, in_the_money, option.amount)
+	}
+
+	/// Notional value of `amount` of `pair.base` at current prices, in USD.
+	///
+	/// notional_value = price * amount * quote_usd_price
+	fn notional_value(pair: TradingPair, amount: Balance) -> BalanceResult {
+		let price = Self::price(pair.base, pair.quote)?;
+		Self::usd_value_of_quote(pair.quote, price, amount)
+	}
+
+	/// USD value of `amount` at `price_in_quote`.
+	fn usd_value_of_quote(quote: CurrencyId, price_in_quote: Price, amount: Balance) -> BalanceResult {
+		let quote_usd_price = Self::price(quote, CurrencyId::AUSD)?;
+		let value = price_in_quote
+			.checked_mul(&Price::from_inner(amount))
+			.and_then(|v| v.checked_mul(&quote_usd_price))
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		Ok(value.into_inner())
+	}
+
+	/// Liabilities of an option, with `intrinsic_value` and `notional_value` in USD.
+	///
+	/// Written options don't offset each other, so the notional value counts fully in both net position and longest
+	/// leg.
+	fn liabilities_of_option(
+		intrinsic_value: Balance,
+		notional_value: Balance,
+	) -> result::Result<PoolLiabilityAmounts, DispatchError> {
+		let notional_value = fixed_i128_from_balance(notional_value).map_err(|_| Error::<T>::BalanceOverflow)?;
+		Ok(PoolLiabilityAmounts {
+			unrealized_loss: fixed_i128_from_balance(intrinsic_value).map_err(|_| Error::<T>::BalanceOverflow)?,
+			net_position: notional_value,
+			longest_leg: notional_value,
+		})
+	}
+}
+
+impl<T: Trait> PoolLiabilities for Module<T> {
+	fn liabilities(pool_id: LiquidityPoolId) -> result::Result<PoolLiabilityAmounts, DispatchError> {
+		Self::options_by_pool(pool_id).into_iter().try_fold(
+			PoolLiabilityAmounts::default(),
+			|amounts, option_id| -> result::Result<PoolLiabilityAmounts, DispatchError> {
+				let option = Self::options(option_id).ok_or(Error::<T>::OptionNotFound)?;
+				let liabilities = Self::liabilities_of_option(
+					Self::intrinsic_value(&option)?,
+					Self::notional_value(option.pair, option.amount)?,
+				)?;
+				amounts
+					.checked_add(&liabilities)
+					.ok_or_else(|| Error::<T>::NumOutOfBound.into())
+			},
+		)
+	}
+
+	fn has_liabilities(pool_id: LiquidityPoolId) -> bool {
+		!Self::options_by_pool(pool_id).is_empty()
+	}
+
+	/// Unsettled options of the pool are iterated, at most `GetPoolMaxOptions`.
+	fn liabilities_weight() -> Weight {
+		let option_weight =
+			LIABILITIES_WEIGHT_PER_OPTION.saturating_add(T::DbWeight::get().reads(LIABILITIES_READS_PER_OPTION));
+		T::DbWeight::get()
+			.reads(1)
+			.saturating_add((T::GetPoolMaxOptions::get() as Weight).saturating_mul(option_weight))
+	}
+}
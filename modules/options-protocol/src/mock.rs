@@ -0,0 +1,159 @@
+//! Mocks for the options protocol module.
+
+#![cfg(test)]
+
+use frame_support::{impl_outer_event, impl_outer_origin, ord_parameter_types, parameter_types};
+use frame_system as system;
+use system::EnsureSignedBy;
+
+use orml_currencies::Currency;
+use orml_traits::DefaultPriceProvider;
+
+use super::*;
+
+pub use laminar_test_support::{MockBuilder, MockPoolRisk, MockPriceStatus, MockPrices, EUR_JPY_PAIR, EUR_USD_PAIR};
+pub use module_primitives::{Balance, CurrencyId};
+
+impl_outer_origin! {
+	pub enum Origin for Runtime {}
+}
+
+ord_parameter_types! {
+	pub const UpdateOrigin: AccountId = 3;
+}
+
+mod options_protocol {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for Runtime {
+		frame_system<T>,
+		orml_tokens<T>, orml_currencies<T>,
+		options_protocol<T>,
+	}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Runtime;
+laminar_test_support::impl_mock_system!(Runtime, AccountId, TestEvent, ());
+
+pub type AccountId = u32;
+pub type System = system::Module<Runtime>;
+
+type Amount = i128;
+
+impl orml_tokens::Trait for Runtime {
+	type Event = TestEvent;
+	type Balance = Balance;
+	type Amount = Amount;
+	type CurrencyId = CurrencyId;
+	type OnReceived = ();
+}
+
+parameter_types! {
+	pub const GetNativeCurrencyId: CurrencyId = CurrencyId::LAMI;
+	pub const GetCollateralCurrencyId: CurrencyId = CurrencyId::AUSD;
+}
+
+type NativeCurrency = Currency<Runtime, GetNativeCurrencyId>;
+pub type CollateralCurrency = Currency<Runtime, GetCollateralCurrencyId>;
+
+impl orml_currencies::Trait for Runtime {
+	type Event = TestEvent;
+	type MultiCurrency = orml_tokens::Module<Runtime>;
+	type NativeCurrency = NativeCurrency;
+	type GetNativeCurrencyId = GetNativeCurrencyId;
+}
+
+parameter_types! {
+	pub MockPools: Vec<LiquidityPoolId> = vec![MOCK_POOL];
+}
+
+/// Liquidity of mock pools is the collateral balance of the pool ID account. ALICE is the mock owner.
+pub type MockLiquidityPools = laminar_test_support::MockLiquidityPools<CollateralCurrency, MockPools>;
+
+parameter_types! {
+	pub const GetMaxOptionsPerExpiry: u32 = 2;
+	pub const GetPoolMaxOptions: u32 = 3;
+}
+
+impl Trait for Runtime {
+	type Event = TestEvent;
+	type LiquidityPools = MockLiquidityPools;
+	type PoolRisk = MockPoolRisk;
+	type PriceProvider = DefaultPriceProvider<CurrencyId, MockPrices>;
+	type PriceStatus = MockPriceStatus;
+	type GetMaxOptionsPerExpiry = GetMaxOptionsPerExpiry;
+	type GetPoolMaxOptions = GetPoolMaxOptions;
+	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
+}
+pub type OptionsProtocol = Module<Runtime>;
+
+pub const ALICE: AccountId = 0;
+pub const BOB: AccountId = 1;
+
+pub const MOCK_POOL: LiquidityPoolId = 100;
+
+pub const ONE: Balance = 1_000_000_000_000_000_000;
+
+pub fn dollar(amount: u128) -> Balance {
+	amount * ONE
+}
+
+pub struct ExtBuilder {
+	endowed_accounts: Vec<(AccountId, CurrencyId, Balance)>,
+	mocks: MockBuilder,
+}
+
+impl Default for ExtBuilder {
+	/// $10_000 for BOB and the mock pool.
+	fn default() -> Self {
+		Self {
+			endowed_accounts: vec![
+				(BOB, CurrencyId::AUSD, dollar(10_000)),
+				(MOCK_POOL, CurrencyId::AUSD, dollar(10_000)),
+			],
+			mocks: MockBuilder::default(),
+		}
+	}
+}
+
+impl ExtBuilder {
+	pub fn pool_liquidity(mut self, liquidity: Balance) -> Self {
+		self.endowed_accounts.retain(|(who, _, _)| *who != MOCK_POOL);
+		self.endowed_accounts.push((MOCK_POOL, CurrencyId::AUSD, liquidity));
+		self
+	}
+
+	/// `price`: rational(x, y)
+	pub fn price(mut self, currency_id: CurrencyId, price: (u128, u128)) -> Self {
+		self.mocks
+			.price(currency_id, Price::saturating_from_rational(price.0, price.1));
+		self
+	}
+
+	fn set_mocks(&self) {
+		self.mocks.set_mocks();
+		MockPoolRisk::set_mock_pool_safe(true);
+	}
+
+	pub fn build(self) -> sp_io::TestExternalities {
+		self.set_mocks();
+
+		let mut t = frame_system::GenesisConfig::default()
+			.build_storage::<Runtime>()
+			.unwrap();
+
+		orml_tokens::GenesisConfig::<Runtime> {
+			endowed_accounts: self.endowed_accounts,
+		}
+		.assimilate_storage(&mut t)
+		.unwrap();
+
+		let mut ext = sp_io::TestExternalities::new(t);
+		ext.execute_with(|| System::set_block_number(1));
+		ext
+	}
+}
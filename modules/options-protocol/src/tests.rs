@@ -0,0 +1,494 @@
+//! Unit tests for the options protocol module.
+
+#![cfg(test)]
+
+use super::*;
+use frame_support::{assert_noop, assert_ok, traits::OnInitialize};
+use mock::*;
+use orml_traits::BasicCurrency;
+use sp_arithmetic::FixedI128;
+use sp_runtime::{traits::BadOrigin, DispatchResult};
+
+const EXPIRY: u64 = 11;
+
+fn price(dollar_cent: u128) -> Price {
+	Price::saturating_from_rational(dollar_cent, 100)
+}
+
+fn fixed_dollar(amount: i128) -> FixedI128 {
+	FixedI128::saturating_from_integer(amount)
+}
+
+fn origin_of(who: AccountId) -> Origin {
+	Origin::signed(who)
+}
+
+fn terms(call_premium_percent: u32, put_premium_percent: u32) -> OptionTerms<u64> {
+	OptionTerms {
+		call_premium: Permill::from_percent(call_premium_percent),
+		put_premium: Permill::from_percent(put_premium_percent),
+		max_duration: 100,
+	}
+}
+
+/// Enable EUR/USD, with 1% call premium and 2% put premium in the mock pool.
+fn setup() {
+	assert_ok!(OptionsProtocol::enable_trading_pair(
+		origin_of(UpdateOrigin::get()),
+		EUR_USD_PAIR
+	));
+	assert_ok!(OptionsProtocol::set_option_terms(
+		origin_of(ALICE),
+		MOCK_POOL,
+		EUR_USD_PAIR,
+		Some(terms(1, 2))
+	));
+}
+
+/// BOB buys an option of `amount` EUR at strike price `strike_cent / 100` USD, expiring at `expiry`.
+fn buy(kind: OptionKind, strike_cent: u128, amount: u128, expiry: u64) -> DispatchResult {
+	OptionsProtocol::buy_option(
+		origin_of(BOB),
+		MOCK_POOL,
+		EUR_USD_PAIR,
+		kind,
+		price(strike_cent),
+		dollar(amount),
+		expiry,
+		dollar(1_000),
+	)
+}
+
+fn collateral_balance(who: AccountId) -> Balance {
+	CollateralCurrency::free_balance(&who)
+}
+
+fn set_feur_price(dollar_cent: u128) {
+	MockPrices::set_mock_price(CurrencyId::FEUR, Some(price(dollar_cent)));
+}
+
+fn settle_at(block_number: u64) {
+	System::set_block_number(block_number);
+	<OptionsProtocol as OnInitialize<u64>>::on_initialize(block_number);
+}
+
+#[test]
+fn enable_and_disable_trading_pair_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(OptionsProtocol::enable_trading_pair(
+			origin_of(UpdateOrigin::get()),
+			EUR_USD_PAIR
+		));
+		assert!(OptionsProtocol::enabled_trading_pairs(EUR_USD_PAIR));
+		let event = TestEvent::options_protocol(RawEvent::TradingPairEnabled(EUR_USD_PAIR));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_ok!(OptionsProtocol::disable_trading_pair(
+			origin_of(UpdateOrigin::get()),
+			EUR_USD_PAIR
+		));
+		assert!(!OptionsProtocol::enabled_trading_pairs(EUR_USD_PAIR));
+		let event = TestEvent::options_protocol(RawEvent::TradingPairDisabled(EUR_USD_PAIR));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn enable_and_disable_trading_pair_requires_update_origin() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			OptionsProtocol::enable_trading_pair(origin_of(ALICE), EUR_USD_PAIR),
+			BadOrigin
+		);
+		assert_noop!(
+			OptionsProtocol::disable_trading_pair(origin_of(ALICE), EUR_USD_PAIR),
+			BadOrigin
+		);
+	});
+}
+
+#[test]
+fn set_option_terms_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		setup();
+		assert_eq!(
+			OptionsProtocol::pool_option_terms(MOCK_POOL, EUR_USD_PAIR),
+			Some(terms(1, 2))
+		);
+		let event = TestEvent::options_protocol(RawEvent::OptionTermsSet(
+			ALICE,
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			Some(terms(1, 2)),
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_ok!(OptionsProtocol::set_option_terms(
+			origin_of(ALICE),
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			None
+		));
+		assert_eq!(OptionsProtocol::pool_option_terms(MOCK_POOL, EUR_USD_PAIR), None);
+		assert_noop!(
+			buy(OptionKind::Call, 110, 1_000, EXPIRY),
+			Error::<Runtime>::OptionsNotOffered
+		);
+	});
+}
+
+#[test]
+fn set_option_terms_fails_if_no_permission_or_pair_not_enabled() {
+	ExtBuilder::default().build().execute_with(|| {
+		setup();
+		assert_noop!(
+			OptionsProtocol::set_option_terms(origin_of(BOB), MOCK_POOL, EUR_USD_PAIR, Some(terms(1, 2))),
+			Error::<Runtime>::NoPermission
+		);
+		assert_noop!(
+			OptionsProtocol::set_option_terms(origin_of(ALICE), MOCK_POOL, EUR_JPY_PAIR, Some(terms(1, 2))),
+			Error::<Runtime>::TradingPairNotEnabled
+		);
+	});
+}
+
+#[test]
+fn buy_call_option_works() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.build()
+		.execute_with(|| {
+			setup();
+			// premium = intrinsic value $100 + 1% of notional value $1200
+			assert_ok!(buy(OptionKind::Call, 110, 1_000, EXPIRY));
+
+			assert_eq!(
+				OptionsProtocol::options(0),
+				Some(FxOption {
+					owner: BOB,
+					pool: MOCK_POOL,
+					pair: EUR_USD_PAIR,
+					kind: OptionKind::Call,
+					strike: price(110),
+					amount: dollar(1_000),
+					expiry: EXPIRY,
+					premium: dollar(112),
+				})
+			);
+			assert_eq!(OptionsProtocol::options_by_expiry(EXPIRY), vec![0]);
+			assert_eq!(OptionsProtocol::options_by_pool(MOCK_POOL), vec![0]);
+			assert_eq!(OptionsProtocol::next_option_id(), 1);
+			assert_eq!(collateral_balance(BOB), dollar(9_888));
+			assert_eq!(collateral_balance(MOCK_POOL), dollar(10_112));
+
+			assert_eq!(
+				MockPoolRisk::checked_liabilities(),
+				Some((
+					EUR_USD_PAIR,
+					PoolLiabilityAmounts {
+						unrealized_loss: fixed_dollar(100),
+						net_position: fixed_dollar(1_200),
+						longest_leg: fixed_dollar(1_200),
+					}
+				))
+			);
+
+			let event = TestEvent::options_protocol(RawEvent::OptionBought(BOB, 0, MOCK_POOL, dollar(112)));
+			assert!(System::events().iter().any(|record| record.event == event));
+		});
+}
+
+#[test]
+fn buy_put_option_works() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.build()
+		.execute_with(|| {
+			setup();
+			// out of the money: premium = 2% of notional value $1200
+			assert_ok!(buy(OptionKind::Put, 110, 1_000, EXPIRY));
+			assert_eq!(OptionsProtocol::options(0).map(|o| o.premium), Some(dollar(24)));
+			assert_eq!(collateral_balance(BOB), dollar(9_976));
+
+			// in the money: premium = intrinsic value $100 + 2% of notional value $1200
+			assert_ok!(buy(OptionKind::Put, 130, 1_000, EXPIRY));
+			assert_eq!(OptionsProtocol::options(1).map(|o| o.premium), Some(dollar(124)));
+			assert_eq!(collateral_balance(BOB), dollar(9_852));
+		});
+}
+
+#[test]
+fn buy_option_on_cross_pair_values_in_usd() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.price(CurrencyId::FJPY, (1, 100))
+		.build()
+		.execute_with(|| {
+			assert_ok!(OptionsProtocol::enable_trading_pair(
+				origin_of(UpdateOrigin::get()),
+				EUR_JPY_PAIR
+			));
+			assert_ok!(OptionsProtocol::set_option_terms(
+				origin_of(ALICE),
+				MOCK_POOL,
+				EUR_JPY_PAIR,
+				Some(terms(1, 2))
+			));
+
+			// EUR/JPY = 120, intrinsic value = 10_000 JPY = $100, notional value = 120_000 JPY = $1200
+			assert_ok!(OptionsProtocol::buy_option(
+				origin_of(BOB),
+				MOCK_POOL,
+				EUR_JPY_PAIR,
+				OptionKind::Call,
+				Price::saturating_from_integer(110),
+				dollar(1_000),
+				EXPIRY,
+				dollar(1_000),
+			));
+			assert_eq!(OptionsProtocol::options(0).map(|o| o.premium), Some(dollar(112)));
+			assert_eq!(
+				OptionsProtocol::liabilities(MOCK_POOL),
+				Ok(PoolLiabilityAmounts {
+					unrealized_loss: fixed_dollar(100),
+					net_position: fixed_dollar(1_200),
+					longest_leg: fixed_dollar(1_200),
+				})
+			);
+		});
+}
+
+#[test]
+fn buy_option_fails_if_invalid_params() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.build()
+		.execute_with(|| {
+			setup();
+			assert_noop!(buy(OptionKind::Call, 110, 0, EXPIRY), Error::<Runtime>::ZeroAmount);
+			assert_noop!(
+				OptionsProtocol::buy_option(
+					origin_of(BOB),
+					MOCK_POOL + 1,
+					EUR_USD_PAIR,
+					OptionKind::Call,
+					price(110),
+					dollar(1_000),
+					EXPIRY,
+					dollar(1_000),
+				),
+				Error::<Runtime>::PoolNotFound
+			);
+			assert_noop!(
+				OptionsProtocol::buy_option(
+					origin_of(BOB),
+					MOCK_POOL,
+					EUR_JPY_PAIR,
+					OptionKind::Call,
+					price(110),
+					dollar(1_000),
+					EXPIRY,
+					dollar(1_000),
+				),
+				Error::<Runtime>::TradingPairNotEnabled
+			);
+			assert_noop!(buy(OptionKind::Call, 110, 1_000, 1), Error::<Runtime>::InvalidExpiry);
+			assert_noop!(buy(OptionKind::Call, 110, 1_000, 102), Error::<Runtime>::InvalidExpiry);
+			assert_noop!(
+				OptionsProtocol::buy_option(
+					origin_of(BOB),
+					MOCK_POOL,
+					EUR_USD_PAIR,
+					OptionKind::Call,
+					price(110),
+					dollar(1_000),
+					EXPIRY,
+					dollar(111),
+				),
+				Error::<Runtime>::PremiumTooHigh
+			);
+		});
+}
+
+#[test]
+fn buy_option_fails_if_pool_would_be_unsafe() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.build()
+		.execute_with(|| {
+			setup();
+			MockPoolRisk::set_mock_pool_safe(false);
+			assert_noop!(
+				buy(OptionKind::Call, 110, 1_000, EXPIRY),
+				DispatchError::Other("pool would be unsafe")
+			);
+		});
+}
+
+#[test]
+fn buy_option_fails_if_price_unavailable() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.build()
+		.execute_with(|| {
+			setup();
+			MockPrices::set_mock_price(CurrencyId::FEUR, None);
+			assert_noop!(buy(OptionKind::Call, 110, 1_000, EXPIRY), Error::<Runtime>::NoPrice);
+
+			MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, Some(PriceUnavailableReason::StalePrice));
+			assert_noop!(buy(OptionKind::Call, 110, 1_000, EXPIRY), Error::<Runtime>::StalePrice);
+		});
+}
+
+#[test]
+fn buy_option_fails_if_too_many_options() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.build()
+		.execute_with(|| {
+			setup();
+			assert_ok!(buy(OptionKind::Call, 110, 1, EXPIRY));
+			assert_ok!(buy(OptionKind::Call, 110, 1, EXPIRY));
+			assert_noop!(
+				buy(OptionKind::Call, 110, 1, EXPIRY),
+				Error::<Runtime>::TooManyOptionsAtExpiry
+			);
+
+			assert_ok!(buy(OptionKind::Call, 110, 1, EXPIRY + 1));
+			assert_noop!(
+				buy(OptionKind::Call, 110, 1, EXPIRY + 1),
+				Error::<Runtime>::TooManyPoolOptions
+			);
+		});
+}
+
+#[test]
+fn options_are_settled_at_expiry() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.build()
+		.execute_with(|| {
+			setup();
+			assert_ok!(buy(OptionKind::Call, 110, 1_000, EXPIRY));
+			assert_ok!(buy(OptionKind::Put, 110, 1_000, EXPIRY));
+			assert_eq!(collateral_balance(BOB), dollar(9_864));
+			assert_eq!(collateral_balance(MOCK_POOL), dollar(10_136));
+
+			// not settled before expiry
+			set_feur_price(150);
+			settle_at(EXPIRY - 1);
+			assert_eq!(OptionsProtocol::options_by_pool(MOCK_POOL), vec![0, 1]);
+
+			// call pays out (1.5 - 1.1) * 1000, put expires worthless
+			settle_at(EXPIRY);
+			assert_eq!(collateral_balance(BOB), dollar(10_264));
+			assert_eq!(collateral_balance(MOCK_POOL), dollar(9_736));
+			assert_eq!(OptionsProtocol::options(0), None);
+			assert_eq!(OptionsProtocol::options(1), None);
+			assert_eq!(OptionsProtocol::options_by_pool(MOCK_POOL), Vec::<OptionId>::new());
+			assert_eq!(OptionsProtocol::options_by_expiry(EXPIRY), Vec::<OptionId>::new());
+
+			let event = TestEvent::options_protocol(RawEvent::OptionSettled(BOB, 0, dollar(400)));
+			assert!(System::events().iter().any(|record| record.event == event));
+			let event = TestEvent::options_protocol(RawEvent::OptionSettled(BOB, 1, 0));
+			assert!(System::events().iter().any(|record| record.event == event));
+		});
+}
+
+#[test]
+fn settlement_payout_is_capped_by_pool_liquidity() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.pool_liquidity(dollar(200))
+		.build()
+		.execute_with(|| {
+			setup();
+			assert_ok!(buy(OptionKind::Call, 110, 1_000, EXPIRY));
+
+			// intrinsic value $1900, pool liquidity $312
+			set_feur_price(300);
+			settle_at(EXPIRY);
+			assert_eq!(collateral_balance(MOCK_POOL), 0);
+			assert_eq!(collateral_balance(BOB), dollar(10_200));
+			let event = TestEvent::options_protocol(RawEvent::OptionSettled(BOB, 0, dollar(312)));
+			assert!(System::events().iter().any(|record| record.event == event));
+		});
+}
+
+#[test]
+fn settlement_is_deferred_if_price_unavailable() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.build()
+		.execute_with(|| {
+			setup();
+			assert_ok!(buy(OptionKind::Call, 110, 1_000, EXPIRY));
+
+			MockPrices::set_mock_price(CurrencyId::FEUR, None);
+			settle_at(EXPIRY);
+			let event = TestEvent::options_protocol(RawEvent::SettlementDeferred(0, EXPIRY + 1));
+			assert!(System::events().iter().any(|record| record.event == event));
+			assert!(OptionsProtocol::options(0).is_some());
+			assert_eq!(OptionsProtocol::options_by_pool(MOCK_POOL), vec![0]);
+			assert_eq!(OptionsProtocol::options_by_expiry(EXPIRY), vec![]);
+			assert_eq!(OptionsProtocol::options_by_expiry(EXPIRY + 1), vec![0]);
+
+			set_feur_price(130);
+			settle_at(EXPIRY + 1);
+			assert_eq!(collateral_balance(BOB), dollar(10_088));
+			assert_eq!(OptionsProtocol::options(0), None);
+			assert_eq!(OptionsProtocol::options_by_pool(MOCK_POOL), vec![]);
+			assert_eq!(OptionsProtocol::options_by_expiry(EXPIRY + 1), vec![]);
+		});
+}
+
+#[test]
+fn deferred_settlement_skips_full_blocks() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.build()
+		.execute_with(|| {
+			setup();
+			assert_ok!(buy(OptionKind::Call, 110, 1_000, EXPIRY));
+			assert_ok!(buy(OptionKind::Call, 110, 1_000, EXPIRY + 1));
+			assert_ok!(buy(OptionKind::Put, 130, 1_000, EXPIRY + 1));
+
+			MockPrices::set_mock_price(CurrencyId::FEUR, None);
+			settle_at(EXPIRY);
+			let event = TestEvent::options_protocol(RawEvent::SettlementDeferred(0, EXPIRY + 2));
+			assert!(System::events().iter().any(|record| record.event == event));
+			assert_eq!(OptionsProtocol::options_by_expiry(EXPIRY + 1), vec![1, 2]);
+			assert_eq!(OptionsProtocol::options_by_expiry(EXPIRY + 2), vec![0]);
+		});
+}
+
+#[test]
+fn pool_liabilities_works() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.build()
+		.execute_with(|| {
+			setup();
+			assert!(!OptionsProtocol::has_liabilities(MOCK_POOL));
+			assert_eq!(OptionsProtocol::liabilities(MOCK_POOL), Ok(Default::default()));
+
+			assert_ok!(buy(OptionKind::Call, 110, 1_000, EXPIRY));
+			assert_ok!(buy(OptionKind::Put, 110, 500, EXPIRY + 1));
+			assert!(OptionsProtocol::has_liabilities(MOCK_POOL));
+
+			// call intrinsic value (1.4 - 1.1) * 1000, notional values 1.4 * 1500
+			set_feur_price(140);
+			assert_eq!(
+				OptionsProtocol::liabilities(MOCK_POOL),
+				Ok(PoolLiabilityAmounts {
+					unrealized_loss: fixed_dollar(300),
+					net_position: fixed_dollar(2_100),
+					longest_leg: fixed_dollar(2_100),
+				})
+			);
+
+			settle_at(EXPIRY);
+			settle_at(EXPIRY + 1);
+			assert!(!OptionsProtocol::has_liabilities(MOCK_POOL));
+		});
+}
@@ -0,0 +1,160 @@
+//! Automatic trading halts on unhealthy oracle feeds.
+//!
+//! Every block, the oracle feed of each monitored currency is checked for heartbeat and quorum. Trading of a currency
+//! is halted as soon as its feed becomes unhealthy, and resumed after it has stayed healthy for the governance-set
+//! `RecoveryPeriod`. Liquidity pools consult the halts via `TradingHalt`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Decode, Encode};
+use frame_support::{
+	decl_event, decl_module, decl_storage,
+	traits::{EnsureOrigin, Get},
+	weights::Weight,
+};
+use sp_runtime::{traits::Saturating, RuntimeDebug};
+use sp_std::prelude::*;
+
+use module_primitives::CurrencyId;
+use module_traits::{PriceStatus, TradingHalt};
+
+mod mock;
+mod tests;
+
+/// Computation weight of checking the feed of one currency, excluding storage accesses.
+const CHECK_WEIGHT_PER_CURRENCY: Weight = 10_000;
+/// Storage reads of checking the feed of one currency: halt, recovery period and oracle raw values.
+const CHECK_READS_PER_CURRENCY: Weight = 3;
+/// Storage writes of checking the feed of one currency: halt.
+const CHECK_WRITES_PER_CURRENCY: Weight = 1;
+
+pub trait Trait: frame_system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+
+	/// Health of the oracle feeds, regardless of trading halts.
+	type FeedStatus: PriceStatus;
+
+	/// Currencies whose oracle feeds are monitored.
+	type MonitoredCurrencyIds: Get<Vec<CurrencyId>>;
+
+	/// Required origin for setting the recovery period.
+	type UpdateOrigin: EnsureOrigin<Self::Origin>;
+}
+
+/// Trading halt of a currency.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct Halt<BlockNumber> {
+	/// The block the feed became unhealthy at.
+	pub halted_at: BlockNumber,
+	/// The block the feed has been healthy since, or `None` if it is still unhealthy.
+	pub healthy_since: Option<BlockNumber>,
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as OracleWatchdog {
+		/// Number of blocks a feed needs to stay healthy before trading is resumed.
+		RecoveryPeriod get(fn recovery_period) config(): T::BlockNumber;
+
+		/// Trading halts of currencies with unhealthy or recovering feeds.
+		Halts get(fn halts): map hasher(twox_64_concat) CurrencyId => Option<Halt<T::BlockNumber>>;
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where
+		<T as frame_system::Trait>::BlockNumber,
+	{
+		/// Recovery period set: [period]
+		RecoveryPeriodSet(BlockNumber),
+
+		/// Trading halted on an unhealthy feed: [currency_id]
+		TradingHalted(CurrencyId),
+
+		/// Trading resumed on a recovered feed: [currency_id]
+		TradingResumed(CurrencyId),
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		/// Set the number of blocks a feed needs to stay healthy before trading is resumed.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_recovery_period(origin, period: T::BlockNumber) {
+			T::UpdateOrigin::ensure_origin(origin)?;
+			<RecoveryPeriod<T>>::put(period);
+			Self::deposit_event(RawEvent::RecoveryPeriodSet(period));
+		}
+
+		fn on_initialize(now: T::BlockNumber) -> Weight {
+			let currency_ids = T::MonitoredCurrencyIds::get();
+			for currency_id in currency_ids.iter() {
+				Self::check_feed(*currency_id, now);
+			}
+
+			let count = currency_ids.len() as Weight;
+			CHECK_WEIGHT_PER_CURRENCY.saturating_mul(count).saturating_add(T::DbWeight::get().reads_writes(
+				CHECK_READS_PER_CURRENCY.saturating_mul(count),
+				CHECK_WRITES_PER_CURRENCY.saturating_mul(count),
+			))
+		}
+	}
+}
+
+// Private methods
+impl<T: Trait> Module<T> {
+	/// Halt trading of `currency_id` if its feed is unhealthy, or resume it if the feed has recovered.
+	fn check_feed(currency_id: CurrencyId, now: T::BlockNumber) {
+		let healthy = T::FeedStatus::unavailable_reason(currency_id).is_none();
+		match (Self::halts(currency_id), healthy) {
+			(None, true) => {}
+			(None, false) => {
+				<Halts<T>>::insert(
+					currency_id,
+					Halt {
+						halted_at: now,
+						healthy_since: None,
+					},
+				);
+				Self::deposit_event(RawEvent::TradingHalted(currency_id));
+			}
+			(Some(halt), false) => {
+				// recovery restarts on any unhealthy block
+				if halt.healthy_since.is_some() {
+					<Halts<T>>::insert(
+						currency_id,
+						Halt {
+							healthy_since: None,
+							..halt
+						},
+					);
+				}
+			}
+			(Some(halt), true) => {
+				let healthy_since = halt.healthy_since.unwrap_or(now);
+				if now.saturating_sub(healthy_since) >= Self::recovery_period() {
+					<Halts<T>>::remove(currency_id);
+					Self::deposit_event(RawEvent::TradingResumed(currency_id));
+				} else if halt.healthy_since.is_none() {
+					<Halts<T>>::insert(
+						currency_id,
+						Halt {
+							healthy_since: Some(healthy_since),
+							..halt
+						},
+					);
+				}
+			}
+		}
+	}
+}
+
+impl<T: Trait> TradingHalt for Module<T> {
+	fn is_trading_halted(currency_id: CurrencyId) -> bool {
+		<Halts<T>>::contains_key(currency_id)
+	}
+}
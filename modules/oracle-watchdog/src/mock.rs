@@ -0,0 +1,80 @@
+//! Mocks for the oracle watchdog module.
+
+#![cfg(test)]
+
+use frame_support::{impl_outer_event, impl_outer_origin, ord_parameter_types, parameter_types};
+use frame_system as system;
+use system::EnsureSignedBy;
+
+use super::*;
+
+pub use laminar_test_support::MockPriceStatus;
+
+impl_outer_origin! {
+	pub enum Origin for Runtime {}
+}
+
+mod oracle_watchdog {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for Runtime {
+		frame_system<T>,
+		oracle_watchdog<T>,
+	}
+}
+
+ord_parameter_types! {
+	pub const UpdateOrigin: AccountId = 0;
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Runtime;
+
+type AccountId = u64;
+laminar_test_support::impl_mock_system!(Runtime, AccountId, TestEvent, ());
+pub type System = system::Module<Runtime>;
+
+parameter_types! {
+	pub MonitoredCurrencyIds: Vec<CurrencyId> = vec![CurrencyId::FEUR, CurrencyId::FJPY];
+}
+
+impl Trait for Runtime {
+	type Event = TestEvent;
+	type FeedStatus = MockPriceStatus;
+	type MonitoredCurrencyIds = MonitoredCurrencyIds;
+	type UpdateOrigin = EnsureSignedBy<UpdateOrigin, AccountId>;
+}
+
+pub type OracleWatchdog = Module<Runtime>;
+
+const ALICE_ACC_ID: AccountId = 0;
+pub fn alice() -> Origin {
+	Origin::signed(ALICE_ACC_ID)
+}
+
+const BOB_ACC_ID: AccountId = 1;
+pub fn bob() -> Origin {
+	Origin::signed(BOB_ACC_ID)
+}
+
+#[derive(Default)]
+pub struct ExtBuilder;
+
+impl ExtBuilder {
+	pub fn build(self) -> sp_io::TestExternalities {
+		let mut t = frame_system::GenesisConfig::default()
+			.build_storage::<Runtime>()
+			.unwrap();
+
+		GenesisConfig::<Runtime> { recovery_period: 3 }
+			.assimilate_storage(&mut t)
+			.unwrap();
+
+		let mut ext = sp_io::TestExternalities::new(t);
+		ext.execute_with(|| System::set_block_number(1));
+		ext
+	}
+}
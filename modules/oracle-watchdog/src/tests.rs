@@ -0,0 +1,112 @@
+//! Unit tests for the oracle watchdog module.
+
+#![cfg(test)]
+
+use super::*;
+use mock::*;
+
+use frame_support::{assert_noop, assert_ok, traits::OnInitialize};
+use module_traits::PriceUnavailableReason;
+use sp_runtime::traits::BadOrigin;
+
+fn run_to_block(n: u64) {
+	System::set_block_number(n);
+	<OracleWatchdog as OnInitialize<u64>>::on_initialize(n);
+}
+
+#[test]
+fn set_recovery_period_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(OracleWatchdog::set_recovery_period(bob(), 5), BadOrigin);
+
+		assert_ok!(OracleWatchdog::set_recovery_period(alice(), 5));
+		assert_eq!(OracleWatchdog::recovery_period(), 5);
+		let event = TestEvent::oracle_watchdog(RawEvent::RecoveryPeriodSet(5));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn unhealthy_feed_halts_trading() {
+	ExtBuilder::default().build().execute_with(|| {
+		run_to_block(1);
+		assert!(!OracleWatchdog::is_trading_halted(CurrencyId::FEUR));
+
+		MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, Some(PriceUnavailableReason::StalePrice));
+		run_to_block(2);
+		assert!(OracleWatchdog::is_trading_halted(CurrencyId::FEUR));
+		assert!(!OracleWatchdog::is_trading_halted(CurrencyId::FJPY));
+		assert_eq!(
+			OracleWatchdog::halts(CurrencyId::FEUR),
+			Some(Halt {
+				halted_at: 2,
+				healthy_since: None,
+			})
+		);
+		let event = TestEvent::oracle_watchdog(RawEvent::TradingHalted(CurrencyId::FEUR));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		// not monitored
+		MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FBTC, Some(PriceUnavailableReason::NoPrice));
+		run_to_block(3);
+		assert!(!OracleWatchdog::is_trading_halted(CurrencyId::FBTC));
+	});
+}
+
+#[test]
+fn trading_resumes_after_recovery_period() {
+	ExtBuilder::default().build().execute_with(|| {
+		MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, Some(PriceUnavailableReason::BelowOracleQuorum));
+		run_to_block(1);
+		assert!(OracleWatchdog::is_trading_halted(CurrencyId::FEUR));
+
+		MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, None);
+		run_to_block(2);
+		assert_eq!(
+			OracleWatchdog::halts(CurrencyId::FEUR),
+			Some(Halt {
+				halted_at: 1,
+				healthy_since: Some(2),
+			})
+		);
+
+		run_to_block(4);
+		assert!(OracleWatchdog::is_trading_halted(CurrencyId::FEUR));
+		let event = TestEvent::oracle_watchdog(RawEvent::TradingResumed(CurrencyId::FEUR));
+		assert!(!System::events().iter().any(|record| record.event == event));
+
+		run_to_block(5);
+		assert!(!OracleWatchdog::is_trading_halted(CurrencyId::FEUR));
+		let event = TestEvent::oracle_watchdog(RawEvent::TradingResumed(CurrencyId::FEUR));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn unhealthy_feed_restarts_recovery() {
+	ExtBuilder::default().build().execute_with(|| {
+		MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, Some(PriceUnavailableReason::StalePrice));
+		run_to_block(1);
+
+		MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, None);
+		run_to_block(2);
+
+		MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, Some(PriceUnavailableReason::StalePrice));
+		run_to_block(3);
+		assert_eq!(
+			OracleWatchdog::halts(CurrencyId::FEUR),
+			Some(Halt {
+				halted_at: 1,
+				healthy_since: None,
+			})
+		);
+
+		MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, None);
+		run_to_block(4);
+		run_to_block(6);
+		assert!(OracleWatchdog::is_trading_halted(CurrencyId::FEUR));
+
+		run_to_block(7);
+		assert!(!OracleWatchdog::is_trading_halted(CurrencyId::FEUR));
+	});
+}
@@ -0,0 +1,824 @@
+//! Perpetual swaps against margin liquidity pools.
+//!
+//! Positions have no expiry. They are opened and closed at the mark price: the oracle index price, plus a premium
+//! proportional to the open interest skew of the trading pair. Every funding interval, the premium is charged as the
+//! funding rate, longs paying shorts if positive. Liquidity pools are the counterparties: profits are paid out of, and
+//! losses are paid into, pool liquidity. Positions whose equity drops below the maintenance margin could be
+//! liquidated at the index price by anyone. Open positions are liabilities of the pool, folded into its ENP and ELL.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Decode, Encode};
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage, ensure,
+	traits::{EnsureOrigin, Get},
+	weights::Weight,
+	IterableStorageDoubleMap, IterableStorageMap,
+};
+use sp_arithmetic::{
+	traits::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Saturating, Zero},
+	FixedI128, FixedPointNumber, Permill,
+};
+use sp_runtime::{traits::AccountIdConversion, DispatchError, ModuleId, RuntimeDebug};
+use sp_std::{cmp, prelude::*, result};
+// FIXME: `pallet/frame-` prefix should be used for all pallet modules, but currently `frame_system`
+// would cause compiling error in `decl_module!` and `construct_runtime!`
+// #3295 https://github.com/paritytech/substrate/issues/3295
+use frame_system::{self as system, ensure_signed};
+
+use orml_traits::{BasicCurrency, PriceProvider};
+use orml_utilities::with_transaction_result;
+
+use module_primitives::{
+	arithmetic::{balance_from_fixed_i128, fixed_i128_from_balance, fixed_i128_from_fixed_u128, fixed_i128_mul_signum},
+	Balance, CurrencyId, LiquidityPoolId, Price, TradingPair,
+};
+use module_traits::{
+	LiquidityPools, MarginPoolRisk, PoolLiabilities, PoolLiabilityAmounts, PriceStatus, PriceUnavailableReason,
+};
+
+mod mock;
+mod tests;
+
+const MODULE_ID: ModuleId = ModuleId(*b"lami/ppt");
+
+/// Computation weight of applying the funding rate of a trading pair, excluding storage accesses.
+const FUNDING_WEIGHT_PER_PAIR: Weight = 10_000;
+/// Storage reads of applying the funding rate of a trading pair: open interest, cumulative funding and prices.
+const FUNDING_READS_PER_PAIR: Weight = 4;
+/// Storage writes of applying the funding rate of a trading pair: cumulative funding.
+const FUNDING_WRITES_PER_PAIR: Weight = 1;
+/// Computation weight of a position in pool liabilities, excluding storage accesses.
+const LIABILITIES_WEIGHT_PER_POSITION: Weight = 5_000;
+/// Storage reads of a position in pool liabilities: position, cumulative funding and prices.
+const LIABILITIES_READS_PER_POSITION: Weight = 4;
+
+pub trait Trait: frame_system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+
+	/// The currency used for margins.
+	type LiquidityCurrency: BasicCurrency<Self::AccountId, Balance = Balance>;
+
+	/// The liquidity pools taking the other side of positions.
+	type LiquidityPools: LiquidityPools<Self::AccountId>;
+
+	/// Safety checks of liquidity pools taking new positions.
+	type PoolRisk: MarginPoolRisk;
+
+	/// Provides market prices.
+	type PriceProvider: PriceProvider<CurrencyId, Price>;
+
+	/// Reasons of unavailable prices, to return granular price errors.
+	type PriceStatus: PriceStatus;
+
+	/// Maximum number of positions one trader could open.
+	type GetTraderMaxPositions: Get<u32>;
+
+	/// Maximum number of positions could be opened in a pool.
+	type GetPoolMaxPositions: Get<u32>;
+
+	/// Required origin for updating market parameters, and enabling and disabling trading pairs.
+	type UpdateOrigin: EnsureOrigin<Self::Origin>;
+}
+
+pub type PositionId = u64;
+
+/// Side of a position.
+#[derive(Encode, Decode, Clone, Copy, RuntimeDebug, Eq, PartialEq)]
+pub enum Side {
+	Long,
+	Short,
+}
+
+impl Side {
+	fn signum(self) -> i128 {
+		match self {
+			Side::Long => 1,
+			Side::Short => -1,
+		}
+	}
+}
+
+/// Parameters of the perpetual market of a trading pair.
+#[derive(Encode, Decode, Clone, Copy, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct MarketParams<BlockNumber> {
+	/// Max notional value of a position, as a multiple of its margin.
+	pub max_leverage: u8,
+
+	/// Positions whose equity is below this rate of the notional value could be liquidated.
+	pub maintenance_margin: Permill,
+
+	/// Fee paid to the pool out of the remaining equity on liquidation, as a rate of the notional value.
+	pub liquidation_fee: Permill,
+
+	/// Number of blocks between funding payments.
+	pub funding_interval: BlockNumber,
+
+	/// The open interest skew, in `pair.base`, at which the mark premium would be 100%.
+	pub skew_scale: Balance,
+
+	/// Max premium of the mark price over the index price. It's also the max funding rate of an interval.
+	pub max_premium: Permill,
+}
+
+/// Total sizes of the long and short positions, in `pair.base`.
+#[derive(Encode, Decode, Clone, Copy, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct OpenInterest {
+	pub long: Balance,
+	pub short: Balance,
+}
+
+impl OpenInterest {
+	fn checked_add(&self, side: Side, size: Balance) -> Option<Self> {
+		match side {
+			Side::Long => Some(Self {
+				long: self.long.checked_add(size)?,
+				..*self
+			}),
+			Side::Short => Some(Self {
+				short: self.short.checked_add(size)?,
+				..*self
+			}),
+		}
+	}
+
+	fn saturating_sub(&self, side: Side, size: Balance) -> Self {
+		match side {
+			Side::Long => Self {
+				long: self.long.saturating_sub(size),
+				..*self
+			},
+			Side::Short => Self {
+				short: self.short.saturating_sub(size),
+				..*self
+			},
+		}
+	}
+}
+
+/// A perpetual position.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq)]
+pub struct Position<AccountId> {
+	/// Owner.
+	pub owner: AccountId,
+
+	/// Liquidity pool ID.
+	pub pool: LiquidityPoolId,
+
+	/// Trading pair.
+	pub pair: TradingPair,
+
+	/// Long or short.
+	pub side: Side,
+
+	/// Size in `pair.base`.
+	pub size: Balance,
+
+	/// Mark price at opening, of `pair.base` in `pair.quote`.
+	pub entry_price: Price,
+
+	/// Margin held, in USD.
+	pub margin: Balance,
+
+	/// Cumulative funding of `pair` at opening.
+	pub entry_funding: FixedI128,
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as PerpetualProtocol {
+		/// Market parameters of trading pairs.
+		MarketParamsOf get(fn market_params): map hasher(twox_64_concat) TradingPair => Option<MarketParams<T::BlockNumber>>;
+
+		/// Trading pairs new positions could be opened on.
+		EnabledTradingPairs get(fn enabled_trading_pairs): map hasher(twox_64_concat) TradingPair => bool;
+
+		/// Open interest of trading pairs, across all pools.
+		OpenInterests get(fn open_interests): map hasher(twox_64_concat) TradingPair => OpenInterest;
+
+		/// Open interest of trading pairs in liquidity pools.
+		PoolOpenInterests get(fn pool_open_interests): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) TradingPair => OpenInterest;
+
+		/// Cumulative funding of trading pairs, in `pair.quote` per `pair.base`. It grows by the funding rate times the
+		/// index price every funding interval, and long positions pay the growth since opening.
+		CumulativeFunding get(fn cumulative_funding): map hasher(twox_64_concat) TradingPair => FixedI128;
+
+		/// Next available position ID.
+		NextPositionId get(fn next_position_id): PositionId;
+
+		/// Open positions.
+		Positions get(fn positions): map hasher(twox_64_concat) PositionId => Option<Position<T::AccountId>>;
+
+		/// Open positions of traders.
+		PositionsByTrader get(fn positions_by_trader): map hasher(twox_64_concat) T::AccountId => Vec<PositionId>;
+
+		/// Open positions in liquidity pools.
+		PositionsByPool get(fn positions_by_pool): map hasher(twox_64_concat) LiquidityPoolId => Vec<PositionId>;
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where
+		<T as frame_system::Trait>::AccountId,
+		<T as frame_system::Trait>::BlockNumber,
+	{
+		/// Market parameters set: [pair, params]
+		MarketParamsSet(TradingPair, MarketParams<BlockNumber>),
+
+		/// Trading pair enabled: [pair]
+		TradingPairEnabled(TradingPair),
+
+		/// Trading pair disabled: [pair]
+		TradingPairDisabled(TradingPair),
+
+		/// Position opened: [who, position_id, pool_id, pair, side, size, price, margin]
+		PositionOpened(AccountId, PositionId, LiquidityPoolId, TradingPair, Side, Balance, Price, Balance),
+
+		/// Position closed: [who, position_id, price, realized_pl]
+		PositionClosed(AccountId, PositionId, Price, FixedI128),
+
+		/// Position liquidated: [owner, position_id, price, realized_pl, liquidation_fee]
+		PositionLiquidated(AccountId, PositionId, Price, FixedI128, Balance),
+
+		/// Funding rate applied: [pair, rate, cumulative_funding]
+		FundingRateApplied(TradingPair, FixedI128, FixedI128),
+
+		/// Funding payment deferred to the next interval, as prices were unavailable: [pair]
+		FundingDeferred(TradingPair),
+	}
+}
+
+decl_error! {
+	/// Errors for the perpetual protocol module.
+	pub enum Error for Module<T: Trait> {
+		/// Caller doesn't have permission.
+		NoPermission,
+		/// Pool not found.
+		PoolNotFound,
+		/// Market parameters not set.
+		NoMarketParams,
+		/// Market parameters have a zero max leverage, funding interval or skew scale.
+		InvalidMarketParams,
+		/// Trading pair not enabled.
+		TradingPairNotEnabled,
+		/// Position size or margin is zero.
+		ZeroAmount,
+		/// The notional value exceeds the margin times the max leverage.
+		ExceedsMaxLeverage,
+		/// Market price is too high.
+		MarketPriceTooHigh,
+		/// Market price is too low.
+		MarketPriceTooLow,
+		/// Reached the max number of positions of the trader.
+		TooManyTraderPositions,
+		/// Reached the max number of positions of the pool.
+		TooManyPoolPositions,
+		/// No available position ID.
+		NoAvailablePositionId,
+		/// Position not found.
+		PositionNotFound,
+		/// Position equity is above the maintenance margin.
+		SafePosition,
+		/// No price has been fed.
+		NoPrice,
+		/// All fed prices have expired.
+		StalePrice,
+		/// Not enough oracle operators fed unexpired prices.
+		BelowOracleQuorum,
+		/// The market is halted.
+		MarketHalted,
+		/// Number out of bound in calculation.
+		NumOutOfBound,
+		/// A negative amount converted to balance.
+		BalanceUnderflow,
+		/// A balance too large to convert to amount.
+		BalanceOverflow,
+	}
+}
+
+impl<T: Trait> From<PriceUnavailableReason> for Error<T> {
+	fn from(reason: PriceUnavailableReason) -> Self {
+		match reason {
+			PriceUnavailableReason::NoPrice => Error::<T>::NoPrice,
+			PriceUnavailableReason::StalePrice => Error::<T>::StalePrice,
+			PriceUnavailableReason::BelowOracleQuorum => Error::<T>::BelowOracleQuorum,
+			PriceUnavailableReason::MarketHalted => Error::<T>::MarketHalted,
+		}
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		type Error = Error<T>;
+
+		fn deposit_event() = default;
+
+		const GetTraderMaxPositions: u32 = T::GetTraderMaxPositions::get();
+		const GetPoolMaxPositions: u32 = T::GetPoolMaxPositions::get();
+
+		/// Set market parameters of `pair`. They apply to open positions too.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_market_params(origin, pair: TradingPair, params: MarketParams<T::BlockNumber>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				ensure!(
+					params.max_leverage > 0 && !params.funding_interval.is_zero() && !params.skew_scale.is_zero(),
+					Error::<T>::InvalidMarketParams
+				);
+				<MarketParamsOf<T>>::insert(pair, params);
+				Self::deposit_event(RawEvent::MarketParamsSet(pair, params));
+				Ok(())
+			})?;
+		}
+
+		/// Enable opening positions on `pair`. Market parameters must be set.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn enable_trading_pair(origin, pair: TradingPair) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				ensure!(Self::market_params(pair).is_some(), Error::<T>::NoMarketParams);
+				EnabledTradingPairs::insert(pair, true);
+				Self::deposit_event(RawEvent::TradingPairEnabled(pair));
+				Ok(())
+			})?;
+		}
+
+		/// Disable opening positions on `pair`. Open positions could still be closed and liquidated, and funding is
+		/// still applied.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn disable_trading_pair(origin, pair: TradingPair) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin)?;
+				EnabledTradingPairs::remove(pair);
+				Self::deposit_event(RawEvent::TradingPairDisabled(pair));
+				Ok(())
+			})?;
+		}
+
+		/// Open a position of `size` of `pair.base` in `pool_id`, holding `margin`.
+		///
+		/// The mark price may not be above `price_limit` for long positions, or below it for short ones. Weight
+		/// includes the safety checks of the pool.
+		#[weight = 30_000 + T::PoolRisk::pool_risk_weight()]
+		pub fn open_position(
+			origin,
+			#[compact] pool_id: LiquidityPoolId,
+			pair: TradingPair,
+			side: Side,
+			#[compact] size: Balance,
+			#[compact] margin: Balance,
+			price_limit: Price,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let (position_id, price) = Self::do_open_position(&who, pool_id, pair, side, size, margin, price_limit)?;
+				Self::deposit_event(RawEvent::PositionOpened(who, position_id, pool_id, pair, side, size, price, margin));
+				Ok(())
+			})?;
+		}
+
+		/// Close a position at the mark price. Profits are paid out of the pool liquidity, capped by it.
+		///
+		/// The mark price may not be below `price_limit` for long positions, or above it for short ones.
+		#[weight = 30_000]
+		pub fn close_position(origin, #[compact] position_id: PositionId, price_limit: Price) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let (price, realized_pl) = Self::do_close_position(&who, position_id, price_limit)?;
+				Self::deposit_event(RawEvent::PositionClosed(who, position_id, price, realized_pl));
+				Ok(())
+			})?;
+		}
+
+		/// Liquidate a position whose equity is below the maintenance margin, at the index price.
+		///
+		/// May be called from anyone.
+		#[weight = 30_000]
+		pub fn liquidate(origin, #[compact] position_id: PositionId) {
+			with_transaction_result(|| {
+				ensure_signed(origin)?;
+				let (owner, price, realized_pl, fee) = Self::do_liquidate(position_id)?;
+				Self::deposit_event(RawEvent::PositionLiquidated(owner, position_id, price, realized_pl, fee));
+				Ok(())
+			})?;
+		}
+
+		fn on_initialize(now: T::BlockNumber) -> Weight {
+			let mut pairs: Weight = 0;
+			for (pair, params) in <MarketParamsOf<T>>::iter() {
+				pairs += 1;
+				if now % params.funding_interval != Zero::zero() {
+					continue;
+				}
+
+				match with_transaction_result(|| Self::apply_funding(pair, &params)) {
+					Ok((rate, cumulative)) => Self::deposit_event(RawEvent::FundingRateApplied(pair, rate, cumulative)),
+					Err(_) => Self::deposit_event(RawEvent::FundingDeferred(pair)),
+				}
+			}
+
+			pairs.saturating_mul(FUNDING_WEIGHT_PER_PAIR.saturating_add(
+				T::DbWeight::get().reads_writes(FUNDING_READS_PER_PAIR, FUNDING_WRITES_PER_PAIR),
+			))
+		}
+	}
+}
+
+type FixedI128Result = result::Result<FixedI128, DispatchError>;
+type PriceResult = result::Result<Price, DispatchError>;
+
+// Dispatchable calls implementation
+impl<T: Trait> Module<T> {
+	#[allow(clippy::too_many_arguments)]
+	fn do_open_position(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		side: Side,
+		size: Balance,
+		margin: Balance,
+		price_limit: Price,
+	) -> result::Result<(PositionId, Price), DispatchError> {
+		ensure!(!size.is_zero() && !margin.is_zero(), Error::<T>::ZeroAmount);
+		ensure!(T::LiquidityPools::pool_exists(pool_id), Error::<T>::PoolNotFound);
+		ensure!(Self::enabled_trading_pairs(pair), Error::<T>::TradingPairNotEnabled);
+		let params = Self::market_params(pair).ok_or(Error::<T>::NoMarketParams)?;
+
+		let mut trader_positions = Self::positions_by_trader(who);
+		ensure!(
+			(trader_positions.len() as u32) < T::GetTraderMaxPositions::get(),
+			Error::<T>::TooManyTraderPositions
+		);
+		let mut pool_positions = Self::positions_by_pool(pool_id);
+		ensure!(
+			(pool_positions.len() as u32) < T::GetPoolMaxPositions::get(),
+			Error::<T>::TooManyPoolPositions
+		);
+
+		let open_interest = Self::open_interests(pair)
+			.checked_add(side, size)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let price = Self::mark_price(pair, &params, open_interest)?;
+		match side {
+			Side::Long => ensure!(price <= price_limit, Error::<T>::MarketPriceTooHigh),
+			Side::Short => ensure!(price >= price_limit, Error::<T>::MarketPriceTooLow),
+		}
+
+		let notional_value = Self::notional_value(pair, size, price)?;
+		let max_notional_value = margin
+			.checked_mul(params.max_leverage.into())
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		ensure!(notional_value <= max_notional_value, Error::<T>::ExceedsMaxLeverage);
+
+		let position = Position {
+			owner: who.clone(),
+			pool: pool_id,
+			pair,
+			side,
+			size,
+			entry_price: price,
+			margin,
+			entry_funding: Self::cumulative_funding(pair),
+		};
+
+		T::LiquidityCurrency::transfer(who, &Self::account_id(), margin)?;
+		let pool_open_interest = Self::pool_open_interest_with(pool_id, pair, side, size)?;
+		T::PoolRisk::ensure_pool_safe_with(
+			pool_id,
+			pair,
+			Self::liabilities_of_new_position(&position, pool_open_interest)?,
+		)?;
+
+		let position_id = Self::next_position_id();
+		let next_position_id = position_id.checked_add(1).ok_or(Error::<T>::NoAvailablePositionId)?;
+		NextPositionId::put(next_position_id);
+
+		OpenInterests::insert(pair, open_interest);
+		PoolOpenInterests::insert(pool_id, pair, pool_open_interest);
+
+		<Positions<T>>::insert(position_id, position);
+		trader_positions.push(position_id);
+		<PositionsByTrader<T>>::insert(who, trader_positions);
+		pool_positions.push(position_id);
+		PositionsByPool::insert(pool_id, pool_positions);
+
+		Ok((position_id, price))
+	}
+
+	/// Returns `(price, realized_pl)`.
+	fn do_close_position(
+		who: &T::AccountId,
+		position_id: PositionId,
+		price_limit: Price,
+	) -> result::Result<(Price, FixedI128), DispatchError> {
+		let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+		ensure!(position.owner == *who, Error::<T>::NoPermission);
+		let params = Self::market_params(position.pair).ok_or(Error::<T>::NoMarketParams)?;
+
+		let open_interest = Self::open_interests(position.pair).saturating_sub(position.side, position.size);
+		let price = Self::mark_price(position.pair, &params, open_interest)?;
+		match position.side {
+			Side::Long => ensure!(price >= price_limit, Error::<T>::MarketPriceTooLow),
+			Side::Short => ensure!(price <= price_limit, Error::<T>::MarketPriceTooHigh),
+		}
+
+		let unrealized_pl = Self::unrealized_pl_of_position(&position, price)?;
+		let realized_pl = Self::settle_position(&position, unrealized_pl)?;
+		Self::remove_position(position_id, &position);
+
+		Ok((price, realized_pl))
+	}
+
+	/// Returns `(owner, price, realized_pl, liquidation_fee)`.
+	fn do_liquidate(
+		position_id: PositionId,
+	) -> result::Result<(T::AccountId, Price, FixedI128, Balance), DispatchError> {
+		let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+		let params = Self::market_params(position.pair).ok_or(Error::<T>::NoMarketParams)?;
+
+		let price = Self::price(position.pair.base, position.pair.quote)?;
+		let unrealized_pl = Self::unrealized_pl_of_position(&position, price)?;
+		let equity = Self::fixed_i128(position.margin)?
+			.checked_add(&unrealized_pl)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let notional_value = Self::notional_value(position.pair, position.size, price)?;
+		let maintenance_margin = Self::fixed_i128(params.maintenance_margin.mul_floor(notional_value))?;
+		ensure!(equity < maintenance_margin, Error::<T>::SafePosition);
+
+		// the fee is paid out of the remaining equity, if any
+		let remaining = cmp::max(equity, FixedI128::zero());
+		let fee = cmp::min(
+			remaining,
+			Self::fixed_i128(params.liquidation_fee.mul_floor(notional_value))?,
+		);
+		let pl_after_fee = unrealized_pl.checked_sub(&fee).ok_or(Error::<T>::NumOutOfBound)?;
+		let realized_pl = Self::settle_position(&position, pl_after_fee)?;
+		Self::remove_position(position_id, &position);
+
+		let fee = balance_from_fixed_i128(fee).map_err(|_| Error::<T>::BalanceUnderflow)?;
+		Ok((position.owner, price, realized_pl, fee))
+	}
+}
+
+// Position helpers
+impl<T: Trait> Module<T> {
+	pub fn account_id() -> T::AccountId {
+		MODULE_ID.into_account()
+	}
+
+	/// Pay `pl` of `position` and release its margin. Profits are capped by the pool liquidity, and losses by the
+	/// margin.
+	///
+	/// Returns the realized PL.
+	fn settle_position(position: &Position<T::AccountId>, pl: FixedI128) -> FixedI128Result {
+		if pl.is_negative() {
+			let loss = balance_from_fixed_i128(pl.saturating_abs()).map_err(|_| Error::<T>::BalanceUnderflow)?;
+			let loss = cmp::min(loss, position.margin);
+			if !loss.is_zero() {
+				T::LiquidityPools::deposit_liquidity(&Self::account_id(), position.pool, loss)?;
+			}
+			let remaining = position.margin - loss;
+			if !remaining.is_zero() {
+				T::LiquidityCurrency::transfer(&Self::account_id(), &position.owner, remaining)?;
+			}
+			Ok(fixed_i128_mul_signum(Self::fixed_i128(loss)?, -1))
+		} else {
+			let profit = balance_from_fixed_i128(pl).map_err(|_| Error::<T>::BalanceUnderflow)?;
+			let profit = cmp::min(profit, T::LiquidityPools::liquidity(position.pool));
+			if !profit.is_zero() {
+				T::LiquidityPools::withdraw_liquidity(&position.owner, position.pool, profit)?;
+			}
+			T::LiquidityCurrency::transfer(&Self::account_id(), &position.owner, position.margin)?;
+			Self::fixed_i128(profit)
+		}
+	}
+
+	fn remove_position(position_id: PositionId, position: &Position<T::AccountId>) {
+		OpenInterests::mutate(position.pair, |oi| {
+			*oi = oi.saturating_sub(position.side, position.size)
+		});
+		PoolOpenInterests::mutate(position.pool, position.pair, |oi| {
+			*oi = oi.saturating_sub(position.side, position.size)
+		});
+
+		<Positions<T>>::remove(position_id);
+		<PositionsByTrader<T>>::mutate(&position.owner, |ids| ids.retain(|id| *id != position_id));
+		PositionsByPool::mutate(position.pool, |ids| ids.retain(|id| *id != position_id));
+	}
+
+	/// Open interest of `pair` in `pool_id`, with a new position of `side` and `size` added.
+	fn pool_open_interest_with(
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		side: Side,
+		size: Balance,
+	) -> result::Result<OpenInterest, DispatchError> {
+		Self::pool_open_interests(pool_id, pair)
+			.checked_add(side, size)
+			.ok_or_else(|| Error::<T>::NumOutOfBound.into())
+	}
+
+	/// Unrealized PL of `position` at `price`, including funding payments, in USD.
+	///
+	/// unrealized_pl = signed_size * ((price - entry_price) - (cumulative_funding - entry_funding)) * quote_usd_price
+	fn unrealized_pl_of_position(position: &Position<T::AccountId>, price: Price) -> FixedI128Result {
+		let price_change = fixed_i128_from_fixed_u128(price)
+			.checked_sub(&fixed_i128_from_fixed_u128(position.entry_price))
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let funding = Self::cumulative_funding(position.pair)
+			.checked_sub(&position.entry_funding)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let quote_usd_price = fixed_i128_from_fixed_u128(Self::price(position.pair.quote, CurrencyId::AUSD)?);
+
+		let signed_size = fixed_i128_mul_signum(Self::fixed_i128(position.size)?, position.side.signum());
+		price_change
+			.checked_sub(&funding)
+			.and_then(|p| p.checked_mul(&signed_size))
+			.and_then(|p| p.checked_mul(&quote_usd_price))
+			.ok_or_else(|| Error::<T>::NumOutOfBound.into())
+	}
+
+	fn fixed_i128(balance: Balance) -> FixedI128Result {
+		fixed_i128_from_balance(balance).map_err(|_| Error::<T>::BalanceOverflow.into())
+	}
+}
+
+// Pricing helpers
+impl<T: Trait> Module<T> {
+	fn price(base: CurrencyId, quote: CurrencyId) -> result::Result<Price, Error<T>> {
+		T::PriceProvider::get_price(base, quote).ok_or_else(|| {
+			T::PriceStatus::unavailable_reason(base)
+				.or_else(|| T::PriceStatus::unavailable_reason(quote))
+				.map_or(Error::<T>::NoPrice, Into::into)
+		})
+	}
+
+	/// Premium of the mark price over the index price, by the skew of `open_interest`.
+	///
+	/// premium = (long_open_interest - short_open_interest) / skew_scale, capped by `max_premium` both ways
+	fn premium(open_interest: OpenInterest, params: &MarketParams<T::BlockNumber>) -> FixedI128Result {
+		let skew = Self::fixed_i128(open_interest.long)?
+			.checked_sub(&Self::fixed_i128(open_interest.short)?)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let premium = skew
+			.checked_div(&Self::fixed_i128(params.skew_scale)?)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+
+		let max_premium = fixed_i128_from_fixed_u128(params.max_premium.into());
+		Ok(cmp::max(
+			cmp::min(premium, max_premium),
+			fixed_i128_mul_signum(max_premium, -1),
+		))
+	}
+
+	/// Mark price of `pair` with `open_interest`. Trades are made at the mark price of the open interest after the
+	/// trade, so that opening and closing a position at once can't profit from its own skew.
+	///
+	/// mark_price = index_price * (1 + premium)
+	fn mark_price(
+		pair: TradingPair,
+		params: &MarketParams<T::BlockNumber>,
+		open_interest: OpenInterest,
+	) -> PriceResult {
+		let index_price = Self::price(pair.base, pair.quote)?;
+		let premium = Self::premium(open_interest, params)?;
+		let mark_price = fixed_i128_from_fixed_u128(index_price)
+			.checked_mul(&FixedI128::one().saturating_add(premium))
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		Ok(Price::from_inner(
+			balance_from_fixed_i128(mark_price).map_err(|_| Error::<T>::BalanceUnderflow)?,
+		))
+	}
+
+	/// Notional value of `size` of `pair.base` at `price`, in USD.
+	fn notional_value(pair: TradingPair, size: Balance, price: Price) -> result::Result<Balance, DispatchError> {
+		let quote_usd_price = Self::price(pair.quote, CurrencyId::AUSD)?;
+		let value = price
+			.checked_mul(&Price::from_inner(size))
+			.and_then(|v| v.checked_mul(&quote_usd_price))
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		Ok(value.into_inner())
+	}
+
+	/// Apply the funding rate of `pair` for one interval.
+	///
+	/// Returns `(rate, cumulative_funding)`.
+	fn apply_funding(
+		pair: TradingPair,
+		params: &MarketParams<T::BlockNumber>,
+	) -> result::Result<(FixedI128, FixedI128), DispatchError> {
+		let rate = Self::premium(Self::open_interests(pair), params)?;
+		let index_price = fixed_i128_from_fixed_u128(Self::price(pair.base, pair.quote)?);
+		let cumulative = rate
+			.checked_mul(&index_price)
+			.and_then(|f| Self::cumulative_funding(pair).checked_add(&f))
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		CumulativeFunding::insert(pair, cumulative);
+		Ok((rate, cumulative))
+	}
+}
+
+// Liquidity pool helpers
+impl<T: Trait> Module<T> {
+	/// Net position and longest leg of `open_interest` of `pair`, in USD.
+	///
+	/// net_position = abs(long - short) * base_usd_price
+	/// longest_leg = max(long, short) * base_usd_price
+	fn exposure(
+		pair: TradingPair,
+		open_interest: OpenInterest,
+	) -> result::Result<(FixedI128, FixedI128), DispatchError> {
+		let base_usd_price = fixed_i128_from_fixed_u128(Self::price(pair.base, CurrencyId::AUSD)?);
+		let long = Self::fixed_i128(open_interest.long)?;
+		let short = Self::fixed_i128(open_interest.short)?;
+		let net_position = long
+			.checked_sub(&short)
+			.and_then(|n| n.saturating_abs().checked_mul(&base_usd_price))
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		let longest_leg = cmp::max(long, short)
+			.checked_mul(&base_usd_price)
+			.ok_or(Error::<T>::NumOutOfBound)?;
+		Ok((net_position, longest_leg))
+	}
+
+	/// Pool unrealized loss of `position` at the index price. Trader losses are capped by the margin.
+	fn pool_unrealized_loss_of_position(position: &Position<T::AccountId>) -> FixedI128Result {
+		let price = Self::price(position.pair.base, position.pair.quote)?;
+		let unrealized_pl = Self::unrealized_pl_of_position(position, price)?;
+		let max_loss = fixed_i128_mul_signum(Self::fixed_i128(position.margin)?, -1);
+		Ok(cmp::max(unrealized_pl, max_loss))
+	}
+
+	/// Liabilities added to the pool by a new position, with `pool_open_interest` of the pair including it.
+	///
+	/// The exposure is the change of the pool's net position and longest leg of the pair, so positions reducing the
+	/// pool skew lower its net position.
+	fn liabilities_of_new_position(
+		position: &Position<T::AccountId>,
+		pool_open_interest: OpenInterest,
+	) -> result::Result<PoolLiabilityAmounts, DispatchError> {
+		let (net_before, longest_before) =
+			Self::exposure(position.pair, Self::pool_open_interests(position.pool, position.pair))?;
+		let (net_after, longest_after) = Self::exposure(position.pair, pool_open_interest)?;
+		Ok(PoolLiabilityAmounts {
+			unrealized_loss: Self::pool_unrealized_loss_of_position(position)?,
+			net_position: net_after.checked_sub(&net_before).ok_or(Error::<T>::NumOutOfBound)?,
+			longest_leg: longest_after
+				.checked_sub(&longest_before)
+				.ok_or(Error::<T>::NumOutOfBound)?,
+		})
+	}
+}
+
+impl<T: Trait> PoolLiabilities for Module<T> {
+	fn liabilities(pool_id: LiquidityPoolId) -> result::Result<PoolLiabilityAmounts, DispatchError> {
+		let exposure = PoolOpenInterests::iter_prefix(pool_id).try_fold(
+			PoolLiabilityAmounts::default(),
+			|amounts, (pair, open_interest)| -> result::Result<PoolLiabilityAmounts, DispatchError> {
+				let (net_position, longest_leg) = Self::exposure(pair, open_interest)?;
+				let exposure = PoolLiabilityAmounts {
+					unrealized_loss: Zero::zero(),
+					net_position,
+					longest_leg,
+				};
+				amounts
+					.checked_add(&exposure)
+					.ok_or_else(|| Error::<T>::NumOutOfBound.into())
+			},
+		)?;
+
+		Self::positions_by_pool(pool_id).into_iter().try_fold(
+			exposure,
+			|amounts, position_id| -> result::Result<PoolLiabilityAmounts, DispatchError> {
+				let position = Self::positions(position_id).ok_or(Error::<T>::PositionNotFound)?;
+				let unrealized_loss = amounts
+					.unrealized_loss
+					.checked_add(&Self::pool_unrealized_loss_of_position(&position)?)
+					.ok_or(Error::<T>::NumOutOfBound)?;
+				Ok(PoolLiabilityAmounts {
+					unrealized_loss,
+					..amounts
+				})
+			},
+		)
+	}
+
+	fn has_liabilities(pool_id: LiquidityPoolId) -> bool {
+		!Self::positions_by_pool(pool_id).is_empty()
+	}
+
+	/// Positions of the pool are iterated, at most `GetPoolMaxPositions`.
+	fn liabilities_weight() -> Weight {
+		let position_weight =
+			LIABILITIES_WEIGHT_PER_POSITION.saturating_add(T::DbWeight::get().reads(LIABILITIES_READS_PER_POSITION));
+		T::DbWeight::get()
+			.reads(1)
+			.saturating_add((T::GetPoolMaxPositions::get() as Weight).saturating_mul(position_weight))
+	}
+}
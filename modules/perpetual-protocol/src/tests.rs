@@ -0,0 +1,603 @@
+//! Unit tests for the perpetual protocol module.
+
+#![cfg(test)]
+
+use super::*;
+use frame_support::{assert_noop, assert_ok, traits::OnInitialize};
+use mock::*;
+use sp_arithmetic::traits::Bounded;
+use sp_runtime::{traits::BadOrigin, DispatchResult};
+
+fn price(dollar_cent: u128) -> Price {
+	Price::saturating_from_rational(dollar_cent, 100)
+}
+
+fn fixed_dollar(amount: i128) -> FixedI128 {
+	FixedI128::saturating_from_integer(amount)
+}
+
+fn origin_of(who: AccountId) -> Origin {
+	Origin::signed(who)
+}
+
+/// 20x max leverage, 5% maintenance margin, 1% liquidation fee, funding every 10 blocks, and 1% premium per 10_000
+/// EUR of skew, up to 5%.
+fn params() -> MarketParams<u64> {
+	MarketParams {
+		max_leverage: 20,
+		maintenance_margin: Permill::from_percent(5),
+		liquidation_fee: Permill::from_percent(1),
+		funding_interval: 10,
+		skew_scale: dollar(1_000_000),
+		max_premium: Permill::from_percent(5),
+	}
+}
+
+fn new_market() -> sp_io::TestExternalities {
+	ExtBuilder::default().price(CurrencyId::FEUR, (12, 10)).build()
+}
+
+/// Set market params of EUR/USD, and enable it.
+fn setup() {
+	assert_ok!(PerpetualProtocol::set_market_params(
+		origin_of(UpdateOrigin::get()),
+		EUR_USD_PAIR,
+		params()
+	));
+	assert_ok!(PerpetualProtocol::enable_trading_pair(
+		origin_of(UpdateOrigin::get()),
+		EUR_USD_PAIR
+	));
+}
+
+/// `who` opens a position of `size` EUR with `margin` dollars in the mock pool, at any price.
+fn open(who: AccountId, side: Side, size: u128, margin: u128) -> DispatchResult {
+	let price_limit = match side {
+		Side::Long => Price::max_value(),
+		Side::Short => Price::zero(),
+	};
+	PerpetualProtocol::open_position(
+		origin_of(who),
+		MOCK_POOL,
+		EUR_USD_PAIR,
+		side,
+		dollar(size),
+		dollar(margin),
+		price_limit,
+	)
+}
+
+/// `who` closes `position_id` at any price.
+fn close(who: AccountId, position_id: PositionId) -> DispatchResult {
+	let price_limit = match PerpetualProtocol::positions(position_id).map(|p| p.side) {
+		Some(Side::Short) => Price::max_value(),
+		_ => Price::zero(),
+	};
+	PerpetualProtocol::close_position(origin_of(who), position_id, price_limit)
+}
+
+fn collateral_balance(who: AccountId) -> Balance {
+	CollateralCurrency::free_balance(&who)
+}
+
+fn set_feur_price(dollar_cent: u128) {
+	MockPrices::set_mock_price(CurrencyId::FEUR, Some(price(dollar_cent)));
+}
+
+fn execute_block(block_number: u64) {
+	System::set_block_number(block_number);
+	<PerpetualProtocol as OnInitialize<u64>>::on_initialize(block_number);
+}
+
+fn liabilities(unrealized_loss: i128, net_position: i128, longest_leg: i128) -> PoolLiabilityAmounts {
+	PoolLiabilityAmounts {
+		unrealized_loss: fixed_dollar(unrealized_loss),
+		net_position: fixed_dollar(net_position),
+		longest_leg: fixed_dollar(longest_leg),
+	}
+}
+
+#[test]
+fn set_market_params_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_ok!(PerpetualProtocol::set_market_params(
+			origin_of(UpdateOrigin::get()),
+			EUR_USD_PAIR,
+			params()
+		));
+		assert_eq!(PerpetualProtocol::market_params(EUR_USD_PAIR), Some(params()));
+		let event = TestEvent::perpetual_protocol(RawEvent::MarketParamsSet(EUR_USD_PAIR, params()));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn set_market_params_fails_if_bad_origin_or_invalid_params() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			PerpetualProtocol::set_market_params(origin_of(ALICE), EUR_USD_PAIR, params()),
+			BadOrigin
+		);
+		assert_noop!(
+			PerpetualProtocol::set_market_params(
+				origin_of(UpdateOrigin::get()),
+				EUR_USD_PAIR,
+				MarketParams {
+					skew_scale: 0,
+					..params()
+				}
+			),
+			Error::<Runtime>::InvalidMarketParams
+		);
+		assert_noop!(
+			PerpetualProtocol::set_market_params(
+				origin_of(UpdateOrigin::get()),
+				EUR_USD_PAIR,
+				MarketParams {
+					funding_interval: 0,
+					..params()
+				}
+			),
+			Error::<Runtime>::InvalidMarketParams
+		);
+	});
+}
+
+#[test]
+fn enable_and_disable_trading_pair_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			PerpetualProtocol::enable_trading_pair(origin_of(UpdateOrigin::get()), EUR_USD_PAIR),
+			Error::<Runtime>::NoMarketParams
+		);
+		assert_noop!(
+			PerpetualProtocol::enable_trading_pair(origin_of(ALICE), EUR_USD_PAIR),
+			BadOrigin
+		);
+
+		setup();
+		assert!(PerpetualProtocol::enabled_trading_pairs(EUR_USD_PAIR));
+		let event = TestEvent::perpetual_protocol(RawEvent::TradingPairEnabled(EUR_USD_PAIR));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_ok!(PerpetualProtocol::disable_trading_pair(
+			origin_of(UpdateOrigin::get()),
+			EUR_USD_PAIR
+		));
+		assert!(!PerpetualProtocol::enabled_trading_pairs(EUR_USD_PAIR));
+		let event = TestEvent::perpetual_protocol(RawEvent::TradingPairDisabled(EUR_USD_PAIR));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn open_position_works() {
+	new_market().execute_with(|| {
+		setup();
+		// 1% premium with 10_000 EUR long skew
+		assert_ok!(open(BOB, Side::Long, 10_000, 1_000));
+
+		assert_eq!(
+			PerpetualProtocol::positions(0),
+			Some(Position {
+				owner: BOB,
+				pool: MOCK_POOL,
+				pair: EUR_USD_PAIR,
+				side: Side::Long,
+				size: dollar(10_000),
+				entry_price: Price::saturating_from_rational(1212, 1000),
+				margin: dollar(1_000),
+				entry_funding: FixedI128::zero(),
+			})
+		);
+		let event = TestEvent::perpetual_protocol(RawEvent::PositionOpened(
+			BOB,
+			0,
+			MOCK_POOL,
+			EUR_USD_PAIR,
+			Side::Long,
+			dollar(10_000),
+			Price::saturating_from_rational(1212, 1000),
+			dollar(1_000),
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_eq!(collateral_balance(BOB), dollar(9_000));
+		assert_eq!(collateral_balance(PerpetualProtocol::account_id()), dollar(1_000));
+		assert_eq!(
+			PerpetualProtocol::open_interests(EUR_USD_PAIR),
+			OpenInterest {
+				long: dollar(10_000),
+				short: 0,
+			}
+		);
+		assert_eq!(
+			PerpetualProtocol::pool_open_interests(MOCK_POOL, EUR_USD_PAIR),
+			PerpetualProtocol::open_interests(EUR_USD_PAIR)
+		);
+		assert_eq!(PerpetualProtocol::positions_by_trader(BOB), vec![0]);
+		assert_eq!(PerpetualProtocol::positions_by_pool(MOCK_POOL), vec![0]);
+
+		// $120 premium over the index price is the pool's gain
+		assert_eq!(
+			MockPoolRisk::checked_liabilities(),
+			Some((EUR_USD_PAIR, liabilities(-120, 12_000, 12_000)))
+		);
+	});
+}
+
+#[test]
+fn trades_are_made_at_mark_price_after_trade() {
+	new_market().execute_with(|| {
+		setup();
+		assert_ok!(open(ALICE, Side::Short, 10_000, 1_000));
+		assert_eq!(
+			PerpetualProtocol::positions(0).unwrap().entry_price,
+			Price::saturating_from_rational(1188, 1000)
+		);
+
+		// no skew after the trade
+		assert_ok!(open(BOB, Side::Long, 10_000, 1_000));
+		assert_eq!(PerpetualProtocol::positions(1).unwrap().entry_price, price(120));
+
+		// the long position offsets the pool's net position
+		assert_eq!(
+			MockPoolRisk::checked_liabilities(),
+			Some((EUR_USD_PAIR, liabilities(0, -12_000, 0)))
+		);
+	});
+}
+
+#[test]
+fn mark_premium_is_capped() {
+	new_market().execute_with(|| {
+		setup();
+		assert_ok!(open(BOB, Side::Long, 100_000, 10_000));
+		assert_eq!(
+			PerpetualProtocol::positions(0).unwrap().entry_price,
+			Price::saturating_from_rational(126, 100)
+		);
+	});
+}
+
+#[test]
+fn open_position_fails_if_invalid_params() {
+	new_market().execute_with(|| {
+		assert_noop!(
+			open(BOB, Side::Long, 10_000, 1_000),
+			Error::<Runtime>::TradingPairNotEnabled
+		);
+
+		setup();
+		assert_noop!(open(BOB, Side::Long, 0, 1_000), Error::<Runtime>::ZeroAmount);
+		assert_noop!(open(BOB, Side::Long, 10_000, 0), Error::<Runtime>::ZeroAmount);
+		assert_noop!(
+			PerpetualProtocol::open_position(
+				origin_of(BOB),
+				MOCK_POOL + 1,
+				EUR_USD_PAIR,
+				Side::Long,
+				dollar(10_000),
+				dollar(1_000),
+				Price::max_value()
+			),
+			Error::<Runtime>::PoolNotFound
+		);
+
+		// $12_120 notional value
+		assert_noop!(open(BOB, Side::Long, 10_000, 600), Error::<Runtime>::ExceedsMaxLeverage);
+		assert_ok!(open(BOB, Side::Long, 10_000, 607));
+	});
+}
+
+#[test]
+fn open_position_fails_if_price_limit_exceeded() {
+	new_market().execute_with(|| {
+		setup();
+		assert_noop!(
+			PerpetualProtocol::open_position(
+				origin_of(BOB),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Side::Long,
+				dollar(10_000),
+				dollar(1_000),
+				price(121)
+			),
+			Error::<Runtime>::MarketPriceTooHigh
+		);
+		assert_noop!(
+			PerpetualProtocol::open_position(
+				origin_of(BOB),
+				MOCK_POOL,
+				EUR_USD_PAIR,
+				Side::Short,
+				dollar(10_000),
+				dollar(1_000),
+				price(119)
+			),
+			Error::<Runtime>::MarketPriceTooLow
+		);
+	});
+}
+
+#[test]
+fn open_position_fails_if_pool_would_be_unsafe() {
+	new_market().execute_with(|| {
+		setup();
+		MockPoolRisk::set_mock_pool_safe(false);
+		assert_noop!(
+			open(BOB, Side::Long, 10_000, 1_000),
+			DispatchError::Other("pool would be unsafe")
+		);
+	});
+}
+
+#[test]
+fn open_position_fails_if_price_unavailable() {
+	new_market().execute_with(|| {
+		setup();
+		MockPrices::set_mock_price(CurrencyId::FEUR, None);
+		assert_noop!(open(BOB, Side::Long, 10_000, 1_000), Error::<Runtime>::NoPrice);
+
+		MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, Some(PriceUnavailableReason::StalePrice));
+		assert_noop!(open(BOB, Side::Long, 10_000, 1_000), Error::<Runtime>::StalePrice);
+		MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, None);
+	});
+}
+
+#[test]
+fn open_position_fails_if_too_many_positions() {
+	new_market().execute_with(|| {
+		setup();
+		assert_ok!(open(BOB, Side::Long, 1_000, 100));
+		assert_ok!(open(BOB, Side::Long, 1_000, 100));
+		assert_noop!(
+			open(BOB, Side::Long, 1_000, 100),
+			Error::<Runtime>::TooManyTraderPositions
+		);
+
+		assert_ok!(open(ALICE, Side::Short, 1_000, 100));
+		assert_noop!(
+			open(ALICE, Side::Short, 1_000, 100),
+			Error::<Runtime>::TooManyPoolPositions
+		);
+	});
+}
+
+#[test]
+fn close_position_works() {
+	new_market().execute_with(|| {
+		setup();
+		assert_ok!(open(BOB, Side::Long, 10_000, 1_000));
+		assert_noop!(close(ALICE, 0), Error::<Runtime>::NoPermission);
+		assert_noop!(close(BOB, 1), Error::<Runtime>::PositionNotFound);
+
+		// no skew after closing, at $1.3
+		set_feur_price(130);
+		assert_ok!(close(BOB, 0));
+		let event = TestEvent::perpetual_protocol(RawEvent::PositionClosed(BOB, 0, price(130), fixed_dollar(880)));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_eq!(collateral_balance(BOB), dollar(10_880));
+		assert_eq!(collateral_balance(MOCK_POOL), dollar(9_120));
+		assert_eq!(collateral_balance(PerpetualProtocol::account_id()), 0);
+		assert_eq!(PerpetualProtocol::positions(0), None);
+		assert_eq!(PerpetualProtocol::open_interests(EUR_USD_PAIR), OpenInterest::default());
+		assert_eq!(
+			PerpetualProtocol::pool_open_interests(MOCK_POOL, EUR_USD_PAIR),
+			OpenInterest::default()
+		);
+		assert!(PerpetualProtocol::positions_by_trader(BOB).is_empty());
+		assert!(!PerpetualProtocol::has_liabilities(MOCK_POOL));
+	});
+}
+
+#[test]
+fn close_short_position_works() {
+	new_market().execute_with(|| {
+		setup();
+		assert_ok!(open(BOB, Side::Short, 10_000, 1_000));
+
+		set_feur_price(110);
+		assert_ok!(close(BOB, 0));
+		assert_eq!(collateral_balance(BOB), dollar(10_880));
+		assert_eq!(collateral_balance(MOCK_POOL), dollar(9_120));
+	});
+}
+
+#[test]
+fn close_position_works_after_trading_pair_disabled() {
+	new_market().execute_with(|| {
+		setup();
+		assert_ok!(open(BOB, Side::Long, 10_000, 1_000));
+		assert_ok!(PerpetualProtocol::disable_trading_pair(
+			origin_of(UpdateOrigin::get()),
+			EUR_USD_PAIR
+		));
+
+		set_feur_price(115);
+		assert_ok!(close(BOB, 0));
+		assert_eq!(collateral_balance(BOB), dollar(9_380));
+		assert_eq!(collateral_balance(MOCK_POOL), dollar(10_620));
+	});
+}
+
+#[test]
+fn close_position_fails_if_price_limit_exceeded() {
+	new_market().execute_with(|| {
+		setup();
+		assert_ok!(open(BOB, Side::Long, 10_000, 1_000));
+		set_feur_price(130);
+		assert_noop!(
+			PerpetualProtocol::close_position(origin_of(BOB), 0, price(131)),
+			Error::<Runtime>::MarketPriceTooLow
+		);
+	});
+}
+
+#[test]
+fn realized_pl_is_capped_by_margin_and_pool_liquidity() {
+	ExtBuilder::default()
+		.price(CurrencyId::FEUR, (12, 10))
+		.pool_liquidity(dollar(500))
+		.build()
+		.execute_with(|| {
+			setup();
+			assert_ok!(open(BOB, Side::Long, 10_000, 1_000));
+			assert_ok!(open(BOB, Side::Long, 10_000, 1_000));
+
+			// $2_020 loss of the first position at $1.01, capped by margin
+			set_feur_price(100);
+			assert_ok!(close(BOB, 0));
+			let event =
+				TestEvent::perpetual_protocol(RawEvent::PositionClosed(BOB, 0, price(101), fixed_dollar(-1_000)));
+			assert!(System::events().iter().any(|record| record.event == event));
+			assert_eq!(collateral_balance(BOB), dollar(8_000));
+			assert_eq!(collateral_balance(MOCK_POOL), dollar(1_500));
+
+			// $1_760 profit of the second position, capped by pool liquidity
+			set_feur_price(140);
+			assert_ok!(close(BOB, 1));
+			let event =
+				TestEvent::perpetual_protocol(RawEvent::PositionClosed(BOB, 1, price(140), fixed_dollar(1_500)));
+			assert!(System::events().iter().any(|record| record.event == event));
+			assert_eq!(collateral_balance(BOB), dollar(10_500));
+			assert_eq!(collateral_balance(MOCK_POOL), 0);
+		});
+}
+
+#[test]
+fn funding_is_applied_every_interval() {
+	new_market().execute_with(|| {
+		setup();
+		assert_ok!(open(BOB, Side::Long, 10_000, 1_000));
+
+		// 1% premium of the $1.2 index price
+		execute_block(10);
+		assert_eq!(
+			PerpetualProtocol::cumulative_funding(EUR_USD_PAIR),
+			FixedI128::saturating_from_rational(12, 1_000)
+		);
+		let event = TestEvent::perpetual_protocol(RawEvent::FundingRateApplied(
+			EUR_USD_PAIR,
+			FixedI128::saturating_from_rational(1, 100),
+			FixedI128::saturating_from_rational(12, 1_000),
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		execute_block(15);
+		assert_eq!(
+			PerpetualProtocol::cumulative_funding(EUR_USD_PAIR),
+			FixedI128::saturating_from_rational(12, 1_000)
+		);
+
+		execute_block(20);
+		assert_eq!(
+			PerpetualProtocol::cumulative_funding(EUR_USD_PAIR),
+			FixedI128::saturating_from_rational(24, 1_000)
+		);
+
+		// $120 premium and $240 funding paid
+		assert_ok!(close(BOB, 0));
+		assert_eq!(collateral_balance(BOB), dollar(9_640));
+		assert_eq!(collateral_balance(MOCK_POOL), dollar(10_360));
+	});
+}
+
+#[test]
+fn funding_is_deferred_if_price_unavailable() {
+	new_market().execute_with(|| {
+		setup();
+		assert_ok!(open(BOB, Side::Long, 10_000, 1_000));
+
+		MockPrices::set_mock_price(CurrencyId::FEUR, None);
+		execute_block(10);
+		assert_eq!(PerpetualProtocol::cumulative_funding(EUR_USD_PAIR), FixedI128::zero());
+		let event = TestEvent::perpetual_protocol(RawEvent::FundingDeferred(EUR_USD_PAIR));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn liquidate_works() {
+	new_market().execute_with(|| {
+		setup();
+		assert_ok!(open(BOB, Side::Long, 10_000, 1_000));
+
+		// $680 equity, above the $590 maintenance margin
+		set_feur_price(118);
+		assert_noop!(
+			PerpetualProtocol::liquidate(origin_of(ALICE), 0),
+			Error::<Runtime>::SafePosition
+		);
+
+		// $180 equity, below the $565 maintenance margin; $113 liquidation fee
+		set_feur_price(113);
+		assert_ok!(PerpetualProtocol::liquidate(origin_of(ALICE), 0));
+		let event = TestEvent::perpetual_protocol(RawEvent::PositionLiquidated(
+			BOB,
+			0,
+			price(113),
+			fixed_dollar(-933),
+			dollar(113),
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_eq!(collateral_balance(BOB), dollar(9_067));
+		assert_eq!(collateral_balance(MOCK_POOL), dollar(10_933));
+		assert_eq!(collateral_balance(PerpetualProtocol::account_id()), 0);
+		assert_eq!(PerpetualProtocol::positions(0), None);
+		assert_eq!(PerpetualProtocol::open_interests(EUR_USD_PAIR), OpenInterest::default());
+	});
+}
+
+#[test]
+fn liquidate_without_remaining_equity_works() {
+	new_market().execute_with(|| {
+		setup();
+		assert_ok!(open(BOB, Side::Long, 10_000, 1_000));
+
+		set_feur_price(100);
+		assert_ok!(PerpetualProtocol::liquidate(origin_of(ALICE), 0));
+		let event = TestEvent::perpetual_protocol(RawEvent::PositionLiquidated(
+			BOB,
+			0,
+			price(100),
+			fixed_dollar(-1_000),
+			0,
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+		assert_eq!(collateral_balance(BOB), dollar(9_000));
+		assert_eq!(collateral_balance(MOCK_POOL), dollar(11_000));
+	});
+}
+
+#[test]
+fn pool_liabilities_works() {
+	new_market().execute_with(|| {
+		setup();
+		assert_eq!(PerpetualProtocol::liabilities(MOCK_POOL), Ok(liabilities(0, 0, 0)));
+
+		assert_ok!(open(BOB, Side::Long, 10_000, 1_000));
+		assert_ok!(open(ALICE, Side::Short, 5_000, 1_000));
+		assert_eq!(
+			MockPoolRisk::checked_liabilities(),
+			Some((EUR_USD_PAIR, liabilities(30, -6_000, 0)))
+		);
+
+		// -$120 of the long and $30 of the short position
+		assert_eq!(
+			PerpetualProtocol::liabilities(MOCK_POOL),
+			Ok(liabilities(-90, 6_000, 12_000))
+		);
+		assert!(PerpetualProtocol::has_liabilities(MOCK_POOL));
+
+		// -$2_120 of the long position capped by margin, and $1_030 of the short position
+		set_feur_price(100);
+		assert_eq!(
+			PerpetualProtocol::liabilities(MOCK_POOL),
+			Ok(liabilities(30, 5_000, 10_000))
+		);
+	});
+}
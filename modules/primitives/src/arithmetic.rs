@@ -1,4 +1,23 @@
-use sp_arithmetic::{traits::UniqueSaturatedInto, FixedI128, FixedPointNumber, FixedU128};
+use sp_arithmetic::{
+	helpers_128bit::multiply_by_rational, traits::UniqueSaturatedInto, FixedI128, FixedPointNumber, FixedU128,
+};
+use sp_runtime::RuntimeDebug;
+use sp_std::convert::TryFrom;
+
+use crate::Balance;
+
+/// Rounding direction of fixed point arithmetic.
+///
+/// Rounding policy of the protocols is to always round against the trader, in favor of the pool: values in the
+/// trader's favor, like profits, received amounts and sell prices, are rounded down; values against the trader,
+/// like losses, paid amounts, buy prices and margin held, are rounded up.
+#[derive(Clone, Copy, Eq, PartialEq, RuntimeDebug)]
+pub enum Rounding {
+	/// Round towards negative infinity.
+	Down,
+	/// Round towards positive infinity.
+	Up,
+}
 
 /// Create a `FixedI128` from `FixedU128` by saturating.
 ///
@@ -15,6 +34,11 @@ pub fn fixed_i128_mul_signum(f: FixedI128, signum: i128) -> FixedI128 {
 	FixedI128::from_inner(f.into_inner().saturating_mul(signum))
 }
 
+/// Absolute value of a `FixedI128`. Returns `None` on overflow.
+pub fn fixed_i128_checked_abs(f: FixedI128) -> Option<FixedI128> {
+	f.into_inner().checked_abs().map(FixedI128::from_inner)
+}
+
 /// Create a `FixedI128` from `u128` by saturating.
 ///
 /// Note the conversion may be lossy.
@@ -32,3 +56,259 @@ pub fn u128_from_fixed_i128(f: FixedI128) -> u128 {
 
 	f.into_inner().unique_saturated_into()
 }
+
+/// Error of a checked conversion between a `FixedI128` amount and a `Balance`.
+#[derive(Clone, Copy, Eq, PartialEq, RuntimeDebug)]
+pub enum BalanceConversionError {
+	/// The amount is negative, and can't be a `Balance`.
+	Underflow,
+	/// The balance is above the max `FixedI128` amount.
+	Overflow,
+}
+
+/// Convert a `FixedI128` amount to a `Balance` of the same precision.
+///
+/// Amounts moved between accounts, like realized profit and loss, liquidity and fees, must convert with this or
+/// `fixed_i128_from_balance`, so that a negative or out of range amount is an error instead of being saturated.
+pub fn balance_from_fixed_i128(f: FixedI128) -> Result<Balance, BalanceConversionError> {
+	Balance::try_from(f.into_inner()).map_err(|_| BalanceConversionError::Underflow)
+}
+
+/// Convert a `Balance` to a `FixedI128` amount of the same precision.
+pub fn fixed_i128_from_balance(b: Balance) -> Result<FixedI128, BalanceConversionError> {
+	i128::try_from(b)
+		.map(FixedI128::from_inner)
+		.map_err(|_| BalanceConversionError::Overflow)
+}
+
+/// Full 256 bits product of `a * b`, as `(high, low)`.
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+	const MASK: u128 = u64::max_value() as u128;
+	let (a_high, a_low) = (a >> 64, a & MASK);
+	let (b_high, b_low) = (b >> 64, b & MASK);
+
+	let low_low = a_low * b_low;
+	let high_low = a_high * b_low;
+	let low_high = a_low * b_high;
+	let high_high = a_high * b_high;
+
+	let cross = (low_low >> 64) + (high_low & MASK) + (low_high & MASK);
+	let low = (cross << 64) | (low_low & MASK);
+	let high = high_high + (high_low >> 64) + (low_high >> 64) + (cross >> 64);
+	(high, low)
+}
+
+/// `a * b / c` rounded to an integer by `rounding`. Returns `None` if overflow or `c` is zero.
+fn multiply_by_rational_with_rounding(a: u128, b: u128, c: u128, rounding: Rounding) -> Option<u128> {
+	if c == 0 {
+		return None;
+	}
+
+	// `multiply_by_rational` may round to the nearest, so the approximation is off by one at most.
+	let approximation = multiply_by_rational(a, b, c).ok()?;
+	let product = full_mul(a, b);
+	let floor = if full_mul(approximation, c) > product {
+		approximation - 1
+	} else {
+		approximation
+	};
+
+	let is_exact = full_mul(floor, c) == product;
+	match rounding {
+		Rounding::Up if !is_exact => floor.checked_add(1),
+		_ => Some(floor),
+	}
+}
+
+/// Signed `a * b / c` rounded to an integer by `rounding`. Returns `None` if overflow or `c` is zero.
+fn signed_multiply_by_rational_with_rounding(a: i128, b: i128, c: i128, rounding: Rounding) -> Option<i128> {
+	let abs = |x: i128| if x < 0 { (x as u128).wrapping_neg() } else { x as u128 };
+	let negative = (a < 0) ^ (b < 0) ^ (c < 0);
+	// Rounding the magnitude of a negative value up rounds the value down, and vice versa.
+	let magnitude_rounding = match (negative, rounding) {
+		(true, Rounding::Down) | (false, Rounding::Up) => Rounding::Up,
+		_ => Rounding::Down,
+	};
+	let magnitude = multiply_by_rational_with_rounding(abs(a), abs(b), abs(c), magnitude_rounding)?;
+
+	if negative {
+		if magnitude > i128::max_value() as u128 + 1 {
+			return None;
+		}
+		Some((magnitude as i128).wrapping_neg())
+	} else {
+		if magnitude > i128::max_value() as u128 {
+			return None;
+		}
+		Some(magnitude as i128)
+	}
+}
+
+/// `a * b` rounded by `rounding`. Returns `None` if overflow.
+pub fn fixed_i128_mul_with_rounding(a: FixedI128, b: FixedI128, rounding: Rounding) -> Option<FixedI128> {
+	signed_multiply_by_rational_with_rounding(a.into_inner(), b.into_inner(), FixedI128::accuracy(), rounding)
+		.map(FixedI128::from_inner)
+}
+
+/// `a / b` rounded by `rounding`. Returns `None` if overflow or `b` is zero.
+pub fn fixed_i128_div_with_rounding(a: FixedI128, b: FixedI128, rounding: Rounding) -> Option<FixedI128> {
+	signed_multiply_by_rational_with_rounding(a.into_inner(), FixedI128::accuracy(), b.into_inner(), rounding)
+		.map(FixedI128::from_inner)
+}
+
+/// `a / b` rounded by `rounding`. Returns `None` if overflow or `b` is zero.
+pub fn fixed_u128_div_with_rounding(a: FixedU128, b: FixedU128, rounding: Rounding) -> Option<FixedU128> {
+	multiply_by_rational_with_rounding(a.into_inner(), FixedU128::accuracy(), b.into_inner(), rounding)
+		.map(FixedU128::from_inner)
+}
+
+/// `f * n` rounded to an integer by `rounding`. Returns `None` if overflow.
+pub fn fixed_u128_mul_int_with_rounding(f: FixedU128, n: u128, rounding: Rounding) -> Option<u128> {
+	multiply_by_rational_with_rounding(f.into_inner(), n, FixedU128::accuracy(), rounding)
+}
+
+/// `a * b / c` rounded by `rounding`. Returns `None` if overflow or `c` is zero.
+pub fn balance_mul_div_with_rounding(a: Balance, b: Balance, c: Balance, rounding: Rounding) -> Option<Balance> {
+	multiply_by_rational_with_rounding(a, b, c, rounding)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Deterministic pseudo random `u128`s, with varying magnitudes.
+	fn samples() -> Vec<u128> {
+		let mut seed: u128 = 0x2545_f491_4f6c_dd1d;
+		(0..500)
+			.map(|i| {
+				seed = seed
+					.wrapping_mul(6_364_136_223_846_793_005)
+					.wrapping_add(1_442_695_040_888_963_407);
+				seed >> (i % 128)
+			})
+			.collect()
+	}
+
+	#[test]
+	fn balance_conversion_works() {
+		assert_eq!(balance_from_fixed_i128(FixedI128::zero()), Ok(0));
+		assert_eq!(
+			balance_from_fixed_i128(FixedI128::saturating_from_rational(15, 10)),
+			Ok(1_500_000_000_000_000_000)
+		);
+		assert_eq!(
+			balance_from_fixed_i128(FixedI128::max_value()),
+			Ok(i128::max_value() as Balance)
+		);
+		assert_eq!(
+			balance_from_fixed_i128(FixedI128::from_inner(-1)),
+			Err(BalanceConversionError::Underflow)
+		);
+		assert_eq!(
+			balance_from_fixed_i128(FixedI128::min_value()),
+			Err(BalanceConversionError::Underflow)
+		);
+
+		assert_eq!(fixed_i128_from_balance(0), Ok(FixedI128::zero()));
+		assert_eq!(
+			fixed_i128_from_balance(1_500_000_000_000_000_000),
+			Ok(FixedI128::saturating_from_rational(15, 10))
+		);
+		assert_eq!(
+			fixed_i128_from_balance(i128::max_value() as Balance),
+			Ok(FixedI128::max_value())
+		);
+		assert_eq!(
+			fixed_i128_from_balance(i128::max_value() as Balance + 1),
+			Err(BalanceConversionError::Overflow)
+		);
+		assert_eq!(
+			fixed_i128_from_balance(Balance::max_value()),
+			Err(BalanceConversionError::Overflow)
+		);
+	}
+
+	#[test]
+	fn balance_conversion_round_trips() {
+		for b in samples().into_iter().filter(|b| *b <= i128::max_value() as Balance) {
+			let f = fixed_i128_from_balance(b).unwrap();
+			assert_eq!(balance_from_fixed_i128(f), Ok(b));
+		}
+	}
+
+	#[test]
+	fn rounding_works() {
+		let third = FixedI128::saturating_from_rational(1, 3);
+		assert_eq!(
+			fixed_i128_div_with_rounding(FixedI128::one(), FixedI128::saturating_from_integer(3), Rounding::Down),
+			Some(third)
+		);
+		assert_eq!(
+			fixed_i128_div_with_rounding(FixedI128::one(), FixedI128::saturating_from_integer(3), Rounding::Up),
+			Some(FixedI128::from_inner(third.into_inner() + 1))
+		);
+		assert_eq!(
+			fixed_i128_div_with_rounding(FixedI128::one(), FixedI128::saturating_from_integer(-3), Rounding::Down),
+			Some(FixedI128::from_inner(-third.into_inner() - 1))
+		);
+		assert_eq!(
+			fixed_i128_div_with_rounding(FixedI128::one(), FixedI128::saturating_from_integer(-3), Rounding::Up),
+			Some(FixedI128::from_inner(-third.into_inner()))
+		);
+		assert_eq!(
+			fixed_u128_mul_int_with_rounding(FixedU128::saturating_from_rational(1, 3), 3, Rounding::Down),
+			Some(0)
+		);
+		assert_eq!(
+			fixed_u128_mul_int_with_rounding(FixedU128::saturating_from_rational(1, 3), 3, Rounding::Up),
+			Some(1)
+		);
+		assert_eq!(balance_mul_div_with_rounding(10, 2, 3, Rounding::Down), Some(6));
+		assert_eq!(balance_mul_div_with_rounding(10, 2, 3, Rounding::Up), Some(7));
+		assert_eq!(balance_mul_div_with_rounding(10, 2, 0, Rounding::Down), None);
+		assert_eq!(
+			fixed_i128_mul_with_rounding(FixedI128::max_value(), FixedI128::saturating_from_integer(2), Rounding::Down),
+			None
+		);
+		assert_eq!(
+			fixed_i128_div_with_rounding(FixedI128::one(), FixedI128::zero(), Rounding::Down),
+			None
+		);
+	}
+
+	#[test]
+	fn rounding_down_never_exceeds_rounding_up() {
+		let samples = samples();
+		for (a, b) in samples.iter().zip(samples.iter().rev()) {
+			let (a, b) = (FixedU128::from_inner(*a), FixedU128::from_inner(*b));
+			if let (Some(down), Some(up)) = (
+				fixed_u128_div_with_rounding(a, b, Rounding::Down),
+				fixed_u128_div_with_rounding(a, b, Rounding::Up),
+			) {
+				assert!(up.into_inner() - down.into_inner() <= 1);
+				// rounded down `a / b` times `b` never exceeds `a`, and rounded up never falls below
+				let down_product = fixed_u128_mul_int_with_rounding(b, down.into_inner(), Rounding::Down).unwrap();
+				assert!(down_product <= a.into_inner());
+				if let Some(up_product) = fixed_u128_mul_int_with_rounding(b, up.into_inner(), Rounding::Up) {
+					assert!(up_product >= a.into_inner());
+				}
+			}
+
+			let (a, b) = (
+				FixedI128::from_inner(a.into_inner() as i128),
+				FixedI128::from_inner(-((b.into_inner() >> 1) as i128)),
+			);
+			if let (Some(down), Some(up)) = (
+				fixed_i128_mul_with_rounding(a, b, Rounding::Down),
+				fixed_i128_mul_with_rounding(a, b, Rounding::Up),
+			) {
+				assert!(down <= up);
+				assert!(up.into_inner() - down.into_inner() <= 1);
+				// default rounding lies in between
+				if let Some(product) = a.checked_mul(&b) {
+					assert!(down <= product && product <= up);
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,177 @@
+//! Human-readable decimal rendering of fixed point values in RPC responses.
+//!
+//! Fixed point values are rendered as `{ "inner": "1073500000000000000", "decimal": "1.0735", "scale":
+//! "1000000000000000000" }`, with `decimal = inner / scale`. Integers are kept in strings as they may not fit in
+//! JSON numbers.
+
+use serde::{Deserialize, Serialize};
+use sp_arithmetic::{FixedI128, FixedPointNumber, FixedU128, PerThing, Permill};
+
+use crate::CurrencyId;
+
+pub use serde_json::{Error, Map, Value};
+
+pub type Result<T> = sp_std::result::Result<T, Error>;
+
+/// A fixed point value with its raw inner value, decimal string and scaling factor.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Decimal {
+	pub inner: String,
+	pub decimal: String,
+	pub scale: String,
+}
+
+impl Decimal {
+	/// Create a `Decimal` of `inner / scale`.
+	pub fn new(inner: i128, scale: u128) -> Self {
+		// `wrapping_neg` as `i128::MIN` has no positive counterpart
+		let abs = if inner < 0 {
+			(inner as u128).wrapping_neg()
+		} else {
+			inner as u128
+		};
+		Self::from_parts(inner < 0, abs, scale)
+	}
+
+	/// Create a `Decimal` of unsigned `inner / scale`.
+	pub fn new_unsigned(inner: u128, scale: u128) -> Self {
+		Self::from_parts(false, inner, scale)
+	}
+
+	fn from_parts(negative: bool, abs: u128, scale: u128) -> Self {
+		let (integer, fraction) = (abs / scale, abs % scale);
+
+		let sign = if negative { "-" } else { "" };
+		let mut decimal = format!("{}{}", sign, integer);
+		if fraction > 0 {
+			let width = scale.to_string().len() - 1;
+			let fraction = format!("{:0width$}", fraction, width = width);
+			decimal.push('.');
+			decimal.push_str(fraction.trim_end_matches('0'));
+		}
+
+		Decimal {
+			inner: format!("{}{}", sign, abs),
+			decimal,
+			scale: scale.to_string(),
+		}
+	}
+}
+
+/// Render a value with fixed point values as `Decimal`s.
+pub trait ToDecimal {
+	fn to_decimal(&self) -> Result<Value>;
+}
+
+impl ToDecimal for FixedI128 {
+	fn to_decimal(&self) -> Result<Value> {
+		serde_json::to_value(Decimal::new(self.into_inner(), FixedI128::accuracy() as u128))
+	}
+}
+
+impl ToDecimal for FixedU128 {
+	fn to_decimal(&self) -> Result<Value> {
+		serde_json::to_value(Decimal::new_unsigned(self.into_inner(), FixedU128::accuracy()))
+	}
+}
+
+impl ToDecimal for Permill {
+	fn to_decimal(&self) -> Result<Value> {
+		serde_json::to_value(Decimal::new(self.deconstruct().into(), Permill::ACCURACY.into()))
+	}
+}
+
+impl<T: ToDecimal> ToDecimal for Option<T> {
+	fn to_decimal(&self) -> Result<Value> {
+		self.as_ref().map_or(Ok(Value::Null), ToDecimal::to_decimal)
+	}
+}
+
+impl<T: ToDecimal> ToDecimal for Vec<T> {
+	fn to_decimal(&self) -> Result<Value> {
+		self.iter()
+			.map(ToDecimal::to_decimal)
+			.collect::<Result<Vec<_>>>()
+			.map(Value::Array)
+	}
+}
+
+macro_rules! impl_to_decimal_as_is {
+	( $( $ty:ty ),* ) => {
+		$(
+			impl ToDecimal for $ty {
+				fn to_decimal(&self) -> Result<Value> {
+					serde_json::to_value(self)
+				}
+			}
+		)*
+	};
+}
+
+impl_to_decimal_as_is!(bool, u8, u32, u64, u128, CurrencyId);
+
+/// Implement `ToDecimal` for a struct, rendering each of the given fields.
+#[macro_export]
+macro_rules! impl_to_decimal {
+	( $name:ident { $( $field:ident ),* $(,)? } ) => {
+		impl $crate::decimal::ToDecimal for $name {
+			fn to_decimal(&self) -> $crate::decimal::Result<$crate::decimal::Value> {
+				let mut map = $crate::decimal::Map::new();
+				$(
+					map.insert(
+						stringify!($field).into(),
+						$crate::decimal::ToDecimal::to_decimal(&self.$field)?,
+					);
+				)*
+				Ok($crate::decimal::Value::Object(map))
+			}
+		}
+	};
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn decimal(value: impl ToDecimal) -> String {
+		value.to_decimal().unwrap()["decimal"].as_str().unwrap().to_string()
+	}
+
+	#[test]
+	fn decimal_string_has_correct_scale() {
+		assert_eq!(decimal(FixedI128::saturating_from_rational(10735, 10000)), "1.0735");
+		assert_eq!(decimal(FixedI128::saturating_from_rational(-1, 2)), "-0.5");
+		assert_eq!(decimal(FixedI128::saturating_from_integer(-3)), "-3");
+		assert_eq!(decimal(FixedI128::from_inner(1)), "0.000000000000000001");
+		assert_eq!(decimal(FixedI128::from_inner(0)), "0");
+		assert_eq!(decimal(FixedU128::saturating_from_rational(10735, 10000)), "1.0735");
+		assert_eq!(decimal(Permill::from_parts(50_500)), "0.0505");
+		assert_eq!(decimal(Permill::one()), "1");
+	}
+
+	#[test]
+	fn renders_inner_value_and_scale() {
+		assert_eq!(
+			FixedI128::saturating_from_rational(-10735, 10000).to_decimal().unwrap(),
+			serde_json::json!({
+				"inner": "-1073500000000000000",
+				"decimal": "-1.0735",
+				"scale": "1000000000000000000",
+			})
+		);
+		assert_eq!(
+			FixedU128::from_inner(u128::max_value()).to_decimal().unwrap()["inner"],
+			u128::max_value().to_string()
+		);
+	}
+
+	#[test]
+	fn renders_options_and_vectors() {
+		assert_eq!(None::<FixedI128>.to_decimal().unwrap(), Value::Null);
+		assert_eq!(
+			vec![Some(Permill::from_percent(5)), None].to_decimal().unwrap(),
+			serde_json::json!([{ "inner": "50000", "decimal": "0.05", "scale": "1000000" }, null])
+		);
+		assert_eq!(10u128.to_decimal().unwrap(), serde_json::json!(10));
+	}
+}
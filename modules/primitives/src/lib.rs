@@ -1,7 +1,7 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 use codec::{Decode, Encode, Error, Input};
-use sp_runtime::{FixedU128, RuntimeDebug};
+use sp_runtime::{traits::AtLeast32Bit, FixedU128, RuntimeDebug};
 use sp_std::{prelude::*, vec};
 
 #[macro_use]
@@ -11,6 +11,8 @@ extern crate bitmask;
 use serde::{Deserialize, Serialize};
 
 pub mod arithmetic;
+#[cfg(feature = "std")]
+pub mod decimal;
 
 pub type LiquidityPoolId = u32;
 
@@ -93,6 +95,21 @@ impl Decode for Leverage {
 	}
 }
 
+#[cfg(feature = "std")]
+impl Serialize for Leverage {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_u8(u16::trailing_zeros(**self) as u8)
+	}
+}
+
+#[cfg(feature = "std")]
+impl<'de> Deserialize<'de> for Leverage {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let trailing_zeros = u8::deserialize(deserializer)?;
+		Leverage::decode(&mut &[trailing_zeros][..]).map_err(|_| serde::de::Error::custom("invalid leverage"))
+	}
+}
+
 impl Leverage {
 	pub fn is_long(&self) -> bool {
 		!self.is_short()
@@ -114,6 +131,28 @@ impl Leverage {
 			Leverage::LongReserved | Leverage::ShortReserved => 100,
 		}
 	}
+
+	/// The leverage of the same value in the opposite direction.
+	pub fn opposite(&self) -> Self {
+		match *self {
+			Leverage::LongTwo => Leverage::ShortTwo,
+			Leverage::LongThree => Leverage::ShortThree,
+			Leverage::LongFive => Leverage::ShortFive,
+			Leverage::LongTen => Leverage::ShortTen,
+			Leverage::LongTwenty => Leverage::ShortTwenty,
+			Leverage::LongThirty => Leverage::ShortThirty,
+			Leverage::LongFifty => Leverage::ShortFifty,
+			Leverage::LongReserved => Leverage::ShortReserved,
+			Leverage::ShortTwo => Leverage::LongTwo,
+			Leverage::ShortThree => Leverage::LongThree,
+			Leverage::ShortFive => Leverage::LongFive,
+			Leverage::ShortTen => Leverage::LongTen,
+			Leverage::ShortTwenty => Leverage::LongTwenty,
+			Leverage::ShortThirty => Leverage::LongThirty,
+			Leverage::ShortFifty => Leverage::LongFifty,
+			Leverage::ShortReserved => Leverage::LongReserved,
+		}
+	}
 }
 
 #[cfg(not(feature = "std"))]
@@ -154,6 +193,40 @@ pub struct TradingPair {
 	pub quote: CurrencyId,
 }
 
+/// Trading pair conventions, shared by UIs and the protocol.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct TradingPairMetadata {
+	/// Price change of one pip, e.g. `0.0001` for EUR/USD.
+	pub pip_size: Price,
+
+	/// Number of decimal places quoted prices are displayed in.
+	pub quote_precision: u8,
+
+	/// Minimum price increment. Spreads are multiples of it.
+	pub min_increment: Price,
+
+	/// Display ordering, ascending.
+	pub display_order: u32,
+}
+
+/// Trader classification tier, e.g. for regulatory leverage limits.
+#[derive(Encode, Decode, Copy, Clone, RuntimeDebug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum TraderTier {
+	/// Retail trader. Traders not classified are retail.
+	Retail,
+
+	/// Professional trader.
+	Professional,
+}
+
+impl Default for TraderTier {
+	fn default() -> Self {
+		TraderTier::Retail
+	}
+}
+
 /// Liquidity pool identity info.
 #[derive(Encode, Decode, RuntimeDebug, Eq, PartialEq, Default, Clone)]
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
@@ -176,6 +249,164 @@ pub struct IdentityInfo {
 	pub image_url: Vec<u8>,
 }
 
+/// The origin of a privileged parameter change.
+#[derive(Encode, Decode, RuntimeDebug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum AuditActor<AccountId> {
+	/// Root origin.
+	Root,
+
+	/// Signed origin.
+	Signed(AccountId),
+
+	/// Any other origin, e.g. a council.
+	Other,
+}
+
+/// A privileged parameter change record.
+#[derive(Encode, Decode, RuntimeDebug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct AuditRecord<AccountId, BlockNumber> {
+	/// Who made the change.
+	pub actor: AuditActor<AccountId>,
+
+	/// Name of the pallet, e.g. `MarginProtocol`.
+	pub pallet: Vec<u8>,
+
+	/// Name of the parameter, e.g. `RiskThresholds`.
+	pub parameter: Vec<u8>,
+
+	/// SCALE encoded key of the parameter, empty if the parameter is a single value.
+	pub key: Vec<u8>,
+
+	/// SCALE encoded value before the change.
+	pub old_value: Vec<u8>,
+
+	/// SCALE encoded value after the change.
+	pub new_value: Vec<u8>,
+
+	/// The block number the change was made in.
+	pub block_number: BlockNumber,
+}
+
+/// Volume accumulated in the current period, with the volume of the previous period.
+#[derive(Encode, Decode, RuntimeDebug, Eq, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct RollingVolume<BlockNumber, Volume> {
+	/// Start block number of the current period.
+	pub period_start: BlockNumber,
+
+	/// Volume of the current period.
+	pub current: Volume,
+
+	/// Volume of the previous period.
+	pub previous: Volume,
+}
+
+impl<BlockNumber: AtLeast32Bit + Copy, Volume: Default> RollingVolume<BlockNumber, Volume> {
+	/// Roll over to the period `now` is in, if the current period ended. Periods start at multiples of `period`.
+	pub fn roll_over(&mut self, now: BlockNumber, period: BlockNumber) {
+		if period.is_zero() {
+			return;
+		}
+		let period_start = now - now % period;
+		if period_start <= self.period_start {
+			return;
+		}
+
+		let current = sp_std::mem::take(&mut self.current);
+		// volume of skipped periods is zero
+		self.previous = if period_start - self.period_start == period {
+			current
+		} else {
+			Default::default()
+		};
+		self.period_start = period_start;
+	}
+}
+
+/// Weights of splitting a fee or penalty among the pool owner, the insurance fund, the treasury and the keeper.
+#[derive(Encode, Decode, RuntimeDebug, Eq, PartialEq, Clone, Copy, Default)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct FeeSplit {
+	/// Weight of the liquidity pool owner.
+	pub pool_owner: u32,
+
+	/// Weight of the insurance fund.
+	pub insurance_fund: u32,
+
+	/// Weight of the treasury.
+	pub treasury: u32,
+
+	/// Weight of the keeper who triggered the fee or penalty, e.g. the liquidator.
+	pub keeper: u32,
+}
+
+/// Shares of a fee or penalty split by `FeeSplit`.
+#[derive(Encode, Decode, RuntimeDebug, Eq, PartialEq, Clone, Copy, Default)]
+pub struct FeeSplitAmounts {
+	/// Share of the liquidity pool owner.
+	pub pool_owner: Balance,
+
+	/// Share of the insurance fund.
+	pub insurance_fund: Balance,
+
+	/// Share of the treasury.
+	pub treasury: Balance,
+
+	/// Share of the keeper.
+	pub keeper: Balance,
+}
+
+impl FeeSplit {
+	/// All to the treasury.
+	pub fn all_to_treasury() -> Self {
+		Self {
+			treasury: 1,
+			..Default::default()
+		}
+	}
+
+	/// All to the keeper.
+	pub fn all_to_keeper() -> Self {
+		Self {
+			keeper: 1,
+			..Default::default()
+		}
+	}
+
+	/// Split `amount` by weights, rounding down. Rounding remainders go to the treasury, and so does all of `amount`
+	/// if all weights are zero.
+	pub fn split(&self, amount: Balance) -> FeeSplitAmounts {
+		let total = Balance::from(self.pool_owner)
+			+ Balance::from(self.insurance_fund)
+			+ Balance::from(self.treasury)
+			+ Balance::from(self.keeper);
+		if total == 0 {
+			return FeeSplitAmounts {
+				treasury: amount,
+				..Default::default()
+			};
+		}
+
+		// share <= amount, never overflows
+		let share = |weight: u32| {
+			arithmetic::balance_mul_div_with_rounding(amount, weight.into(), total, arithmetic::Rounding::Down)
+				.unwrap_or_default()
+		};
+		let pool_owner = share(self.pool_owner);
+		let insurance_fund = share(self.insurance_fund);
+		let keeper = share(self.keeper);
+
+		FeeSplitAmounts {
+			pool_owner,
+			insurance_fund,
+			treasury: amount - pool_owner - insurance_fund - keeper,
+			keeper,
+		}
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -202,6 +433,59 @@ mod tests {
 		Leverage::LongReserved,
 	];
 
+	#[test]
+	fn rolling_volume_roll_over_works() {
+		let rolling = |period_start, current, previous| RollingVolume {
+			period_start,
+			current,
+			previous,
+		};
+		let mut volume = rolling(0u32, 10u128, 0);
+
+		volume.roll_over(9, 10);
+		assert_eq!(volume, rolling(0, 10, 0));
+
+		volume.roll_over(15, 10);
+		assert_eq!(volume, rolling(10, 0, 10));
+
+		volume.current = 20;
+		volume.roll_over(29, 0);
+		assert_eq!(volume, rolling(10, 20, 10));
+
+		// skipped a period
+		volume.roll_over(30, 10);
+		assert_eq!(volume, rolling(30, 0, 0));
+	}
+
+	#[test]
+	fn fee_split_works() {
+		let split = FeeSplit {
+			pool_owner: 5,
+			insurance_fund: 2,
+			treasury: 2,
+			keeper: 1,
+		};
+		assert_eq!(
+			split.split(1_005),
+			FeeSplitAmounts {
+				pool_owner: 502,
+				insurance_fund: 201,
+				treasury: 202,
+				keeper: 100,
+			}
+		);
+
+		assert_eq!(
+			FeeSplit::default().split(100),
+			FeeSplitAmounts {
+				treasury: 100,
+				..Default::default()
+			}
+		);
+		assert_eq!(FeeSplit::all_to_keeper().split(100).keeper, 100);
+		assert_eq!(FeeSplit::all_to_treasury().split(100).treasury, 100);
+	}
+
 	#[test]
 	fn check_leverages_all_value() {
 		assert_eq!(*Leverages::all(), 0xffff);
@@ -248,6 +532,14 @@ mod tests {
 		assert_eq!(Leverage::ShortFifty.value(), 50);
 	}
 
+	#[test]
+	fn opposite_should_work() {
+		for (long, short) in LONGS.iter().zip(SHORTS.iter()) {
+			assert_eq!(long.opposite(), *short);
+			assert_eq!(short.opposite(), *long);
+		}
+	}
+
 	#[test]
 	fn encode_decode_should_work() {
 		let mut all = LONGS.clone().to_vec();
@@ -0,0 +1,422 @@
+//! Spot exchange between the collateral currency and synthetic tokens.
+//!
+//! Synthetic liquidity pools deposit synthetic token inventories, and anyone could buy from or sell to them at the
+//! oracle price plus or minus the pool spread. Unlike minting and redeeming in the synthetic protocol, no collateral
+//! positions are created: the collateral paid goes into, and is paid out of, the pool liquidity directly.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use frame_support::{decl_error, decl_event, decl_module, decl_storage, ensure, traits::Get, IterableStorageDoubleMap};
+use sp_runtime::{
+	traits::{AccountIdConversion, CheckedAdd, CheckedSub, Zero},
+	DispatchError, DispatchResult, FixedPointNumber, ModuleId, Permill,
+};
+use sp_std::{prelude::*, result};
+// FIXME: `pallet/frame-` prefix should be used for all pallet modules, but currently `frame_system`
+// would cause compiling error in `decl_module!` and `construct_runtime!`
+// #3295 https://github.com/paritytech/substrate/issues/3295
+use frame_system::{self as system, ensure_signed};
+
+use orml_traits::{MultiCurrency, PriceProvider};
+use orml_utilities::with_transaction_result;
+
+use module_primitives::{
+	arithmetic::{fixed_u128_div_with_rounding, fixed_u128_mul_int_with_rounding, Rounding},
+	Balance, CurrencyId, LiquidityPoolId, Price,
+};
+use module_traits::{
+	BaseLiquidityPoolManager, FeeDiscount, LiquidityPools, PriceStatus, PriceUnavailableReason,
+	SyntheticProtocolLiquidityPools,
+};
+
+mod mock;
+mod tests;
+
+const MODULE_ID: ModuleId = ModuleId(*b"lami/spt");
+
+pub trait Trait: frame_system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+
+	/// The `MultiCurrency` implementation for synthetic.
+	type MultiCurrency: MultiCurrency<Self::AccountId, Balance = Balance, CurrencyId = CurrencyId>;
+
+	/// `Get` implementation of collateral currency ID.
+	type GetCollateralCurrencyId: Get<CurrencyId>;
+
+	/// Synthetic currency IDs could be exchanged.
+	type SyntheticCurrencyIds: Get<Vec<CurrencyId>>;
+
+	/// Provides market prices.
+	type PriceProvider: PriceProvider<CurrencyId, Price>;
+
+	/// Reasons of unavailable prices, to return granular price errors.
+	type PriceStatus: PriceStatus;
+
+	/// The synthetic liquidity pools providing spreads and collateral liquidity.
+	type LiquidityPools: SyntheticProtocolLiquidityPools<Self::AccountId>;
+
+	/// Trading fee discounts of traders, applied to spreads.
+	type FeeDiscount: FeeDiscount<Self::AccountId>;
+
+	/// Maximum collateral amount of one trade.
+	type GetMaxTradeAmount: Get<Balance>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as SpotExchange {
+		/// Synthetic token inventories of liquidity pools, held by the module account.
+		Inventories get(fn inventories): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) CurrencyId => Balance;
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where
+		<T as frame_system::Trait>::AccountId,
+	{
+		/// Inventory deposited: [who, pool_id, synthetic_currency_id, synthetic_amount]
+		InventoryDeposited(AccountId, LiquidityPoolId, CurrencyId, Balance),
+
+		/// Inventory withdrew: [who, pool_id, synthetic_currency_id, synthetic_amount]
+		InventoryWithdrew(AccountId, LiquidityPoolId, CurrencyId, Balance),
+
+		/// Synthetic token bought: [who, synthetic_currency_id, pool_id, collateral_amount, synthetic_amount]
+		Bought(AccountId, CurrencyId, LiquidityPoolId, Balance, Balance),
+
+		/// Synthetic token sold: [who, synthetic_currency_id, pool_id, collateral_amount, synthetic_amount]
+		Sold(AccountId, CurrencyId, LiquidityPoolId, Balance, Balance),
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		type Error = Error<T>;
+
+		fn deposit_event() = default;
+
+		const GetCollateralCurrencyId: CurrencyId = T::GetCollateralCurrencyId::get();
+		const GetMaxTradeAmount: Balance = T::GetMaxTradeAmount::get();
+
+		/// Deposit `synthetic_amount` of `currency_id` into the inventory of `pool_id`.
+		///
+		/// May only be called from the pool owner.
+		#[weight = 10_000]
+		pub fn deposit_inventory(
+			origin,
+			#[compact] pool_id: LiquidityPoolId,
+			currency_id: CurrencyId,
+			#[compact] synthetic_amount: Balance,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::do_deposit_inventory(&who, pool_id, currency_id, synthetic_amount)?;
+				Self::deposit_event(RawEvent::InventoryDeposited(who, pool_id, currency_id, synthetic_amount));
+				Ok(())
+			})?;
+		}
+
+		/// Withdraw `synthetic_amount` of `currency_id` from the inventory of `pool_id`.
+		///
+		/// May only be called from the pool owner.
+		#[weight = 10_000]
+		pub fn withdraw_inventory(
+			origin,
+			#[compact] pool_id: LiquidityPoolId,
+			currency_id: CurrencyId,
+			#[compact] synthetic_amount: Balance,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				Self::do_withdraw_inventory(&who, pool_id, currency_id, synthetic_amount)?;
+				Self::deposit_event(RawEvent::InventoryWithdrew(who, pool_id, currency_id, synthetic_amount));
+				Ok(())
+			})?;
+		}
+
+		/// Buy synthetic tokens from the inventory of `pool_id` with `collateral_amount`.
+		#[weight = 10_000]
+		pub fn buy(
+			origin,
+			#[compact] pool_id: LiquidityPoolId,
+			currency_id: CurrencyId,
+			#[compact] collateral_amount: Balance,
+			max_price: Price,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let synthetic_amount = Self::do_buy(&who, pool_id, currency_id, collateral_amount, max_price)?;
+				Self::deposit_event(RawEvent::Bought(who, currency_id, pool_id, collateral_amount, synthetic_amount));
+				Ok(())
+			})?;
+		}
+
+		/// Sell `synthetic_amount` to the inventory of `pool_id`, for collateral out of the pool liquidity.
+		#[weight = 10_000]
+		pub fn sell(
+			origin,
+			#[compact] pool_id: LiquidityPoolId,
+			currency_id: CurrencyId,
+			#[compact] synthetic_amount: Balance,
+			min_price: Price,
+		) {
+			with_transaction_result(|| {
+				let who = ensure_signed(origin)?;
+				let collateral_amount = Self::do_sell(&who, pool_id, currency_id, synthetic_amount, min_price)?;
+				Self::deposit_event(RawEvent::Sold(who, currency_id, pool_id, collateral_amount, synthetic_amount));
+				Ok(())
+			})?;
+		}
+	}
+}
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// Caller doesn't have permission.
+		NoPermission,
+
+		/// The currency is not a valid synthetic currency.
+		NotValidSyntheticCurrencyId,
+
+		/// The currency is not enabled in pool.
+		CannotTradeInPool,
+
+		/// The collateral amount of the trade exceeds the max trade amount.
+		ExceedsMaxTradeAmount,
+
+		/// Insufficient synthetic inventory in pool.
+		InsufficientInventory,
+
+		/// Insufficient liquidity in pool.
+		InsufficientLiquidityInPool,
+
+		/// Ask price is too high.
+		AskPriceTooHigh,
+
+		/// Bid price is too low.
+		BidPriceTooLow,
+
+		/// Bid spread not set.
+		NoBidSpread,
+
+		/// Ask spread not set.
+		NoAskSpread,
+
+		/// Number overflow in calculation.
+		NumOverflow,
+
+		/// No price from provider.
+		NoPrice,
+
+		/// Price expired.
+		StalePrice,
+
+		/// Not enough oracle operators fed the price.
+		BelowOracleQuorum,
+
+		/// Market of the price is halted.
+		MarketHalted,
+	}
+}
+
+impl<T: Trait> From<PriceUnavailableReason> for Error<T> {
+	fn from(reason: PriceUnavailableReason) -> Self {
+		match reason {
+			PriceUnavailableReason::NoPrice => Error::<T>::NoPrice,
+			PriceUnavailableReason::StalePrice => Error::<T>::StalePrice,
+			PriceUnavailableReason::BelowOracleQuorum => Error::<T>::BelowOracleQuorum,
+			PriceUnavailableReason::MarketHalted => Error::<T>::MarketHalted,
+		}
+	}
+}
+
+type BalanceResult = result::Result<Balance, DispatchError>;
+
+// Dispatchable calls implementation
+impl<T: Trait> Module<T> {
+	fn do_deposit_inventory(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		synthetic: Balance,
+	) -> DispatchResult {
+		Self::ensure_valid_currency(currency_id)?;
+		ensure!(T::LiquidityPools::is_owner(pool_id, who), Error::<T>::NoPermission);
+
+		let inventory = Self::inventories(pool_id, currency_id)
+			.checked_add(synthetic)
+			.ok_or(Error::<T>::NumOverflow)?;
+		T::MultiCurrency::transfer(currency_id, who, &Self::account_id(), synthetic)?;
+		Inventories::insert(pool_id, currency_id, inventory);
+
+		Ok(())
+	}
+
+	fn do_withdraw_inventory(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		synthetic: Balance,
+	) -> DispatchResult {
+		ensure!(T::LiquidityPools::is_owner(pool_id, who), Error::<T>::NoPermission);
+
+		let inventory = Self::inventories(pool_id, currency_id)
+			.checked_sub(synthetic)
+			.ok_or(Error::<T>::InsufficientInventory)?;
+		T::MultiCurrency::transfer(currency_id, &Self::account_id(), who, synthetic)?;
+		Inventories::insert(pool_id, currency_id, inventory);
+
+		Ok(())
+	}
+
+	fn do_buy(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		collateral: Balance,
+		max_price: Price,
+	) -> BalanceResult {
+		Self::ensure_can_trade(pool_id, currency_id)?;
+		ensure!(
+			collateral <= T::GetMaxTradeAmount::get(),
+			Error::<T>::ExceedsMaxTradeAmount
+		);
+
+		let price = Self::price(currency_id)?;
+		let discount = T::FeeDiscount::fee_discount(who);
+		let ask_price = Self::ask_price(pool_id, currency_id, price, max_price, discount)?;
+
+		// synthetic = collateral / ask_price, rounded down in favor of the pool
+		let synthetic = fixed_u128_div_with_rounding(Price::from_inner(collateral), ask_price, Rounding::Down)
+			.map(|x| x.into_inner())
+			.ok_or(Error::<T>::NumOverflow)?;
+		let inventory = Self::inventories(pool_id, currency_id)
+			.checked_sub(synthetic)
+			.ok_or(Error::<T>::InsufficientInventory)?;
+
+		T::LiquidityPools::deposit_liquidity(who, pool_id, collateral)?;
+		T::MultiCurrency::transfer(currency_id, &Self::account_id(), who, synthetic)?;
+		Inventories::insert(pool_id, currency_id, inventory);
+
+		Ok(synthetic)
+	}
+
+	fn do_sell(
+		who: &T::AccountId,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		synthetic: Balance,
+		min_price: Price,
+	) -> BalanceResult {
+		Self::ensure_can_trade(pool_id, currency_id)?;
+
+		let price = Self::price(currency_id)?;
+		let discount = T::FeeDiscount::fee_discount(who);
+		let bid_price = Self::bid_price(pool_id, currency_id, price, min_price, discount)?;
+
+		// collateral = synthetic * bid_price, rounded down in favor of the pool
+		let collateral =
+			fixed_u128_mul_int_with_rounding(bid_price, synthetic, Rounding::Down).ok_or(Error::<T>::NumOverflow)?;
+		ensure!(
+			collateral <= T::GetMaxTradeAmount::get(),
+			Error::<T>::ExceedsMaxTradeAmount
+		);
+		let inventory = Self::inventories(pool_id, currency_id)
+			.checked_add(synthetic)
+			.ok_or(Error::<T>::NumOverflow)?;
+
+		T::MultiCurrency::transfer(currency_id, who, &Self::account_id(), synthetic)?;
+		T::LiquidityPools::withdraw_liquidity(who, pool_id, collateral)
+			.map_err(|_| Error::<T>::InsufficientLiquidityInPool)?;
+		Inventories::insert(pool_id, currency_id, inventory);
+
+		Ok(collateral)
+	}
+}
+
+// Private methods
+impl<T: Trait> Module<T> {
+	pub fn account_id() -> T::AccountId {
+		MODULE_ID.into_account()
+	}
+
+	fn ensure_valid_currency(currency_id: CurrencyId) -> DispatchResult {
+		ensure!(
+			T::SyntheticCurrencyIds::get().contains(&currency_id),
+			Error::<T>::NotValidSyntheticCurrencyId
+		);
+		Ok(())
+	}
+
+	/// Return `Ok` iff `currency_id` could be traded in `pool_id`.
+	fn ensure_can_trade(pool_id: LiquidityPoolId, currency_id: CurrencyId) -> DispatchResult {
+		Self::ensure_valid_currency(currency_id)?;
+		ensure!(
+			T::LiquidityPools::can_mint(pool_id, currency_id),
+			Error::<T>::CannotTradeInPool
+		);
+		Ok(())
+	}
+
+	/// Price of `currency_id` in collateral currency.
+	fn price(currency_id: CurrencyId) -> result::Result<Price, Error<T>> {
+		let collateral_currency_id = T::GetCollateralCurrencyId::get();
+		T::PriceProvider::get_price(currency_id, collateral_currency_id).ok_or_else(|| {
+			T::PriceStatus::unavailable_reason(currency_id)
+				.or_else(|| T::PriceStatus::unavailable_reason(collateral_currency_id))
+				.map_or(Error::<T>::NoPrice, Into::into)
+		})
+	}
+
+	/// Get ask price from liquidity pool for a given currency. Would fail if price could not meet
+	/// max slippage.
+	///
+	/// ask_price = price + ask_spread * (1 - discount)
+	fn ask_price(
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		price: Price,
+		max_price: Price,
+		discount: Permill,
+	) -> result::Result<Price, DispatchError> {
+		let ask_spread = T::LiquidityPools::ask_spread(pool_id, currency_id).ok_or(Error::<T>::NoAskSpread)?;
+		let ask_spread = ask_spread.saturating_sub(discount * ask_spread);
+		let ask_price = price
+			.checked_add(&Price::from_inner(ask_spread))
+			.ok_or(Error::<T>::NumOverflow)?;
+
+		ensure!(ask_price <= max_price, Error::<T>::AskPriceTooHigh);
+		Ok(ask_price)
+	}
+
+	/// Get bid price from liquidity pool for a given currency. Would fail if price could not meet
+	/// min slippage.
+	///
+	/// bid_price = price - bid_spread * (1 - discount)
+	fn bid_price(
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		price: Price,
+		min_price: Price,
+		discount: Permill,
+	) -> result::Result<Price, DispatchError> {
+		let bid_spread = T::LiquidityPools::bid_spread(pool_id, currency_id).ok_or(Error::<T>::NoBidSpread)?;
+		let bid_spread = bid_spread.saturating_sub(discount * bid_spread);
+		let bid_price = price
+			.checked_sub(&Price::from_inner(bid_spread))
+			.ok_or(Error::<T>::NumOverflow)?;
+
+		ensure!(bid_price >= min_price, Error::<T>::BidPriceTooLow);
+		Ok(bid_price)
+	}
+}
+
+impl<T: Trait> BaseLiquidityPoolManager<LiquidityPoolId, Balance> for Module<T> {
+	/// Pools with synthetic inventories can't be removed.
+	fn can_remove(pool_id: LiquidityPoolId) -> bool {
+		Inventories::iter_prefix(pool_id).all(|(_, inventory)| inventory.is_zero())
+	}
+
+	fn ensure_can_withdraw(_pool: LiquidityPoolId, _amount: Balance) -> DispatchResult {
+		Ok(())
+	}
+}
@@ -0,0 +1,187 @@
+//! Mocks for the spot-exchange module.
+
+#![cfg(test)]
+
+use frame_support::{impl_outer_event, impl_outer_origin, parameter_types};
+use frame_system as system;
+use sp_runtime::Permill;
+
+use orml_currencies::Currency;
+use orml_traits::DefaultPriceProvider;
+
+use module_primitives::LiquidityPoolId;
+
+use super::*;
+
+pub use laminar_test_support::{MockBuilder, MockFeeDiscount, MockPriceStatus, MockPrices};
+pub use module_primitives::{Balance, CurrencyId};
+
+impl_outer_origin! {
+	pub enum Origin for Runtime {}
+}
+
+mod spot_exchange {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for Runtime {
+		frame_system<T>,
+		orml_tokens<T>, orml_currencies<T>,
+		spot_exchange<T>,
+	}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Runtime;
+laminar_test_support::impl_mock_system!(Runtime, AccountId, TestEvent, ());
+
+pub type AccountId = u32;
+pub type System = system::Module<Runtime>;
+
+type Amount = i128;
+
+impl orml_tokens::Trait for Runtime {
+	type Event = TestEvent;
+	type Balance = Balance;
+	type Amount = Amount;
+	type CurrencyId = CurrencyId;
+	type OnReceived = ();
+}
+
+parameter_types! {
+	pub const GetNativeCurrencyId: CurrencyId = CurrencyId::LAMI;
+}
+
+type NativeCurrency = Currency<Runtime, GetNativeCurrencyId>;
+
+impl orml_currencies::Trait for Runtime {
+	type Event = TestEvent;
+	type MultiCurrency = orml_tokens::Module<Runtime>;
+	type NativeCurrency = NativeCurrency;
+	type GetNativeCurrencyId = GetNativeCurrencyId;
+}
+
+parameter_types! {
+	pub const GetCollateralCurrencyId: CurrencyId = CurrencyId::AUSD;
+	pub const GetSyntheticCurrencyId: CurrencyId = CurrencyId::FEUR;
+	pub SyntheticCurrencyIds: Vec<CurrencyId> = vec![CurrencyId::FEUR];
+	pub const GetMaxTradeAmount: Balance = ONE_MILL / 2;
+}
+
+pub type CollateralCurrency = orml_currencies::Currency<Runtime, GetCollateralCurrencyId>;
+pub type SyntheticCurrency = orml_currencies::Currency<Runtime, GetSyntheticCurrencyId>;
+
+parameter_types! {
+	pub MockPools: Vec<LiquidityPoolId> = vec![MOCK_POOL];
+}
+
+/// Liquidity of mock pools is the collateral balance of the pool ID account, and spreads are the mock spread of the
+/// synthetic price. ALICE is the mock owner.
+pub type MockLiquidityPools = laminar_test_support::MockLiquidityPools<CollateralCurrency, MockPools>;
+
+impl Trait for Runtime {
+	type Event = TestEvent;
+	type MultiCurrency = orml_currencies::Module<Runtime>;
+	type GetCollateralCurrencyId = GetCollateralCurrencyId;
+	type SyntheticCurrencyIds = SyntheticCurrencyIds;
+	type PriceProvider = DefaultPriceProvider<CurrencyId, MockPrices>;
+	type PriceStatus = MockPriceStatus;
+	type LiquidityPools = MockLiquidityPools;
+	type FeeDiscount = MockFeeDiscount;
+	type GetMaxTradeAmount = GetMaxTradeAmount;
+}
+pub type SpotExchange = Module<Runtime>;
+
+pub const ALICE: AccountId = 0;
+pub const BOB: AccountId = 1;
+pub fn origin_of(account_id: AccountId) -> Origin {
+	Origin::signed(account_id)
+}
+
+pub const MOCK_POOL: LiquidityPoolId = 100;
+
+pub struct ExtBuilder {
+	endowed_accounts: Vec<(AccountId, CurrencyId, Balance)>,
+	mocks: MockBuilder,
+	spread: Permill,
+	is_allowed: bool,
+}
+
+impl Default for ExtBuilder {
+	fn default() -> Self {
+		Self {
+			endowed_accounts: vec![],
+			mocks: MockBuilder::default(),
+			spread: Permill::zero(),
+			is_allowed: true,
+		}
+	}
+}
+
+pub const ONE_MILL: Balance = 1000_000;
+impl ExtBuilder {
+	pub fn balances(mut self, endowed_accounts: Vec<(AccountId, CurrencyId, Balance)>) -> Self {
+		self.endowed_accounts = endowed_accounts;
+		self
+	}
+
+	/// ALICE owns synthetic tokens to deposit as inventory, BOB trades with collateral.
+	pub fn one_million_for_all(self) -> Self {
+		self.balances(vec![
+			(ALICE, CurrencyId::FEUR, ONE_MILL),
+			(BOB, CurrencyId::AUSD, ONE_MILL),
+			(MOCK_POOL, CurrencyId::AUSD, ONE_MILL),
+		])
+	}
+
+	pub fn synthetic_price(mut self, price: Price) -> Self {
+		self.mocks.price(CurrencyId::FEUR, price);
+		self
+	}
+
+	/// set synthetic price to `3`
+	pub fn synthetic_price_three(self) -> Self {
+		self.synthetic_price(Price::saturating_from_rational(3, 1))
+	}
+
+	pub fn spread(mut self, spread: Permill) -> Self {
+		self.spread = spread;
+		self
+	}
+
+	pub fn one_percent_spread(self) -> Self {
+		self.spread(Permill::from_percent(1))
+	}
+
+	pub fn set_is_allowed(mut self, val: bool) -> Self {
+		self.is_allowed = val;
+		self
+	}
+
+	fn set_mocks(&self) {
+		self.mocks.set_mocks();
+
+		MockLiquidityPools::set_mock_spread(self.spread);
+		MockLiquidityPools::set_is_allowed(self.is_allowed);
+	}
+
+	pub fn build(self) -> sp_io::TestExternalities {
+		self.set_mocks();
+
+		let mut t = frame_system::GenesisConfig::default()
+			.build_storage::<Runtime>()
+			.unwrap();
+
+		orml_tokens::GenesisConfig::<Runtime> {
+			endowed_accounts: self.endowed_accounts,
+		}
+		.assimilate_storage(&mut t)
+		.unwrap();
+
+		let mut ext = sp_io::TestExternalities::new(t);
+		ext.execute_with(|| System::set_block_number(1));
+		ext
+	}
+}
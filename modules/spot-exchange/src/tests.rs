@@ -0,0 +1,369 @@
+//! Unit tests for the spot-exchange module.
+
+#![cfg(test)]
+
+use super::*;
+use frame_support::{assert_noop, assert_ok};
+use mock::*;
+use orml_traits::BasicCurrency;
+use sp_runtime::{DispatchResult, Permill};
+
+fn deposit_inventory(who: AccountId, amount: Balance) -> DispatchResult {
+	SpotExchange::deposit_inventory(origin_of(who), MOCK_POOL, CurrencyId::FEUR, amount)
+}
+
+fn withdraw_inventory(who: AccountId, amount: Balance) -> DispatchResult {
+	SpotExchange::withdraw_inventory(origin_of(who), MOCK_POOL, CurrencyId::FEUR, amount)
+}
+
+fn buy_feur(who: AccountId, amount: Balance) -> DispatchResult {
+	SpotExchange::buy(
+		origin_of(who),
+		MOCK_POOL,
+		CurrencyId::FEUR,
+		amount,
+		Price::saturating_from_rational(4, 1),
+	)
+}
+
+fn sell_feur(who: AccountId, amount: Balance) -> DispatchResult {
+	SpotExchange::sell(
+		origin_of(who),
+		MOCK_POOL,
+		CurrencyId::FEUR,
+		amount,
+		Price::saturating_from_rational(2, 1),
+	)
+}
+
+fn inventory() -> Balance {
+	SpotExchange::inventories(MOCK_POOL, CurrencyId::FEUR)
+}
+
+fn mock_pool_liquidity() -> Balance {
+	MockLiquidityPools::liquidity(MOCK_POOL)
+}
+
+fn collateral_balance(who: AccountId) -> Balance {
+	CollateralCurrency::free_balance(&who)
+}
+
+fn synthetic_balance(who: AccountId) -> Balance {
+	SyntheticCurrency::free_balance(&who)
+}
+
+#[test]
+fn deposit_and_withdraw_inventory_works() {
+	ExtBuilder::default().one_million_for_all().build().execute_with(|| {
+		assert_ok!(deposit_inventory(ALICE, 300_000));
+		assert_eq!(inventory(), 300_000);
+		assert_eq!(synthetic_balance(ALICE), ONE_MILL - 300_000);
+		assert_eq!(synthetic_balance(SpotExchange::account_id()), 300_000);
+
+		let event = TestEvent::spot_exchange(RawEvent::InventoryDeposited(
+			ALICE,
+			MOCK_POOL,
+			CurrencyId::FEUR,
+			300_000,
+		));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		assert_ok!(withdraw_inventory(ALICE, 100_000));
+		assert_eq!(inventory(), 200_000);
+		assert_eq!(synthetic_balance(ALICE), ONE_MILL - 200_000);
+		assert_eq!(synthetic_balance(SpotExchange::account_id()), 200_000);
+
+		let event = TestEvent::spot_exchange(RawEvent::InventoryWithdrew(ALICE, MOCK_POOL, CurrencyId::FEUR, 100_000));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
+
+#[test]
+fn only_owner_could_deposit_and_withdraw_inventory() {
+	ExtBuilder::default().one_million_for_all().build().execute_with(|| {
+		assert_noop!(deposit_inventory(BOB, 100), Error::<Runtime>::NoPermission);
+		assert_ok!(deposit_inventory(ALICE, 100));
+		assert_noop!(withdraw_inventory(BOB, 100), Error::<Runtime>::NoPermission);
+	});
+}
+
+#[test]
+fn deposit_inventory_fails_if_currency_is_not_supported() {
+	ExtBuilder::default().one_million_for_all().build().execute_with(|| {
+		assert_noop!(
+			SpotExchange::deposit_inventory(origin_of(ALICE), MOCK_POOL, CurrencyId::AUSD, 100),
+			Error::<Runtime>::NotValidSyntheticCurrencyId
+		);
+	});
+}
+
+#[test]
+fn withdraw_inventory_fails_if_not_enough_inventory() {
+	ExtBuilder::default().one_million_for_all().build().execute_with(|| {
+		assert_ok!(deposit_inventory(ALICE, 100));
+		assert_noop!(withdraw_inventory(ALICE, 101), Error::<Runtime>::InsufficientInventory);
+	});
+}
+
+#[test]
+fn buy_does_correct_math() {
+	ExtBuilder::default()
+		.one_million_for_all()
+		.synthetic_price_three()
+		.one_percent_spread()
+		.build()
+		.execute_with(|| {
+			assert_ok!(deposit_inventory(ALICE, 300_000));
+			assert_ok!(buy_feur(BOB, 303_000));
+
+			// bought synthetic
+			// = collateral / ask_price
+			// = 303_000 / (3 * (1 + 0.01))
+			// = 100_000
+			let synthetic = 100_000;
+
+			assert_eq!(collateral_balance(BOB), ONE_MILL - 303_000);
+			assert_eq!(synthetic_balance(BOB), synthetic);
+			assert_eq!(mock_pool_liquidity(), ONE_MILL + 303_000);
+			assert_eq!(inventory(), 300_000 - synthetic);
+			assert_eq!(synthetic_balance(SpotExchange::account_id()), 300_000 - synthetic);
+
+			let event =
+				TestEvent::spot_exchange(RawEvent::Bought(BOB, CurrencyId::FEUR, MOCK_POOL, 303_000, synthetic));
+			assert!(System::events().iter().any(|record| record.event == event));
+		});
+}
+
+#[test]
+fn sell_does_correct_math() {
+	ExtBuilder::default()
+		.one_million_for_all()
+		.synthetic_price_three()
+		.one_percent_spread()
+		.build()
+		.execute_with(|| {
+			assert_ok!(deposit_inventory(ALICE, 300_000));
+			assert_ok!(buy_feur(BOB, 303_000));
+			assert_ok!(sell_feur(BOB, 50_000));
+
+			// sold collateral
+			// = synthetic * bid_price
+			// = 50_000 * (3 * (1 - 0.01))
+			// = 148_500
+			let collateral = 148_500;
+
+			assert_eq!(collateral_balance(BOB), ONE_MILL - 303_000 + collateral);
+			assert_eq!(synthetic_balance(BOB), 50_000);
+			assert_eq!(mock_pool_liquidity(), ONE_MILL + 303_000 - collateral);
+			assert_eq!(inventory(), 250_000);
+			assert_eq!(synthetic_balance(SpotExchange::account_id()), 250_000);
+
+			let event = TestEvent::spot_exchange(RawEvent::Sold(BOB, CurrencyId::FEUR, MOCK_POOL, collateral, 50_000));
+			assert!(System::events().iter().any(|record| record.event == event));
+		});
+}
+
+#[test]
+fn buy_and_sell_with_fee_discount_works() {
+	ExtBuilder::default()
+		.one_million_for_all()
+		.synthetic_price_three()
+		.one_percent_spread()
+		.build()
+		.execute_with(|| {
+			MockFeeDiscount::set_mock_fee_discount(Permill::from_percent(50));
+			assert_ok!(deposit_inventory(ALICE, 300_000));
+			assert_ok!(buy_feur(BOB, 301_500));
+
+			// bought synthetic
+			// = 301_500 / (3 * (1 + 0.01 * (1 - 0.5)))
+			// = 100_000
+			assert_eq!(synthetic_balance(BOB), 100_000);
+
+			assert_ok!(sell_feur(BOB, 100_000));
+
+			// sold collateral
+			// = 100_000 * 3 * (1 - 0.01 * (1 - 0.5))
+			// = 298_500
+			assert_eq!(collateral_balance(BOB), ONE_MILL - 301_500 + 298_500);
+		});
+}
+
+#[test]
+fn buy_and_sell_never_rounds_in_trader_favor() {
+	// prices with recurring decimals
+	let prices = [(10, 3), (22, 7), (29, 9), (31, 11)];
+	let amounts = [7, 1_001, 333_333];
+	for &(x, y) in prices.iter() {
+		ExtBuilder::default()
+			.one_million_for_all()
+			.synthetic_price(Price::saturating_from_rational(x, y))
+			.build()
+			.execute_with(|| {
+				assert_ok!(deposit_inventory(ALICE, ONE_MILL));
+				for &amount in amounts.iter() {
+					let collateral = collateral_balance(BOB);
+					assert_ok!(buy_feur(BOB, amount));
+					let synthetic = synthetic_balance(BOB);
+					if synthetic > 0 {
+						assert_ok!(sell_feur(BOB, synthetic));
+					}
+
+					// without spread or price change, selling right after buying never makes profit
+					assert!(collateral_balance(BOB) <= collateral);
+				}
+			});
+	}
+}
+
+#[test]
+fn buy_fails_if_slippage_too_greedy() {
+	ExtBuilder::default()
+		.one_million_for_all()
+		.synthetic_price_three()
+		.one_percent_spread()
+		.build()
+		.execute_with(|| {
+			assert_ok!(deposit_inventory(ALICE, 300_000));
+			assert_noop!(
+				SpotExchange::buy(
+					origin_of(BOB),
+					MOCK_POOL,
+					CurrencyId::FEUR,
+					100,
+					Price::saturating_from_rational(3, 1)
+				),
+				Error::<Runtime>::AskPriceTooHigh
+			);
+		});
+}
+
+#[test]
+fn sell_fails_if_slippage_too_greedy() {
+	ExtBuilder::default()
+		.one_million_for_all()
+		.synthetic_price_three()
+		.one_percent_spread()
+		.build()
+		.execute_with(|| {
+			assert_ok!(deposit_inventory(ALICE, 300_000));
+			assert_ok!(buy_feur(BOB, 303_000));
+			assert_noop!(
+				SpotExchange::sell(
+					origin_of(BOB),
+					MOCK_POOL,
+					CurrencyId::FEUR,
+					100,
+					Price::saturating_from_rational(3, 1)
+				),
+				Error::<Runtime>::BidPriceTooLow
+			);
+		});
+}
+
+#[test]
+fn buy_fails_if_currency_is_not_supported() {
+	ExtBuilder::default()
+		.one_million_for_all()
+		.synthetic_price_three()
+		.build()
+		.execute_with(|| {
+			assert_noop!(
+				SpotExchange::buy(
+					origin_of(BOB),
+					MOCK_POOL,
+					CurrencyId::AUSD,
+					100,
+					Price::saturating_from_rational(4, 1)
+				),
+				Error::<Runtime>::NotValidSyntheticCurrencyId
+			);
+		});
+}
+
+#[test]
+fn buy_and_sell_fail_if_not_allowed() {
+	ExtBuilder::default()
+		.one_million_for_all()
+		.synthetic_price_three()
+		.set_is_allowed(false)
+		.build()
+		.execute_with(|| {
+			assert_ok!(deposit_inventory(ALICE, 300_000));
+			assert_noop!(buy_feur(BOB, 100), Error::<Runtime>::CannotTradeInPool);
+			assert_noop!(sell_feur(ALICE, 100), Error::<Runtime>::CannotTradeInPool);
+		});
+}
+
+#[test]
+fn buy_and_sell_fail_if_exceeds_max_trade_amount() {
+	ExtBuilder::default()
+		.one_million_for_all()
+		.synthetic_price_three()
+		.build()
+		.execute_with(|| {
+			assert_ok!(deposit_inventory(ALICE, ONE_MILL));
+			assert_noop!(
+				buy_feur(BOB, GetMaxTradeAmount::get() + 1),
+				Error::<Runtime>::ExceedsMaxTradeAmount
+			);
+
+			assert_ok!(buy_feur(BOB, GetMaxTradeAmount::get()));
+			// 166_667 * 3 > max trade amount
+			assert_noop!(sell_feur(BOB, 166_667), Error::<Runtime>::ExceedsMaxTradeAmount);
+		});
+}
+
+#[test]
+fn buy_fails_if_not_enough_inventory() {
+	ExtBuilder::default()
+		.one_million_for_all()
+		.synthetic_price_three()
+		.build()
+		.execute_with(|| {
+			assert_ok!(deposit_inventory(ALICE, 99));
+			assert_noop!(buy_feur(BOB, 300), Error::<Runtime>::InsufficientInventory);
+			assert_ok!(buy_feur(BOB, 297));
+		});
+}
+
+#[test]
+fn sell_fails_if_pool_has_no_liquidity() {
+	ExtBuilder::default()
+		.balances(vec![(ALICE, CurrencyId::FEUR, ONE_MILL)])
+		.synthetic_price_three()
+		.build()
+		.execute_with(|| {
+			assert_noop!(sell_feur(ALICE, 100), Error::<Runtime>::InsufficientLiquidityInPool);
+		});
+}
+
+#[test]
+fn buy_fails_if_no_price() {
+	ExtBuilder::default().one_million_for_all().build().execute_with(|| {
+		assert_ok!(deposit_inventory(ALICE, 100));
+		assert_noop!(buy_feur(BOB, 100), Error::<Runtime>::NoPrice);
+	});
+}
+
+#[test]
+fn buy_fails_with_price_unavailable_reason() {
+	ExtBuilder::default().one_million_for_all().build().execute_with(|| {
+		MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, Some(PriceUnavailableReason::StalePrice));
+		assert_noop!(buy_feur(BOB, 100), Error::<Runtime>::StalePrice);
+		MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, Some(PriceUnavailableReason::MarketHalted));
+		assert_noop!(sell_feur(ALICE, 100), Error::<Runtime>::MarketHalted);
+		MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, None);
+	});
+}
+
+#[test]
+fn pool_with_inventory_could_not_be_removed() {
+	ExtBuilder::default().one_million_for_all().build().execute_with(|| {
+		assert!(SpotExchange::can_remove(MOCK_POOL));
+		assert_ok!(deposit_inventory(ALICE, 100));
+		assert!(!SpotExchange::can_remove(MOCK_POOL));
+		assert_ok!(withdraw_inventory(ALICE, 100));
+		assert!(SpotExchange::can_remove(MOCK_POOL));
+	});
+}
@@ -3,8 +3,8 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 use codec::{Codec, Decode, Encode};
-use module_primitives::{CurrencyId, LiquidityPoolId};
-use sp_arithmetic::FixedU128;
+use module_primitives::{Balance, CurrencyId, LiquidityPoolId};
+use sp_arithmetic::{FixedU128, Permill};
 use sp_core::RuntimeDebug;
 use sp_std::prelude::*;
 
@@ -17,11 +17,99 @@ pub struct SyntheticPoolState {
 	pub collateral_ratio: FixedU128,
 	pub is_safe: bool,
 }
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(SyntheticPoolState {
+	collateral_ratio,
+	is_safe
+});
+
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
+pub struct SyntheticLiquidationCheck {
+	pub can_liquidate: bool,
+	pub incentive: Balance,
+}
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(SyntheticLiquidationCheck {
+	can_liquidate,
+	incentive
+});
+
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
+pub struct SyntheticTokenPool {
+	pub pool_id: LiquidityPoolId,
+	pub bid_spread: Option<Balance>,
+	pub ask_spread: Option<Balance>,
+	pub can_mint: bool,
+	pub synthetic_issued: Balance,
+	pub collateral_ratio: Option<FixedU128>,
+	pub additional_collateral_ratio: Permill,
+}
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(SyntheticTokenPool {
+	pool_id,
+	bid_spread,
+	ask_spread,
+	can_mint,
+	synthetic_issued,
+	collateral_ratio,
+	additional_collateral_ratio
+});
+
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
+pub struct SyntheticToken {
+	pub currency_id: CurrencyId,
+	pub price: Option<FixedU128>,
+	pub total_issuance: Balance,
+	pub extreme_ratio: Permill,
+	pub liquidation_ratio: Permill,
+	pub collateral_ratio: Permill,
+	pub pools: Vec<SyntheticTokenPool>,
+}
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(SyntheticToken {
+	currency_id,
+	price,
+	total_issuance,
+	extreme_ratio,
+	liquidation_ratio,
+	collateral_ratio,
+	pools
+});
+
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Eq, PartialEq, Default, RuntimeDebug)]
+pub struct SyntheticVolume {
+	/// Start block number of the current period.
+	pub period_start: u64,
+	/// Collateral volumes of the current and the previous period.
+	pub minted: Balance,
+	pub redeemed: Balance,
+	pub previous_minted: Balance,
+	pub previous_redeemed: Balance,
+}
+#[cfg(feature = "std")]
+module_primitives::impl_to_decimal!(SyntheticVolume {
+	period_start,
+	minted,
+	redeemed,
+	previous_minted,
+	previous_redeemed
+});
 
 sp_api::decl_runtime_apis! {
 	pub trait SyntheticProtocolApi<AccountId> where
 		AccountId: Codec,
 	{
 		fn pool_state(pool_id: LiquidityPoolId, currency_id: CurrencyId) -> Option<SyntheticPoolState>;
+		fn check_liquidate(
+			pool_id: LiquidityPoolId,
+			currency_id: CurrencyId,
+			synthetic_amount: Balance,
+		) -> SyntheticLiquidationCheck;
+		fn synthetic_tokens() -> Vec<SyntheticToken>;
+		fn synthetic_volume(pool_id: LiquidityPoolId, currency_id: CurrencyId) -> SyntheticVolume;
 	}
 }
@@ -1,12 +1,18 @@
 use codec::Codec;
 use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
 use jsonrpc_derive::rpc;
-use module_primitives::{CurrencyId, LiquidityPoolId};
+use module_primitives::{
+	decimal::{ToDecimal, Value},
+	Balance, CurrencyId, LiquidityPoolId,
+};
 use sp_api::ProvideRuntimeApi;
 use sp_blockchain::HeaderBackend;
 use sp_runtime::{generic::BlockId, traits::Block as BlockT};
 use std::sync::Arc;
-pub use synthetic_protocol_rpc_runtime_api::{SyntheticPoolState, SyntheticProtocolApi as SyntheticProtocolRuntimeApi};
+pub use synthetic_protocol_rpc_runtime_api::{
+	SyntheticLiquidationCheck, SyntheticPoolState, SyntheticProtocolApi as SyntheticProtocolRuntimeApi, SyntheticToken,
+	SyntheticTokenPool, SyntheticVolume,
+};
 
 #[rpc]
 pub trait SyntheticProtocolApi<BlockHash, AccountId> {
@@ -17,6 +23,56 @@ pub trait SyntheticProtocolApi<BlockHash, AccountId> {
 		currency_id: CurrencyId,
 		at: Option<BlockHash>,
 	) -> Result<Option<SyntheticPoolState>>;
+
+	#[rpc(name = "synthetic_checkLiquidate")]
+	fn check_liquidate(
+		&self,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		synthetic_amount: Balance,
+		at: Option<BlockHash>,
+	) -> Result<SyntheticLiquidationCheck>;
+
+	#[rpc(name = "synthetic_tokens")]
+	fn synthetic_tokens(&self, at: Option<BlockHash>) -> Result<Vec<SyntheticToken>>;
+
+	#[rpc(name = "synthetic_volume")]
+	fn synthetic_volume(
+		&self,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		at: Option<BlockHash>,
+	) -> Result<SyntheticVolume>;
+
+	// Variants of the methods above rendering fixed point values as `Decimal`s of inner value, decimal
+	// string and scaling factor.
+	#[rpc(name = "synthetic_poolStateDecimal")]
+	fn pool_state_decimal(
+		&self,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		at: Option<BlockHash>,
+	) -> Result<Value>;
+
+	#[rpc(name = "synthetic_checkLiquidateDecimal")]
+	fn check_liquidate_decimal(
+		&self,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		synthetic_amount: Balance,
+		at: Option<BlockHash>,
+	) -> Result<Value>;
+
+	#[rpc(name = "synthetic_tokensDecimal")]
+	fn synthetic_tokens_decimal(&self, at: Option<BlockHash>) -> Result<Value>;
+
+	#[rpc(name = "synthetic_volumeDecimal")]
+	fn synthetic_volume_decimal(
+		&self,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		at: Option<BlockHash>,
+	) -> Result<Value>;
 }
 
 /// A struct that implements the [`SyntheticProtocolApi`].
@@ -37,16 +93,27 @@ impl<C, B> SyntheticProtocol<C, B> {
 
 pub enum Error {
 	RuntimeError,
+	DecimalError,
 }
 
 impl From<Error> for i64 {
 	fn from(e: Error) -> i64 {
 		match e {
 			Error::RuntimeError => 1,
+			Error::DecimalError => 2,
 		}
 	}
 }
 
+/// Render `value` with fixed point values as `Decimal`s.
+fn to_decimal<T: ToDecimal>(value: Result<T>) -> Result<Value> {
+	value?.to_decimal().map_err(|e| RpcError {
+		code: ErrorCode::ServerError(Error::DecimalError.into()),
+		message: "Unable to render decimals.".into(),
+		data: Some(format!("{:?}", e).into()),
+	})
+}
+
 impl<C, Block, AccountId> SyntheticProtocolApi<<Block as BlockT>::Hash, AccountId> for SyntheticProtocol<C, Block>
 where
 	Block: BlockT,
@@ -72,4 +139,89 @@ where
 			})
 			.into()
 	}
+
+	fn check_liquidate(
+		&self,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		synthetic_amount: Balance,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<SyntheticLiquidationCheck> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+		api.check_liquidate(&at, pool_id, currency_id, synthetic_amount)
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(Error::RuntimeError.into()),
+				message: "Unable to check liquidate.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})
+			.into()
+	}
+
+	fn synthetic_tokens(&self, at: Option<<Block as BlockT>::Hash>) -> Result<Vec<SyntheticToken>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+		api.synthetic_tokens(&at)
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(Error::RuntimeError.into()),
+				message: "Unable to get synthetic tokens.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})
+			.into()
+	}
+
+	fn synthetic_volume(
+		&self,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<SyntheticVolume> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+		api.synthetic_volume(&at, pool_id, currency_id)
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(Error::RuntimeError.into()),
+				message: "Unable to get synthetic volume.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})
+			.into()
+	}
+
+	fn pool_state_decimal(
+		&self,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Value> {
+		to_decimal(self.pool_state(pool_id, currency_id, at))
+	}
+
+	fn check_liquidate_decimal(
+		&self,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		synthetic_amount: Balance,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Value> {
+		to_decimal(self.check_liquidate(pool_id, currency_id, synthetic_amount, at))
+	}
+
+	fn synthetic_tokens_decimal(&self, at: Option<<Block as BlockT>::Hash>) -> Result<Value> {
+		to_decimal(self.synthetic_tokens(at))
+	}
+
+	fn synthetic_volume_decimal(
+		&self,
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Value> {
+		to_decimal(self.synthetic_volume(pool_id, currency_id, at))
+	}
 }
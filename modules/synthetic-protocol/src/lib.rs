@@ -1,11 +1,16 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-use frame_support::{decl_error, decl_event, decl_module, decl_storage, ensure, traits::Get, weights::DispatchClass};
+use codec::{Decode, Encode};
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage, ensure,
+	traits::{EnsureOrigin, Get},
+	weights::DispatchClass,
+};
 use sp_runtime::{
 	traits::{CheckedAdd, CheckedDiv, CheckedSub, Saturating, Zero},
-	DispatchError, DispatchResult, FixedPointNumber, FixedU128,
+	DispatchError, DispatchResult, FixedPointNumber, FixedU128, Permill, RuntimeDebug,
 };
-use sp_std::result;
+use sp_std::{prelude::*, result};
 // FIXME: `pallet/frame-` prefix should be used for all pallet modules, but currently `frame_system`
 // would cause compiling error in `decl_module!` and `construct_runtime!`
 // #3295 https://github.com/paritytech/substrate/issues/3295
@@ -14,8 +19,13 @@ use frame_system::{self as system, ensure_signed};
 use orml_traits::{BasicCurrency, MultiCurrency, PriceProvider};
 use orml_utilities::with_transaction_result;
 
-use module_primitives::{Balance, CurrencyId, LiquidityPoolId, Price};
-use module_traits::{LiquidityPools, SyntheticProtocolLiquidityPools};
+use module_primitives::{
+	arithmetic::{fixed_u128_div_with_rounding, fixed_u128_mul_int_with_rounding, Rounding},
+	Balance, CurrencyId, FeeSplit, LiquidityPoolId, Price, RollingVolume,
+};
+use module_traits::{
+	AuditLog, FeeDiscount, LiquidityPools, PriceStatus, PriceUnavailableReason, SyntheticProtocolLiquidityPools,
+};
 
 mod mock;
 mod tests;
@@ -36,15 +46,98 @@ pub trait Trait: module_synthetic_tokens::Trait {
 	/// Provides market prices.
 	type PriceProvider: PriceProvider<CurrencyId, Price>;
 
+	/// Reasons of unavailable prices, to return granular price errors.
+	type PriceStatus: PriceStatus;
+
 	/// The basic liquidity pools.
 	type LiquidityPools: LiquidityPools<Self::AccountId>;
 
 	/// The synthetic protocol liquidity pools.
 	type SyntheticProtocolLiquidityPools: SyntheticProtocolLiquidityPools<Self::AccountId>;
+
+	/// Trading fee discounts of traders, applied to spreads on minting and redeeming.
+	type FeeDiscount: FeeDiscount<Self::AccountId>;
+
+	/// Period of blocks of rolling minting and redeeming volumes. `0` means volumes are never rolled over.
+	type GetVolumePeriod: Get<Self::BlockNumber>;
+
+	/// The treasury account ID.
+	type GetTreasuryAccountId: Get<Self::AccountId>;
+
+	/// The insurance fund account ID.
+	type GetInsuranceFundAccountId: Get<Self::AccountId>;
+}
+
+/// Minting and redeeming volume of a synthetic currency in a pool, in collateral currency.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct SyntheticVolume {
+	/// Collateral paid on minting.
+	pub minted: Balance,
+
+	/// Collateral received on redeeming.
+	pub redeemed: Balance,
+}
+
+/// A liquidity pool offering a synthetic currency.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct SyntheticTokenPool {
+	/// Liquidity pool ID.
+	pub pool_id: LiquidityPoolId,
+
+	/// Bid spread, or `None` if not set by pool owner.
+	pub bid_spread: Option<Balance>,
+
+	/// Ask spread, or `None` if not set by pool owner.
+	pub ask_spread: Option<Balance>,
+
+	/// If the synthetic currency can be minted in the pool.
+	pub can_mint: bool,
+
+	/// Synthetic amount issued by the pool.
+	pub synthetic_issued: Balance,
+
+	/// Current collateral ratio, or `None` if no price.
+	pub collateral_ratio: Option<FixedU128>,
+
+	/// Additional collateral ratio required on minting.
+	pub additional_collateral_ratio: Permill,
+}
+
+/// Directory entry of a synthetic currency.
+#[derive(Encode, Decode, Clone, RuntimeDebug, Eq, PartialEq, Default)]
+pub struct SyntheticTokenInfo {
+	/// Synthetic currency ID.
+	pub currency_id: CurrencyId,
+
+	/// Oracle price in collateral currency, or `None` if not available.
+	pub price: Option<Price>,
+
+	/// Total issuance.
+	pub total_issuance: Balance,
+
+	/// Extreme liquidation ratio.
+	pub extreme_ratio: Permill,
+
+	/// Liquidation ratio.
+	pub liquidation_ratio: Permill,
+
+	/// Collateral ratio.
+	pub collateral_ratio: Permill,
+
+	/// Pools offering the synthetic currency, either mintable or with issued synthetic.
+	pub pools: Vec<SyntheticTokenPool>,
 }
 
 decl_storage! {
-	trait Store for Module<T: Trait> as SyntheticProtocol {}
+	trait Store for Module<T: Trait> as SyntheticProtocol {
+		/// Rolling minting and redeeming volumes of synthetic currencies in liquidity pools.
+		///
+		/// Not rolled over until the next mint or redeem, see `synthetic_volume` for up to date volumes.
+		SyntheticVolumes get(fn synthetic_volumes): double_map hasher(twox_64_concat) LiquidityPoolId, hasher(twox_64_concat) CurrencyId => RollingVolume<T::BlockNumber, SyntheticVolume>;
+
+		/// Split of liquidation incentives of a liquidity pool. All to the liquidator if not set.
+		FeeSplits get(fn fee_splits): map hasher(twox_64_concat) LiquidityPoolId => Option<FeeSplit>;
+	}
 }
 
 decl_event! {
@@ -65,6 +158,9 @@ decl_event! {
 
 		/// Collateral withdrew: [who, synthetic_currency_id, pool_id, collateral_amount]
 		CollateralWithdrew(AccountId, CurrencyId, LiquidityPoolId, Balance),
+
+		/// Fee split of a liquidity pool set: [pool_id, split]
+		FeeSplitSet(LiquidityPoolId, Option<FeeSplit>),
 	}
 }
 
@@ -158,6 +254,33 @@ decl_module! {
 				Ok(())
 			})?;
 		}
+
+		/// Set the split of liquidation incentives of a liquidity pool. `None` to give all to the liquidator.
+		///
+		/// The liquidator is the keeper, and the pool owner share is refunded to the pool.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_fee_split(origin, #[compact] pool_id: LiquidityPoolId, split: Option<FeeSplit>) {
+			with_transaction_result(|| {
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Self::fee_splits(pool_id);
+				match split {
+					Some(s) => FeeSplits::insert(pool_id, s),
+					None => FeeSplits::remove(pool_id),
+				}
+				T::AuditLog::record(
+					origin,
+					b"SyntheticProtocol",
+					b"FeeSplits",
+					pool_id.encode(),
+					old.encode(),
+					split.encode(),
+				);
+				Self::deposit_event(RawEvent::FeeSplitSet(pool_id, split));
+				Ok(())
+			})?;
+		}
 	}
 }
 
@@ -181,6 +304,15 @@ decl_error! {
 		/// No price from provider.
 		NoPrice,
 
+		/// Price expired.
+		StalePrice,
+
+		/// Not enough oracle operators fed the price.
+		BelowOracleQuorum,
+
+		/// Market of the price is halted.
+		MarketHalted,
+
 		/// Negative required additional amount from pool.
 		///
 		/// May caused by wrong spread and ratio config of pool
@@ -212,6 +344,17 @@ decl_error! {
 	}
 }
 
+impl<T: Trait> From<PriceUnavailableReason> for Error<T> {
+	fn from(reason: PriceUnavailableReason) -> Self {
+		match reason {
+			PriceUnavailableReason::NoPrice => Error::<T>::NoPrice,
+			PriceUnavailableReason::StalePrice => Error::<T>::StalePrice,
+			PriceUnavailableReason::BelowOracleQuorum => Error::<T>::BelowOracleQuorum,
+			PriceUnavailableReason::MarketHalted => Error::<T>::MarketHalted,
+		}
+	}
+}
+
 type SyntheticTokens<T> = module_synthetic_tokens::Module<T>;
 type BalanceResult = result::Result<Balance, DispatchError>;
 
@@ -234,13 +377,12 @@ impl<T: Trait> Module<T> {
 			Error::<T>::CannotMintInPool
 		);
 
-		let price =
-			T::PriceProvider::get_price(currency_id, T::GetCollateralCurrencyId::get()).ok_or(Error::<T>::NoPrice)?;
-		let ask_price = Self::ask_price(pool_id, currency_id, price, max_price)?;
+		let price = Self::price(currency_id)?;
+		let discount = T::FeeDiscount::fee_discount(who);
+		let ask_price = Self::ask_price(pool_id, currency_id, price, max_price, discount)?;
 
-		// synthetic = collateral / ask_price
-		let synthetic = Price::from_inner(collateral)
-			.checked_div(&ask_price)
+		// synthetic = collateral / ask_price, rounded down in favor of the pool
+		let synthetic = fixed_u128_div_with_rounding(Price::from_inner(collateral), ask_price, Rounding::Down)
 			.map(|x| x.into_inner())
 			.ok_or(Error::<T>::NumOverflow)?;
 
@@ -261,6 +403,7 @@ impl<T: Trait> Module<T> {
 
 		let total_collateral = collateral + additional_collateral;
 		<SyntheticTokens<T>>::add_position(pool_id, currency_id, total_collateral, synthetic);
+		Self::accumulate_volume(pool_id, currency_id, collateral, true);
 
 		Ok(synthetic)
 	}
@@ -280,13 +423,14 @@ impl<T: Trait> Module<T> {
 		// burn synthetic
 		T::MultiCurrency::withdraw(currency_id, who, synthetic)?;
 
-		let price =
-			T::PriceProvider::get_price(currency_id, T::GetCollateralCurrencyId::get()).ok_or(Error::<T>::NoPrice)?;
+		let price = Self::price(currency_id)?;
 		// bid_price = price - bid_spread
-		let bid_price = Self::bid_price(pool_id, currency_id, price, Some(min_price))?;
+		let discount = T::FeeDiscount::fee_discount(who);
+		let bid_price = Self::bid_price(pool_id, currency_id, price, Some(min_price), discount)?;
 
-		// collateral = synthetic * bid_price
-		let redeemed_collateral = bid_price.checked_mul_int(synthetic).ok_or(Error::<T>::NumOverflow)?;
+		// collateral = synthetic * bid_price, rounded down in favor of the pool
+		let redeemed_collateral =
+			fixed_u128_mul_int_with_rounding(bid_price, synthetic, Rounding::Down).ok_or(Error::<T>::NumOverflow)?;
 		let (collateral_position_delta, pool_refund_collateral) =
 			Self::collateral_change_on_remove_position(pool_id, currency_id, price, synthetic, redeemed_collateral)?;
 
@@ -297,6 +441,7 @@ impl<T: Trait> Module<T> {
 			.map_err(|_| Error::<T>::InsufficientLockedCollateral)?;
 
 		<SyntheticTokens<T>>::remove_position(pool_id, currency_id, collateral_position_delta, synthetic);
+		Self::accumulate_volume(pool_id, currency_id, redeemed_collateral, false);
 
 		Ok(redeemed_collateral)
 	}
@@ -307,29 +452,37 @@ impl<T: Trait> Module<T> {
 		currency_id: CurrencyId,
 		synthetic: Balance,
 	) -> BalanceResult {
-		ensure!(
-			T::SyntheticCurrencyIds::get().contains(&currency_id),
-			Error::<T>::NotValidSyntheticCurrencyId
-		);
-
-		let price =
-			T::PriceProvider::get_price(currency_id, T::GetCollateralCurrencyId::get()).ok_or(Error::<T>::NoPrice)?;
-		let bid_price = Self::bid_price(pool_id, currency_id, price, None)?;
-		// collateral = synthetic * bid_price
-		let collateral = bid_price.checked_mul_int(synthetic).ok_or(Error::<T>::NumOverflow)?;
-
-		let (collateral_position_delta, pool_refund_collateral, incentive) =
-			Self::collateral_change_on_liquidation(pool_id, currency_id, price, synthetic, collateral)?;
+		let (collateral, collateral_position_delta, pool_refund_collateral, incentive) =
+			Self::liquidation_changes(pool_id, currency_id, synthetic)?;
 
 		// burn synthetic
 		T::MultiCurrency::withdraw(currency_id, who, synthetic)?;
 
+		let split = Self::fee_splits(pool_id)
+			.unwrap_or_else(FeeSplit::all_to_keeper)
+			.split(incentive);
+
 		// Give liquidator collateral and incentive.
-		let collateral_with_incentive = collateral.checked_add(incentive).ok_or(Error::<T>::NumOverflow)?;
+		let collateral_with_incentive = collateral.checked_add(split.keeper).ok_or(Error::<T>::NumOverflow)?;
 		T::CollateralCurrency::transfer(&<SyntheticTokens<T>>::account_id(), who, collateral_with_incentive)
 			.map_err(|_| Error::<T>::InsufficientLockedCollateral)?;
-
-		// refund to pool
+		T::CollateralCurrency::transfer(
+			&<SyntheticTokens<T>>::account_id(),
+			&T::GetInsuranceFundAccountId::get(),
+			split.insurance_fund,
+		)
+		.map_err(|_| Error::<T>::InsufficientLockedCollateral)?;
+		T::CollateralCurrency::transfer(
+			&<SyntheticTokens<T>>::account_id(),
+			&T::GetTreasuryAccountId::get(),
+			split.treasury,
+		)
+		.map_err(|_| Error::<T>::InsufficientLockedCollateral)?;
+
+		// refund to pool, with the pool owner share of incentive
+		let pool_refund_collateral = pool_refund_collateral
+			.checked_add(split.pool_owner)
+			.ok_or(Error::<T>::NumOverflow)?;
 		T::LiquidityPools::deposit_liquidity(&<SyntheticTokens<T>>::account_id(), pool_id, pool_refund_collateral)
 			.map_err(|_| Error::<T>::InsufficientLockedCollateral)?;
 
@@ -365,8 +518,7 @@ impl<T: Trait> Module<T> {
 
 		ensure!(T::LiquidityPools::is_owner(pool_id, who), Error::<T>::NoPermission);
 
-		let price =
-			T::PriceProvider::get_price(currency_id, T::GetCollateralCurrencyId::get()).ok_or(Error::<T>::NoPrice)?;
+		let price = Self::price(currency_id)?;
 		let (collateral_position_delta, pool_refund_collateral) =
 			Self::collateral_change_on_remove_position(pool_id, currency_id, price, Zero::zero(), Zero::zero())?;
 
@@ -382,18 +534,30 @@ impl<T: Trait> Module<T> {
 
 // Private methods
 impl<T: Trait> Module<T> {
+	/// Price of `currency_id` in collateral currency.
+	fn price(currency_id: CurrencyId) -> result::Result<Price, Error<T>> {
+		let collateral_currency_id = T::GetCollateralCurrencyId::get();
+		T::PriceProvider::get_price(currency_id, collateral_currency_id).ok_or_else(|| {
+			T::PriceStatus::unavailable_reason(currency_id)
+				.or_else(|| T::PriceStatus::unavailable_reason(collateral_currency_id))
+				.map_or(Error::<T>::NoPrice, Into::into)
+		})
+	}
+
 	/// Get ask price from liquidity pool for a given currency. Would fail if price could not meet
 	/// max slippage.
 	///
-	/// ask_price = price + ask_spread
+	/// ask_price = price + ask_spread * (1 - discount)
 	fn ask_price(
 		pool_id: LiquidityPoolId,
 		currency_id: CurrencyId,
 		price: Price,
 		max_price: Price,
+		discount: Permill,
 	) -> result::Result<Price, DispatchError> {
 		let ask_spread =
 			T::SyntheticProtocolLiquidityPools::ask_spread(pool_id, currency_id).ok_or(Error::<T>::NoAskSpread)?;
+		let ask_spread = ask_spread.saturating_sub(discount * ask_spread);
 		let ask_price = price
 			.checked_add(&Price::from_inner(ask_spread))
 			.ok_or(Error::<T>::NumOverflow)?;
@@ -405,15 +569,17 @@ impl<T: Trait> Module<T> {
 	/// Get bid price from liquidity pool for a given currency. Would fail if price could not meet
 	/// max slippage.
 	///
-	/// bid_price = price - bid_spread
+	/// bid_price = price - bid_spread * (1 - discount)
 	fn bid_price(
 		pool_id: LiquidityPoolId,
 		currency_id: CurrencyId,
 		price: Price,
 		min_price: Option<Price>,
+		discount: Permill,
 	) -> result::Result<Price, DispatchError> {
 		let bid_spread =
 			T::SyntheticProtocolLiquidityPools::bid_spread(pool_id, currency_id).ok_or(Error::<T>::NoBidSpread)?;
+		let bid_spread = bid_spread.saturating_sub(discount * bid_spread);
 		let bid_price = price
 			.checked_sub(&Price::from_inner(bid_spread))
 			.expect("price > spread_amount; qed");
@@ -492,6 +658,31 @@ impl<T: Trait> Module<T> {
 		Ok((collateral_position_delta, pool_refund_collateral))
 	}
 
+	/// Calculate liquidized collateral, position change and incentive for liquidating `synthetic`.
+	///
+	/// If `Ok`, return with `(collateral, collateral_position_delta, pool_refund_collateral, incentive)`
+	fn liquidation_changes(
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		synthetic: Balance,
+	) -> result::Result<(Balance, Balance, Balance, Balance), DispatchError> {
+		ensure!(
+			T::SyntheticCurrencyIds::get().contains(&currency_id),
+			Error::<T>::NotValidSyntheticCurrencyId
+		);
+
+		let price = Self::price(currency_id)?;
+		let bid_price = Self::bid_price(pool_id, currency_id, price, None, Permill::zero())?;
+		// collateral = synthetic * bid_price, rounded down in favor of the pool
+		let collateral =
+			fixed_u128_mul_int_with_rounding(bid_price, synthetic, Rounding::Down).ok_or(Error::<T>::NumOverflow)?;
+
+		let (collateral_position_delta, pool_refund_collateral, incentive) =
+			Self::collateral_change_on_liquidation(pool_id, currency_id, price, synthetic, collateral)?;
+
+		Ok((collateral, collateral_position_delta, pool_refund_collateral, incentive))
+	}
+
 	/// Calculate position change and incentive for a remove.
 	///
 	/// If `Ok`, return with `(collateral_position_delta, pool_refund_collateral, incentive)`
@@ -563,6 +754,19 @@ impl<T: Trait> Module<T> {
 			Ok((liquidized_collateral, Zero::zero(), Zero::zero()))
 		}
 	}
+
+	/// Add `collateral` of a mint or redeem to the volume of `currency_id` in `pool_id`, rolling over first if
+	/// needed.
+	fn accumulate_volume(pool_id: LiquidityPoolId, currency_id: CurrencyId, collateral: Balance, minted: bool) {
+		<SyntheticVolumes<T>>::mutate(pool_id, currency_id, |volumes| {
+			volumes.roll_over(<frame_system::Module<T>>::block_number(), T::GetVolumePeriod::get());
+			if minted {
+				volumes.current.minted = volumes.current.minted.saturating_add(collateral);
+			} else {
+				volumes.current.redeemed = volumes.current.redeemed.saturating_add(collateral);
+			}
+		});
+	}
 }
 
 // RPC methods.
@@ -584,4 +788,66 @@ impl<T: Trait> Module<T> {
 		let safe_ratio_threshold = Into::<FixedU128>::into(liquidation_ratio).saturating_add(FixedU128::one());
 		ratio > safe_ratio_threshold
 	}
+
+	/// All synthetic currencies with their prices, issuance, ratios and the pools offering them.
+	pub fn synthetic_tokens() -> Vec<SyntheticTokenInfo> {
+		let pool_ids = T::LiquidityPools::all();
+		T::SyntheticCurrencyIds::get()
+			.into_iter()
+			.map(|currency_id| {
+				let pools = pool_ids
+					.iter()
+					.filter_map(|&pool_id| {
+						let can_mint = T::SyntheticProtocolLiquidityPools::can_mint(pool_id, currency_id);
+						let (_, synthetic_issued) = <SyntheticTokens<T>>::get_position(pool_id, currency_id);
+						if !can_mint && synthetic_issued.is_zero() {
+							return None;
+						}
+						Some(SyntheticTokenPool {
+							pool_id,
+							bid_spread: T::SyntheticProtocolLiquidityPools::bid_spread(pool_id, currency_id),
+							ask_spread: T::SyntheticProtocolLiquidityPools::ask_spread(pool_id, currency_id),
+							can_mint,
+							synthetic_issued,
+							collateral_ratio: Self::collateral_ratio(pool_id, currency_id),
+							additional_collateral_ratio:
+								T::SyntheticProtocolLiquidityPools::additional_collateral_ratio(pool_id, currency_id),
+						})
+					})
+					.collect();
+
+				SyntheticTokenInfo {
+					currency_id,
+					price: T::PriceProvider::get_price(currency_id, T::GetCollateralCurrencyId::get()),
+					total_issuance: T::MultiCurrency::total_issuance(currency_id),
+					extreme_ratio: <SyntheticTokens<T>>::extreme_ratio_or_default(currency_id),
+					liquidation_ratio: <SyntheticTokens<T>>::liquidation_ratio_or_default(currency_id),
+					collateral_ratio: <SyntheticTokens<T>>::collateral_ratio_or_default(currency_id),
+					pools,
+				}
+			})
+			.collect()
+	}
+
+	/// Minting and redeeming volumes of `currency_id` in `pool_id`, rolled over to the current period.
+	pub fn synthetic_volume(
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+	) -> RollingVolume<T::BlockNumber, SyntheticVolume> {
+		let mut volumes = Self::synthetic_volumes(pool_id, currency_id);
+		volumes.roll_over(<frame_system::Module<T>>::block_number(), T::GetVolumePeriod::get());
+		volumes
+	}
+
+	/// Incentive the liquidator would get for liquidating `synthetic` of `currency_id` in `pool_id`.
+	///
+	/// Returns `Err` if the liquidation would fail. Liquidator's synthetic balance is not checked.
+	pub fn liquidation_incentive(
+		pool_id: LiquidityPoolId,
+		currency_id: CurrencyId,
+		synthetic: Balance,
+	) -> BalanceResult {
+		let (_, _, _, incentive) = Self::liquidation_changes(pool_id, currency_id, synthetic)?;
+		Ok(incentive)
+	}
 }
@@ -6,17 +6,18 @@ use frame_support::{impl_outer_event, impl_outer_origin, ord_parameter_types, pa
 use frame_system as system;
 use sp_core::H256;
 use sp_runtime::{testing::Header, traits::IdentityLookup, DispatchResult, Perbill, Permill};
-use sp_std::{cell::RefCell, collections::btree_map::BTreeMap};
+use sp_std::cell::RefCell;
 use system::EnsureSignedBy;
 
 use orml_currencies::Currency;
-use orml_traits::{DataProvider, DefaultPriceProvider};
+use orml_traits::DefaultPriceProvider;
 
 use module_primitives::LiquidityPoolId;
 use module_traits::{LiquidityPools, SyntheticProtocolLiquidityPools};
 
 use super::*;
 
+pub use laminar_test_support::{MockBuilder, MockFeeDiscount, MockPriceStatus, MockPrices};
 pub use module_primitives::{Balance, CurrencyId, Leverage};
 
 impl_outer_origin! {
@@ -108,6 +109,9 @@ parameter_types! {
 	pub const DefaultExtremeRatio: Permill = Permill::from_percent(1);
 	pub const DefaultLiquidationRatio: Permill = Permill::from_percent(5);
 	pub const DefaultCollateralRatio: Permill = Permill::from_percent(10);
+	pub const GetVolumePeriod: u64 = 10;
+	pub const GetTreasuryAccountId: AccountId = TREASURY;
+	pub const GetInsuranceFundAccountId: AccountId = INSURANCE_FUND;
 }
 
 pub type CollateralCurrency = orml_currencies::Currency<Runtime, GetCollateralCurrencyId>;
@@ -120,34 +124,10 @@ impl module_synthetic_tokens::Trait for Runtime {
 	type DefaultCollateralRatio = DefaultCollateralRatio;
 	type SyntheticCurrencyIds = SyntheticCurrencyIds;
 	type UpdateOrigin = EnsureSignedBy<One, AccountId>;
+	type AuditLog = ();
 }
 pub type TestSyntheticTokens = module_synthetic_tokens::Module<Runtime>;
 
-thread_local! {
-	static PRICES: RefCell<BTreeMap<CurrencyId, Price>> = RefCell::new(BTreeMap::new());
-}
-
-pub struct MockPrices;
-impl MockPrices {
-	pub fn set_mock_price(currency_id: CurrencyId, price: Option<Price>) {
-		if let Some(p) = price {
-			PRICES.with(|v| v.borrow_mut().insert(currency_id, p));
-		} else {
-			PRICES.with(|v| v.borrow_mut().remove(&currency_id));
-		}
-	}
-
-	fn prices(currency_id: CurrencyId) -> Option<Price> {
-		PRICES.with(|v| v.borrow_mut().get(&currency_id).map(|p| *p))
-	}
-}
-
-impl DataProvider<CurrencyId, Price> for MockPrices {
-	fn get(key: &CurrencyId) -> Option<Price> {
-		Self::prices(*key)
-	}
-}
-
 thread_local! {
 	static SPREAD: RefCell<Permill> = RefCell::new(Permill::zero());
 	static ADDITIONAL_COLLATERAL_RATIO: RefCell<Permill> = RefCell::new(Permill::zero());
@@ -183,7 +163,7 @@ impl MockLiquidityPools {
 
 impl LiquidityPools<AccountId> for MockLiquidityPools {
 	fn all() -> Vec<LiquidityPoolId> {
-		unimplemented!()
+		vec![MOCK_POOL]
 	}
 
 	/// ALICE is the mock owner
@@ -234,13 +214,20 @@ impl Trait for Runtime {
 	type CollateralCurrency = CollateralCurrency;
 	type GetCollateralCurrencyId = GetCollateralCurrencyId;
 	type PriceProvider = DefaultPriceProvider<CurrencyId, MockPrices>;
+	type PriceStatus = MockPriceStatus;
 	type LiquidityPools = MockLiquidityPools;
 	type SyntheticProtocolLiquidityPools = MockLiquidityPools;
+	type FeeDiscount = MockFeeDiscount;
+	type GetVolumePeriod = GetVolumePeriod;
+	type GetTreasuryAccountId = GetTreasuryAccountId;
+	type GetInsuranceFundAccountId = GetInsuranceFundAccountId;
 }
 pub type SyntheticProtocol = Module<Runtime>;
 
 pub const ALICE: AccountId = 0;
 pub const BOB: AccountId = 1;
+pub const TREASURY: AccountId = 2;
+pub const INSURANCE_FUND: AccountId = 3;
 pub fn origin_of(account_id: AccountId) -> Origin {
 	Origin::signed(account_id)
 }
@@ -250,7 +237,7 @@ pub const ANOTHER_MOCK_POOL: LiquidityPoolId = 101;
 
 pub struct ExtBuilder {
 	endowed_accounts: Vec<(AccountId, CurrencyId, Balance)>,
-	prices: Vec<(CurrencyId, Price)>,
+	mocks: MockBuilder,
 	spread: Permill,
 	additional_collateral_ratio: Permill,
 	is_allowed: bool,
@@ -260,8 +247,7 @@ impl Default for ExtBuilder {
 	fn default() -> Self {
 		Self {
 			endowed_accounts: vec![],
-			// collateral price set to `1` for calculation simplicity.
-			prices: vec![(CurrencyId::AUSD, FixedU128::saturating_from_rational(1, 1))],
+			mocks: MockBuilder::default(),
 			spread: Permill::zero(),
 			additional_collateral_ratio: Permill::zero(),
 			is_allowed: true,
@@ -286,7 +272,7 @@ impl ExtBuilder {
 	}
 
 	pub fn synthetic_price(mut self, price: Price) -> Self {
-		self.prices.push((CurrencyId::FEUR, price));
+		self.mocks.price(CurrencyId::FEUR, price);
 		self
 	}
 
@@ -319,9 +305,7 @@ impl ExtBuilder {
 	}
 
 	fn set_mocks(&self) {
-		self.prices
-			.iter()
-			.for_each(|(c, p)| MockPrices::set_mock_price(*c, Some(*p)));
+		self.mocks.set_mocks();
 
 		MockLiquidityPools::set_mock_spread(self.spread);
 		MockLiquidityPools::set_mock_additional_collateral_ratio(self.additional_collateral_ratio);
@@ -5,7 +5,7 @@
 use super::*;
 use frame_support::{assert_noop, assert_ok};
 use mock::*;
-use sp_runtime::{DispatchResult, Permill};
+use sp_runtime::{traits::BadOrigin, DispatchResult, Permill};
 
 fn mint_feur(who: AccountId, amount: Balance) -> DispatchResult {
 	SyntheticProtocol::mint(
@@ -87,6 +87,25 @@ fn mint_fails_if_no_price() {
 		});
 }
 
+#[test]
+fn mint_fails_with_price_unavailable_reason() {
+	ExtBuilder::default()
+		.one_million_for_alice_n_mock_pool()
+		.one_percent_spread()
+		.ten_percent_additional_collateral_ratio()
+		.build()
+		.execute_with(|| {
+			MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, Some(PriceUnavailableReason::StalePrice));
+			assert_noop!(mint_feur(ALICE, 100), Error::<Runtime>::StalePrice);
+
+			MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, Some(PriceUnavailableReason::MarketHalted));
+			assert_noop!(mint_feur(ALICE, 100), Error::<Runtime>::MarketHalted);
+
+			MockPriceStatus::set_mock_unavailable_reason(CurrencyId::FEUR, None);
+			assert_noop!(mint_feur(ALICE, 100), Error::<Runtime>::NoPrice);
+		});
+}
+
 #[test]
 fn mint_fails_if_slippage_too_greedy() {
 	ExtBuilder::default()
@@ -220,6 +239,63 @@ fn mint_does_correct_math() {
 		});
 }
 
+#[test]
+fn mint_and_redeem_with_fee_discount_works() {
+	ExtBuilder::default()
+		.one_million_for_alice_n_mock_pool()
+		.synthetic_price_three()
+		.one_percent_spread()
+		.ten_percent_additional_collateral_ratio()
+		.build()
+		.execute_with(|| {
+			MockFeeDiscount::set_mock_fee_discount(Permill::from_percent(50));
+			assert_ok!(mint_feur(ALICE, ONE_MILL));
+
+			// minted synthetic
+			// = ONE_MILL / ask_price
+			// = ONE_MILL / (3 * (1 + 0.01 * (1 - 0.5)))
+			// = 331_674.9585 ~ 331_674
+			assert_eq!(synthetic_balance(ALICE), 331_674);
+
+			assert_ok!(redeem_ausd(ALICE, 100_000));
+
+			// redeemed_collateral
+			// = synthetic * bid_price
+			// = 100_000 * 3 * (1 - 0.01 * (1 - 0.5))
+			// = 298_500
+			assert_eq!(collateral_balance(ALICE), 298_500);
+			assert_eq!(synthetic_balance(ALICE), 231_674);
+		});
+}
+
+#[test]
+fn mint_and_redeem_never_rounds_in_trader_favor() {
+	// prices with recurring decimals
+	let prices = [(10, 3), (22, 7), (29, 9), (31, 11)];
+	let amounts = [7, 1_001, 333_333, 600_000];
+	for &(x, y) in prices.iter() {
+		ExtBuilder::default()
+			.one_million_for_alice_n_mock_pool()
+			.synthetic_price(Price::saturating_from_rational(x, y))
+			.spread(Permill::zero())
+			.ten_percent_additional_collateral_ratio()
+			.build()
+			.execute_with(|| {
+				for &amount in amounts.iter() {
+					let collateral = collateral_balance(ALICE);
+					assert_ok!(mint_feur(ALICE, amount));
+					let synthetic = synthetic_balance(ALICE);
+					if synthetic > 0 {
+						assert_ok!(redeem_ausd(ALICE, synthetic));
+					}
+
+					// without spread or price change, redeeming right after minting never makes profit
+					assert!(collateral_balance(ALICE) <= collateral);
+				}
+			});
+	}
+}
+
 #[test]
 fn redeem_fails_if_not_enough_synthetic() {
 	ExtBuilder::default()
@@ -650,6 +726,34 @@ fn liquidate_fails_if_currency_is_not_supported() {
 		});
 }
 
+#[test]
+fn liquidation_incentive_works() {
+	ExtBuilder::default()
+		.one_million_for_alice_n_mock_pool()
+		.synthetic_price_three()
+		.one_percent_spread()
+		.ten_percent_additional_collateral_ratio()
+		.build()
+		.execute_with(|| {
+			assert_ok!(mint_feur(ALICE, ONE_MILL));
+
+			set_mock_feur_price(31, 10);
+			assert_eq!(
+				SyntheticProtocol::liquidation_incentive(MOCK_POOL, CurrencyId::FEUR, 100_000),
+				Err(Error::<Runtime>::StillInSafePosition.into())
+			);
+
+			// same as `liquidate_does_correct_math`
+			set_mock_feur_price(32, 10);
+			assert_eq!(
+				SyntheticProtocol::liquidation_incentive(MOCK_POOL, CurrencyId::FEUR, 100_000),
+				Ok(6_187)
+			);
+			// no storage change
+			assert_eq!(position(), (1_089_109, 330_033));
+		});
+}
+
 #[test]
 fn liquidate_does_correct_math() {
 	ExtBuilder::default()
@@ -769,6 +873,54 @@ fn liquidate_does_correct_math() {
 		});
 }
 
+#[test]
+fn liquidation_incentive_is_split() {
+	ExtBuilder::default()
+		.one_million_for_alice_n_mock_pool()
+		.synthetic_price_three()
+		.one_percent_spread()
+		.ten_percent_additional_collateral_ratio()
+		.build()
+		.execute_with(|| {
+			let split = FeeSplit {
+				pool_owner: 1,
+				insurance_fund: 1,
+				treasury: 1,
+				keeper: 1,
+			};
+			assert_noop!(
+				SyntheticProtocol::set_fee_split(origin_of(ALICE), MOCK_POOL, Some(split)),
+				BadOrigin
+			);
+			assert_ok!(SyntheticProtocol::set_fee_split(
+				origin_of(One::get()),
+				MOCK_POOL,
+				Some(split)
+			));
+			assert_eq!(SyntheticProtocol::fee_splits(MOCK_POOL), Some(split));
+			let event = TestEvent::synthetic_protocol(RawEvent::FeeSplitSet(MOCK_POOL, Some(split)));
+			assert!(System::events().iter().any(|record| record.event == event));
+
+			assert_ok!(mint_feur(ALICE, ONE_MILL));
+			set_mock_feur_price(32, 10);
+			assert_ok!(SyntheticCurrency::deposit(&BOB, 100_000));
+			assert_ok!(liquidate(BOB, 100_000));
+
+			// same as `liquidate_does_correct_math`, incentive is 6_187, and the rounding remainder goes to treasury
+			let (liquidized_collateral, collateral_from_pool, pool_refund_collateral) = (316_800, 89_109, 7_014);
+			assert_eq!(collateral_balance(BOB), liquidized_collateral + 1_546);
+			assert_eq!(collateral_balance(INSURANCE_FUND), 1_546);
+			assert_eq!(collateral_balance(TREASURY), 1_549);
+			assert_eq!(
+				mock_pool_liquidity(),
+				ONE_MILL - collateral_from_pool + pool_refund_collateral + 1_546
+			);
+
+			assert_ok!(SyntheticProtocol::set_fee_split(origin_of(One::get()), MOCK_POOL, None));
+			assert_eq!(SyntheticProtocol::fee_splits(MOCK_POOL), None);
+		});
+}
+
 #[test]
 fn add_collateral_fails_if_balance_too_low() {
 	ExtBuilder::default()
@@ -1048,3 +1200,94 @@ fn mint_all_of_collateral() {
 			assert_eq!(mock_pool_liquidity(), 20);
 		});
 }
+
+#[test]
+fn synthetic_tokens_works() {
+	ExtBuilder::default()
+		.one_million_for_alice_n_mock_pool()
+		.synthetic_price_three()
+		.one_percent_spread()
+		.ten_percent_additional_collateral_ratio()
+		.build()
+		.execute_with(|| {
+			let info = |pools| SyntheticTokenInfo {
+				currency_id: CurrencyId::FEUR,
+				price: Some(Price::saturating_from_rational(3, 1)),
+				total_issuance: synthetic_balance(ALICE),
+				extreme_ratio: TestSyntheticTokens::extreme_ratio_or_default(CurrencyId::FEUR),
+				liquidation_ratio: TestSyntheticTokens::liquidation_ratio_or_default(CurrencyId::FEUR),
+				collateral_ratio: TestSyntheticTokens::collateral_ratio_or_default(CurrencyId::FEUR),
+				pools,
+			};
+			let pool = |can_mint| SyntheticTokenPool {
+				pool_id: MOCK_POOL,
+				bid_spread: Some(30_000_000_000_000_000),
+				ask_spread: Some(30_000_000_000_000_000),
+				can_mint,
+				synthetic_issued: position().1,
+				collateral_ratio: SyntheticProtocol::collateral_ratio(MOCK_POOL, CurrencyId::FEUR),
+				additional_collateral_ratio: Permill::from_percent(10),
+			};
+
+			assert_eq!(SyntheticProtocol::synthetic_tokens(), vec![info(vec![pool(true)])]);
+
+			MockLiquidityPools::set_is_allowed(false);
+			assert_eq!(SyntheticProtocol::synthetic_tokens(), vec![info(vec![])]);
+
+			MockLiquidityPools::set_is_allowed(true);
+			assert_ok!(mint_feur(ALICE, 1000));
+			MockLiquidityPools::set_is_allowed(false);
+			assert!(synthetic_balance(ALICE) > 0);
+
+			// still listed for redeeming and liquidating issued synthetic
+			assert_eq!(SyntheticProtocol::synthetic_tokens(), vec![info(vec![pool(false)])]);
+		});
+}
+
+#[test]
+fn synthetic_volume_works() {
+	ExtBuilder::default()
+		.one_million_for_alice_n_mock_pool()
+		.synthetic_price_three()
+		.one_percent_spread()
+		.ten_percent_additional_collateral_ratio()
+		.build()
+		.execute_with(|| {
+			let volumes = |period_start, minted, redeemed, previous| RollingVolume {
+				period_start,
+				current: SyntheticVolume { minted, redeemed },
+				previous,
+			};
+
+			assert_ok!(mint_feur(ALICE, ONE_MILL));
+			// redeemed_collateral = 100_000 * 3 * (1 - 0.01) = 297_000
+			assert_ok!(redeem_ausd(ALICE, 100_000));
+			assert_eq!(
+				SyntheticProtocol::synthetic_volume(MOCK_POOL, CurrencyId::FEUR),
+				volumes(0, ONE_MILL, 297_000, SyntheticVolume::default())
+			);
+
+			// rolled over on the next period
+			System::set_block_number(10);
+			let previous = SyntheticVolume {
+				minted: ONE_MILL,
+				redeemed: 297_000,
+			};
+			assert_eq!(
+				SyntheticProtocol::synthetic_volume(MOCK_POOL, CurrencyId::FEUR),
+				volumes(10, 0, 0, previous.clone())
+			);
+			assert_ok!(redeem_ausd(ALICE, 100_000));
+			assert_eq!(
+				SyntheticProtocol::synthetic_volume(MOCK_POOL, CurrencyId::FEUR),
+				volumes(10, 0, 297_000, previous)
+			);
+
+			// not traded in the previous period
+			System::set_block_number(30);
+			assert_eq!(
+				SyntheticProtocol::synthetic_volume(MOCK_POOL, CurrencyId::FEUR),
+				volumes(30, 0, 0, SyntheticVolume::default())
+			);
+		});
+}
@@ -7,7 +7,7 @@ use frame_support::{
 };
 use frame_system as system;
 use module_primitives::{Balance, CurrencyId, LiquidityPoolId};
-use module_traits::BaseLiquidityPoolManager;
+use module_traits::{AuditLog, BaseLiquidityPoolManager};
 use orml_utilities::with_transaction_result;
 use sp_runtime::{
 	traits::{AccountIdConversion, CheckedDiv, CheckedSub, Zero},
@@ -39,6 +39,9 @@ pub trait Trait: frame_system::Trait {
 
 	/// Required origin for updating protocol options.
 	type UpdateOrigin: EnsureOrigin<Self::Origin>;
+
+	/// Audit log of privileged parameter changes.
+	type AuditLog: AuditLog<Self::Origin>;
 }
 
 /// Synthetic token position.
@@ -109,8 +112,9 @@ decl_module! {
 		#[weight = 10_000]
 		pub fn set_extreme_ratio(origin, currency_id: CurrencyId, #[compact] ratio: Permill) {
 			with_transaction_result(|| {
-				T::UpdateOrigin::ensure_origin(origin)?;
-				Ratios::mutate(currency_id, |r| r.extreme = Some(ratio));
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Ratios::mutate(currency_id, |r| r.extreme.replace(ratio));
+				Self::record_ratio_change(origin, b"ExtremeRatio", currency_id, old, ratio);
 				Self::deposit_event(Event::ExtremeRatioUpdated(currency_id, ratio));
 				Ok(())
 			})?;
@@ -122,8 +126,9 @@ decl_module! {
 		#[weight = 10_000]
 		pub fn set_liquidation_ratio(origin, currency_id: CurrencyId, #[compact] ratio: Permill) {
 			with_transaction_result(|| {
-				T::UpdateOrigin::ensure_origin(origin)?;
-				Ratios::mutate(currency_id, |r| r.liquidation = Some(ratio));
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Ratios::mutate(currency_id, |r| r.liquidation.replace(ratio));
+				Self::record_ratio_change(origin, b"LiquidationRatio", currency_id, old, ratio);
 				Self::deposit_event(Event::LiquidationRatioUpdated(currency_id, ratio));
 				Ok(())
 			})?;
@@ -135,8 +140,9 @@ decl_module! {
 		#[weight = 10_000]
 		pub fn set_collateral_ratio(origin, currency_id: CurrencyId, #[compact] ratio: Permill) {
 			with_transaction_result(|| {
-				T::UpdateOrigin::ensure_origin(origin)?;
-				Ratios::mutate(currency_id, |r| r.collateral = Some(ratio));
+				T::UpdateOrigin::ensure_origin(origin.clone())?;
+				let old = Ratios::mutate(currency_id, |r| r.collateral.replace(ratio));
+				Self::record_ratio_change(origin, b"CollateralRatio", currency_id, old, ratio);
 				Self::deposit_event(Event::CollateralRatioUpdated(currency_id, ratio));
 				Ok(())
 			})?;
@@ -210,6 +216,24 @@ impl<T: Trait> Module<T> {
 			.checked_div(&liquidation_to_extreme_gap)
 			.expect("liquidation_ratio > extreme_ratio; qed")
 	}
+
+	/// Record a change of ratio `parameter` of `currency_id` into the audit log.
+	fn record_ratio_change(
+		origin: T::Origin,
+		parameter: &[u8],
+		currency_id: CurrencyId,
+		old: Option<Permill>,
+		new: Permill,
+	) {
+		T::AuditLog::record(
+			origin,
+			b"SyntheticTokens",
+			parameter,
+			currency_id.encode(),
+			old.encode(),
+			Some(new).encode(),
+		);
+	}
 }
 
 impl<T: Trait> Module<T> {
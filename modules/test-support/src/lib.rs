@@ -0,0 +1,504 @@
+//! Shared mocks and helpers for module unit tests.
+//!
+//! Mock runtimes stay in each module's `mock.rs`, as they implement the module's own `Trait`. The parts every mock
+//! runtime needs, like the `frame_system` config, oracle prices, fee discounts and pool liquidities, live here, so
+//! that a new module's mock only has to wire them up.
+
+use std::{
+	cell::RefCell,
+	collections::{BTreeMap, BTreeSet},
+	marker::PhantomData,
+};
+
+use frame_support::{traits::Get, weights::Weight};
+use orml_traits::{BasicCurrency, DataProvider};
+use primitives::{arithmetic::*, Balance, CurrencyId, Leverage, LiquidityPoolId, Price, TradingPair};
+use sp_arithmetic::{
+	traits::{CheckedAdd, CheckedDiv, CheckedSub},
+	FixedI128, FixedPointNumber,
+};
+use sp_runtime::{DispatchError, DispatchResult, Permill};
+use traits::{
+	BaseLiquidityPoolManager, FeeDiscount, LiquidityPools, MarginPoolRisk, MarketCalendar, PoolLiabilityAmounts,
+	PriceStatus, PriceUnavailableReason, SyntheticProtocolLiquidityPools, TradingHalt,
+};
+
+#[doc(hidden)]
+pub mod reexports {
+	pub use frame_support;
+	pub use frame_system;
+	pub use sp_core;
+	pub use sp_runtime;
+}
+
+/// Implement `frame_system::Trait` for the mock `$runtime`, with `u64` block numbers and the same block limits for
+/// all mocks.
+///
+/// `Origin` is the one declared by `impl_outer_origin!` or `construct_runtime!` at the call site. `Call` and the base
+/// call filter are `()` unless given.
+#[macro_export]
+macro_rules! impl_mock_system {
+	($runtime:ty, $account_id:ty, $event:ty, $account_data:ty) => {
+		$crate::impl_mock_system!($runtime, $account_id, $event, $account_data, (), ());
+	};
+	($runtime:ty, $account_id:ty, $event:ty, $account_data:ty, $call:ty, $base_call_filter:ty) => {
+		$crate::reexports::frame_support::parameter_types! {
+			pub const BlockHashCount: u64 = 250;
+			pub const MaximumBlockWeight: $crate::reexports::frame_support::weights::Weight = 1024;
+			pub const MaximumBlockLength: u32 = 2 * 1024;
+			pub const AvailableBlockRatio: $crate::reexports::sp_runtime::Perbill =
+				$crate::reexports::sp_runtime::Perbill::one();
+		}
+
+		impl $crate::reexports::frame_system::Trait for $runtime {
+			type Origin = Origin;
+			type Call = $call;
+			type Index = u64;
+			type BlockNumber = u64;
+			type Hash = $crate::reexports::sp_core::H256;
+			type Hashing = $crate::reexports::sp_runtime::traits::BlakeTwo256;
+			type AccountId = $account_id;
+			type Lookup = $crate::reexports::sp_runtime::traits::IdentityLookup<Self::AccountId>;
+			type Header = $crate::reexports::sp_runtime::testing::Header;
+			type Event = $event;
+			type BlockHashCount = BlockHashCount;
+			type MaximumExtrinsicWeight = MaximumBlockWeight;
+			type MaximumBlockWeight = MaximumBlockWeight;
+			type DbWeight = ();
+			type BlockExecutionWeight = ();
+			type ExtrinsicBaseWeight = ();
+			type MaximumBlockLength = MaximumBlockLength;
+			type AvailableBlockRatio = AvailableBlockRatio;
+			type Version = ();
+			type ModuleToIndex = ();
+			type OnNewAccount = ();
+			type OnKilledAccount = ();
+			type AccountData = $account_data;
+			type BaseCallFilter = $base_call_filter;
+		}
+	};
+}
+
+pub const EUR_USD_PAIR: TradingPair = TradingPair {
+	base: CurrencyId::FEUR,
+	quote: CurrencyId::AUSD,
+};
+
+pub const JPY_USD_PAIR: TradingPair = TradingPair {
+	base: CurrencyId::FJPY,
+	quote: CurrencyId::AUSD,
+};
+
+pub const EUR_JPY_PAIR: TradingPair = TradingPair {
+	base: CurrencyId::FEUR,
+	quote: CurrencyId::FJPY,
+};
+
+/// `n` is a natural currency amount by cent, with 2 fractional digits precision.
+pub fn fixedi128_saturating_from_integer_currency_cent(n: i128) -> FixedI128 {
+	FixedI128::from_inner(n * 10_000_000_000_000_000)
+}
+
+/// `b` is a natural currency amount by cent, with 2 fractional digits precision.
+pub fn balance_saturating_from_integer_currency_cent(b: u128) -> Balance {
+	b * 10_000_000_000_000_000
+}
+
+thread_local! {
+	static PRICES: RefCell<BTreeMap<CurrencyId, Price>> = RefCell::new(BTreeMap::new());
+	static PRICE_READS: RefCell<u32> = RefCell::new(0);
+}
+
+/// Mock oracle prices, in USD.
+pub struct MockPrices;
+impl MockPrices {
+	pub fn set_mock_price(currency_id: CurrencyId, price: Option<Price>) {
+		if let Some(p) = price {
+			PRICES.with(|v| v.borrow_mut().insert(currency_id, p));
+		} else {
+			PRICES.with(|v| v.borrow_mut().remove(&currency_id));
+		}
+	}
+
+	/// Price of `currency_id`. Counted as a read.
+	pub fn prices(currency_id: CurrencyId) -> Option<Price> {
+		PRICE_READS.with(|v| *v.borrow_mut() += 1);
+		PRICES.with(|v| v.borrow().get(&currency_id).copied())
+	}
+
+	/// Price of `pair.base` in `pair.quote`. Counted as two reads.
+	pub fn pair_price(pair: TradingPair) -> Option<Price> {
+		let base_price = Self::prices(pair.base)?;
+		let quote_price = Self::prices(pair.quote)?;
+		base_price.checked_div(&quote_price)
+	}
+
+	/// Number of price reads since last reset.
+	pub fn reads() -> u32 {
+		PRICE_READS.with(|v| *v.borrow())
+	}
+
+	pub fn reset_reads() {
+		PRICE_READS.with(|v| *v.borrow_mut() = 0);
+	}
+}
+
+impl DataProvider<CurrencyId, Price> for MockPrices {
+	fn get(key: &CurrencyId) -> Option<Price> {
+		Self::prices(*key)
+	}
+}
+
+thread_local! {
+	static UNAVAILABLE_REASONS: RefCell<BTreeMap<CurrencyId, PriceUnavailableReason>> = RefCell::new(BTreeMap::new());
+}
+
+/// Mock reasons of unavailable prices. Prices without a reason set are reported as `NoPrice` by the protocols.
+pub struct MockPriceStatus;
+impl MockPriceStatus {
+	pub fn set_mock_unavailable_reason(currency_id: CurrencyId, reason: Option<PriceUnavailableReason>) {
+		if let Some(r) = reason {
+			UNAVAILABLE_REASONS.with(|v| v.borrow_mut().insert(currency_id, r));
+		} else {
+			UNAVAILABLE_REASONS.with(|v| v.borrow_mut().remove(&currency_id));
+		}
+	}
+}
+
+impl PriceStatus for MockPriceStatus {
+	fn unavailable_reason(currency_id: CurrencyId) -> Option<PriceUnavailableReason> {
+		UNAVAILABLE_REASONS.with(|v| v.borrow().get(&currency_id).copied())
+	}
+}
+
+thread_local! {
+	static FEE_DISCOUNT: RefCell<Permill> = RefCell::new(Permill::zero());
+}
+
+/// Mock fee discount, the same for all accounts.
+pub struct MockFeeDiscount;
+impl MockFeeDiscount {
+	pub fn set_mock_fee_discount(discount: Permill) {
+		FEE_DISCOUNT.with(|v| *v.borrow_mut() = discount);
+	}
+}
+
+impl<AccountId> FeeDiscount<AccountId> for MockFeeDiscount {
+	fn fee_discount(_who: &AccountId) -> Permill {
+		FEE_DISCOUNT.with(|v| *v.borrow())
+	}
+}
+
+thread_local! {
+	static CLOSED_MARKETS: RefCell<BTreeSet<TradingPair>> = RefCell::new(BTreeSet::new());
+}
+
+/// Mock market calendar. All markets are open unless set closed.
+pub struct MockMarketCalendar;
+impl MockMarketCalendar {
+	pub fn set_mock_market_closed(pair: TradingPair, closed: bool) {
+		if closed {
+			CLOSED_MARKETS.with(|v| v.borrow_mut().insert(pair));
+		} else {
+			CLOSED_MARKETS.with(|v| v.borrow_mut().remove(&pair));
+		}
+	}
+}
+
+impl MarketCalendar for MockMarketCalendar {
+	fn is_market_closed(pair: TradingPair) -> bool {
+		CLOSED_MARKETS.with(|v| v.borrow().contains(&pair))
+	}
+}
+
+thread_local! {
+	static LIQUIDITIES: RefCell<BTreeMap<LiquidityPoolId, Balance>> = RefCell::new(BTreeMap::new());
+}
+
+/// Mock liquidity of pools, for mock liquidity pools which don't hold liquidity in accounts.
+pub struct MockLiquidities;
+impl MockLiquidities {
+	/// Liquidity of `pool_id`. Panics if not set.
+	pub fn liquidity(pool_id: LiquidityPoolId) -> Balance {
+		LIQUIDITIES.with(|v| v.borrow().get(&pool_id).copied()).unwrap()
+	}
+
+	pub fn set_mock_liquidity(pool_id: LiquidityPoolId, liquidity: Balance) {
+		LIQUIDITIES.with(|v| v.borrow_mut().insert(pool_id, liquidity));
+	}
+}
+
+thread_local! {
+	static SPREAD: RefCell<Permill> = RefCell::new(Permill::zero());
+	static ADDITIONAL_COLLATERAL_RATIO: RefCell<Permill> = RefCell::new(Permill::zero());
+	static IS_ALLOWED: RefCell<bool> = RefCell::new(true);
+}
+
+/// Mock liquidity pools of `u32` account mock runtimes. `Pools` exist, and account `0` owns them all.
+///
+/// Liquidity of a pool is the `Currency` balance of the pool ID account. For synthetic protocols, bid and ask spreads
+/// are the mock spread of the mock price, and minting is allowed unless set otherwise.
+pub struct MockLiquidityPools<Currency, Pools>(PhantomData<(Currency, Pools)>);
+impl<Currency, Pools> MockLiquidityPools<Currency, Pools> {
+	pub fn set_mock_spread(spread: Permill) {
+		SPREAD.with(|v| *v.borrow_mut() = spread);
+	}
+
+	pub fn set_mock_additional_collateral_ratio(ratio: Permill) {
+		ADDITIONAL_COLLATERAL_RATIO.with(|v| *v.borrow_mut() = ratio);
+	}
+
+	pub fn set_is_allowed(allowed: bool) {
+		IS_ALLOWED.with(|v| *v.borrow_mut() = allowed);
+	}
+
+	fn spread_of(currency_id: CurrencyId) -> Option<Balance> {
+		let price = MockPrices::prices(currency_id)?;
+		Some(SPREAD.with(|v| *v.borrow()).mul_ceil(price.into_inner()))
+	}
+}
+
+impl<Currency, Pools> LiquidityPools<u32> for MockLiquidityPools<Currency, Pools>
+where
+	Currency: BasicCurrency<u32, Balance = Balance>,
+	Pools: Get<Vec<LiquidityPoolId>>,
+{
+	fn all() -> Vec<LiquidityPoolId> {
+		Pools::get()
+	}
+
+	fn is_owner(_pool_id: LiquidityPoolId, who: &u32) -> bool {
+		*who == 0
+	}
+
+	fn pool_exists(pool_id: LiquidityPoolId) -> bool {
+		Pools::get().contains(&pool_id)
+	}
+
+	fn liquidity(pool_id: LiquidityPoolId) -> Balance {
+		Currency::free_balance(&pool_id)
+	}
+
+	fn deposit_liquidity(from: &u32, pool_id: LiquidityPoolId, amount: Balance) -> DispatchResult {
+		Currency::transfer(from, &pool_id, amount)
+	}
+
+	fn withdraw_liquidity(to: &u32, pool_id: LiquidityPoolId, amount: Balance) -> DispatchResult {
+		Currency::transfer(&pool_id, to, amount)
+	}
+}
+
+impl<Currency, Pools> SyntheticProtocolLiquidityPools<u32> for MockLiquidityPools<Currency, Pools>
+where
+	Currency: BasicCurrency<u32, Balance = Balance>,
+	Pools: Get<Vec<LiquidityPoolId>>,
+{
+	fn bid_spread(_pool_id: LiquidityPoolId, currency_id: CurrencyId) -> Option<Balance> {
+		Self::spread_of(currency_id)
+	}
+
+	fn ask_spread(_pool_id: LiquidityPoolId, currency_id: CurrencyId) -> Option<Balance> {
+		Self::spread_of(currency_id)
+	}
+
+	fn additional_collateral_ratio(_pool_id: LiquidityPoolId, _currency_id: CurrencyId) -> Permill {
+		ADDITIONAL_COLLATERAL_RATIO.with(|v| *v.borrow())
+	}
+
+	fn can_mint(_pool_id: LiquidityPoolId, _currency_id: CurrencyId) -> bool {
+		IS_ALLOWED.with(|v| *v.borrow())
+	}
+}
+
+thread_local! {
+	static IS_POOL_SAFE: RefCell<bool> = RefCell::new(true);
+	static CHECKED_LIABILITIES: RefCell<Option<(TradingPair, PoolLiabilityAmounts)>> = RefCell::new(None);
+}
+
+/// Mock pool safety checks, recording the last checked liabilities. Pools are safe unless set otherwise.
+pub struct MockPoolRisk;
+impl MockPoolRisk {
+	pub fn set_mock_pool_safe(safe: bool) {
+		IS_POOL_SAFE.with(|v| *v.borrow_mut() = safe);
+	}
+
+	pub fn checked_liabilities() -> Option<(TradingPair, PoolLiabilityAmounts)> {
+		CHECKED_LIABILITIES.with(|v| *v.borrow())
+	}
+}
+
+impl MarginPoolRisk for MockPoolRisk {
+	fn ensure_pool_safe_with(
+		_pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		liabilities: PoolLiabilityAmounts,
+	) -> DispatchResult {
+		CHECKED_LIABILITIES.with(|v| *v.borrow_mut() = Some((pair, liabilities)));
+		if IS_POOL_SAFE.with(|v| *v.borrow()) {
+			Ok(())
+		} else {
+			Err(DispatchError::Other("pool would be unsafe"))
+		}
+	}
+
+	fn pool_risk_weight() -> Weight {
+		0
+	}
+}
+
+thread_local! {
+	static CAN_WITHDRAW: RefCell<bool> = RefCell::new(true);
+}
+
+/// Mock base liquidity pool manager. Pools can always be removed, and withdrawals are allowed unless set otherwise.
+pub struct MockPoolManager;
+impl MockPoolManager {
+	pub fn set_mock_can_withdraw(can_withdraw: bool) {
+		CAN_WITHDRAW.with(|v| *v.borrow_mut() = can_withdraw);
+	}
+}
+
+impl BaseLiquidityPoolManager<LiquidityPoolId, Balance> for MockPoolManager {
+	fn can_remove(_pool_id: LiquidityPoolId) -> bool {
+		true
+	}
+
+	fn ensure_can_withdraw(_pool_id: LiquidityPoolId, _amount: Balance) -> DispatchResult {
+		if CAN_WITHDRAW.with(|v| *v.borrow()) {
+			Ok(())
+		} else {
+			Err(DispatchError::Other("pool would be unsafe"))
+		}
+	}
+}
+
+thread_local! {
+	static HALTED_CURRENCIES: RefCell<BTreeSet<CurrencyId>> = RefCell::new(BTreeSet::new());
+}
+
+/// Mock trading halts. Trading of all currencies is open unless set halted.
+pub struct MockTradingHalt;
+impl MockTradingHalt {
+	pub fn set_mock_halted(currency_id: CurrencyId, halted: bool) {
+		if halted {
+			HALTED_CURRENCIES.with(|v| v.borrow_mut().insert(currency_id));
+		} else {
+			HALTED_CURRENCIES.with(|v| v.borrow_mut().remove(&currency_id));
+		}
+	}
+}
+
+impl TradingHalt for MockTradingHalt {
+	fn is_trading_halted(currency_id: CurrencyId) -> bool {
+		HALTED_CURRENCIES.with(|v| v.borrow().contains(&currency_id))
+	}
+}
+
+/// Builder of the shared mocks state, to be embedded in module `ExtBuilder`s.
+pub struct MockBuilder {
+	prices: Vec<(CurrencyId, Price)>,
+	pool_liquidities: Vec<(LiquidityPoolId, Balance)>,
+	fee_discount: Permill,
+}
+
+impl Default for MockBuilder {
+	/// Collateral price set to `1` for calculation simplicity.
+	fn default() -> Self {
+		Self {
+			prices: vec![(CurrencyId::AUSD, Price::saturating_from_integer(1))],
+			pool_liquidities: vec![],
+			fee_discount: Permill::zero(),
+		}
+	}
+}
+
+impl MockBuilder {
+	pub fn price(&mut self, currency_id: CurrencyId, price: Price) {
+		self.prices.push((currency_id, price));
+	}
+
+	pub fn pool_liquidity(&mut self, pool_id: LiquidityPoolId, liquidity: Balance) {
+		self.pool_liquidities.push((pool_id, liquidity));
+	}
+
+	pub fn fee_discount(&mut self, discount: Permill) {
+		self.fee_discount = discount;
+	}
+
+	/// Set the mocks state. Price reads are reset.
+	pub fn set_mocks(&self) {
+		self.prices
+			.iter()
+			.for_each(|(c, p)| MockPrices::set_mock_price(*c, Some(*p)));
+		self.pool_liquidities
+			.iter()
+			.for_each(|(p, l)| MockLiquidities::set_mock_liquidity(*p, *l));
+		MockFeeDiscount::set_mock_fee_discount(self.fee_discount);
+		MockPrices::reset_reads();
+	}
+}
+
+/// Leveraged amounts and margin held of a new position.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PositionAmounts {
+	pub leveraged_held: FixedI128,
+	pub leveraged_debits: FixedI128,
+	pub margin_held: FixedI128,
+}
+
+/// Amounts of a position of `leveraged_amount` opened at `price` with `spread` of the price, and without fee
+/// discount. Rounded as the margin protocol does: against the trader.
+///
+/// `quote_usd_price` is the USD price of the pair's quote currency.
+pub fn open_position_amounts(
+	leverage: Leverage,
+	leveraged_amount: Balance,
+	price: Price,
+	spread: Permill,
+	quote_usd_price: Price,
+) -> PositionAmounts {
+	let spread = Price::from_inner(spread.mul_ceil(price.into_inner()));
+	let (debits_price, debits_rounding, held_signum) = if leverage.is_long() {
+		(price.checked_add(&spread).unwrap(), Rounding::Up, 1)
+	} else {
+		(price.checked_sub(&spread).unwrap(), Rounding::Down, -1)
+	};
+
+	let leveraged_held = fixed_i128_from_u128(leveraged_amount);
+	let leveraged_debits = fixed_i128_mul_with_rounding(
+		leveraged_held,
+		fixed_i128_from_fixed_u128(debits_price),
+		debits_rounding,
+	)
+	.unwrap();
+	let leveraged_held_in_usd = fixed_i128_mul_with_rounding(
+		leveraged_debits,
+		fixed_i128_from_fixed_u128(quote_usd_price),
+		Rounding::Up,
+	)
+	.unwrap();
+	let margin_held = fixed_i128_div_with_rounding(
+		leveraged_held_in_usd,
+		FixedI128::saturating_from_integer(leverage.value()),
+		Rounding::Up,
+	)
+	.unwrap();
+
+	PositionAmounts {
+		leveraged_held: fixed_i128_mul_signum(leveraged_held, held_signum),
+		leveraged_debits: fixed_i128_mul_signum(leveraged_debits, -held_signum),
+		margin_held,
+	}
+}
+
+/// Amounts of a position of `leveraged_amount` of `pair`, opened at the current mock prices with `spread`.
+pub fn open_position_amounts_at_mock_prices(
+	pair: TradingPair,
+	leverage: Leverage,
+	leveraged_amount: Balance,
+	spread: Permill,
+) -> PositionAmounts {
+	let price = MockPrices::pair_price(pair).expect("mock prices set");
+	let quote_usd_price = MockPrices::prices(pair.quote).expect("mock prices set");
+	open_position_amounts(leverage, leveraged_amount, price, spread, quote_usd_price)
+}
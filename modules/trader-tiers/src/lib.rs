@@ -0,0 +1,83 @@
+//! Registry of trader classification tiers.
+//!
+//! Traders are retail unless classified otherwise by governance. Protocols consult the tier of a trader to apply
+//! tier-specific limits, e.g. the retail leverage caps required in some jurisdictions.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::Encode;
+use frame_support::{decl_event, decl_module, decl_storage, traits::EnsureOrigin};
+// FIXME: `pallet/frame-` prefix should be used for all pallet modules, but currently `frame_system`
+// would cause compiling error in `decl_module!` and `construct_runtime!`
+// #3295 https://github.com/paritytech/substrate/issues/3295
+use frame_system as system;
+
+use module_primitives::TraderTier;
+use module_traits::{AuditLog, TraderTiers as TraderTiersT};
+
+mod mock;
+mod tests;
+
+pub trait Trait: frame_system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+
+	/// Required origin for classifying traders.
+	type UpdateOrigin: EnsureOrigin<Self::Origin>;
+
+	/// Audit log of privileged parameter changes.
+	type AuditLog: AuditLog<Self::Origin>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as TraderTiers {
+		/// Tiers of classified traders. Traders not in the map are retail.
+		TraderTiers get(fn trader_tier): map hasher(twox_64_concat) T::AccountId => TraderTier;
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where
+		<T as frame_system::Trait>::AccountId,
+	{
+		/// Trader tier set: [who, tier]
+		TraderTierSet(AccountId, TraderTier),
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		/// Set the tier of `who`. Setting the default tier removes the classification.
+		///
+		/// May only be called from `UpdateOrigin`.
+		#[weight = 10_000]
+		pub fn set_trader_tier(origin, who: T::AccountId, tier: TraderTier) {
+			T::UpdateOrigin::ensure_origin(origin.clone())?;
+
+			let old = Self::trader_tier(&who);
+			if tier == TraderTier::default() {
+				TraderTiers::<T>::remove(&who);
+			} else {
+				TraderTiers::<T>::insert(&who, tier);
+			}
+			T::AuditLog::record(
+				origin,
+				b"TraderTiers",
+				b"TraderTiers",
+				who.encode(),
+				old.encode(),
+				tier.encode(),
+			);
+
+			Self::deposit_event(RawEvent::TraderTierSet(who, tier));
+		}
+	}
+}
+
+impl<T: Trait> TraderTiersT<T::AccountId> for Module<T> {
+	fn trader_tier(who: &T::AccountId) -> TraderTier {
+		Self::trader_tier(who)
+	}
+}
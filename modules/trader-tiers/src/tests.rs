@@ -0,0 +1,52 @@
+//! Unit tests for the trader tiers module.
+
+#![cfg(test)]
+
+use super::*;
+use frame_support::{assert_noop, assert_ok};
+use mock::*;
+use sp_runtime::DispatchError::BadOrigin;
+
+#[test]
+fn traders_are_retail_by_default() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_eq!(
+			<TraderTiersModule as TraderTiersT<AccountId>>::trader_tier(&ALICE),
+			TraderTier::Retail
+		);
+	});
+}
+
+#[test]
+fn set_trader_tier_works() {
+	ExtBuilder::default().build().execute_with(|| {
+		assert_noop!(
+			TraderTiersModule::set_trader_tier(Origin::signed(ALICE), ALICE, TraderTier::Professional),
+			BadOrigin
+		);
+
+		assert_ok!(TraderTiersModule::set_trader_tier(
+			Origin::signed(UpdateOrigin::get()),
+			ALICE,
+			TraderTier::Professional
+		));
+		assert_eq!(
+			<TraderTiersModule as TraderTiersT<AccountId>>::trader_tier(&ALICE),
+			TraderTier::Professional
+		);
+		assert_eq!(TraderTiersModule::trader_tier(BOB), TraderTier::Retail);
+		let event = TestEvent::trader_tiers(RawEvent::TraderTierSet(ALICE, TraderTier::Professional));
+		assert!(System::events().iter().any(|record| record.event == event));
+
+		// back to retail removes the classification
+		assert_ok!(TraderTiersModule::set_trader_tier(
+			Origin::signed(UpdateOrigin::get()),
+			ALICE,
+			TraderTier::Retail
+		));
+		assert_eq!(TraderTiersModule::trader_tier(ALICE), TraderTier::Retail);
+		assert!(!TraderTiers::<Runtime>::contains_key(ALICE));
+		let event = TestEvent::trader_tiers(RawEvent::TraderTierSet(ALICE, TraderTier::Retail));
+		assert!(System::events().iter().any(|record| record.event == event));
+	});
+}
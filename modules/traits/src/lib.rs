@@ -1,8 +1,9 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-use primitives::{Balance, CurrencyId, Leverage, LiquidityPoolId, TradingPair};
-use sp_arithmetic::FixedI128;
-use sp_runtime::{DispatchResult, Permill, RuntimeDebug};
+use frame_support::weights::Weight;
+use primitives::{Balance, CurrencyId, Leverage, LiquidityPoolId, TraderTier, TradingPair};
+use sp_arithmetic::{traits::CheckedAdd, FixedI128};
+use sp_runtime::{DispatchError, DispatchResult, Permill, RuntimeDebug};
 use sp_std::{prelude::*, result};
 
 /// An abstraction of liquidity pools basic functionalities.
@@ -34,6 +35,115 @@ pub trait BaseLiquidityPoolManager<LiquidityPoolId, Balance> {
 	/// Return `Ok` iff the account is able to make a withdrawal of the given amount.
 	/// Basically, it's just a dry-run of `withdraw`.
 	fn ensure_can_withdraw(pool_id: LiquidityPoolId, amount: Balance) -> DispatchResult;
+
+	/// Worst case weight of `ensure_can_withdraw`, on top of the withdrawal. Zero for checks of a few storage reads.
+	fn ensure_can_withdraw_weight() -> Weight {
+		0
+	}
+}
+
+/// Checks of both `A` and `B`, for pools shared by several modules.
+impl<LiquidityPoolId: Copy, Balance: Copy, A, B> BaseLiquidityPoolManager<LiquidityPoolId, Balance> for (A, B)
+where
+	A: BaseLiquidityPoolManager<LiquidityPoolId, Balance>,
+	B: BaseLiquidityPoolManager<LiquidityPoolId, Balance>,
+{
+	fn can_remove(pool_id: LiquidityPoolId) -> bool {
+		A::can_remove(pool_id) && B::can_remove(pool_id)
+	}
+
+	fn ensure_can_withdraw(pool_id: LiquidityPoolId, amount: Balance) -> DispatchResult {
+		A::ensure_can_withdraw(pool_id, amount)?;
+		B::ensure_can_withdraw(pool_id, amount)
+	}
+
+	fn ensure_can_withdraw_weight() -> Weight {
+		A::ensure_can_withdraw_weight().saturating_add(B::ensure_can_withdraw_weight())
+	}
+}
+
+/// An abstraction of the privileged parameter changes log.
+pub trait AuditLog<Origin> {
+	/// Record a change of `parameter` in `pallet` made by `origin`. `key`, `old_value` and `new_value` are SCALE
+	/// encoded.
+	fn record(origin: Origin, pallet: &[u8], parameter: &[u8], key: Vec<u8>, old_value: Vec<u8>, new_value: Vec<u8>);
+}
+
+impl<Origin> AuditLog<Origin> for () {
+	fn record(_: Origin, _: &[u8], _: &[u8], _: Vec<u8>, _: Vec<u8>, _: Vec<u8>) {}
+}
+
+/// An abstraction of trading fee discounts.
+pub trait FeeDiscount<AccountId> {
+	/// Return the discount on trading fees of `who`.
+	fn fee_discount(who: &AccountId) -> Permill;
+}
+
+impl<AccountId> FeeDiscount<AccountId> for () {
+	fn fee_discount(_: &AccountId) -> Permill {
+		Permill::zero()
+	}
+}
+
+/// An abstraction of trader classification tiers.
+pub trait TraderTiers<AccountId> {
+	/// Return the tier of `who`.
+	fn trader_tier(who: &AccountId) -> TraderTier;
+}
+
+impl<AccountId> TraderTiers<AccountId> for () {
+	fn trader_tier(_: &AccountId) -> TraderTier {
+		TraderTier::default()
+	}
+}
+
+/// Reason of a price being unavailable.
+#[derive(Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum PriceUnavailableReason {
+	/// No price has been fed.
+	NoPrice,
+	/// All fed prices have expired.
+	StalePrice,
+	/// Not enough oracle operators fed unexpired prices.
+	BelowOracleQuorum,
+	/// The market is halted.
+	MarketHalted,
+}
+
+/// An abstraction of price availability.
+pub trait PriceStatus {
+	/// Return why the price of `currency_id` is unavailable, or `None` if it is available or the reason is unknown.
+	fn unavailable_reason(currency_id: CurrencyId) -> Option<PriceUnavailableReason>;
+}
+
+impl PriceStatus for () {
+	fn unavailable_reason(_: CurrencyId) -> Option<PriceUnavailableReason> {
+		None
+	}
+}
+
+/// An abstraction of market calendars.
+pub trait MarketCalendar {
+	/// Return `true` if the market of `pair` is closed now, like on holidays.
+	fn is_market_closed(pair: TradingPair) -> bool;
+}
+
+impl MarketCalendar for () {
+	fn is_market_closed(_: TradingPair) -> bool {
+		false
+	}
+}
+
+/// An abstraction of trading halts on unhealthy oracle feeds.
+pub trait TradingHalt {
+	/// Return `true` if trading of `currency_id` is halted.
+	fn is_trading_halted(currency_id: CurrencyId) -> bool;
+}
+
+impl TradingHalt for () {
+	fn is_trading_halted(_: CurrencyId) -> bool {
+		false
+	}
 }
 
 /// An abstraction of liquidity pools for Synthetic Protocol.
@@ -57,6 +167,8 @@ pub enum OpenPositionError {
 	TradingPairNotEnabled,
 	TradingPairNotEnabledInPool,
 	BelowMinLeveragedAmount,
+	BackstopNotActive,
+	AboveMaxLeveragedAmount,
 }
 
 /// An abstraction of liquidity pools for Margin Protocol.
@@ -80,12 +192,121 @@ pub trait MarginProtocolLiquidityPools<AccountId>: LiquidityPools<AccountId> {
 		leverage: Leverage,
 		leveraged_amount: Balance,
 	) -> result::Result<(), OpenPositionError>;
+
+	/// Returns the max leverage value allowed for traders of `tier` in `pool_id`, or `None` if not capped.
+	fn trader_max_leverage(pool_id: LiquidityPoolId, tier: TraderTier) -> Option<u8>;
+
+	/// Returns the commission rate of the notional value charged on opening and closing positions of `pair` in
+	/// `pool_id`.
+	fn commission(pool_id: LiquidityPoolId, pair: TradingPair) -> Permill;
 }
 
 /// Margin protocol liquidity pools manager.
 pub trait MarginProtocolLiquidityPoolsManager {
 	/// Return `Ok` iff the trading pair could be enabled in `pool_id`.
 	fn ensure_can_enable_trading_pair(pool_id: LiquidityPoolId, pair: TradingPair) -> DispatchResult;
+
+	/// Returns if `pool_id` is safe: not margin called, and ENP and ELL above margin call thresholds.
+	fn is_pool_safe(pool_id: LiquidityPoolId) -> bool;
+
+	/// Returns ENP and ELL of `pool_id`, or `None` if not available.
+	fn enp_and_ell(pool_id: LiquidityPoolId) -> Option<(FixedI128, FixedI128)>;
+}
+
+/// Liabilities of a liquidity pool outside of margin positions, in USD.
+#[derive(Clone, Copy, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct PoolLiabilityAmounts {
+	/// Loss that would be realized if the liabilities were settled at current prices.
+	pub unrealized_loss: FixedI128,
+	/// Exposure counted in the net position of the pool.
+	pub net_position: FixedI128,
+	/// Exposure counted in the longest leg of the pool.
+	pub longest_leg: FixedI128,
+}
+
+impl PoolLiabilityAmounts {
+	/// Sum of `self` and `other`. Returns `None` on overflow.
+	pub fn checked_add(&self, other: &Self) -> Option<Self> {
+		Some(Self {
+			unrealized_loss: self.unrealized_loss.checked_add(&other.unrealized_loss)?,
+			net_position: self.net_position.checked_add(&other.net_position)?,
+			longest_leg: self.longest_leg.checked_add(&other.longest_leg)?,
+		})
+	}
+}
+
+/// An abstraction of liquidity pool liabilities outside of margin positions, like written options. They are folded
+/// into ENP and ELL of margin liquidity pools.
+pub trait PoolLiabilities {
+	/// Return liabilities of `pool_id`.
+	fn liabilities(pool_id: LiquidityPoolId) -> result::Result<PoolLiabilityAmounts, DispatchError>;
+
+	/// Return `true` if `pool_id` has any liability. Pools with liabilities can't be removed.
+	fn has_liabilities(pool_id: LiquidityPoolId) -> bool;
+
+	/// Worst case weight of `liabilities`.
+	fn liabilities_weight() -> Weight;
+}
+
+impl PoolLiabilities for () {
+	fn liabilities(_: LiquidityPoolId) -> result::Result<PoolLiabilityAmounts, DispatchError> {
+		Ok(Default::default())
+	}
+
+	fn has_liabilities(_: LiquidityPoolId) -> bool {
+		false
+	}
+
+	fn liabilities_weight() -> Weight {
+		0
+	}
+}
+
+/// Liabilities of both `A` and `B`, for pools shared by several modules.
+impl<A: PoolLiabilities, B: PoolLiabilities> PoolLiabilities for (A, B) {
+	fn liabilities(pool_id: LiquidityPoolId) -> result::Result<PoolLiabilityAmounts, DispatchError> {
+		A::liabilities(pool_id)?
+			.checked_add(&B::liabilities(pool_id)?)
+			.ok_or(DispatchError::Other("pool liabilities overflow"))
+	}
+
+	fn has_liabilities(pool_id: LiquidityPoolId) -> bool {
+		A::has_liabilities(pool_id) || B::has_liabilities(pool_id)
+	}
+
+	fn liabilities_weight() -> Weight {
+		A::liabilities_weight().saturating_add(B::liabilities_weight())
+	}
+}
+
+/// An abstraction of an insurance fund covering trader shortfalls before they hit pool liquidity.
+pub trait InsuranceFund<AccountId> {
+	/// Cover up to `amount` of a shortfall in `pool_id` by transferring it to `dest`. Return the covered amount,
+	/// which is less than `amount` if the fund runs short.
+	fn cover_shortfall(
+		dest: &AccountId,
+		pool_id: LiquidityPoolId,
+		amount: Balance,
+	) -> result::Result<Balance, DispatchError>;
+}
+
+impl<AccountId> InsuranceFund<AccountId> for () {
+	fn cover_shortfall(_: &AccountId, _: LiquidityPoolId, _: Balance) -> result::Result<Balance, DispatchError> {
+		Ok(0)
+	}
+}
+
+/// Safety checks of margin liquidity pools, for new liabilities outside of margin positions.
+pub trait MarginPoolRisk {
+	/// Return `Ok` iff `pool_id` is safe with `liabilities` on `pair` added. Risk thresholds of `pair` apply.
+	fn ensure_pool_safe_with(
+		pool_id: LiquidityPoolId,
+		pair: TradingPair,
+		liabilities: PoolLiabilityAmounts,
+	) -> DispatchResult;
+
+	/// Worst case weight of `ensure_pool_safe_with`.
+	fn pool_risk_weight() -> Weight;
 }
 
 /// The liquidity pool was disabled by owner.
@@ -99,3 +320,13 @@ pub trait OnRemoveLiquidityPool {
 	/// Invoked when the liquiditiy pool has been removed.
 	fn on_remove(pool_id: LiquidityPoolId);
 }
+
+/// The liquidity of a pool was updated.
+pub trait OnLiquidityUpdate {
+	/// Invoked when the liquidity of the pool has been updated to `liquidity`, or to zero on removal.
+	fn on_liquidity_update(pool_id: LiquidityPoolId, liquidity: Balance);
+}
+
+impl OnLiquidityUpdate for () {
+	fn on_liquidity_update(_: LiquidityPoolId, _: Balance) {}
+}
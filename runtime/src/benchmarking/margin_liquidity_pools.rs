@@ -1,6 +1,7 @@
-use super::utils::dollars;
-use crate::{AccountId, BaseLiquidityPoolsForMargin, MarginLiquidityPools, MarginProtocol, Runtime};
+use super::utils::{dollars, SYNTHETIC_CURRENCY_IDS};
+use crate::{AccountId, BaseLiquidityPoolsForMargin, MarginLiquidityPools, MarginProtocol, Runtime, Timestamp};
 
+use frame_support::traits::OnInitialize;
 use frame_system::RawOrigin;
 use sp_runtime::{DispatchError, FixedI128, Permill};
 use sp_std::prelude::*;
@@ -17,6 +18,8 @@ const MAX_POOL_INDEX: u32 = 1000;
 const MAX_SPREAD: u32 = 1000;
 const MAX_SWAP_RATE: u32 = 1000;
 const MAX_AMOUNT: u32 = 1000;
+const MAX_ACCUMULATED_POOLS: u32 = 100;
+const MAX_ACCUMULATED_PAIRS: u32 = 9;
 
 const EUR_USD: TradingPair = TradingPair {
 	base: CurrencyId::FEUR,
@@ -38,6 +41,8 @@ runtime_benchmarks! {
 		let s in 1 .. MAX_SPREAD => ();
 		let r in 1 .. MAX_SWAP_RATE => ();
 		let a in 1 .. MAX_AMOUNT => ();
+		let n in 1 .. MAX_ACCUMULATED_POOLS => ();
+		let e in 1 .. MAX_ACCUMULATED_PAIRS => ();
 	}
 
 	set_spread {
@@ -89,6 +94,7 @@ runtime_benchmarks! {
 		let threshold = RiskThreshold {
 			margin_call: Permill::from_percent(5),
 			stop_out: Permill::from_percent(2),
+			warning: Permill::from_percent(10),
 		};
 		MarginProtocol::set_trading_pair_risk_threshold(
 			RawOrigin::Root.into(),
@@ -107,6 +113,7 @@ runtime_benchmarks! {
 		let threshold = RiskThreshold {
 			margin_call: Permill::from_percent(5),
 			stop_out: Permill::from_percent(2),
+			warning: Permill::from_percent(10),
 		};
 		MarginProtocol::set_trading_pair_risk_threshold(
 			RawOrigin::Root.into(),
@@ -135,6 +142,49 @@ runtime_benchmarks! {
 			a.into(),
 		)?;
 	}: _(RawOrigin::Signed(caller), 0, a.into())
+
+	on_initialize_accumulate {
+		let n in ...;
+		for i in 0 .. n {
+			create_pool(i)?;
+		}
+		let swap_rate = SwapRate {
+			long: FixedI128::from_inner(1),
+			short: FixedI128::from_inner(1),
+		};
+		MarginLiquidityPools::set_swap_rate(RawOrigin::Root.into(), EUR_USD, swap_rate)?;
+		// accumulate every minute
+		MarginLiquidityPools::set_accumulate_config(RawOrigin::Root.into(), EUR_USD, 60, 0)?;
+		Timestamp::set_timestamp(60_000);
+	}: {
+		MarginLiquidityPools::on_initialize(1);
+	}
+
+	// `on_initialize` with `n` pools and `e` enabled pairs to accumulate
+	on_initialize_accumulate_with_enabled_pairs {
+		let n in ...;
+		let e in ...;
+		for i in 0 .. n {
+			create_pool(i)?;
+		}
+		let swap_rate = SwapRate {
+			long: FixedI128::from_inner(1),
+			short: FixedI128::from_inner(1),
+		};
+		for currency_id in SYNTHETIC_CURRENCY_IDS.iter().take(e as usize) {
+			let pair = TradingPair {
+				base: *currency_id,
+				quote: CurrencyId::AUSD,
+			};
+			MarginLiquidityPools::enable_trading_pair(RawOrigin::Root.into(), pair)?;
+			MarginLiquidityPools::set_swap_rate(RawOrigin::Root.into(), pair, swap_rate.clone())?;
+			// accumulate every minute
+			MarginLiquidityPools::set_accumulate_config(RawOrigin::Root.into(), pair, 60, 0)?;
+		}
+		Timestamp::set_timestamp(60_000);
+	}: {
+		MarginLiquidityPools::on_initialize(1);
+	}
 }
 
 #[cfg(test)]
@@ -232,4 +282,18 @@ mod tests {
 			assert_ok!(test_benchmark_set_min_leveraged_amount());
 		});
 	}
+
+	#[test]
+	fn on_initialize_accumulate() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_on_initialize_accumulate());
+		});
+	}
+
+	#[test]
+	fn on_initialize_accumulate_with_enabled_pairs() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_on_initialize_accumulate_with_enabled_pairs());
+		});
+	}
 }
@@ -1,7 +1,10 @@
-use super::utils::{dollars, lookup_of_account, set_ausd_balance, set_price};
-use crate::{AccountId, BaseLiquidityPoolsForMargin, MarginLiquidityPools, MarginProtocol, Oracle, Price, Runtime};
+use super::utils::{dollars, lookup_of_account, set_ausd_balance, set_price, SYNTHETIC_CURRENCY_IDS};
+use crate::{
+	AccountId, BaseLiquidityPoolsForMargin, GetStopOutChunkSize, MarginLiquidityPools, MarginProtocol, Oracle, Price,
+	Runtime,
+};
 
-use frame_support::traits::ChangeMembers;
+use frame_support::traits::{ChangeMembers, Get};
 use frame_system::RawOrigin;
 use sp_runtime::{DispatchError, DispatchResult, FixedI128, FixedPointNumber, Permill};
 use sp_std::prelude::*;
@@ -17,6 +20,10 @@ const MAX_TRADER_INDEX: u32 = 1000;
 const MAX_POOL_OWNER_INDEX: u32 = 1000;
 const MAX_DOLLARS: u32 = 1000;
 const MAX_THRESHOLD: u32 = 100;
+// `GetTraderMaxOpenPositions`
+const MAX_OPEN_POSITIONS: u32 = 200;
+// `SYNTHETIC_CURRENCY_IDS` pairs
+const MAX_PAIRS: u32 = 9;
 
 const EUR_USD: TradingPair = TradingPair {
 	base: CurrencyId::FEUR,
@@ -30,6 +37,7 @@ fn create_pool(p: u32) -> Result<AccountId, DispatchError> {
 	let threshold = RiskThreshold {
 		margin_call: Permill::from_percent(5),
 		stop_out: Permill::from_percent(2),
+		warning: Permill::from_percent(10),
 	};
 	MarginProtocol::set_trading_pair_risk_threshold(
 		RawOrigin::Root.into(),
@@ -57,6 +65,62 @@ fn add_liquidity(owner: &AccountId, liquidity: Balance) -> DispatchResult {
 	BaseLiquidityPoolsForMargin::deposit_liquidity(RawOrigin::Signed(owner.clone()).into(), 0, liquidity)
 }
 
+/// Open `count` long positions of `trader`, with `amount` leveraged amount in total.
+fn open_positions(trader: &AccountId, count: u32, amount: Balance, price: Price) -> DispatchResult {
+	for _ in 0..count {
+		MarginProtocol::open_position(
+			RawOrigin::Signed(trader.clone()).into(),
+			0,
+			EUR_USD,
+			Leverage::LongTwo,
+			amount / Balance::from(count),
+			price,
+			None,
+		)?;
+	}
+	Ok(())
+}
+
+/// Enable `count` pairs of synthetic currencies in aUSD, including EUR/USD, in the pool of `owner`.
+fn enable_pairs(owner: &AccountId, count: u32) -> DispatchResult {
+	let threshold = RiskThreshold {
+		margin_call: Permill::from_percent(5),
+		stop_out: Permill::from_percent(2),
+		warning: Permill::from_percent(10),
+	};
+	// EUR/USD is enabled on pool creation
+	for pair in pairs(count).into_iter().skip(1) {
+		MarginProtocol::set_trading_pair_risk_threshold(
+			RawOrigin::Root.into(),
+			pair,
+			Some(threshold.clone()),
+			Some(threshold.clone()),
+			Some(threshold.clone()),
+		)?;
+		MarginLiquidityPools::set_spread(RawOrigin::Signed(owner.clone()).into(), 0, pair, 0, 0)?;
+		MarginLiquidityPools::enable_trading_pair(RawOrigin::Root.into(), pair)?;
+		MarginLiquidityPools::set_enabled_leverages(RawOrigin::Signed(owner.clone()).into(), 0, pair, Leverages::all())?;
+		MarginLiquidityPools::liquidity_pool_enable_trading_pair(RawOrigin::Signed(owner.clone()).into(), 0, pair)?;
+	}
+	Ok(())
+}
+
+fn pairs(count: u32) -> Vec<TradingPair> {
+	SYNTHETIC_CURRENCY_IDS
+		.iter()
+		.take(count as usize)
+		.map(|currency_id| TradingPair {
+			base: *currency_id,
+			quote: CurrencyId::AUSD,
+		})
+		.collect()
+}
+
+/// Set the price of the base currencies of `count` pairs to `price`.
+fn set_pair_prices(count: u32, price: Price) -> DispatchResult {
+	set_price(pairs(count).into_iter().map(|pair| (pair.base, price)).collect())
+}
+
 fn set_up_oracle() {
 	<Oracle as ChangeMembers<_>>::change_members_sorted(
 		&vec![],
@@ -73,6 +137,8 @@ runtime_benchmarks! {
 		let p in 1 .. MAX_POOL_OWNER_INDEX => ();
 		let d in 100 .. MAX_DOLLARS => ();
 		let h in 1 .. MAX_THRESHOLD => ();
+		let o in 1 .. MAX_OPEN_POSITIONS => ();
+		let e in 1 .. MAX_PAIRS => ();
 	}
 
 	deposit {
@@ -121,7 +187,7 @@ runtime_benchmarks! {
 
 		set_up_oracle();
 		set_price(vec![(CurrencyId::FEUR, Price::saturating_from_integer(1))])?;
-	}: _(RawOrigin::Signed(trader), 0, EUR_USD, Leverage::LongTwo, balance, Price::saturating_from_integer(2))
+	}: _(RawOrigin::Signed(trader), 0, EUR_USD, Leverage::LongTwo, balance, Price::saturating_from_integer(2), None)
 
 	// `open_position` when there is already ten positions in pool
 	open_position_with_ten_in_pool {
@@ -148,10 +214,11 @@ runtime_benchmarks! {
 				EUR_USD,
 				Leverage::LongTwo,
 				balance / 10,
-				Price::saturating_from_integer(2)
+				Price::saturating_from_integer(2),
+				None
 			)?;
 		}
-	}: open_position(RawOrigin::Signed(trader), 0, EUR_USD, Leverage::LongTwo, balance, Price::saturating_from_integer(2))
+	}: open_position(RawOrigin::Signed(trader), 0, EUR_USD, Leverage::LongTwo, balance, Price::saturating_from_integer(2), None)
 
 	close_position {
 		let t in ...;
@@ -176,9 +243,10 @@ runtime_benchmarks! {
 			EUR_USD,
 			Leverage::LongTwo,
 			balance,
-			Price::saturating_from_integer(2)
+			Price::saturating_from_integer(2),
+			None
 		)?;
-	}: _(RawOrigin::Signed(trader), 0, Price::zero())
+	}: _(RawOrigin::Signed(trader), 0, Price::zero(), None)
 
 	// `close_position` when there is already ten positions in pool
 	close_position_with_ten_in_pool {
@@ -205,10 +273,36 @@ runtime_benchmarks! {
 				EUR_USD,
 				Leverage::LongTwo,
 				balance / 10,
-				Price::saturating_from_integer(2)
+				Price::saturating_from_integer(2),
+				None
 			)?;
 		}
-	}: close_position(RawOrigin::Signed(trader), 0, Price::zero())
+	}: close_position(RawOrigin::Signed(trader), 0, Price::zero(), None)
+
+	// `close_position` when the trader has `o` open positions
+	close_position_with_open_positions {
+		let t in ...;
+		let p in ...;
+		let d in ...;
+		let o in ...;
+
+		let pool_owner = create_pool(p)?;
+
+		let trader: AccountId = account("trader", t, SEED);
+		let balance = dollars(d);
+		deposit_balance(&trader, balance)?;
+
+		let liquidity = balance;
+		add_liquidity(&pool_owner, liquidity)?;
+
+		set_up_oracle();
+		set_price(vec![(CurrencyId::FEUR, Price::saturating_from_integer(1))])?;
+
+		open_positions(&trader, o, balance, Price::saturating_from_integer(2))?;
+	}: close_position(RawOrigin::Signed(trader.clone()), 0, Price::zero(), None)
+	verify {
+		assert_eq!(MarginProtocol::positions_by_trader(&trader, (0, 0)), None);
+	}
 
 	trader_margin_call {
 		let t in ...;
@@ -233,7 +327,8 @@ runtime_benchmarks! {
 			EUR_USD,
 			Leverage::LongTwo,
 			balance,
-			Price::saturating_from_integer(3)
+			Price::saturating_from_integer(3),
+			None
 		)?;
 
 		set_price(vec![(CurrencyId::FEUR, Price::saturating_from_integer(1))])?;
@@ -265,7 +360,8 @@ runtime_benchmarks! {
 			EUR_USD,
 			Leverage::LongTwo,
 			balance,
-			Price::saturating_from_integer(3)
+			Price::saturating_from_integer(3),
+			None
 		)?;
 
 		set_price(vec![(CurrencyId::FEUR, Price::saturating_from_integer(1))])?;
@@ -306,7 +402,8 @@ runtime_benchmarks! {
 			EUR_USD,
 			Leverage::LongTwo,
 			balance,
-			Price::saturating_from_integer(3)
+			Price::saturating_from_integer(3),
+			None
 		)?;
 		assert_eq!(MarginProtocol::positions_by_trader(&trader, (0, 0)), Some(()));
 
@@ -316,6 +413,36 @@ runtime_benchmarks! {
 		assert_eq!(MarginProtocol::positions_by_trader(&trader, (0, 0)), None);
 	}
 
+	// `trader_stop_out` when the trader has `o` open positions, of which at most `GetStopOutChunkSize` are closed
+	trader_stop_out_with_open_positions {
+		let t in ...;
+		let p in ...;
+		let d in ...;
+		let o in ...;
+
+		let pool_owner = create_pool(p)?;
+
+		let trader: AccountId = account("trader", t, SEED);
+		let balance = dollars(d);
+		deposit_balance(&trader, balance)?;
+
+		let liquidity = balance;
+		add_liquidity(&pool_owner, liquidity)?;
+
+		set_up_oracle();
+		set_price(vec![(CurrencyId::FEUR, Price::saturating_from_integer(2))])?;
+
+		open_positions(&trader, o, balance, Price::saturating_from_integer(3))?;
+
+		set_price(vec![(CurrencyId::FEUR, Price::saturating_from_integer(1))])?;
+	}: trader_stop_out(RawOrigin::None, lookup_of_account(trader.clone()), 0)
+	verify {
+		assert_eq!(
+			MarginProtocol::pending_trader_stop_outs(&trader, 0).len() as u32,
+			o.saturating_sub(GetStopOutChunkSize::get())
+		);
+	}
+
 	liquidity_pool_margin_call {
 		let t in ...;
 		let p in ...;
@@ -339,7 +466,8 @@ runtime_benchmarks! {
 			EUR_USD,
 			Leverage::LongTwo,
 			balance,
-			Price::saturating_from_integer(2)
+			Price::saturating_from_integer(2),
+			None
 		)?;
 
 		set_price(vec![(CurrencyId::FEUR, Price::saturating_from_integer(2))])?;
@@ -371,7 +499,8 @@ runtime_benchmarks! {
 			EUR_USD,
 			Leverage::LongTwo,
 			balance,
-			Price::saturating_from_integer(2)
+			Price::saturating_from_integer(2),
+			None
 		)?;
 
 		set_price(vec![(CurrencyId::FEUR, Price::saturating_from_integer(2))])?;
@@ -407,7 +536,8 @@ runtime_benchmarks! {
 			EUR_USD,
 			Leverage::LongTwo,
 			balance,
-			Price::saturating_from_integer(2)
+			Price::saturating_from_integer(2),
+			None
 		)?;
 		assert_eq!(MarginProtocol::positions_by_pool(0, (EUR_USD, 0)), Some(()));
 
@@ -417,6 +547,73 @@ runtime_benchmarks! {
 		assert_eq!(MarginProtocol::positions_by_pool(0, (EUR_USD, 0)), None);
 	}
 
+	// `liquidity_pool_force_close` when the pool has `o` open positions, of which at most `GetStopOutChunkSize` are
+	// closed
+	liquidity_pool_force_close_with_open_positions {
+		let t in ...;
+		let p in ...;
+		let d in ...;
+		let o in ...;
+
+		let pool_owner = create_pool(p)?;
+
+		let trader: AccountId = account("trader", t, SEED);
+		let balance = dollars(d);
+		deposit_balance(&trader, balance)?;
+
+		let liquidity = balance;
+		add_liquidity(&pool_owner, liquidity)?;
+
+		set_up_oracle();
+		set_price(vec![(CurrencyId::FEUR, Price::saturating_from_integer(1))])?;
+
+		open_positions(&trader, o, balance, Price::saturating_from_integer(2))?;
+
+		set_price(vec![(CurrencyId::FEUR, Price::saturating_from_integer(2))])?;
+	}: liquidity_pool_force_close(RawOrigin::None, 0)
+	verify {
+		let remaining = MarginProtocol::pending_pool_force_closes(0).map_or(0, |(_, positions)| positions.len());
+		assert_eq!(remaining as u32, o.saturating_sub(GetStopOutChunkSize::get()));
+	}
+
+	// `liquidity_pool_margin_call` when the pool has positions in `e` pairs, which ENP and ELL are computed over
+	liquidity_pool_margin_call_with_pairs {
+		let t in ...;
+		let p in ...;
+		let d in ...;
+		let e in ...;
+
+		let pool_owner = create_pool(p)?;
+		enable_pairs(&pool_owner, e)?;
+
+		let trader: AccountId = account("trader", t, SEED);
+		let balance = dollars(d);
+		deposit_balance(&trader, balance)?;
+
+		let liquidity = balance;
+		add_liquidity(&pool_owner, liquidity)?;
+
+		set_up_oracle();
+		set_pair_prices(e, Price::saturating_from_integer(1))?;
+
+		for pair in pairs(e) {
+			MarginProtocol::open_position(
+				RawOrigin::Signed(trader.clone()).into(),
+				0,
+				pair,
+				Leverage::LongTwo,
+				balance / Balance::from(e),
+				Price::saturating_from_integer(2),
+				None
+			)?;
+		}
+
+		set_pair_prices(e, Price::saturating_from_integer(2))?;
+	}: liquidity_pool_margin_call(RawOrigin::None, 0)
+	verify {
+		assert_eq!(MarginProtocol::margin_called_pools(0), Some(()))
+	}
+
 	set_trading_pair_risk_threshold {
 		let p in ...;
 		let h in ...;
@@ -430,6 +627,7 @@ runtime_benchmarks! {
 		let threshold = RiskThreshold {
 			margin_call: Permill::from_percent(h),
 			stop_out: Permill::from_percent(h),
+			warning: Permill::from_percent(h),
 		};
 	}: _(RawOrigin::Root, EUR_USD, Some(threshold.clone()), Some(threshold.clone()), Some(threshold.clone()))
 	verify {
@@ -501,6 +699,13 @@ mod tests {
 		});
 	}
 
+	#[test]
+	fn close_position_with_open_positions() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_close_position_with_open_positions());
+		});
+	}
+
 	#[test]
 	fn trader_margin_call() {
 		new_test_ext().execute_with(|| {
@@ -522,6 +727,13 @@ mod tests {
 		});
 	}
 
+	#[test]
+	fn trader_stop_out_with_open_positions() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_trader_stop_out_with_open_positions());
+		});
+	}
+
 	#[test]
 	fn liquidity_pool_margin_call() {
 		new_test_ext().execute_with(|| {
@@ -543,6 +755,20 @@ mod tests {
 		});
 	}
 
+	#[test]
+	fn liquidity_pool_force_close_with_open_positions() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_liquidity_pool_force_close_with_open_positions());
+		});
+	}
+
+	#[test]
+	fn liquidity_pool_margin_call_with_pairs() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_liquidity_pool_margin_call_with_pairs());
+		});
+	}
+
 	#[test]
 	fn set_trading_pair_risk_threshold() {
 		new_test_ext().execute_with(|| {
@@ -7,6 +7,19 @@ use sp_runtime::{
 	DispatchResult,
 };
 
+/// Synthetic currencies of trading pairs, all quoted in aUSD.
+pub const SYNTHETIC_CURRENCY_IDS: [CurrencyId; 9] = [
+	CurrencyId::FEUR,
+	CurrencyId::FJPY,
+	CurrencyId::FBTC,
+	CurrencyId::FETH,
+	CurrencyId::FAUD,
+	CurrencyId::FCAD,
+	CurrencyId::FCHF,
+	CurrencyId::FXAU,
+	CurrencyId::FOIL,
+];
+
 pub fn lookup_of_account(who: AccountId) -> <<Runtime as frame_system::Trait>::Lookup as StaticLookup>::Source {
 	<Runtime as frame_system::Trait>::Lookup::unlookup(who)
 }
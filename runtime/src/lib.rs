@@ -25,7 +25,8 @@ use sp_core::{
 	u32_trait::{_1, _2, _3, _4},
 };
 use sp_runtime::traits::{
-	BlakeTwo256, Block as BlockT, Convert, NumberFor, OpaqueKeys, SaturatedConversion, StaticLookup,
+	AccountIdConversion, BlakeTwo256, Block as BlockT, Convert, NumberFor, OpaqueKeys, SaturatedConversion,
+	StaticLookup,
 };
 use sp_runtime::{
 	create_runtime_str,
@@ -33,22 +34,31 @@ use sp_runtime::{
 	generic, impl_opaque_keys,
 	traits::{Extrinsic, Saturating, Verify},
 	transaction_validity::{TransactionPriority, TransactionSource, TransactionValidity},
-	ApplyExtrinsicResult, FixedPointNumber, ModuleId,
+	ApplyExtrinsicResult, DispatchError, FixedPointNumber, ModuleId,
 };
-use sp_std::prelude::*;
+use sp_std::{cmp, marker::PhantomData, prelude::*};
 #[cfg(feature = "std")]
 use sp_version::NativeVersion;
 use sp_version::RuntimeVersion;
 
+use frame_support::traits::Get;
 pub use frame_system::{self as system, Call as SystemCall, EnsureOneOf, EnsureRoot};
 pub use module_primitives::{Balance, CurrencyId, LiquidityPoolId, Price};
+use module_primitives::{AuditRecord, Leverage, TradingPair, TradingPairMetadata};
+use module_traits::{MarginProtocolLiquidityPools, PriceStatus, PriceUnavailableReason};
 use orml_currencies::BasicCurrencyAdapter;
 pub use orml_oracle::AuthorityId as OracleId;
 use orml_traits::DataProvider;
 pub use sp_arithmetic::FixedI128;
 
-use margin_protocol_rpc_runtime_api::{MarginPoolState, MarginTraderState};
-use synthetic_protocol_rpc_runtime_api::SyntheticPoolState;
+use margin_protocol_rpc_runtime_api::{
+	MarginAccumulatedSwapRate, MarginCollateral, MarginEnpEllSnapshot, MarginOpenInterest, MarginPoolState,
+	MarginPositionSimulation, MarginRiskParams, MarginRiskThreshold, MarginSwapRates, MarginTraderState,
+	MarginTradingVolume,
+};
+use synthetic_protocol_rpc_runtime_api::{
+	SyntheticLiquidationCheck, SyntheticPoolState, SyntheticToken, SyntheticTokenPool, SyntheticVolume,
+};
 
 // A few exports that help ease life for downstream crates.
 pub use frame_support::{
@@ -100,7 +110,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("laminar"),
 	impl_name: create_runtime_str!("laminar"),
 	authoring_version: 1,
-	spec_version: 201,
+	spec_version: 202,
 	impl_version: 0,
 	apis: RUNTIME_API_VERSIONS,
 	transaction_version: 1,
@@ -117,8 +127,19 @@ pub fn native_version() -> NativeVersion {
 
 pub struct BaseFilter;
 impl Filter<Call> for BaseFilter {
-	fn filter(_call: &Call) -> bool {
-		true
+	fn filter(call: &Call) -> bool {
+		emergency_control::PausedCallFilter::<Runtime>::filter(call) && recovery_delay_allowed(call)
+	}
+}
+
+/// Recovery configs must have a delay of at least `MinRecoveryDelayPeriod`, so that the lost account has a window to
+/// cancel malicious recovery attempts before its margin balances and positions are taken over.
+fn recovery_delay_allowed(call: &Call) -> bool {
+	match call {
+		Call::Recovery(pallet_recovery::Call::create_recovery(_, _, delay_period)) => {
+			*delay_period >= MinRecoveryDelayPeriod::get()
+		}
+		_ => true,
 	}
 }
 
@@ -217,6 +238,27 @@ impl pallet_multisig::Trait for Runtime {
 	type MaxSignatories = MaxSignatories;
 }
 
+parameter_types! {
+	// One storage item; key size is 32; value is size 4+16+2 bytes = 22 bytes.
+	pub const ConfigDepositBase: Balance = deposit(1, 54);
+	// Additional friend of 32 bytes.
+	pub const FriendDepositFactor: Balance = deposit(0, 32);
+	pub const MaxFriends: u16 = 9;
+	// One storage item; key size is 64; value is size 4+16 bytes = 20 bytes.
+	pub const RecoveryDeposit: Balance = deposit(1, 84);
+	pub const MinRecoveryDelayPeriod: BlockNumber = 1 * DAYS;
+}
+
+impl pallet_recovery::Trait for Runtime {
+	type Event = Event;
+	type Call = Call;
+	type Currency = Balances;
+	type ConfigDepositBase = ConfigDepositBase;
+	type FriendDepositFactor = FriendDepositFactor;
+	type MaxFriends = MaxFriends;
+	type RecoveryDeposit = RecoveryDeposit;
+}
+
 impl pallet_babe::Trait for Runtime {
 	type EpochDuration = EpochDuration;
 	type ExpectedBlockTime = ExpectedBlockTime;
@@ -542,6 +584,7 @@ impl pallet_staking::Trait for Runtime {
 parameter_types! {
 	pub const MinimumCount: u32 = 1;
 	pub const ExpiresIn: Moment = 1000 * 60 * 60 * 24 * 3; // 3 days
+	pub const MarginMaxPriceAge: Moment = 1000 * 60 * 60; // 1 hour
 }
 
 impl orml_oracle::Trait for Runtime {
@@ -592,15 +635,107 @@ impl orml_currencies::Trait for Runtime {
 	type GetNativeCurrencyId = GetLaminarTokenId;
 }
 
-pub struct LaminarDataProvider;
-impl DataProvider<CurrencyId, Price> for LaminarDataProvider {
+/// Oracle prices, if fed within `MaxAge`.
+pub struct LaminarDataProvider<MaxAge = ExpiresIn>(PhantomData<MaxAge>);
+impl<MaxAge: Get<Moment>> DataProvider<CurrencyId, Price> for LaminarDataProvider<MaxAge> {
 	fn get(currency: &CurrencyId) -> Option<Price> {
 		match currency {
 			CurrencyId::AUSD => Some(Price::saturating_from_integer(1)),
+			_ if OraclePriceStatus::<MaxAge>::unavailable_reason(*currency).is_some() => None,
 			_ => <Oracle as DataProvider<CurrencyId, Price>>::get(currency),
 		}
 	}
 }
+
+/// Reasons of prices unavailable from `LaminarDataProvider`.
+pub struct OraclePriceStatus<MaxAge = ExpiresIn>(PhantomData<MaxAge>);
+impl<MaxAge: Get<Moment>> PriceStatus for OraclePriceStatus<MaxAge> {
+	fn unavailable_reason(currency: CurrencyId) -> Option<PriceUnavailableReason> {
+		if currency == CurrencyId::AUSD {
+			return None;
+		}
+		if EmergencyControl::is_market_halted(currency) {
+			return Some(PriceUnavailableReason::MarketHalted);
+		}
+		OracleFeedStatus::<MaxAge>::unavailable_reason(currency)
+	}
+}
+
+/// Health of the oracle feeds, regardless of market halts.
+///
+/// The oracle keeps returning the last combined price after fed prices expired, so prices fed earlier than `MaxAge`
+/// and under quorum prices are rejected here. Prices are not fed on market closures, so on closed days the age
+/// counts from the start of the closure.
+pub struct OracleFeedStatus<MaxAge = ExpiresIn>(PhantomData<MaxAge>);
+impl<MaxAge: Get<Moment>> PriceStatus for OracleFeedStatus<MaxAge> {
+	fn unavailable_reason(currency: CurrencyId) -> Option<PriceUnavailableReason> {
+		if currency == CurrencyId::AUSD {
+			return None;
+		}
+
+		let raw_values = Oracle::read_raw_values(&currency);
+		if raw_values.is_empty() {
+			return Some(PriceUnavailableReason::NoPrice);
+		}
+		let now = Timestamp::now();
+		let expiry_reference = MarketCalendar::currency_closed_since(currency).map_or(now, |start| start.min(now));
+		let unexpired_count = raw_values
+			.iter()
+			.filter(|v| v.timestamp.saturating_add(MaxAge::get()) > expiry_reference)
+			.count() as u32;
+		if unexpired_count == 0 {
+			Some(PriceUnavailableReason::StalePrice)
+		} else if unexpired_count < MinimumCount::get() {
+			Some(PriceUnavailableReason::BelowOracleQuorum)
+		} else {
+			None
+		}
+	}
+}
+impl emergency_control::Trait for Runtime {
+	type Event = Event;
+	type UpdateOrigin = EnsureHalfGeneralCouncilOrRoot;
+}
+
+parameter_types! {
+	pub const MaxMarketClosures: u32 = 100;
+	pub const WeekLength: Moment = 1000 * 60 * 60 * 24 * 7; // 7 days
+}
+
+impl market_calendar::Trait for Runtime {
+	type Event = Event;
+	type UpdateOrigin = EnsureHalfGeneralCouncilOrRoot;
+	type Time = Timestamp;
+	type MaxClosures = MaxMarketClosures;
+	type WeekLength = WeekLength;
+}
+
+impl oracle_watchdog::Trait for Runtime {
+	type Event = Event;
+	type FeedStatus = OracleFeedStatus;
+	type MonitoredCurrencyIds = SyntheticCurrencyIds;
+	type UpdateOrigin = EnsureHalfGeneralCouncilOrRoot;
+}
+
+parameter_types! {
+	pub const MaxAuditRecords: u32 = 10_000;
+}
+
+impl audit_log::Trait for Runtime {
+	type MaxAuditRecords = MaxAuditRecords;
+}
+
+parameter_types! {
+	pub const FeeDiscountMinLockPeriod: BlockNumber = 7 * DAYS;
+}
+
+impl fee_discount::Trait for Runtime {
+	type Event = Event;
+	type Currency = Balances;
+	type MinLockPeriod = FeeDiscountMinLockPeriod;
+	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
+}
+
 impl synthetic_tokens::Trait for Runtime {
 	type Event = Event;
 	type DefaultExtremeRatio = DefaultExtremeRatio;
@@ -608,11 +743,13 @@ impl synthetic_tokens::Trait for Runtime {
 	type DefaultCollateralRatio = DefaultCollateralRatio;
 	type SyntheticCurrencyIds = SyntheticCurrencyIds;
 	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
+	type AuditLog = AuditLog;
 }
 
 parameter_types! {
 	pub const GetLiquidityCurrencyId: CurrencyId = CurrencyId::AUSD;
 	pub MaxSwap: FixedI128 = FixedI128::saturating_from_integer(2); // TODO: set this
+	pub const MaxSwapRateHistory: u32 = 48;
 }
 
 type LiquidityCurrency = orml_currencies::Currency<Runtime, GetLiquidityCurrencyId>;
@@ -627,13 +764,14 @@ parameter_types! {
 impl base_liquidity_pools::Trait<BaseLiquidityPoolsMarginInstance> for Runtime {
 	type Event = Event;
 	type LiquidityCurrency = LiquidityCurrency;
-	type PoolManager = MarginProtocol;
+	type PoolManager = (MarginProtocol, EarnVault);
 	type ExistentialDeposit = LiquidityPoolExistentialDeposit;
 	type IdentityDeposit = IdentityDeposit;
 	type IdentityDepositCurrency = Balances;
 	type ModuleId = MarginLiquidityPoolsModuleId;
 	type OnDisableLiquidityPool = MarginLiquidityPools;
 	type OnRemoveLiquidityPool = MarginLiquidityPools;
+	type OnLiquidityUpdate = incentives::MarginLiquidityStakes<Runtime>;
 	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
 }
 
@@ -644,13 +782,14 @@ parameter_types! {
 impl base_liquidity_pools::Trait<BaseLiquidityPoolsSyntheticInstance> for Runtime {
 	type Event = Event;
 	type LiquidityCurrency = LiquidityCurrency;
-	type PoolManager = SyntheticTokens;
+	type PoolManager = (SyntheticTokens, SpotExchange);
 	type ExistentialDeposit = LiquidityPoolExistentialDeposit;
 	type IdentityDeposit = IdentityDeposit;
 	type IdentityDepositCurrency = Balances;
 	type ModuleId = SyntheticLiquidityPoolsModuleId;
 	type OnDisableLiquidityPool = SyntheticLiquidityPools;
 	type OnRemoveLiquidityPool = SyntheticLiquidityPools;
+	type OnLiquidityUpdate = incentives::SyntheticLiquidityStakes<Runtime>;
 	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
 }
 
@@ -659,19 +798,26 @@ impl margin_liquidity_pools::Trait for Runtime {
 	type BaseLiquidityPools = BaseLiquidityPoolsForMargin;
 	type PoolManager = MarginProtocol;
 	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
+	type AuditLog = AuditLog;
 	type MaxSwapRate = MaxSwap;
 	type UnixTime = Timestamp;
 	type Moment = Moment;
+	type GetTreasuryAccountId = GetTreasuryAccountId;
+	type MaxSwapRateHistory = MaxSwapRateHistory;
+	type TradingHalt = OracleWatchdog;
 }
 
 impl synthetic_liquidity_pools::Trait for Runtime {
 	type Event = Event;
 	type BaseLiquidityPools = BaseLiquidityPoolsForSynthetic;
 	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
+	type AuditLog = AuditLog;
+	type TradingHalt = OracleWatchdog;
 }
 
 parameter_types! {
 	pub const GetCollateralCurrencyId: CurrencyId = CurrencyId::AUSD;
+	pub const GetTradingVolumePeriod: BlockNumber = DAYS;
 }
 type CollateralCurrency = orml_currencies::Currency<Runtime, GetCollateralCurrencyId>;
 impl synthetic_protocol::Trait for Runtime {
@@ -680,8 +826,13 @@ impl synthetic_protocol::Trait for Runtime {
 	type CollateralCurrency = CollateralCurrency;
 	type GetCollateralCurrencyId = GetCollateralCurrencyId;
 	type PriceProvider = orml_traits::DefaultPriceProvider<CurrencyId, LaminarDataProvider>;
+	type PriceStatus = OraclePriceStatus;
 	type LiquidityPools = synthetic_liquidity_pools::Module<Runtime>;
 	type SyntheticProtocolLiquidityPools = synthetic_liquidity_pools::Module<Runtime>;
+	type FeeDiscount = FeeDiscount;
+	type GetVolumePeriod = GetTradingVolumePeriod;
+	type GetTreasuryAccountId = GetTreasuryAccountId;
+	type GetInsuranceFundAccountId = GetInsuranceFundAccountId;
 }
 
 impl<LocalCall> frame_system::offchain::CreateSignedTransaction<LocalCall> for Runtime
@@ -744,18 +895,155 @@ parameter_types! {
 	pub const GetTraderMaxOpenPositions: usize = 200;
 	pub const GetPoolMaxOpenPositions: usize = 1000;
 	pub GetTreasuryAccountId: AccountId = pallet_treasury::Module::<Runtime>::account_id();
+	pub const InsuranceFundModuleId: ModuleId = insurance_fund::MODULE_ID;
+	pub GetInsuranceFundAccountId: AccountId = InsuranceFundModuleId::get().into_account();
+	pub const GetStopOutDisputeWindow: BlockNumber = 7 * DAYS;
+	pub const GetSlippageInsurancePremiumPeriod: BlockNumber = DAYS;
+	pub const GetEnpEllSnapshotInterval: BlockNumber = HOURS;
+	pub const GetMaxEnpEllSnapshots: u32 = 7 * 24;
+	pub const GetSwapSettlementInterval: BlockNumber = DAYS;
+	pub const GetMaxCopyFollowers: u32 = 100;
+	pub const GetStopOutChunkSize: u32 = 50;
+	pub const GetMaxTradeHistory: u32 = 100;
+	pub const GetTraderMaxPendingOrders: u32 = 50;
 }
 
 impl margin_protocol::Trait for Runtime {
 	type Event = Event;
 	type LiquidityCurrency = LiquidityCurrency;
+	type MultiCurrency = orml_currencies::Module<Runtime>;
 	type LiquidityPools = margin_liquidity_pools::Module<Runtime>;
-	type PriceProvider = orml_traits::DefaultPriceProvider<CurrencyId, LaminarDataProvider>;
+	// margin trading is rejected on prices older than `MarginMaxPriceAge`, with `StalePrice` error
+	type PriceProvider = orml_traits::DefaultPriceProvider<CurrencyId, LaminarDataProvider<MarginMaxPriceAge>>;
+	type PriceStatus = OraclePriceStatus<MarginMaxPriceAge>;
+	type MarketCalendar = MarketCalendar;
 	type GetTreasuryAccountId = GetTreasuryAccountId;
+	type GetInsuranceFundAccountId = GetInsuranceFundAccountId;
+	type GetStopOutDisputeWindow = GetStopOutDisputeWindow;
+	type GetSlippageInsurancePremiumPeriod = GetSlippageInsurancePremiumPeriod;
 	type GetTraderMaxOpenPositions = GetTraderMaxOpenPositions;
 	type GetPoolMaxOpenPositions = GetPoolMaxOpenPositions;
 	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
+	type PauseOrigin = EnsureOneThirdGeneralCouncilOrRoot;
+	type AuditLog = AuditLog;
+	type FeeDiscount = FeeDiscount;
+	type TraderTiers = TraderTiers;
 	type UnsignedPriority = MarginProtocolUnsignedPriority;
+	type GetEnpEllSnapshotInterval = GetEnpEllSnapshotInterval;
+	type GetMaxEnpEllSnapshots = GetMaxEnpEllSnapshots;
+	type GetSwapSettlementInterval = GetSwapSettlementInterval;
+	type GetMaxCopyFollowers = GetMaxCopyFollowers;
+	type GetStopOutChunkSize = GetStopOutChunkSize;
+	type GetMaxTradeHistory = GetMaxTradeHistory;
+	type GetTraderMaxPendingOrders = GetTraderMaxPendingOrders;
+	type GetVolumePeriod = GetTradingVolumePeriod;
+	type PoolLiabilities = (OptionsProtocol, PerpetualProtocol);
+	type InsuranceFund = InsuranceFund;
+	type QuoteSignature = Signature;
+	type QuoteSigner = <Signature as Verify>::Signer;
+}
+
+parameter_types! {
+	pub const GetMaxOptionsPerExpiry: u32 = 50;
+	pub const GetPoolMaxOptions: u32 = 1000;
+}
+
+impl options_protocol::Trait for Runtime {
+	type Event = Event;
+	type LiquidityPools = margin_liquidity_pools::Module<Runtime>;
+	type PoolRisk = MarginProtocol;
+	type PriceProvider = orml_traits::DefaultPriceProvider<CurrencyId, LaminarDataProvider>;
+	type PriceStatus = OraclePriceStatus;
+	type GetMaxOptionsPerExpiry = GetMaxOptionsPerExpiry;
+	type GetPoolMaxOptions = GetPoolMaxOptions;
+	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
+}
+
+parameter_types! {
+	pub const GetPerpetualTraderMaxPositions: u32 = 200;
+	pub const GetPerpetualPoolMaxPositions: u32 = 1000;
+}
+
+impl perpetual_protocol::Trait for Runtime {
+	type Event = Event;
+	type LiquidityCurrency = LiquidityCurrency;
+	type LiquidityPools = margin_liquidity_pools::Module<Runtime>;
+	type PoolRisk = MarginProtocol;
+	type PriceProvider = orml_traits::DefaultPriceProvider<CurrencyId, LaminarDataProvider>;
+	type PriceStatus = OraclePriceStatus;
+	type GetTraderMaxPositions = GetPerpetualTraderMaxPositions;
+	type GetPoolMaxPositions = GetPerpetualPoolMaxPositions;
+	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
+}
+
+parameter_types! {
+	pub const GetSpotMaxTradeAmount: Balance = 1_000_000 * DOLLARS;
+}
+
+impl spot_exchange::Trait for Runtime {
+	type Event = Event;
+	type MultiCurrency = orml_currencies::Module<Runtime>;
+	type GetCollateralCurrencyId = GetCollateralCurrencyId;
+	type SyntheticCurrencyIds = SyntheticCurrencyIds;
+	type PriceProvider = orml_traits::DefaultPriceProvider<CurrencyId, LaminarDataProvider>;
+	type PriceStatus = OraclePriceStatus;
+	type LiquidityPools = synthetic_liquidity_pools::Module<Runtime>;
+	type FeeDiscount = FeeDiscount;
+	type GetMaxTradeAmount = GetSpotMaxTradeAmount;
+}
+
+parameter_types! {
+	pub const EarnVaultInterestPeriod: BlockNumber = 365 * DAYS;
+	pub const GetEarnVaultMaxPools: u32 = 20;
+	pub const GetEarnVaultMaxQueuedWithdrawals: u32 = 500;
+}
+
+impl earn_vault::Trait for Runtime {
+	type Event = Event;
+	type LiquidityCurrency = LiquidityCurrency;
+	type LiquidityPools = margin_liquidity_pools::Module<Runtime>;
+	type PoolManager = MarginProtocol;
+	type InterestPeriod = EarnVaultInterestPeriod;
+	type GetMaxPools = GetEarnVaultMaxPools;
+	type GetMaxQueuedWithdrawals = GetEarnVaultMaxQueuedWithdrawals;
+	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
+}
+
+impl incentives::Trait for Runtime {
+	type Event = Event;
+	type RewardCurrency = LaminarToken;
+	type MultiCurrency = orml_currencies::Module<Runtime>;
+	type SyntheticCurrencyIds = SyntheticCurrencyIds;
+	type MarginLiquidityPools = margin_liquidity_pools::Module<Runtime>;
+	type SyntheticLiquidityPools = synthetic_liquidity_pools::Module<Runtime>;
+	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
+}
+
+parameter_types! {
+	pub const FeeBuybackPeriod: BlockNumber = DAYS;
+	pub const FeeBuybackMinBidIncrement: Permill = Permill::from_percent(1);
+}
+
+impl fee_buyback::Trait for Runtime {
+	type Event = Event;
+	type FeeCurrency = LiquidityCurrency;
+	type NativeCurrency = LaminarToken;
+	type GetTreasuryAccountId = GetTreasuryAccountId;
+	type BuybackPeriod = FeeBuybackPeriod;
+	type MinBidIncrement = FeeBuybackMinBidIncrement;
+	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
+}
+
+impl insurance_fund::Trait for Runtime {
+	type Event = Event;
+	type Currency = LiquidityCurrency;
+	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
+}
+
+impl trader_tiers::Trait for Runtime {
+	type Event = Event;
+	type UpdateOrigin = EnsureHalfFinancialCouncilOrRoot;
+	type AuditLog = AuditLog;
 }
 
 construct_runtime!(
@@ -784,6 +1072,7 @@ construct_runtime!(
 		OperatorMembership: pallet_membership::<Instance3>::{Module, Call, Storage, Event<T>, Config<T>},
 		Utility: pallet_utility::{Module, Call, Storage, Event},
 		Multisig: pallet_multisig::{Module, Call, Storage, Event<T>},
+		Recovery: pallet_recovery::{Module, Call, Storage, Event<T>},
 		PalletTreasury: pallet_treasury::{Module, Call, Storage, Config, Event<T>},
 		Staking: pallet_staking::{Module, Call, Config<T>, Storage, Event<T>},
 		Session: pallet_session::{Module, Call, Storage, Event, Config<T>},
@@ -796,6 +1085,19 @@ construct_runtime!(
 		MarginLiquidityPools: margin_liquidity_pools::{Module, Storage, Call, Event<T>, Config<T>},
 		BaseLiquidityPoolsForSynthetic: base_liquidity_pools::<Instance2>::{Module, Storage, Call, Event<T>},
 		SyntheticLiquidityPools: synthetic_liquidity_pools::{Module, Storage, Call, Event<T>, Config},
+		EmergencyControl: emergency_control::{Module, Storage, Call, Event<T>},
+		AuditLog: audit_log::{Module, Storage},
+		FeeDiscount: fee_discount::{Module, Storage, Call, Event<T>},
+		OptionsProtocol: options_protocol::{Module, Storage, Call, Event<T>},
+		PerpetualProtocol: perpetual_protocol::{Module, Storage, Call, Event<T>},
+		SpotExchange: spot_exchange::{Module, Storage, Call, Event<T>},
+		EarnVault: earn_vault::{Module, Storage, Call, Event<T>},
+		Incentives: incentives::{Module, Storage, Call, Event<T>},
+		MarketCalendar: market_calendar::{Module, Storage, Call, Event<T>},
+		FeeBuyback: fee_buyback::{Module, Storage, Call, Event<T>},
+		OracleWatchdog: oracle_watchdog::{Module, Storage, Call, Event<T>, Config<T>},
+		InsuranceFund: insurance_fund::{Module, Storage, Call, Event<T>},
+		TraderTiers: trader_tiers::{Module, Storage, Call, Event<T>},
 	}
 );
 
@@ -1003,11 +1305,148 @@ impl_runtime_apis! {
 			}
 		}
 
+		fn trader_collaterals(who: AccountId, pool_id: LiquidityPoolId) -> Vec<MarginCollateral> {
+			MarginProtocol::collaterals_of_trader(&who, pool_id)
+				.unwrap_or_default()
+				.into_iter()
+				.map(|(currency_id, amount, value)| MarginCollateral {
+					currency_id,
+					amount,
+					value,
+				})
+				.collect()
+		}
+
 		fn pool_state(pool_id: LiquidityPoolId) -> Option<MarginPoolState> {
 			let (enp, ell) = MarginProtocol::enp_and_ell(pool_id)?;
 			let required_deposit = MarginProtocol::pool_required_deposit(pool_id)?;
+			let equity = MarginProtocol::equity_of_pool(pool_id)?;
+			let (above_margin_call, above_stop_out) = MarginProtocol::pool_above_risk_thresholds(pool_id)?;
+
+			Some(MarginPoolState {
+				enp,
+				ell,
+				required_deposit,
+				equity,
+				above_margin_call,
+				above_stop_out,
+			})
+		}
+
+		fn simulate_open_position(
+			who: AccountId,
+			pool_id: LiquidityPoolId,
+			pair: TradingPair,
+			leverage: Leverage,
+			leveraged_amount: Balance,
+			price: Price,
+		) -> Result<MarginPositionSimulation, DispatchError> {
+			let simulation =
+				MarginProtocol::simulate_open_position(&who, pool_id, pair, leverage, leveraged_amount, price)?;
+
+			Ok(MarginPositionSimulation {
+				open_price: simulation.open_price,
+				margin_held: simulation.margin_held,
+				margin_level: simulation.margin_level,
+				enp: simulation.enp,
+				ell: simulation.ell,
+			})
+		}
 
-			Some(MarginPoolState { enp, ell, required_deposit })
+		fn check_stop_out(who: AccountId, pool_id: LiquidityPoolId) -> bool {
+			MarginProtocol::can_stop_out_trader(&who, pool_id)
+		}
+
+		fn pool_enp_ell_snapshots(pool_id: LiquidityPoolId) -> Vec<MarginEnpEllSnapshot<BlockNumber>> {
+			MarginProtocol::enp_ell_snapshots(pool_id)
+				.into_iter()
+				.map(|s| MarginEnpEllSnapshot {
+					block_number: s.block_number,
+					enp: s.enp,
+					ell: s.ell,
+				})
+				.collect()
+		}
+
+		fn trading_pair_metadatas() -> Vec<(TradingPair, TradingPairMetadata)> {
+			MarginLiquidityPools::trading_pair_metadatas()
+		}
+
+		fn swap_rates(pool_id: LiquidityPoolId, pair: TradingPair) -> MarginSwapRates {
+			let swap_rate = |is_long| {
+				<MarginLiquidityPools as MarginProtocolLiquidityPools<AccountId>>::swap_rate(pool_id, pair, is_long)
+			};
+			let accumulated = MarginLiquidityPools::accumulated_swap_rate(pool_id, pair);
+			let history = MarginLiquidityPools::accumulated_swap_rate_history(pool_id, pair)
+				.into_iter()
+				.map(|s| MarginAccumulatedSwapRate {
+					time: s.time,
+					long: s.accumulated.long,
+					short: s.accumulated.short,
+				})
+				.collect();
+
+			MarginSwapRates {
+				long: swap_rate(true),
+				short: swap_rate(false),
+				accumulated_long: accumulated.long,
+				accumulated_short: accumulated.short,
+				history,
+				next_accumulate_time: MarginLiquidityPools::next_accumulate_time(pair),
+			}
+		}
+
+		fn risk_params(who: AccountId, pool_id: LiquidityPoolId, pair: TradingPair) -> MarginRiskParams {
+			let params = MarginProtocol::risk_params(&who, pool_id, pair);
+			let tier = TraderTiers::trader_tier(&who);
+			// the lower one of the pool and protocol max leverage of the tier applies
+			let max_leverage = cmp::min(
+				MarginLiquidityPools::trader_max_leverage(pool_id, tier).unwrap_or(u8::max_value()),
+				MarginProtocol::trader_tier_option(tier).max_leverage.unwrap_or(u8::max_value()),
+			);
+			let threshold = |t: margin_protocol::RiskThreshold| MarginRiskThreshold {
+				margin_call: t.margin_call,
+				stop_out: t.stop_out,
+				warning: t.warning,
+			};
+
+			MarginRiskParams {
+				trader_threshold: threshold(params.trader_threshold),
+				enp_threshold: threshold(params.enp_threshold),
+				ell_threshold: threshold(params.ell_threshold),
+				max_long_leverage: MarginLiquidityPools::max_enabled_leverage_up_to(pool_id, pair, true, max_leverage),
+				max_short_leverage: MarginLiquidityPools::max_enabled_leverage_up_to(pool_id, pair, false, max_leverage),
+				min_leveraged_amount: MarginLiquidityPools::min_leveraged_amount_of_pair(pool_id, pair),
+				bid_spread: params.bid_spread,
+				ask_spread: params.ask_spread,
+				trader_positions_headroom: params.trader_positions_headroom,
+				pool_positions_headroom: params.pool_positions_headroom,
+			}
+		}
+
+		fn open_interest(pair: TradingPair) -> MarginOpenInterest {
+			let open_interest = MarginProtocol::open_interest(pair);
+			MarginOpenInterest {
+				long: open_interest.long,
+				short: open_interest.short,
+				long_positions_count: open_interest.long_positions_count,
+				short_positions_count: open_interest.short_positions_count,
+			}
+		}
+
+		fn trading_volume(pool_id: LiquidityPoolId, pair: TradingPair) -> MarginTradingVolume {
+			let volumes = MarginProtocol::trading_volume(pool_id, pair);
+			MarginTradingVolume {
+				period_start: volumes.period_start.into(),
+				opened: volumes.current.opened,
+				closed: volumes.current.closed,
+				previous_opened: volumes.previous.opened,
+				previous_closed: volumes.previous.closed,
+			}
+		}
+
+		fn estimated_liquidation_price(position_id: u64) -> Option<Price> {
+			MarginProtocol::estimated_liquidation_price(position_id)
 		}
 	}
 
@@ -1018,6 +1457,61 @@ impl_runtime_apis! {
 
 			Some(SyntheticPoolState { collateral_ratio, is_safe })
 		}
+
+		fn check_liquidate(
+			pool_id: LiquidityPoolId,
+			currency_id: CurrencyId,
+			synthetic_amount: Balance,
+		) -> SyntheticLiquidationCheck {
+			match SyntheticProtocol::liquidation_incentive(pool_id, currency_id, synthetic_amount) {
+				Ok(incentive) => SyntheticLiquidationCheck { can_liquidate: true, incentive },
+				Err(_) => SyntheticLiquidationCheck::default(),
+			}
+		}
+
+		fn synthetic_tokens() -> Vec<SyntheticToken> {
+			SyntheticProtocol::synthetic_tokens()
+				.into_iter()
+				.map(|info| SyntheticToken {
+					currency_id: info.currency_id,
+					price: info.price,
+					total_issuance: info.total_issuance,
+					extreme_ratio: info.extreme_ratio,
+					liquidation_ratio: info.liquidation_ratio,
+					collateral_ratio: info.collateral_ratio,
+					pools: info
+						.pools
+						.into_iter()
+						.map(|pool| SyntheticTokenPool {
+							pool_id: pool.pool_id,
+							bid_spread: pool.bid_spread,
+							ask_spread: pool.ask_spread,
+							can_mint: pool.can_mint,
+							synthetic_issued: pool.synthetic_issued,
+							collateral_ratio: pool.collateral_ratio,
+							additional_collateral_ratio: pool.additional_collateral_ratio,
+						})
+						.collect(),
+				})
+				.collect()
+		}
+
+		fn synthetic_volume(pool_id: LiquidityPoolId, currency_id: CurrencyId) -> SyntheticVolume {
+			let volumes = SyntheticProtocol::synthetic_volume(pool_id, currency_id);
+			SyntheticVolume {
+				period_start: volumes.period_start.into(),
+				minted: volumes.current.minted,
+				redeemed: volumes.current.redeemed,
+				previous_minted: volumes.previous.minted,
+				previous_redeemed: volumes.previous.redeemed,
+			}
+		}
+	}
+
+	impl audit_log_runtime_api::AuditLogApi<Block, AccountId> for Runtime {
+		fn audit_records(from_id: u64, limit: u32) -> Vec<(u64, AuditRecord<AccountId, BlockNumber>)> {
+			AuditLog::audit_records_from(from_id, limit)
+		}
 	}
 
 	#[cfg(feature = "runtime-benchmarks")]
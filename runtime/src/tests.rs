@@ -54,6 +54,7 @@ pub fn risk_threshold(margin_call_percent: u32, stop_out_percent: u32) -> RiskTh
 	RiskThreshold {
 		margin_call: Permill::from_percent(margin_call_percent),
 		stop_out: Permill::from_percent(stop_out_percent),
+		warning: Permill::zero(),
 	}
 }
 
@@ -128,6 +129,8 @@ impl ExtBuilder {
 					risk_threshold(30, 10),
 				),
 			],
+			force_close_penalty_tiers: vec![],
+			risk_candidate_buffer: None,
 		}
 		.assimilate_storage(&mut t)
 		.unwrap();
@@ -415,11 +418,11 @@ pub fn margin_open_position(
 	amount: Balance,
 	price: Price,
 ) -> DispatchResult {
-	ModuleMarginProtocol::open_position(origin_of(who), LIQUIDITY_POOL_ID_0, pair, leverage, amount, price)
+	ModuleMarginProtocol::open_position(origin_of(who), LIQUIDITY_POOL_ID_0, pair, leverage, amount, price, None)
 }
 
 pub fn margin_close_position(who: &AccountId, position_id: PositionId, price: Price) -> DispatchResult {
-	ModuleMarginProtocol::close_position(origin_of(who), position_id, price)
+	ModuleMarginProtocol::close_position(origin_of(who), position_id, price, None)
 }
 
 pub fn margin_deposit(who: &AccountId, amount: Balance) -> DispatchResult {
@@ -430,6 +433,11 @@ pub fn margin_withdraw(who: &AccountId, amount: Balance) -> DispatchResult {
 	ModuleMarginProtocol::withdraw(origin_of(who), LIQUIDITY_POOL_ID_0, amount)
 }
 
+/// Collateral held by margin protocol, which backs balances of all traders.
+pub fn margin_module_balance() -> Balance {
+	collateral_balance(&ModuleMarginProtocol::account_id())
+}
+
 pub fn margin_pool_required_deposit() -> FixedI128 {
 	ModuleMarginProtocol::pool_required_deposit(LIQUIDITY_POOL_ID_0).unwrap()
 }
@@ -498,6 +506,17 @@ pub fn margin_execute_time(range: Range<Moment>) {
 	}
 }
 
+/// Execute blocks of `range` seconds, one block per second, running `on_initialize` of both margin liquidity
+/// pools and margin protocol, e.g. swap accumulation, pending stop outs and ENP/ELL snapshots.
+pub fn margin_execute_blocks(range: Range<Moment>) {
+	for i in range {
+		System::set_block_number(i as u32);
+		Timestamp::set_timestamp(i * 1000);
+		MarginLiquidityPools::on_initialize(i as u32);
+		ModuleMarginProtocol::on_initialize(i as u32);
+	}
+}
+
 pub fn margin_set_risk_threshold(
 	pair: TradingPair,
 	trader: Option<RiskThreshold>,
@@ -114,7 +114,10 @@ mod tests {
 					Some(MarginPoolState {
 						enp: FixedI128::max_value(),
 						ell: FixedI128::max_value(),
-						required_deposit: FixedI128::zero()
+						required_deposit: FixedI128::zero(),
+						equity: FixedI128::saturating_from_integer(10_000),
+						above_margin_call: true,
+						above_stop_out: true,
 					})
 				);
 				assert_eq!(
@@ -141,7 +144,10 @@ mod tests {
 					Some(MarginPoolState {
 						enp: FixedI128::from_inner(0_686666666666666667),
 						ell: FixedI128::from_inner(0_686666666666666667),
-						required_deposit: FixedI128::zero()
+						required_deposit: FixedI128::zero(),
+						equity: FixedI128::saturating_from_integer(10_300),
+						above_margin_call: true,
+						above_stop_out: true,
 					})
 				);
 				assert_eq!(
@@ -172,7 +178,10 @@ mod tests {
 					Some(MarginPoolState {
 						enp: FixedI128::max_value(),
 						ell: FixedI128::max_value(),
-						required_deposit: FixedI128::zero()
+						required_deposit: FixedI128::zero(),
+						equity: FixedI128::saturating_from_integer(10_300),
+						above_margin_call: true,
+						above_stop_out: true,
 					})
 				);
 				assert_eq!(
@@ -349,7 +358,10 @@ mod tests {
 					Some(MarginPoolState {
 						enp: FixedI128::from_inner(0_686666666666666667),
 						ell: FixedI128::from_inner(0_686666666666666667),
-						required_deposit: FixedI128::zero()
+						required_deposit: FixedI128::zero(),
+						equity: FixedI128::saturating_from_integer(10_300),
+						above_margin_call: true,
+						above_stop_out: true,
 					})
 				);
 				// margin = leveraged_amount * price / leverage
@@ -378,7 +390,10 @@ mod tests {
 					Some(MarginPoolState {
 						enp: FixedI128::from_inner(1_409523809523809524),
 						ell: FixedI128::from_inner(1_409523809523809524),
-						required_deposit: FixedI128::zero()
+						required_deposit: FixedI128::zero(),
+						equity: FixedI128::saturating_from_integer(14_800),
+						above_margin_call: true,
+						above_stop_out: true,
 					})
 				);
 				assert_ok!(margin_trader_margin_call(&ALICE::get()));
@@ -410,7 +425,10 @@ mod tests {
 					Some(MarginPoolState {
 						enp: FixedI128::from_inner(1_409523809523809524),
 						ell: FixedI128::from_inner(1_409523809523809524),
-						required_deposit: FixedI128::zero()
+						required_deposit: FixedI128::zero(),
+						equity: FixedI128::saturating_from_integer(14_800),
+						above_margin_call: true,
+						above_stop_out: true,
 					})
 				);
 				assert_ok!(margin_trader_margin_call(&ALICE::get()));
@@ -667,7 +685,10 @@ mod tests {
 					Some(MarginPoolState {
 						enp: FixedI128::max_value(),
 						ell: FixedI128::max_value(),
-						required_deposit: FixedI128::zero()
+						required_deposit: FixedI128::zero(),
+						equity: FixedI128::saturating_from_integer(20_000),
+						above_margin_call: true,
+						above_stop_out: true,
 					})
 				);
 				assert_eq!(
@@ -847,7 +868,10 @@ mod tests {
 					Some(MarginPoolState {
 						enp: FixedI128::max_value(),
 						ell: FixedI128::max_value(),
-						required_deposit: FixedI128::zero()
+						required_deposit: FixedI128::zero(),
+						equity: FixedI128::from_inner(19629806451612903226800),
+						above_margin_call: true,
+						above_stop_out: true,
 					})
 				);
 				assert_eq!(
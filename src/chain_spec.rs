@@ -5,9 +5,9 @@ use module_primitives::{AccumulateConfig, TradingPair};
 use runtime::{
 	opaque::SessionKeys, AccountId, BabeConfig, BalancesConfig, Block, CurrencyId, FinancialCouncilMembershipConfig,
 	GeneralCouncilMembershipConfig, GenesisConfig, GrandpaConfig, IndicesConfig, MarginLiquidityPoolsConfig,
-	MarginProtocolConfig, Moment, OperatorMembershipConfig, OracleConfig, OracleId, SessionConfig, Signature,
-	StakerStatus, StakingConfig, SudoConfig, SyntheticLiquidityPoolsConfig, SyntheticTokensConfig, SystemConfig,
-	TokensConfig, CENTS, DOLLARS, MILLICENTS, WASM_BINARY,
+	MarginProtocolConfig, Moment, OperatorMembershipConfig, OracleConfig, OracleId, OracleWatchdogConfig,
+	SessionConfig, Signature, StakerStatus, StakingConfig, SudoConfig, SyntheticLiquidityPoolsConfig,
+	SyntheticTokensConfig, SystemConfig, TokensConfig, CENTS, DOLLARS, MILLICENTS, MINUTES, WASM_BINARY,
 };
 use sc_chain_spec::ChainSpecExtension;
 use sc_service;
@@ -277,6 +277,7 @@ fn risk_threshold(margin_call_percent: u32, stop_out_percent: u32) -> RiskThresh
 	RiskThreshold {
 		margin_call: Permill::from_percent(margin_call_percent),
 		stop_out: Permill::from_percent(stop_out_percent),
+		warning: Permill::zero(),
 	}
 }
 
@@ -338,6 +339,17 @@ fn dev_genesis(
 		}),
 		synthetic_liquidity_pools: Some(SyntheticLiquidityPoolsConfig {
 			min_additional_collateral_ratio: Permill::from_percent(10), // default min additional collateral ratio
+			max_spreads: vec![
+				(CurrencyId::FEUR, 1 * CENTS),
+				(CurrencyId::FJPY, 1 * CENTS),
+				(CurrencyId::FAUD, 1 * CENTS),
+				(CurrencyId::FCAD, 1 * CENTS),
+				(CurrencyId::FCHF, 1 * CENTS),
+				(CurrencyId::FXAU, 1 * CENTS),
+				(CurrencyId::FOIL, 1 * CENTS),
+				(CurrencyId::FBTC, 20 * DOLLARS),
+				(CurrencyId::FETH, 1 * DOLLARS),
+			],
 		}),
 		synthetic_tokens: Some(SyntheticTokensConfig {
 			ratios: vec![
@@ -549,11 +561,16 @@ fn dev_genesis(
 					risk_threshold(40, 12),
 				),
 			],
+			force_close_penalty_tiers: vec![],
+			risk_candidate_buffer: None,
 		}),
 		orml_oracle: Some(OracleConfig {
 			members: Default::default(), // initialized by OperatorMembership
 			session_keys: oracle_session_keys,
 		}),
+		oracle_watchdog: Some(OracleWatchdogConfig {
+			recovery_period: 5 * MINUTES,
+		}),
 	}
 }
 
@@ -619,6 +636,17 @@ fn turbulence_genesis(
 		}),
 		synthetic_liquidity_pools: Some(SyntheticLiquidityPoolsConfig {
 			min_additional_collateral_ratio: Permill::from_percent(10), // default min additional collateral ratio
+			max_spreads: vec![
+				(CurrencyId::FEUR, 1 * CENTS),
+				(CurrencyId::FJPY, 1 * CENTS),
+				(CurrencyId::FAUD, 1 * CENTS),
+				(CurrencyId::FCAD, 1 * CENTS),
+				(CurrencyId::FCHF, 1 * CENTS),
+				(CurrencyId::FXAU, 1 * CENTS),
+				(CurrencyId::FOIL, 1 * CENTS),
+				(CurrencyId::FBTC, 20 * DOLLARS),
+				(CurrencyId::FETH, 1 * DOLLARS),
+			],
 		}),
 		synthetic_tokens: Some(SyntheticTokensConfig {
 			ratios: vec![
@@ -830,10 +858,15 @@ fn turbulence_genesis(
 					risk_threshold(40, 12),
 				),
 			],
+			force_close_penalty_tiers: vec![],
+			risk_candidate_buffer: None,
 		}),
 		orml_oracle: Some(OracleConfig {
 			members: Default::default(), // initialized by OperatorMembership
 			session_keys: oracle_session_keys,
 		}),
+		oracle_watchdog: Some(OracleWatchdogConfig {
+			recovery_period: 5 * MINUTES,
+		}),
 	}
 }
@@ -4,7 +4,7 @@ use cucumber::cucumber;
 
 use frame_support::{assert_noop, assert_ok};
 use margin_protocol::RiskThreshold;
-use margin_protocol_rpc_runtime_api::{MarginPoolState, MarginTraderState};
+use margin_protocol_rpc_runtime_api::MarginTraderState;
 use module_primitives::{Balance, Leverage, TradingPair};
 use runtime::{tests::*, AccountId, CurrencyId, Moment};
 use sp_arithmetic::{FixedI128, FixedU128};
@@ -224,6 +224,7 @@ fn parse_threshold(value: Option<&String>) -> Option<RiskThreshold> {
 	Some(RiskThreshold {
 		margin_call: threshold[0],
 		stop_out: threshold[1],
+		warning: threshold.get(2).cloned().unwrap_or_default(),
 	})
 }
 
@@ -574,12 +575,8 @@ mod steps {
 					});
 					for (enp, ell, required_deposit) in iter {
 						assert_eq!(
-							margin_pool_state(),
-							Some(MarginPoolState {
-								enp: enp,
-								ell: ell,
-								required_deposit: required_deposit,
-							})
+							margin_pool_state().map(|s| (s.enp, s.ell, s.required_deposit)),
+							Some((enp, ell, required_deposit))
 						);
 					}
 				})
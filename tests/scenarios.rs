@@ -0,0 +1,225 @@
+//! Multi-block scenarios running the full runtime: price paths, swap accumulation, margin calls, stop outs and
+//! liquidity pool force closures, verified by end state balances.
+//!
+//! Keeper calls, normally submitted by the offchain worker, are made directly between blocks.
+
+#![cfg(test)]
+
+use frame_support::{assert_noop, assert_ok};
+use module_primitives::{Balance, Leverage, Price};
+use runtime::{tests::*, CurrencyId, EmergencyControl, ExpiresIn, Moment, Origin, Runtime, System, Timestamp};
+use sp_arithmetic::{FixedI128, FixedPointNumber};
+use sp_runtime::traits::{Bounded, Zero};
+
+type MarginError = margin_protocol::Error<Runtime>;
+
+const POSITION_ID: PositionId = 0;
+
+fn price(dollar_cent: u128) -> Price {
+	Price::from_inner(cent(dollar_cent))
+}
+
+fn fixed_dollar_cent(dollar_cent: i128) -> FixedI128 {
+	FixedI128::saturating_from_rational(dollar_cent, 100)
+}
+
+/// Set EUR price to each of `prices` in turn, and execute `blocks` blocks after each.
+fn price_path(prices: &[u128], blocks: Moment) {
+	for dollar_cent in prices {
+		assert_ok!(set_oracle_price(vec![(CurrencyId::FEUR, price(*dollar_cent))]));
+		let start = System::block_number() as Moment + 1;
+		margin_execute_blocks(start..start + blocks);
+	}
+}
+
+/// Total collateral of accounts, margin protocol, liquidity pool and treasury.
+fn total_collateral() -> Balance {
+	collateral_balance(&POOL::get())
+		+ collateral_balance(&ALICE::get())
+		+ collateral_balance(&BOB::get())
+		+ margin_module_balance()
+		+ margin_liquidity()
+		+ treasury_balance()
+}
+
+/// Market of EUR/USD at $1.2 with $0.01 spread, `pool_liquidity` deposited by pool and $1000 by Alice.
+fn new_market(pool_liquidity: u128) -> sp_io::TestExternalities {
+	let mut ext = ExtBuilder::default()
+		.balances(vec![
+			(POOL::get(), CurrencyId::AUSD, dollar(200_000)),
+			(ALICE::get(), CurrencyId::AUSD, dollar(10_000)),
+			(BOB::get(), CurrencyId::AUSD, dollar(10_000)),
+		])
+		.build();
+	ext.execute_with(|| {
+		assert_ok!(set_oracle_price(vec![(CurrencyId::FEUR, price(120))]));
+		assert_ok!(margin_create_pool());
+		assert_ok!(margin_set_enabled_trades());
+		assert_ok!(margin_enable_trading_pair(EUR_USD));
+		assert_ok!(margin_liquidity_pool_enable_trading_pair(EUR_USD));
+		assert_ok!(margin_set_spread(EUR_USD, cent(1)));
+		assert_ok!(margin_deposit_liquidity(&POOL::get(), dollar(pool_liquidity)));
+		assert_ok!(margin_deposit(&ALICE::get(), dollar(1_000)));
+	});
+	ext
+}
+
+/// Alice opens long 10_000 EUR with 20x leverage, at ask price $1.21.
+fn open_long_position() {
+	assert_ok!(margin_open_position(
+		&ALICE::get(),
+		EUR_USD,
+		Leverage::LongTwenty,
+		dollar(10_000),
+		Price::max_value()
+	));
+}
+
+#[test]
+fn falling_price_margin_calls_and_stops_out_trader() {
+	new_market(100_000).execute_with(|| {
+		let total = total_collateral();
+		open_long_position();
+
+		// equity $600, margin level 4.96%
+		price_path(&[118], 10);
+		assert_noop!(margin_trader_margin_call(&ALICE::get()), MarginError::SafeTrader);
+
+		// equity $300, margin level 2.48%
+		price_path(&[115], 10);
+		assert_ok!(margin_trader_margin_call(&ALICE::get()));
+		assert_noop!(
+			margin_trader_stop_out(&ALICE::get()),
+			MarginError::NotReachedRiskThreshold
+		);
+		assert_noop!(
+			margin_open_position(
+				&ALICE::get(),
+				EUR_USD,
+				Leverage::LongTwenty,
+				dollar(100),
+				Price::max_value()
+			),
+			MarginError::MarginCalledTrader
+		);
+
+		// equity -$100, loss realized up to the $1000 balance
+		price_path(&[113, 111], 5);
+		assert_ok!(margin_trader_stop_out(&ALICE::get()));
+
+		assert_eq!(ModuleMarginProtocol::positions(POSITION_ID), None);
+		assert_eq!(
+			ModuleMarginProtocol::margin_called_traders(ALICE::get(), LIQUIDITY_POOL_ID_0),
+			None
+		);
+		assert_eq!(margin_balance(&ALICE::get()), FixedI128::zero());
+		assert_eq!(collateral_balance(&ALICE::get()), dollar(9_000));
+		assert_eq!(margin_liquidity(), dollar(101_000));
+		assert_eq!(margin_module_balance(), 0);
+		assert_eq!(total_collateral(), total);
+	});
+}
+
+#[test]
+fn accumulated_swap_margin_calls_trader() {
+	new_market(100_000).execute_with(|| {
+		let total = total_collateral();
+		// -0.5% of leveraged debits for long positions, every minute
+		assert_ok!(margin_set_accumulate(EUR_USD, ONE_MINUTE, 0));
+		assert_ok!(margin_set_swap_rate(
+			EUR_USD,
+			FixedI128::saturating_from_rational(-5, 1000),
+			FixedI128::saturating_from_rational(5, 1000)
+		));
+		open_long_position();
+
+		// 7 accumulations: equity $376.5, margin level 3.11%
+		margin_execute_blocks(1..421);
+		assert_eq!(margin_equity(&ALICE::get()), fixed_dollar_cent(37_650));
+		assert_noop!(margin_trader_margin_call(&ALICE::get()), MarginError::SafeTrader);
+
+		// 8 accumulations: equity $316, margin level 2.61%
+		margin_execute_blocks(421..481);
+		assert_eq!(margin_equity(&ALICE::get()), fixed_dollar_cent(31_600));
+		assert_ok!(margin_trader_margin_call(&ALICE::get()));
+
+		// $200 spread and $484 swap realized
+		assert_ok!(margin_close_position(&ALICE::get(), POSITION_ID, Price::zero()));
+		assert_ok!(margin_trader_become_safe(&ALICE::get()));
+		assert_eq!(margin_balance(&ALICE::get()), fixed_dollar_cent(31_600));
+		assert_eq!(margin_liquidity(), dollar(100_684));
+
+		assert_ok!(margin_withdraw(&ALICE::get(), dollar(316)));
+		assert_eq!(margin_balance(&ALICE::get()), FixedI128::zero());
+		assert_eq!(collateral_balance(&ALICE::get()), dollar(9_316));
+		assert_eq!(margin_module_balance(), 0);
+		assert_eq!(total_collateral(), total);
+	});
+}
+
+#[test]
+fn rising_price_margin_calls_and_force_closes_pool() {
+	new_market(5_000).execute_with(|| {
+		let total = total_collateral();
+		let treasury = treasury_balance();
+		open_long_position();
+
+		// pool equity $3200, ENP 22.86%
+		price_path(&[130, 140], 5);
+		assert_ok!(margin_liquidity_pool_margin_call());
+		assert_noop!(
+			margin_liquidity_pool_force_close(),
+			MarginError::NotReachedRiskThreshold
+		);
+		assert_noop!(
+			margin_open_position(
+				&ALICE::get(),
+				EUR_USD,
+				Leverage::LongTwenty,
+				dollar(100),
+				Price::max_value()
+			),
+			MarginError::MarginCalledPool
+		);
+
+		// pool equity $1200, ENP 7.5%
+		price_path(&[160], 10);
+		assert_ok!(margin_liquidity_pool_force_close());
+
+		// Alice's $3800 profit is paid, and the $100 spread profit and $100 penalty go to treasury.
+		assert_eq!(ModuleMarginProtocol::positions(POSITION_ID), None);
+		assert_eq!(ModuleMarginProtocol::margin_called_pools(LIQUIDITY_POOL_ID_0), None);
+		assert_eq!(margin_balance(&ALICE::get()), fixed_i128_dollar(4_800));
+		assert_eq!(margin_module_balance(), dollar(4_800));
+		assert_eq!(margin_liquidity(), dollar(1_000));
+		assert_eq!(treasury_balance(), treasury + dollar(200));
+		assert_eq!(total_collateral(), total);
+
+		assert_ok!(margin_withdraw(&ALICE::get(), dollar(4_800)));
+		assert_eq!(collateral_balance(&ALICE::get()), dollar(13_800));
+	});
+}
+
+#[test]
+fn halted_market_and_stale_price_block_closing_position() {
+	new_market(100_000).execute_with(|| {
+		open_long_position();
+
+		assert_ok!(EmergencyControl::halt_market(Origin::root(), CurrencyId::FEUR));
+		assert_noop!(
+			margin_close_position(&ALICE::get(), POSITION_ID, Price::zero()),
+			MarginError::MarketHalted
+		);
+		assert_ok!(EmergencyControl::resume_market(Origin::root(), CurrencyId::FEUR));
+
+		// fed prices expired
+		Timestamp::set_timestamp(ExpiresIn::get() + 1);
+		assert_noop!(
+			margin_close_position(&ALICE::get(), POSITION_ID, Price::zero()),
+			MarginError::StalePrice
+		);
+
+		assert_ok!(set_oracle_price(vec![(CurrencyId::FEUR, price(120))]));
+		assert_ok!(margin_close_position(&ALICE::get(), POSITION_ID, Price::zero()));
+	});
+}